@@ -0,0 +1,71 @@
+//! 压缩包装器的吞吐基准
+//!
+//! 在进程内的内存传输上比较不压缩、LZ4 与 Zstandard 收发一条消息的耗时，
+//! 数据分为可压缩的 JSON 遥测与不可压缩的随机字节两类。
+//!
+//! 运行：`cargo bench --bench compression --features testing,compression-lz4,compression-zstd`
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use virga::client::{ClientConfig, VirgeClient};
+use virga::Compression;
+
+const MESSAGE_LEN: usize = 64 * 1024;
+
+/// 字段名反复出现的 JSON 遥测数据
+fn telemetry(len: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(len + 64);
+    let mut seq = 0;
+    while data.len() < len {
+        data.extend_from_slice(format!(r#"{{"seq":{},"cpu":{},"state":"running"}}"#, seq, seq % 100).as_bytes());
+        data.push(b'\n');
+        seq += 1;
+    }
+    data.truncate(len);
+    data
+}
+
+/// 固定种子的 xorshift 随机字节
+fn random(len: usize) -> Vec<u8> {
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn algorithms() -> Vec<(&'static str, Option<Compression>)> {
+    let mut algorithms = vec![("none", None), ("lz4", Some(Compression::Lz4))];
+    #[cfg(feature = "compression-zstd")]
+    algorithms.push(("zstd-3", Some(Compression::Zstd { level: 3 })));
+    algorithms
+}
+
+fn round_trip(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("compression");
+    group.throughput(Throughput::Bytes(MESSAGE_LEN as u64));
+
+    for (data_name, data) in [("telemetry", telemetry(MESSAGE_LEN)), ("random", random(MESSAGE_LEN))] {
+        for (name, compression) in algorithms() {
+            let config = ClientConfig::default().with_compression(compression).with_chunk_size(MESSAGE_LEN as u32);
+            let (mut client, mut server) = runtime.block_on(VirgeClient::new_in_memory(config)).unwrap();
+            group.bench_with_input(BenchmarkId::new(name, data_name), &data, |b, data| {
+                b.iter(|| {
+                    runtime.block_on(async {
+                        let (sent, received) = tokio::join!(client.send(data.clone()), server.recv());
+                        sent.unwrap();
+                        assert_eq!(received.unwrap().len(), data.len());
+                    })
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, round_trip);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+//! `recv_into` 与 `recv` 的接收分配基准
+//!
+//! 在进程内的内存传输上比较两种接收方式：`recv` 为每条消息分配一个新的 `Vec`，
+//! `recv_into` 复用调用方的缓冲区，只在容量不足时增长。
+//!
+//! 运行：`cargo bench --bench recv_into --features testing`
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use virga::client::{ClientConfig, VirgeClient};
+
+const MESSAGE_LENS: [usize; 3] = [64, 4 * 1024, 64 * 1024];
+
+fn receive(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("receive");
+
+    for len in MESSAGE_LENS {
+        let data = vec![0xA5u8; len];
+        let config = ClientConfig::default().with_chunk_size(len as u32);
+        let (mut client, mut server) = runtime.block_on(VirgeClient::new_in_memory(config)).unwrap();
+        group.throughput(Throughput::Bytes(len as u64));
+
+        group.bench_with_input(BenchmarkId::new("recv", len), &data, |b, data| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let (sent, received) = tokio::join!(client.send(data.clone()), server.recv());
+                    sent.unwrap();
+                    assert_eq!(received.unwrap().len(), data.len());
+                })
+            });
+        });
+
+        let mut buf = Vec::new();
+        group.bench_with_input(BenchmarkId::new("recv_into", len), &data, |b, data| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let (sent, received) = tokio::join!(client.send(data.clone()), server.recv_into(&mut buf));
+                    sent.unwrap();
+                    assert_eq!(received.unwrap(), data.len());
+                })
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, receive);
+criterion_main!(benches);
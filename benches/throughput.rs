@@ -0,0 +1,128 @@
+//! 客户端与服务器之间的往返延迟与吞吐基准
+//!
+//! 在进程内的内存传输与回环 TCP（启用 `use-tcp` 时）上，按不同的 chunk_size 与消息大小
+//! 收发长度前缀消息：`round_trip` 测量一条消息发出并被回显的耗时，`one_way` 测量单向吞吐。
+//!
+//! 运行：`cargo bench --bench throughput --features testing,use-tcp`
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::runtime::Runtime;
+use virga::client::{ClientConfig, VirgeClient};
+use virga::server::{ServerManager, VirgeServer};
+use virga::{KIB, MIB};
+
+const CHUNK_SIZES: [usize; 2] = [4 * KIB, 64 * KIB];
+
+const MESSAGE_LENS: [usize; 4] = [64, 4 * KIB, 64 * KIB, MIB];
+
+/// 一对已连接的客户端与服务器，TCP 连接同时持有其监听器
+struct Pair {
+    transport: &'static str,
+    client: VirgeClient,
+    server: VirgeServer,
+    _manager: Option<ServerManager>,
+}
+
+async fn memory(chunk_size: usize) -> Pair {
+    let config = ClientConfig::default().with_chunk_size(chunk_size as u32);
+    let (client, server) = VirgeClient::new_in_memory(config).await.unwrap();
+    Pair { transport: "memory", client, server, _manager: None }
+}
+
+#[cfg(feature = "use-tcp")]
+async fn tcp(chunk_size: usize) -> Pair {
+    use virga::server::ServerConfig;
+    use virga::TransportKind;
+
+    let config = ServerConfig::default()
+        .with_listen_port(ServerConfig::PORT_ANY)
+        .with_transport_kind(TransportKind::Tcp)
+        .with_chunk_size(chunk_size as u32);
+    let mut manager = ServerManager::new(config);
+    manager.start().await.unwrap();
+    let port = manager.local_addr().unwrap().port();
+
+    let config = ClientConfig::default()
+        .with_server_cid(ClientConfig::CID_LOCAL)
+        .with_server_port(port)
+        .with_transport_kind(TransportKind::Tcp)
+        .with_chunk_size(chunk_size as u32);
+    let mut client = VirgeClient::new(config);
+    let (connected, server) = tokio::join!(client.connect(), manager.accept());
+    connected.unwrap();
+    Pair { transport: "tcp", client, server: server.unwrap(), _manager: Some(manager) }
+}
+
+fn pairs(runtime: &Runtime, chunk_size: usize) -> Vec<Pair> {
+    #[allow(unused_mut)]
+    let mut pairs = vec![runtime.block_on(memory(chunk_size))];
+    #[cfg(feature = "use-tcp")]
+    pairs.push(runtime.block_on(tcp(chunk_size)));
+    pairs
+}
+
+fn bench_id(pair: &Pair, chunk_size: usize, len: usize) -> BenchmarkId {
+    BenchmarkId::new(format!("{}/chunk-{}", pair.transport, chunk_size), len)
+}
+
+fn round_trip(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("round_trip");
+
+    for chunk_size in CHUNK_SIZES {
+        for mut pair in pairs(&runtime, chunk_size) {
+            for len in MESSAGE_LENS {
+                let data = vec![0xA5u8; len];
+                group.throughput(Throughput::Bytes(len as u64));
+                group.bench_with_input(bench_id(&pair, chunk_size, len), &data, |b, data| {
+                    let Pair { client, server, .. } = &mut pair;
+                    b.iter(|| {
+                        runtime.block_on(async {
+                            let (reply, echoed) = tokio::join!(
+                                async {
+                                    client.send_msg(data).await?;
+                                    client.recv_msg().await
+                                },
+                                async {
+                                    let message = server.recv_msg().await?;
+                                    server.send_msg(&message).await
+                                },
+                            );
+                            echoed.unwrap();
+                            assert_eq!(reply.unwrap().len(), data.len());
+                        })
+                    });
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+fn one_way(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("one_way");
+
+    for chunk_size in CHUNK_SIZES {
+        for mut pair in pairs(&runtime, chunk_size) {
+            for len in MESSAGE_LENS {
+                let data = vec![0xA5u8; len];
+                group.throughput(Throughput::Bytes(len as u64));
+                group.bench_with_input(bench_id(&pair, chunk_size, len), &data, |b, data| {
+                    let Pair { client, server, .. } = &mut pair;
+                    b.iter(|| {
+                        runtime.block_on(async {
+                            let (sent, received) = tokio::join!(client.send_msg(data), server.recv_msg());
+                            sent.unwrap();
+                            assert_eq!(received.unwrap().len(), data.len());
+                        })
+                    });
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, round_trip, one_way);
+criterion_main!(benches);
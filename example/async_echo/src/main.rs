@@ -0,0 +1,71 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use virga::client::client_async::VirgeClientAsync;
+use virga::client::ClientConfig;
+use virga::server::{ServerConfig, ServerManager};
+use virga::VirgeServerAsync;
+
+/// 以字节流原样回显，直到客户端关闭写方向
+async fn echo(mut server: VirgeServerAsync) -> std::io::Result<()> {
+    let mut buf = vec![0u8; 4096];
+    loop {
+        let n = server.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        server.write_all(&buf[..n]).await?;
+        server.flush().await?;
+    }
+    server.shutdown().await
+}
+
+async fn run_server() -> Result<(), Box<dyn std::error::Error>> {
+    let config = ServerConfig::new(ServerConfig::CID_ANY, 1234, 1024, false);
+    let mut manager = ServerManager::new(config);
+    manager.start().await?;
+
+    while let Ok(server) = manager.accept().await {
+        tokio::spawn(async move {
+            if let Err(e) = echo(VirgeServerAsync::from(server)).await {
+                eprintln!("回显失败: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn run_client() -> Result<(), Box<dyn std::error::Error>> {
+    let config = ClientConfig::new(2, 1234, 1024, false);
+    let mut client = VirgeClientAsync::new(config);
+    client.connect().await?;
+
+    // 标准输入经 io::copy 写入连接，回显按原长度读回
+    let mut input = Vec::new();
+    tokio::io::stdin().read_to_end(&mut input).await?;
+    let copied = tokio::io::copy(&mut &input[..], &mut client).await?;
+    client.flush().await?;
+
+    let mut echoed = vec![0u8; copied as usize];
+    client.read_exact(&mut echoed).await?;
+    if echoed != input {
+        return Err("echoed data differs from the input".into());
+    }
+    println!("echoed {} bytes", echoed.len());
+
+    // 关闭写方向后服务器结束回显，读到 EOF
+    client.shutdown().await?;
+    let mut rest = Vec::new();
+    client.read_to_end(&mut rest).await?;
+    client.disconnect().await?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    match std::env::args().nth(1).as_deref() {
+        Some("server") => run_server().await,
+        Some("client") | None => run_client().await,
+        Some(other) => Err(format!("unknown mode {:?}, expected server or client", other).into()),
+    }
+}
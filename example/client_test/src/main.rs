@@ -8,8 +8,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut client = VirgeClient::new(config);
     client.connect().await?;
     
-    client.send(vec![1; 512]).await?;
-    let data = client.recv().await?;
+    client.send_msg(&[1; 512]).await?;
+    let data = client.recv_msg().await?;
     println!("{}", data.len());
     
     client.disconnect().await?;
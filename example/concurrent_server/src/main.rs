@@ -0,0 +1,56 @@
+use futures::StreamExt;
+use virga::server::{ServerConfig, ServerManager, VirgeServer};
+
+/// 同时处理的最大连接数
+const MAX_CONCURRENT: usize = 4;
+
+/// 用法：concurrent_server [port]
+///
+/// 以 `incoming()` 流接受连接，`for_each_concurrent` 限制同时处理的连接数；
+/// 达到上限时流不再被轮询，新连接留在内核队列中。按 Ctrl-C 停止服务器，流随之结束。
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let port: u32 = std::env::args().nth(1).map(|port| port.parse()).transpose()?.unwrap_or(1234);
+    let config = ServerConfig::builder().listen_port(port).build()?;
+    let mut manager = ServerManager::new(config);
+    manager.start().await?;
+
+    let stop = manager.stop_handle().ok_or("server not started")?;
+    tokio::spawn(async move {
+        let _ = tokio::signal::ctrl_c().await;
+        stop.stop();
+    });
+
+    manager
+        .incoming()
+        .for_each_concurrent(MAX_CONCURRENT, |accepted| async move {
+            let server = match accepted {
+                Ok(server) => server,
+                Err(e) => {
+                    eprintln!("接受连接失败: {}", e);
+                    return;
+                }
+            };
+            // 连接在独立任务中处理，阻塞式的 accept 不会拖住正在处理的连接
+            if let Err(e) = tokio::spawn(echo(server)).await {
+                eprintln!("连接处理任务异常退出: {}", e);
+            }
+        })
+        .await;
+
+    println!("server stopped");
+    Ok(())
+}
+
+/// 原样回显收到的每条消息，直到客户端断开
+async fn echo(mut server: VirgeServer) {
+    println!("connection #{} from {:?}", server.id(), server.peer_addr());
+    while let Ok(data) = server.recv().await {
+        if data.is_empty() || server.send(data).await.is_err() {
+            break;
+        }
+    }
+    let _ = server.disconnect().await;
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+use virga::client::{VirgeClient, ClientConfig};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let mut clients = Vec::new();
+    for _ in 0..2 {
+        let mut client = VirgeClient::new(ClientConfig::new(2, 1234, 1024, false));
+        client.connect().await?;
+        clients.push(client);
+    }
+
+    for (i, client) in clients.iter_mut().enumerate() {
+        while client.try_send(&vec![i as u8; 512]).await?.is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    // 类 select 的轮询循环：任意客户端有数据即处理，都没有数据时短暂休眠
+    let mut pending = clients.len();
+    while pending > 0 {
+        let mut idle = true;
+        for (i, client) in clients.iter_mut().enumerate() {
+            if let Some(data) = client.try_recv().await? {
+                println!("client {} received {} bytes", i, data.len());
+                pending -= 1;
+                idle = false;
+            }
+        }
+        if idle {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    for client in clients.iter_mut() {
+        client.disconnect().await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use std::time::Duration;
+use virga::client::{VirgeClient, ClientConfig};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let config = ClientConfig::new(2, 1234, 1024, false).with_call_timeout(Duration::from_secs(5));
+    let mut client = VirgeClient::new(config);
+    client.connect().await?;
+
+    for request in ["hello", "virga", "rpc"] {
+        let response = client.call(request.as_bytes()).await?;
+        println!("{} -> {}", request, String::from_utf8_lossy(&response));
+    }
+
+    client.disconnect().await?;
+    Ok(())
+}
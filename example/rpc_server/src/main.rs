@@ -0,0 +1,26 @@
+use virga::server::{ServerManager, ServerConfig};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let config = ServerConfig::new(ServerConfig::CID_ANY, 1234, 1024, false);
+
+    let mut manager = ServerManager::new(config);
+    manager.start().await?;
+
+    while let Ok(mut server) = manager.accept().await {
+        println!("new rpc connection from {:?}", server.peer_addr());
+        tokio::spawn(async move {
+            // 将请求原样转为大写后返回
+            let result = server
+                .serve_requests(|request| request.to_ascii_uppercase())
+                .await;
+            if let Err(e) = result {
+                eprintln!("处理请求失败: {}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
@@ -4,7 +4,7 @@ use virga::server::{ServerManager, ServerConfig};
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
-    let config = ServerConfig::new(0xFFFFFFFF, 1234, 1024, false);
+    let config = ServerConfig::new(ServerConfig::CID_ANY, 1234, 1024, false);
 
     let mut manager = ServerManager::new(config);
     manager.start().await?;
@@ -16,8 +16,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             if server.is_connected(){
                 println!("after get virga server, the server is connected");
             }
-            let data_result = server.recv().await;
-            println!("server.recv");
+            let data_result = server.recv_msg().await;
+            println!("server.recv_msg");
             let data = match data_result {
                 Ok(data) => data,
                 Err(e) => {
@@ -28,7 +28,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("len date = {}", data.len());
             
             // 处理发送数据
-            if let Err(e) = server.send(data).await {
+            if let Err(e) = server.send_msg(&data).await {
                 eprintln!("发送数据失败: {}", e);
             }
             
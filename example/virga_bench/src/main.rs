@@ -0,0 +1,146 @@
+use std::time::{Duration, Instant};
+use virga::client::{ClientConfig, VirgeClient};
+use virga::server::{ServerConfig, ServerManager, VirgeServer};
+use virga::TransportKind;
+
+/// 扫描的数据块大小
+const CHUNK_SIZES: [u32; 3] = [1024, 16 * 1024, 64 * 1024];
+/// 扫描的消息大小
+const MESSAGE_SIZES: [usize; 4] = [64, 1024, 64 * 1024, 1024 * 1024];
+
+/// 用法：virga_bench [memory|tcp] [iterations]
+///
+/// 对每组 chunk_size 与消息大小测量往返延迟（逐条 send/recv）与吞吐量（拆分读写后流水线发送），
+/// 服务器端原样回显每条消息。
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let mut args = std::env::args().skip(1);
+    let transport = args.next().unwrap_or_else(|| "memory".to_string());
+    let iterations: usize = args.next().map(|n| n.parse()).transpose()?.unwrap_or(200);
+    if transport != "memory" && transport != "tcp" {
+        return Err(format!("unknown transport {}, expected memory or tcp", transport).into());
+    }
+
+    println!("transport={} iterations={}", transport, iterations);
+    println!(
+        "{:>10} {:>10} {:>12} {:>12} {:>12}",
+        "chunk", "message", "avg (us)", "p99 (us)", "MiB/s"
+    );
+
+    for chunk_size in CHUNK_SIZES {
+        for message_size in MESSAGE_SIZES {
+            let client = connect(&transport, chunk_size).await?;
+            let (latencies, client) = measure_latency(client, message_size, iterations).await?;
+            let throughput = measure_throughput(client, message_size, iterations).await?;
+
+            let avg = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+            let p99 = latencies[(latencies.len() * 99 / 100).min(latencies.len() - 1)];
+            println!(
+                "{:>10} {:>10} {:>12.1} {:>12.1} {:>12.1}",
+                chunk_size,
+                message_size,
+                avg.as_secs_f64() * 1e6,
+                p99.as_secs_f64() * 1e6,
+                throughput
+            );
+        }
+    }
+    Ok(())
+}
+
+/// 建立一对相连的客户端与回显服务器
+async fn connect(transport: &str, chunk_size: u32) -> Result<VirgeClient, Box<dyn std::error::Error>> {
+    let client_config = ClientConfig::builder().chunk_size(chunk_size).build()?;
+
+    if transport == "memory" {
+        let (client, server) = VirgeClient::new_in_memory(client_config).await?;
+        tokio::spawn(echo(server));
+        return Ok(client);
+    }
+
+    let server_config = ServerConfig::builder()
+        .listen_port(0)
+        .chunk_size(chunk_size)
+        .build()?
+        .with_transport_kind(TransportKind::Tcp);
+    let mut manager = ServerManager::new(server_config);
+    manager.start().await?;
+    let port = manager.local_addr()?.port();
+    tokio::spawn(async move {
+        if let Ok(server) = manager.accept().await {
+            echo(server).await;
+        }
+    });
+
+    let client_config = ClientConfig::builder()
+        .server_cid(ClientConfig::CID_LOCAL)
+        .server_port(port)
+        .chunk_size(chunk_size)
+        .build()?
+        .with_transport_kind(TransportKind::Tcp);
+    let mut client = VirgeClient::new(client_config);
+    client.connect().await?;
+    Ok(client)
+}
+
+/// 原样回显收到的每条消息，直到客户端断开
+async fn echo(mut server: VirgeServer) {
+    while let Ok(data) = server.recv().await {
+        if data.is_empty() || server.send(data).await.is_err() {
+            break;
+        }
+    }
+    let _ = server.disconnect().await;
+}
+
+/// 逐条发送并等待回显，返回排序后的往返耗时
+async fn measure_latency(
+    mut client: VirgeClient,
+    message_size: usize,
+    iterations: usize,
+) -> Result<(Vec<Duration>, VirgeClient), Box<dyn std::error::Error>> {
+    let payload = vec![0xA5; message_size];
+    let mut latencies = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        client.send(payload.clone()).await?;
+        let mut received = 0;
+        while received < message_size {
+            received += client.recv().await?.len();
+        }
+        latencies.push(start.elapsed());
+    }
+    latencies.sort();
+    Ok((latencies, client))
+}
+
+/// 拆分读写后连续发送 `iterations` 条消息并接收全部回显，返回单向吞吐量（MiB/s）
+async fn measure_throughput(
+    client: VirgeClient,
+    message_size: usize,
+    iterations: usize,
+) -> Result<f64, Box<dyn std::error::Error>> {
+    let (mut reader, mut writer) = client.split();
+    let payload = vec![0x5A; message_size];
+    let start = Instant::now();
+
+    let sender = tokio::spawn(async move {
+        for _ in 0..iterations {
+            writer.send(payload.clone()).await?;
+        }
+        Ok::<_, virga::VirgeError>(writer)
+    });
+
+    let total = message_size * iterations;
+    let mut received = 0;
+    while received < total {
+        received += reader.recv().await?.len();
+    }
+    let elapsed = start.elapsed();
+
+    let mut writer = sender.await??;
+    writer.disconnect().await?;
+    Ok(total as f64 / (1024.0 * 1024.0) / elapsed.as_secs_f64())
+}
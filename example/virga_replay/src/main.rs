@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::time::UNIX_EPOCH;
+use virga::protocol::{FrameHeader, Layer, CHECKSUM_SIZE};
+use virga::transport::{RecordedEvent, RecordedLayers};
+use virga::TrafficLog;
+
+/// 每帧最多显示的负载字节数
+const PREVIEW: usize = 32;
+
+/// 用法：virga-replay <log> [conn]
+///
+/// 读取 `with_recorder` 录制的流量日志，逐帧打印时间、方向与按录制时的包装器组合解出的各层帧头，
+/// 只显示编号为 `conn` 的连接，省略时显示全部连接。
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let Some(path) = args.next() else {
+        return Err("usage: virga-replay <log> [conn]".into());
+    };
+    let only: Option<u32> = args.next().map(|conn| conn.parse()).transpose()?;
+
+    let log = TrafficLog::open(&path)?;
+    let started = log.started.duration_since(UNIX_EPOCH).unwrap_or_default();
+    println!(
+        "{}: {} records, {} connections, started at {}.{:06} (unix)",
+        path,
+        log.records.len(),
+        log.connections().len(),
+        started.as_secs(),
+        started.subsec_micros()
+    );
+
+    let mut layers: HashMap<u32, RecordedLayers> = HashMap::new();
+    // 每个连接每个方向上是否处于一条普通消息的中间分片之后
+    let mut continuing: HashMap<(u32, bool), bool> = HashMap::new();
+    for record in log.records.iter().filter(|record| only.is_none_or(|conn| conn == record.conn)) {
+        let at = format!("{:>10.6}", record.at.as_secs_f64());
+        match &record.event {
+            RecordedEvent::Open(opened) => {
+                layers.insert(record.conn, *opened);
+                println!("{} conn {} open [{}]", at, record.conn, describe_layers(opened));
+            }
+            RecordedEvent::Connect => {
+                continuing.retain(|&(conn, _), _| conn != record.conn);
+                println!("{} conn {} connect", at, record.conn);
+            }
+            RecordedEvent::Disconnect => println!("{} conn {} disconnect", at, record.conn),
+            RecordedEvent::Send(frame) | RecordedEvent::Recv(frame) => {
+                let send = matches!(record.event, RecordedEvent::Send(_));
+                let layers = layers.get(&record.conn).copied().unwrap_or_default();
+                let continuing = continuing.entry((record.conn, send)).or_default();
+                println!(
+                    "{} conn {} {} {:>6} bytes  {}",
+                    at,
+                    record.conn,
+                    if send { "send" } else { "recv" },
+                    frame.len(),
+                    describe_frame(&layers, continuing, frame)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn describe_layers(layers: &RecordedLayers) -> String {
+    let names = [
+        (layers.encryption, "encryption"),
+        (layers.integrity, "integrity"),
+        (layers.compression, "compression"),
+        (layers.keepalive, "keepalive"),
+        (layers.ack, "ack"),
+        (layers.lanes, "lanes"),
+        (layers.sequence, "sequence"),
+        (layers.metadata, "metadata"),
+    ];
+    let enabled: Vec<&str> = names.iter().filter(|(enabled, _)| *enabled).map(|(_, name)| *name).collect();
+    if enabled.is_empty() {
+        "none".to_string()
+    } else {
+        enabled.join(", ")
+    }
+}
+
+/// 由内到外逐层解析一帧，返回以 ` | ` 分隔的各层帧头与负载预览
+fn describe_frame(layers: &RecordedLayers, continuing: &mut bool, frame: &[u8]) -> String {
+    if frame.is_empty() {
+        return "(empty)".to_string();
+    }
+    if layers.encryption {
+        return "(encrypted)".to_string();
+    }
+    let mut parts = Vec::new();
+    let mut rest = frame;
+    if layers.integrity {
+        let Some(split) = rest.len().checked_sub(CHECKSUM_SIZE) else {
+            return "(shorter than the checksum)".to_string();
+        };
+        let (body, crc) = rest.split_at(split);
+        parts.push(format!("Crc32(0x{})", hex(crc)));
+        rest = body;
+    }
+
+    let mut message_start = true;
+    let stack = [
+        (layers.compression, Layer::Compression),
+        (layers.keepalive, Layer::Keepalive),
+        (layers.ack, Layer::Ack),
+        (layers.lanes, Layer::Lane),
+    ];
+    for (_, layer) in stack.into_iter().filter(|&(enabled, _)| enabled) {
+        let header = match FrameHeader::decode(layer, rest) {
+            Ok((header, len)) => {
+                rest = &rest[len..];
+                header
+            }
+            Err(e) => {
+                parts.push(format!("<{}>", e));
+                return parts.join(" | ");
+            }
+        };
+        parts.push(format!("{:?}", header));
+        match header {
+            // 压缩后的负载需要对应的解压实现，不再向内解析
+            FrameHeader::Compressed { .. } => {
+                parts.push(format!("{} compressed bytes", rest.len()));
+                return parts.join(" | ");
+            }
+            // 控制帧没有更内层的数据
+            FrameHeader::Ping | FrameHeader::Pong | FrameHeader::Ack { .. } => return parts.join(" | "),
+            FrameHeader::BulkMore => {
+                message_start = !*continuing;
+                *continuing = true;
+            }
+            FrameHeader::BulkLast => {
+                message_start = !*continuing;
+                *continuing = false;
+            }
+            // 优先消息不携带序号与元数据
+            FrameHeader::Priority => message_start = false,
+            _ => {}
+        }
+    }
+
+    // 序号与元数据只随一条普通消息的第一片出现
+    if message_start {
+        if layers.sequence {
+            match FrameHeader::decode(Layer::Sequence, rest) {
+                Ok((header, len)) => {
+                    parts.push(format!("{:?}", header));
+                    rest = &rest[len..];
+                }
+                Err(e) => parts.push(format!("<{}>", e)),
+            }
+        }
+        if layers.metadata {
+            match FrameHeader::decode(Layer::Metadata, rest) {
+                Ok((header @ FrameHeader::Metadata { len }, header_len)) => {
+                    parts.push(format!("{:?}", header));
+                    rest = &rest[(header_len + len as usize).min(rest.len())..];
+                }
+                Ok(_) => {}
+                Err(e) => parts.push(format!("<{}>", e)),
+            }
+        }
+    }
+    parts.push(preview(rest));
+    parts.join(" | ")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// 负载的十六进制与可打印字符预览
+fn preview(payload: &[u8]) -> String {
+    let shown = &payload[..payload.len().min(PREVIEW)];
+    let text: String = shown
+        .iter()
+        .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+        .collect();
+    let ellipsis = if payload.len() > shown.len() { " ..." } else { "" };
+    format!("{} bytes {}{} \"{}\"", payload.len(), hex(shown), ellipsis, text)
+}
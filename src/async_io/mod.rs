@@ -0,0 +1,320 @@
+//! tokio IO 适配模块
+//!
+//! `VirgeClient`/`VirgeServer` 的接口本身是 `async fn`，但 `tokio::io::copy`、`BufReader`、
+//! `AsyncBufReadExt::lines` 等工具需要基于 poll 的 `AsyncRead`/`AsyncBufRead`/`AsyncWrite`。
+//! [`AsyncIo`] 持有一个连接，在 poll 时把连接移入进行中的操作，操作完成后取回，
+//! 将这些 trait 接到连接已有的 `fill_buf`/`consume`/`write`/`flush`/`shutdown_write` 上。
+//!
+//! # 语义
+//! - 读取为字节流语义：消息边界不会表现为 EOF，对端关闭连接或关闭写方向且数据读完后返回 `Ok(0)`
+//! - 每次 `poll_write` 的数据经 `write` 按 `chunk_size` 分为消息发送（启用 `with_batching` 时先进入批次缓冲区），
+//!   中途失败时返回已完整发出的字节数，错误留给下一次写入；`poll_write_vectored` 将各缓冲区合并为一条消息；
+//!   `poll_shutdown` 关闭写方向
+//! - 同一时刻只有一个进行中的操作，等待数据的读取完成前写入也需等待；需要同时收发时使用 `split`
+//! - 错误按 `From<VirgeError> for io::Error` 转换，原始的 `VirgeError` 作为 payload 保留
+
+use crate::client::{ClientConfig, VirgeClient};
+use crate::error::{Result, VirgeError};
+use crate::server::VirgeServer;
+use std::io::{self, IoSlice};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+
+mod sealed {
+    use crate::error::Result;
+    use async_trait::async_trait;
+
+    /// 可由 `AsyncIo` 适配的连接，仅由 `VirgeClient` 与 `VirgeServer` 实现
+    #[async_trait]
+    pub trait Connection: Send + 'static {
+        /// 已收到但尚未读取的数据
+        fn buffered(&self) -> &[u8];
+        fn consume(&mut self, amt: usize);
+        /// 缓冲区为空时从传输层接收，返回后缓冲区仍为空表示流结束
+        async fn fill(&mut self) -> Result<()>;
+        async fn write(&mut self, data: Vec<u8>) -> Result<usize>;
+        async fn flush(&mut self) -> Result<()>;
+        async fn shutdown(&mut self) -> Result<()>;
+    }
+}
+
+use sealed::Connection;
+
+/// 基于 `VirgeClient` 的 tokio IO 适配器
+pub type VirgeClientAsync = AsyncIo<VirgeClient>;
+
+/// 基于 `VirgeServer` 的 tokio IO 适配器
+pub type VirgeServerAsync = AsyncIo<VirgeServer>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Fill,
+    Write,
+    Flush,
+    Shutdown,
+}
+
+/// 一次操作的结果，只有写入返回有意义的字节数
+struct Done {
+    kind: Kind,
+    result: Result<usize>,
+}
+
+type Pending<T> = Pin<Box<dyn Future<Output = (Box<T>, Done)> + Send>>;
+
+/// 为 `VirgeClient`/`VirgeServer` 实现 `AsyncRead`、`AsyncBufRead` 与 `AsyncWrite` 的适配器
+///
+/// 由 `From` 创建，`get_mut` 与 `into_inner` 在进行中的操作完成后取回连接，
+/// 以便继续使用按消息收发的接口。
+pub struct AsyncIo<T> {
+    /// 没有进行中的操作时持有连接
+    inner: Option<Box<T>>,
+    /// 进行中的操作，完成后归还连接
+    pending: Option<Pending<T>>,
+    /// 由其他 poll 驱动完成、尚未交给发起方的操作结果
+    completed: Vec<Done>,
+}
+
+impl<T> From<T> for AsyncIo<T> {
+    fn from(inner: T) -> Self {
+        Self { inner: Some(Box::new(inner)), pending: None, completed: Vec::new() }
+    }
+}
+
+impl<T: Connection> AsyncIo<T> {
+    /// 等待进行中的操作完成后返回连接，该操作的结果仍由之后对应的 poll 返回
+    pub async fn get_mut(&mut self) -> &mut T {
+        if let Some(pending) = self.pending.take() {
+            let (inner, done) = pending.await;
+            self.inner = Some(inner);
+            self.completed.push(done);
+        }
+        self.connection()
+    }
+
+    /// 等待进行中的操作完成后取回连接，未读取的数据保留在连接中
+    pub async fn into_inner(mut self) -> T {
+        self.get_mut().await;
+        *self.inner.take().expect("AsyncIo holds the connection once no operation is pending")
+    }
+
+    fn connection(&mut self) -> &mut T {
+        self.inner.as_deref_mut().expect("AsyncIo holds the connection once no operation is pending")
+    }
+
+    /// 驱动 `kind` 类的操作直到完成，没有进行中或已完成的同类操作时以 `start` 发起
+    ///
+    /// 先完成的其他类操作的结果暂存，交给之后发起它的 poll。接收的结果需要读取连接的缓冲区，
+    /// 返回前先等待其后发起的操作完成。
+    fn poll_op<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        kind: Kind,
+        start: impl FnOnce(Box<T>) -> F,
+    ) -> Poll<io::Result<usize>>
+    where
+        F: Future<Output = (Box<T>, Result<usize>)> + Send + 'static,
+    {
+        let mut start = Some(start);
+        loop {
+            let found = self.completed.iter().position(|done| done.kind == kind);
+            if let Some(i) = found.filter(|_| kind != Kind::Fill || self.pending.is_none()) {
+                return Poll::Ready(self.completed.remove(i).result.map_err(io::Error::from));
+            }
+            if let Some(pending) = self.pending.as_mut() {
+                let (inner, done) = ready!(pending.as_mut().poll(cx));
+                self.pending = None;
+                self.inner = Some(inner);
+                self.completed.push(done);
+                continue;
+            }
+            let inner = self.inner.take().expect("AsyncIo holds the connection once no operation is pending");
+            let start = start.take().expect("an operation is started at most once per poll");
+            let future = start(inner);
+            self.pending = Some(Box::pin(async move {
+                let (inner, result) = future.await;
+                (inner, Done { kind, result })
+            }));
+        }
+    }
+
+    fn poll_write_owned(&mut self, cx: &mut Context<'_>, data: impl FnOnce() -> Vec<u8>) -> Poll<io::Result<usize>> {
+        self.poll_op(cx, Kind::Write, |mut inner| {
+            let data = data();
+            async move {
+                let result = inner.write(data).await;
+                (inner, result)
+            }
+        })
+    }
+}
+
+impl VirgeClientAsync {
+    /// 创建尚未连接的客户端，与 `VirgeClient::new` 相同
+    pub fn new(config: ClientConfig) -> Self {
+        Self::from(VirgeClient::new(config))
+    }
+
+    /// 连接服务器，见 `VirgeClient::connect`
+    pub async fn connect(&mut self) -> Result<()> {
+        self.get_mut().await.connect().await
+    }
+
+    /// 发送一条消息，见 `VirgeClient::send`
+    pub async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        self.get_mut().await.send(data).await
+    }
+
+    /// 接收一条消息，见 `VirgeClient::recv`
+    pub async fn recv(&mut self) -> Result<Vec<u8>> {
+        self.get_mut().await.recv().await
+    }
+
+    /// 断开连接，见 `VirgeClient::disconnect`
+    pub async fn disconnect(&mut self) -> Result<()> {
+        self.get_mut().await.disconnect().await
+    }
+}
+
+impl<T: Connection> AsyncBufRead for AsyncIo<T> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        let buffered =
+            this.pending.is_none() && this.inner.as_ref().is_some_and(|inner| !inner.buffered().is_empty());
+        if !buffered {
+            ready!(this.poll_op(cx, Kind::Fill, |mut inner| async move {
+                let result = inner.fill().await.map(|()| 0);
+                (inner, result)
+            }))?;
+        }
+        Poll::Ready(Ok(this.connection().buffered()))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        if let Some(inner) = self.get_mut().inner.as_deref_mut() {
+            inner.consume(amt);
+        }
+    }
+}
+
+impl<T: Connection> AsyncRead for AsyncIo<T> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        let this = self.get_mut();
+        let data = ready!(Pin::new(&mut *this).poll_fill_buf(cx))?;
+        let n = data.len().min(buf.remaining());
+        buf.put_slice(&data[..n]);
+        this.connection().consume(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: Connection> AsyncWrite for AsyncIo<T> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        self.get_mut().poll_write_owned(cx, || buf.to_vec())
+    }
+
+    /// 各缓冲区合并为一条消息，对端的一次 `recv()` 即收到全部数据
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Poll::Ready(Ok(0));
+        }
+        self.get_mut().poll_write_owned(cx, || bufs.concat())
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let flushed = self.get_mut().poll_op(cx, Kind::Flush, |mut inner| async move {
+            let result = inner.flush().await.map(|()| 0);
+            (inner, result)
+        });
+        ready!(flushed)?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let shutdown = self.get_mut().poll_op(cx, Kind::Shutdown, |mut inner| async move {
+            let result = inner.shutdown().await.map(|()| 0);
+            (inner, result)
+        });
+        ready!(shutdown)?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// 与 `io::Write::write` 的约定一致，部分写入时返回已发出的字节数而不是错误
+fn committed(written: Result<usize>) -> Result<usize> {
+    match written {
+        Err(VirgeError::PartialWrite { committed, .. }) => Ok(committed),
+        written => written,
+    }
+}
+
+#[async_trait::async_trait]
+impl Connection for VirgeClient {
+    fn buffered(&self) -> &[u8] {
+        self.buffered()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        VirgeClient::consume(self, amt);
+    }
+
+    async fn fill(&mut self) -> Result<()> {
+        self.fill_buf().await.map(|_| ())
+    }
+
+    async fn write(&mut self, data: Vec<u8>) -> Result<usize> {
+        committed(VirgeClient::write(self, &data).await)
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        VirgeClient::flush(self).await
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        VirgeClient::flush(self).await?;
+        self.shutdown_write().await
+    }
+}
+
+#[async_trait::async_trait]
+impl Connection for VirgeServer {
+    fn buffered(&self) -> &[u8] {
+        self.buffered()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        VirgeServer::consume(self, amt);
+    }
+
+    async fn fill(&mut self) -> Result<()> {
+        self.fill_buf().await.map(|_| ())
+    }
+
+    async fn write(&mut self, data: Vec<u8>) -> Result<usize> {
+        committed(VirgeServer::write(self, &data).await)
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        VirgeServer::flush(self).await
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        VirgeServer::flush(self).await?;
+        self.shutdown_write().await
+    }
+}
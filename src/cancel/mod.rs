@@ -0,0 +1,54 @@
+//! 接收取消模块
+//!
+//! `CancelToken` 可在任意线程或任务中取消正在等待的 `recv_cancellable`。
+//!
+//! # 机制
+//! - 等待期间以非阻塞的 `try_recv` 轮询传输层，每轮之间检查取消标记，
+//!   因此取消后至多一个轮询间隔（10ms）即返回
+//! - 被取消时不会中断进行到一半的读取，已到达的数据保留在传输层中，连接保持可用
+
+use crate::error::{Result, VirgeError};
+use crate::split::{POLL_INTERVAL_MAX, POLL_INTERVAL_MIN};
+use crate::transport::Transport;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// 接收取消令牌，克隆出的令牌共享同一个取消状态
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 取消所有使用该令牌的接收，之后使用该令牌的接收立即返回
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// 是否已被取消
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// 轮询传输层直到收到一条消息，`token` 被取消时返回 `ErrorKind::Interrupted`
+pub(crate) async fn recv(transport: &mut dyn Transport, token: &CancelToken) -> Result<Vec<u8>> {
+    let mut interval = POLL_INTERVAL_MIN;
+    loop {
+        if token.is_cancelled() {
+            return Err(VirgeError::IoError(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "recv cancelled",
+            )));
+        }
+        if let Some(data) = transport.try_recv().await? {
+            return Ok(data);
+        }
+        crate::transport::sleep(interval).await;
+        interval = (interval * 2).min(POLL_INTERVAL_MAX);
+    }
+}
@@ -0,0 +1,143 @@
+//! 类型化双向通道模块
+//!
+//! `VirgaChannel<S, R>` 包装一个 `VirgeClient` 或 `VirgeServer`，发送 `S` 类型的值、接收 `R` 类型的值，
+//! 用法接近跨越虚拟机边界的 mpsc 通道。
+//!
+//! # 机制
+//! - 每个值按连接配置的 `WireFormat` 编码，再加 8 字节大端长度前缀发送，与 `send_msg` / `recv_msg` 的帧格式一致
+//! - 接收端在通道内部缓冲不完整的消息，`try_recv` 不会丢弃已到达的部分数据
+//! - 解码失败返回 `VirgeError::CodecError`，附带消息长度与 serde 错误；该消息被丢弃，通道保持可用
+
+use crate::client::VirgeClient;
+use crate::codec::{self, WireFormat};
+use crate::error::{Result, VirgeError};
+use crate::server::VirgeServer;
+use crate::transport::framing;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+
+/// 通道底层的连接
+enum Endpoint {
+    Client(VirgeClient),
+    Server(VirgeServer),
+}
+
+/// 类型化双向通道：发送 `S`，接收 `R`
+///
+/// 两端的类型需互相对应：一端的 `VirgaChannel<A, B>` 对应另一端的 `VirgaChannel<B, A>`。
+pub struct VirgaChannel<S, R> {
+    endpoint: Endpoint,
+    format: WireFormat,
+    max_message_size: usize,
+    /// 已收到但尚不足一条完整消息的数据
+    pending: Vec<u8>,
+    _types: PhantomData<fn(&S) -> R>,
+}
+
+impl<S: Serialize, R: DeserializeOwned> VirgaChannel<S, R> {
+    /// 以已连接的客户端创建通道，编码格式与消息大小上限取自客户端配置
+    pub fn from_client(client: VirgeClient) -> Self {
+        let (format, max_message_size) = client.codec_options();
+        Self::new(Endpoint::Client(client), format, max_message_size)
+    }
+
+    /// 以已接受的服务器连接创建通道，编码格式与消息大小上限取自服务器配置
+    pub fn from_server(server: VirgeServer) -> Self {
+        let (format, max_message_size) = server.codec_options();
+        Self::new(Endpoint::Server(server), format, max_message_size)
+    }
+
+    fn new(endpoint: Endpoint, format: WireFormat, max_message_size: usize) -> Self {
+        Self {
+            endpoint,
+            format,
+            max_message_size,
+            pending: Vec::new(),
+            _types: PhantomData,
+        }
+    }
+
+    /// 编码并发送一个值
+    pub async fn send(&mut self, value: &S) -> Result<()> {
+        let data = codec::encode(self.format, value, self.max_message_size)?;
+        match &mut self.endpoint {
+            Endpoint::Client(client) => client.send_msg(&data).await,
+            Endpoint::Server(server) => server.send_msg(&data).await,
+        }
+    }
+
+    /// 接收并解码一个值，对端关闭写方向或断开时返回错误
+    pub async fn recv(&mut self) -> Result<R> {
+        loop {
+            if let Some(value) = self.take_value()? {
+                return Ok(value);
+            }
+            let data = match &mut self.endpoint {
+                Endpoint::Client(client) => client.recv().await?,
+                Endpoint::Server(server) => server.recv().await?,
+            };
+            self.extend(data)?;
+        }
+    }
+
+    /// 非阻塞接收一个值，尚无完整消息时返回 `Ok(None)`
+    pub async fn try_recv(&mut self) -> Result<Option<R>> {
+        loop {
+            if let Some(value) = self.take_value()? {
+                return Ok(Some(value));
+            }
+            let data = match &mut self.endpoint {
+                Endpoint::Client(client) => client.try_recv().await?,
+                Endpoint::Server(server) => server.try_recv().await?,
+            };
+            match data {
+                Some(data) => self.extend(data)?,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// 连接是否仍然可用
+    pub fn is_connected(&self) -> bool {
+        match &self.endpoint {
+            Endpoint::Client(client) => client.is_connected(),
+            Endpoint::Server(server) => server.is_connected(),
+        }
+    }
+
+    /// 断开底层连接
+    pub async fn disconnect(&mut self) -> Result<()> {
+        match &mut self.endpoint {
+            Endpoint::Client(client) => client.disconnect().await,
+            Endpoint::Server(server) => server.disconnect().await,
+        }
+    }
+
+    /// 从缓冲区取出一条完整消息并解码
+    fn take_value(&mut self) -> Result<Option<R>> {
+        let Some(message) = framing::take_message(&mut self.pending, self.max_message_size)? else {
+            return Ok(None);
+        };
+        codec::decode(self.format, &message, self.max_message_size)
+            .map(Some)
+            .map_err(|e| match e {
+                VirgeError::CodecError(msg) => {
+                    VirgeError::CodecError(format!("{} ({} byte payload)", msg, message.len()))
+                }
+                e => e,
+            })
+    }
+
+    /// 追加收到的数据，空数据表示对端已关闭写方向
+    fn extend(&mut self, data: Vec<u8>) -> Result<()> {
+        if data.is_empty() {
+            if !self.pending.is_empty() {
+                return Err(framing::unexpected_eof());
+            }
+            return Err(VirgeError::Disconnected("peer closed the channel".to_string()));
+        }
+        self.pending.extend_from_slice(&data);
+        Ok(())
+    }
+}
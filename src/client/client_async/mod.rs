@@ -0,0 +1,7 @@
+//! 客户端的 tokio IO 适配
+//!
+//! [`VirgeClientAsync`] 即 [`crate::async_io::AsyncIo`]`<VirgeClient>`：`connect`/`send`/`recv`/`disconnect`
+//! 与 `VirgeClient` 一致，并实现 `tokio::io::AsyncRead`/`AsyncBufRead`/`AsyncWrite`，
+//! 读取与 `VirgeClient::fill_buf`/`consume` 共用同一个接收缓冲区，语义见 [`crate::async_io`]。
+
+pub use crate::async_io::VirgeClientAsync;
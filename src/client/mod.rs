@@ -6,18 +6,103 @@
 //! - 封装客户端的连接逻辑
 //! - 提供简洁的发送/接收接口
 //! - 管理传输协议选择
+//!
+//! # 异步运行时
+//! `VirgeClient` 的接口本身即为 `async fn`，可直接在 tokio 应用中 `.await`。需要 `tokio::io::AsyncRead`/
+//! `AsyncWrite` 的场合（如 `tokio::io::copy`、`BufReader`）使用 [`client_async::VirgeClientAsync`] 包装。
+//! yamux 传输完全非阻塞；xtransport 传输内部为阻塞 IO，在多线程运行时中长时间阻塞的
+//! `recv()` 会占用一个工作线程，必要时可配合 `set_read_timeout` 或 `try_recv` 使用。
 
+use futures::future::BoxFuture;
+use futures::FutureExt;
 use log::*;
-use crate::error::Result;
-use crate::transport::Transport;
+use std::io::{IoSlice, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(feature = "tokio-runtime")]
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+use crate::config::{Layer, CLIENT_KEYS};
+use crate::error::{Result, VirgeError};
+use crate::rpc;
+use crate::split::{self, VirgeReadHalf, VirgeWriteHalf};
+use crate::handle::VirgeClientHandle;
+use crate::cancel::{self, CancelToken};
+use crate::transport::keepalive::KeepaliveConfig;
+use crate::transport::observer::{ObserverHandle, TransportObserver};
+use crate::transport::ratelimit::{Rate, RateLimitTransport};
+use crate::transport::retry::SendRetry;
+use crate::transport::stats::{Stats, StatsCounters, StatsTransport};
+#[cfg(feature = "use-uds")]
+use crate::transport::check_uds_path;
+#[cfg(feature = "use-raw")]
+use crate::transport::check_seqpacket;
+use crate::protocol::{FrameHeader, CHUNK_ABORT_LEN, CHUNK_HEADER_SIZE, LEN_PREFIX_SIZE, MAX_AUTH_TOKEN_SIZE};
+#[cfg(feature = "encryption")]
+use crate::transport::secure::PresharedKey;
+use crate::transport::{
+    check_config, framing, AckStats, AuthToken, HeaderMap, SocketBufferSizes, Transport, TransportKind, TransportOptions,
+    VsockAddr,
+};
+#[cfg(feature = "serde")]
+use crate::codec::{self, WireFormat};
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Serialize};
+#[cfg(feature = "compression")]
+use crate::transport::Compression;
+#[cfg(feature = "testing")]
+use crate::transport::{FaultPlan, ReplayTransport, TrafficRecorder};
+#[cfg(feature = "use-yamux")]
+use crate::transport::{VirgeStream, YamuxConfig};
+#[cfg(feature = "testing")]
+use crate::transport::memory_impl;
+#[cfg(feature = "testing")]
+use crate::server::VirgeServer;
+
+#[cfg(feature = "tokio-runtime")]
+pub mod client_async;
+
+/// 自动重连策略
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// 单次重连最多尝试的次数（不含首次）
+    pub max_retries: u32,
+    /// 首次重试前的等待时间，之后每次翻倍
+    pub backoff: Duration,
+}
 
 /// 客户端配置
-#[derive(Clone, Debug)]
+///
+/// 连接方向不受限制：来宾机连接宿主机时 `server_cid` 为 `CID_HOST`，宿主机连接来宾机内的
+/// 监听服务时为该来宾机的 CID（3 及以上，如 Kata 为沙箱分配的 CID），其余用法完全相同。
+///
+/// 配置可克隆后逐项修改，便于批量生成只有端口等少数参数不同的配置；`Debug` 输出全部参数（包括默认值），
+/// 可直接写入日志描述一次连接。
+///
+/// ```
+/// use virga::ClientConfig;
+///
+/// let base = ClientConfig::default().with_server_cid(ClientConfig::CID_HOST);
+/// let configs: Vec<ClientConfig> = (1234..1238).map(|port| base.clone().with_server_port(port)).collect();
+/// assert_ne!(configs[0], configs[1]);
+/// assert_eq!(configs[0], base.with_server_port(1234));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
 pub struct ClientConfig {
     server_cid: u32,
     server_port: u32,
     chunk_size: u32,
     is_ack: bool,
+    connect_timeout: Option<Duration>,
+    reconnect: Option<ReconnectPolicy>,
+    call_timeout: Duration,
+    /// `write()` 批量合并的最长延迟与字节数阈值，`None` 表示每次写入立即发送
+    batching: Option<(Duration, usize)>,
+    transport_kind: TransportKind,
+    transport_options: TransportOptions,
+    #[cfg(feature = "serde")]
+    wire_format: WireFormat,
 }
 
 impl Default for ClientConfig {
@@ -27,106 +112,2059 @@ impl Default for ClientConfig {
             server_port: crate::DEFAULT_SERVER_PORT as u32,
             chunk_size: crate::DEAFULT_CHUNK_SIZE as u32,
             is_ack: crate::DEFAULT_IS_ACK,
+            connect_timeout: None,
+            reconnect: None,
+            call_timeout: crate::DEFAULT_CALL_TIMEOUT,
+            batching: None,
+            transport_kind: TransportKind::default(),
+            transport_options: TransportOptions::default(),
+            #[cfg(feature = "serde")]
+            wire_format: WireFormat::default(),
         }
     }
 }
 
 impl ClientConfig {
+    /// 虚拟机监控程序的 CID
+    pub const VMADDR_CID_HYPERVISOR: u32 = crate::VMADDR_CID_HYPERVISOR as u32;
+    /// 本机回环的 CID
+    pub const VMADDR_CID_LOCAL: u32 = crate::VMADDR_CID_LOCAL as u32;
+    /// 宿主机的 CID
+    pub const VMADDR_CID_HOST: u32 = crate::VMADDR_CID_HOST as u32;
+    /// 任意 CID，客户端不可使用
+    pub const VMADDR_CID_ANY: u32 = crate::VMADDR_CID_ANY as u32;
+    /// 同 `VMADDR_CID_ANY`
+    pub const CID_ANY: u32 = Self::VMADDR_CID_ANY;
+    /// 同 `VMADDR_CID_HYPERVISOR`
+    pub const CID_HYPERVISOR: u32 = Self::VMADDR_CID_HYPERVISOR;
+    /// 同 `VMADDR_CID_LOCAL`
+    pub const CID_LOCAL: u32 = Self::VMADDR_CID_LOCAL;
+    /// 同 `VMADDR_CID_HOST`
+    pub const CID_HOST: u32 = Self::VMADDR_CID_HOST;
+
+    /// 以具名参数构造配置，`build()` 时校验参数
+    ///
+    /// ```no_run
+    /// # fn main() -> virga::Result<()> {
+    /// use virga::ClientConfig;
+    ///
+    /// let config = ClientConfig::builder()
+    ///     .server_cid(ClientConfig::VMADDR_CID_HOST)
+    ///     .server_port(1234)
+    ///     .chunk_size(64 * 1024)
+    ///     .ack(false)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn builder() -> ClientConfigBuilder {
+        ClientConfigBuilder::default()
+    }
+
+    /// 从 `VIRGA_*` 环境变量读取配置，必须提供 `VIRGA_SERVER_CID` 与 `VIRGA_SERVER_PORT`
+    ///
+    /// 需要与其他来源组合时使用 `builder().env()`。
+    pub fn from_env() -> Result<Self> {
+        let layer = Layer::from_env(CLIENT_KEYS);
+        layer.require(&["server_cid", "server_port"])?;
+        Self::builder().apply(&layer)?.build()
+    }
+
+    /// 从 TOML 配置文件读取配置，必须提供 `server_cid` 与 `server_port`
+    #[cfg(feature = "config-file")]
+    pub fn from_toml_file(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let layer = Layer::from_toml_file(path.as_ref(), CLIENT_KEYS)?;
+        layer.require(&["server_cid", "server_port"])?;
+        Self::builder().apply(&layer)?.build()
+    }
+
+    /// 按位置参数构造配置，参数在 `connect()` 时按与 `builder()` 相同的规则校验
     pub fn new(cid: u32, port: u32, chunk: u32, isack: bool) -> Self{
         Self { 
             server_cid: cid, 
             server_port: port, 
             chunk_size: chunk, 
             is_ack: isack, 
+            connect_timeout: None,
+            reconnect: None,
+            call_timeout: crate::DEFAULT_CALL_TIMEOUT,
+            batching: None,
+            transport_kind: TransportKind::default(),
+            transport_options: TransportOptions::default(),
+            #[cfg(feature = "serde")]
+            wire_format: WireFormat::default(),
+        }
+    }
+
+    /// 校验配置参数，非法时返回 `VirgeError::ConfigError`
+    pub fn validate(&self) -> Result<()> {
+        if self.server_cid == Self::VMADDR_CID_ANY {
+            return Err(VirgeError::ConfigError(
+                "server_cid cannot be VMADDR_CID_ANY".to_string(),
+            ));
+        }
+        if self.server_port == crate::VMADDR_PORT_ANY as u32 {
+            return Err(VirgeError::ConfigError(
+                "server_port cannot be VMADDR_PORT_ANY".to_string(),
+            ));
+        }
+        if matches!(self.batching, Some((_, 0))) {
+            return Err(VirgeError::ConfigError("batching max_bytes must be greater than 0".to_string()));
+        }
+        if self.transport_options.ack_window == 0 {
+            return Err(VirgeError::ConfigError("max_unacked_chunks must be greater than 0".to_string()));
+        }
+        if self.transport_options.send_buffer_limit == Some(0) {
+            return Err(VirgeError::ConfigError("send_buffer_limit must be greater than 0".to_string()));
+        }
+        if let Some(sizes) = &self.transport_options.socket_buffer {
+            sizes.validate()?;
+        }
+        if self.transport_options.send_retry.is_some_and(|retry| retry.max_attempts == 0) {
+            return Err(VirgeError::ConfigError("send_retry max_attempts must be greater than 0".to_string()));
+        }
+        self.transport_options.rate_limit.validate()?;
+        if let Some(AuthToken(token)) = &self.transport_options.auth_token {
+            if !self.transport_options.handshake {
+                return Err(VirgeError::ConfigError("auth_token requires the handshake to be enabled".to_string()));
+            }
+            if token.len() > MAX_AUTH_TOKEN_SIZE {
+                return Err(VirgeError::ConfigError(format!(
+                    "auth_token must be at most {} bytes, got {}",
+                    MAX_AUTH_TOKEN_SIZE,
+                    token.len()
+                )));
+            }
+        }
+        if self.transport_options.uses_encryption() && !self.transport_options.handshake {
+            return Err(VirgeError::ConfigError("encryption requires the handshake to be enabled".to_string()));
+        }
+        if self.transport_options.local_port == Some(crate::VMADDR_PORT_ANY as u32) {
+            return Err(VirgeError::ConfigError(
+                "local_port cannot be VMADDR_PORT_ANY; leave it unset for an ephemeral port".to_string(),
+            ));
+        }
+        #[cfg(feature = "use-uds")]
+        check_uds_path(self.transport_kind, &self.transport_options)?;
+        #[cfg(feature = "use-raw")]
+        check_seqpacket(self.transport_kind, &self.transport_options)?;
+        check_config(self.chunk_size, self.transport_options.max_message_size)
+    }
+
+    /// 设置服务器 CID，见 `ClientConfigBuilder::server_cid`
+    pub fn with_server_cid(mut self, cid: u32) -> Self {
+        self.server_cid = cid;
+        self
+    }
+
+    /// 设置服务器端口
+    pub fn with_server_port(mut self, port: u32) -> Self {
+        self.server_port = port;
+        self
+    }
+
+    /// 设置 xtransport 数据块大小，需在 `MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE` 之间
+    pub fn with_chunk_size(mut self, size: u32) -> Self {
+        self.chunk_size = size;
+        self
+    }
+
+    /// 设置是否启用 ACK，见 `ClientConfigBuilder::ack`
+    pub fn with_ack(mut self, enabled: bool) -> Self {
+        self.is_ack = enabled;
+        self
+    }
+
+    /// 设置连接超时时间，`connect()` 将在超时后返回 `VirgeError::Timeout`
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// 设置连接前绑定的本地 vsock 端口，`None`（默认）由内核分配临时端口
+    ///
+    /// 便于宿主机按来源端口配置防火墙或识别来宾；端口已被占用时 `connect()` 返回 `VirgeError::LocalPortInUse`。
+    /// 断开后内核可能短暂保留该端口，立即重连可能同样返回该错误。仅对 vsock 传输（xtransport、yamux、raw）生效。
+    pub fn with_local_port(mut self, port: Option<u32>) -> Self {
+        self.transport_options.local_port = port;
+        self
+    }
+
+    /// 设置 `call()` 等待响应的超时时间，默认 `DEFAULT_CALL_TIMEOUT`
+    pub fn with_call_timeout(mut self, timeout: Duration) -> Self {
+        self.call_timeout = timeout;
+        self
+    }
+
+    /// 启用 `write()` 批量合并：小的写入先缓冲，累计达到 `max_bytes`、距批次中第一次写入超过
+    /// `max_delay` 或调用 `flush()` 时作为一条消息发出
+    ///
+    /// 没有后台定时任务，超时在下一次 `write()` 时检查；其他收发操作与 `disconnect()` 会先发出
+    /// 缓冲的数据，以保持发送顺序。`send()`/`send_msg()` 等按消息发送的接口不参与合并，
+    /// 对端收到的消息边界不变。`split()`/`handle()` 之前应先调用 `flush()`。
+    pub fn with_batching(mut self, max_delay: Duration, max_bytes: usize) -> Self {
+        self.batching = Some((max_delay, max_bytes));
+        self
+    }
+
+    /// 启用自动重连：send/recv 遇到连接断开时按指数退避重连，并重试一次该操作
+    pub fn with_reconnect(mut self, max_retries: u32, backoff: Duration) -> Self {
+        self.reconnect = Some(ReconnectPolicy { max_retries, backoff });
+        self
+    }
+
+    /// 设置单条消息允许的最大字节数，默认 `DEFAULT_MAX_MESSAGE_SIZE`
+    ///
+    /// 超限的发送在本地直接返回 `VirgeError::MessageTooLarge`；接收时长度前缀超限
+    /// 在分配内存前返回同样的错误，raw/yamux 传输会丢弃该消息并保持连接可用。
+    pub fn with_max_message_size(mut self, size: usize) -> Self {
+        self.transport_options.max_message_size = size;
+        self
+    }
+
+    /// 设置传输协议，需与服务器端一致
+    pub fn with_transport_kind(mut self, kind: TransportKind) -> Self {
+        self.transport_kind = kind;
+        self
+    }
+
+    /// 设置是否执行连接握手，默认开启，需与服务器端一致
+    ///
+    /// 关闭后不校验协议版本与参数，仅用于与未进行握手的旧版本互通。
+    pub fn with_handshake(mut self, enabled: bool) -> Self {
+        self.transport_options.handshake = enabled;
+        self
+    }
+
+    /// 启用心跳保活，需与服务器端同时启用
+    ///
+    /// 接收空闲超过 `interval` 时发送 PING，发出后 `timeout` 内未收到对端任何数据则
+    /// recv/send 返回 `ConnectionError { message: "peer timed out", .. }`。`timeout` 不得小于 `interval`。
+    pub fn with_keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.transport_options.keepalive = Some(KeepaliveConfig { interval, timeout });
+        self
+    }
+
+    /// 启用逐条消息的 CRC32 完整性校验，需与对端一致，默认关闭
+    ///
+    /// 设置在握手中协商，不一致时连接建立失败；关闭握手时无法协商，需自行保证两端一致。
+    /// 校验失败的消息被丢弃并返回 `VirgeError::IntegrityError`，连接保持可用。
+    pub fn with_integrity(mut self, enabled: bool) -> Self {
+        self.transport_options.integrity = enabled;
+        self
+    }
+
+    /// 设置握手中两端 chunk_size 不一致时的处理方式，默认 `RequireEqual` 直接失败
+    ///
+    /// `UseMinimum` 时双方均采用较小的值；仅 xtransport 会协商 chunk_size。
+    pub fn with_chunk_size_policy(mut self, policy: crate::transport::ChunkSizePolicy) -> Self {
+        self.transport_options.chunk_policy = policy;
+        self
+    }
+
+    /// 启用优先通道，需与对端一致，默认关闭
+    ///
+    /// 启用后普通消息按 64 KiB 分片发送，`send_priority` 发出的消息可插在两片之间，
+    /// 对端通过 `recv_priority` 接收；设置在握手中协商，不一致时连接建立失败。
+    pub fn with_priority_lanes(mut self, enabled: bool) -> Self {
+        self.transport_options.lanes = enabled;
+        self
+    }
+
+    /// 启用消息元数据，需与对端一致，默认关闭
+    ///
+    /// 启用后可通过 `send_with_meta` / `recv_with_meta` 随消息收发字符串键值对，元数据块不超过 4 KiB；
+    /// 普通的 `send` / `recv` 照常工作，元数据为空。设置在握手中协商，不一致时连接建立失败。
+    pub fn with_metadata(mut self, enabled: bool) -> Self {
+        self.transport_options.metadata = enabled;
+        self
+    }
+
+    /// 为每条普通消息附加递增的序号，需与对端一致，默认关闭
+    ///
+    /// 启用后可通过 `last_sent_seq` / `last_received_seq` 查询双方的进度，重连后据此判断需要重发的消息；
+    /// 收到重复或倒退的序号时 `recv` 返回 `VirgeError::ProtocolError`。每条消息多 8 字节，优先消息不编号。
+    /// 设置在握手中协商，不一致时连接建立失败。
+    pub fn with_sequence_numbers(mut self, enabled: bool) -> Self {
+        self.transport_options.sequence = enabled;
+        self
+    }
+
+    /// 设置握手时向服务器出示的认证令牌，不超过 `MAX_AUTH_TOKEN_SIZE` 字节，需开启握手
+    ///
+    /// 令牌由服务器通过 `ServerConfig::with_authenticator` 注册的函数检查。认证失败时握手本身仍会完成，
+    /// 服务器随即以原因码 `CLOSE_AUTH_FAILED` 关闭连接，客户端随后的接收返回 `VirgeError::PeerClosed`
+    /// （xtransport 无法传递原因，仅表现为连接断开）。令牌不会出现在配置的调试输出中。
+    pub fn with_auth_token(mut self, token: Vec<u8>) -> Self {
+        self.transport_options.auth_token = Some(AuthToken(token));
+        self
+    }
+
+    /// 以 32 字节预共享密钥加密连接上的全部消息，需与服务器的密钥一致并开启握手，默认不加密
+    ///
+    /// 连接建立后双方以临时 X25519 密钥交换并结合预共享密钥导出 ChaCha20-Poly1305 会话密钥，每条消息多 24 字节。
+    /// 一端加密另一端不加密，或双方密钥不一致时，连接以 `VirgeError::EncryptionError` 失败。
+    /// 密钥不会出现在配置的调试输出中；不支持 `new_in_memory`。
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption_key(mut self, key: [u8; 32]) -> Self {
+        self.transport_options.encryption = Some(PresharedKey(key));
+        self
+    }
+
+    /// 设置 ACK 模式下允许的未确认消息数，默认 1，需大于 0
+    ///
+    /// 握手时取两端设置的较小值。大于 1 时 `send` 不再等待每条消息的确认，
+    /// 返回时最近的 n - 1 条消息可能尚未被对端确认；未确认数达到上限时 `send` 等待最早一条的确认。
+    /// 仅对非 xtransport 传输生效，xtransport 使用其自身的确认机制。
+    pub fn with_max_unacked_chunks(mut self, n: u32) -> Self {
+        self.transport_options.ack_window = n;
+        self
+    }
+
+    /// 设置底层套接字的内核发送缓冲区字节数（SO_SNDBUF），默认使用系统值，需大于 0
+    ///
+    /// 对端停止接收后，已发出但未被读取的数据至多约为该值加上对端的接收缓冲区（yamux 为对端的接收窗口），
+    /// 之后 `send` 按 `with_nonblocking_send` 的设置等待或返回 `ErrorKind::WouldBlock`。
+    /// 内核会将设置值翻倍，且上限受 `net.core.wmem_max` 约束；内存传输不受影响。
+    pub fn with_send_buffer_limit(mut self, bytes: usize) -> Self {
+        self.transport_options.send_buffer_limit = Some(bytes);
+        self
+    }
+
+    /// 设置 vsock 套接字的缓冲区下限、大小与上限（`SO_VM_SOCKETS_BUFFER_*`），需满足 `min <= size <= max`，默认使用系统值
+    ///
+    /// 连接建立后立即设置，失败时连接返回附带 errno 的 `VirgeError::ConfigError`。内核可能再做调整，
+    /// 实际生效的值可由 `socket_buffer_sizes()` 读回。virtio-vsock（KVM 上的 QEMU、Firecracker、cloud-hypervisor
+    /// 等的来宾端）以 `size` 作为向对端通告的接收缓冲区，调大可提升吞吐；VMCI（VMware）只在连接前分配队列，
+    /// Hyper-V 的 hv_sock 使用固定大小的环形缓冲区，两者都不受影响。tcp、uds 与内存传输没有 vsock 套接字，同样不受影响。
+    pub fn with_socket_buffer(mut self, min: u64, size: u64, max: u64) -> Self {
+        self.transport_options.socket_buffer = Some(SocketBufferSizes { min, size, max });
+        self
+    }
+
+    /// 发送缓冲区已满时 `send` 立即返回 `ErrorKind::WouldBlock`，默认关闭，即等待至有可用空间或写超时
+    ///
+    /// yamux 传输在对端接收窗口耗尽时同样返回该错误。非阻塞模式下 `send` 不等待 ACK 确认；
+    /// 消息一旦开始写入就会写完，不会留下半条消息。
+    pub fn with_nonblocking_send(mut self, enabled: bool) -> Self {
+        self.transport_options.nonblocking_send = enabled;
+        self
+    }
+
+    /// 设置接收缓冲区池至多保存的空闲读缓冲区数，默认 `DEFAULT_BUFFER_POOL_SIZE`，0 表示不启用
+    ///
+    /// Raw、Tcp、UDS、yamux 与内存传输从流中读取时优先复用池中的缓冲区，不再每次分配一块新的读缓冲区；
+    /// 复用与新分配的次数见 `stats()` 的 `buffer_pool_hits` 与 `buffer_pool_misses`。
+    /// xtransport 与 SOCK_SEQPACKET 传输不经由缓冲区池读取，不受影响。
+    pub fn with_buffer_pool_size(mut self, size: usize) -> Self {
+        self.transport_options.buffer_pool_size = size;
+        self
+    }
+
+    /// 发送遇到暂时性错误（`EAGAIN`、`ENOBUFS`、`ENOMEM` 或被信号中断）时重试，默认不重试
+    ///
+    /// 包括第一次在内最多尝试 `max_attempts` 次（需大于 0），两次尝试之间从 1 毫秒开始逐次翻倍等待，
+    /// 累计等待不超过 `max_total_delay`。用尽后返回附带尝试次数的 `VirgeError::TransportError`，
+    /// 原始 IO 错误可通过 `source()` 取回；连接重置等其他错误立即返回。来宾内存紧张时 vsock 发送可能短暂返回这些错误。
+    /// 启用后每次发送多一次数据拷贝，`with_nonblocking_send` 的发送不参与重试。
+    pub fn with_send_retry(mut self, max_attempts: u32, max_total_delay: Duration) -> Self {
+        self.transport_options.send_retry = Some(SendRetry { max_attempts, max_total_delay });
+        self
+    }
+
+    /// 以令牌桶限制接收速率：每秒 `bytes_per_sec` 字节，允许突发 `burst` 字节，两者都需大于 0，默认不限速
+    ///
+    /// 令牌不足时推迟读取，未读取的数据留在内核缓冲区中，对端的发送随之变慢；等待由定时器唤醒，不会忙等。
+    /// 单条消息可以超过突发量，超出部分由之后的接收偿还。令牌不足时 `try_recv` 返回 `Ok(None)`，不等待；
+    /// 等待超过读超时时返回 `VirgeError::Timeout`。
+    /// 受限状态与累计等待时长见 `stats()` 的 `throttled` 与 `throttled_time`。
+    pub fn with_rate_limit(mut self, bytes_per_sec: u64, burst: u64) -> Self {
+        self.transport_options.rate_limit.recv_bytes = Some(Rate { per_sec: bytes_per_sec, burst });
+        self
+    }
+
+    /// 以令牌桶限制每秒接收的消息数，与 `with_rate_limit` 可同时启用，按传输帧计数，默认不限速
+    pub fn with_message_rate_limit(mut self, messages_per_sec: u64, burst: u64) -> Self {
+        self.transport_options.rate_limit.recv_messages = Some(Rate { per_sec: messages_per_sec, burst });
+        self
+    }
+
+    /// 以令牌桶限制发送速率，参数含义与 `with_rate_limit` 相同，默认不限速
+    pub fn with_send_rate_limit(mut self, bytes_per_sec: u64, burst: u64) -> Self {
+        self.transport_options.rate_limit.send_bytes = Some(Rate { per_sec: bytes_per_sec, burst });
+        self
+    }
+
+    /// 设置消息压缩算法，需与对端一致，默认 `None` 不压缩
+    ///
+    /// 算法在握手中协商，不一致时连接建立失败；短于 256 字节或压缩后没有变小的消息原样发送。
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.transport_options.compression = compression;
+        self
+    }
+
+    /// 设置 `send_serialized`/`recv_deserialized` 的编码格式，默认 bincode，需与对端一致
+    #[cfg(feature = "serde")]
+    pub fn with_wire_format(mut self, format: WireFormat) -> Self {
+        self.wire_format = format;
+        self
+    }
+
+    /// 经由 Firecracker、cloud-hypervisor 等监控程序导出的 vsock Unix 套接字连接来宾，需配合 `TransportKind::Raw`
+    ///
+    /// 连接时先向该套接字发送 `CONNECT <server_port>\n`，`server_cid` 被忽略；来宾内的服务器使用 Raw 协议即可互通。
+    #[cfg(feature = "use-uds")]
+    pub fn with_uds_path(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.transport_options.uds_path = Some(path.into());
+        self
+    }
+
+    /// 使用 virtio-vsock 的 SOCK_SEQPACKET 套接字代替字节流，需配合 `TransportKind::Raw`，服务器需同样启用
+    ///
+    /// 不超过 64 KiB 的消息以单个数据报发送，更大的消息拆分为多个数据报，`recv` 每次恰好返回一条消息。
+    /// 内核（Linux 5.14 之前）或 vsock 传输不支持时 `connect()` 返回 `VirgeError::ConfigError`，不会退回到字节流；
+    /// 连接成功后 `stats().seqpacket` 为 `true`。
+    #[cfg(feature = "use-raw")]
+    pub fn with_seqpacket(mut self, enabled: bool) -> Self {
+        self.transport_options.seqpacket = enabled;
+        self
+    }
+
+    /// 按 `plan` 在传输协议之上注入故障，用于测试连接中断、消息损坏等错误路径
+    #[cfg(feature = "testing")]
+    pub fn with_fault_plan(mut self, plan: FaultPlan) -> Self {
+        self.transport_options.faults = Some(plan);
+        self
+    }
+
+    /// 把传输层实际收发的每一帧连同时间戳记录到 `recorder` 的日志文件，用于离线复现问题
+    ///
+    /// 录制位于最内层，客户端的重连沿用同一个连接编号；日志可用 `example/virga_replay` 查看，
+    /// 或以 `VirgeClient::replay` 回放。
+    #[cfg(feature = "testing")]
+    pub fn with_recorder(mut self, recorder: TrafficRecorder) -> Self {
+        self.transport_options.recorder = Some(recorder);
+        self
+    }
+
+    /// 注册收发事件观察者，如 `HexDumpObserver`，未注册时没有额外开销
+    pub fn with_observer(mut self, observer: impl TransportObserver + 'static) -> Self {
+        self.transport_options.observer = Some(ObserverHandle(Arc::new(observer)));
+        self
+    }
+
+    /// 设置 yamux 协议参数（接收窗口、最大流数量等），非法组合在连接时返回 `ConfigError`
+    #[cfg(feature = "use-yamux")]
+    pub fn with_yamux_config(mut self, config: YamuxConfig) -> Self {
+        self.transport_options.yamux = config;
+        self
+    }
+}
+
+/// `ClientConfig` 构造器，未设置的参数取默认值
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClientConfigBuilder {
+    config: ClientConfig,
+}
+
+impl ClientConfigBuilder {
+    /// 服务器 CID，默认为 `DEFAULT_SERVER_CID`；宿主机连接来宾机时为来宾机的 CID
+    pub fn server_cid(mut self, cid: u32) -> Self {
+        self.config.server_cid = cid;
+        self
+    }
+
+    /// 服务器端口，默认为 `DEFAULT_SERVER_PORT`
+    pub fn server_port(mut self, port: u32) -> Self {
+        self.config.server_port = port;
+        self
+    }
+
+    /// 连接前绑定的本地 vsock 端口，默认由内核分配，见 `ClientConfig::with_local_port`
+    pub fn local_port(mut self, port: Option<u32>) -> Self {
+        self.config.transport_options.local_port = port;
+        self
+    }
+
+    /// 使用 SOCK_SEQPACKET 套接字，见 `ClientConfig::with_seqpacket`
+    #[cfg(feature = "use-raw")]
+    pub fn seqpacket(mut self, enabled: bool) -> Self {
+        self.config.transport_options.seqpacket = enabled;
+        self
+    }
+
+    /// xtransport 数据块大小，需在 `MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE` 之间
+    pub fn chunk_size(mut self, size: u32) -> Self {
+        self.config.chunk_size = size;
+        self
+    }
+
+    /// 是否启用 ACK，默认关闭，需与对端一致
+    ///
+    /// 启用后 `send` 等待对端确认送达才返回（写超时内未确认返回 `VirgeError::Timeout`），
+    /// 可用 `send_noack` 逐条跳过；关闭时 `send` 在数据交给内核后即返回。
+    pub fn ack(mut self, enabled: bool) -> Self {
+        self.config.is_ack = enabled;
+        self
+    }
+
+    /// 限制接收速率，见 `ClientConfig::with_rate_limit`
+    pub fn rate_limit(mut self, bytes_per_sec: u64, burst: u64) -> Self {
+        self.config = self.config.with_rate_limit(bytes_per_sec, burst);
+        self
+    }
+
+    /// 设置 vsock 套接字的缓冲区大小，见 `ClientConfig::with_socket_buffer`
+    pub fn socket_buffer(mut self, min: u64, size: u64, max: u64) -> Self {
+        self.config = self.config.with_socket_buffer(min, size, max);
+        self
+    }
+
+    /// 以 `VIRGA_*` 环境变量覆盖当前参数
+    ///
+    /// 各来源按调用顺序覆盖，例如 `builder().toml_file(path)?.env()?.server_port(port)`
+    /// 的优先级为：显式设置 > 环境变量 > 配置文件 > 默认值。
+    pub fn env(self) -> Result<Self> {
+        self.apply(&Layer::from_env(CLIENT_KEYS))
+    }
+
+    /// 以 TOML 配置文件中的键覆盖当前参数
+    #[cfg(feature = "config-file")]
+    pub fn toml_file(self, path: impl AsRef<std::path::Path>) -> Result<Self> {
+        self.apply(&Layer::from_toml_file(path.as_ref(), CLIENT_KEYS)?)
+    }
+
+    pub(crate) fn apply(mut self, layer: &Layer) -> Result<Self> {
+        if let Some(cid) = layer.get("server_cid")? {
+            self.config.server_cid = cid;
+        }
+        if let Some(port) = layer.get("server_port")? {
+            self.config.server_port = port;
+        }
+        if let Some(ms) = layer.get("connect_timeout_ms")? {
+            self.config.connect_timeout = Some(Duration::from_millis(ms));
+        }
+        if let Some(size) = layer.get("chunk_size")? {
+            self.config.chunk_size = size;
+        }
+        if let Some(ack) = layer.get_bool("ack")? {
+            self.config.is_ack = ack;
+        }
+        if let Some(size) = layer.get("max_message_size")? {
+            self.config.transport_options.max_message_size = size;
+        }
+        if let Some(kind) = layer.get("transport")? {
+            self.config.transport_kind = kind;
+        }
+        #[cfg(feature = "use-uds")]
+        if let Some(path) = layer.get::<std::path::PathBuf>("uds_path")? {
+            self.config.transport_options.uds_path = Some(path);
+        }
+        #[cfg(feature = "use-raw")]
+        if let Some(enabled) = layer.get_bool("seqpacket")? {
+            self.config.transport_options.seqpacket = enabled;
+        }
+        Ok(self)
+    }
+
+    /// 校验参数并生成配置，其余选项可继续通过 `with_xxx` 设置
+    pub fn build(self) -> Result<ClientConfig> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// `disconnect_with` 对尚未读取的数据的处理策略
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisconnectPolicy {
+    /// 读缓冲区或传输层中仍有未读数据时返回错误，连接保持可用
+    RefuseIfUnreadData,
+    /// 直接丢弃未读数据并断开，与 `disconnect()` 相同
+    DiscardUnreadData,
+    /// 断开前持续读取并丢弃对端已发出的数据，直到没有待读数据、对端关闭或超时
+    DrainFirst(Duration),
+}
+
+impl DisconnectPolicy {
+    /// 在断开前按策略处理 `pending` 与传输层中尚未读取的数据
+    pub(crate) async fn prepare(self, transport: &mut dyn Transport, pending: &mut Vec<u8>) -> Result<()> {
+        match self {
+            DisconnectPolicy::DiscardUnreadData => {}
+            DisconnectPolicy::RefuseIfUnreadData => {
+                // 探测到的数据放回读缓冲区，拒绝断开后仍可正常读取
+                if let Ok(Some(data)) = transport.try_recv().await {
+                    pending.extend_from_slice(&data);
+                }
+                if !pending.is_empty() {
+                    return Err(VirgeError::Other(format!(
+                        "Refusing to disconnect with {} unread bytes",
+                        pending.len()
+                    )));
+                }
+            }
+            DisconnectPolicy::DrainFirst(timeout) => {
+                let deadline = Instant::now() + timeout;
+                let mut discarded = std::mem::take(pending).len();
+                while Instant::now() < deadline {
+                    match transport.try_recv().await {
+                        Ok(Some(data)) => discarded += data.len(),
+                        _ => break,
+                    }
+                }
+                if discarded > 0 {
+                    debug!("Drained {} unread bytes before disconnect", discarded);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `recv_msg_timeout` 的结果
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecvOutcome {
+    /// 在超时前收到了一条完整消息
+    Complete(Vec<u8>),
+    /// 超时时已收到消息的一部分，已收到的数据保留，之后的 `recv_msg` 从中断处继续
+    ///
+    /// `received` 为已收到的消息体字节数（分块消息包括块头）；长度前缀尚未收全或消息为分块消息时 `expected` 为 0。
+    TimedOutPartial { received: usize, expected: usize },
+    /// 超时前没有收到任何新消息的数据
+    TimedOutIdle,
+    /// 对端已正常关闭连接或关闭了写方向，不会再收到消息
+    Closed,
+}
+
+impl RecvOutcome {
+    /// 在 `timeout` 内接收一条带长度前缀的消息，不足一条消息的数据保留在 `buf` 中
+    ///
+    /// 调用方负责在返回后恢复传输层的读超时。
+    pub(crate) async fn receive(
+        transport: &mut dyn Transport,
+        buf: &mut Vec<u8>,
+        max: usize,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(message) = framing::take_message(buf, max)? {
+                return Ok(RecvOutcome::Complete(message));
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Self::timed_out(buf, max);
+            }
+            transport.set_read_timeout(Some(remaining))?;
+
+            let partial = !buf.is_empty();
+            match transport.recv().await {
+                Ok(data) if data.is_empty() && partial => return Err(framing::unexpected_eof()),
+                Ok(data) => buf.extend_from_slice(&data),
+                Err(e) if e.is_timeout() => return Self::timed_out(buf, max),
+                Err(_) if partial => return Err(framing::unexpected_eof()),
+                Err(VirgeError::EndOfStream) => return Ok(RecvOutcome::Closed),
+                Err(e) => return Err(e),
+            }
         }
     }
+
+    /// 按缓冲区中的部分消息生成超时结果
+    fn timed_out(buf: &[u8], max: usize) -> Result<Self> {
+        if buf.is_empty() {
+            return Ok(RecvOutcome::TimedOutIdle);
+        }
+        let expected = framing::message_len(buf, max)?.unwrap_or(0);
+        let received = buf.len().saturating_sub(framing::LEN_PREFIX_SIZE);
+        Ok(RecvOutcome::TimedOutPartial { received, expected })
+    }
 }
 
 /// Virga 客户端：提供基于选定传输协议的高级客户端接口。
+///
+/// 未调用 `disconnect` 就被释放时强制关闭传输，尚未读取的数据被丢弃并记录警告。
 pub struct VirgeClient {
     transport: Box<dyn Transport>,
     config: ClientConfig,
     connected: bool,
+    /// 已从传输层收到但尚未被 recv_msg 消费的数据
+    read_buffer: Vec<u8>,
+    stats: Arc<StatsCounters>,
+    /// 用户设置的读超时，call 等待响应结束后恢复
+    read_timeout: Option<Duration>,
+    /// 用户设置的写超时，`flush_timeout` 结束后恢复
+    write_timeout: Option<Duration>,
+    /// 下一次 call 的关联 ID
+    next_call_id: u32,
+    /// 本端已调用 `shutdown_write`，不再发送数据
+    write_shutdown: bool,
+    /// 对端已关闭写方向，此后 recv/read 返回流结束
+    peer_eof: bool,
+    /// 启用批量合并时 `write()` 缓冲的待发送数据
+    write_batch: Vec<u8>,
+    /// 当前批次第一次写入的时间
+    batch_started: Option<Instant>,
+    /// `MessageWriter` 发出部分数据后未完成即被释放，下一次收发前先发出该序号消息的放弃块头
+    abort_pending: Option<u32>,
+    /// 下一条分块消息的序号，写入每个块头
+    next_chunked: u32,
+    /// 当前连接的 ID，每次连接成功后分配
+    conn_id: Option<u64>,
 }
 
 
 impl VirgeClient {
+    /// 按配置中的传输协议创建客户端
     pub fn new(config: ClientConfig) -> Self {
-        #[cfg(feature = "use-xtransport")]
-        if cfg!(feature = "use-xtransport") {
-            return Self::with_xtransport(config);
-        }
-        #[cfg(feature = "use-yamux")]
-        if cfg!(feature = "use-yamux") {
-            return Self::with_yamux(config);
-        }
-        panic!("Either use-yamux or use-xtransport feature must be enabled");
+        let transport = config.transport_kind.create(false, config.is_ack, &config.transport_options);
+        Self::from_transport(config, transport)
     }
 
-    #[cfg(feature = "use-yamux")]
-    pub fn with_yamux(config: ClientConfig) -> Self {
+    fn from_transport(config: ClientConfig, transport: Box<dyn Transport>) -> Self {
+        let stats = Arc::new(StatsCounters::default());
+        let transport = RateLimitTransport::wrap(transport, &config.transport_options.rate_limit, &stats, None);
         Self {
-            transport: Box::new(crate::transport::YamuxTransport::new_client()),
+            transport: Box::new(
+                StatsTransport::new(transport, stats.clone())
+                    .with_seqpacket(config.transport_options.uses_seqpacket()),
+            ),
             config,
             connected: false,
+            read_buffer: Vec::new(),
+            stats,
+            read_timeout: None,
+            write_timeout: None,
+            next_call_id: 0,
+            write_shutdown: false,
+            peer_eof: false,
+            write_batch: Vec::new(),
+            batch_started: None,
+            abort_pending: None,
+            next_chunked: 0,
+            conn_id: None,
         }
     }
 
+    #[cfg(feature = "use-yamux")]
+    pub fn with_yamux(config: ClientConfig) -> Self {
+        Self::new(config.with_transport_kind(TransportKind::Yamux))
+    }
+
     #[cfg(feature = "use-xtransport")]
     pub fn with_xtransport(config: ClientConfig) -> Self {
-        Self {
-            transport: Box::new(crate::transport::XTransportHandler::new()),
-            config,
-            connected: false,
-        }
+        Self::new(config.with_transport_kind(TransportKind::XTransport))
+    }
+
+    #[cfg(feature = "use-raw")]
+    pub fn with_raw(config: ClientConfig) -> Self {
+        Self::new(config.with_transport_kind(TransportKind::Raw))
+    }
+
+    /// 创建经由进程内内存传输直接相连的客户端与服务器，无需任何套接字
+    ///
+    /// 服务器端使用与 `config` 相同的 chunk_size、ACK、包装器与消息上限，收发经过与真实连接相同的
+    /// 分帧、ACK、心跳、校验与压缩代码；`server_cid`/`server_port` 与传输协议被忽略，不执行握手。
+    #[cfg(feature = "testing")]
+    pub async fn new_in_memory(config: ClientConfig) -> Result<(VirgeClient, VirgeServer)> {
+        config.validate()?;
+        memory_impl::check_options(&config.transport_options)?;
+        let (client, server) = memory_impl::pair(config.chunk_size, config.is_ack, &config.transport_options);
+        let server = VirgeServer::in_memory(
+            server,
+            config.chunk_size,
+            config.is_ack,
+            &config.transport_options,
+            #[cfg(feature = "serde")]
+            config.wire_format,
+        ).await?;
+        let mut client = Self::from_transport(config, client);
+        client.connect().await?;
+        Ok((client, server))
+    }
+
+    /// 以录制的流量代替服务器创建并连接客户端，用于离线复现问题
+    ///
+    /// `replay` 按录制顺序交付当时收到的帧，其上按 `config` 叠加包装器，包装器组合须与录制时一致，
+    /// 否则返回 `VirgeError::ConfigError`；`server_cid`/`server_port` 与传输协议被忽略，不执行握手。
+    #[cfg(feature = "testing")]
+    pub async fn replay(config: ClientConfig, replay: ReplayTransport) -> Result<VirgeClient> {
+        config.validate()?;
+        let transport = replay.wrap(&config.transport_options)?;
+        let mut client = Self::from_transport(config, transport);
+        client.connect().await?;
+        Ok(client)
+    }
+
+    /// 以服务器端配置创建并连接内存传输的客户端一端，由 `VirgeServer::new_in_memory` 调用
+    #[cfg(feature = "testing")]
+    pub(crate) async fn in_memory(
+        transport: Box<dyn Transport>,
+        chunk_size: u32,
+        is_ack: bool,
+        transport_options: TransportOptions,
+        #[cfg(feature = "serde")] wire_format: WireFormat,
+    ) -> Result<Self> {
+        let config = ClientConfig {
+            server_cid: crate::VMADDR_CID_LOCAL as u32,
+            chunk_size,
+            is_ack,
+            transport_options,
+            #[cfg(feature = "serde")]
+            wire_format,
+            ..ClientConfig::default()
+        };
+        let mut client = Self::from_transport(config, transport);
+        client.connect().await?;
+        Ok(client)
+    }
+
+    /// 使用本地回环 TCP 传输，连接 `127.0.0.1:server_port`，`server_cid` 被忽略
+    #[cfg(feature = "use-tcp")]
+    pub fn with_tcp(config: ClientConfig) -> Self {
+        Self::new(config.with_transport_kind(TransportKind::Tcp))
     }
     
     /// 建立连接
+    ///
+    /// 若配置了 `connect_timeout`，等价于调用 [`VirgeClient::connect_timeout`]。
     pub async fn connect(&mut self) -> Result<()> {
+        if let Some(timeout) = self.config.connect_timeout {
+            return self.connect_timeout(timeout).await;
+        }
+        self.config.validate()?;
+
         info!(
             "VirgeClient connecting to cid={}, port={}",
             self.config.server_cid,
             self.config.server_port
         );
 
+        self.connected = false;
+        self.reset_half_close();
+        self.abort_pending = None;
         self.transport.connect(self.config.server_cid, self.config.server_port, self.config.chunk_size, self.config.is_ack).await?;
+        crate::transport::apply_send_buffer_limit(self.transport.as_ref(), &self.config.transport_options)?;
+        crate::transport::apply_socket_buffer(self.transport.as_ref(), &self.config.transport_options)?;
+        self.assign_connection_id();
+        self.connected = true;
+        Ok(())
+    }
+
+    /// 在限定时间内建立连接
+    ///
+    /// 超时返回 `VirgeError::Timeout`，客户端保持未连接状态，可直接重试。
+    pub async fn connect_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.config.validate()?;
+        info!(
+            "VirgeClient connecting to cid={}, port={} with timeout {:?}",
+            self.config.server_cid,
+            self.config.server_port,
+            timeout
+        );
+
+        self.connected = false;
+        self.reset_half_close();
+        self.abort_pending = None;
+        self.transport.connect_timeout(
+            self.config.server_cid,
+            self.config.server_port,
+            self.config.chunk_size,
+            self.config.is_ack,
+            timeout,
+        ).await?;
+        crate::transport::apply_send_buffer_limit(self.transport.as_ref(), &self.config.transport_options)?;
+        crate::transport::apply_socket_buffer(self.transport.as_ref(), &self.config.transport_options)?;
+        self.assign_connection_id();
         self.connected = true;
         Ok(())
     }
     
+    /// 重新建立连接
+    ///
+    /// 先丢弃当前传输状态，再连接到相同的 cid/port；配置了重连策略时按指数退避重试，
+    /// 否则只尝试一次。
+    pub async fn reconnect(&mut self) -> Result<()> {
+        if let Err(e) = self.transport.disconnect().await {
+            debug!("VirgeClient ignoring disconnect error before reconnect: {}", e);
+        }
+        self.connected = false;
+        // 丢弃旧会话中未读完的数据，避免残留的半条消息混入新会话
+        self.read_buffer.clear();
+
+        let (max_retries, mut backoff) = match self.config.reconnect {
+            Some(policy) => (policy.max_retries, policy.backoff),
+            None => (0, Duration::ZERO),
+        };
+
+        let mut attempt = 0;
+        loop {
+            match self.connect().await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < max_retries => {
+                    attempt += 1;
+                    warn!(
+                        "VirgeClient reconnect failed: {}, retry {}/{} in {:?}",
+                        e, attempt, max_retries, backoff
+                    );
+                    crate::transport::sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// 判断错误是否意味着连接已不可用，需要重连
+    fn is_link_error(err: &VirgeError) -> bool {
+        !err.is_timeout() && !matches!(
+            err,
+            VirgeError::ConfigError(_)
+                | VirgeError::ProtocolError(_)
+                | VirgeError::MessageTooLarge { .. }
+                | VirgeError::IntegrityError { .. }
+                | VirgeError::EncryptionError(_)
+                | VirgeError::CodecError(_)
+                | VirgeError::EndOfStream
+                | VirgeError::PeerClosed { .. }
+        )
+    }
+
     /// 断开连接
+    ///
+    /// 等同于原因码为 0、没有说明的 `disconnect_with_reason`，服务器随后照常读到流结束。
     pub async fn disconnect(&mut self) -> Result<()> {
+        self.disconnect_with_reason(0, "").await
+    }
+
+    /// 携带原因码与说明断开连接
+    ///
+    /// 服务器之后的 `recv()` 返回 `VirgeError::PeerClosed { code, message }`，原因码的含义由应用自行约定；
+    /// 说明超过 `MAX_CLOSE_REASON_SIZE` 字节的部分被截断。xtransport 传输无法传递原因，仅断开连接。
+    pub async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
         info!("VirgeClient disconnecting");
-        self.transport.disconnect().await?;
+        if self.connected {
+            if let Err(e) = self.flush_batch().await {
+                warn!("VirgeClient failed to flush batched writes before disconnect: {}", e);
+            }
+        }
+        self.transport.disconnect_with_reason(code, message).await?;
         self.connected = false;
+        self.read_buffer.clear();
         Ok(())
     }
+
+    /// 按 `policy` 处理尚未读取的数据后断开连接
+    ///
+    /// `RefuseIfUnreadData` 拒绝时返回 `VirgeError::Other`，连接与未读数据保持不变。
+    pub async fn disconnect_with(&mut self, policy: DisconnectPolicy) -> Result<()> {
+        if self.connected {
+            policy.prepare(self.transport.as_mut(), &mut self.read_buffer).await?;
+        }
+        self.disconnect().await
+    }
     
-    /// 发送数据
-    pub async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+    /// 关闭写方向（半关闭）：通知服务器不会再发送请求，之后仍可接收服务器的响应
+    ///
+    /// 服务器的 `recv()` 随后返回空消息、`read()` 返回 `Ok(0)`。之后的发送返回 `Disconnected`；
+    /// 与自动重连一起使用时，重连会恢复写方向。xtransport 传输不支持。
+    pub async fn shutdown_write(&mut self) -> Result<()> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        if self.write_shutdown {
+            return Ok(());
+        }
+        self.flush_batch().await?;
+        info!("VirgeClient shutting down write side");
+        self.transport.shutdown_write().await?;
+        self.write_shutdown = true;
+        Ok(())
+    }
+
+    /// 检查本端仍可发送数据
+    fn check_writable(&self) -> Result<()> {
         if !self.connected {
-            return Err(crate::error::VirgeError::Other(
+            return Err(VirgeError::Disconnected(
                 "Client not connected".to_string(),
             ));
         }
-        
-        self.transport.send(data).await?;
+        if self.write_shutdown {
+            return Err(VirgeError::Disconnected(
+                "Client write side has been shut down".to_string(),
+            ));
+        }
         Ok(())
     }
+
+    fn reset_half_close(&mut self) {
+        self.write_shutdown = false;
+        self.peer_eof = false;
+    }
+
+    /// 将对端的半关闭通知转换为流结束的返回值 `eof`，并记住该状态
+    fn end_of_stream<T>(&mut self, result: Result<T>, eof: T) -> Result<T> {
+        match result {
+            Err(VirgeError::EndOfStream) => {
+                if self.transport.is_connected() {
+                    info!("VirgeClient peer shut down its write side");
+                } else {
+                    info!("VirgeClient peer closed the connection");
+                }
+                self.peer_eof = true;
+                Ok(eof)
+            }
+            result => result,
+        }
+    }
+
+    /// 发送数据
+    ///
+    /// 启用 ACK 时等待对端确认送达后返回，写超时内未确认返回 `VirgeError::Timeout`。
+    /// 启用自动重连时，连接断开会触发重连并重试一次发送。
+    /// 超过 `max_message_size` 的数据在本地直接返回 `VirgeError::MessageTooLarge`。
+    /// 对端停止接收、发送缓冲区已满时等待至有可用空间（受写超时约束），
+    /// 启用 `with_nonblocking_send` 时改为返回 `ErrorKind::WouldBlock`。
+    pub async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        framing::check_size(data.len(), self.config.transport_options.max_message_size)?;
+        self.flush_batch().await?;
+        self.send_frame(data, true).await
+    }
+
+    /// 将已校验大小的数据交给传输层发送
+    async fn send_frame(&mut self, data: Vec<u8>, ack: bool) -> Result<()> {
+        self.check_writable()?;
+
+        if self.config.reconnect.is_none() {
+            return self.transmit(data, ack).await;
+        }
+        if !self.transport.is_connected() {
+            warn!("VirgeClient transport disconnected, reconnecting before send");
+            self.reconnect().await?;
+            return self.transmit(data, ack).await;
+        }
+
+        match self.transmit(data.clone(), ack).await {
+            Err(e) if Self::is_link_error(&e) => {
+                warn!("VirgeClient send failed: {}, reconnecting", e);
+                self.reconnect().await?;
+                self.transmit(data, ack).await
+            }
+            result => result,
+        }
+    }
+
+    async fn transmit(&mut self, data: Vec<u8>, ack: bool) -> Result<()> {
+        if self.config.transport_options.nonblocking_send {
+            crate::transport::send_nonblocking(self.transport.as_mut(), &data).await
+        } else if ack {
+            self.transport.send(data).await
+        } else {
+            self.transport.send_noack(data).await
+        }
+    }
+
+    /// 发送数据且不等待对端确认，未启用 ACK 时与 `send` 相同
+    ///
+    /// xtransport 的 ACK 按连接生效，无法逐条跳过，此时同样等待确认。
+    pub async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        framing::check_size(data.len(), self.config.transport_options.max_message_size)?;
+        self.flush_batch().await?;
+        self.send_frame(data, false).await
+    }
+
+    /// 最近一次 `send` 从发出到收到对端确认的耗时，未启用 ACK 时为 `None`
+    pub fn last_ack_latency(&self) -> Option<Duration> {
+        self.transport.last_ack_latency()
+    }
+
+    /// 最近 128 次 ACK 往返耗时的统计，未启用 ACK 或尚无样本时为 `None`
+    pub fn ack_stats(&self) -> Option<AckStats> {
+        self.transport.ack_stats()
+    }
+
+    /// 最近一条发送成功的消息的序号，尚未发送时为 0，未启用 `with_sequence_numbers` 时为 `None`
+    ///
+    /// 序号在重连之间延续：重连后与服务器的 `last_received_seq` 比较，即可知道断开前的哪些消息需要重发。
+    pub fn last_sent_seq(&self) -> Option<u64> {
+        self.transport.last_sent_seq()
+    }
+
+    /// 当前连接上最近收到的消息的序号，尚未收到时为 0，未启用 `with_sequence_numbers` 时为 `None`
+    pub fn last_received_seq(&self) -> Option<u64> {
+        self.transport.last_received_seq()
+    }
     
     /// 接收数据
+    ///
+    /// 启用自动重连时，连接断开会触发重连并重试一次接收。
+    /// 服务器调用 `shutdown_write` 后返回空消息，表示不会再收到数据。
     pub async fn recv(&mut self) -> Result<Vec<u8>> {
         if !self.connected {
-            return Err(crate::error::VirgeError::Other(
+            return Err(VirgeError::Disconnected(
                 "Client not connected".to_string(),
             ));
         }
-        
-        self.transport.recv().await
+        self.flush_batch().await?;
+
+        // 优先返回 recv_msg 预读但未消费的数据
+        if !self.read_buffer.is_empty() {
+            return Ok(std::mem::take(&mut self.read_buffer));
+        }
+        if self.peer_eof {
+            return Ok(Vec::new());
+        }
+        let result = self.recv_frame().await;
+        self.end_of_stream(result, Vec::new())
     }
-    
-    /// 检查连接状态
-    pub fn is_connected(&self) -> bool {
-        self.connected && self.transport.is_connected()
+
+    /// 接收数据，`token` 在其他线程或任务中被取消时及时返回 `ErrorKind::Interrupted`
+    ///
+    /// 取消不会中断进行到一半的读取，连接保持可用，可继续接收；不参与自动重连，也不受读超时约束。
+    pub async fn recv_cancellable(&mut self, token: &CancelToken) -> Result<Vec<u8>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.flush_batch().await?;
+        if !self.read_buffer.is_empty() {
+            return Ok(std::mem::take(&mut self.read_buffer));
+        }
+        if self.peer_eof {
+            return Ok(Vec::new());
+        }
+        let result = cancel::recv(self.transport.as_mut(), token).await;
+        self.end_of_stream(result, Vec::new())
+    }
+
+    async fn recv_frame(&mut self) -> Result<Vec<u8>> {
+        if self.config.reconnect.is_none() {
+            return self.transport.recv().await;
+        }
+        if !self.transport.is_connected() {
+            warn!("VirgeClient transport disconnected, reconnecting before recv");
+            self.reconnect().await?;
+            return self.transport.recv().await;
+        }
+
+        match self.transport.recv().await {
+            Err(e) if Self::is_link_error(&e) => {
+                warn!("VirgeClient recv failed: {}, reconnecting", e);
+                self.reconnect().await?;
+                self.transport.recv().await
+            }
+            result => result,
+        }
+    }
+    
+    /// 将多个缓冲区作为一条消息发送，避免调用方先行拼接
+    ///
+    /// # Returns
+    /// 返回发送的总字节数
+    pub async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        self.check_writable()?;
+        let total = slices.iter().map(|slice| slice.len()).sum();
+        framing::check_size(total, self.config.transport_options.max_message_size)?;
+        self.flush_batch().await?;
+        self.transport.send_slices(slices).await
+    }
+
+    /// 将多个缓冲区作为一条消息写入，与 `Write::write_vectored` 对应，对端的一次 `recv()` 即收到全部数据
+    ///
+    /// 未启用批量写入时不拼接缓冲区，直接经 `send_slices` 发送；成功时返回各缓冲区的总字节数。
+    pub async fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Ok(0);
+        }
+        if self.config.batching.is_some() {
+            return self.write(&bufs.concat()).await;
+        }
+        self.send_slices(bufs).await
+    }
+
+    /// 以字节流方式读取数据，返回读取的字节数
+    ///
+    /// 与按消息接收的 `recv()`/`recv_msg()` 不同，消息边界不会表现为 EOF：
+    /// 缓冲区为空时继续从传输层接收，仅在对端关闭连接或关闭写方向时返回 `Ok(0)`，
+    /// 可在其上实现 `read_exact`、`read_to_end` 等字节流语义。
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.flush_batch().await?;
+        if self.peer_eof && self.read_buffer.is_empty() {
+            return Ok(0);
+        }
+        let result = framing::read(self.transport.as_mut(), &mut self.read_buffer, buf).await;
+        self.end_of_stream(result, 0)
+    }
+
+    /// 返回内部缓冲区中尚未读取的数据，缓冲区为空时先从传输层接收，与 `BufRead::fill_buf` 对应
+    ///
+    /// 返回的数据需通过 `consume` 标记为已读；对端关闭连接或关闭写方向且数据已读完时返回空切片。
+    pub async fn fill_buf(&mut self) -> Result<&[u8]> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.flush_batch().await?;
+        if !self.peer_eof {
+            let result = framing::fill(self.transport.as_mut(), &mut self.read_buffer).await;
+            self.end_of_stream(result, false)?;
+        }
+        Ok(&self.read_buffer)
+    }
+
+    /// 将 `fill_buf` 返回的前 `amt` 字节标记为已读
+    pub fn consume(&mut self, amt: usize) {
+        let amt = amt.min(self.read_buffer.len());
+        self.read_buffer.drain(..amt);
+    }
+
+    /// 内部缓冲区中尚未读取的数据，不从传输层接收
+    #[cfg(feature = "tokio-runtime")]
+    pub(crate) fn buffered(&self) -> &[u8] {
+        &self.read_buffer
+    }
+
+    /// 将下一条消息开头的数据复制到 `buf` 而不消费，返回复制的字节数
+    ///
+    /// 缓冲区为空时先从传输层接收一条消息；至多返回该条消息剩余的数据，不会跨越到下一条消息。
+    /// 之后的 `read`/`recv` 仍会返回这些数据。流结束时返回 `Ok(0)`。
+    pub async fn peek(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let data = self.fill_buf().await?;
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    /// 非阻塞查看下一次 `recv` 将返回的字节数而不消费，当前没有可读数据时返回 `Ok(None)`
+    pub async fn peek_msg_len(&mut self) -> Result<Option<usize>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.flush_batch().await?;
+        if !self.read_buffer.is_empty() || self.peer_eof {
+            return Ok(Some(self.read_buffer.len()));
+        }
+        let result = self.transport.try_recv().await;
+        match self.end_of_stream(result, Some(Vec::new()))? {
+            Some(data) => {
+                self.read_buffer = data;
+                Ok(Some(self.read_buffer.len()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// 跨消息边界读取直到 `delim`（包含）或流结束，追加到 `buf`，返回读取的字节数
+    ///
+    /// 流结束时返回 `Ok(0)`；出错时已读取的数据保留在 `buf` 中。
+    pub async fn read_until(&mut self, delim: u8, buf: &mut Vec<u8>) -> Result<usize> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.flush_batch().await?;
+        let start = buf.len();
+        if self.peer_eof {
+            framing::take_until(&mut self.read_buffer, delim, buf);
+        } else {
+            let result = framing::read_until(self.transport.as_mut(), &mut self.read_buffer, delim, buf).await;
+            self.end_of_stream(result, ())?;
+        }
+        Ok(buf.len() - start)
+    }
+
+    /// 读取一行（包含结尾的 `\n`）追加到 `line`，返回读取的字节数，流结束时返回 `Ok(0)`
+    ///
+    /// 一行可以跨越任意多条消息，消息边界不会截断行；数据不是合法 UTF-8 时返回
+    /// `VirgeError::CodecError`，`line` 保持不变。
+    pub async fn read_line(&mut self, line: &mut String) -> Result<usize> {
+        let mut bytes = Vec::new();
+        let n = self.read_until(b'\n', &mut bytes).await?;
+        framing::push_line(line, bytes)?;
+        Ok(n)
+    }
+
+    /// 读取下一行并去掉结尾的 `\n` 或 `\r\n`，流结束时返回 `Ok(None)`，可用于逐行遍历
+    pub async fn next_line(&mut self) -> Result<Option<String>> {
+        let mut line = String::new();
+        if self.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        framing::trim_newline(&mut line);
+        Ok(Some(line))
+    }
+
+    /// 以字节流方式写入数据，与 `read()` 配合使用
+    ///
+    /// 缓冲区按 `chunk_size` 分为多条消息依次提交给传输层，成功时返回值恒等于 `buf.len()`，因此无需循环调用。
+    /// 中途失败时返回 `VirgeError::PartialWrite`，`committed` 为此前已完整发出（启用 ACK 时已被确认）的字节数，
+    /// 对端按序收到的恰好是这些字节；尚未发出任何字节时返回原错误。
+    /// 启用 `with_batching` 时数据先进入批次缓冲区，返回时不一定已经发出。
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let Some((max_delay, max_bytes)) = self.config.batching else {
+            let mut committed = 0;
+            for chunk in buf.chunks(self.config.chunk_size as usize) {
+                if let Err(e) = self.send(chunk.to_vec()).await {
+                    return Err(VirgeError::partial_write(committed, e));
+                }
+                committed += chunk.len();
+            }
+            return Ok(committed);
+        };
+
+        self.check_writable()?;
+        let max = self.config.transport_options.max_message_size;
+        framing::check_size(buf.len(), max)?;
+        // 合并后会超过单条消息上限时，先发出已有的批次
+        if self.write_batch.len() + buf.len() > max {
+            self.flush_batch().await?;
+        }
+        self.write_batch.extend_from_slice(buf);
+        let started = *self.batch_started.get_or_insert_with(Instant::now);
+        if self.write_batch.len() >= max_bytes || started.elapsed() >= max_delay {
+            self.flush_batch().await?;
+        }
+        Ok(buf.len())
+    }
+
+    /// 发出 `write()` 批量缓冲的数据，并等待已提交的数据全部推送到线路上
+    ///
+    /// 依次发出批次缓冲区中的数据（发送失败时这些数据被丢弃）、冲刷传输层的发送缓冲
+    /// （yamux 虚拟流、vsock 流），启用 ACK 时再等待全部已发出消息的确认。
+    /// 受写超时约束，传输层的错误原样返回。
+    pub async fn flush(&mut self) -> Result<()> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.flush_batch().await?;
+        self.transport.flush().await
+    }
+
+    /// 与 `flush` 相同，但写入与等待确认的每一步最长等待 `timeout`，超时返回 `VirgeError::Timeout`
+    ///
+    /// 期间临时替换 `set_write_timeout` 设置的写超时，返回前恢复。
+    pub async fn flush_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.transport.set_write_timeout(Some(timeout))?;
+        let result = self.flush().await;
+        self.transport.set_write_timeout(self.write_timeout)?;
+        result
+    }
+
+    /// 将 `write()` 批量缓冲的数据立即作为一条消息发出，缓冲区为空时直接返回
+    ///
+    /// 之前有未完成的 `MessageWriter` 时先发出其放弃块头。发送失败时缓冲的数据被丢弃。
+    async fn flush_batch(&mut self) -> Result<()> {
+        self.send_pending_abort().await?;
+        if self.write_batch.is_empty() {
+            return Ok(());
+        }
+        self.batch_started = None;
+        let data = std::mem::take(&mut self.write_batch);
+        self.send_frame(data, true).await
+    }
+
+    /// 发出被放弃的 `MessageWriter` 消息的放弃块头，没有时立即返回
+    async fn send_pending_abort(&mut self) -> Result<()> {
+        if let Some(msg) = self.abort_pending.take() {
+            self.transport.send(FrameHeader::ChunkAbort { msg }.to_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// 接收数据到调用方提供的缓冲区，缓冲区仅在容量不足时增长
+    ///
+    /// # Returns
+    /// 返回接收到的字节数
+    pub async fn recv_into(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.flush_batch().await?;
+        if !self.read_buffer.is_empty() {
+            buf.clear();
+            buf.append(&mut self.read_buffer);
+            return Ok(buf.len());
+        }
+        self.transport.recv_into(buf).await
+    }
+
+    /// 发送一条带 8 字节大端长度前缀的消息
+    pub async fn send_msg(&mut self, data: &[u8]) -> Result<()> {
+        let frame = framing::encode(data, self.config.transport_options.max_message_size)?;
+        self.flush_batch().await?;
+        self.send_frame(frame, true).await
+    }
+
+    /// 接收一条带长度前缀的消息
+    ///
+    /// 消息可跨越多次传输层接收；对端在消息中途断开时返回 `ErrorKind::UnexpectedEof`，
+    /// 长度前缀超过 `max_message_size` 时在分配内存前返回错误。
+    pub async fn recv_msg(&mut self) -> Result<Vec<u8>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.flush_batch().await?;
+        framing::recv(self.transport.as_mut(), &mut self.read_buffer, self.config.transport_options.max_message_size).await
+    }
+
+    /// 在 `timeout` 内接收一条带长度前缀的消息，超时时报告是否已收到部分数据
+    ///
+    /// 超时不会丢弃已收到的部分消息，之后的 `recv_msg` 或 `recv_msg_timeout` 从中断处继续；
+    /// `TimedOutPartial` 表示对端仍在发送但较慢，`TimedOutIdle` 表示期间没有任何数据到达，
+    /// `Closed` 表示对端已正常关闭连接或关闭了写方向。
+    /// 返回前恢复 `set_read_timeout` 设置的读超时；对端在消息中途断开时返回 `ErrorKind::UnexpectedEof`。
+    pub async fn recv_msg_timeout(&mut self, timeout: Duration) -> Result<RecvOutcome> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        if self.peer_eof && self.read_buffer.is_empty() {
+            return Ok(RecvOutcome::Closed);
+        }
+        self.flush_batch().await?;
+        let max = self.config.transport_options.max_message_size;
+        let result = RecvOutcome::receive(self.transport.as_mut(), &mut self.read_buffer, max, timeout).await;
+        self.transport.set_read_timeout(self.read_timeout)?;
+        if matches!(result, Ok(RecvOutcome::Closed)) {
+            self.peer_eof = true;
+        }
+        result
+    }
+
+    /// 发送请求并等待服务器 `serve_requests` 的响应，超时时间取 `with_call_timeout` 的设置
+    ///
+    /// 超时返回 `VirgeError::Timeout`（转换为 `io::Error` 时为 `ErrorKind::TimedOut`），
+    /// 连接保持可用，迟到的响应会在之后的调用中被丢弃。请求可能已被处理，因此不会自动重连重试。
+    pub async fn call(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        self.call_timeout(payload, self.config.call_timeout).await
+    }
+
+    /// 以指定超时发送请求并等待响应
+    pub async fn call_timeout(&mut self, payload: &[u8], timeout: Duration) -> Result<Vec<u8>> {
+        self.check_writable()?;
+        self.flush_batch().await?;
+        let id = self.next_call_id;
+        self.next_call_id = self.next_call_id.wrapping_add(1);
+        rpc::call(
+            self.transport.as_mut(),
+            id,
+            payload,
+            self.config.transport_options.max_message_size,
+            timeout,
+            self.read_timeout,
+        )
+        .await
+    }
+
+    /// 将值按配置的 `WireFormat` 编码为一条消息发送
+    #[cfg(feature = "serde")]
+    pub async fn send_serialized<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let max = self.config.transport_options.max_message_size;
+        let data = codec::encode(self.config.wire_format, value, max)?;
+        self.send(data).await
+    }
+
+    /// 接收一条消息并按配置的 `WireFormat` 解码，数据与类型不符时返回 `VirgeError::CodecError`
+    #[cfg(feature = "serde")]
+    pub async fn recv_deserialized<T: DeserializeOwned>(&mut self) -> Result<T> {
+        let data = self.recv().await?;
+        codec::decode(self.config.wire_format, &data, self.config.transport_options.max_message_size)
+    }
+
+    /// 类型化消息的编码格式与单条消息大小上限
+    #[cfg(feature = "serde")]
+    pub(crate) fn codec_options(&self) -> (WireFormat, usize) {
+        (self.config.wire_format, self.config.transport_options.max_message_size)
+    }
+
+    /// 连接统计快照，重连后继续累加，`connect_time` 更新为最近一次连接的时间
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot()
+    }
+
+    /// 将连接拆分为读半部与写半部，可分别在不同的任务或线程中使用
+    ///
+    /// 已收到但未消费的数据转移到读半部；拆分后不再自动重连，任一半部释放不影响另一半部。
+    pub fn split(mut self) -> (VirgeReadHalf, VirgeWriteHalf) {
+        let transport = std::mem::replace(&mut self.transport, Box::new(split::Detached));
+        self.connected = false;
+        split::split(
+            transport,
+            std::mem::take(&mut self.read_buffer),
+            self.config.transport_options.max_message_size,
+            None,
+        )
+    }
+
+    /// 将连接转换为可克隆的句柄，交给多个任务或线程共用
+    ///
+    /// 并发发送按消息粒度串行化，加锁行为见 `handle` 模块文档；转换后不再自动重连。
+    pub fn handle(mut self) -> VirgeClientHandle {
+        let transport = std::mem::replace(&mut self.transport, Box::new(split::Detached));
+        self.connected = false;
+        VirgeClientHandle::new(
+            transport,
+            std::mem::take(&mut self.read_buffer),
+            self.config.transport_options.max_message_size,
+        )
+    }
+
+    /// 按配置的 chunk_size 分块发送一条带长度前缀的消息，每块发送后回调 `(已发送字节数, 总字节数)`
+    ///
+    /// 对端可使用 `recv_msg` 或 `recv_with_progress` 接收。回调 panic 时返回错误并断开连接，
+    /// 对端以 `UnexpectedEof` 结束接收，不会收到被截断却看似完整的消息。
+    pub async fn send_with_progress(&mut self, data: &[u8], callback: impl FnMut(u64, u64)) -> Result<()> {
+        self.check_writable()?;
+        self.flush_batch().await?;
+        framing::send_with_progress(
+            self.transport.as_mut(),
+            data,
+            self.config.chunk_size as usize,
+            self.config.transport_options.max_message_size,
+            callback,
+        ).await
+    }
+
+    /// 开始一条分块发送的消息，返回将数据直接写入分块缓冲区的写入器
+    ///
+    /// 消息的总长度无需事先确定，`len_hint` 只用于预留第一块的空间，超过 `max_message_size` 时直接返回
+    /// `MessageTooLarge`。写入器的 `write`/`write_all` 每写满一块即发出，可逐段写入任意长度的数据，
+    /// 调用 `finish` 后消息才算完成；对端照常使用 `recv_msg` 或 `recv_stream` 接收。
+    /// 写入器未调用 `finish` 即被释放时，对端的这条消息以 `MessageAborted` 结束，不会收到被截断的消息。
+    pub async fn start_message(&mut self, len_hint: usize) -> Result<MessageWriter<'_>> {
+        self.check_writable()?;
+        let max = self.config.transport_options.max_message_size;
+        framing::check_size(len_hint, max)?;
+        self.flush_batch().await?;
+        Ok(MessageWriter::new(self, len_hint, max))
+    }
+
+    /// 从 `reader` 流式发送 `len` 字节，按 chunk_size 分块读取，不在内存中缓存整个负载
+    ///
+    /// 对端需使用 `recv_to_file` 接收；`reader` 提前结束时返回错误并断开连接。
+    pub async fn send_file<R: Read>(&mut self, reader: &mut R, len: u64) -> Result<u64> {
+        self.check_writable()?;
+        self.flush_batch().await?;
+        framing::send_stream(self.transport.as_mut(), reader, len, self.config.chunk_size as usize).await
+    }
+
+    /// 接收 `send_file` 发送的数据并逐块写入 `writer`，返回写入的字节数
+    pub async fn recv_to_file<W: Write>(&mut self, writer: &mut W) -> Result<u64> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.flush_batch().await?;
+        framing::recv_stream(self.transport.as_mut(), &mut self.read_buffer, writer).await
+    }
+
+    /// 接收一条带长度前缀的消息，每次收到数据后回调 `(已接收字节数, 总字节数)`
+    ///
+    /// 回调 panic 时返回错误，已收到的数据保留，可继续通过 `recv_msg` 接收该消息。
+    pub async fn recv_with_progress(&mut self, callback: impl FnMut(u64, u64)) -> Result<Vec<u8>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.flush_batch().await?;
+        framing::recv_with_progress(
+            self.transport.as_mut(),
+            &mut self.read_buffer,
+            self.config.transport_options.max_message_size,
+            callback,
+        ).await
+    }
+
+    /// 非阻塞接收数据，当前没有可读数据时返回 `Ok(None)`
+    pub async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.flush_batch().await?;
+        self.transport.try_recv().await
+    }
+
+    /// 非阻塞发送数据，当前无法写入时返回 `Ok(None)`，成功时返回发送的字节数
+    pub async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        self.check_writable()?;
+        framing::check_size(data.len(), self.config.transport_options.max_message_size)?;
+        self.flush_batch().await?;
+        self.transport.try_send(data).await
+    }
+
+    /// 经优先通道发送一条消息，对端通过 `recv_priority` 接收
+    ///
+    /// 需两端都启用 `with_priority_lanes`，否则返回 `VirgeError::ConfigError`。不等待批量写入的缓冲，
+    /// 但先发出被放弃的 `MessageWriter` 消息的放弃块头，也不参与自动重连。
+    /// 要在大消息发送期间插队，需将连接转换为句柄，见 `VirgeClientHandle::send_priority`。
+    pub async fn send_priority(&mut self, data: &[u8]) -> Result<()> {
+        self.check_writable()?;
+        framing::check_size(data.len(), self.config.transport_options.max_message_size)?;
+        self.send_pending_abort().await?;
+        self.transport.send_priority(data.to_vec()).await
+    }
+
+    /// 接收一条优先消息，期间收到的普通消息留给 `recv`
+    ///
+    /// 对端关闭写方向后返回空消息。
+    pub async fn recv_priority(&mut self) -> Result<Vec<u8>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.flush_batch().await?;
+        if self.peer_eof {
+            return Ok(Vec::new());
+        }
+        let result = self.transport.recv_priority().await;
+        self.end_of_stream(result, Vec::new())
+    }
+
+    /// 发送一条附带元数据的消息，对端通过 `recv_with_meta` 取回元数据，普通的 `recv` 只收到 `data`
+    ///
+    /// 需两端都启用 `with_metadata`，否则返回 `VirgeError::ConfigError`；
+    /// 元数据编码后超过 4 KiB 或单个键超过 255 字节时返回 `VirgeError::MessageTooLarge`。
+    pub async fn send_with_meta(&mut self, data: &[u8], meta: &HeaderMap) -> Result<()> {
+        self.check_writable()?;
+        framing::check_size(data.len(), self.config.transport_options.max_message_size)?;
+        self.flush_batch().await?;
+        self.transport.send_with_meta(data.to_vec(), meta).await
+    }
+
+    /// 接收一条消息及其元数据，对端以普通 `send` 发出的消息元数据为空
+    ///
+    /// 服务器关闭写方向后返回空消息与空元数据。
+    pub async fn recv_with_meta(&mut self) -> Result<(Vec<u8>, HeaderMap)> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.flush_batch().await?;
+        // 优先返回 recv_msg 预读但未消费的数据，其元数据已随之前的读取丢弃
+        if !self.read_buffer.is_empty() {
+            return Ok((std::mem::take(&mut self.read_buffer), HeaderMap::new()));
+        }
+        if self.peer_eof {
+            return Ok((Vec::new(), HeaderMap::new()));
+        }
+        let result = self.transport.recv_with_meta().await;
+        self.end_of_stream(result, (Vec::new(), HeaderMap::new()))
+    }
+
+    /// 设置读超时，`None` 表示一直阻塞
+    ///
+    /// 超时后 `recv()` 返回 `VirgeError::Timeout`，连接保持可用，可直接重试。
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.transport.set_read_timeout(timeout)?;
+        self.read_timeout = timeout;
+        Ok(())
+    }
+
+    /// 设置写超时，`None` 表示一直阻塞
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.transport.set_write_timeout(timeout)?;
+        self.write_timeout = timeout;
+        Ok(())
+    }
+
+    /// 在当前连接上打开一个独立的虚拟流，仅 yamux 传输支持
+    ///
+    /// 虚拟流与 send/recv 使用的主流互不影响，可用于在同一 vsock 连接上分离控制与批量数据通道。
+    #[cfg(feature = "use-yamux")]
+    pub async fn open_stream(&mut self) -> Result<VirgeStream> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Client not connected".to_string(),
+            ));
+        }
+        self.transport.open_stream().await
+    }
+
+    /// 检查连接状态（被动）
+    ///
+    /// 只读取本地记录的状态，不访问套接字：对端异常退出后，在下一次收发失败之前仍可能返回 `true`。
+    /// 启用 keepalive 时，发出的 PING 超过心跳超时未获回应也返回 `false`。需要确认对端存活时使用 `probe_connection`。
+    pub fn is_connected(&self) -> bool {
+        self.connected && self.transport.is_connected()
+    }
+
+    /// 主动检测连接是否存活，最多等待 `timeout`
+    ///
+    /// 检查底层套接字是否已挂断或读到对端关闭；启用 keepalive 时还会立即发送 PING，
+    /// 并等待对端在 `timeout` 内发来任意帧，期间收到的消息保留给之后的接收调用。
+    /// 对端需要在此期间调用接收接口才能应答 PING。未启用 keepalive 时无法发现未关闭套接字就失联的对端。
+    ///
+    /// # Returns
+    /// 存活返回 `Ok(true)`；已断开或未在 `timeout` 内回应返回 `Ok(false)`，连接状态保持不变；
+    /// `timeout` 为零或发送 PING 时出现其他错误返回 `Err`
+    pub async fn probe_connection(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        crate::transport::check_timeout(Some(timeout))?;
+        if !self.is_connected() {
+            return Ok(false);
+        }
+        match self.transport.probe(timeout).await {
+            Ok(alive) => Ok(alive),
+            Err(e) if e.is_disconnected() => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 当前连接的本地地址，用于记录实际使用的本地端口
+    ///
+    /// 未连接时为 `None`；uds 与内存传输没有本地 vsock 地址，同样为 `None`，tcp 传输映射为 `VMADDR_CID_LOCAL` 加本地 TCP 端口。
+    pub fn local_addr(&self) -> Option<VsockAddr> {
+        if !self.connected {
+            return None;
+        }
+        self.transport.local_addr()
+    }
+
+    /// 当前连接的 vsock 套接字实际生效的缓冲区大小，内核可能调整了 `with_socket_buffer` 设置的值
+    ///
+    /// 未连接时返回 `VirgeError::Disconnected`；tcp、uds 与内存传输没有 vsock 套接字，返回 `VirgeError::ConfigError`。
+    pub fn socket_buffer_sizes(&self) -> Result<SocketBufferSizes> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected("Client not connected".to_string()));
+        }
+        crate::transport::socket_buffer_sizes(self.transport.as_ref())
+    }
+
+    /// 当前连接的对端地址，即配置的 `server_cid:server_port`，未连接时为 `None`
+    pub fn peer_addr(&self) -> Option<VsockAddr> {
+        self.connected.then(|| VsockAddr::new(self.config.server_cid, self.config.server_port))
+    }
+
+    /// 当前连接的 ID，每次连接（包括重连）成功后重新分配，尚未连接时为 `None`
+    ///
+    /// 进程内唯一；xtransport 启用 `tracing` 特性时与连接 span 中的 `conn_id` 字段一致，便于关联应用日志。
+    pub fn connection_id(&self) -> Option<u64> {
+        self.conn_id
+    }
+
+    /// 连接成功后分配连接 ID，传输自身分配了 ID 时沿用
+    fn assign_connection_id(&mut self) {
+        let id = self.transport.connection_id().unwrap_or_else(crate::transport::next_connection_id);
+        debug!("VirgeClient connection id {}", id);
+        self.conn_id = Some(id);
+    }
+
+    /// 是否有已读出但尚未消费的数据，连接池据此判断连接能否复用
+    ///
+    /// 为 `true` 时即使描述符不可读，`recv`/`try_recv` 也可能立即返回数据；
+    /// 使用自定义事件循环时，应在等待描述符可读前先检查此方法。
+    pub fn has_buffered_data(&self) -> bool {
+        !self.read_buffer.is_empty() || self.transport.has_buffered_data()
+    }
+
+    /// 是否有一条消息可由 `recv`/`try_recv` 立即取得而无需等待新数据
+    ///
+    /// 先把套接字上已到达的消息读入传输内部的队列，再像 `peek_msg_len` 一样非阻塞地查看下一条消息，
+    /// 查看到的消息留给下一次接收；已开始到达的消息会等待其余分块。在执行耗时工作前取完积压的消息：
+    /// `while conn.has_pending_message().await? { handle(conn.recv().await?) }`。
+    pub async fn has_pending_message(&mut self) -> std::io::Result<bool> {
+        if self.connected && self.read_buffer.is_empty() && !self.peer_eof {
+            self.transport.prefetch().await?;
+        }
+        Ok(self.peek_msg_len().await?.is_some())
+    }
+
+    /// 已接收但尚未被读取的字节数：`recv_msg`、`peek_msg_len` 等接口预读的数据，加上传输内部已完整接收、
+    /// 尚未交付的消息（目前只有 xtransport 在 `has_pending_message` 时预读整条消息）
+    pub fn pending_bytes(&self) -> usize {
+        self.read_buffer.len() + self.transport.pending_bytes()
+    }
+}
+
+/// 返回底层套接字的描述符，供 epoll 等事件循环注册可读通知；未连接或内存传输时返回 `-1`
+///
+/// 描述符可读只表示有字节到达，不保证已有一条完整的消息，收到通知后应使用 `try_recv` 读取；
+/// 反之，数据可能已被读入内部缓冲区而描述符不再可读，见 `has_buffered_data`。
+/// yamux 传输的套接字由后台驱动任务读取，描述符的可读状态不能反映消息是否到达。
+/// 不要直接从描述符读写或关闭它，否则会破坏协议状态。
+impl AsRawFd for VirgeClient {
+    fn as_raw_fd(&self) -> RawFd {
+        self.transport.raw_fd().unwrap_or(-1)
+    }
+}
+
+impl Drop for VirgeClient {
+    fn drop(&mut self) {
+        if !self.connected {
+            return;
+        }
+        if !self.write_batch.is_empty() {
+            warn!(
+                "VirgeClient dropped without flush, discarding {} batched bytes",
+                self.write_batch.len()
+            );
+        }
+        // 传输实例随客户端一同释放，其持有的套接字在此关闭
+        if self.read_buffer.is_empty() {
+            debug!("VirgeClient dropped without disconnect, closing transport");
+        } else {
+            warn!(
+                "VirgeClient dropped without disconnect, discarding {} unread bytes",
+                self.read_buffer.len()
+            );
+        }
+    }
+}
+
+/// `VirgeClient::start_message` 返回的单条消息写入器，写入的数据直接进入分块缓冲区
+///
+/// 每写满 `chunk_size` 字节即封为一块立即发出，内存中只保留正在写入的一块，消息的总长度不受内存限制；
+/// 写入器存在期间独占该客户端。未调用 `finish` 即被释放时，若已有数据发出，则在客户端下一次发送或接收前
+/// 发出放弃块头，对端丢弃已收到的部分并返回 `MessageAborted`；尚未写满过一块时线路上不留痕迹。
+///
+/// 启用 tokio 运行时时实现 `tokio::io::AsyncWrite`，可直接交给 `tokio::io::copy` 或各类编码器：
+/// `poll_write` 与 `write` 相同，`poll_flush` 将当前未满的块也封口发出，`poll_shutdown` 与 `finish` 相同，
+/// 完成这条消息。一块正在发送时释放写入器会中断帧的发送，连接随之断开。
+pub struct MessageWriter<'a> {
+    client: &'a mut VirgeClient,
+    /// 本消息的序号，写入每个块头
+    msg: u32,
+    /// 正在写入的块，`data_start` 之前为长度前缀（仅第一块）与预留的块头
+    current: Vec<u8>,
+    data_start: usize,
+    chunk_size: usize,
+    max: usize,
+    /// 已写入的负载字节数
+    written: usize,
+    /// 正在发送的块；发送期间传输实例移入其中，完成后归还客户端
+    sending: Option<PendingSend>,
+    /// 已开始向传输层发送，放弃时需要通知对端
+    started: bool,
+    /// 发送失败后该消息已不完整，不再接受写入
+    failed: bool,
+    /// 结束块头已封入最后一块，不再接受写入
+    finished: bool,
+}
+
+type PendingSend = BoxFuture<'static, (Box<dyn Transport>, Result<()>)>;
+
+impl<'a> MessageWriter<'a> {
+    fn new(client: &'a mut VirgeClient, len_hint: usize, max: usize) -> Self {
+        // 块长度不能与保留的放弃块头相同
+        let chunk_size = (client.config.chunk_size as usize).clamp(1, CHUNK_ABORT_LEN as usize - 1);
+        let msg = client.next_chunked;
+        client.next_chunked = msg.wrapping_add(1);
+        let data_start = LEN_PREFIX_SIZE + CHUNK_HEADER_SIZE;
+        let mut current = Vec::with_capacity(data_start + len_hint.min(chunk_size));
+        FrameHeader::ChunkedMessage.encode_into(&mut current);
+        current.extend_from_slice(&[0; CHUNK_HEADER_SIZE]);
+        Self {
+            client,
+            msg,
+            current,
+            data_start,
+            chunk_size,
+            max,
+            written: 0,
+            sending: None,
+            started: false,
+            failed: false,
+            finished: false,
+        }
+    }
+
+    /// 已写入的负载字节数
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// 当前块中尚未发出的负载字节数，恒小于 `chunk_size`
+    pub fn buffered(&self) -> usize {
+        self.current.len().saturating_sub(self.data_start)
+    }
+
+    /// 写入当前块，至多写到块满为止，块写满时立即发出并等待发送完成；返回写入的字节数
+    ///
+    /// 累计超过 `max_message_size` 时整段拒绝并返回 `MessageTooLarge`，已写入的数据不受影响。
+    /// 发送失败时该消息已无法完成，之后的写入与 `finish` 均返回错误，释放写入器即放弃该消息。
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let n = futures::future::poll_fn(|cx| self.poll_write_chunk(cx, buf)).await?;
+        futures::future::poll_fn(|cx| self.poll_sent(cx)).await?;
+        Ok(n)
+    }
+
+    /// 写入 `buf` 的全部数据，期间每写满一块即发出；累计超过 `max_message_size` 时不写入任何数据
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+        self.check_usable()?;
+        framing::check_size(self.written.saturating_add(buf.len()), self.max)?;
+        while !buf.is_empty() {
+            let n = self.write(buf).await?;
+            buf = &buf[n..];
+        }
+        Ok(())
+    }
+
+    /// 封口当前块并写入结束块头后发出，消息随之完成
+    pub async fn finish(mut self) -> Result<()> {
+        futures::future::poll_fn(|cx| self.poll_finish(cx)).await
+    }
+
+    /// 等待之前的块发送完成后写入当前块，块写满时开始发送
+    fn poll_write_chunk(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        ready!(self.poll_sent(cx))?;
+        self.check_usable()?;
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        framing::check_size(self.written.saturating_add(buf.len()), self.max)?;
+        let room = self.chunk_size - self.buffered();
+        let n = room.min(buf.len());
+        self.current.extend_from_slice(&buf[..n]);
+        self.written += n;
+        if n == room {
+            self.send_current(cx)?;
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    /// 将未满的当前块也封口发出，等待所有块发送完成
+    fn poll_push(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        ready!(self.poll_sent(cx))?;
+        if !self.finished && !self.failed && self.buffered() > 0 {
+            self.send_current(cx)?;
+            ready!(self.poll_sent(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// 封口最后一块并写入结束块头，等待其发送完成
+    fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        ready!(self.poll_sent(cx))?;
+        if self.finished && !self.failed {
+            return Poll::Ready(Ok(()));
+        }
+        self.check_usable()?;
+        let mut last = if self.buffered() > 0 {
+            self.seal()
+        } else {
+            // 当前块为空：去掉预留的块头，结束块头单独发出（消息为空时前面还有长度前缀）
+            let mut last = std::mem::take(&mut self.current);
+            last.truncate(self.data_start - CHUNK_HEADER_SIZE);
+            last
+        };
+        FrameHeader::ChunkEnd { msg: self.msg }.encode_into(&mut last);
+        self.finished = true;
+        self.start_send(last);
+        self.poll_sent(cx)
+    }
+
+    fn check_usable(&self) -> Result<()> {
+        if self.failed {
+            return Err(VirgeError::transport("message writer failed to send an earlier chunk"));
+        }
+        if self.finished {
+            return Err(VirgeError::transport("message writer has already finished the message"));
+        }
+        Ok(())
+    }
+
+    /// 封口当前块并开始发送，随后换上一块只预留块头的新块；发送立即失败时返回错误
+    fn send_current(&mut self, cx: &mut Context<'_>) -> Result<()> {
+        let chunk = self.seal();
+        self.current = Vec::with_capacity(CHUNK_HEADER_SIZE + self.chunk_size);
+        self.current.extend_from_slice(&[0; CHUNK_HEADER_SIZE]);
+        self.data_start = CHUNK_HEADER_SIZE;
+        self.start_send(chunk);
+        match self.poll_sent(cx) {
+            Poll::Ready(Err(e)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// 写入预留的块头并取出当前块，当前块须不为空
+    fn seal(&mut self) -> Vec<u8> {
+        let header = self.data_start - CHUNK_HEADER_SIZE;
+        let chunk = FrameHeader::Chunk { msg: self.msg, len: self.buffered() as u32 };
+        self.current[header..self.data_start].copy_from_slice(&chunk.to_bytes());
+        std::mem::take(&mut self.current)
+    }
+
+    /// 将传输实例移入发送中的操作，完成前客户端持有占位传输
+    fn start_send(&mut self, chunk: Vec<u8>) {
+        self.started = true;
+        let mut transport = std::mem::replace(&mut self.client.transport, Box::new(split::Detached));
+        self.sending = Some(
+            async move {
+                let result = transport.send(chunk).await;
+                (transport, result)
+            }
+            .boxed(),
+        );
+    }
+
+    /// 驱动发送中的块直到完成并归还传输实例，没有发送中的块时立即返回
+    fn poll_sent(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let Some(sending) = self.sending.as_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+        let (transport, result) = ready!(sending.poll_unpin(cx));
+        self.sending = None;
+        self.client.transport = transport;
+        if result.is_err() {
+            self.failed = true;
+        }
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl tokio::io::AsyncWrite for MessageWriter<'_> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        self.get_mut().poll_write_chunk(cx, buf).map_err(std::io::Error::from)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.get_mut().poll_push(cx).map_err(std::io::Error::from)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.get_mut().poll_finish(cx).map_err(std::io::Error::from)
+    }
+}
+
+impl Drop for MessageWriter<'_> {
+    fn drop(&mut self) {
+        if self.sending.is_some() {
+            // 传输实例随未完成的发送一同释放，半个帧之后的数据已无法解析
+            warn!("MessageWriter dropped while sending a chunk, closing the connection");
+            self.client.connected = false;
+            return;
+        }
+        if (self.finished && !self.failed) || !self.started {
+            return;
+        }
+        debug!("MessageWriter dropped before finish, aborting the message after {} bytes", self.written);
+        self.client.abort_pending = Some(self.msg);
     }
 }
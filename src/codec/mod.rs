@@ -0,0 +1,53 @@
+//! 类型化消息编解码模块
+//!
+//! 启用 `serde` 特性后，`send_serialized` / `recv_deserialized` 将实现了 serde 的类型编码为一条消息收发。
+//! 编码格式由配置中的 [`WireFormat`] 选择，两端必须一致：
+//! - `Bincode`（默认）：紧凑的二进制编码
+//! - `Json`（`serde-json` 特性）：便于调试与跨语言互通
+//!
+//! 编解码失败返回 `VirgeError::CodecError`，连接保持可用。
+
+use crate::error::{Result, VirgeError};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// 类型化消息的编码格式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WireFormat {
+    /// bincode 编码
+    #[default]
+    Bincode,
+    /// JSON 编码
+    #[cfg(feature = "serde-json")]
+    Json,
+}
+
+/// bincode 编码参数：限制解码时的分配不超过消息上限
+fn bincode_options(max_message_size: usize) -> impl Options {
+    bincode::DefaultOptions::new().with_limit(max_message_size as u64)
+}
+
+/// 将值编码为一条消息
+pub(crate) fn encode<T: Serialize + ?Sized>(format: WireFormat, value: &T, max_message_size: usize) -> Result<Vec<u8>> {
+    match format {
+        WireFormat::Bincode => bincode_options(max_message_size)
+            .serialize(value)
+            .map_err(|e| VirgeError::CodecError(format!("bincode serialize failed: {}", e))),
+        #[cfg(feature = "serde-json")]
+        WireFormat::Json => serde_json::to_vec(value)
+            .map_err(|e| VirgeError::CodecError(format!("json serialize failed: {}", e))),
+    }
+}
+
+/// 从一条消息解码值
+pub(crate) fn decode<T: DeserializeOwned>(format: WireFormat, data: &[u8], max_message_size: usize) -> Result<T> {
+    match format {
+        WireFormat::Bincode => bincode_options(max_message_size)
+            .deserialize(data)
+            .map_err(|e| VirgeError::CodecError(format!("bincode deserialize failed: {}", e))),
+        #[cfg(feature = "serde-json")]
+        WireFormat::Json => serde_json::from_slice(data)
+            .map_err(|e| VirgeError::CodecError(format!("json deserialize failed: {}", e))),
+    }
+}
@@ -0,0 +1,315 @@
+//! 外部配置加载模块
+//!
+//! 从环境变量或 TOML 配置文件（`config-file` 特性）读取 `ClientConfig`/`ServerConfig` 的参数。
+//! 每个来源被读取为一层键值，由构造器按调用顺序覆盖，后应用的来源优先，
+//! 取值最终仍经过 `build()` 的统一校验。
+//!
+//! # 环境变量
+//! 键名为 `VIRGA_` 加上大写的配置键，例如 `server_port` 对应 `VIRGA_SERVER_PORT`。
+//!
+//! | 配置键               | 环境变量                    | 适用     |
+//! |----------------------|-----------------------------|----------|
+//! | `server_cid`         | `VIRGA_SERVER_CID`          | 客户端   |
+//! | `server_port`        | `VIRGA_SERVER_PORT`         | 客户端   |
+//! | `connect_timeout_ms` | `VIRGA_CONNECT_TIMEOUT_MS`  | 客户端   |
+//! | `listen_cid`         | `VIRGA_LISTEN_CID`          | 服务器   |
+//! | `listen_port`        | `VIRGA_LISTEN_PORT`         | 服务器   |
+//! | `max_connections`    | `VIRGA_MAX_CONNECTIONS`     | 服务器   |
+//! | `backlog`            | `VIRGA_BACKLOG`             | 服务器   |
+//! | `reuse_addr`         | `VIRGA_REUSE_ADDR`          | 服务器   |
+//! | `chunk_size`         | `VIRGA_CHUNK_SIZE`          | 两者     |
+//! | `ack`                | `VIRGA_ACK`                 | 两者     |
+//! | `max_message_size`   | `VIRGA_MAX_MESSAGE_SIZE`    | 两者     |
+//! | `transport`          | `VIRGA_TRANSPORT`           | 两者     |
+//! | `uds_path`           | `VIRGA_UDS_PATH`            | 两者     |
+//! | `seqpacket`          | `VIRGA_SEQPACKET`           | 两者     |
+//!
+//! 布尔值接受 `true/false`、`1/0`、`yes/no`、`on/off`；`transport` 取 `xtransport`、`yamux`、`raw` 或 `tcp`；`uds_path` 需启用 `use-uds` 特性，
+//! `seqpacket` 需启用 `use-raw` 特性。
+//! 无法识别的 `VIRGA_*` 变量与配置文件中的未知键只记录警告。
+
+use crate::error::{Result, VirgeError};
+use log::*;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// 环境变量前缀
+const ENV_PREFIX: &str = "VIRGA_";
+
+/// 客户端配置键
+pub(crate) const CLIENT_KEYS: &[&str] = &[
+    "server_cid",
+    "server_port",
+    "connect_timeout_ms",
+    "chunk_size",
+    "ack",
+    "max_message_size",
+    "transport",
+    #[cfg(feature = "use-uds")]
+    "uds_path",
+    #[cfg(feature = "use-raw")]
+    "seqpacket",
+];
+
+/// 服务器配置键
+pub(crate) const SERVER_KEYS: &[&str] = &[
+    "listen_cid",
+    "listen_port",
+    "max_connections",
+    "backlog",
+    "reuse_addr",
+    "chunk_size",
+    "ack",
+    "max_message_size",
+    "transport",
+    #[cfg(feature = "use-uds")]
+    "uds_path",
+    #[cfg(feature = "use-raw")]
+    "seqpacket",
+];
+
+/// 配置来源
+enum Origin {
+    Env,
+    #[cfg(feature = "config-file")]
+    File(String),
+}
+
+/// 一个配置来源中的原始键值
+pub(crate) struct Layer {
+    origin: Origin,
+    values: HashMap<&'static str, String>,
+}
+
+impl Layer {
+    /// 读取 `keys` 对应的 `VIRGA_*` 环境变量
+    pub(crate) fn from_env(keys: &[&'static str]) -> Self {
+        Self::from_vars(std::env::vars(), keys)
+    }
+
+    /// 从给定的变量中读取 `keys` 对应的 `VIRGA_*` 变量，与 `from_env` 规则相同
+    fn from_vars(vars: impl IntoIterator<Item = (String, String)>, keys: &[&'static str]) -> Self {
+        let mut values = HashMap::new();
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let key = key.to_ascii_lowercase();
+            match keys.iter().find(|known| **known == key) {
+                Some(known) => {
+                    values.insert(*known, value);
+                }
+                // 客户端与服务器共用 VIRGA_ 前缀，仅对两者都不认识的变量告警
+                None if !CLIENT_KEYS.contains(&key.as_str()) && !SERVER_KEYS.contains(&key.as_str()) => {
+                    warn!("Ignoring unknown environment variable {}", name);
+                }
+                None => {}
+            }
+        }
+        Self { origin: Origin::Env, values }
+    }
+
+    /// 读取 TOML 配置文件中 `keys` 对应的顶层键
+    #[cfg(feature = "config-file")]
+    pub(crate) fn from_toml_file(path: &std::path::Path, keys: &[&'static str]) -> Result<Self> {
+        let origin = path.display().to_string();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| VirgeError::ConfigError(format!("Failed to read {}: {}", origin, e)))?;
+        let table: toml::Table = toml::from_str(&content)
+            .map_err(|e| VirgeError::ConfigError(format!("Failed to parse {}: {}", origin, e)))?;
+
+        let mut values = HashMap::new();
+        for (key, value) in table {
+            let Some(known) = keys.iter().find(|known| **known == key) else {
+                warn!("Ignoring unknown key '{}' in {}", key, origin);
+                continue;
+            };
+            let value = match value {
+                toml::Value::String(s) => s,
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Boolean(b) => b.to_string(),
+                other => {
+                    return Err(VirgeError::ConfigError(format!(
+                        "Key '{}' in {} has unsupported type {}",
+                        key,
+                        origin,
+                        other.type_str()
+                    )));
+                }
+            };
+            values.insert(*known, value);
+        }
+        Ok(Self { origin: Origin::File(origin), values })
+    }
+
+    /// 错误信息中的键名：环境变量为变量名，配置文件为键名及文件路径
+    fn describe(&self, key: &str) -> String {
+        match &self.origin {
+            Origin::Env => format!("{}{}", ENV_PREFIX, key.to_ascii_uppercase()),
+            #[cfg(feature = "config-file")]
+            Origin::File(path) => format!("'{}' in {}", key, path),
+        }
+    }
+
+    /// 确认必需的键均已提供，缺失时返回指明键名的 `ConfigError`
+    pub(crate) fn require(&self, keys: &[&str]) -> Result<()> {
+        match keys.iter().find(|key| !self.values.contains_key(**key)) {
+            Some(key) => Err(VirgeError::ConfigError(format!(
+                "Missing required config {}",
+                self.describe(key)
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// 解析键值，未提供时返回 `Ok(None)`
+    pub(crate) fn get<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        let Some(raw) = self.values.get(key) else {
+            return Ok(None);
+        };
+        raw.trim().parse().map(Some).map_err(|e| {
+            VirgeError::ConfigError(format!("Invalid value '{}' for {}: {}", raw, self.describe(key), e))
+        })
+    }
+
+    /// 解析布尔键值
+    pub(crate) fn get_bool(&self, key: &str) -> Result<Option<bool>> {
+        let Some(raw) = self.values.get(key) else {
+            return Ok(None);
+        };
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Ok(Some(true)),
+            "false" | "0" | "no" | "off" => Ok(Some(false)),
+            _ => Err(VirgeError::ConfigError(format!(
+                "Invalid boolean '{}' for {}",
+                raw,
+                self.describe(key)
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ClientConfig;
+    use crate::server::ServerConfig;
+
+    fn env(vars: &[(&str, &str)], keys: &[&'static str]) -> Layer {
+        Layer::from_vars(vars.iter().map(|(k, v)| (k.to_string(), v.to_string())), keys)
+    }
+
+    fn config_error<T>(result: Result<T>) -> String {
+        match result {
+            Err(VirgeError::ConfigError(message)) => message,
+            other => panic!("expected ConfigError, got {:?}", other.err()),
+        }
+    }
+
+    /// 写入进程内唯一的临时 TOML 文件，`Drop` 时删除
+    #[cfg(feature = "config-file")]
+    struct TempToml(std::path::PathBuf);
+
+    #[cfg(feature = "config-file")]
+    impl TempToml {
+        fn new(name: &str, content: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("virga-{}-{}.toml", std::process::id(), name));
+            std::fs::write(&path, content).unwrap();
+            Self(path)
+        }
+    }
+
+    #[cfg(feature = "config-file")]
+    impl Drop for TempToml {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn env_layer_picks_known_keys_and_ignores_others() {
+        let layer = env(
+            &[("VIRGA_SERVER_PORT", "20"), ("VIRGA_LISTEN_PORT", "30"), ("VIRGA_BOGUS", "1"), ("PATH", "/bin")],
+            CLIENT_KEYS,
+        );
+        assert_eq!(layer.get::<u32>("server_port").unwrap(), Some(20));
+        // 服务器的键不属于客户端配置，不会被读入
+        assert_eq!(layer.get::<u32>("listen_port").unwrap(), None);
+        assert_eq!(layer.values.len(), 1);
+    }
+
+    #[test]
+    fn missing_required_key_is_named() {
+        let layer = env(&[("VIRGA_SERVER_CID", "3")], CLIENT_KEYS);
+        let message = config_error(layer.require(&["server_cid", "server_port"]));
+        assert!(message.contains("VIRGA_SERVER_PORT"), "{}", message);
+    }
+
+    #[test]
+    fn invalid_values_name_the_variable() {
+        let layer = env(&[("VIRGA_CHUNK_SIZE", "big"), ("VIRGA_ACK", "maybe")], CLIENT_KEYS);
+        let message = config_error(layer.get::<u32>("chunk_size"));
+        assert!(message.contains("VIRGA_CHUNK_SIZE") && message.contains("big"), "{}", message);
+        let message = config_error(layer.get_bool("ack"));
+        assert!(message.contains("VIRGA_ACK"), "{}", message);
+        assert_eq!(env(&[("VIRGA_ACK", " On ")], CLIENT_KEYS).get_bool("ack").unwrap(), Some(true));
+    }
+
+    #[test]
+    fn env_values_go_through_builder_validation() {
+        let layer = env(&[("VIRGA_CHUNK_SIZE", "1")], CLIENT_KEYS);
+        let builder = ClientConfig::builder().apply(&layer).unwrap();
+        config_error(builder.build());
+        let layer = env(&[("VIRGA_CHUNK_SIZE", "1")], SERVER_KEYS);
+        config_error(ServerConfig::builder().apply(&layer).unwrap().build());
+    }
+
+    #[cfg(feature = "config-file")]
+    #[test]
+    fn explicit_overrides_env_overrides_file_overrides_defaults() {
+        let file = TempToml::new(
+            "precedence",
+            "server_cid = 3\nserver_port = 10\nchunk_size = 4096\nack = true\nunknown_key = \"ignored\"\n",
+        );
+        let file = Layer::from_toml_file(&file.0, CLIENT_KEYS).unwrap();
+        let env = env(&[("VIRGA_SERVER_PORT", "20"), ("VIRGA_CHUNK_SIZE", "2048")], CLIENT_KEYS);
+
+        let config = ClientConfig::builder()
+            .apply(&file)
+            .unwrap()
+            .apply(&env)
+            .unwrap()
+            .server_port(30)
+            .build()
+            .unwrap();
+        let expected = ClientConfig::builder()
+            .server_cid(3) // 只在配置文件中
+            .server_port(30) // 显式设置优先于环境变量与配置文件
+            .chunk_size(2048) // 环境变量优先于配置文件
+            .ack(true) // 只在配置文件中
+            .build()
+            .unwrap();
+        // 三个来源都没有设置的参数保持默认值，因此与只设置上述四项的配置相等
+        assert_eq!(config, expected);
+    }
+
+    #[cfg(feature = "config-file")]
+    #[test]
+    fn server_file_layer_loads_and_validates() {
+        let file = TempToml::new("server", "listen_port = 9000\nbacklog = 64\nreuse_addr = \"yes\"\n");
+        let layer = Layer::from_toml_file(&file.0, SERVER_KEYS).unwrap();
+        let config = ServerConfig::builder().apply(&layer).unwrap().build().unwrap();
+        let expected = ServerConfig::builder().listen_port(9000).reuse_addr(true).build().unwrap().with_backlog(64);
+        assert_eq!(config, expected);
+
+        let file = TempToml::new("bad-type", "chunk_size = [1, 2]\n");
+        let message = config_error(Layer::from_toml_file(&file.0, SERVER_KEYS));
+        assert!(message.contains("chunk_size") && message.contains("array"), "{}", message);
+        let missing = std::env::temp_dir().join(format!("virga-{}-missing.toml", std::process::id()));
+        config_error(Layer::from_toml_file(&missing, SERVER_KEYS));
+    }
+}
@@ -3,8 +3,21 @@
 //! 统一定义库中所有的错误类型，使用 `thiserror` 或自定义 enum。
 //!
 //! # 错误分类
-//! - `ConnectionError`：vsock 连接相关错误（连接失败、超时等）
+//! - `ConnectionError`：vsock 连接相关错误（连接失败、对端无响应等），保留底层 `io::Error` 作为 `source()`
+//! - `Disconnected`：连接未建立或已断开
+//! - `Timeout`：操作在限定时间内未完成，连接保持可用
+//! - `MessageTooLarge`：消息超过允许的最大字节数
+//! - `MessageAborted`：发送方放弃了写到一半的分块消息，连接保持可用
+//! - `PartialWrite`：字节流写入中途失败，携带失败前已完整发出的字节数
 //! - `TransportError`：传输协议相关错误（编码、解码、发送、接收失败）
+//! - `ProtocolError`：握手失败或协议不兼容（版本、类型、参数不一致）
+//! - `IntegrityError`：消息校验和不匹配，数据在传输中被破坏
+//! - `EncryptionError`：加密握手失败（双方加密设置或预共享密钥不一致），或加密帧无法解密
+//! - `CodecError`：类型化消息序列化或反序列化失败
+//! - `EndOfStream`：对端已关闭写方向，不会再收到数据
+//! - `PeerClosed`：对端调用 `disconnect_with_reason` 关闭了连接，携带其给出的原因
+//! - `PortInUse`：监听地址已被其他监听器占用
+//! - `LocalPortInUse`：客户端指定的本地端口已被占用
 //! - `InvalidConfig`：配置参数非法
 //! - `Unknown`：未知错误
 
@@ -13,11 +26,61 @@ use std::fmt;
 /// 库的统一错误类型
 #[derive(Debug)]
 pub enum VirgeError {
-    /// 连接层错误
-    ConnectionError(String),
+    /// 连接层错误，`source` 保留底层 IO 错误，可通过 `Error::source()` 取回
+    ConnectionError {
+        message: String,
+        source: Option<std::io::Error>,
+    },
     
-    /// 传输层错误
-    TransportError(String),
+    /// 连接未建立或已断开
+    Disconnected(String),
+    
+    /// 操作超时
+    Timeout(String),
+    
+    /// 消息超过允许的最大字节数
+    MessageTooLarge { size: usize, max: usize },
+
+    /// 发送方在分块消息写完前放弃了它（`MessageWriter` 未调用 `finish` 即被释放），
+    /// 已收到的部分被丢弃，之后的消息照常接收
+    MessageAborted,
+
+    /// `write()` 写入中途失败：前 `committed` 字节已完整发出，其余未发出，`source` 为导致失败的错误
+    PartialWrite {
+        committed: usize,
+        source: Box<VirgeError>,
+    },
+    
+    /// 传输层错误，`source` 保留底层 IO 错误
+    TransportError {
+        message: String,
+        source: Option<std::io::Error>,
+    },
+    
+    /// 协议错误：握手失败或双方协议不兼容
+    ProtocolError(String),
+
+    /// 完整性校验失败：`expected` 为随消息携带的 CRC32，`actual` 为按收到的负载计算的值
+    IntegrityError { expected: u32, actual: u32 },
+
+    /// 加密握手失败或加密帧无法通过认证，与 `ProtocolError` 区分以便应用识别密钥配置问题
+    EncryptionError(String),
+
+    /// 类型化消息编解码失败，如收到的数据与期望的类型不符
+    CodecError(String),
+
+    /// 对端调用 `shutdown_write` 关闭了写方向，之后不会再收到数据，本端仍可发送
+    EndOfStream,
+
+    /// 对端调用 `disconnect_with_reason` 正常关闭了连接，`code` 与 `message` 为其给出的原因；
+    /// 之后不会再收到数据，重新连接前也不应再发送
+    PeerClosed { code: u32, message: String },
+
+    /// 绑定监听地址时端口已被占用（`EADDRINUSE`），通常是另一个实例仍在运行
+    PortInUse { cid: u32, port: u32 },
+
+    /// 客户端绑定 `local_port` 时端口已被占用（`EADDRINUSE`），可能是之前的连接尚未释放或另一个客户端正在使用
+    LocalPortInUse { port: u32 },
     
     /// 配置错误
     ConfigError(String),
@@ -32,8 +95,43 @@ pub enum VirgeError {
 impl fmt::Display for VirgeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            VirgeError::ConnectionError(msg) => write!(f, "Connection error: {}", msg),
-            VirgeError::TransportError(msg) => write!(f, "Transport error: {}", msg),
+            VirgeError::ConnectionError { message, source } => {
+                write!(f, "Connection error: {}", message)?;
+                write_source(f, source)
+            }
+            VirgeError::Disconnected(msg) => write!(f, "Disconnected: {}", msg),
+            VirgeError::Timeout(msg) => write!(f, "Timed out: {}", msg),
+            VirgeError::MessageTooLarge { size, max } => {
+                write!(f, "Message size {} exceeds limit {}", size, max)
+            }
+            VirgeError::MessageAborted => write!(f, "Message aborted by the sender before it was finished"),
+            VirgeError::PartialWrite { committed, source } => {
+                write!(f, "Write failed after {} bytes were committed: {}", committed, source)
+            }
+            VirgeError::TransportError { message, source } => {
+                write!(f, "Transport error: {}", message)?;
+                write_source(f, source)
+            }
+            VirgeError::ProtocolError(msg) => write!(f, "Protocol error: {}", msg),
+            VirgeError::IntegrityError { expected, actual } => {
+                write!(f, "Integrity check failed: expected crc32 {:08x}, got {:08x}", expected, actual)
+            }
+            VirgeError::EncryptionError(msg) => write!(f, "Encryption error: {}", msg),
+            VirgeError::CodecError(msg) => write!(f, "Codec error: {}", msg),
+            VirgeError::EndOfStream => write!(f, "End of stream: peer shut down its write side"),
+            VirgeError::PeerClosed { code, message } => {
+                write!(f, "Peer closed the connection with code {}", code)?;
+                if !message.is_empty() {
+                    write!(f, ": {}", message)?;
+                }
+                Ok(())
+            }
+            VirgeError::PortInUse { cid, port } => {
+                write!(f, "Address in use: cid={}, port={} is already bound by another listener", cid, port)
+            }
+            VirgeError::LocalPortInUse { port } => {
+                write!(f, "Local port {} is already in use; choose another local_port or leave it unset", port)
+            }
             VirgeError::ConfigError(msg) => write!(f, "Config error: {}", msg),
             VirgeError::IoError(e) => write!(f, "IO error: {}", e),
             VirgeError::Other(msg) => write!(f, "Error: {}", msg),
@@ -41,11 +139,147 @@ impl fmt::Display for VirgeError {
     }
 }
 
-impl std::error::Error for VirgeError {}
+fn write_source(f: &mut fmt::Formatter<'_>, source: &Option<std::io::Error>) -> fmt::Result {
+    match source {
+        Some(e) => write!(f, ": {}", e),
+        None => Ok(()),
+    }
+}
+
+impl std::error::Error for VirgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VirgeError::ConnectionError { source, .. } | VirgeError::TransportError { source, .. } => {
+                source.as_ref().map(|e| e as &(dyn std::error::Error + 'static))
+            }
+            VirgeError::IoError(e) => Some(e),
+            VirgeError::PartialWrite { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl VirgeError {
+    /// 构造不带底层错误的连接层错误
+    pub(crate) fn connection(message: impl Into<String>) -> Self {
+        VirgeError::ConnectionError { message: message.into(), source: None }
+    }
+
+    /// 构造保留底层 IO 错误的连接层错误
+    pub(crate) fn connection_io(message: impl Into<String>, source: std::io::Error) -> Self {
+        VirgeError::ConnectionError { message: message.into(), source: Some(source) }
+    }
+
+    /// 构造不带底层错误的传输层错误
+    pub(crate) fn transport(message: impl Into<String>) -> Self {
+        VirgeError::TransportError { message: message.into(), source: None }
+    }
+
+    /// 构造写入中途失败的错误，尚未发出任何字节时原样返回 `source`
+    pub(crate) fn partial_write(committed: usize, source: VirgeError) -> Self {
+        if committed == 0 {
+            return source;
+        }
+        VirgeError::PartialWrite { committed, source: Box::new(source) }
+    }
+
+    /// 底层 IO 错误的类型（若有）
+    pub fn io_kind(&self) -> Option<std::io::ErrorKind> {
+        match self {
+            VirgeError::IoError(e) => Some(e.kind()),
+            VirgeError::ConnectionError { source, .. } | VirgeError::TransportError { source, .. } => {
+                source.as_ref().map(std::io::Error::kind)
+            }
+            VirgeError::PortInUse { .. } | VirgeError::LocalPortInUse { .. } => Some(std::io::ErrorKind::AddrInUse),
+            VirgeError::PartialWrite { source, .. } => source.io_kind(),
+            _ => None,
+        }
+    }
+
+    /// 是否为超时错误，超时后连接保持可用
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            VirgeError::Timeout(_) => true,
+            VirgeError::PartialWrite { source, .. } => source.is_timeout(),
+            VirgeError::IoError(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+            ),
+            _ => false,
+        }
+    }
+
+    /// 是否表示连接已断开或对端不可达，需要重新连接
+    pub fn is_disconnected(&self) -> bool {
+        match self {
+            VirgeError::Disconnected(_) | VirgeError::ConnectionError { .. } => true,
+            VirgeError::PartialWrite { source, .. } => source.is_disconnected(),
+            _ => self.io_kind().is_some_and(|kind| {
+                matches!(
+                    kind,
+                    std::io::ErrorKind::NotConnected
+                        | std::io::ErrorKind::ConnectionReset
+                        | std::io::ErrorKind::ConnectionAborted
+                        | std::io::ErrorKind::BrokenPipe
+                        | std::io::ErrorKind::UnexpectedEof
+                )
+            }),
+        }
+    }
+
+    /// 是否可以重试：超时与中断可直接重试，连接断开可在重新连接后重试；
+    /// 配置、协议与消息过大错误重试也不会成功
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            VirgeError::IoError(e) if e.kind() == std::io::ErrorKind::Interrupted => true,
+            VirgeError::PartialWrite { source, .. } => source.is_retryable(),
+            _ => self.is_timeout() || self.is_disconnected(),
+        }
+    }
+}
 
 impl From<std::io::Error> for VirgeError {
     fn from(err: std::io::Error) -> Self {
-        VirgeError::IoError(err)
+        match err.kind() {
+            std::io::ErrorKind::TimedOut => VirgeError::Timeout(err.to_string()),
+            _ => VirgeError::IoError(err),
+        }
+    }
+}
+
+impl From<VirgeError> for std::io::Error {
+    fn from(err: VirgeError) -> Self {
+        match err {
+            VirgeError::IoError(e) => e,
+            err => std::io::Error::new(io_error_kind(&err), err),
+        }
+    }
+}
+
+/// 转换为 `io::Error` 时使用的类型，原错误作为 payload，source 链不丢失
+fn io_error_kind(err: &VirgeError) -> std::io::ErrorKind {
+    use std::io::ErrorKind;
+
+    match err {
+        VirgeError::IoError(e) => e.kind(),
+        // 保留底层 IO 错误的类型
+        VirgeError::ConnectionError { source: Some(e), .. }
+        | VirgeError::TransportError { source: Some(e), .. } => e.kind(),
+        VirgeError::Disconnected(_) => ErrorKind::NotConnected,
+        VirgeError::ConnectionError { .. } => ErrorKind::ConnectionAborted,
+        VirgeError::Timeout(_) => ErrorKind::TimedOut,
+        VirgeError::MessageTooLarge { .. }
+        | VirgeError::MessageAborted
+        | VirgeError::ProtocolError(_)
+        | VirgeError::IntegrityError { .. }
+        | VirgeError::EncryptionError(_)
+        | VirgeError::CodecError(_) => ErrorKind::InvalidData,
+        VirgeError::ConfigError(_) => ErrorKind::InvalidInput,
+        VirgeError::EndOfStream => ErrorKind::UnexpectedEof,
+        VirgeError::PeerClosed { .. } => ErrorKind::ConnectionAborted,
+        VirgeError::PortInUse { .. } | VirgeError::LocalPortInUse { .. } => ErrorKind::AddrInUse,
+        VirgeError::PartialWrite { source, .. } => io_error_kind(source),
+        VirgeError::TransportError { .. } | VirgeError::Other(_) => ErrorKind::Other,
     }
 }
 
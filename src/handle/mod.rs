@@ -0,0 +1,312 @@
+//! 可克隆的连接句柄模块
+//!
+//! `VirgeClient::handle` / `VirgeServer::handle` 将连接转换为可廉价克隆的句柄，交给多个子系统共用。
+//!
+//! # 加锁行为
+//! - 所有句柄通过 `Arc` 共享同一个传输实例，传输实例由异步互斥锁保护
+//! - `send` 在整个发送期间持有锁，一条消息的全部分块发送完毕（启用 ACK 时还包括等待确认）
+//!   后才释放，并发的发送按消息粒度串行化，不同消息的分块不会交错
+//! - `recv` 以非阻塞的 `try_recv` 轮询，只在取数据的瞬间持有锁，等待中的接收不会阻塞其他句柄的发送；
+//!   多个句柄同时接收时，每条消息只交给其中一个
+//! - 启用优先通道时，`send_priority` 不必等待进行中的 `send` 释放锁：消息放入发件队列后，
+//!   由正在发送的句柄在下一个分片之后代为发出，最多等待一个分片（64 KiB）
+//! - 全部句柄释放后连接关闭
+//!
+//! 转换为句柄后的连接不再自动重连。
+
+use crate::error::{Result, VirgeError};
+use crate::split::{self, POLL_INTERVAL_MAX, POLL_INTERVAL_MIN};
+use crate::transport::lane::{Outgoing, PriorityOutbox};
+use crate::transport::{framing, Transport, VsockAddr};
+use futures::channel::oneshot;
+use futures::future::{self, Either};
+use futures::lock::Mutex;
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+
+/// 排队的优先消息在发出前被丢弃（连接已重置）
+fn discarded() -> VirgeError {
+    VirgeError::Disconnected("Priority message discarded before it was sent".to_string())
+}
+
+/// 所有句柄共享的连接状态
+struct Shared {
+    transport: Mutex<Box<dyn Transport>>,
+    /// 转换前已收到但尚未消费的数据，由第一个接收的句柄取走
+    pending: std::sync::Mutex<Vec<u8>>,
+    connected: AtomicBool,
+    max_message_size: usize,
+    /// 优先发件队列，未启用优先通道时为 `None`
+    outbox: Option<PriorityOutbox>,
+    /// 随连接一同释放的资源（如服务器的活跃连接计数）
+    _guard: Option<Box<dyn Any + Send + Sync>>,
+}
+
+impl Shared {
+    fn new(
+        transport: Box<dyn Transport>,
+        pending: Vec<u8>,
+        max_message_size: usize,
+        guard: Option<Box<dyn Any + Send + Sync>>,
+    ) -> Arc<Self> {
+        let outbox = transport.priority_outbox();
+        Arc::new(Self {
+            transport: Mutex::new(transport),
+            pending: std::sync::Mutex::new(pending),
+            connected: AtomicBool::new(true),
+            max_message_size,
+            outbox,
+            _guard: guard,
+        })
+    }
+
+    async fn send(&self, data: Vec<u8>, ack: bool) -> Result<()> {
+        framing::check_size(data.len(), self.max_message_size)?;
+        let mut transport = self.transport.lock().await;
+        let result = if ack {
+            transport.send(data).await
+        } else {
+            transport.send_noack(data).await
+        };
+        self.connected.store(transport.is_connected(), Ordering::SeqCst);
+        result
+    }
+
+    /// 发送一条优先消息：发送锁空闲时直接发送，否则放入发件队列，等待持锁的发送方代为发出
+    async fn send_priority(&self, data: &[u8]) -> Result<()> {
+        framing::check_size(data.len(), self.max_message_size)?;
+        let Some(outbox) = &self.outbox else {
+            return self.transport.lock().await.send_priority(data.to_vec()).await;
+        };
+        if let Some(mut transport) = self.transport.try_lock() {
+            return transport.send_priority(data.to_vec()).await;
+        }
+
+        let (done, sent) = oneshot::channel();
+        outbox
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(Outgoing { data: data.to_vec(), done: Some(done) });
+        let (mut transport, mut sent) = match future::select(self.transport.lock(), sent).await {
+            Either::Left(locked) => locked,
+            Either::Right((Ok(()), _)) => return Ok(()),
+            Either::Right((Err(_), _)) => return Err(discarded()),
+        };
+
+        // 取得锁时消息可能仍在队列中（持锁方已发完最后一个分片），改为自行发送
+        let queued = {
+            let mut queue = outbox.lock().unwrap_or_else(|e| e.into_inner());
+            let position = queue
+                .iter()
+                .position(|outgoing| outgoing.done.as_ref().is_some_and(|done| done.is_connected_to(&sent)));
+            position.and_then(|position| queue.remove(position))
+        };
+        if let Some(outgoing) = queued {
+            return transport.send_priority(outgoing.data).await;
+        }
+        match sent.try_recv() {
+            Ok(Some(())) => Ok(()),
+            _ => Err(discarded()),
+        }
+    }
+
+    async fn recv(&self) -> Result<Vec<u8>> {
+        if let Some(data) = self.take_pending() {
+            return Ok(data);
+        }
+        let result = split::poll_recv(&self.transport, None).await;
+        self.track(result)
+    }
+
+    async fn try_recv(&self) -> Result<Option<Vec<u8>>> {
+        if let Some(data) = self.take_pending() {
+            return Ok(Some(data));
+        }
+        let result = self.transport.lock().await.try_recv().await;
+        self.track(result)
+    }
+
+    /// 轮询接收一条优先消息，与 `recv` 一样只在取数据的瞬间持有锁
+    async fn recv_priority(&self) -> Result<Vec<u8>> {
+        let mut interval = POLL_INTERVAL_MIN;
+        loop {
+            let result = self.transport.lock().await.try_recv_priority().await;
+            if let Some(data) = self.track(result)? {
+                return Ok(data);
+            }
+            crate::transport::sleep(interval).await;
+            interval = (interval * 2).min(POLL_INTERVAL_MAX);
+        }
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        let mut transport = self.transport.lock().await;
+        self.connected.store(false, Ordering::SeqCst);
+        transport.disconnect().await
+    }
+
+    fn take_pending(&self) -> Option<Vec<u8>> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        (!pending.is_empty()).then(|| std::mem::take(&mut *pending))
+    }
+
+    /// 接收报告连接断开时更新连接状态
+    fn track<T>(&self, result: Result<T>) -> Result<T> {
+        if result.as_ref().is_err_and(|e| e.is_disconnected()) {
+            self.connected.store(false, Ordering::SeqCst);
+        }
+        result
+    }
+}
+
+/// `VirgeClient` 的可克隆句柄，由 `VirgeClient::handle` 创建，加锁行为见模块文档
+#[derive(Clone)]
+pub struct VirgeClientHandle {
+    shared: Arc<Shared>,
+}
+
+impl VirgeClientHandle {
+    pub(crate) fn new(transport: Box<dyn Transport>, pending: Vec<u8>, max_message_size: usize) -> Self {
+        Self { shared: Shared::new(transport, pending, max_message_size, None) }
+    }
+
+    /// 发送一条消息，与其他句柄的发送按消息粒度串行化
+    pub async fn send(&self, data: Vec<u8>) -> Result<()> {
+        self.shared.send(data, true).await
+    }
+
+    /// 发送一条消息且不等待对端确认，未启用 ACK 时与 `send` 相同
+    pub async fn send_noack(&self, data: Vec<u8>) -> Result<()> {
+        self.shared.send(data, false).await
+    }
+
+    /// 接收一条消息，等待期间不阻塞其他句柄的发送
+    pub async fn recv(&self) -> Result<Vec<u8>> {
+        self.shared.recv().await
+    }
+
+    /// 非阻塞接收数据，当前没有可读数据时返回 `Ok(None)`
+    pub async fn try_recv(&self) -> Result<Option<Vec<u8>>> {
+        self.shared.try_recv().await
+    }
+
+    /// 经优先通道发送一条消息，其他句柄正在发送的大消息会在下一个分片之后让出
+    ///
+    /// 需两端都启用 `with_priority_lanes`，否则返回 `VirgeError::ConfigError`。
+    pub async fn send_priority(&self, data: &[u8]) -> Result<()> {
+        self.shared.send_priority(data).await
+    }
+
+    /// 接收一条优先消息，期间收到的普通消息留给 `recv`
+    pub async fn recv_priority(&self) -> Result<Vec<u8>> {
+        self.shared.recv_priority().await
+    }
+
+    /// 连接是否仍可用，反映最近一次操作后的状态
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::SeqCst)
+    }
+
+    /// 断开连接，所有句柄随后的收发均返回错误
+    pub async fn disconnect(&self) -> Result<()> {
+        self.shared.disconnect().await
+    }
+}
+
+/// `VirgeServer` 的可克隆句柄，由 `VirgeServer::handle` 创建，加锁行为见模块文档
+#[derive(Clone)]
+pub struct VirgeServerHandle {
+    shared: Arc<Shared>,
+    id: u64,
+    peer_addr: VsockAddr,
+}
+
+impl VirgeServerHandle {
+    pub(crate) fn new(
+        transport: Box<dyn Transport>,
+        pending: Vec<u8>,
+        max_message_size: usize,
+        guard: Option<Box<dyn Any + Send + Sync>>,
+        id: u64,
+        peer_addr: VsockAddr,
+    ) -> Self {
+        Self {
+            shared: Shared::new(transport, pending, max_message_size, guard),
+            id,
+            peer_addr,
+        }
+    }
+
+    /// 发送一条消息，与其他句柄的发送按消息粒度串行化
+    pub async fn send(&self, data: Vec<u8>) -> Result<()> {
+        self.shared.send(data, true).await
+    }
+
+    /// 发送一条消息且不等待对端确认，未启用 ACK 时与 `send` 相同
+    pub async fn send_noack(&self, data: Vec<u8>) -> Result<()> {
+        self.shared.send(data, false).await
+    }
+
+    /// 接收一条消息，等待期间不阻塞其他句柄的发送
+    pub async fn recv(&self) -> Result<Vec<u8>> {
+        self.shared.recv().await
+    }
+
+    /// 非阻塞接收数据，当前没有可读数据时返回 `Ok(None)`
+    pub async fn try_recv(&self) -> Result<Option<Vec<u8>>> {
+        self.shared.try_recv().await
+    }
+
+    /// 经优先通道发送一条消息，其他句柄正在发送的大消息会在下一个分片之后让出
+    ///
+    /// 需两端都启用 `with_priority_lanes`，否则返回 `VirgeError::ConfigError`。
+    pub async fn send_priority(&self, data: &[u8]) -> Result<()> {
+        self.shared.send_priority(data).await
+    }
+
+    /// 接收一条优先消息，期间收到的普通消息留给 `recv`
+    pub async fn recv_priority(&self) -> Result<Vec<u8>> {
+        self.shared.recv_priority().await
+    }
+
+    /// 连接是否仍可用，反映最近一次操作后的状态
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::SeqCst)
+    }
+
+    /// 断开连接，所有句柄随后的收发均返回错误
+    pub async fn disconnect(&self) -> Result<()> {
+        self.shared.disconnect().await
+    }
+
+    /// 连接序号，与 `VirgeServer::id` 一致
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 对端地址
+    pub fn peer_addr(&self) -> VsockAddr {
+        self.peer_addr
+    }
+
+    /// 创建不延长连接生命周期的弱引用，供管理器的连接登记表使用
+    pub(crate) fn downgrade(&self) -> WeakServerHandle {
+        WeakServerHandle { shared: Arc::downgrade(&self.shared), id: self.id, peer_addr: self.peer_addr }
+    }
+}
+
+/// `VirgeServerHandle` 的弱引用，全部句柄释放后无法再升级
+pub(crate) struct WeakServerHandle {
+    shared: Weak<Shared>,
+    id: u64,
+    peer_addr: VsockAddr,
+}
+
+impl WeakServerHandle {
+    /// 仍有句柄存活时返回一个新的句柄
+    pub(crate) fn upgrade(&self) -> Option<VirgeServerHandle> {
+        let shared = self.shared.upgrade()?;
+        Some(VirgeServerHandle { shared, id: self.id, peer_addr: self.peer_addr })
+    }
+}
@@ -35,20 +35,18 @@
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
 //!     let config = ServerConfig::default();
 //!
-//!     // 方法1：简单回显服务器（自动管理连接）
-//!     let manager = ServerManager::new(config);
-//!     manager.run_simple().await?;
-//!
-//!     // 方法2：自定义连接处理器
-//!     let manager = ServerManager::new(config);
-//!     manager.run(|mut server| async move {
+//!     // 方法1：自定义连接处理器（内部 accept 循环并发分发连接）
+//!     let mut manager = ServerManager::new(config.clone().with_max_connections(64));
+//!     manager.start().await?;
+//!     manager.serve(|mut server| async move {
 //!         // 处理每个VirgeServer连接的业务逻辑
-//!         let data = server.recv().await?;
-//!         server.send(data).await?;
-//!         server.disconnect().await?;
+//!         if let Ok(data) = server.recv().await {
+//!             let _ = server.send(data).await;
+//!         }
+//!         let _ = server.disconnect().await;
 //!     }).await?;
 //!
-//!     // 方法3：手动管理连接
+//!     // 方法2：手动管理连接
 //!     let mut manager = ServerManager::new(config);
 //!     manager.start().await?;
 //!
@@ -69,24 +67,67 @@ pub mod error;
 pub use error::{VirgeError, Result};
 
 // 协议层
+pub mod protocol;
 pub mod transport;
+pub use transport::{
+    AckStats, ChunkSizePolicy, HeaderMap, HexDumpObserver, RateLimitPolicy, SocketBufferSizes, Stats, TransportKind,
+    TransportObserver, VsockAddr,
+};
+#[cfg(feature = "use-yamux")]
+pub use transport::{VirgeStream, YamuxConfig};
+#[cfg(feature = "compression")]
+pub use transport::Compression;
+#[cfg(feature = "testing")]
+pub use transport::{FaultPlan, TrafficLog, TrafficRecorder};
+
+// 配置层
+mod config;
 
 // 应用层
 pub mod client;
 pub mod server;
+pub mod split;
+pub mod handle;
+pub mod cancel;
+pub mod pool;
+#[cfg(feature = "tokio-runtime")]
+pub mod async_io;
+mod rpc;
+#[cfg(feature = "serde")]
+pub mod codec;
+#[cfg(feature = "serde")]
+pub mod channel;
 
-pub use client::{VirgeClient, ClientConfig};
-pub use server::{ServerManager, VirgeServer, ServerConfig};
+pub use client::{VirgeClient, ClientConfig, ClientConfigBuilder, DisconnectPolicy, MessageWriter, RecvOutcome};
+pub use server::{ServerManager, VirgeServer, ServerConfig, ServerConfigBuilder, QueueOverflow, ServerEvent, DisconnectReason, MessageReader, BroadcastReport, AuthRequest, RejectReason};
+pub use split::{VirgeReadHalf, VirgeWriteHalf};
+pub use handle::{VirgeClientHandle, VirgeServerHandle};
+pub use cancel::CancelToken;
+pub use pool::{ClientPool, PooledClient};
+#[cfg(feature = "tokio-runtime")]
+pub use async_io::{AsyncIo, VirgeClientAsync, VirgeServerAsync};
+#[cfg(feature = "serde")]
+pub use codec::WireFormat;
+#[cfg(feature = "serde")]
+pub use channel::VirgaChannel;
 
 pub const KIB: usize = 1024;
 pub const MIB: usize = KIB * 1024;
 pub const GIB: usize = MIB * 1024;
 
 pub const DEFAULT_SERVER_CID: usize = 103;
+pub const VMADDR_CID_HYPERVISOR: usize = 0;
+pub const VMADDR_CID_LOCAL: usize = 1;
+pub const VMADDR_CID_HOST: usize = 2;
 pub const VMADDR_CID_ANY: usize = 0xFFFFFFFF;
+pub const VMADDR_PORT_ANY: usize = 0xFFFFFFFF;
 pub const DEFAULT_SERVER_PORT: usize = 1234;
 
 pub const DEAFULT_CHUNK_SIZE: usize = KIB;
+pub const MIN_CHUNK_SIZE: usize = 512;
+pub const MAX_CHUNK_SIZE: usize = 16 * MIB;
 pub const DEFAULT_IS_ACK: bool = false;
-
-
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 8 * MIB;
+pub const DEFAULT_BUFFER_POOL_SIZE: usize = 4;
+pub const DEFAULT_CALL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+pub const DEFAULT_HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
@@ -0,0 +1,278 @@
+//! 连接池模块
+//!
+//! 多个任务或线程访问同一服务时复用已建立的连接，避免每个请求都重新建立 vsock 连接与协议握手。
+//!
+//! # 机制
+//! - `get()` 优先取出最近归还的空闲连接，没有空闲连接且未达到 `max_size` 时新建连接，否则等待归还
+//! - 空闲连接在复用前检查 `is_connected()` 并做一次非阻塞接收探测：连接已断开或收到了不属于任何请求的数据时丢弃
+//! - 空闲超过 `idle_timeout` 的连接在下一次 `get()` 时被断开并移除
+//! - `PooledClient` 释放时归还连接，已断开的连接直接丢弃；调用方发现连接异常时可调用 `discard()` 主动丢弃
+//!
+//! 等待不依赖特定的异步运行时，取消等待中的 `get()` 不会影响其他调用方。
+
+use crate::client::{ClientConfig, VirgeClient};
+use crate::error::Result;
+use futures::channel::oneshot;
+use log::*;
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// 空闲连接的默认最长保留时间
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// 一个空闲连接及其归还时间
+struct Idle {
+    client: VirgeClient,
+    since: Instant,
+}
+
+/// 池的可变状态
+struct PoolState {
+    /// 空闲连接的最长保留时间
+    idle_timeout: Duration,
+    /// 空闲连接，队尾为最近归还的连接
+    idle: VecDeque<Idle>,
+    /// 空闲与借出的连接总数
+    total: usize,
+    /// 等待连接归还的调用方
+    waiters: VecDeque<oneshot::Sender<()>>,
+}
+
+impl PoolState {
+    /// 释放一个连接名额并唤醒一个仍在等待的调用方
+    fn release_slot(&mut self) {
+        self.total = self.total.saturating_sub(1);
+        self.wake_one();
+    }
+
+    fn wake_one(&mut self) {
+        // 已取消的等待方的接收端已被丢弃，发送失败时继续唤醒下一个
+        while let Some(waiter) = self.waiters.pop_front() {
+            if waiter.send(()).is_ok() {
+                break;
+            }
+        }
+    }
+}
+
+struct PoolInner {
+    config: ClientConfig,
+    max_size: usize,
+    state: Mutex<PoolState>,
+}
+
+impl PoolInner {
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// `get()` 在持有锁时作出的决定
+enum Next {
+    Reuse(VirgeClient),
+    Create,
+    Wait(oneshot::Receiver<()>),
+}
+
+/// 客户端连接池，克隆共享同一个池
+#[derive(Clone)]
+pub struct ClientPool {
+    inner: Arc<PoolInner>,
+}
+
+impl ClientPool {
+    /// 创建至多同时持有 `max_size` 个连接的池，连接在首次 `get()` 时按 `config` 建立
+    pub fn new(config: ClientConfig, max_size: usize) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                config,
+                max_size: max_size.max(1),
+                state: Mutex::new(PoolState {
+                    idle_timeout: DEFAULT_IDLE_TIMEOUT,
+                    idle: VecDeque::new(),
+                    total: 0,
+                    waiters: VecDeque::new(),
+                }),
+            }),
+        }
+    }
+
+    /// 设置空闲连接的最长保留时间，默认为 `DEFAULT_IDLE_TIMEOUT`，对共享该池的所有克隆生效
+    pub fn with_idle_timeout(self, timeout: Duration) -> Self {
+        self.inner.lock().idle_timeout = timeout;
+        self
+    }
+
+    /// 借出一个可用的连接，池已满时等待其他调用方归还
+    ///
+    /// 新建连接失败时返回连接错误，名额随即释放。
+    pub async fn get(&self) -> Result<PooledClient> {
+        loop {
+            let (next, expired) = self.next();
+            for mut client in expired {
+                debug!("ClientPool evicting idle connection");
+                if let Err(e) = client.disconnect().await {
+                    debug!("ClientPool ignoring disconnect error on eviction: {}", e);
+                }
+            }
+
+            match next {
+                Next::Reuse(mut client) => {
+                    if Self::probe(&mut client).await {
+                        return Ok(self.wrap(client));
+                    }
+                    warn!("ClientPool discarding broken idle connection");
+                    self.inner.lock().release_slot();
+                }
+                Next::Create => {
+                    let mut client = VirgeClient::new(self.inner.config.clone());
+                    return match client.connect().await {
+                        Ok(()) => Ok(self.wrap(client)),
+                        Err(e) => {
+                            self.inner.lock().release_slot();
+                            Err(e)
+                        }
+                    };
+                }
+                Next::Wait(receiver) => {
+                    // 发送端被丢弃同样意味着状态已变化，重新检查即可
+                    let _ = receiver.await;
+                }
+            }
+        }
+    }
+
+    /// 当前的空闲连接数
+    pub fn idle_count(&self) -> usize {
+        self.inner.lock().idle.len()
+    }
+
+    /// 当前空闲与借出的连接总数
+    pub fn size(&self) -> usize {
+        self.inner.lock().total
+    }
+
+    /// 断开并移除所有空闲连接，借出的连接归还时照常放回
+    pub async fn clear(&self) {
+        let idle: Vec<Idle> = {
+            let mut state = self.inner.lock();
+            let idle: Vec<Idle> = state.idle.drain(..).collect();
+            for _ in 0..idle.len() {
+                state.release_slot();
+            }
+            idle
+        };
+        for mut entry in idle {
+            if let Err(e) = entry.client.disconnect().await {
+                debug!("ClientPool ignoring disconnect error on clear: {}", e);
+            }
+        }
+    }
+
+    /// 在锁内移除过期的空闲连接并决定下一步，过期连接在锁外断开
+    fn next(&self) -> (Next, Vec<VirgeClient>) {
+        let mut state = self.inner.lock();
+        let mut expired = Vec::new();
+        while let Some(entry) = state.idle.front() {
+            if entry.since.elapsed() < state.idle_timeout {
+                break;
+            }
+            if let Some(entry) = state.idle.pop_front() {
+                expired.push(entry.client);
+                state.release_slot();
+            }
+        }
+
+        let next = if let Some(entry) = state.idle.pop_back() {
+            Next::Reuse(entry.client)
+        } else if state.total < self.inner.max_size {
+            state.total += 1;
+            Next::Create
+        } else {
+            let (sender, receiver) = oneshot::channel();
+            state.waiters.push_back(sender);
+            Next::Wait(receiver)
+        };
+        (next, expired)
+    }
+
+    /// 复用前的健康检查：连接仍然可用且没有残留的数据
+    async fn probe(client: &mut VirgeClient) -> bool {
+        if !client.is_connected() || client.has_buffered_data() {
+            return false;
+        }
+        match client.try_recv().await {
+            Ok(None) => true,
+            Ok(Some(data)) => {
+                warn!("ClientPool idle connection received {} unexpected bytes", data.len());
+                false
+            }
+            Err(e) => {
+                debug!("ClientPool idle connection probe failed: {}", e);
+                false
+            }
+        }
+    }
+
+    fn wrap(&self, client: VirgeClient) -> PooledClient {
+        PooledClient { client: Some(client), pool: self.inner.clone() }
+    }
+}
+
+/// 从池中借出的连接，释放时归还，可通过 `Deref` 当作 `VirgeClient` 使用
+pub struct PooledClient {
+    client: Option<VirgeClient>,
+    pool: Arc<PoolInner>,
+}
+
+impl PooledClient {
+    /// 丢弃该连接而不归还，用于调用方确认连接已不可用的情况
+    pub fn discard(mut self) {
+        if self.client.take().is_some() {
+            self.pool.lock().release_slot();
+        }
+    }
+}
+
+impl Deref for PooledClient {
+    type Target = VirgeClient;
+
+    fn deref(&self) -> &VirgeClient {
+        self.client.as_ref().expect("PooledClient used after release")
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut VirgeClient {
+        self.client.as_mut().expect("PooledClient used after release")
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        let Some(client) = self.client.take() else {
+            return;
+        };
+        let mut state = self.pool.lock();
+        if client.is_connected() {
+            state.idle.push_back(Idle { client, since: Instant::now() });
+            state.wake_one();
+        } else {
+            debug!("ClientPool dropping disconnected connection");
+            state.release_slot();
+        }
+    }
+}
+
+impl std::fmt::Debug for ClientPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.inner.lock();
+        f.debug_struct("ClientPool")
+            .field("max_size", &self.inner.max_size)
+            .field("total", &state.total)
+            .field("idle", &state.idle.len())
+            .finish()
+    }
+}
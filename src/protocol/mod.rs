@@ -0,0 +1,827 @@
+//! 线路格式模块
+//!
+//! 集中定义 virga 在线路上使用的全部字节布局：连接握手、字节流传输的流帧、各包装器的帧头，
+//! 以及 `send_msg` 的长度前缀与请求/响应帧头。传输实现与包装器均通过本模块编解码，
+//! 本模块的文档即线路格式的规范，可据此在其他语言中实现兼容的对端。
+//!
+//! 所有多字节整数均为大端序。线路格式发生不兼容的变化时 [`PROTOCOL_VERSION`] 递增，
+//! 握手时版本不一致的连接直接失败。
+//!
+//! # 连接握手
+//! 连接建立后双方各发送一条 [`HELLO_SIZE`] 字节的握手消息，再读取并校验对端的握手消息：
+//! ```text
+//! 偏移  长度  字段
+//! 0     4     magic       "VIRG"
+//! 4     2     version     u16，当前为 PROTOCOL_VERSION
+//! 6     1     kind        传输协议：1 xtransport、2 yamux、3 raw、4 tcp（uds 与 raw 相同）
+//! 7     1     flags       bit 0 ACK；bit 1 完整性校验；bit 2..=3 压缩算法（0 不压缩、1 lz4、2 zstd）；
+//!                         bit 4 优先通道；bit 5 消息元数据；bit 6 消息序号；bit 7 附带扩展块
+//! 8     4     chunk_size  u32，xtransport 的数据块大小，其他协议为 0
+//! 12    4     ack_window  u32，确认包装器允许的未确认消息数，双方取较小值
+//! ```
+//! magic、version、kind 与 flags 的 bit 0..=6 必须一致，否则双方都关闭连接。
+//! flags 的 bit 7 只表示发送方紧接着在同一条握手消息中附带扩展块，不要求双方一致。
+//! 扩展块由总长度与若干条目组成，`ext_len` 不超过 [`MAX_EXTENSIONS_SIZE`]：
+//! ```text
+//! ┌────────────────────┬────────────────┬───────────┬───────────┬────────────┐
+//! │ hello: 16 字节     │ ext_len: u16   │ type: u8  │ len: u16  │ value      │ ...
+//! └────────────────────┴────────────────┴───────────┴───────────┴────────────┘
+//! ```
+//! 接收方跳过不认识的条目类型，条目越过扩展块末尾时关闭连接。目前定义的条目：
+//!
+//! | type | 条目 | value |
+//! |------|------|-------|
+//! | [`EXT_AUTH_TOKEN`] | 认证令牌 | 令牌本身，不超过 [`MAX_AUTH_TOKEN_SIZE`] 字节 |
+//! | [`EXT_ENCRYPTION`] | 加密 | `cipher: u8`，当前只有 [`CIPHER_X25519_CHACHA20POLY1305`] |
+//!
+//! 认证令牌不要求双方一致，是否有效由服务器的认证函数判断，
+//! 认证失败时服务器以原因码 [`CLOSE_AUTH_FAILED`] 关闭连接。
+//! 加密条目必须一致（没有该条目视为不加密），否则双方都以 `VirgeError::EncryptionError` 关闭连接，
+//! 一致且启用时握手之后紧接着进行密钥交换，见下文的加密帧。
+//!
+//! # 流帧
+//! raw、yamux（每个虚拟流内）、tcp、uds 与内存传输在字节流上以流帧划分消息边界：
+//! ```text
+//! ┌──────────────┬──────────────────┐
+//! │ len: u32     │ payload: [u8]    │
+//! └──────────────┴──────────────────┘
+//! ```
+//! `len` 为 `0xFFFF_FFFF` 的流帧没有负载，表示发送方已关闭写方向（半关闭），之后只会出现包装器的控制帧。
+//! `len` 为 `0xFFFF_FFFE` 的流帧同样没有负载，是发送方调用 `disconnect` 正常关闭连接前发出的最后一帧；
+//! 没有收到该帧就读到字节流结束的连接视为异常断开。不认识该帧的旧版本对端将其报告为超长消息，
+//! 与此前读到字节流结束时一样以错误结束接收。
+//! `len` 为 `0xFFFF_FFFD` 的流帧是携带关闭原因的正常关闭帧，由 `disconnect_with_reason` 发出，
+//! 其后依次为 `code: u32`、`reason_len: u16` 与 `reason_len` 字节的 UTF-8 原因说明（不超过 [`MAX_CLOSE_REASON_SIZE`]）：
+//! ```text
+//! ┌──────────────────────┬────────────┬──────────────────┬────────────┐
+//! │ 0xFFFF_FFFD: u32     │ code: u32  │ reason_len: u16  │ reason     │
+//! └──────────────────────┴────────────┴──────────────────┴────────────┘
+//! ```
+//! 原因码为 0 且没有说明的关闭等同于 `0xFFFF_FFFE`，发送方此时总是发出后者。
+//! xtransport 的分块格式由 xtransport 库定义，不在本模块范围内；需要自行实现对端时应选用 raw 传输。
+//!
+//! # SOCK_SEQPACKET 数据报
+//! 启用 `seqpacket` 的 raw 传输不使用流帧，内核保留每个数据报的边界，握手消息本身即一个数据报。
+//! 此后每个数据报以一个标志字节开始：
+//! ```text
+//! ┌──────────────┬──────────────────┐
+//! │ flag: u8     │ payload: [u8]    │
+//! └──────────────┴──────────────────┘
+//! ```
+//! `flag` 为 0 表示消息还有后续分片，1 表示消息的最后一片（不超过 [`SEQPACKET_FRAGMENT_SIZE`] 的消息只有这一片）；
+//! 2 与 3 没有负载，含义分别与保留长度 `0xFFFF_FFFE`（正常关闭）和 `0xFFFF_FFFF`（半关闭）的流帧相同；
+//! 4 的负载为 `code: u32` 加 UTF-8 原因说明，含义与保留长度 `0xFFFF_FFFD` 的流帧相同。
+//! 分片重组后的负载与流帧负载一样由包装器逐层封装。
+//!
+//! # 加密帧
+//! 握手协商启用加密后，双方先以三条流帧交换密钥，之后的每条流帧负载都是一条加密帧。
+//! 预共享密钥 `psk` 为双方配置中的 32 字节密钥，`pub_c`、`pub_s` 为客户端、服务器临时 X25519 公钥：
+//! ```text
+//! 客户端 → 服务器：pub_c: [u8; 32]
+//! 服务器 → 客户端：pub_s: [u8; 32] | confirm_s: [u8; 16]
+//! 客户端 → 服务器：confirm_c: [u8; 16]
+//! ```
+//! 双方以 HKDF-SHA256（salt 为 `psk`，ikm 为 X25519 共享密钥，info 为 `"virga secure v1" | pub_c | pub_s`）
+//! 导出 64 字节，前 32 字节为客户端到服务器方向的密钥，后 32 字节为反方向的密钥。
+//! `confirm` 为发送方向的密钥以全零 nonce、`pub_c | pub_s` 为附加数据加密空消息得到的 Poly1305 标签，
+//! 对方校验失败（通常意味着预共享密钥不一致）时以 `VirgeError::EncryptionError` 关闭连接。
+//!
+//! 此后每条加密帧为：
+//! ```text
+//! ┌────────────────┬──────────────────────┬──────────────┐
+//! │ counter: u64   │ ciphertext: [u8]     │ tag: [u8;16] │
+//! └────────────────┴──────────────────────┴──────────────┘
+//! ```
+//! ChaCha20-Poly1305 的 nonce 为 4 个零字节加 `counter`，没有附加数据。每个方向的 `counter` 从 1 开始严格递增，
+//! 接收方拒绝重复或倒退的 `counter`；标签校验失败或 `counter` 倒退的帧被丢弃并返回 `VirgeError::EncryptionError`。
+//! 明文即下文包装器帧封装后的流帧负载。
+//!
+//! # 包装器帧
+//! 每条流帧的负载由启用的包装器逐层封装，自外向内（即按字节出现的顺序）依次为：
+//! ```text
+//! 流帧负载 = 压缩帧头? | 心跳帧头? | 确认帧头? | 通道帧头? | 序号? | 元数据块? | 用户数据 | crc32?
+//! ```
+//! 各层均为可选，是否启用在握手的 flags 中协商（心跳由两端各自配置，需同时启用）。
+//! 元数据与序号位于优先通道之上：普通消息分片时二者只出现在第一片的开头，优先消息不携带它们。
+//! 压缩帧的负载是其内层（心跳、确认、通道帧头、序号、元数据块与用户数据）整体压缩后的结果；
+//! crc32 覆盖它之前的全部字节，即实际传输的（可能已压缩的）数据。
+//!
+//! | 层 | 帧头 | 含义 |
+//! |----|------|------|
+//! | 完整性校验 | 尾部 `crc32: u32` | CRC32（IEEE 802.3）|
+//! | 压缩 | `0` | 负载原样 |
+//! | | `method: u8, original_len: u32` | 负载为压缩数据，`method` 1 为 lz4 块格式、2 为 zstd 帧 |
+//! | 心跳 | `0` / `1` / `2` | 用户数据 / PING / PONG，后两者没有负载 |
+//! | 确认 | `0` | 无需确认的数据 |
+//! | | `1, seq: u32` | 需要确认的数据，接收方读到后立即回复 ACK |
+//! | | `2, seq: u32` | ACK，确认该序号及之前的全部消息，没有负载 |
+//! | 优先通道 | `0` / `1` / `2` | 普通消息的中间分片 / 最后一片 / 优先消息 |
+//! | 序号 | `seq: u64` | 普通消息的序号，每个连接上从 1 开始严格递增 |
+//! | 元数据 | `len: u16` | 其后 `len` 字节为元数据块，`len` 不超过 [`MAX_METADATA_SIZE`]，0 表示没有元数据 |
+//!
+//! 元数据块由若干键值对依次排列，键与值均为 UTF-8 字符串，键在块内不重复：
+//! ```text
+//! ┌──────────────┬──────────┬────────────────┬────────────┐
+//! │ key_len: u8  │ key      │ value_len: u16 │ value      │ ...
+//! └──────────────┴──────────┴────────────────┴────────────┘
+//! ```
+//!
+//! # 消息帧
+//! `send_msg` / `recv_msg` 在用户数据之上再加一层长度前缀，一条消息可能跨越多个流帧：
+//! ```text
+//! ┌──────────────┬──────────────────┐
+//! │ len: u64     │ payload: [u8]    │
+//! └──────────────┴──────────────────┘
+//! ```
+//! 长度前缀为 `0xFFFF_FFFF_FFFF_FFFF`（[`MESSAGE_CHUNKED_LEN`]）的消息由 `start_message` 分块发送，
+//! 发送方开始写入时还不知道消息的总长度，前缀之后是若干数据块，每个块头由块长度与消息序号组成：
+//! ```text
+//! ┌──────────────────┬───────────┬───────────┬─────────┬─────┬────────────────────────┬───────────┐
+//! │ 0xFFFF..FF: u64  │ len: u32  │ msg: u32  │ data    │ ... │ 0 或 0xFFFF_FFFF: u32  │ msg: u32  │
+//! └──────────────────┴───────────┴───────────┴─────────┴─────┴────────────────────────┴───────────┘
+//! ```
+//! `len` 为 0 的块头结束该消息，消息负载即各块数据依次拼接；`len` 为 `0xFFFF_FFFF` 的块头表示发送方放弃了该消息，
+//! 接收方丢弃已收到的部分并返回 `VirgeError::MessageAborted`，之后的消息照常接收。
+//! `msg` 由发送方为每条分块消息递增分配（到达 `u32::MAX` 后回绕），同一条消息的所有块头（含结束与放弃块头）相同；
+//! 接收方发现块头的 `msg` 与该消息第一个块头不同时，说明另一条消息的块交错了进来，返回 `VirgeError::ProtocolError`。
+//! 不认识分块消息的旧版本对端将其报告为超长消息。
+//!
+//! `call` / `serve_requests` 的每条消息以请求/响应帧头开始：`kind: u8`（0 请求、1 响应）加 `id: u32`。
+
+use crate::error::{Result, VirgeError};
+use std::fmt;
+
+/// 当前协议版本，线路格式发生不兼容的变化时递增
+pub const PROTOCOL_VERSION: u16 = 3;
+
+/// 握手魔数
+pub const MAGIC: [u8; 4] = *b"VIRG";
+/// 握手消息的字节数
+pub const HELLO_SIZE: usize = 16;
+
+/// 握手中 xtransport 的协议类型字节
+pub const KIND_XTRANSPORT: u8 = 1;
+/// 握手中 yamux 的协议类型字节
+pub const KIND_YAMUX: u8 = 2;
+/// 握手中 raw（包括 uds）的协议类型字节
+pub const KIND_RAW: u8 = 3;
+/// 握手中 tcp 的协议类型字节
+pub const KIND_TCP: u8 = 4;
+
+/// 握手 flags：启用 ACK
+pub const FLAG_ACK: u8 = 1 << 0;
+/// 握手 flags：启用完整性校验
+pub const FLAG_INTEGRITY: u8 = 1 << 1;
+/// 握手 flags 中压缩算法字段的起始位
+pub const COMPRESSION_SHIFT: u8 = 2;
+/// 握手 flags 中压缩算法字段的掩码
+pub const COMPRESSION_MASK: u8 = 0b11 << COMPRESSION_SHIFT;
+/// 握手 flags：启用优先通道
+pub const FLAG_LANES: u8 = 1 << 4;
+/// 握手 flags：启用消息元数据
+pub const FLAG_METADATA: u8 = 1 << 5;
+/// 握手 flags：启用消息序号
+pub const FLAG_SEQUENCE: u8 = 1 << 6;
+/// 握手 flags：握手消息之后附带扩展块
+pub const FLAG_EXTENSIONS: u8 = 1 << 7;
+/// 扩展块总长度字段的字节数
+pub const EXTENSIONS_LEN_SIZE: usize = 2;
+/// 扩展块（不含总长度字段）的最大字节数
+pub const MAX_EXTENSIONS_SIZE: usize = 1024;
+/// 扩展条目头（`type: u8` 与 `len: u16`）的字节数
+pub const EXTENSION_HEADER_SIZE: usize = 3;
+/// 扩展条目类型：认证令牌
+pub const EXT_AUTH_TOKEN: u8 = 1;
+/// 扩展条目类型：加密算法
+pub const EXT_ENCRYPTION: u8 = 2;
+/// 认证令牌的最大字节数
+pub const MAX_AUTH_TOKEN_SIZE: usize = 256;
+/// 加密算法：X25519 密钥交换加 ChaCha20-Poly1305
+pub const CIPHER_X25519_CHACHA20POLY1305: u8 = 1;
+/// 加密帧头（`counter: u64`）的字节数
+pub const ENCRYPTION_HEADER_SIZE: usize = 8;
+/// 加密帧尾部认证标签的字节数
+pub const ENCRYPTION_TAG_SIZE: usize = 16;
+/// 服务器因认证失败关闭连接时使用的关闭原因码
+pub const CLOSE_AUTH_FAILED: u32 = 0xFFFF_0001;
+/// 对端持续超出限速、按 `RateLimitPolicy::Disconnect` 关闭连接时使用的关闭原因码
+pub const CLOSE_RATE_LIMITED: u32 = 0xFFFF_0002;
+
+/// 流帧头的字节数
+pub const STREAM_HEADER_SIZE: usize = 4;
+/// 保留的流帧长度，表示发送方已关闭写方向
+pub const STREAM_EOF_LEN: u32 = u32::MAX;
+/// 保留的流帧长度，表示发送方正常关闭了连接
+pub const STREAM_CLOSE_LEN: u32 = u32::MAX - 1;
+/// 保留的流帧长度，表示发送方携带关闭原因正常关闭了连接
+pub const STREAM_CLOSE_REASON_LEN: u32 = u32::MAX - 2;
+/// 携带关闭原因的关闭帧头字节数：保留长度、原因码与原因说明长度
+pub const CLOSE_REASON_HEADER_SIZE: usize = 10;
+/// 关闭原因说明的最大字节数
+pub const MAX_CLOSE_REASON_SIZE: usize = 1024;
+/// 消息长度前缀的字节数
+pub const LEN_PREFIX_SIZE: usize = 8;
+/// 保留的消息长度，表示消息以分块形式发送，总长度事先未知
+pub const MESSAGE_CHUNKED_LEN: u64 = u64::MAX;
+/// 分块消息块头（块长度与消息序号）的字节数
+pub const CHUNK_HEADER_SIZE: usize = 8;
+/// 保留的块长度，结束当前分块消息
+pub const CHUNK_END_LEN: u32 = 0;
+/// 保留的块长度，表示发送方放弃了当前分块消息
+pub const CHUNK_ABORT_LEN: u32 = u32::MAX;
+/// SOCK_SEQPACKET 数据报标志：消息还有后续分片
+pub const SEQPACKET_MORE: u8 = 0;
+/// SOCK_SEQPACKET 数据报标志：消息的最后一片
+pub const SEQPACKET_LAST: u8 = 1;
+/// SOCK_SEQPACKET 数据报标志：发送方正常关闭了连接
+pub const SEQPACKET_CLOSE: u8 = 2;
+/// SOCK_SEQPACKET 数据报标志：发送方已关闭写方向
+pub const SEQPACKET_EOF: u8 = 3;
+/// SOCK_SEQPACKET 数据报标志：发送方携带关闭原因正常关闭了连接
+pub const SEQPACKET_CLOSE_REASON: u8 = 4;
+/// SOCK_SEQPACKET 单个数据报的最大负载字节数，远低于 virtio-vsock 默认 256 KiB 的套接字缓冲区
+pub const SEQPACKET_FRAGMENT_SIZE: usize = 64 * 1024;
+/// 完整性校验尾部的字节数
+pub const CHECKSUM_SIZE: usize = 4;
+/// 原样负载的压缩帧头字节数，也是压缩层对单条消息增加的最大开销
+pub const COMPRESSION_HEADER_SIZE: usize = 1;
+/// 压缩负载的压缩帧头字节数：算法字节加原始长度
+pub const COMPRESSED_HEADER_SIZE: usize = 5;
+/// 心跳帧头的字节数
+pub const KEEPALIVE_HEADER_SIZE: usize = 1;
+/// 需确认数据与 ACK 的确认帧头字节数，也是确认层对单条消息增加的最大开销
+pub const ACK_HEADER_SIZE: usize = 5;
+/// 通道帧头的字节数
+pub const LANE_HEADER_SIZE: usize = 1;
+/// 序号帧头的字节数
+pub const SEQUENCE_HEADER_SIZE: usize = 8;
+/// 元数据帧头（元数据块长度）的字节数
+pub const METADATA_HEADER_SIZE: usize = 2;
+/// 单条消息元数据块的最大字节数
+pub const MAX_METADATA_SIZE: usize = 4 * 1024;
+/// 元数据键的最大字节数
+pub const MAX_METADATA_KEY_SIZE: usize = u8::MAX as usize;
+/// 请求/响应帧头的字节数
+pub const RPC_HEADER_SIZE: usize = 5;
+
+const METHOD_STORED: u8 = 0;
+
+const KEEPALIVE_DATA: u8 = 0;
+const KEEPALIVE_PING: u8 = 1;
+const KEEPALIVE_PONG: u8 = 2;
+
+const ACK_DATA: u8 = 0;
+const ACK_DATA_ACK: u8 = 1;
+const ACK_ACK: u8 = 2;
+
+const LANE_BULK_MORE: u8 = 0;
+const LANE_BULK_LAST: u8 = 1;
+const LANE_PRIORITY: u8 = 2;
+
+const RPC_REQUEST: u8 = 0;
+const RPC_RESPONSE: u8 = 1;
+
+/// 帧所在的层，决定帧头的解析方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layer {
+    /// 字节流传输的流帧
+    Stream,
+    /// 压缩层
+    Compression,
+    /// 心跳层
+    Keepalive,
+    /// 确认层
+    Ack,
+    /// 优先通道层
+    Lane,
+    /// 序号层
+    Sequence,
+    /// 元数据层
+    Metadata,
+    /// `send_msg` 的长度前缀
+    Message,
+    /// 分块消息的块头
+    Chunk,
+    /// 请求/响应帧头
+    Rpc,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Layer::Stream => "stream",
+            Layer::Compression => "compression",
+            Layer::Keepalive => "keepalive",
+            Layer::Ack => "ack",
+            Layer::Lane => "lane",
+            Layer::Sequence => "sequence",
+            Layer::Metadata => "metadata",
+            Layer::Message => "message",
+            Layer::Chunk => "chunk",
+            Layer::Rpc => "rpc",
+        };
+        f.write_str(name)
+    }
+}
+
+/// 一层帧的帧头
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameHeader {
+    /// 流帧，`len` 为负载字节数
+    Stream { len: u32 },
+    /// 半关闭控制帧，没有负载
+    StreamEof,
+    /// 正常关闭控制帧，没有负载
+    StreamClose,
+    /// 携带关闭原因的正常关闭控制帧，`len` 为其后原因说明的字节数
+    StreamCloseReason { code: u32, len: u16 },
+    /// 原样负载的压缩帧
+    Stored,
+    /// 压缩负载的压缩帧，`original_len` 为解压后的字节数
+    Compressed { method: u8, original_len: u32 },
+    /// 携带用户数据的心跳帧
+    KeepaliveData,
+    /// 心跳请求，没有负载
+    Ping,
+    /// 心跳应答，没有负载
+    Pong,
+    /// 无需确认的数据
+    Data,
+    /// 需要确认的数据
+    DataAck { seq: u32 },
+    /// 确认，没有负载
+    Ack { seq: u32 },
+    /// 普通消息的中间分片
+    BulkMore,
+    /// 普通消息的最后一片
+    BulkLast,
+    /// 优先消息
+    Priority,
+    /// 普通消息的序号，其后为元数据块或用户数据
+    Sequence { seq: u64 },
+    /// 元数据块，`len` 为其字节数，其后为用户数据
+    Metadata { len: u16 },
+    /// 消息长度前缀，`len` 为消息字节数
+    Message { len: u64 },
+    /// 分块消息的长度前缀，其后为若干数据块
+    ChunkedMessage,
+    /// 分块消息的数据块，`msg` 为所属消息的序号，`len` 为其字节数
+    Chunk { msg: u32, len: u32 },
+    /// 结束序号为 `msg` 的分块消息的块头，没有负载
+    ChunkEnd { msg: u32 },
+    /// 放弃序号为 `msg` 的分块消息的块头，没有负载
+    ChunkAbort { msg: u32 },
+    /// 请求
+    Request { id: u32 },
+    /// 响应
+    Response { id: u32 },
+}
+
+impl FrameHeader {
+    /// 帧头所在的层
+    pub fn layer(&self) -> Layer {
+        match self {
+            FrameHeader::Stream { .. }
+            | FrameHeader::StreamEof
+            | FrameHeader::StreamClose
+            | FrameHeader::StreamCloseReason { .. } => Layer::Stream,
+            FrameHeader::Stored | FrameHeader::Compressed { .. } => Layer::Compression,
+            FrameHeader::KeepaliveData | FrameHeader::Ping | FrameHeader::Pong => Layer::Keepalive,
+            FrameHeader::Data | FrameHeader::DataAck { .. } | FrameHeader::Ack { .. } => Layer::Ack,
+            FrameHeader::BulkMore | FrameHeader::BulkLast | FrameHeader::Priority => Layer::Lane,
+            FrameHeader::Sequence { .. } => Layer::Sequence,
+            FrameHeader::Metadata { .. } => Layer::Metadata,
+            FrameHeader::Message { .. } | FrameHeader::ChunkedMessage => Layer::Message,
+            FrameHeader::Chunk { .. } | FrameHeader::ChunkEnd { .. } | FrameHeader::ChunkAbort { .. } => Layer::Chunk,
+            FrameHeader::Request { .. } | FrameHeader::Response { .. } => Layer::Rpc,
+        }
+    }
+
+    /// 编码后的字节数
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            FrameHeader::Stream { .. } | FrameHeader::StreamEof | FrameHeader::StreamClose => STREAM_HEADER_SIZE,
+            FrameHeader::StreamCloseReason { .. } => CLOSE_REASON_HEADER_SIZE,
+            FrameHeader::Stored => COMPRESSION_HEADER_SIZE,
+            FrameHeader::Compressed { .. } => COMPRESSED_HEADER_SIZE,
+            FrameHeader::KeepaliveData | FrameHeader::Ping | FrameHeader::Pong => KEEPALIVE_HEADER_SIZE,
+            FrameHeader::Data => 1,
+            FrameHeader::DataAck { .. } | FrameHeader::Ack { .. } => ACK_HEADER_SIZE,
+            FrameHeader::BulkMore | FrameHeader::BulkLast | FrameHeader::Priority => LANE_HEADER_SIZE,
+            FrameHeader::Sequence { .. } => SEQUENCE_HEADER_SIZE,
+            FrameHeader::Metadata { .. } => METADATA_HEADER_SIZE,
+            FrameHeader::Message { .. } | FrameHeader::ChunkedMessage => LEN_PREFIX_SIZE,
+            FrameHeader::Chunk { .. } | FrameHeader::ChunkEnd { .. } | FrameHeader::ChunkAbort { .. } => {
+                CHUNK_HEADER_SIZE
+            }
+            FrameHeader::Request { .. } | FrameHeader::Response { .. } => RPC_HEADER_SIZE,
+        }
+    }
+
+    /// 帧头声明的负载字节数；`None` 表示负载占据所在消息的剩余部分，或如分块消息那样事先未知
+    pub fn payload_len(&self) -> Option<u64> {
+        match self {
+            FrameHeader::Stream { len } => Some(*len as u64),
+            FrameHeader::Message { len } => Some(*len),
+            FrameHeader::Chunk { len, .. } => Some(*len as u64),
+            FrameHeader::Metadata { len } | FrameHeader::StreamCloseReason { len, .. } => Some(*len as u64),
+            FrameHeader::StreamEof
+            | FrameHeader::StreamClose
+            | FrameHeader::Ping
+            | FrameHeader::Pong
+            | FrameHeader::Ack { .. }
+            | FrameHeader::ChunkEnd { .. }
+            | FrameHeader::ChunkAbort { .. } => Some(0),
+            _ => None,
+        }
+    }
+
+    /// 将帧头追加到 `out`
+    pub fn encode_into(&self, out: &mut Vec<u8>) {
+        match *self {
+            FrameHeader::Stream { len } => out.extend_from_slice(&len.to_be_bytes()),
+            FrameHeader::StreamEof => out.extend_from_slice(&STREAM_EOF_LEN.to_be_bytes()),
+            FrameHeader::StreamClose => out.extend_from_slice(&STREAM_CLOSE_LEN.to_be_bytes()),
+            FrameHeader::StreamCloseReason { code, len } => {
+                out.extend_from_slice(&STREAM_CLOSE_REASON_LEN.to_be_bytes());
+                out.extend_from_slice(&code.to_be_bytes());
+                out.extend_from_slice(&len.to_be_bytes());
+            }
+            FrameHeader::Stored => out.push(METHOD_STORED),
+            FrameHeader::Compressed { method, original_len } => {
+                out.push(method);
+                out.extend_from_slice(&original_len.to_be_bytes());
+            }
+            FrameHeader::KeepaliveData => out.push(KEEPALIVE_DATA),
+            FrameHeader::Ping => out.push(KEEPALIVE_PING),
+            FrameHeader::Pong => out.push(KEEPALIVE_PONG),
+            FrameHeader::Data => out.push(ACK_DATA),
+            FrameHeader::DataAck { seq } => {
+                out.push(ACK_DATA_ACK);
+                out.extend_from_slice(&seq.to_be_bytes());
+            }
+            FrameHeader::Ack { seq } => {
+                out.push(ACK_ACK);
+                out.extend_from_slice(&seq.to_be_bytes());
+            }
+            FrameHeader::BulkMore => out.push(LANE_BULK_MORE),
+            FrameHeader::BulkLast => out.push(LANE_BULK_LAST),
+            FrameHeader::Priority => out.push(LANE_PRIORITY),
+            FrameHeader::Sequence { seq } => out.extend_from_slice(&seq.to_be_bytes()),
+            FrameHeader::Metadata { len } => out.extend_from_slice(&len.to_be_bytes()),
+            FrameHeader::Message { len } => out.extend_from_slice(&len.to_be_bytes()),
+            FrameHeader::ChunkedMessage => out.extend_from_slice(&MESSAGE_CHUNKED_LEN.to_be_bytes()),
+            FrameHeader::Chunk { msg, len } => {
+                out.extend_from_slice(&len.to_be_bytes());
+                out.extend_from_slice(&msg.to_be_bytes());
+            }
+            FrameHeader::ChunkEnd { msg } => {
+                out.extend_from_slice(&CHUNK_END_LEN.to_be_bytes());
+                out.extend_from_slice(&msg.to_be_bytes());
+            }
+            FrameHeader::ChunkAbort { msg } => {
+                out.extend_from_slice(&CHUNK_ABORT_LEN.to_be_bytes());
+                out.extend_from_slice(&msg.to_be_bytes());
+            }
+            FrameHeader::Request { id } => {
+                out.push(RPC_REQUEST);
+                out.extend_from_slice(&id.to_be_bytes());
+            }
+            FrameHeader::Response { id } => {
+                out.push(RPC_RESPONSE);
+                out.extend_from_slice(&id.to_be_bytes());
+            }
+        }
+    }
+
+    /// 编码为独立的字节数组
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.encoded_len());
+        self.encode_into(&mut out);
+        out
+    }
+
+    /// 从 `buf` 开头解析 `layer` 层的帧头，返回帧头与其字节数
+    ///
+    /// 数据短于帧头或类型字节未知时返回 `VirgeError::ProtocolError`；
+    /// 流帧、消息帧与块头的帧头长度固定（携带关闭原因的关闭帧为 [`CLOSE_REASON_HEADER_SIZE`] 字节），
+    /// 从字节流中读取时应先确认已有足够的数据。
+    pub fn decode(layer: Layer, buf: &[u8]) -> Result<(FrameHeader, usize)> {
+        let Some(&first) = buf.first() else {
+            return Err(truncated(layer, 0, 1));
+        };
+        let header = match layer {
+            Layer::Stream => match read_u32(layer, buf, 0)? {
+                STREAM_EOF_LEN => FrameHeader::StreamEof,
+                STREAM_CLOSE_LEN => FrameHeader::StreamClose,
+                STREAM_CLOSE_REASON_LEN => FrameHeader::StreamCloseReason {
+                    code: read_u32(layer, buf, 4)?,
+                    len: read_u16(layer, buf, 8)?,
+                },
+                len => FrameHeader::Stream { len },
+            },
+            Layer::Compression => match first {
+                METHOD_STORED => FrameHeader::Stored,
+                method => FrameHeader::Compressed { method, original_len: read_u32(layer, buf, 1)? },
+            },
+            Layer::Keepalive => match first {
+                KEEPALIVE_DATA => FrameHeader::KeepaliveData,
+                KEEPALIVE_PING => FrameHeader::Ping,
+                KEEPALIVE_PONG => FrameHeader::Pong,
+                other => return Err(unknown(layer, other)),
+            },
+            Layer::Ack => match first {
+                ACK_DATA => FrameHeader::Data,
+                ACK_DATA_ACK => FrameHeader::DataAck { seq: read_u32(layer, buf, 1)? },
+                ACK_ACK => FrameHeader::Ack { seq: read_u32(layer, buf, 1)? },
+                other => return Err(unknown(layer, other)),
+            },
+            Layer::Lane => match first {
+                LANE_BULK_MORE => FrameHeader::BulkMore,
+                LANE_BULK_LAST => FrameHeader::BulkLast,
+                LANE_PRIORITY => FrameHeader::Priority,
+                other => return Err(unknown(layer, other)),
+            },
+            Layer::Sequence => {
+                let Some(bytes) = buf.get(..SEQUENCE_HEADER_SIZE) else {
+                    return Err(truncated(layer, buf.len(), SEQUENCE_HEADER_SIZE));
+                };
+                let mut seq = [0u8; SEQUENCE_HEADER_SIZE];
+                seq.copy_from_slice(bytes);
+                FrameHeader::Sequence { seq: u64::from_be_bytes(seq) }
+            }
+            Layer::Metadata => FrameHeader::Metadata { len: read_u16(layer, buf, 0)? },
+            Layer::Message => {
+                if buf.len() < LEN_PREFIX_SIZE {
+                    return Err(truncated(layer, buf.len(), LEN_PREFIX_SIZE));
+                }
+                let mut prefix = [0u8; LEN_PREFIX_SIZE];
+                prefix.copy_from_slice(&buf[..LEN_PREFIX_SIZE]);
+                match u64::from_be_bytes(prefix) {
+                    MESSAGE_CHUNKED_LEN => FrameHeader::ChunkedMessage,
+                    len => FrameHeader::Message { len },
+                }
+            }
+            Layer::Chunk => {
+                let msg = read_u32(layer, buf, 4)?;
+                match read_u32(layer, buf, 0)? {
+                    CHUNK_END_LEN => FrameHeader::ChunkEnd { msg },
+                    CHUNK_ABORT_LEN => FrameHeader::ChunkAbort { msg },
+                    len => FrameHeader::Chunk { msg, len },
+                }
+            }
+            Layer::Rpc => match first {
+                RPC_REQUEST => FrameHeader::Request { id: read_u32(layer, buf, 1)? },
+                RPC_RESPONSE => FrameHeader::Response { id: read_u32(layer, buf, 1)? },
+                other => return Err(unknown(layer, other)),
+            },
+        };
+        Ok((header, header.encoded_len()))
+    }
+}
+
+/// 帧头与负载
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub header: FrameHeader,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(header: FrameHeader, payload: Vec<u8>) -> Self {
+        Self { header, payload }
+    }
+
+    /// 编码后的字节数
+    pub fn encoded_len(&self) -> usize {
+        self.header.encoded_len() + self.payload.len()
+    }
+
+    /// 将帧头与负载追加到 `out`
+    pub fn encode_into(&self, out: &mut Vec<u8>) {
+        out.reserve(self.encoded_len());
+        self.header.encode_into(out);
+        out.extend_from_slice(&self.payload);
+    }
+
+    /// 从 `buf` 开头解析 `layer` 层的一个完整帧，返回帧与消耗的字节数
+    ///
+    /// 流帧与消息帧按帧头声明的长度取出负载，其后的数据留给下一帧；没有负载的控制帧只消耗帧头；
+    /// 其余各层的负载为 `buf` 的剩余部分。数据不足一个完整帧时返回 `VirgeError::ProtocolError`。
+    pub fn decode(layer: Layer, buf: &[u8]) -> Result<(Frame, usize)> {
+        let (header, header_len) = FrameHeader::decode(layer, buf)?;
+        let rest = &buf[header_len..];
+        let payload_len = match header.payload_len() {
+            Some(len) => {
+                let len = usize::try_from(len).unwrap_or(usize::MAX);
+                if rest.len() < len {
+                    return Err(truncated(layer, buf.len(), header_len.saturating_add(len)));
+                }
+                len
+            }
+            None => rest.len(),
+        };
+        let frame = Frame::new(header, rest[..payload_len].to_vec());
+        Ok((frame, header_len + payload_len))
+    }
+}
+
+fn read_u16(layer: Layer, buf: &[u8], offset: usize) -> Result<u16> {
+    let Some(bytes) = buf.get(offset..offset + 2) else {
+        return Err(truncated(layer, buf.len(), offset + 2));
+    };
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(layer: Layer, buf: &[u8], offset: usize) -> Result<u32> {
+    let Some(bytes) = buf.get(offset..offset + 4) else {
+        return Err(truncated(layer, buf.len(), offset + 4));
+    };
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn truncated(layer: Layer, len: usize, need: usize) -> VirgeError {
+    VirgeError::ProtocolError(format!("{} frame of {} bytes is shorter than the {} bytes it needs", layer, len, need))
+}
+
+fn unknown(layer: Layer, byte: u8) -> VirgeError {
+    VirgeError::ProtocolError(format!("Unknown {} frame type {}", layer, byte))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const LAYERS: [Layer; 10] = [
+        Layer::Stream,
+        Layer::Compression,
+        Layer::Keepalive,
+        Layer::Ack,
+        Layer::Lane,
+        Layer::Sequence,
+        Layer::Metadata,
+        Layer::Message,
+        Layer::Chunk,
+        Layer::Rpc,
+    ];
+
+    /// 每一层的全部帧头，数值字段取边界值
+    fn headers() -> Vec<FrameHeader> {
+        vec![
+            FrameHeader::Stream { len: 0 },
+            FrameHeader::Stream { len: STREAM_CLOSE_REASON_LEN - 1 },
+            FrameHeader::StreamEof,
+            FrameHeader::StreamClose,
+            FrameHeader::StreamCloseReason { code: u32::MAX, len: u16::MAX },
+            FrameHeader::Stored,
+            FrameHeader::Compressed { method: 2, original_len: u32::MAX },
+            FrameHeader::KeepaliveData,
+            FrameHeader::Ping,
+            FrameHeader::Pong,
+            FrameHeader::Data,
+            FrameHeader::DataAck { seq: u32::MAX },
+            FrameHeader::Ack { seq: 0 },
+            FrameHeader::BulkMore,
+            FrameHeader::BulkLast,
+            FrameHeader::Priority,
+            FrameHeader::Sequence { seq: u64::MAX },
+            FrameHeader::Metadata { len: u16::MAX },
+            FrameHeader::Message { len: MESSAGE_CHUNKED_LEN - 1 },
+            FrameHeader::ChunkedMessage,
+            FrameHeader::Chunk { msg: u32::MAX, len: CHUNK_ABORT_LEN - 1 },
+            FrameHeader::ChunkEnd { msg: 0 },
+            FrameHeader::ChunkAbort { msg: 7 },
+            FrameHeader::Request { id: 0 },
+            FrameHeader::Response { id: u32::MAX },
+        ]
+    }
+
+    /// 固定种子的 xorshift 生成器，失败时可以复现；种子不能为 0
+    pub(crate) struct Rng(pub(crate) u64);
+
+    impl Rng {
+        pub(crate) fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        pub(crate) fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.next_u64() as u8).collect()
+        }
+    }
+
+    #[test]
+    fn headers_round_trip() {
+        for header in headers() {
+            let bytes = header.to_bytes();
+            assert_eq!(bytes.len(), header.encoded_len(), "{:?}", header);
+            assert_eq!(FrameHeader::decode(header.layer(), &bytes).unwrap(), (header, bytes.len()));
+        }
+    }
+
+    #[test]
+    fn truncated_headers_are_rejected() {
+        for header in headers() {
+            let bytes = header.to_bytes();
+            for len in 0..bytes.len() {
+                let result = FrameHeader::decode(header.layer(), &bytes[..len]);
+                assert!(
+                    matches!(result, Err(VirgeError::ProtocolError(_))),
+                    "{:?} cut to {} bytes: {:?}",
+                    header,
+                    len,
+                    result
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_frame_types_are_rejected() {
+        // 各层第一个未定义的类型字节
+        let first_unknown = [(Layer::Keepalive, 3), (Layer::Ack, 3), (Layer::Lane, 3), (Layer::Rpc, 2)];
+        for (layer, first) in first_unknown {
+            for byte in first..=u8::MAX {
+                let buf = [byte, 0, 0, 0, 0, 0, 0, 0];
+                let result = FrameHeader::decode(layer, &buf);
+                assert!(matches!(result, Err(VirgeError::ProtocolError(_))), "{} type {}", layer, byte);
+            }
+        }
+    }
+
+    #[test]
+    fn adversarial_lengths_do_not_allocate_or_panic() {
+        // 声明的负载远超实际数据时在复制负载前失败
+        let oversized = [
+            FrameHeader::Stream { len: STREAM_CLOSE_REASON_LEN - 1 },
+            FrameHeader::Message { len: 1 << 40 },
+            FrameHeader::Message { len: MESSAGE_CHUNKED_LEN - 1 },
+            FrameHeader::Chunk { msg: 1, len: CHUNK_ABORT_LEN - 1 },
+            FrameHeader::Metadata { len: u16::MAX },
+            FrameHeader::StreamCloseReason { code: 0, len: u16::MAX },
+        ];
+        for header in oversized {
+            let mut buf = header.to_bytes();
+            buf.extend_from_slice(&[0xAB; 64]);
+            let result = Frame::decode(header.layer(), &buf);
+            assert!(matches!(result, Err(VirgeError::ProtocolError(_))), "{:?}: {:?}", header, result);
+        }
+    }
+
+    #[test]
+    fn frames_round_trip_and_leave_trailing_data() {
+        let frame = Frame::new(FrameHeader::Stream { len: 5 }, b"hello".to_vec());
+        let mut buf = Vec::new();
+        frame.encode_into(&mut buf);
+        buf.extend_from_slice(b"next");
+        assert_eq!(Frame::decode(Layer::Stream, &buf).unwrap(), (frame, 9));
+
+        let frame = Frame::new(FrameHeader::Priority, b"rest of message".to_vec());
+        let mut buf = Vec::new();
+        frame.encode_into(&mut buf);
+        assert_eq!(Frame::decode(Layer::Lane, &buf).unwrap(), (frame, buf.len()));
+    }
+
+    #[test]
+    fn random_bytes_never_panic() {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        for _ in 0..20_000 {
+            let len = (rng.next_u64() % 32) as usize;
+            let buf = rng.bytes(len);
+            for layer in LAYERS {
+                if let Ok((header, used)) = FrameHeader::decode(layer, &buf) {
+                    assert!(used <= buf.len());
+                    assert_eq!(header.layer(), layer);
+                }
+                if let Ok((frame, used)) = Frame::decode(layer, &buf) {
+                    assert!(used <= buf.len());
+                    assert_eq!(used, frame.encoded_len());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_frames_never_panic() {
+        // 在合法帧上随机翻转比特并截断，解码只能成功或返回错误
+        let mut rng = Rng(0xD1B5_4A32_D192_ED03);
+        for header in headers() {
+            let mut valid = header.to_bytes();
+            valid.extend_from_slice(&rng.bytes(16));
+            for _ in 0..500 {
+                let mut buf = valid.clone();
+                let flips = 1 + rng.next_u64() % 4;
+                for _ in 0..flips {
+                    let bit = (rng.next_u64() % (buf.len() as u64 * 8)) as usize;
+                    buf[bit / 8] ^= 1 << (bit % 8);
+                }
+                buf.truncate((rng.next_u64() % (buf.len() as u64 + 1)) as usize);
+                if let Ok((_, used)) = Frame::decode(header.layer(), &buf) {
+                    assert!(used <= buf.len());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! 请求/响应模块
+//!
+//! 在普通消息之上提供 `VirgeClient::call` 与 `VirgeServer::serve_requests`：
+//! 每条消息带一个帧头，标明请求或响应以及 u32 关联 ID，响应携带与请求相同的 ID。
+//!
+//! # 机制
+//! - 客户端为每次调用分配递增的 ID，只接受 ID 匹配的响应；超时调用的迟到响应在之后的调用中被丢弃
+//! - 等待响应超过调用超时返回 `VirgeError::Timeout`（转换为 `io::Error` 时为 `ErrorKind::TimedOut`）
+//! - 服务器按到达顺序逐条处理请求，对端断开时 `serve_requests` 返回 `Ok(())`
+//!
+//! 使用 call 的连接上不应再混用普通 send/recv，否则消息会被当作无效帧丢弃。
+//! 需要并发调用时，可在 yamux 的多个虚拟流上各自发起调用，ID 只需在同一条流内唯一。
+//!
+//! # 帧格式
+//! ```text
+//! ┌──────────┬────────────────┬──────────────────┐
+//! │ kind: u8 │ id: u32 (BE)   │ payload: [u8]    │
+//! └──────────┴────────────────┴──────────────────┘
+//! ```
+//!
+//! 帧头的编解码见 `protocol` 模块。
+
+use crate::error::{Result, VirgeError};
+use crate::protocol::{FrameHeader, Layer};
+use crate::transport::{framing, Transport};
+use log::*;
+use std::time::{Duration, Instant};
+
+/// 帧头字节数
+pub(crate) const HEADER_SIZE: usize = crate::protocol::RPC_HEADER_SIZE;
+
+fn encode(header: FrameHeader, payload: &[u8], max_message_size: usize) -> Result<Vec<u8>> {
+    framing::check_size(HEADER_SIZE + payload.len(), max_message_size)?;
+    let mut frame = Vec::with_capacity(HEADER_SIZE + payload.len());
+    header.encode_into(&mut frame);
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+/// 解析帧头，帧过短或类型未知时返回 `ProtocolError`
+fn decode(mut frame: Vec<u8>) -> Result<(FrameHeader, Vec<u8>)> {
+    let (header, len) = FrameHeader::decode(Layer::Rpc, &frame)?;
+    frame.drain(..len);
+    Ok((header, frame))
+}
+
+/// 发送请求并等待匹配的响应，结束后恢复调用方的读超时 `read_timeout`
+pub(crate) async fn call(
+    transport: &mut dyn Transport,
+    id: u32,
+    payload: &[u8],
+    max_message_size: usize,
+    timeout: Duration,
+    read_timeout: Option<Duration>,
+) -> Result<Vec<u8>> {
+    let frame = encode(FrameHeader::Request { id }, payload, max_message_size)?;
+    let deadline = Instant::now() + timeout;
+    transport.send(frame).await?;
+
+    let result = wait_response(transport, id, deadline).await;
+    transport.set_read_timeout(read_timeout)?;
+    result
+}
+
+async fn wait_response(transport: &mut dyn Transport, id: u32, deadline: Instant) -> Result<Vec<u8>> {
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(VirgeError::Timeout(format!("No response to call {}", id)));
+        }
+        transport.set_read_timeout(Some(remaining))?;
+
+        let frame = match transport.recv().await {
+            Ok(frame) => frame,
+            Err(e) if e.is_timeout() => continue,
+            Err(e) => return Err(e),
+        };
+        match decode(frame) {
+            Ok((FrameHeader::Response { id: reply_id }, payload)) if reply_id == id => return Ok(payload),
+            Ok((FrameHeader::Response { id: reply_id }, _)) => debug!("RPC: discarding stale response {}", reply_id),
+            Ok((header, _)) => warn!("RPC: discarding unexpected frame {:?}", header),
+            Err(e) => warn!("RPC: discarding invalid frame: {}", e),
+        }
+    }
+}
+
+/// 逐条读取请求并以 `handler` 的返回值应答，对端断开时返回 `Ok(())`
+pub(crate) async fn serve<F>(transport: &mut dyn Transport, max_message_size: usize, mut handler: F) -> Result<()>
+where
+    F: FnMut(Vec<u8>) -> Vec<u8>,
+{
+    loop {
+        let frame = match transport.recv().await {
+            Ok(frame) => frame,
+            Err(e) if e.is_timeout() => continue,
+            Err(VirgeError::EndOfStream) => {
+                info!("RPC: peer shut down its write side, stopping request loop");
+                return Ok(());
+            }
+            Err(VirgeError::PeerClosed { code, message }) => {
+                info!("RPC: peer closed the connection with code {} ({}), stopping request loop", code, message);
+                return Ok(());
+            }
+            Err(e) if e.is_disconnected() => {
+                info!("RPC: peer disconnected, stopping request loop");
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        match decode(frame) {
+            Ok((FrameHeader::Request { id }, payload)) => {
+                let response = handler(payload);
+                transport.send(encode(FrameHeader::Response { id }, &response, max_message_size)?).await?;
+            }
+            Ok((header, _)) => warn!("RPC: discarding unexpected frame {:?}", header),
+            Err(e) => warn!("RPC: discarding invalid frame: {}", e),
+        }
+    }
+}
@@ -7,9 +7,61 @@
 //! - VirgeServer: 单个连接的数据传输，与VirgeClient类似
 
 
+use futures::FutureExt;
 use log::*;
+use std::any::Any;
+use std::io::{IoSlice, Read, Write};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::{ControlFlow, RangeInclusive};
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant, SystemTime};
+use crate::client::{DisconnectPolicy, RecvOutcome};
+use crate::config::{Layer, SERVER_KEYS};
 use crate::error::{Result, VirgeError};
-use crate::transport::Transport;
+use crate::protocol::CLOSE_AUTH_FAILED;
+use crate::rpc;
+use crate::split::{self, VirgeReadHalf, VirgeWriteHalf};
+use crate::handle::{VirgeServerHandle, WeakServerHandle};
+use crate::cancel::{self, CancelToken};
+use crate::transport::keepalive::KeepaliveConfig;
+use crate::transport::observer::{ObserverHandle, TransportObserver};
+use crate::transport::ratelimit::{Rate, RateLimitPolicy, RateLimitTransport, ViolationHook};
+use crate::transport::retry::SendRetry;
+use crate::transport::stats::{Stats, StatsCounters, StatsTransport};
+#[cfg(feature = "use-uds")]
+use crate::transport::check_uds_path;
+#[cfg(feature = "use-raw")]
+use crate::transport::{check_seqpacket, seqpacket_impl};
+#[cfg(feature = "use-raw")]
+use tokio::io::unix::AsyncFd;
+#[cfg(feature = "encryption")]
+use crate::transport::secure::PresharedKey;
+use crate::transport::{
+    check_config, framing, AckStats, HeaderMap, SocketBufferSizes, Transport, TransportKind, TransportOptions, VsockAddr,
+};
+#[cfg(any(feature = "use-xtransport", feature = "use-raw"))]
+use crate::transport::sys;
+#[cfg(feature = "serde")]
+use crate::codec::{self, WireFormat};
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Serialize};
+#[cfg(feature = "compression")]
+use crate::transport::Compression;
+#[cfg(feature = "testing")]
+use crate::transport::{FaultPlan, ReplayTransport, TrafficRecorder};
+#[cfg(feature = "use-yamux")]
+use crate::transport::{VirgeStream, YamuxConfig};
+#[cfg(feature = "testing")]
+use crate::client::VirgeClient;
+#[cfg(feature = "testing")]
+use crate::transport::memory_impl;
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+#[cfg(any(feature = "use-xtransport", feature = "use-yamux", feature = "use-raw"))]
+use std::os::unix::io::IntoRawFd;
+#[cfg(feature = "use-xtransport")]
+use std::os::unix::net::UnixStream;
 
 
 /// 监听器枚举
@@ -18,15 +70,151 @@ enum Listener {
     Yamux(tokio_vsock::VsockListener),
     #[cfg(feature = "use-xtransport")]
     XTransport(vsock::VsockListener),
+    #[cfg(feature = "use-raw")]
+    Raw(tokio_vsock::VsockListener),
+    /// 启用 `seqpacket` 时的 SOCK_SEQPACKET vsock 监听套接字
+    #[cfg(feature = "use-raw")]
+    Seqpacket(AsyncFd<OwnedFd>),
+    #[cfg(feature = "use-tcp")]
+    Tcp(tokio::net::TcpListener),
+    /// 监控程序转发来宾连接的 Unix 套接字，附带对外呈现的 vsock 地址
+    #[cfg(feature = "use-uds")]
+    Uds(tokio::net::UnixListener, VsockAddr),
+}
+
+impl AsRawFd for Listener {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            #[cfg(feature = "use-yamux")]
+            Listener::Yamux(listener) => listener.as_raw_fd(),
+            #[cfg(feature = "use-xtransport")]
+            Listener::XTransport(listener) => listener.as_raw_fd(),
+            #[cfg(feature = "use-raw")]
+            Listener::Raw(listener) => listener.as_raw_fd(),
+            #[cfg(feature = "use-raw")]
+            Listener::Seqpacket(listener) => listener.as_raw_fd(),
+            #[cfg(feature = "use-tcp")]
+            Listener::Tcp(listener) => listener.as_raw_fd(),
+            #[cfg(feature = "use-uds")]
+            Listener::Uds(listener, _) => listener.as_raw_fd(),
+        }
+    }
+}
+
+impl Listener {
+    /// 监听器的本地地址，tcp 监听器映射为 `VMADDR_CID_LOCAL` 加监听端口
+    fn local_addr(&self) -> Result<VsockAddr> {
+        match self {
+            #[cfg(feature = "use-yamux")]
+            Listener::Yamux(listener) => Ok(listener.local_addr()?),
+            #[cfg(feature = "use-xtransport")]
+            Listener::XTransport(listener) => Ok(listener.local_addr()?),
+            #[cfg(feature = "use-raw")]
+            Listener::Raw(listener) => Ok(listener.local_addr()?),
+            #[cfg(feature = "use-raw")]
+            Listener::Seqpacket(listener) => {
+                let (cid, port) = sys::local_vsock_addr(listener.as_raw_fd())?;
+                Ok(VsockAddr::new(cid, port))
+            }
+            #[cfg(feature = "use-uds")]
+            Listener::Uds(_, addr) => Ok(*addr),
+            #[cfg(feature = "use-tcp")]
+            Listener::Tcp(listener) => {
+                Ok(VsockAddr::new(crate::VMADDR_CID_LOCAL as u32, listener.local_addr()?.port() as u32))
+            }
+        }
+    }
+
+    /// 对端地址是否带有真实的 CID，tcp 与 uds 监听器的对端地址不含来宾 CID
+    fn has_peer_cid(&self) -> bool {
+        match self {
+            #[cfg(feature = "use-tcp")]
+            Listener::Tcp(_) => false,
+            #[cfg(feature = "use-uds")]
+            Listener::Uds(..) => false,
+            #[allow(unreachable_patterns)]
+            _ => true,
+        }
+    }
 }
 
 /// 服务器配置
-#[derive(Clone, Debug)]
+///
+/// 与 `ClientConfig` 一样可克隆后逐项修改、按值比较；`Debug` 输出全部参数，并附带由它们推导出的
+/// 实际监听端口、接受队列与消息组装上限。
+#[derive(Clone, PartialEq)]
 pub struct ServerConfig {
     listen_cid: u32,
     listen_port: u32,
     chunk_size: u32,
     is_ack: bool,
+    max_connections: Option<usize>,
+    /// 内核监听队列长度，`None` 使用系统默认值
+    backlog: Option<u32>,
+    /// 绑定 vsock 监听套接字前设置 SO_REUSEADDR
+    reuse_addr: bool,
+    /// 管理器内部接受队列的容量与溢出策略，`None` 表示不启用
+    accept_queue: Option<(usize, QueueOverflow)>,
+    /// 允许连接的对端 CID 范围，为空时接受所有 CID
+    allowed_cids: Vec<RangeInclusive<u32>>,
+    /// 除 `listen_port` 外同时监听的端口
+    extra_ports: Vec<u32>,
+    /// 连接无收发活动超过该时长后由管理器关闭，`None` 表示不限制
+    idle_timeout: Option<Duration>,
+    /// `recv_msg` 等接口在内存中组装一条消息的字节数上限，`None` 时与 `max_message_size` 相同
+    max_reassembly_bytes: Option<usize>,
+    /// 已接受的连接完成传输协议初始化的时限
+    handshake_timeout: Duration,
+    /// 转换为句柄的连接是否登记到管理器，供 `ServerManager::broadcast` 使用
+    track_connections: bool,
+    /// 检查对端在握手中出示的认证令牌，`None` 表示不认证
+    authenticator: Option<Authenticator>,
+    transport_kind: TransportKind,
+    transport_options: TransportOptions,
+    #[cfg(feature = "serde")]
+    wire_format: WireFormat,
+}
+
+impl std::fmt::Debug for ServerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut s = f.debug_struct("ServerConfig");
+        s.field("listen_cid", &self.listen_cid)
+            .field("listen_port", &self.listen_port)
+            .field("chunk_size", &self.chunk_size)
+            .field("is_ack", &self.is_ack)
+            .field("max_connections", &self.max_connections)
+            .field("backlog", &self.backlog)
+            .field("reuse_addr", &self.reuse_addr)
+            .field("accept_queue", &self.accept_queue)
+            .field("allowed_cids", &self.allowed_cids)
+            .field("extra_ports", &self.extra_ports)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("max_reassembly_bytes", &self.max_reassembly_bytes)
+            .field("handshake_timeout", &self.handshake_timeout)
+            .field("track_connections", &self.track_connections)
+            .field("authenticator", &self.authenticator)
+            .field("transport_kind", &self.transport_kind)
+            .field("transport_options", &self.transport_options);
+        #[cfg(feature = "serde")]
+        s.field("wire_format", &self.wire_format);
+        // 推导出的实际取值
+        s.field("ports", &self.ports())
+            .field("effective_accept_queue", &self.effective_accept_queue())
+            .field("reassembly_limit", &self.reassembly_limit())
+            .finish()
+    }
+}
+
+/// 监听多个端口且未设置 `with_accept_queue` 时内部接受队列的容量
+pub const DEFAULT_MULTI_PORT_QUEUE: usize = 128;
+
+/// 内部接受队列已满时的处理策略
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueueOverflow {
+    /// 关闭队列中等待最久的连接，为新连接腾出位置
+    DropOldest,
+    /// 直接关闭新接受的连接
+    RefuseNew,
 }
 
 impl Default for ServerConfig {
@@ -36,168 +224,3920 @@ impl Default for ServerConfig {
             listen_port: crate::DEFAULT_SERVER_PORT as u32,
             chunk_size: crate::DEAFULT_CHUNK_SIZE as u32,
             is_ack: crate::DEFAULT_IS_ACK,
+            max_connections: None,
+            backlog: None,
+            reuse_addr: false,
+            accept_queue: None,
+            allowed_cids: Vec::new(),
+            extra_ports: Vec::new(),
+            idle_timeout: None,
+            max_reassembly_bytes: None,
+            handshake_timeout: crate::DEFAULT_HANDSHAKE_TIMEOUT,
+            track_connections: false,
+            authenticator: None,
+            transport_kind: TransportKind::default(),
+            transport_options: TransportOptions::default(),
+            #[cfg(feature = "serde")]
+            wire_format: WireFormat::default(),
         }
     }
 }
 
 impl ServerConfig {
+    /// 虚拟机监控程序的 CID
+    pub const VMADDR_CID_HYPERVISOR: u32 = crate::VMADDR_CID_HYPERVISOR as u32;
+    /// 本机回环的 CID
+    pub const VMADDR_CID_LOCAL: u32 = crate::VMADDR_CID_LOCAL as u32;
+    /// 宿主机的 CID
+    pub const VMADDR_CID_HOST: u32 = crate::VMADDR_CID_HOST as u32;
+    /// 监听所有 CID
+    pub const VMADDR_CID_ANY: u32 = crate::VMADDR_CID_ANY as u32;
+    /// 同 `VMADDR_CID_ANY`
+    pub const CID_ANY: u32 = Self::VMADDR_CID_ANY;
+    /// 同 `VMADDR_CID_HYPERVISOR`
+    pub const CID_HYPERVISOR: u32 = Self::VMADDR_CID_HYPERVISOR;
+    /// 同 `VMADDR_CID_LOCAL`
+    pub const CID_LOCAL: u32 = Self::VMADDR_CID_LOCAL;
+    /// 同 `VMADDR_CID_HOST`
+    pub const CID_HOST: u32 = Self::VMADDR_CID_HOST;
+    /// 由系统分配的监听端口，`VMADDR_PORT_ANY` 同样表示由系统分配
+    pub const PORT_ANY: u32 = 0;
+
+    /// 通过 `/dev/vsock` 查询本机的 CID：来宾返回其自身的 CID，宿主机返回 `CID_HOST`
+    ///
+    /// 没有 vsock 设备（如未加载 vsock 模块）时返回 `VirgeError::IoError`。
+    pub fn local_cid() -> Result<u32> {
+        let device = std::fs::File::open("/dev/vsock")?;
+        let mut cid: u32 = 0;
+        // SAFETY: 该 ioctl 向 cid 写入一个 u32，device 在调用期间保持打开
+        let ret = unsafe { libc::ioctl(device.as_raw_fd(), IOCTL_VM_SOCKETS_GET_LOCAL_CID as _, &mut cid) };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(cid)
+    }
+
+    /// 以具名参数构造配置，`build()` 时校验参数
+    ///
+    /// ```no_run
+    /// # fn main() -> virga::Result<()> {
+    /// use virga::ServerConfig;
+    ///
+    /// let config = ServerConfig::builder()
+    ///     .listen_cid(ServerConfig::VMADDR_CID_ANY)
+    ///     .listen_port(1234)
+    ///     .chunk_size(64 * 1024)
+    ///     .ack(false)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn builder() -> ServerConfigBuilder {
+        ServerConfigBuilder::default()
+    }
+
+    /// 从 `VIRGA_*` 环境变量读取配置，必须提供 `VIRGA_LISTEN_PORT`
+    ///
+    /// 需要与其他来源组合时使用 `builder().env()`。
+    pub fn from_env() -> Result<Self> {
+        let layer = Layer::from_env(SERVER_KEYS);
+        layer.require(&["listen_port"])?;
+        Self::builder().apply(&layer)?.build()
+    }
+
+    /// 从 TOML 配置文件读取配置，必须提供 `listen_port`
+    #[cfg(feature = "config-file")]
+    pub fn from_toml_file(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let layer = Layer::from_toml_file(path.as_ref(), SERVER_KEYS)?;
+        layer.require(&["listen_port"])?;
+        Self::builder().apply(&layer)?.build()
+    }
+
+    /// 按位置参数构造配置，参数在 `start()` 时按与 `builder()` 相同的规则校验
     pub fn new(cid: u32, port: u32, chunk: u32, isack: bool) -> Self{
         Self { 
             listen_cid: cid, 
             listen_port: port, 
             chunk_size: chunk, 
             is_ack: isack, 
+            max_connections: None,
+            backlog: None,
+            reuse_addr: false,
+            accept_queue: None,
+            allowed_cids: Vec::new(),
+            extra_ports: Vec::new(),
+            idle_timeout: None,
+            max_reassembly_bytes: None,
+            handshake_timeout: crate::DEFAULT_HANDSHAKE_TIMEOUT,
+            track_connections: false,
+            authenticator: None,
+            transport_kind: TransportKind::default(),
+            transport_options: TransportOptions::default(),
+            #[cfg(feature = "serde")]
+            wire_format: WireFormat::default(),
         }
     }
-}
 
+    /// 校验配置参数，非法时返回 `VirgeError::ConfigError`
+    pub fn validate(&self) -> Result<()> {
+        #[cfg(feature = "use-uds")]
+        check_uds_path(self.transport_kind, &self.transport_options)?;
+        #[cfg(feature = "use-raw")]
+        check_seqpacket(self.transport_kind, &self.transport_options)?;
+        if self.backlog == Some(0) {
+            return Err(VirgeError::ConfigError("backlog must be greater than 0".to_string()));
+        }
+        if self.authenticator.is_some() && !self.transport_options.handshake {
+            return Err(VirgeError::ConfigError("authenticator requires the handshake to be enabled".to_string()));
+        }
+        if self.transport_options.uses_encryption() && !self.transport_options.handshake {
+            return Err(VirgeError::ConfigError("encryption requires the handshake to be enabled".to_string()));
+        }
+        self.check_listen_cid()?;
+        if self.idle_timeout == Some(Duration::ZERO) {
+            return Err(VirgeError::ConfigError("idle_timeout must be greater than 0".to_string()));
+        }
+        if self.max_reassembly_bytes == Some(0) {
+            return Err(VirgeError::ConfigError("max_reassembly_bytes must be greater than 0".to_string()));
+        }
+        if self.handshake_timeout.is_zero() {
+            return Err(VirgeError::ConfigError("handshake_timeout must be greater than 0".to_string()));
+        }
+        if matches!(self.accept_queue, Some((0, _))) {
+            return Err(VirgeError::ConfigError("accept queue capacity must be greater than 0".to_string()));
+        }
+        // 由系统分配的端口互不相同，可以重复列出
+        let ports = self.ports();
+        let duplicate = |(i, port): (usize, &u32)| (!is_any_port(*port) && ports[..i].contains(port)).then_some(*port);
+        if let Some(port) = ports.iter().enumerate().find_map(duplicate) {
+            return Err(VirgeError::ConfigError(format!("listen port {} is listed more than once", port)));
+        }
+        // 监控程序按端口号转发到固定的路径，无法由系统分配端口
+        #[cfg(feature = "use-uds")]
+        if self.transport_options.uds_path.is_some() && ports.iter().any(|&port| is_any_port(port)) {
+            return Err(VirgeError::ConfigError("uds_path requires fixed listen ports".to_string()));
+        }
+        if let Some(range) = self.allowed_cids.iter().find(|range| range.is_empty()) {
+            return Err(VirgeError::ConfigError(format!("allowed CID range {:?} is empty", range)));
+        }
+        if self.transport_options.ack_window == 0 {
+            return Err(VirgeError::ConfigError("max_unacked_chunks must be greater than 0".to_string()));
+        }
+        if self.transport_options.send_buffer_limit == Some(0) {
+            return Err(VirgeError::ConfigError("send_buffer_limit must be greater than 0".to_string()));
+        }
+        if let Some(sizes) = &self.transport_options.socket_buffer {
+            sizes.validate()?;
+        }
+        if self.transport_options.send_retry.is_some_and(|retry| retry.max_attempts == 0) {
+            return Err(VirgeError::ConfigError("send_retry max_attempts must be greater than 0".to_string()));
+        }
+        self.transport_options.rate_limit.validate()?;
+        check_config(self.chunk_size, self.transport_options.max_message_size)
+    }
 
+    /// 监听 CID 只能是 `CID_ANY`、`CID_LOCAL` 或本机的 CID，绑定到其他 CID 不会收到任何连接
+    ///
+    /// tcp 与 uds 监听器不使用 CID，不做检查。
+    fn check_listen_cid(&self) -> Result<()> {
+        if matches!(self.listen_cid, Self::CID_ANY | Self::CID_LOCAL) {
+            return Ok(());
+        }
+        #[cfg(feature = "use-tcp")]
+        if self.transport_kind == TransportKind::Tcp {
+            return Ok(());
+        }
+        #[cfg(feature = "use-uds")]
+        if self.transport_options.uds_path.is_some() {
+            return Ok(());
+        }
+        match Self::local_cid() {
+            Ok(cid) if cid == self.listen_cid => Ok(()),
+            Ok(cid) => Err(VirgeError::ConfigError(format!(
+                "listen_cid {} must be CID_ANY, CID_LOCAL or the local CID {}",
+                self.listen_cid, cid
+            ))),
+            Err(e) => Err(VirgeError::ConfigError(format!(
+                "listen_cid {} must be CID_ANY or CID_LOCAL, local CID unavailable: {}",
+                self.listen_cid, e
+            ))),
+        }
+    }
 
+    /// 设置监听 CID，见 `ServerConfigBuilder::listen_cid`
+    pub fn with_listen_cid(mut self, cid: u32) -> Self {
+        self.listen_cid = cid;
+        self
+    }
 
-/// 服务器管理器：负责管理vsock监听和连接接受，为每个连接生成VirgeServer实例
-pub struct ServerManager {
-    config: ServerConfig,
-    listener: Option<Listener>,
-    running: bool,
-}
+    /// 设置监听端口，`PORT_ANY`（0）或 `VMADDR_PORT_ANY` 表示由系统分配，`start()` 后由 `local_addr()` 获知实际端口
+    pub fn with_listen_port(mut self, port: u32) -> Self {
+        self.listen_port = port;
+        self
+    }
 
-/// Virga 服务器连接：与VirgeClient类似，负责单个连接的数据传输。
-pub struct VirgeServer {
-    transport: Box<dyn Transport>,
-    connected: bool,
-}
+    /// 设置 xtransport 数据块大小，需在 `MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE` 之间
+    pub fn with_chunk_size(mut self, size: u32) -> Self {
+        self.chunk_size = size;
+        self
+    }
 
-impl ServerManager {
-    pub const fn new(config: ServerConfig) -> Self {
-        Self {
-            config,
-            listener: None,
-            running: false,
-        }
+    /// 设置是否启用 ACK，见 `ServerConfigBuilder::ack`
+    pub fn with_ack(mut self, enabled: bool) -> Self {
+        self.is_ack = enabled;
+        self
     }
 
-    pub async fn start(&mut self) -> Result<()> {
-        info!(
-            "ServerManager starting on cid={}, port={}",
-            self.config.listen_cid,
-            self.config.listen_port
-        );
+    /// 设置 serve() 同时处理的最大连接数，超出的连接会被直接关闭
+    pub fn with_max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
 
-        self.listener = Some(self.create_listener().await?);
-        self.running = true;
-        Ok(())
+    /// 设置监听套接字的内核 backlog，即已完成握手但尚未被 accept 的连接数上限，默认使用系统值
+    ///
+    /// 实际上限受 `net.core.somaxconn` 约束，超出的值被内核静默截断。
+    pub fn with_backlog(mut self, backlog: u32) -> Self {
+        self.backlog = Some(backlog);
+        self
     }
 
-    async fn create_listener(&self) -> Result<Listener> {
-        #[cfg(feature = "use-yamux")]
-        {
-            let addr = tokio_vsock::VsockAddr::new(self.config.listen_cid, self.config.listen_port);
-            let listener = tokio_vsock::VsockListener::bind(addr)
-                .map_err(|e| VirgeError::ConnectionError(format!("Failed to bind yamux listener: {}", e)))?;
-            return Ok(Listener::Yamux(listener));
+    /// 绑定 vsock 监听套接字前是否设置 SO_REUSEADDR，默认关闭
+    ///
+    /// 内核的 vsock 实现接受该选项但目前不改变绑定行为，端口仍被占用时 `start()` 返回
+    /// `VirgeError::PortInUse`；tcp 监听器始终启用该选项，uds 监听器不适用。
+    pub fn with_reuse_addr(mut self, enabled: bool) -> Self {
+        self.reuse_addr = enabled;
+        self
+    }
+
+    /// 启用管理器内部的接受队列：后台持续从监听器接受连接，暂存至多 `capacity` 个供 accept() 取走
+    ///
+    /// 应用处理不及时时内核队列也不会被占满。队列已满时按 `overflow` 关闭等待最久的连接或新连接，
+    /// 并记录警告；此时连接的传输协议尚未初始化，对端将在握手时收到连接断开。
+    pub fn with_accept_queue(mut self, capacity: usize, overflow: QueueOverflow) -> Self {
+        self.accept_queue = Some((capacity, overflow));
+        self
+    }
+
+    /// 同时监听多个端口，第一个端口替换 `listen_port`，所有端口的连接经由同一个 accept() 交付
+    ///
+    /// 监听多个端口时管理器总是使用内部接受队列（未设置 `with_accept_queue` 时容量为
+    /// `DEFAULT_MULTI_PORT_QUEUE`，满时拒绝新连接），每个监听器由独立的后台任务接受连接；
+    /// `VirgeServer::local_port()` 返回连接到达的端口，stop() 关闭全部监听器。`ports` 为空时不做修改。
+    pub fn with_ports(mut self, ports: &[u32]) -> Self {
+        if let Some((&first, rest)) = ports.split_first() {
+            self.listen_port = first;
+            self.extra_ports = rest.to_vec();
         }
+        self
+    }
 
-        #[cfg(feature = "use-xtransport")]
-        {
-            let addr = vsock::VsockAddr::new(self.config.listen_cid, self.config.listen_port);
-            let listener = vsock::VsockListener::bind(&addr)
-                .map_err(|e| VirgeError::ConnectionError(format!("Failed to bind xtransport listener: {}", e)))?;
-            return Ok(Listener::XTransport(listener));
+    /// 需要监听的全部端口，第一个为 `listen_port`
+    fn ports(&self) -> Vec<u32> {
+        std::iter::once(self.listen_port).chain(self.extra_ports.iter().copied()).collect()
+    }
+
+    /// 实际使用的接受队列参数，监听多个端口时总是启用
+    fn effective_accept_queue(&self) -> Option<(usize, QueueOverflow)> {
+        match self.accept_queue {
+            None if !self.extra_ports.is_empty() => Some((DEFAULT_MULTI_PORT_QUEUE, QueueOverflow::RefuseNew)),
+            queue => queue,
         }
+    }
 
-        #[cfg(not(any(feature = "use-yamux", feature = "use-xtransport")))]
-        unreachable!("Either use-yamux or use-xtransport feature must be enabled");
+    /// 只接受来自 `cids` 中 CID 的连接，替换之前设置的 CID 与范围，为空时接受所有 CID（默认）
+    ///
+    /// 其他 CID 的连接在接受后立即关闭，不会交给 accept()，计入 `ServerManager::rejected_connections`
+    /// 并投递 `ServerEvent::Rejected`。tcp 与 uds 监听器的对端地址不含来宾 CID，不做检查。
+    pub fn with_allowed_cids(mut self, cids: Vec<u32>) -> Self {
+        self.allowed_cids = cids.into_iter().map(|cid| cid..=cid).collect();
+        self
     }
 
-    pub async fn accept(&mut self) -> Result<VirgeServer> {
-        if !self.running {
-            return Err(VirgeError::Other(
-                "ServerManager not running".to_string(),
+    /// 追加一段允许连接的 CID 范围，适用于动态分配 CID 的场景（如 Kata Containers）
+    pub fn with_allowed_cid_range(mut self, range: RangeInclusive<u32>) -> Self {
+        self.allowed_cids.push(range);
+        self
+    }
+
+    /// 设置空闲超时：连接在该时长内没有任何成功的收发时由管理器关闭，`None`（默认）表示不限制
+    ///
+    /// 每个 `ServerManager` 只有一个后台任务按超时的四分之一周期检查全部连接，不为每个连接创建线程。
+    /// 被关闭的连接随后的收发返回 `VirgeError::Timeout`，阻塞中的调用同样被唤醒，
+    /// 断开事件的原因为 `DisconnectReason::Idle`。心跳帧不计为活动。
+    pub fn with_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// 设置 `recv_msg`、`recv_msg_timeout` 与 `recv_with_progress` 在内存中组装一条消息的字节数上限，
+    /// `None`（默认）时与 `max_message_size` 相同
+    ///
+    /// 长度前缀超限时在分配内存前返回 `VirgeError::MessageTooLarge`，该消息的负载随后被逐块接收并丢弃，
+    /// 连接保持可用，下一次接收得到其后的消息。需要接收超限消息时使用 `VirgeServer::recv_stream`。
+    pub fn with_max_reassembly_bytes(mut self, max: Option<usize>) -> Self {
+        self.max_reassembly_bytes = max;
+        self
+    }
+
+    /// 单条消息的组装上限
+    fn reassembly_limit(&self) -> usize {
+        self.max_reassembly_bytes.unwrap_or(self.transport_options.max_message_size)
+    }
+
+    /// 设置已接受的连接完成传输协议初始化（握手）的时限，默认为 `DEFAULT_HANDSHAKE_TIMEOUT`
+    ///
+    /// 内核 accept 之后的握手在独立的工作任务中进行，只有初始化完成的连接才会交付给 `accept()`；
+    /// 超时或握手失败的连接被关闭，记录警告并计入 `ServerManager::failed_handshakes()`，
+    /// 断开事件的原因为 `DisconnectReason::HandshakeFailed`。
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// 设置是否登记转换为句柄的连接，默认关闭
+    ///
+    /// 启用后经 `VirgeServer::handle` 转换的连接登记到管理器，可由 `ServerManager::broadcast` 统一发送。
+    /// 管理器只保存弱引用，不延长连接的生命周期；未转换为句柄的 `VirgeServer` 由调用方独占，不会被登记。
+    pub fn with_connection_tracking(mut self, enabled: bool) -> Self {
+        self.track_connections = enabled;
+        self
+    }
+
+    /// 注册认证函数：已接受的连接完成握手后、交给 `accept()` 之前，以对端在握手中出示的令牌调用，返回 `false` 时拒绝
+    ///
+    /// 客户端通过 `ClientConfig::with_auth_token` 设置令牌。被拒绝的连接以原因码 `CLOSE_AUTH_FAILED` 关闭，
+    /// 计入 `rejected_connections()` 并投递 `ServerEvent::Rejected`（原因为 `RejectReason::AuthFailed`），
+    /// 随后是该连接的 `Disconnected` 事件。通过认证的令牌可由 `VirgeServer::peer_identity` 读取。
+    /// 认证函数在握手线程或任务中同步调用，不应阻塞；需开启握手，只作用于接受的连接，不作用于 `dial`。
+    pub fn with_authenticator(mut self, authenticator: impl Fn(&AuthRequest<'_>) -> bool + Send + Sync + 'static) -> Self {
+        self.authenticator = Some(Authenticator(Arc::new(authenticator)));
+        self
+    }
+
+    /// 只接受出示 `token` 的连接，以常数时间比较，见 `with_authenticator`
+    pub fn with_required_auth_token(self, token: Vec<u8>) -> Self {
+        self.with_authenticator(move |request| request.token_matches(&token))
+    }
+
+    /// 以 32 字节预共享密钥加密连接上的全部消息，需与客户端的密钥一致并开启握手，默认不加密
+    ///
+    /// 同时作用于接受与 `dial` 的连接，见 `ClientConfig::with_encryption_key`。密钥交换在握手之后、
+    /// 连接交给 `accept()` 之前完成，计入 `handshake_timeout`；失败的连接计入 `failed_handshakes()`，不会交给 `accept()`。
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption_key(mut self, key: [u8; 32]) -> Self {
+        self.transport_options.encryption = Some(PresharedKey(key));
+        self
+    }
+
+    /// 校验单个连接的覆盖配置：传输协议需与本监听配置一致
+    fn check_override(&self, config: &ServerConfig) -> Result<()> {
+        if config.transport_kind != self.transport_kind {
+            return Err(VirgeError::ConfigError(format!(
+                "Per-connection transport kind {:?} does not match listener {:?}",
+                config.transport_kind, self.transport_kind
+            )));
+        }
+        #[cfg(feature = "use-uds")]
+        if config.transport_options.uds_path != self.transport_options.uds_path {
+            return Err(VirgeError::ConfigError(
+                "Per-connection uds_path does not match listener".to_string(),
+            ));
+        }
+        #[cfg(feature = "use-raw")]
+        if config.transport_options.seqpacket != self.transport_options.seqpacket {
+            return Err(VirgeError::ConfigError(
+                "Per-connection seqpacket setting does not match listener".to_string(),
             ));
         }
+        config.validate()
+    }
 
-        if let Some(ref mut listener) = self.listener {
-            let transport: Box<dyn Transport> = match listener {
-                #[cfg(feature = "use-yamux")]
-                Listener::Yamux(yamux_listener) => {
-                    let (stream, addr) = yamux_listener.accept().await
-                        .map_err(|e| VirgeError::ConnectionError(format!("Failed to accept yamux connection: {}", e)))?;
-                    info!("Accepted yamux connection from {:?}", addr);
+    /// 对端 CID 是否在允许范围内
+    fn allows_cid(&self, cid: u32) -> bool {
+        self.allowed_cids.is_empty() || self.allowed_cids.iter().any(|range| range.contains(&cid))
+    }
 
-                    // 创建 YamuxTransport 实例并从流初始化
-                    let mut transport = Box::new(crate::transport::YamuxTransport::new_server());
-                    transport.from_tokio_stream(stream).await?;
-                    transport as Box<dyn Transport>
-                }
+    /// 设置单条消息允许的最大字节数，默认 `DEFAULT_MAX_MESSAGE_SIZE`
+    ///
+    /// 超限的发送在本地直接返回 `VirgeError::MessageTooLarge`；接收时长度前缀超限
+    /// 在分配内存前返回同样的错误，raw/yamux 传输会丢弃该消息并保持连接可用。
+    pub fn with_max_message_size(mut self, size: usize) -> Self {
+        self.transport_options.max_message_size = size;
+        self
+    }
 
-                #[cfg(feature = "use-xtransport")]
-                Listener::XTransport(xtransport_listener) => {
-                    let (stream, addr) = xtransport_listener.accept()
-                        .map_err(|e| VirgeError::ConnectionError(format!("Failed to accept xtransport connection: {}", e)))?;
-                    info!("Accepted xtransport connection from {:?}", addr);
+    /// 设置传输协议，需与客户端一致
+    pub fn with_transport_kind(mut self, kind: TransportKind) -> Self {
+        self.transport_kind = kind;
+        self
+    }
 
-                    // 创建 XTransportHandler 实例并从流初始化
-                    let mut transport = Box::new(crate::transport::XTransportHandler::new());
-                    transport.from_stream(stream, self.config.chunk_size, self.config.is_ack).await?;
-                    transport as Box<dyn Transport>
-                }
-            };
+    /// 设置是否执行连接握手，默认开启，需与客户端端一致
+    ///
+    /// 关闭后不校验协议版本与参数，仅用于与未进行握手的旧版本互通。
+    pub fn with_handshake(mut self, enabled: bool) -> Self {
+        self.transport_options.handshake = enabled;
+        self
+    }
 
-            Ok(VirgeServer {
-                transport,
-                connected: true,
-            })
-        } else {
-            Err(VirgeError::Other("Listener not initialized".to_string()))
-        }
+    /// 启用心跳保活，需与客户端端同时启用
+    ///
+    /// 接收空闲超过 `interval` 时发送 PING，发出后 `timeout` 内未收到对端任何数据则
+    /// recv/send 返回 `ConnectionError { message: "peer timed out", .. }`。`timeout` 不得小于 `interval`。
+    pub fn with_keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.transport_options.keepalive = Some(KeepaliveConfig { interval, timeout });
+        self
     }
 
-    /// 停止服务器
-    pub async fn stop(&mut self) -> Result<()> {
-        info!("ServerManager stopping");
-        self.listener = None;
-        self.running = false;
-        Ok(())
+    /// 启用逐条消息的 CRC32 完整性校验，需与对端一致，默认关闭
+    ///
+    /// 设置在握手中协商，不一致时连接建立失败；关闭握手时无法协商，需自行保证两端一致。
+    /// 校验失败的消息被丢弃并返回 `VirgeError::IntegrityError`，连接保持可用。
+    pub fn with_integrity(mut self, enabled: bool) -> Self {
+        self.transport_options.integrity = enabled;
+        self
     }
 
-    pub fn is_running(&self) -> bool {
-        self.running
+    /// 设置握手中两端 chunk_size 不一致时的处理方式，默认 `RequireEqual` 直接失败
+    ///
+    /// `UseMinimum` 时双方均采用较小的值；仅 xtransport 会协商 chunk_size。
+    pub fn with_chunk_size_policy(mut self, policy: crate::transport::ChunkSizePolicy) -> Self {
+        self.transport_options.chunk_policy = policy;
+        self
+    }
+
+    /// 启用优先通道，需与对端一致，默认关闭
+    ///
+    /// 启用后普通消息按 64 KiB 分片发送，`send_priority` 发出的消息可插在两片之间，
+    /// 对端通过 `recv_priority` 接收；设置在握手中协商，不一致时连接建立失败。
+    pub fn with_priority_lanes(mut self, enabled: bool) -> Self {
+        self.transport_options.lanes = enabled;
+        self
+    }
+
+    /// 启用消息元数据，需与对端一致，默认关闭
+    ///
+    /// 启用后可通过 `send_with_meta` / `recv_with_meta` 随消息收发字符串键值对，元数据块不超过 4 KiB；
+    /// 普通的 `send` / `recv` 照常工作，元数据为空。设置在握手中协商，不一致时连接建立失败。
+    pub fn with_metadata(mut self, enabled: bool) -> Self {
+        self.transport_options.metadata = enabled;
+        self
+    }
+
+    /// 为每条普通消息附加递增的序号，需与对端一致，默认关闭
+    ///
+    /// 启用后可通过 `last_sent_seq` / `last_received_seq` 查询双方的进度，重连后据此判断需要重发的消息；
+    /// 收到重复或倒退的序号时 `recv` 返回 `VirgeError::ProtocolError`。每条消息多 8 字节，优先消息不编号。
+    /// 设置在握手中协商，不一致时连接建立失败。
+    pub fn with_sequence_numbers(mut self, enabled: bool) -> Self {
+        self.transport_options.sequence = enabled;
+        self
+    }
+
+    /// 设置 ACK 模式下允许的未确认消息数，默认 1，需大于 0
+    ///
+    /// 握手时取两端设置的较小值。大于 1 时 `send` 不再等待每条消息的确认，
+    /// 返回时最近的 n - 1 条消息可能尚未被对端确认；未确认数达到上限时 `send` 等待最早一条的确认。
+    /// 仅对非 xtransport 传输生效，xtransport 使用其自身的确认机制。
+    pub fn with_max_unacked_chunks(mut self, n: u32) -> Self {
+        self.transport_options.ack_window = n;
+        self
+    }
+
+    /// 设置底层套接字的内核发送缓冲区字节数（SO_SNDBUF），默认使用系统值，需大于 0
+    ///
+    /// 对端停止接收后，已发出但未被读取的数据至多约为该值加上对端的接收缓冲区（yamux 为对端的接收窗口），
+    /// 之后 `send` 按 `with_nonblocking_send` 的设置等待或返回 `ErrorKind::WouldBlock`。
+    /// 内核会将设置值翻倍，且上限受 `net.core.wmem_max` 约束；内存传输不受影响。
+    pub fn with_send_buffer_limit(mut self, bytes: usize) -> Self {
+        self.transport_options.send_buffer_limit = Some(bytes);
+        self
+    }
+
+    /// 设置每个连接的 vsock 套接字缓冲区下限、大小与上限，默认使用系统值，见 `ClientConfig::with_socket_buffer`
+    ///
+    /// 接受连接后立即设置，失败的连接计入握手失败并投递 `HandshakeFailed`。
+    pub fn with_socket_buffer(mut self, min: u64, size: u64, max: u64) -> Self {
+        self.transport_options.socket_buffer = Some(SocketBufferSizes { min, size, max });
+        self
+    }
+
+    /// 发送缓冲区已满时 `send` 立即返回 `ErrorKind::WouldBlock`，默认关闭，即等待至有可用空间或写超时
+    ///
+    /// yamux 传输在对端接收窗口耗尽时同样返回该错误。非阻塞模式下 `send` 不等待 ACK 确认；
+    /// 消息一旦开始写入就会写完，不会留下半条消息。
+    pub fn with_nonblocking_send(mut self, enabled: bool) -> Self {
+        self.transport_options.nonblocking_send = enabled;
+        self
+    }
+
+    /// 设置每个连接的接收缓冲区池至多保存的空闲读缓冲区数，0 表示不启用，见 `ClientConfig::with_buffer_pool_size`
+    ///
+    /// 每个连接各自拥有缓冲区池，`aggregate_stats()` 的命中与未命中次数为全部连接之和。
+    pub fn with_buffer_pool_size(mut self, size: usize) -> Self {
+        self.transport_options.buffer_pool_size = size;
+        self
+    }
+
+    /// 发送遇到暂时性错误时重试，默认不重试，见 `ClientConfig::with_send_retry`
+    pub fn with_send_retry(mut self, max_attempts: u32, max_total_delay: Duration) -> Self {
+        self.transport_options.send_retry = Some(SendRetry { max_attempts, max_total_delay });
+        self
+    }
+
+    /// 以令牌桶限制每个连接的接收速率，默认不限速，见 `ClientConfig::with_rate_limit`
+    ///
+    /// 用于防止个别来宾机占满宿主机的 vsock 处理能力；对端持续超限时的处理见 `with_rate_limit_policy`。
+    /// 每个连接各自拥有独立的令牌桶，`aggregate_stats()` 的 `throttled_time` 为全部连接受限时长之和。
+    pub fn with_rate_limit(mut self, bytes_per_sec: u64, burst: u64) -> Self {
+        self.transport_options.rate_limit.recv_bytes = Some(Rate { per_sec: bytes_per_sec, burst });
+        self
+    }
+
+    /// 以令牌桶限制每个连接每秒接收的消息数，默认不限速，见 `ClientConfig::with_message_rate_limit`
+    pub fn with_message_rate_limit(mut self, messages_per_sec: u64, burst: u64) -> Self {
+        self.transport_options.rate_limit.recv_messages = Some(Rate { per_sec: messages_per_sec, burst });
+        self
+    }
+
+    /// 以令牌桶限制每个连接的发送速率，默认不限速，见 `ClientConfig::with_send_rate_limit`
+    pub fn with_send_rate_limit(mut self, bytes_per_sec: u64, burst: u64) -> Self {
+        self.transport_options.rate_limit.send_bytes = Some(Rate { per_sec: bytes_per_sec, burst });
+        self
+    }
+
+    /// 设置对端持续超出接收限速时的处理策略，默认 `RateLimitPolicy::Throttle` 只限速
+    ///
+    /// 接收限速会对对端形成背压，因此以持续受限的时长判定超限：令牌桶自某次等待起一直没有恢复为满，
+    /// 且持续超过策略中的 `after`，即投递 `ServerEvent::RateLimited`，每段连续受限只投递一次；
+    /// `Disconnect` 随后以原因码 `CLOSE_RATE_LIMITED` 关闭连接，本端的接收返回 `VirgeError::Disconnected`，
+    /// 断开事件的原因为 `DisconnectReason::RateLimited`。判定在接收时进行，不接收的连接不会被判定超限。
+    pub fn with_rate_limit_policy(mut self, policy: RateLimitPolicy) -> Self {
+        self.transport_options.rate_limit.policy = policy;
+        self
+    }
+
+    /// 设置消息压缩算法，需与对端一致，默认 `None` 不压缩
+    ///
+    /// 算法在握手中协商，不一致时连接建立失败；短于 256 字节或压缩后没有变小的消息原样发送。
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.transport_options.compression = compression;
+        self
     }
 
+    /// 设置 `send_serialized`/`recv_deserialized` 的编码格式，默认 bincode，需与对端一致
+    #[cfg(feature = "serde")]
+    pub fn with_wire_format(mut self, format: WireFormat) -> Self {
+        self.wire_format = format;
+        self
+    }
+
+    /// 在 Firecracker、cloud-hypervisor 等监控程序导出的 vsock Unix 套接字上接受来宾的连接，需配合 `TransportKind::Raw`
+    ///
+    /// 监控程序把来宾对 `listen_port` 的连接转发到 `<path>_<listen_port>`，服务器在该路径上监听，`listen_cid` 被忽略。
+    #[cfg(feature = "use-uds")]
+    pub fn with_uds_path(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.transport_options.uds_path = Some(path.into());
+        self
+    }
+
+    /// 在 virtio-vsock 的 SOCK_SEQPACKET 套接字上监听，需配合 `TransportKind::Raw`，客户端需同样启用
+    ///
+    /// 每条消息保留边界，`recv` 每次恰好返回一条消息；内核（Linux 5.14 之前）或 vsock 传输不支持时
+    /// `start()` 返回 `VirgeError::ConfigError`。连接的 `stats().seqpacket` 为 `true`。
+    #[cfg(feature = "use-raw")]
+    pub fn with_seqpacket(mut self, enabled: bool) -> Self {
+        self.transport_options.seqpacket = enabled;
+        self
+    }
+
+    /// 按 `plan` 在传输协议之上注入故障，用于测试连接中断、消息损坏等错误路径
+    #[cfg(feature = "testing")]
+    pub fn with_fault_plan(mut self, plan: FaultPlan) -> Self {
+        self.transport_options.faults = Some(plan);
+        self
+    }
+
+    /// 把传输层实际收发的每一帧连同时间戳记录到 `recorder` 的日志文件，用于离线复现问题
+    ///
+    /// 录制位于最内层，每个接受的连接各占一个连接编号；日志可用 `example/virga_replay` 查看，
+    /// 或以 `VirgeServer::replay` 回放。
+    #[cfg(feature = "testing")]
+    pub fn with_recorder(mut self, recorder: TrafficRecorder) -> Self {
+        self.transport_options.recorder = Some(recorder);
+        self
+    }
+
+    /// 注册收发事件观察者，如 `HexDumpObserver`，未注册时没有额外开销
+    pub fn with_observer(mut self, observer: impl TransportObserver + 'static) -> Self {
+        self.transport_options.observer = Some(ObserverHandle(Arc::new(observer)));
+        self
+    }
+
+    /// 设置 yamux 协议参数（接收窗口、最大流数量等），非法组合在连接时返回 `ConfigError`
+    #[cfg(feature = "use-yamux")]
+    pub fn with_yamux_config(mut self, config: YamuxConfig) -> Self {
+        self.transport_options.yamux = config;
+        self
+    }
 }
 
-impl VirgeServer {
-    /// 发送数据
-    pub async fn send(&mut self, data: Vec<u8>) -> Result<()> {
-        if !self.connected {
-            return Err(VirgeError::TransportError(
-                "Server not connected".to_string(),
-            ));
+/// `ServerConfig` 构造器，未设置的参数取默认值
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServerConfigBuilder {
+    config: ServerConfig,
+}
+
+impl ServerConfigBuilder {
+    /// 监听 CID，默认为 `CID_ANY`，只能是 `CID_ANY`、`CID_LOCAL` 或 `ServerConfig::local_cid()`
+    pub fn listen_cid(mut self, cid: u32) -> Self {
+        self.config.listen_cid = cid;
+        self
+    }
+
+    /// 监听端口，默认为 `DEFAULT_SERVER_PORT`，`PORT_ANY`（0）或 `VMADDR_PORT_ANY` 表示由系统分配
+    pub fn listen_port(mut self, port: u32) -> Self {
+        self.config.listen_port = port;
+        self
+    }
+
+    /// 同时监听多个端口，见 `ServerConfig::with_ports`
+    pub fn ports(mut self, ports: &[u32]) -> Self {
+        self.config = self.config.with_ports(ports);
+        self
+    }
+
+    /// xtransport 数据块大小，需在 `MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE` 之间
+    pub fn chunk_size(mut self, size: u32) -> Self {
+        self.config.chunk_size = size;
+        self
+    }
+
+    /// 是否启用 ACK，默认关闭，需与对端一致
+    ///
+    /// 启用后 `send` 等待对端确认送达才返回（写超时内未确认返回 `VirgeError::Timeout`），
+    /// 可用 `send_noack` 逐条跳过；关闭时 `send` 在数据交给内核后即返回。
+    pub fn ack(mut self, enabled: bool) -> Self {
+        self.config.is_ack = enabled;
+        self
+    }
+
+    /// 在 SOCK_SEQPACKET 套接字上监听，见 `ServerConfig::with_seqpacket`
+    #[cfg(feature = "use-raw")]
+    pub fn seqpacket(mut self, enabled: bool) -> Self {
+        self.config.transport_options.seqpacket = enabled;
+        self
+    }
+
+    /// 以 `VIRGA_*` 环境变量覆盖当前参数
+    ///
+    /// 各来源按调用顺序覆盖，例如 `builder().toml_file(path)?.env()?.listen_port(port)`
+    /// 的优先级为：显式设置 > 环境变量 > 配置文件 > 默认值。
+    pub fn env(self) -> Result<Self> {
+        self.apply(&Layer::from_env(SERVER_KEYS))
+    }
+
+    /// 以 TOML 配置文件中的键覆盖当前参数
+    #[cfg(feature = "config-file")]
+    pub fn toml_file(self, path: impl AsRef<std::path::Path>) -> Result<Self> {
+        self.apply(&Layer::from_toml_file(path.as_ref(), SERVER_KEYS)?)
+    }
+
+    /// 绑定 vsock 监听套接字前是否设置 SO_REUSEADDR，默认关闭
+    pub fn reuse_addr(mut self, enabled: bool) -> Self {
+        self.config.reuse_addr = enabled;
+        self
+    }
+
+    /// 只接受来自这些 CID 的连接，为空时接受所有 CID，见 `ServerConfig::with_allowed_cids`
+    pub fn allowed_cids(mut self, cids: Vec<u32>) -> Self {
+        self.config = self.config.with_allowed_cids(cids);
+        self
+    }
+
+    /// 追加一段允许连接的 CID 范围，见 `ServerConfig::with_allowed_cid_range`
+    pub fn allow_cid_range(mut self, range: RangeInclusive<u32>) -> Self {
+        self.config = self.config.with_allowed_cid_range(range);
+        self
+    }
+
+    /// 设置空闲超时，见 `ServerConfig::with_idle_timeout`
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.idle_timeout = timeout;
+        self
+    }
+
+    /// 限制每个连接的接收速率，见 `ServerConfig::with_rate_limit`
+    pub fn rate_limit(mut self, bytes_per_sec: u64, burst: u64) -> Self {
+        self.config = self.config.with_rate_limit(bytes_per_sec, burst);
+        self
+    }
+
+    /// 设置每个连接的 vsock 套接字缓冲区大小，见 `ServerConfig::with_socket_buffer`
+    pub fn socket_buffer(mut self, min: u64, size: u64, max: u64) -> Self {
+        self.config = self.config.with_socket_buffer(min, size, max);
+        self
+    }
+
+    /// 设置单条消息的组装上限，见 `ServerConfig::with_max_reassembly_bytes`
+    pub fn max_reassembly_bytes(mut self, max: Option<usize>) -> Self {
+        self.config.max_reassembly_bytes = max;
+        self
+    }
+
+    /// 设置握手时限，见 `ServerConfig::with_handshake_timeout`
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.config.handshake_timeout = timeout;
+        self
+    }
+
+    /// 登记转换为句柄的连接，见 `ServerConfig::with_connection_tracking`
+    pub fn track_connections(mut self, enabled: bool) -> Self {
+        self.config.track_connections = enabled;
+        self
+    }
+
+    pub(crate) fn apply(mut self, layer: &Layer) -> Result<Self> {
+        if let Some(cid) = layer.get("listen_cid")? {
+            self.config.listen_cid = cid;
+        }
+        if let Some(port) = layer.get("listen_port")? {
+            self.config.listen_port = port;
+        }
+        if let Some(max) = layer.get("max_connections")? {
+            self.config.max_connections = Some(max);
+        }
+        if let Some(backlog) = layer.get("backlog")? {
+            self.config.backlog = Some(backlog);
+        }
+        if let Some(reuse) = layer.get_bool("reuse_addr")? {
+            self.config.reuse_addr = reuse;
+        }
+        if let Some(size) = layer.get("chunk_size")? {
+            self.config.chunk_size = size;
+        }
+        if let Some(ack) = layer.get_bool("ack")? {
+            self.config.is_ack = ack;
+        }
+        if let Some(size) = layer.get("max_message_size")? {
+            self.config.transport_options.max_message_size = size;
+        }
+        if let Some(kind) = layer.get("transport")? {
+            self.config.transport_kind = kind;
         }
-        self.transport.send(data).await
+        #[cfg(feature = "use-uds")]
+        if let Some(path) = layer.get::<std::path::PathBuf>("uds_path")? {
+            self.config.transport_options.uds_path = Some(path);
+        }
+        #[cfg(feature = "use-raw")]
+        if let Some(enabled) = layer.get_bool("seqpacket")? {
+            self.config.transport_options.seqpacket = enabled;
+        }
+        Ok(self)
     }
 
-    /// 接收数据
-    pub async fn recv(&mut self) -> Result<Vec<u8>> {
-        if !self.connected {
-            return Err(VirgeError::TransportError(
-                "Server not connected".to_string(),
-            ));
+    /// 校验参数并生成配置，其余选项可继续通过 `with_xxx` 设置
+    pub fn build(self) -> Result<ServerConfig> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+
+
+/// ServerManager 与 StopHandle、各个 VirgeServer 共享的状态
+struct ServerShared {
+    stopped: AtomicBool,
+    active: AtomicUsize,
+    /// 因对端 CID 不在允许范围内而关闭的连接数
+    rejected: AtomicU64,
+    /// 传输协议初始化失败或超时而关闭的连接数
+    handshake_failures: AtomicU64,
+    /// 活跃连接的登记项，键为连接序号
+    connections: Mutex<HashMap<u64, ConnectionEntry>>,
+    /// 启用 `track_connections` 时转换为句柄的连接
+    registry: ConnectionRegistry,
+    next_id: AtomicU64,
+    /// 空闲超时，由 start() 按管理器配置设置，空闲回收任务每个周期读取
+    idle_timeout: Mutex<Option<Duration>>,
+    /// 空闲回收任务是否在运行，与 `idle_timeout` 在同一把锁下修改
+    reaper_running: AtomicBool,
+    /// 通过 `ServerManager::on_event` 注册的生命周期事件回调
+    events: Mutex<Option<EventCallback>>,
+    #[cfg(feature = "tokio-runtime")]
+    notify: tokio::sync::Notify,
+    /// 唤醒管道：(读端, 写端)，读端与监听套接字一起 poll
+    #[cfg(feature = "use-xtransport")]
+    wake: (UnixStream, UnixStream),
+}
+
+impl ServerShared {
+    fn new() -> Result<Self> {
+        #[cfg(feature = "use-xtransport")]
+        let wake = {
+            let (rx, tx) = UnixStream::pair()?;
+            rx.set_nonblocking(true)?;
+            tx.set_nonblocking(true)?;
+            (rx, tx)
+        };
+
+        Ok(Self {
+            stopped: AtomicBool::new(false),
+            active: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0),
+            handshake_failures: AtomicU64::new(0),
+            connections: Mutex::new(HashMap::new()),
+            registry: ConnectionRegistry::default(),
+            next_id: AtomicU64::new(0),
+            idle_timeout: Mutex::new(None),
+            reaper_running: AtomicBool::new(false),
+            events: Mutex::new(None),
+            #[cfg(feature = "tokio-runtime")]
+            notify: tokio::sync::Notify::new(),
+            #[cfg(feature = "use-xtransport")]
+            wake,
+        })
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// 分配下一个连接序号，单调递增
+    fn next_conn_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// 投递生命周期事件，调用回调时不持有锁
+    fn emit(&self, event: ServerEvent) {
+        let callback = self.events.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(callback) = callback {
+            callback(event);
         }
-        self.transport.recv().await
     }
 
-    /// 断开连接
-    pub async fn disconnect(&mut self) -> Result<()> {
-        if self.connected {
-            self.transport.disconnect().await?;
-            self.connected = false;
+    /// 等待停止标记被设置，可同时有多个等待者（每个监听端口一个后台任务）
+    #[cfg(feature = "tokio-runtime")]
+    async fn stop_requested(&self) {
+        let notified = self.notify.notified();
+        tokio::pin!(notified);
+        // 先登记为等待者再检查标记，检查之后的 notify_waiters 不会被错过
+        notified.as_mut().enable();
+        if !self.is_stopped() {
+            notified.await;
         }
-        Ok(())
     }
 
-    /// 检查连接状态
-    pub fn is_connected(&self) -> bool {
-        self.connected && self.transport.is_connected()
+    /// 标记停止并唤醒所有阻塞在 accept 中的任务
+    fn trigger_stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        #[cfg(feature = "tokio-runtime")]
+        self.notify.notify_waiters();
+        #[cfg(feature = "use-xtransport")]
+        let _ = (&self.wake.1).write(&[1]);
+    }
+
+    /// 清除停止标记，供重新 start 使用
+    fn reset(&self) {
+        self.stopped.store(false, Ordering::SeqCst);
+        #[cfg(feature = "use-xtransport")]
+        {
+            let mut buf = [0u8; 64];
+            while let Ok(n) = (&self.wake.0).read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// 服务器停止句柄：可在其他任务或线程中停止 ServerManager，唤醒阻塞的 accept
+#[derive(Clone)]
+pub struct StopHandle {
+    shared: Arc<ServerShared>,
+}
+
+impl StopHandle {
+    /// 停止服务器，不再从内核接受连接；已接受的连接交付完后，阻塞中的 accept 返回 `ErrorKind::NotConnected`
+    pub fn stop(&self) {
+        info!("ServerManager stop requested");
+        self.shared.trigger_stop();
+    }
+}
+
+/// ServerManager 生命周期事件，通过 `ServerManager::on_event` 接收
+#[derive(Debug, Clone)]
+pub enum ServerEvent {
+    /// 监听器已绑定，`port` 为实际监听的端口，配置为由系统分配时为分配到的端口
+    Listening { port: u32 },
+    /// 已接受新连接，尚未完成传输协议初始化
+    Accepted { peer: VsockAddr, conn_id: u64 },
+    /// `dial` 正在向对端发起连接，尚未完成传输协议初始化
+    Dialed { peer: VsockAddr, conn_id: u64 },
+    /// 连接已结束，每个 `Accepted` 或 `Dialed` 事件都对应一个 `Disconnected` 事件
+    Disconnected { conn_id: u64, reason: DisconnectReason },
+    /// 连接已被拒绝并关闭：CID 不在 `allowed_cids` 范围内的连接不分配连接序号，
+    /// 未通过认证的连接此前已投递 `Accepted`，之后还会投递 `Disconnected`
+    Rejected { peer: VsockAddr, reason: RejectReason },
+    /// 接受连接失败，服务器停止引起的错误不会上报
+    AcceptError { error: String },
+    /// 对端已连续 `limited_for` 时长超出接收限速，见 `ServerConfig::with_rate_limit_policy`
+    RateLimited { conn_id: u64, limited_for: Duration },
+}
+
+/// 连接被拒绝的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// 对端 CID 不在 `allowed_cids` 范围内
+    CidNotAllowed,
+    /// 对端未通过 `with_authenticator` 注册的认证函数检查
+    AuthFailed,
+}
+
+/// 连接结束的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// 调用 `disconnect` 或 `disconnect_with` 正常断开
+    Closed,
+    /// 未断开就被释放
+    Dropped,
+    /// 超过 `max_connections` 被 `serve` 拒绝
+    Rejected,
+    /// 覆盖配置非法、传输协议初始化失败或超时、`dial` 连接失败，附带错误描述
+    HandshakeFailed(String),
+    /// 超过 `idle_timeout` 没有收发活动，被管理器关闭
+    Idle,
+    /// 对端持续超出接收限速，按 `RateLimitPolicy::Disconnect` 关闭
+    RateLimited,
+}
+
+type EventCallback = Arc<dyn Fn(ServerEvent) + Send + Sync>;
+
+/// 认证函数收到的连接信息
+#[derive(Debug)]
+pub struct AuthRequest<'a> {
+    peer_addr: VsockAddr,
+    conn_id: u64,
+    token: Option<&'a [u8]>,
+}
+
+impl AuthRequest<'_> {
+    /// 对端地址
+    pub fn peer_addr(&self) -> VsockAddr {
+        self.peer_addr
+    }
+
+    /// 已分配的连接序号，与 `Accepted` 事件中的一致
+    pub fn conn_id(&self) -> u64 {
+        self.conn_id
+    }
+
+    /// 对端在握手中出示的令牌，对端未设置 `with_auth_token` 时为 `None`
+    pub fn token(&self) -> Option<&[u8]> {
+        self.token
+    }
+
+    /// 对端令牌是否等于 `expected`，比较耗时只与两者的长度有关，不因内容提前结束
+    pub fn token_matches(&self, expected: &[u8]) -> bool {
+        self.token.is_some_and(|token| constant_time_eq(token, expected))
+    }
+}
+
+/// 常数时间比较：遍历较长一方的全部字节后才给出结果
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let mut diff = a.len() ^ b.len();
+    for i in 0..a.len().max(b.len()) {
+        let x = a.get(i).copied().unwrap_or(0);
+        let y = b.get(i).copied().unwrap_or(0);
+        diff |= usize::from(x ^ y);
+    }
+    std::hint::black_box(diff) == 0
+}
+
+/// 配置中携带的认证函数，可随配置一起克隆
+#[derive(Clone)]
+struct Authenticator(Arc<dyn Fn(&AuthRequest<'_>) -> bool + Send + Sync>);
+
+impl std::fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Authenticator")
+    }
+}
+
+/// 两个配置携带同一个认证函数实例（包括其克隆）时相等
+impl PartialEq for Authenticator {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// 活跃连接在共享状态中的登记项
+struct ConnectionEntry {
+    stats: Arc<StatsCounters>,
+    /// 连接套接字的副本，供空闲回收任务关闭连接；未配置空闲超时或传输没有套接字时为 `None`
+    ///
+    /// 持有副本保证连接释放前该描述符编号不会被复用，回收任务不会误关其他套接字。
+    socket: Option<OwnedFd>,
+}
+
+/// 转换为句柄的连接登记表，只保存弱引用
+#[derive(Default)]
+struct ConnectionRegistry {
+    handles: Mutex<HashMap<u64, WeakServerHandle>>,
+}
+
+impl ConnectionRegistry {
+    fn insert(&self, handle: &VirgeServerHandle) {
+        self.handles.lock().unwrap_or_else(|e| e.into_inner()).insert(handle.id(), handle.downgrade());
+    }
+
+    fn remove(&self, id: u64) {
+        self.handles.lock().unwrap_or_else(|e| e.into_inner()).remove(&id);
+    }
+
+    /// 取出仍连接的句柄（按连接序号排序），同时移除句柄已全部释放或已断开的登记项
+    fn live(&self) -> Vec<VirgeServerHandle> {
+        let mut live = Vec::new();
+        // 升级出的句柄可能是连接的最后一个引用，释放时守卫会再次加锁，需在解锁后释放
+        let mut stale = Vec::new();
+        self.handles.lock().unwrap_or_else(|e| e.into_inner()).retain(|_, weak| match weak.upgrade() {
+            Some(handle) if handle.is_connected() => {
+                live.push(handle);
+                true
+            }
+            handle => {
+                stale.extend(handle);
+                false
+            }
+        });
+        drop(stale);
+        live.sort_by_key(VirgeServerHandle::id);
+        live
+    }
+}
+
+/// `ServerManager::broadcast` 的结果，按连接序号记录每个连接的发送结果
+#[derive(Debug, Default)]
+pub struct BroadcastReport {
+    results: BTreeMap<u64, Result<()>>,
+}
+
+impl BroadcastReport {
+    /// 全部连接的发送结果，键为连接序号
+    pub fn results(&self) -> &BTreeMap<u64, Result<()>> {
+        &self.results
+    }
+
+    /// 参与本次广播的连接数
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// 没有任何登记的连接
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// 发送成功的连接数
+    pub fn delivered(&self) -> usize {
+        self.results.values().filter(|result| result.is_ok()).count()
+    }
+
+    /// 发送失败的连接及其错误
+    pub fn failures(&self) -> impl Iterator<Item = (u64, &VirgeError)> {
+        self.results.iter().filter_map(|(id, result)| result.as_ref().err().map(|e| (*id, e)))
+    }
+}
+
+/// 活跃连接计数守卫，连接断开或释放时计数减一，将其统计移出聚合范围并投递断开事件
+struct ConnectionGuard {
+    shared: Arc<ServerShared>,
+    id: u64,
+    stats: Arc<StatsCounters>,
+    /// 断开原因，未设置时视为 `Dropped`
+    reason: Option<DisconnectReason>,
+    /// 转换为句柄时是否登记到 `ServerShared::registry`
+    tracked: bool,
+}
+
+impl ConnectionGuard {
+    fn new(shared: Arc<ServerShared>, stats: Arc<StatsCounters>, id: u64, socket: Option<OwnedFd>, tracked: bool) -> Self {
+        shared.active.fetch_add(1, Ordering::SeqCst);
+        let entry = ConnectionEntry { stats: stats.clone(), socket };
+        shared.connections.lock().unwrap_or_else(|e| e.into_inner()).insert(id, entry);
+        Self { shared, id, stats, reason: None, tracked }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.shared.connections.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.id);
+        if self.tracked {
+            self.shared.registry.remove(self.id);
+        }
+        self.shared.active.fetch_sub(1, Ordering::SeqCst);
+        let reason = match self.reason.take() {
+            _ if self.stats.is_idle() => DisconnectReason::Idle,
+            _ if self.stats.is_rate_limited() => DisconnectReason::RateLimited,
+            reason => reason.unwrap_or(DisconnectReason::Dropped),
+        };
+        self.shared.emit(ServerEvent::Disconnected { conn_id: self.id, reason });
+    }
+}
+
+fn stopped_error() -> VirgeError {
+    VirgeError::IoError(std::io::Error::new(
+        std::io::ErrorKind::NotConnected,
+        "server stopped",
+    ))
+}
+
+/// 在 tokio 监听器上等待至多 `wait` 时间接受连接，超时返回 `Ok(None)`
+///
+/// accept future 可安全取消，超时或停止不会丢失连接。
+#[cfg(feature = "tokio-runtime")]
+async fn accept_tokio(
+    listener: &mut tokio_vsock::VsockListener,
+    shared: &ServerShared,
+    wait: Option<Duration>,
+) -> Result<Option<(tokio_vsock::VsockStream, VsockAddr)>> {
+    let accept = async {
+        loop {
+            if shared.is_stopped() {
+                return Err(stopped_error());
+            }
+            tokio::select! {
+                accepted = listener.accept() => {
+                    return accepted.map_err(|e| VirgeError::connection_io("Failed to accept vsock connection", e));
+                }
+                _ = shared.stop_requested() => {}
+            }
+        }
+    };
+    match wait {
+        Some(wait) => match tokio::time::timeout(wait, accept).await {
+            Ok(accepted) => accepted.map(Some),
+            Err(_) => Ok(None),
+        },
+        None => accept.await.map(Some),
+    }
+}
+
+/// 在 SOCK_SEQPACKET 监听套接字上等待至多 `wait` 时间接受连接，超时返回 `Ok(None)`
+#[cfg(feature = "use-raw")]
+async fn accept_seqpacket(
+    listener: &AsyncFd<OwnedFd>,
+    shared: &ServerShared,
+    wait: Option<Duration>,
+) -> Result<Option<(OwnedFd, VsockAddr)>> {
+    let accept_error = |e: std::io::Error| VirgeError::connection_io("Failed to accept seqpacket connection", e);
+    let accept = async {
+        loop {
+            if shared.is_stopped() {
+                return Err(stopped_error());
+            }
+            tokio::select! {
+                guard = listener.readable() => {
+                    let mut guard = guard.map_err(accept_error)?;
+                    if let Ok(accepted) = guard.try_io(|fd| sys::accept_vsock(fd.as_raw_fd())) {
+                        let (socket, (cid, port)) = accepted.map_err(accept_error)?;
+                        return Ok((socket, VsockAddr::new(cid, port)));
+                    }
+                }
+                _ = shared.stop_requested() => {}
+            }
+        }
+    };
+    match wait {
+        Some(wait) => match tokio::time::timeout(wait, accept).await {
+            Ok(accepted) => accepted.map(Some),
+            Err(_) => Ok(None),
+        },
+        None => accept.await.map(Some),
+    }
+}
+
+/// 在本地 TCP 监听器上等待至多 `wait` 时间接受连接，超时返回 `Ok(None)`
+///
+/// 对端地址映射为 `VMADDR_CID_LOCAL` 加对端的 TCP 端口。
+#[cfg(feature = "use-tcp")]
+async fn accept_tcp(
+    listener: &tokio::net::TcpListener,
+    shared: &ServerShared,
+    wait: Option<Duration>,
+) -> Result<Option<(tokio::net::TcpStream, VsockAddr)>> {
+    let accept = async {
+        loop {
+            if shared.is_stopped() {
+                return Err(stopped_error());
+            }
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (stream, addr) = accepted
+                        .map_err(|e| VirgeError::connection_io("Failed to accept tcp connection", e))?;
+                    return Ok((stream, VsockAddr::new(crate::VMADDR_CID_LOCAL as u32, addr.port() as u32)));
+                }
+                _ = shared.stop_requested() => {}
+            }
+        }
+    };
+    match wait {
+        Some(wait) => match tokio::time::timeout(wait, accept).await {
+            Ok(accepted) => accepted.map(Some),
+            Err(_) => Ok(None),
+        },
+        None => accept.await.map(Some),
+    }
+}
+
+/// 在 Unix 套接字监听器上等待至多 `wait` 时间接受连接，超时返回 `Ok(None)`
+#[cfg(feature = "use-uds")]
+async fn accept_uds(
+    listener: &tokio::net::UnixListener,
+    shared: &ServerShared,
+    wait: Option<Duration>,
+) -> Result<Option<tokio::net::UnixStream>> {
+    let accept = async {
+        loop {
+            if shared.is_stopped() {
+                return Err(stopped_error());
+            }
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (stream, _) = accepted
+                        .map_err(|e| VirgeError::connection_io("Failed to accept uds connection", e))?;
+                    return Ok(stream);
+                }
+                _ = shared.stop_requested() => {}
+            }
+        }
+    };
+    match wait {
+        Some(wait) => match tokio::time::timeout(wait, accept).await {
+            Ok(accepted) => accepted.map(Some),
+            Err(_) => Ok(None),
+        },
+        None => accept.await.map(Some),
+    }
+}
+
+/// 删除上次运行遗留的套接字文件，路径上存在其他类型的文件时报错
+#[cfg(feature = "use-uds")]
+fn remove_stale_socket(path: &std::path::Path) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => Ok(std::fs::remove_file(path)?),
+        Ok(_) => Err(VirgeError::ConfigError(format!(
+            "{} exists and is not a socket",
+            path.display()
+        ))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 已接受但尚未初始化传输协议的连接
+enum Accepted {
+    #[cfg(feature = "tokio-runtime")]
+    Tokio(tokio_vsock::VsockStream),
+    #[cfg(feature = "use-tcp")]
+    Tcp(tokio::net::TcpStream),
+    #[cfg(feature = "use-uds")]
+    Uds(tokio::net::UnixStream),
+    #[cfg(feature = "use-xtransport")]
+    XTransport(vsock::VsockStream),
+    #[cfg(feature = "use-raw")]
+    Seqpacket(OwnedFd),
+}
+
+/// 在监听器上等待至多 `wait` 时间接受一个连接，尚未初始化传输协议，超时返回 `Ok(None)`
+///
+/// `ready` 为交付握手完成连接的队列：xtransport 监听器同时等待其唤醒管道，有连接完成握手时提前返回 `Ok(None)`；
+/// 其余监听器由调用方同时等待队列的通知。
+async fn accept_raw(
+    listener: &mut Listener,
+    shared: &ServerShared,
+    wait: Option<Duration>,
+    #[cfg_attr(not(feature = "use-xtransport"), allow(unused_variables))]
+    ready: Option<&AcceptQueue>,
+) -> Result<Option<(Accepted, VsockAddr)>> {
+    let accepted = match listener {
+        #[cfg(feature = "use-yamux")]
+        Listener::Yamux(yamux_listener) => {
+            let Some((stream, addr)) = accept_tokio(yamux_listener, shared, wait).await? else {
+                return Ok(None);
+            };
+            info!("Accepted yamux connection from {:?}", addr);
+            (Accepted::Tokio(stream), addr)
+        }
+
+        #[cfg(feature = "use-raw")]
+        Listener::Raw(raw_listener) => {
+            let Some((stream, addr)) = accept_tokio(raw_listener, shared, wait).await? else {
+                return Ok(None);
+            };
+            info!("Accepted raw connection from {:?}", addr);
+            (Accepted::Tokio(stream), addr)
+        }
+
+        #[cfg(feature = "use-raw")]
+        Listener::Seqpacket(seqpacket_listener) => {
+            let Some((socket, addr)) = accept_seqpacket(seqpacket_listener, shared, wait).await? else {
+                return Ok(None);
+            };
+            info!("Accepted seqpacket connection from {:?}", addr);
+            (Accepted::Seqpacket(socket), addr)
+        }
+
+        #[cfg(feature = "use-uds")]
+        Listener::Uds(uds_listener, addr) => {
+            let Some(stream) = accept_uds(uds_listener, shared, wait).await? else {
+                return Ok(None);
+            };
+            info!("Accepted uds connection for {:?}", addr);
+            (Accepted::Uds(stream), *addr)
+        }
+
+        #[cfg(feature = "use-tcp")]
+        Listener::Tcp(tcp_listener) => {
+            let Some((stream, addr)) = accept_tcp(tcp_listener, shared, wait).await? else {
+                return Ok(None);
+            };
+            info!("Accepted tcp connection from {:?}", addr);
+            (Accepted::Tcp(stream), addr)
+        }
+
+        #[cfg(feature = "use-xtransport")]
+        Listener::XTransport(xtransport_listener) => {
+            // 同时等待监听套接字与唤醒管道，监听套接字可读后再进行 accept
+            let mut fds = vec![xtransport_listener.as_raw_fd(), shared.wake.0.as_raw_fd()];
+            fds.extend(ready.map(|queue| queue.wake.0.as_raw_fd()));
+            match sys::wait_any_readable(&fds, wait)? {
+                None => return Ok(None),
+                Some(0) => {}
+                Some(1) => return Err(stopped_error()),
+                Some(_) => {
+                    if let Some(queue) = ready {
+                        queue.drain_wake();
+                    }
+                    return Ok(None);
+                }
+            }
+            let (stream, addr) = xtransport_listener.accept()
+                .map_err(|e| VirgeError::connection_io("Failed to accept xtransport connection", e))?;
+            info!("Accepted xtransport connection from {:?}", addr);
+            (Accepted::XTransport(stream), addr)
+        }
+    };
+    Ok(Some(accepted))
+}
+
+/// 接受一个对端 CID 在允许范围内的连接，其余连接立即关闭并上报，`wait` 为整体的等待上限，`ready` 见 `accept_raw`
+async fn accept_allowed(
+    listener: &mut Listener,
+    shared: &ServerShared,
+    config: &ServerConfig,
+    wait: Option<Duration>,
+    ready: Option<&AcceptQueue>,
+) -> Result<Option<(Accepted, VsockAddr)>> {
+    let deadline = wait.map(|wait| Instant::now() + wait);
+    loop {
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let Some((stream, addr)) = accept_raw(listener, shared, remaining, ready).await? else {
+            return Ok(None);
+        };
+        if !listener.has_peer_cid() || config.allows_cid(addr.cid()) {
+            return Ok(Some((stream, addr)));
+        }
+        warn!("ServerManager rejecting connection from unexpected CID {:?}", addr);
+        shared.rejected.fetch_add(1, Ordering::Relaxed);
+        shared.emit(ServerEvent::Rejected { peer: addr, reason: RejectReason::CidNotAllowed });
+        drop(stream);
+    }
+}
+
+/// 监听端口是否表示由系统分配：`ServerConfig::PORT_ANY`（0）或 `VMADDR_PORT_ANY`
+fn is_any_port(port: u32) -> bool {
+    port == ServerConfig::PORT_ANY || port as usize == crate::VMADDR_PORT_ANY
+}
+
+/// 创建并绑定 `ty` 类型的 vsock 监听套接字，绑定前按 `reuse_addr` 设置 SO_REUSEADDR
+///
+/// `vsock`/`tokio-vsock` 的 `bind` 不提供设置套接字选项的时机，启用 `reuse_addr` 时改由此函数创建；
+/// 这两个库也不支持 SOCK_SEQPACKET，seqpacket 监听器总是由此函数创建。
+#[cfg(any(feature = "use-xtransport", feature = "use-yamux", feature = "use-raw"))]
+fn bind_vsock(ty: libc::c_int, cid: u32, port: u32, reuse_addr: bool, nonblocking: bool) -> std::io::Result<OwnedFd> {
+    let mut flags = ty | libc::SOCK_CLOEXEC;
+    if nonblocking {
+        flags |= libc::SOCK_NONBLOCK;
+    }
+    // SAFETY: socket 返回新建的描述符或 -1，成功时立即交给 OwnedFd 管理
+    let fd = unsafe { libc::socket(libc::AF_VSOCK, flags, 0) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: fd 为刚创建且未被其他对象持有的套接字
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    if reuse_addr {
+        let enable: libc::c_int = 1;
+        // SAFETY: 选项值指向有效的 c_int，长度与之一致
+        let ret = unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_REUSEADDR,
+                &enable as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    // SAFETY: sockaddr_vm 全零是合法的初始值
+    let mut addr: libc::sockaddr_vm = unsafe { std::mem::zeroed() };
+    addr.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+    addr.svm_cid = cid;
+    addr.svm_port = port;
+    // SAFETY: addr 为完整初始化的 sockaddr_vm，长度与之一致
+    let ret = unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            &addr as *const libc::sockaddr_vm as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // 与标准库监听器相同的默认队列长度，配置了 backlog 时随后由 set_backlog 覆盖
+    // SAFETY: fd 为已绑定的有效套接字
+    if unsafe { libc::listen(fd.as_raw_fd(), 128) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// 查询本机 CID 的 ioctl 请求号，即 `_IO(7, 0xb9)`
+const IOCTL_VM_SOCKETS_GET_LOCAL_CID: u32 = 0x7b9;
+
+/// 对已处于监听状态的套接字再次调用 listen，更新其内核 backlog
+fn set_backlog(fd: RawFd, backlog: u32) -> Result<()> {
+    let backlog = i32::try_from(backlog).unwrap_or(i32::MAX);
+    // SAFETY: fd 为监听器持有的有效套接字
+    if unsafe { libc::listen(fd, backlog) } != 0 {
+        return Err(VirgeError::connection_io(
+            "Failed to set listen backlog",
+            std::io::Error::last_os_error(),
+        ));
+    }
+    Ok(())
+}
+
+/// 读取套接字的整数选项
+fn socket_option(fd: RawFd, name: libc::c_int) -> std::io::Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: 选项值指向有效的 c_int，长度与之一致；无效的描述符只会使调用失败
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            name,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(value)
+}
+
+/// 配置对应的监听套接字：可接受的地址族、套接字类型与用于错误信息的名称
+fn expected_listener(config: &ServerConfig) -> (&'static [libc::c_int], libc::c_int, &'static str) {
+    #[cfg(feature = "use-uds")]
+    if config.transport_options.uds_path.is_some() {
+        return (&[libc::AF_UNIX], libc::SOCK_STREAM, "unix stream");
+    }
+    match config.transport_kind {
+        #[cfg(feature = "use-tcp")]
+        TransportKind::Tcp => (&[libc::AF_INET, libc::AF_INET6], libc::SOCK_STREAM, "tcp"),
+        #[cfg(feature = "use-raw")]
+        TransportKind::Raw if config.transport_options.seqpacket => {
+            (&[libc::AF_VSOCK], libc::SOCK_SEQPACKET, "vsock seqpacket")
+        }
+        #[allow(unreachable_patterns)]
+        _ => (&[libc::AF_VSOCK], libc::SOCK_STREAM, "vsock stream"),
+    }
+}
+
+/// 校验 `fd` 是与配置相符且处于监听状态的套接字，不符时返回 `ConfigError`
+fn check_listener_fd(fd: RawFd, config: &ServerConfig) -> Result<()> {
+    let (domains, ty, name) = expected_listener(config);
+    let invalid = |detail: String| {
+        VirgeError::ConfigError(format!("fd {} is not a listening {} socket: {}", fd, name, detail))
+    };
+    let option = |option| socket_option(fd, option).map_err(|e| invalid(e.to_string()));
+    let domain = option(libc::SO_DOMAIN)?;
+    if !domains.contains(&domain) {
+        return Err(invalid(format!("address family {}", domain)));
+    }
+    let actual = option(libc::SO_TYPE)?;
+    if actual != ty {
+        return Err(invalid(format!("socket type {}", actual)));
+    }
+    if option(libc::SO_ACCEPTCONN)? == 0 {
+        return Err(invalid("socket is not listening".to_string()));
+    }
+    Ok(())
+}
+
+/// 为接管的监听套接字设置 `FD_CLOEXEC`，并按监听器的 IO 方式设置阻塞模式：xtransport 阻塞，其余非阻塞
+#[cfg_attr(not(any(feature = "use-xtransport", feature = "use-uds")), allow(unused_variables))]
+fn prepare_listener_fd(fd: RawFd, config: &ServerConfig) -> std::io::Result<()> {
+    #[cfg(feature = "use-xtransport")]
+    let nonblocking = config.transport_kind != TransportKind::XTransport;
+    #[cfg(not(feature = "use-xtransport"))]
+    let nonblocking = true;
+    #[cfg(feature = "use-uds")]
+    let nonblocking = nonblocking || config.transport_options.uds_path.is_some();
+    // SAFETY: fd 已确认为有效的套接字，fcntl 只读写其描述符与状态标志
+    unsafe {
+        if libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let flags = if nonblocking { flags | libc::O_NONBLOCK } else { flags & !libc::O_NONBLOCK };
+        if libc::fcntl(fd, libc::F_SETFL, flags) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// 以已校验的监听套接字创建与配置相符的监听器
+fn adopt_listener(fd: OwnedFd, config: &ServerConfig) -> Result<Listener> {
+    #[cfg(feature = "use-uds")]
+    if config.transport_options.uds_path.is_some() {
+        let listener = tokio::net::UnixListener::from_std(std::os::unix::net::UnixListener::from(fd))
+            .map_err(|e| VirgeError::connection_io("Failed to adopt uds listener", e))?;
+        return Ok(Listener::Uds(listener, VsockAddr::new(config.listen_cid, config.listen_port)));
+    }
+    match config.transport_kind {
+        #[cfg(feature = "use-yamux")]
+        TransportKind::Yamux => {
+            // SAFETY: 描述符为已处于监听状态的非阻塞 vsock 套接字，所有权转移给监听器
+            let listener = unsafe { tokio_vsock::VsockListener::from_raw_fd(fd.into_raw_fd()) };
+            Ok(Listener::Yamux(listener))
+        }
+
+        #[cfg(feature = "use-xtransport")]
+        TransportKind::XTransport => {
+            // SAFETY: 描述符为已处于监听状态的阻塞 vsock 套接字，所有权转移给监听器
+            let listener = unsafe { vsock::VsockListener::from_raw_fd(fd.into_raw_fd()) };
+            Ok(Listener::XTransport(listener))
+        }
+
+        #[cfg(feature = "use-raw")]
+        TransportKind::Raw if config.transport_options.seqpacket => {
+            let listener = AsyncFd::new(fd)
+                .map_err(|e| VirgeError::connection_io("Failed to adopt seqpacket listener", e))?;
+            Ok(Listener::Seqpacket(listener))
+        }
+
+        #[cfg(feature = "use-raw")]
+        TransportKind::Raw => {
+            // SAFETY: 描述符为已处于监听状态的非阻塞 vsock 套接字，所有权转移给监听器
+            let listener = unsafe { tokio_vsock::VsockListener::from_raw_fd(fd.into_raw_fd()) };
+            Ok(Listener::Raw(listener))
+        }
+
+        #[cfg(feature = "use-tcp")]
+        TransportKind::Tcp => {
+            let listener = tokio::net::TcpListener::from_std(std::net::TcpListener::from(fd))
+                .map_err(|e| VirgeError::connection_io("Failed to adopt tcp listener", e))?;
+            Ok(Listener::Tcp(listener))
+        }
+    }
+}
+
+/// 队列中的连接：已接受的流、对端地址与连接到达的本地端口
+type QueuedConnection = (Accepted, VsockAddr, u32);
+
+/// 从队列取出的连接
+enum Queued {
+    /// 后台任务已接受、尚未初始化传输协议的连接
+    Accepted(QueuedConnection),
+    /// 已在工作任务中完成握手的连接，或握手失败的错误
+    Ready(Box<Result<VirgeServer>>),
+}
+
+struct QueueState {
+    items: VecDeque<QueuedConnection>,
+    /// 已完成握手、等待 accept() 取走的连接，握手失败的连接以错误交付
+    ready: VecDeque<Result<VirgeServer>>,
+    /// 仍在工作任务中握手的连接数
+    handshaking: usize,
+    /// 仍在运行的后台接受任务数
+    acceptors: usize,
+    /// 后台接受任务全部退出或管理器已停止，不会再有新连接；已接受的连接仍可取走
+    closed: bool,
+    /// 管理器已停止接受连接且不再取走队列，排队与之后完成握手的连接直接关闭
+    discarded: bool,
+}
+
+/// 后台接受任务、握手工作任务与 accept() 之间的连接队列
+///
+/// 未启用接受队列时只用于交付握手完成的连接，`items` 始终为空。
+struct AcceptQueue {
+    capacity: usize,
+    overflow: QueueOverflow,
+    state: Mutex<QueueState>,
+    #[cfg(feature = "tokio-runtime")]
+    ready: tokio::sync::Notify,
+    #[cfg(not(feature = "tokio-runtime"))]
+    ready: std::sync::Condvar,
+    /// 握手完成时写入的唤醒管道：(读端, 写端)，读端与 xtransport 监听套接字一起 poll
+    #[cfg(feature = "use-xtransport")]
+    wake: (UnixStream, UnixStream),
+}
+
+impl AcceptQueue {
+    fn new(capacity: usize, overflow: QueueOverflow) -> Result<Self> {
+        #[cfg(feature = "use-xtransport")]
+        let wake = {
+            let (rx, tx) = UnixStream::pair()?;
+            rx.set_nonblocking(true)?;
+            tx.set_nonblocking(true)?;
+            (rx, tx)
+        };
+
+        Ok(Self {
+            capacity,
+            overflow,
+            state: Mutex::new(QueueState {
+                items: VecDeque::new(),
+                ready: VecDeque::new(),
+                handshaking: 0,
+                acceptors: 0,
+                closed: false,
+                discarded: false,
+            }),
+            #[cfg(feature = "tokio-runtime")]
+            ready: tokio::sync::Notify::new(),
+            #[cfg(not(feature = "tokio-runtime"))]
+            ready: std::sync::Condvar::new(),
+            #[cfg(feature = "use-xtransport")]
+            wake,
+        })
+    }
+
+    /// 尚未被 accept() 取走的连接数，包括排队、握手中与已完成握手的连接
+    fn len(&self) -> usize {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.items.len() + state.handshaking + state.ready.len()
+    }
+
+    fn wake(&self) {
+        #[cfg(feature = "tokio-runtime")]
+        self.ready.notify_one();
+        #[cfg(not(feature = "tokio-runtime"))]
+        self.ready.notify_all();
+    }
+
+    /// 清空唤醒管道中的通知
+    #[cfg(feature = "use-xtransport")]
+    fn drain_wake(&self) {
+        let mut buf = [0u8; 64];
+        while let Ok(n) = (&self.wake.0).read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+        }
+    }
+
+    /// 放入一个新连接，队列已满时按溢出策略关闭其中一个连接
+    fn push(&self, accepted: Accepted, addr: VsockAddr, local_port: u32) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.closed {
+            return;
+        }
+        if state.items.len() >= self.capacity {
+            match self.overflow {
+                QueueOverflow::DropOldest => {
+                    if let Some((_, oldest, _)) = state.items.pop_front() {
+                        warn!("Accept queue full ({}), dropping oldest connection from {:?}", self.capacity, oldest);
+                    }
+                }
+                QueueOverflow::RefuseNew => {
+                    warn!("Accept queue full ({}), refusing connection from {:?}", self.capacity, addr);
+                    return;
+                }
+            }
+        }
+        state.items.push_back((accepted, addr, local_port));
+        drop(state);
+        self.wake();
+    }
+
+    /// 登记一个开始握手的连接
+    fn begin_handshake(&self) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).handshaking += 1;
+    }
+
+    /// 结束一个连接的握手，将连接或握手失败的错误放入就绪队列并唤醒 accept()；队列已丢弃时关闭该连接
+    fn finish_handshake(&self, server: Result<VirgeServer>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.handshaking -= 1;
+        if state.discarded {
+            // 释放连接会投递断开事件，不在持锁时进行
+            drop(state);
+            drop(server);
+            return;
+        }
+        state.ready.push_back(server);
+        drop(state);
+        self.wake();
+        #[cfg(feature = "use-xtransport")]
+        let _ = (&self.wake.1).write(&[1]);
+    }
+
+    /// 取出一个已完成握手的连接或握手失败的错误
+    fn take_ready(&self) -> Option<Result<VirgeServer>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).ready.pop_front()
+    }
+
+    /// 等待握手完成的通知，可能提前返回，调用方需重新检查队列
+    #[cfg(feature = "tokio-runtime")]
+    async fn ready_notified(&self) {
+        self.ready.notified().await;
+    }
+
+    /// 标记队列关闭，不再接收新连接；已接受的连接仍由 pop 依次取出，取完后 pop 返回停止错误
+    fn close(&self) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).closed = true;
+        self.wake();
+    }
+
+    /// 登记一个开始运行的后台接受任务
+    fn acceptor_started(&self) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).acceptors += 1;
+    }
+
+    /// 登记一个退出的后台接受任务，全部退出后关闭队列，其退出前接受的连接都已入队
+    fn acceptor_stopped(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.acceptors -= 1;
+        if state.acceptors == 0 {
+            drop(state);
+            self.close();
+        }
+    }
+
+    /// 队列已关闭且已接受的连接全部取走
+    fn is_drained(&self) -> bool {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.closed && state.items.is_empty() && state.ready.is_empty() && state.handshaking == 0
+    }
+
+    /// 关闭队列并关闭尚未取走的连接，仍在握手的连接完成后同样关闭，等待中的 pop 返回停止错误
+    fn discard(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.closed = true;
+        state.discarded = true;
+        let items = std::mem::take(&mut state.items);
+        let ready = std::mem::take(&mut state.ready);
+        drop(state);
+        drop((items, ready));
+        self.wake();
+    }
+
+    /// 等待至多 `wait` 时间取出一个连接（`None` 表示一直等待），超时返回 `Ok(None)`
+    ///
+    /// `with_ready` 为 `false` 时只取尚未握手的连接，供按对端选择配置的 accept 使用。
+    /// 队列关闭后先取完已接受的连接（包括等待仍在握手的连接完成），之后返回停止错误。
+    async fn pop(&self, wait: Option<Duration>, with_ready: bool) -> Result<Option<Queued>> {
+        let deadline = wait.map(|wait| Instant::now() + wait);
+        loop {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let drained = state.items.is_empty() && (!with_ready || (state.ready.is_empty() && state.handshaking == 0));
+            if state.discarded || (state.closed && drained) {
+                return Err(stopped_error());
+            }
+            if let Some(server) = with_ready.then(|| state.ready.pop_front()).flatten() {
+                return Ok(Some(Queued::Ready(Box::new(server))));
+            }
+            if let Some(item) = state.items.pop_front() {
+                return Ok(Some(Queued::Accepted(item)));
+            }
+            let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            if remaining == Some(Duration::ZERO) {
+                return Ok(None);
+            }
+
+            // notify_one 在没有等待者时保留许可，解锁后才入队的连接不会被错过
+            #[cfg(feature = "tokio-runtime")]
+            {
+                drop(state);
+                match remaining {
+                    Some(remaining) => {
+                        let _ = tokio::time::timeout(remaining, self.ready.notified()).await;
+                    }
+                    None => self.ready.notified().await,
+                }
+            }
+            #[cfg(not(feature = "tokio-runtime"))]
+            drop(match remaining {
+                Some(remaining) => self.ready.wait_timeout(state, remaining)
+                    .map(|(state, _)| state)
+                    .unwrap_or_else(|e| e.into_inner().0),
+                None => self.ready.wait(state).unwrap_or_else(|e| e.into_inner()),
+            });
+        }
+    }
+}
+
+/// 后台接受循环：持续从监听器接受连接放入队列，服务器停止后关闭队列并退出
+async fn acceptor_loop(
+    mut listener: Listener,
+    shared: Arc<ServerShared>,
+    queue: Arc<AcceptQueue>,
+    config: ServerConfig,
+    done: futures::channel::oneshot::Sender<()>,
+) {
+    let local_port = listener.local_addr().map_or(config.listen_port, |addr| addr.port());
+    loop {
+        match accept_allowed(&mut listener, &shared, &config, None, None).await {
+            Ok(Some((accepted, addr))) => queue.push(accepted, addr, local_port),
+            Ok(None) => {}
+            Err(_) if shared.is_stopped() => break,
+            Err(e) => {
+                warn!("ServerManager background accept failed: {}", e);
+                shared.emit(ServerEvent::AcceptError { error: e.to_string() });
+                crate::transport::sleep(Duration::from_millis(10)).await;
+            }
+        }
+    }
+    debug!("ServerManager background acceptor stopped");
+    drop(listener);
+    queue.acceptor_stopped();
+    let _ = done.send(());
+}
+
+/// 启动后台接受任务，返回在其退出并关闭监听器后完成的接收端
+///
+/// xtransport 监听器的 accept 会阻塞线程，运行在独立线程中；其余监听器运行在 tokio 任务中。
+fn spawn_acceptor(
+    listener: Listener,
+    shared: Arc<ServerShared>,
+    queue: Arc<AcceptQueue>,
+    config: ServerConfig,
+) -> futures::channel::oneshot::Receiver<()> {
+    let (done_tx, done_rx) = futures::channel::oneshot::channel();
+    queue.acceptor_started();
+    #[cfg(feature = "tokio-runtime")]
+    {
+        #[cfg(feature = "use-xtransport")]
+        let blocking = matches!(listener, Listener::XTransport(_));
+        #[cfg(not(feature = "use-xtransport"))]
+        let blocking = false;
+        if !blocking {
+            tokio::spawn(acceptor_loop(listener, shared, queue, config, done_tx));
+            return done_rx;
+        }
+    }
+    std::thread::spawn(move || futures::executor::block_on(acceptor_loop(listener, shared, queue, config, done_tx)));
+    done_rx
+}
+
+/// 已分配连接序号、等待初始化传输协议的连接
+struct Handshake {
+    stream: Accepted,
+    peer_addr: VsockAddr,
+    local_port: u32,
+    conn_id: u64,
+}
+
+impl Handshake {
+    /// xtransport 的握手使用阻塞 IO，需在独立线程中进行
+    fn is_blocking(&self) -> bool {
+        #[cfg(feature = "use-xtransport")]
+        if matches!(self.stream, Accepted::XTransport(_)) {
+            return true;
+        }
+        false
+    }
+
+    /// 按 `config` 初始化传输协议并生成连接，失败时记录警告、计入握手失败数并投递 `HandshakeFailed`
+    ///
+    /// `manager` 为管理器配置，提供握手时限与空闲超时；`custom` 表示 `config` 来自覆盖函数，需先校验。
+    async fn complete(
+        self,
+        config: &ServerConfig,
+        manager: &ServerConfig,
+        custom: bool,
+        shared: Arc<ServerShared>,
+    ) -> Result<VirgeServer> {
+        let Handshake { stream, peer_addr, local_port, conn_id } = self;
+        let stats = Arc::new(StatsCounters::default());
+        let on_violation = rate_limit_hook(&shared, conn_id);
+        let transport = match init_transport(stream, config, manager, custom, &stats, on_violation).await {
+            Ok(transport) => transport,
+            Err(e) => {
+                warn!("Handshake with {:?} (connection #{}) failed: {}", peer_addr, conn_id, e);
+                shared.handshake_failures.fetch_add(1, Ordering::Relaxed);
+                shared.emit(ServerEvent::Disconnected {
+                    conn_id,
+                    reason: DisconnectReason::HandshakeFailed(e.to_string()),
+                });
+                return Err(e);
+            }
+        };
+        let peer_identity = match authenticate(manager, transport.as_ref(), peer_addr, conn_id) {
+            Ok(identity) => identity,
+            Err(e) => {
+                warn!("Rejecting connection #{} from {:?}: {}", conn_id, peer_addr, e);
+                shared.rejected.fetch_add(1, Ordering::Relaxed);
+                shared.emit(ServerEvent::Rejected { peer: peer_addr, reason: RejectReason::AuthFailed });
+                if let Err(e) = transport.disconnect_with_reason(CLOSE_AUTH_FAILED, "Authentication failed").await {
+                    debug!("Failed to notify connection #{} of the authentication failure: {}", conn_id, e);
+                }
+                shared.emit(ServerEvent::Disconnected {
+                    conn_id,
+                    reason: DisconnectReason::HandshakeFailed(e.to_string()),
+                });
+                return Err(e);
+            }
+        };
+
+        let socket = idle_socket(manager.idle_timeout, transport.as_ref(), conn_id);
+        Ok(VirgeServer {
+            transport,
+            connected: true,
+            peer_addr,
+            local_port,
+            id: conn_id,
+            guard: Some(ConnectionGuard::new(shared, stats.clone(), conn_id, socket, manager.track_connections)),
+            chunk_size: config.chunk_size,
+            max_message_size: config.transport_options.max_message_size,
+            nonblocking_send: config.transport_options.nonblocking_send,
+            read_timeout: None,
+            write_timeout: None,
+            read_buffer: Vec::new(),
+            reassembly_limit: config.reassembly_limit(),
+            discard: framing::Remaining::default(),
+            stats,
+            write_shutdown: false,
+            peer_eof: false,
+            peer_identity,
+            #[cfg(feature = "serde")]
+            wire_format: config.wire_format,
+        })
+    }
+}
+
+/// 在工作任务中按管理器配置完成握手，成功的连接或握手失败的错误放入 `queue` 等待 accept() 取走
+///
+/// xtransport 的握手运行在独立线程中，其余传输运行在 tokio 任务中；失败的连接在 `Handshake::complete` 中记录、
+/// 计数后关闭，只有其错误交给 accept()。
+fn spawn_handshake(handshake: Handshake, config: ServerConfig, shared: Arc<ServerShared>, queue: Arc<AcceptQueue>) {
+    queue.begin_handshake();
+    #[cfg_attr(not(feature = "tokio-runtime"), allow(unused_variables))]
+    let blocking = handshake.is_blocking();
+    let task = async move {
+        let server = handshake.complete(&config, &config, false, shared).await;
+        queue.finish_handshake(server);
+    };
+    #[cfg(feature = "tokio-runtime")]
+    if !blocking {
+        tokio::spawn(task);
+        return;
+    }
+    std::thread::spawn(move || futures::executor::block_on(task));
+}
+
+/// 对端持续超出接收限速时投递 `RateLimited` 事件，管理器已释放时不投递
+fn rate_limit_hook(shared: &Arc<ServerShared>, conn_id: u64) -> ViolationHook {
+    let shared = Arc::downgrade(shared);
+    Arc::new(move |limited_for| {
+        if let Some(shared) = shared.upgrade() {
+            shared.emit(ServerEvent::RateLimited { conn_id, limited_for });
+        }
+    })
+}
+
+/// 按选定的配置创建传输实例并从已接受的流初始化
+///
+/// 初始化超过 `manager.handshake_timeout` 时返回 `VirgeError::Timeout`，该连接随之关闭。
+async fn init_transport(
+    stream: Accepted,
+    config: &ServerConfig,
+    manager: &ServerConfig,
+    custom: bool,
+    stats: &Arc<StatsCounters>,
+    on_violation: ViolationHook,
+) -> Result<Box<dyn Transport>> {
+    if custom {
+        debug!("Using per-connection config");
+        manager.check_override(config)?;
+    }
+
+    let timeout = manager.handshake_timeout;
+    let transport = config.transport_kind.create(true, config.is_ack, &config.transport_options);
+    let limits = &config.transport_options.rate_limit;
+    let transport = RateLimitTransport::wrap(transport, limits, stats, Some(on_violation));
+    let mut transport: Box<dyn Transport> = Box::new(
+        StatsTransport::new(transport, stats.clone())
+            .with_seqpacket(config.transport_options.uses_seqpacket()),
+    );
+    match stream {
+        #[cfg(feature = "tokio-runtime")]
+        Accepted::Tokio(stream) => handshake_within(timeout, transport.from_tokio_stream(stream)).await?,
+        #[cfg(feature = "use-tcp")]
+        Accepted::Tcp(stream) => handshake_within(timeout, transport.from_tcp_stream(stream)).await?,
+        #[cfg(feature = "use-uds")]
+        Accepted::Uds(stream) => handshake_within(timeout, transport.from_uds_stream(stream)).await?,
+        #[cfg(feature = "use-xtransport")]
+        Accepted::XTransport(stream) => {
+            // 握手在阻塞套接字上读写，以套接字超时限定时长，初始化完成时按传输的设置恢复
+            stream.set_read_timeout(Some(timeout))?;
+            stream.set_write_timeout(Some(timeout))?;
+            transport.from_stream(stream, config.chunk_size, config.is_ack).await
+                .map_err(|e| if e.is_timeout() { handshake_timeout_error(timeout) } else { e })?
+        }
+        #[cfg(feature = "use-raw")]
+        Accepted::Seqpacket(socket) => handshake_within(timeout, transport.from_seqpacket(socket)).await?,
+    }
+    crate::transport::apply_send_buffer_limit(transport.as_ref(), &config.transport_options)?;
+    crate::transport::apply_socket_buffer(transport.as_ref(), &config.transport_options)?;
+    Ok(transport)
+}
+
+/// 在 `timeout` 内完成传输协议初始化，超时时丢弃初始化 future 并关闭其持有的流
+#[cfg(feature = "tokio-runtime")]
+async fn handshake_within(timeout: Duration, init: impl Future<Output = Result<()>>) -> Result<()> {
+    tokio::time::timeout(timeout, init).await.unwrap_or_else(|_| Err(handshake_timeout_error(timeout)))
+}
+
+/// 按管理器配置的认证函数检查对端在握手中出示的令牌，通过时返回该令牌
+///
+/// 未配置认证函数时不检查，返回 `Ok(None)`；未通过时返回错误，由调用方关闭连接。
+fn authenticate(
+    manager: &ServerConfig,
+    transport: &dyn Transport,
+    peer_addr: VsockAddr,
+    conn_id: u64,
+) -> Result<Option<Vec<u8>>> {
+    let Some(authenticator) = &manager.authenticator else {
+        return Ok(None);
+    };
+    let token = transport.peer_auth_token().map(<[u8]>::to_vec);
+    let request = AuthRequest { peer_addr, conn_id, token: token.as_deref() };
+    if (authenticator.0)(&request) {
+        return Ok(token);
+    }
+    Err(VirgeError::connection(format!(
+        "Peer {:?} failed authentication ({})",
+        peer_addr,
+        if request.token.is_some() { "token rejected" } else { "no token presented" }
+    )))
+}
+
+fn handshake_timeout_error(timeout: Duration) -> VirgeError {
+    VirgeError::Timeout(format!("Transport handshake not completed within {:?}", timeout))
+}
+
+/// 复制连接套接字供空闲回收任务关闭，未配置 `idle_timeout` 或传输没有套接字时为 `None`
+fn idle_socket(idle_timeout: Option<Duration>, transport: &dyn Transport, conn_id: u64) -> Option<OwnedFd> {
+    idle_timeout.and(transport.raw_fd()).and_then(|fd| {
+        // SAFETY: fd 属于刚初始化的传输，复制期间保持打开
+        unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()
+            .inspect_err(|e| warn!("Blocked calls on connection #{} will not be woken when idle: {}", conn_id, e))
+            .ok()
+    })
+}
+
+/// 空闲回收任务两次检查之间的最短与最长间隔
+const IDLE_CHECK_MIN: Duration = Duration::from_millis(10);
+const IDLE_CHECK_MAX: Duration = Duration::from_secs(1);
+
+/// 检查一次全部连接，关闭超过空闲超时的连接，返回下次检查前的等待时间
+///
+/// 未配置空闲超时时清除运行标记并返回 `None`，回收任务随之退出。
+fn reap_idle(shared: &ServerShared) -> Option<Duration> {
+    let idle_timeout = shared.idle_timeout.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(timeout) = *idle_timeout else {
+        shared.reaper_running.store(false, Ordering::SeqCst);
+        return None;
+    };
+    drop(idle_timeout);
+
+    let now = SystemTime::now();
+    let connections = shared.connections.lock().unwrap_or_else(|e| e.into_inner());
+    for (id, entry) in connections.iter() {
+        if entry.stats.is_idle() {
+            continue;
+        }
+        let idle = entry.stats.idle_since()
+            .and_then(|since| now.duration_since(since).ok())
+            .unwrap_or_default();
+        if idle < timeout {
+            continue;
+        }
+        info!("ServerManager closing connection #{} after {:?} without activity", id, idle);
+        entry.stats.mark_idle();
+        if let Some(socket) = &entry.socket {
+            // SAFETY: 描述符由登记项持有，持有连接表的锁期间不会被关闭
+            unsafe { libc::shutdown(socket.as_raw_fd(), libc::SHUT_RDWR) };
+        }
+    }
+    Some((timeout / 4).clamp(IDLE_CHECK_MIN, IDLE_CHECK_MAX))
+}
+
+/// 启动空闲回收任务，管理器与全部连接都释放后退出
+///
+/// 启用 tokio-runtime 时运行在 tokio 任务中，否则运行在独立线程中。
+fn spawn_idle_reaper(shared: Weak<ServerShared>) {
+    #[cfg(feature = "tokio-runtime")]
+    tokio::spawn(async move {
+        while let Some(wait) = shared.upgrade().and_then(|shared| reap_idle(&shared)) {
+            tokio::time::sleep(wait).await;
+        }
+    });
+    #[cfg(not(feature = "tokio-runtime"))]
+    std::thread::spawn(move || {
+        while let Some(wait) = shared.upgrade().and_then(|shared| reap_idle(&shared)) {
+            std::thread::sleep(wait);
+        }
+    });
+}
+
+/// 不按对端选择配置的 accept，连接在后台按管理器配置握手
+const NO_OVERRIDE: Option<fn(&VsockAddr, u32) -> ServerConfig> = None;
+
+/// 服务器管理器：负责管理vsock监听和连接接受，为每个连接生成VirgeServer实例
+pub struct ServerManager {
+    config: ServerConfig,
+    listener: Option<Listener>,
+    running: bool,
+    shared: Option<Arc<ServerShared>>,
+    /// 运行期间存在：交付握手完成的连接，启用接受队列时还由后台任务填充，监听器随之移交给后台任务
+    queue: Option<Arc<AcceptQueue>>,
+    /// 每个后台接受任务退出时完成
+    acceptor_done: Vec<futures::channel::oneshot::Receiver<()>>,
+    /// 监听器移交给后台任务前记录的本地地址
+    queued_addr: Option<VsockAddr>,
+    /// 移交给后台任务的全部监听器的本地地址
+    listening_addrs: Vec<VsockAddr>,
+    /// 生命周期事件回调，start() 时交给共享状态
+    events: Option<EventCallback>,
+}
+
+/// Virga 服务器连接：与VirgeClient类似，负责单个连接的数据传输。
+///
+/// 未调用 `disconnect` 就被释放时强制关闭传输，尚未读取的数据被丢弃并记录警告。
+pub struct VirgeServer {
+    transport: Box<dyn Transport>,
+    connected: bool,
+    peer_addr: VsockAddr,
+    /// 连接到达的本地监听端口，`dial` 发起的连接为本地端口，内存连接为 0
+    local_port: u32,
+    /// 连接序号，由 ServerManager 单调分配，内存连接为 0
+    id: u64,
+    guard: Option<ConnectionGuard>,
+    chunk_size: u32,
+    max_message_size: usize,
+    /// 发送缓冲区已满时 `send` 返回 `ErrorKind::WouldBlock`
+    nonblocking_send: bool,
+    /// 用户设置的读超时，`recv_msg_timeout` 结束后恢复
+    read_timeout: Option<Duration>,
+    /// 用户设置的写超时，`flush_timeout` 结束后恢复
+    write_timeout: Option<Duration>,
+    /// 已从传输层收到但尚未被 recv_msg 消费的数据
+    read_buffer: Vec<u8>,
+    /// `recv_msg` 等接口组装单条消息的字节数上限
+    reassembly_limit: usize,
+    /// 超限或未读完的消息尚未丢弃的负载，下一次按消息接收前先丢弃
+    discard: framing::Remaining,
+    stats: Arc<StatsCounters>,
+    /// 本端已调用 `shutdown_write`，不再发送数据
+    write_shutdown: bool,
+    /// 客户端已关闭写方向，此后 recv/read 返回流结束
+    peer_eof: bool,
+    /// 对端在握手中出示并通过认证的令牌
+    peer_identity: Option<Vec<u8>>,
+    #[cfg(feature = "serde")]
+    wire_format: WireFormat,
+}
+
+impl ServerManager {
+    pub const fn new(config: ServerConfig) -> Self {
+        Self {
+            config,
+            listener: None,
+            running: false,
+            shared: None,
+            queue: None,
+            acceptor_done: Vec::new(),
+            queued_addr: None,
+            listening_addrs: Vec::new(),
+            events: None,
+        }
+    }
+
+    /// 注册生命周期事件回调，替换之前注册的回调
+    ///
+    /// 回调在接受连接的任务或释放连接的线程中同步调用，应尽快返回。
+    /// 通常在 start() 之前调用，以便收到 `Listening` 事件。
+    pub fn on_event(&mut self, callback: impl Fn(ServerEvent) + Send + Sync + 'static) {
+        let callback: EventCallback = Arc::new(callback);
+        if let Some(shared) = &self.shared {
+            *shared.events.lock().unwrap_or_else(|e| e.into_inner()) = Some(callback.clone());
+        }
+        self.events = Some(callback);
+    }
+
+    /// 绑定并开始监听，stop() 之后可再次调用以重新绑定
+    ///
+    /// 监听地址已被占用（例如另一个实例仍在运行）时返回 `VirgeError::PortInUse`。
+    pub async fn start(&mut self) -> Result<()> {
+        info!(
+            "ServerManager starting on cid={}, port={}",
+            self.config.listen_cid,
+            self.config.listen_port
+        );
+        self.config.validate()?;
+        self.stop_acceptor().await;
+
+        if let Some(shared) = &self.shared {
+            shared.reset();
+        }
+        let shared = self.ensure_shared()?;
+
+        // 先绑定全部端口，任一端口失败时已绑定的监听器随之释放
+        let mut listeners = Vec::new();
+        for port in self.config.ports() {
+            let listener = self.create_listener(port).await?;
+            if let Some(backlog) = self.config.backlog {
+                set_backlog(listener.as_raw_fd(), backlog)?;
+            }
+            listeners.push(listener);
+        }
+        self.launch(shared, listeners)
+    }
+
+    /// 接管进程继承的监听套接字并开始监听，用于不中断服务的原地升级
+    ///
+    /// `fd` 通常来自旧进程的 [`ServerManager::into_raw_listener`]，经 exec 继承或 `SCM_RIGHTS` 传递。
+    /// 描述符须为与 `config` 的传输协议相符、已处于监听状态的套接字：vsock 流套接字（启用 `seqpacket` 时为
+    /// SOCK_SEQPACKET），tcp 传输为 TCP 套接字，配置了 `uds_path` 时为 Unix 套接字；否则返回 `ConfigError`。
+    /// 监听地址取自套接字本身，`config` 中的端口被忽略，不支持 `with_ports` 的额外端口。
+    /// 返回时管理器已处于运行状态，积压在监听队列中的连接由之后的 `accept()` 依次交付。
+    ///
+    /// # Safety
+    /// `fd` 须为调用方独占的描述符。成功时管理器取得其所有权并在停止时关闭它，调用方不得再使用或关闭；
+    /// 返回 `ConfigError` 时描述符保持打开，仍归调用方所有，其他错误发生时描述符已被关闭。
+    pub async unsafe fn from_raw_listener(fd: RawFd, config: ServerConfig) -> Result<Self> {
+        config.validate()?;
+        if !config.extra_ports.is_empty() {
+            return Err(VirgeError::ConfigError(
+                "from_raw_listener adopts a single listener, extra listen ports are not supported".to_string(),
+            ));
+        }
+        check_listener_fd(fd, &config)?;
+        prepare_listener_fd(fd, &config).map_err(|e| VirgeError::connection_io("Failed to adopt listener", e))?;
+        // SAFETY: 调用方保证 fd 为其独占的有效描述符，上面已确认它是监听套接字
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let listener = adopt_listener(fd, &config)?;
+        if let Some(backlog) = config.backlog {
+            set_backlog(listener.as_raw_fd(), backlog)?;
+        }
+        let mut manager = Self::new(config);
+        let shared = manager.ensure_shared()?;
+        info!("ServerManager adopted listener on {:?}", listener.local_addr()?);
+        manager.launch(shared, vec![listener])?;
+        Ok(manager)
+    }
+
+    /// 停止管理器并交出监听套接字，监听队列中尚未接受的连接保留在套接字中，由接管它的进程接受
+    ///
+    /// 返回的描述符是监听套接字的副本，带有 `FD_CLOEXEC`，经 exec 传递给新进程前需由调用方清除该标志。
+    /// 已经交付的 `VirgeServer` 不受影响；已被接受但尚未交给 `accept()` 的连接随管理器关闭。
+    /// 未运行、启用了后台接受队列或监听多个端口时返回 `ConfigError`。
+    pub fn into_raw_listener(mut self) -> Result<RawFd> {
+        if !self.acceptor_done.is_empty() {
+            return Err(VirgeError::ConfigError(
+                "into_raw_listener requires a single listener without a background accept queue".to_string(),
+            ));
+        }
+        let Some(listener) = self.listener.take() else {
+            return Err(VirgeError::ConfigError("ServerManager not running".to_string()));
+        };
+        // SAFETY: 监听器持有的描述符在此期间有效；F_DUPFD_CLOEXEC 返回新的描述符或 -1
+        let fd = unsafe { libc::fcntl(listener.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(VirgeError::connection_io(
+                "Failed to duplicate listener",
+                std::io::Error::last_os_error(),
+            ));
+        }
+        info!("ServerManager handing over listener on {:?}", listener.local_addr());
+        // 关闭原描述符不影响副本，套接字及其监听队列保持不变
+        drop(listener);
+        if let Some(queue) = self.queue.take() {
+            queue.discard();
+        }
+        Ok(fd)
+    }
+
+    /// 启动监听器上的接受流程：启用接受队列时移交给后台任务，否则由 accept() 直接使用
+    fn launch(&mut self, shared: Arc<ServerShared>, mut listeners: Vec<Listener>) -> Result<()> {
+        let addrs = listeners.iter().map(Listener::local_addr).collect::<Result<Vec<_>>>()?;
+        self.arm_idle_reaper(&shared);
+
+        let background = self.config.effective_accept_queue();
+        // 未启用接受队列时队列只交付握手完成的连接，容量不起作用
+        let (capacity, overflow) = background.unwrap_or((0, QueueOverflow::RefuseNew));
+        let queue = Arc::new(AcceptQueue::new(capacity, overflow)?);
+        if background.is_some() {
+            self.queued_addr = addrs.first().copied();
+            self.listening_addrs = addrs.clone();
+            for listener in listeners {
+                self.acceptor_done.push(spawn_acceptor(listener, shared.clone(), queue.clone(), self.config.clone()));
+            }
+        } else {
+            self.listener = listeners.pop();
+        }
+        self.queue = Some(queue);
+        self.running = true;
+        for addr in addrs {
+            shared.emit(ServerEvent::Listening { port: addr.port() });
+        }
+        Ok(())
+    }
+
+    /// 返回共享状态，首次调用时创建，start() 与 dial() 共用
+    fn ensure_shared(&mut self) -> Result<Arc<ServerShared>> {
+        if let Some(shared) = &self.shared {
+            return Ok(shared.clone());
+        }
+        let shared = Arc::new(ServerShared::new()?);
+        *shared.events.lock().unwrap_or_else(|e| e.into_inner()) = self.events.clone();
+        self.shared = Some(shared.clone());
+        Ok(shared)
+    }
+
+    /// 按管理器配置更新空闲超时，需要时启动唯一的空闲回收任务
+    fn arm_idle_reaper(&self, shared: &Arc<ServerShared>) {
+        let mut idle_timeout = shared.idle_timeout.lock().unwrap_or_else(|e| e.into_inner());
+        *idle_timeout = self.config.idle_timeout;
+        if idle_timeout.is_some() && !shared.reaper_running.swap(true, Ordering::SeqCst) {
+            spawn_idle_reaper(Arc::downgrade(shared));
+        }
+    }
+
+    /// 关闭连接队列（仍在握手或尚未取走的连接随之关闭），停止全部后台接受任务并等待其关闭监听器
+    async fn stop_acceptor(&mut self) {
+        if let Some(queue) = self.queue.take() {
+            queue.discard();
+        }
+        self.queued_addr = None;
+        self.listening_addrs.clear();
+        if self.acceptor_done.is_empty() {
+            return;
+        }
+        if let Some(shared) = &self.shared {
+            shared.trigger_stop();
+        }
+        for done in std::mem::take(&mut self.acceptor_done) {
+            let _ = done.await;
+        }
+    }
+
+    /// 已被接受、尚未被 accept() 取走的连接数，包括启用接受队列时排队的连接与仍在握手或已完成握手的连接
+    pub fn pending_connections(&self) -> usize {
+        self.queue.as_ref().map_or(0, |queue| queue.len())
+    }
+
+    /// 获取停止句柄，start() 之后可用
+    pub fn stop_handle(&self) -> Option<StopHandle> {
+        self.shared.as_ref().map(|shared| StopHandle { shared: shared.clone() })
+    }
+
+    /// 当前仍未断开的已接受或 `dial` 发起的连接数
+    pub fn active_connections(&self) -> usize {
+        self.shared.as_ref().map_or(0, |shared| shared.active.load(Ordering::SeqCst))
+    }
+
+    /// 因对端 CID 不在 `allowed_cids` 范围内或未通过认证而被关闭的连接总数
+    pub fn rejected_connections(&self) -> u64 {
+        self.shared.as_ref().map_or(0, |shared| shared.rejected.load(Ordering::Relaxed))
+    }
+
+    /// 因传输协议初始化失败或超过 `handshake_timeout` 而被关闭的连接总数，这些连接不会交付给 `accept()`
+    pub fn failed_handshakes(&self) -> u64 {
+        self.shared.as_ref().map_or(0, |shared| shared.handshake_failures.load(Ordering::Relaxed))
+    }
+
+    /// 已登记且仍连接的句柄数，见 `ServerConfig::with_connection_tracking`
+    pub fn tracked_connections(&self) -> usize {
+        self.shared.as_ref().map_or(0, |shared| shared.registry.live().len())
+    }
+
+    /// 向所有已登记的连接发送同一条消息，返回按连接序号记录的逐连接结果
+    ///
+    /// 只有启用 `with_connection_tracking` 且经 `VirgeServer::handle` 转换的连接参与广播，未启用时返回空结果。
+    /// 句柄已全部释放或已断开的连接在发送前移除，不出现在结果中；本次发送后断开的连接同样被移除。
+    /// 各连接的发送并发进行，与该连接句柄上的其他发送按消息粒度串行化；xtransport 的阻塞 IO 使发送依次完成。
+    pub async fn broadcast(&self, data: &[u8]) -> BroadcastReport {
+        let Some(shared) = &self.shared else {
+            return BroadcastReport::default();
+        };
+        let handles = shared.registry.live();
+        let sends = handles.iter().map(|handle| async move { (handle.id(), handle.send(data.to_vec()).await) });
+        let results = futures::future::join_all(sends).await;
+        for handle in handles.iter().filter(|handle| !handle.is_connected()) {
+            debug!("Broadcast: connection #{} disconnected, removed from registry", handle.id());
+            shared.registry.remove(handle.id());
+        }
+        BroadcastReport { results: results.into_iter().collect() }
+    }
+
+    /// 汇总所有活跃连接的统计，已断开或释放的连接不计入
+    pub fn aggregate_stats(&self) -> Stats {
+        let mut total = Stats::default();
+        if let Some(shared) = &self.shared {
+            let connections = shared.connections.lock().unwrap_or_else(|e| e.into_inner());
+            for entry in connections.values() {
+                total.merge(&entry.stats.snapshot());
+            }
+        }
+        total
+    }
+
+    async fn create_listener(&self, port: u32) -> Result<Listener> {
+        // vsock 的端口 0 是一个普通端口号，由系统分配须使用 VMADDR_PORT_ANY；tcp 两者都映射为端口 0
+        let port = if is_any_port(port) { crate::VMADDR_PORT_ANY as u32 } else { port };
+        #[cfg(feature = "use-uds")]
+        if let Some(path) = &self.config.transport_options.uds_path {
+            let path = crate::transport::uds_impl::listen_path(path, port);
+            remove_stale_socket(&path)?;
+            let listener = tokio::net::UnixListener::bind(&path)
+                .map_err(|e| self.bind_error(port, format!("Failed to bind uds listener {}", path.display()), e))?;
+            let addr = VsockAddr::new(self.config.listen_cid, port);
+            return Ok(Listener::Uds(listener, addr));
+        }
+
+        match self.config.transport_kind {
+            #[cfg(feature = "use-yamux")]
+            TransportKind::Yamux => {
+                let listener = self.bind_tokio_vsock(port)
+                    .map_err(|e| self.bind_error(port, "Failed to bind yamux listener", e))?;
+                Ok(Listener::Yamux(listener))
+            }
+
+            #[cfg(feature = "use-xtransport")]
+            TransportKind::XTransport => {
+                let listener = if self.config.reuse_addr {
+                    bind_vsock(libc::SOCK_STREAM, self.config.listen_cid, port, true, false)
+                        // SAFETY: 描述符为已处于监听状态的 vsock 套接字，所有权转移给监听器
+                        .map(|fd| unsafe { vsock::VsockListener::from_raw_fd(fd.into_raw_fd()) })
+                } else {
+                    let addr = vsock::VsockAddr::new(self.config.listen_cid, port);
+                    vsock::VsockListener::bind(&addr)
+                };
+                let listener = listener.map_err(|e| self.bind_error(port, "Failed to bind xtransport listener", e))?;
+                Ok(Listener::XTransport(listener))
+            }
+
+            #[cfg(feature = "use-raw")]
+            TransportKind::Raw if self.config.transport_options.seqpacket => {
+                Ok(Listener::Seqpacket(self.bind_seqpacket(port)?))
+            }
+
+            #[cfg(feature = "use-raw")]
+            TransportKind::Raw => {
+                let listener = self.bind_tokio_vsock(port)
+                    .map_err(|e| self.bind_error(port, "Failed to bind raw listener", e))?;
+                Ok(Listener::Raw(listener))
+            }
+
+            #[cfg(feature = "use-tcp")]
+            TransportKind::Tcp => {
+                // cid 被忽略，端口映射为本地回环地址上的同号端口
+                let addr = crate::transport::tcp_impl::local_addr(port)?;
+                let listener = tokio::net::TcpListener::bind(addr)
+                    .await
+                    .map_err(|e| self.bind_error(port, "Failed to bind tcp listener", e))?;
+                Ok(Listener::Tcp(listener))
+            }
+        }
+    }
+
+    /// 绑定 yamux/raw 共用的 tokio-vsock 监听器
+    #[cfg(any(feature = "use-yamux", feature = "use-raw"))]
+    fn bind_tokio_vsock(&self, port: u32) -> std::io::Result<tokio_vsock::VsockListener> {
+        if self.config.reuse_addr {
+            let fd = bind_vsock(libc::SOCK_STREAM, self.config.listen_cid, port, true, true)?;
+            // SAFETY: 描述符为已处于监听状态的非阻塞 vsock 套接字，所有权转移给监听器
+            return Ok(unsafe { tokio_vsock::VsockListener::from_raw_fd(fd.into_raw_fd()) });
+        }
+        let addr = tokio_vsock::VsockAddr::new(self.config.listen_cid, port);
+        tokio_vsock::VsockListener::bind(addr)
+    }
+
+    /// 绑定 SOCK_SEQPACKET 监听器，内核或 vsock 传输不支持时返回 `ConfigError`
+    #[cfg(feature = "use-raw")]
+    fn bind_seqpacket(&self, port: u32) -> Result<AsyncFd<OwnedFd>> {
+        let bind = bind_vsock(libc::SOCK_SEQPACKET, self.config.listen_cid, port, self.config.reuse_addr, true)
+            .and_then(AsyncFd::new);
+        bind.map_err(|e| {
+            if sys::seqpacket_unsupported(&e) {
+                seqpacket_impl::unsupported_error()
+            } else {
+                self.bind_error(port, "Failed to bind seqpacket listener", e)
+            }
+        })
+    }
+
+    /// 将绑定失败的 IO 错误转换为 `VirgeError`，端口被占用时返回 `PortInUse`
+    fn bind_error(&self, port: u32, message: impl Into<String>, e: std::io::Error) -> VirgeError {
+        if e.kind() == std::io::ErrorKind::AddrInUse {
+            return VirgeError::PortInUse { cid: self.config.listen_cid, port };
+        }
+        VirgeError::connection_io(message, e)
+    }
+
+    /// 接受一个新连接，阻塞直到有客户端连接并完成传输协议初始化
+    ///
+    /// 握手在独立的工作任务中进行，迟迟不完成握手的对端不会阻塞其他连接；握手失败或超过
+    /// `handshake_timeout` 的连接被记录并关闭，计入 `failed_handshakes()`，其错误由此返回，之后可继续接受。
+    pub async fn accept(&mut self) -> Result<VirgeServer> {
+        self.accept_within(None, NO_OVERRIDE).await?
+            .ok_or_else(|| VirgeError::Other("No pending connection".to_string()))
+    }
+
+    /// 接受一个新连接，并根据对端地址为该连接选择配置
+    ///
+    /// `override_fn` 在传输协议初始化之前调用，返回的配置决定该连接的 chunk_size、ACK、
+    /// 握手、心跳与消息大小上限；监听地址、`max_connections`、`idle_timeout` 与 `handshake_timeout`
+    /// 仍以管理器配置为准。返回配置的传输协议与监听器不一致或参数非法时返回 `ConfigError`，该连接被关闭。
+    ///
+    /// 握手所用的配置取决于对端，因此该连接在当前任务中初始化（以 `handshake_timeout` 为限），失败时
+    /// 返回错误；已由 `accept()` 在后台完成握手的连接不会由此返回。
+    pub async fn accept_with(
+        &mut self,
+        override_fn: impl FnOnce(&VsockAddr) -> ServerConfig,
+    ) -> Result<VirgeServer> {
+        self.accept_within(None, Some(|addr: &VsockAddr, _: u32| override_fn(addr))).await?
+            .ok_or_else(|| VirgeError::Other("No pending connection".to_string()))
+    }
+
+    /// 与 `accept_with` 相同，`override_fn` 额外收到连接到达的本地端口，可按端口选择配置
+    pub async fn accept_with_port(
+        &mut self,
+        override_fn: impl FnOnce(&VsockAddr, u32) -> ServerConfig,
+    ) -> Result<VirgeServer> {
+        self.accept_within(None, Some(override_fn)).await?
+            .ok_or_else(|| VirgeError::Other("No pending connection".to_string()))
+    }
+
+    /// 在限定时间内接受一个新连接
+    ///
+    /// 超时返回 `VirgeError::Timeout`，监听器保持可用，可再次调用；超时时仍在握手的连接继续在后台进行，
+    /// 完成后由之后的 accept 交付。
+    pub async fn accept_timeout(&mut self, timeout: Duration) -> Result<VirgeServer> {
+        self.accept_within(Some(timeout), NO_OVERRIDE).await?
+            .ok_or_else(|| VirgeError::Timeout("ServerManager accept timed out".to_string()))
+    }
+
+    /// 使用管理器配置主动连接 `cid:port` 上监听的对端，例如宿主机连接来宾机内的服务
+    ///
+    /// 无需先调用 start()，同一管理器可以同时接受与发起连接。传输协议、chunk_size、ACK、握手、
+    /// 心跳、包装器与 `idle_timeout` 都取自管理器配置，本端在握手中充当客户端角色，对端应为
+    /// `ServerManager` 监听器。返回的连接与 `accept()` 得到的连接用法相同：分配连接序号、计入
+    /// `active_connections()` 与统计，并先后投递 `ServerEvent::Dialed` 与 `Disconnected`；
+    /// `peer_addr()` 为 `cid:port`，`local_port()` 为内核分配的本地端口。tcp 传输忽略 `cid`，
+    /// 连接 `127.0.0.1:port`；连接失败时返回错误，断开原因为 `HandshakeFailed`。
+    pub async fn dial(&mut self, cid: u32, port: u32) -> Result<VirgeServer> {
+        if cid == crate::VMADDR_CID_ANY as u32 {
+            return Err(VirgeError::ConfigError("dial cid cannot be VMADDR_CID_ANY".to_string()));
+        }
+        if port == crate::VMADDR_PORT_ANY as u32 {
+            return Err(VirgeError::ConfigError("dial port cannot be VMADDR_PORT_ANY".to_string()));
+        }
+        self.config.validate()?;
+        let shared = self.ensure_shared()?;
+        self.arm_idle_reaper(&shared);
+
+        let peer_addr = VsockAddr::new(cid, port);
+        let conn_id = shared.next_conn_id();
+        info!("ServerManager dialing cid={}, port={} as connection #{}", cid, port, conn_id);
+        shared.emit(ServerEvent::Dialed { peer: peer_addr, conn_id });
+
+        let config = &self.config;
+        let stats = Arc::new(StatsCounters::default());
+        let transport = config.transport_kind.create(false, config.is_ack, &config.transport_options);
+        let limits = &config.transport_options.rate_limit;
+        let transport = RateLimitTransport::wrap(transport, limits, &stats, Some(rate_limit_hook(&shared, conn_id)));
+        let mut transport: Box<dyn Transport> = Box::new(
+            StatsTransport::new(transport, stats.clone())
+                .with_seqpacket(config.transport_options.uses_seqpacket()),
+        );
+        let connected = match transport.connect(cid, port, config.chunk_size, config.is_ack).await {
+            Ok(()) => crate::transport::apply_send_buffer_limit(transport.as_ref(), &config.transport_options)
+                .and_then(|()| crate::transport::apply_socket_buffer(transport.as_ref(), &config.transport_options)),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = connected {
+            shared.emit(ServerEvent::Disconnected {
+                conn_id,
+                reason: DisconnectReason::HandshakeFailed(e.to_string()),
+            });
+            return Err(e);
+        }
+
+        let local_port = transport.local_addr().map_or(0, |addr| addr.port());
+        let socket = idle_socket(config.idle_timeout, transport.as_ref(), conn_id);
+        Ok(VirgeServer {
+            transport,
+            connected: true,
+            peer_addr,
+            local_port,
+            id: conn_id,
+            guard: Some(ConnectionGuard::new(shared, stats.clone(), conn_id, socket, config.track_connections)),
+            chunk_size: config.chunk_size,
+            max_message_size: config.transport_options.max_message_size,
+            nonblocking_send: config.transport_options.nonblocking_send,
+            read_timeout: None,
+            write_timeout: None,
+            read_buffer: Vec::new(),
+            reassembly_limit: config.reassembly_limit(),
+            discard: framing::Remaining::default(),
+            stats,
+            write_shutdown: false,
+            peer_eof: false,
+            peer_identity: None,
+            #[cfg(feature = "serde")]
+            wire_format: config.wire_format,
+        })
+    }
+
+    /// 以异步流的形式逐个接受连接，对应 `TcpListener::incoming`
+    ///
+    /// 只产出完成传输协议初始化的连接；握手失败（计入 `failed_handshakes`）与内核 accept 失败产出 `Some(Err(_))`，流继续可用；
+    /// 通过 `StopHandle` 停止服务器后不再从内核接受连接，已接受的连接（包括仍在握手的连接）依次产出后流结束，
+    /// 产出 `None`；服务器未运行时流直接结束。
+    /// 返回的流未实现 `Unpin`，迭代前需先固定，如 `std::pin::pin!(manager.incoming())`。
+    ///
+    /// 流只在被轮询时才从监听器接受下一个连接，自身不缓存连接：消费方处理不及时（如
+    /// `for_each_concurrent` 达到并发上限）时新连接留在内核队列中，已接受的连接都会被产出。
+    /// 启用 `with_accept_queue` 时由后台任务接受，缓存数以队列容量为上限，停止时队列中的连接同样先产出。
+    /// xtransport 的 accept 会阻塞当前线程，连接应交给独立任务处理，见 `example/concurrent_server`。
+    pub fn incoming(&mut self) -> impl futures::Stream<Item = Result<VirgeServer>> + '_ {
+        futures::stream::unfold(self, |manager| async move {
+            if !manager.running {
+                return None;
+            }
+            match manager.accept().await {
+                Ok(server) => Some((Ok(server), manager)),
+                Err(_) if manager.shared.as_ref().is_some_and(|shared| shared.is_stopped()) => None,
+                Err(e) => Some((Err(e), manager)),
+            }
+        })
+    }
+
+    /// 非阻塞接受连接，当前没有完成握手的连接时返回 `Ok(None)`
+    ///
+    /// 新到达的连接先在后台握手，完成后才由之后的调用交付，因此监听套接字可读后的第一次调用可能返回 `Ok(None)`。
+    pub async fn try_accept(&mut self) -> Result<Option<VirgeServer>> {
+        self.accept_within(Some(Duration::ZERO), NO_OVERRIDE).await
+    }
+
+    /// 等待至多 `wait` 时间接受连接（`None` 表示一直等待），超时返回 `Ok(None)`
+    ///
+    /// `select` 为 `Some` 时以其返回的配置在当前任务中初始化下一个连接，否则交付在后台完成握手的连接。
+    async fn accept_within(
+        &mut self,
+        wait: Option<Duration>,
+        select: Option<impl FnOnce(&VsockAddr, u32) -> ServerConfig>,
+    ) -> Result<Option<VirgeServer>> {
+        if !self.running {
+            return Err(VirgeError::Other(
+                "ServerManager not running".to_string(),
+            ));
+        }
+
+        let result = self.accept_listener(wait, select).await;
+
+        // 通过 StopHandle 停止后，已接受的连接全部交付时管理器才停止运行
+        if self.shared.as_ref().is_some_and(|shared| shared.is_stopped())
+            && self.queue.as_ref().is_none_or(|queue| queue.is_drained())
+        {
+            self.running = false;
+        }
+        result
+    }
+
+    async fn accept_listener(
+        &mut self,
+        wait: Option<Duration>,
+        select: Option<impl FnOnce(&VsockAddr, u32) -> ServerConfig>,
+    ) -> Result<Option<VirgeServer>> {
+        let shared = self.shared.clone()
+            .ok_or_else(|| VirgeError::Other("Listener not initialized".to_string()))?;
+        let queue = self.queue.clone()
+            .ok_or_else(|| VirgeError::Other("Listener not initialized".to_string()))?;
+        let with_ready = select.is_none();
+
+        // 内核 accept 得到的连接交给工作任务握手，直到有连接完成握手或等待超时
+        let deadline = wait.map(|wait| Instant::now() + wait);
+        loop {
+            // 通过 StopHandle 停止后不再从内核接受，只交付已接受的连接，取完后返回停止错误；
+            // 后台接受任务全部退出后由其关闭队列
+            if shared.is_stopped() && self.listener.take().is_some() {
+                queue.close();
+            }
+            let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            let next = match self.listener {
+                None => queue.pop(remaining, with_ready).await?,
+                Some(ref mut listener) => {
+                    if let Some(server) = with_ready.then(|| queue.take_ready()).flatten() {
+                        return server.map(Some);
+                    }
+                    let local_port = listener.local_addr().map_or(self.config.listen_port, |addr| addr.port());
+                    let accept = accept_allowed(listener, &shared, &self.config, remaining, Some(&queue));
+                    #[cfg(feature = "tokio-runtime")]
+                    let accepted = tokio::select! {
+                        accepted = accept => accepted,
+                        _ = queue.ready_notified(), if with_ready => Ok(None),
+                    };
+                    #[cfg(not(feature = "tokio-runtime"))]
+                    let accepted = accept.await;
+                    if accepted.is_err() && shared.is_stopped() {
+                        continue;
+                    }
+                    accepted.inspect_err(|e| {
+                        if !shared.is_stopped() {
+                            shared.emit(ServerEvent::AcceptError { error: e.to_string() });
+                        }
+                    })?
+                    .map(|(stream, addr)| Queued::Accepted((stream, addr, local_port)))
+                }
+            };
+            let (stream, peer_addr, local_port) = match next {
+                Some(Queued::Ready(server)) => return (*server).map(Some),
+                Some(Queued::Accepted(accepted)) => accepted,
+                None if deadline.is_some_and(|deadline| Instant::now() >= deadline) => return Ok(None),
+                None => continue,
+            };
+
+            let conn_id = shared.next_conn_id();
+            shared.emit(ServerEvent::Accepted { peer: peer_addr, conn_id });
+            let handshake = Handshake { stream, peer_addr, local_port, conn_id };
+            if let Some(select) = select {
+                let config = select(&peer_addr, local_port);
+                return handshake.complete(&config, &self.config, true, shared).await.map(Some);
+            }
+            spawn_handshake(handshake, self.config.clone(), shared.clone(), queue.clone());
+        }
+    }
+
+    /// 运行内部 accept 循环，将每个连接分发给 `handler` 并发处理
+    ///
+    /// 启用 yamux 或 raw 时每个连接运行在独立的 tokio 任务中，否则运行在独立线程中。
+    /// 超过 `max_connections` 的连接会被记录警告后关闭；调用 stop() 后返回 `Ok(())`。
+    pub async fn serve<F, Fut>(&mut self, handler: F) -> Result<()>
+    where
+        F: Fn(VirgeServer) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+
+        loop {
+            let mut server = match self.accept().await {
+                Ok(server) => server,
+                Err(_) if self.shared.as_ref().is_some_and(|shared| shared.is_stopped()) => {
+                    info!("ServerManager serve loop stopped");
+                    return Ok(());
+                }
+                Err(e) if self.running => {
+                    warn!("ServerManager failed to accept connection: {}", e);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            if let Some(max) = self.config.max_connections {
+                let active = self.active_connections();
+                if active > max {
+                    warn!(
+                        "ServerManager rejecting connection from {:?}: {} active connections exceed limit {}",
+                        server.peer_addr(), active - 1, max
+                    );
+                    if let Some(guard) = &mut server.guard {
+                        guard.reason = Some(DisconnectReason::Rejected);
+                    }
+                    if let Err(e) = server.disconnect().await {
+                        debug!("Failed to close rejected connection: {}", e);
+                    }
+                    continue;
+                }
+            }
+
+            let handler = handler.clone();
+            #[cfg(feature = "tokio-runtime")]
+            tokio::spawn(async move { handler(server).await });
+            #[cfg(not(feature = "tokio-runtime"))]
+            std::thread::spawn(move || futures::executor::block_on(handler(server)));
+        }
+    }
+
+    /// 停止服务器：关闭监听套接字，并唤醒通过 StopHandle 共享的阻塞 accept
+    pub async fn stop(&mut self) -> Result<()> {
+        info!("ServerManager stopping");
+        if let Some(shared) = &self.shared {
+            shared.trigger_stop();
+        }
+        self.stop_acceptor().await;
+        self.listener = None;
+        self.running = false;
+        Ok(())
+    }
+
+    /// 停止服务器并等待已接受的连接全部断开
+    ///
+    /// 超时仍有连接未断开时返回 `VirgeError::Timeout`，服务器保持停止状态。
+    pub async fn stop_and_drain(&mut self, timeout: Duration) -> Result<()> {
+        self.stop().await?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let active = self.active_connections();
+            if active == 0 {
+                info!("ServerManager drained all connections");
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(VirgeError::Timeout(format!(
+                    "{} connections still active after drain timeout", active
+                )));
+            }
+            crate::transport::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// 获取监听的本地地址，`start()` 返回后即可调用，由系统分配端口时用于获知实际端口
+    pub fn local_addr(&self) -> Result<VsockAddr> {
+        match &self.listener {
+            Some(listener) => listener.local_addr(),
+            None => self.queued_addr
+                .ok_or_else(|| VirgeError::Other("Listener not initialized".to_string())),
+        }
+    }
+
+    /// 所有监听器的本地地址，第一个为 `listen_port` 对应的监听器，未启动时为空
+    pub fn local_addrs(&self) -> Vec<VsockAddr> {
+        match &self.listener {
+            Some(listener) => listener.local_addr().into_iter().collect(),
+            None => self.listening_addrs.clone(),
+        }
+    }
+
+}
+
+impl VirgeServer {
+    /// 创建经由进程内内存传输直接相连的服务器与客户端，无需任何套接字
+    ///
+    /// 客户端使用与 `config` 相同的 chunk_size、ACK、包装器与消息上限，收发经过与真实连接相同的
+    /// 分帧、ACK、心跳、校验与压缩代码；监听地址、`max_connections` 与传输协议被忽略，不执行握手。
+    #[cfg(feature = "testing")]
+    pub async fn new_in_memory(config: ServerConfig) -> Result<(VirgeServer, VirgeClient)> {
+        config.validate()?;
+        memory_impl::check_options(&config.transport_options)?;
+        let (server, client) = memory_impl::pair(config.chunk_size, config.is_ack, &config.transport_options);
+        let client = VirgeClient::in_memory(
+            client,
+            config.chunk_size,
+            config.is_ack,
+            config.transport_options.clone(),
+            #[cfg(feature = "serde")]
+            config.wire_format,
+        ).await?;
+        let server = Self::in_memory(
+            server,
+            config.chunk_size,
+            config.is_ack,
+            &config.transport_options,
+            #[cfg(feature = "serde")]
+            config.wire_format,
+        ).await?;
+        Ok((server, client))
+    }
+
+    /// 以录制的流量代替客户端创建服务器端连接，用于离线复现问题
+    ///
+    /// `replay` 按录制顺序交付当时收到的帧，其上按 `config` 叠加包装器，包装器组合须与录制时一致，
+    /// 否则返回 `VirgeError::ConfigError`；监听端口与传输协议被忽略，不执行握手。
+    #[cfg(feature = "testing")]
+    pub async fn replay(config: ServerConfig, replay: ReplayTransport) -> Result<VirgeServer> {
+        config.validate()?;
+        let transport = replay.wrap(&config.transport_options)?;
+        Self::in_memory(
+            transport,
+            config.chunk_size,
+            config.is_ack,
+            &config.transport_options,
+            #[cfg(feature = "serde")]
+            config.wire_format,
+        ).await
+    }
+
+    /// 创建并连接内存传输的服务器一端，由 `new_in_memory` 调用
+    #[cfg(feature = "testing")]
+    pub(crate) async fn in_memory(
+        transport: Box<dyn Transport>,
+        chunk_size: u32,
+        is_ack: bool,
+        transport_options: &TransportOptions,
+        #[cfg(feature = "serde")] wire_format: WireFormat,
+    ) -> Result<Self> {
+        let stats = Arc::new(StatsCounters::default());
+        let transport = RateLimitTransport::wrap(transport, &transport_options.rate_limit, &stats, None);
+        let mut transport: Box<dyn Transport> = Box::new(StatsTransport::new(transport, stats.clone()));
+        transport.connect(crate::VMADDR_CID_LOCAL as u32, 0, chunk_size, is_ack).await?;
+        Ok(Self {
+            transport,
+            connected: true,
+            peer_addr: VsockAddr::new(crate::VMADDR_CID_LOCAL as u32, 0),
+            local_port: 0,
+            id: 0,
+            guard: None,
+            chunk_size,
+            max_message_size: transport_options.max_message_size,
+            nonblocking_send: transport_options.nonblocking_send,
+            read_timeout: None,
+            write_timeout: None,
+            read_buffer: Vec::new(),
+            reassembly_limit: transport_options.max_message_size,
+            discard: framing::Remaining::default(),
+            stats,
+            write_shutdown: false,
+            peer_eof: false,
+            peer_identity: None,
+            #[cfg(feature = "serde")]
+            wire_format,
+        })
+    }
+
+    /// 发送数据，超过 `max_message_size` 时在本地返回 `VirgeError::MessageTooLarge`
+    ///
+    /// 启用 ACK 时等待对端确认送达后返回，写超时内未确认返回 `VirgeError::Timeout`。
+    /// 发送缓冲区已满时的行为与 `VirgeClient::send` 相同，见 `ServerConfig::with_nonblocking_send`。
+    pub async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        framing::check_size(data.len(), self.max_message_size)?;
+        self.send_frame(data, true).await
+    }
+
+    /// 将已校验大小的数据交给传输层发送
+    async fn send_frame(&mut self, data: Vec<u8>, ack: bool) -> Result<()> {
+        self.check_writable()?;
+        if self.nonblocking_send {
+            crate::transport::send_nonblocking(self.transport.as_mut(), &data).await
+        } else if ack {
+            self.transport.send(data).await
+        } else {
+            self.transport.send_noack(data).await
+        }
+    }
+
+    /// 发送数据且不等待对端确认，未启用 ACK 时与 `send` 相同
+    ///
+    /// xtransport 的 ACK 按连接生效，无法逐条跳过，此时同样等待确认。
+    pub async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        framing::check_size(data.len(), self.max_message_size)?;
+        self.send_frame(data, false).await
+    }
+
+    /// 最近一次 `send` 从发出到收到对端确认的耗时，未启用 ACK 时为 `None`
+    pub fn last_ack_latency(&self) -> Option<Duration> {
+        self.transport.last_ack_latency()
+    }
+
+    /// 最近 128 次 ACK 往返耗时的统计，未启用 ACK 或尚无样本时为 `None`
+    pub fn ack_stats(&self) -> Option<AckStats> {
+        self.transport.ack_stats()
+    }
+
+    /// 当前连接上最近一条发送成功的消息的序号，尚未发送时为 0，未启用 `with_sequence_numbers` 时为 `None`
+    pub fn last_sent_seq(&self) -> Option<u64> {
+        self.transport.last_sent_seq()
+    }
+
+    /// 当前连接上最近收到的消息的序号，尚未收到时为 0，未启用 `with_sequence_numbers` 时为 `None`
+    ///
+    /// 客户端的发送序号在重连之间延续，新连接上收到的第一个序号可能大于 1，应用可据此判断是否有消息丢失。
+    pub fn last_received_seq(&self) -> Option<u64> {
+        self.transport.last_received_seq()
+    }
+
+    /// 接收数据
+    ///
+    /// 客户端调用 `shutdown_write` 后返回空消息，表示不会再收到请求，本端仍可发送响应。
+    pub async fn recv(&mut self) -> Result<Vec<u8>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        // 优先返回 recv_msg 预读但未消费的数据
+        if !self.read_buffer.is_empty() {
+            return Ok(std::mem::take(&mut self.read_buffer));
+        }
+        if self.peer_eof {
+            return Ok(Vec::new());
+        }
+        let result = self.transport.recv().await;
+        self.end_of_stream(result, Vec::new())
+    }
+
+    /// 接收数据，`token` 在其他线程或任务中被取消时及时返回 `ErrorKind::Interrupted`
+    ///
+    /// 取消不会中断进行到一半的读取，连接保持可用，可继续接收；不参与自动重连，也不受读超时约束。
+    pub async fn recv_cancellable(&mut self, token: &CancelToken) -> Result<Vec<u8>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        if !self.read_buffer.is_empty() {
+            return Ok(std::mem::take(&mut self.read_buffer));
+        }
+        if self.peer_eof {
+            return Ok(Vec::new());
+        }
+        let result = cancel::recv(self.transport.as_mut(), token).await;
+        self.end_of_stream(result, Vec::new())
+    }
+
+    /// 等待已提交的数据全部推送到线路上：冲刷传输层的发送缓冲（yamux 虚拟流、vsock 流），
+    /// 启用 ACK 时再等待全部已发出消息的确认
+    ///
+    /// 受写超时约束，传输层的错误原样返回。
+    pub async fn flush(&mut self) -> Result<()> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        self.transport.flush().await
+    }
+
+    /// 与 `flush` 相同，但写入与等待确认的每一步最长等待 `timeout`，超时返回 `VirgeError::Timeout`
+    ///
+    /// 期间临时替换 `set_write_timeout` 设置的写超时，返回前恢复。
+    pub async fn flush_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.transport.set_write_timeout(Some(timeout))?;
+        let result = self.flush().await;
+        self.transport.set_write_timeout(self.write_timeout)?;
+        result
+    }
+
+    /// 关闭写方向（半关闭）：通知客户端不会再发送数据，之后仍可接收
+    ///
+    /// 客户端的 `recv()` 随后返回空消息、`read()` 返回 `Ok(0)`，之后的发送返回 `Disconnected`。
+    /// xtransport 传输不支持。
+    pub async fn shutdown_write(&mut self) -> Result<()> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        if self.write_shutdown {
+            return Ok(());
+        }
+        info!("VirgeServer for {:?} shutting down write side", self.peer_addr);
+        self.transport.shutdown_write().await?;
+        self.write_shutdown = true;
+        Ok(())
+    }
+
+    /// 检查本端仍可发送数据
+    fn check_writable(&self) -> Result<()> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        if self.write_shutdown {
+            return Err(VirgeError::Disconnected(
+                "Server write side has been shut down".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// 将对端的半关闭通知转换为流结束的返回值 `eof`，并记住该状态
+    fn end_of_stream<T>(&mut self, result: Result<T>, eof: T) -> Result<T> {
+        match result {
+            Err(VirgeError::EndOfStream) => {
+                if self.transport.is_connected() {
+                    info!("VirgeServer peer {:?} shut down its write side", self.peer_addr);
+                } else {
+                    info!("VirgeServer peer {:?} closed the connection", self.peer_addr);
+                }
+                self.peer_eof = true;
+                Ok(eof)
+            }
+            result => result,
+        }
+    }
+
+    /// 将多个缓冲区作为一条消息发送，避免调用方先行拼接
+    ///
+    /// # Returns
+    /// 返回发送的总字节数
+    pub async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        self.check_writable()?;
+        let total = slices.iter().map(|slice| slice.len()).sum();
+        framing::check_size(total, self.max_message_size)?;
+        self.transport.send_slices(slices).await
+    }
+
+    /// 以字节流方式读取数据，返回读取的字节数
+    ///
+    /// 与按消息接收的 `recv()`/`recv_msg()` 不同，消息边界不会表现为 EOF：
+    /// 缓冲区为空时继续从传输层接收，仅在对端关闭连接或关闭写方向时返回 `Ok(0)`，
+    /// 可在其上实现 `read_exact`、`read_to_end` 等字节流语义。
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        if self.peer_eof && self.read_buffer.is_empty() {
+            return Ok(0);
+        }
+        let result = framing::read(self.transport.as_mut(), &mut self.read_buffer, buf).await;
+        self.end_of_stream(result, 0)
+    }
+
+    /// 返回内部缓冲区中尚未读取的数据，缓冲区为空时先从传输层接收，与 `BufRead::fill_buf` 对应
+    ///
+    /// 返回的数据需通过 `consume` 标记为已读；对端关闭连接或关闭写方向且数据已读完时返回空切片。
+    pub async fn fill_buf(&mut self) -> Result<&[u8]> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        if !self.peer_eof {
+            let result = framing::fill(self.transport.as_mut(), &mut self.read_buffer).await;
+            self.end_of_stream(result, false)?;
+        }
+        Ok(&self.read_buffer)
+    }
+
+    /// 将 `fill_buf` 返回的前 `amt` 字节标记为已读
+    pub fn consume(&mut self, amt: usize) {
+        let amt = amt.min(self.read_buffer.len());
+        self.read_buffer.drain(..amt);
+    }
+
+    /// 内部缓冲区中尚未读取的数据，不从传输层接收
+    #[cfg(feature = "tokio-runtime")]
+    pub(crate) fn buffered(&self) -> &[u8] {
+        &self.read_buffer
+    }
+
+    /// 将下一条消息开头的数据复制到 `buf` 而不消费，返回复制的字节数
+    ///
+    /// 缓冲区为空时先从传输层接收一条消息；至多返回该条消息剩余的数据，不会跨越到下一条消息。
+    /// 之后的 `read`/`recv` 仍会返回这些数据。流结束时返回 `Ok(0)`。
+    pub async fn peek(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let data = self.fill_buf().await?;
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    /// 非阻塞查看下一次 `recv` 将返回的字节数而不消费，当前没有可读数据时返回 `Ok(None)`
+    pub async fn peek_msg_len(&mut self) -> Result<Option<usize>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        if !self.read_buffer.is_empty() || self.peer_eof {
+            return Ok(Some(self.read_buffer.len()));
+        }
+        let result = self.transport.try_recv().await;
+        match self.end_of_stream(result, Some(Vec::new()))? {
+            Some(data) => {
+                self.read_buffer = data;
+                Ok(Some(self.read_buffer.len()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// 跨消息边界读取直到 `delim`（包含）或流结束，追加到 `buf`，返回读取的字节数
+    ///
+    /// 流结束时返回 `Ok(0)`；出错时已读取的数据保留在 `buf` 中。
+    pub async fn read_until(&mut self, delim: u8, buf: &mut Vec<u8>) -> Result<usize> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        let start = buf.len();
+        if self.peer_eof {
+            framing::take_until(&mut self.read_buffer, delim, buf);
+        } else {
+            let result = framing::read_until(self.transport.as_mut(), &mut self.read_buffer, delim, buf).await;
+            self.end_of_stream(result, ())?;
+        }
+        Ok(buf.len() - start)
+    }
+
+    /// 读取一行（包含结尾的 `\n`）追加到 `line`，返回读取的字节数，流结束时返回 `Ok(0)`
+    ///
+    /// 一行可以跨越任意多条消息，消息边界不会截断行；数据不是合法 UTF-8 时返回
+    /// `VirgeError::CodecError`，`line` 保持不变。
+    pub async fn read_line(&mut self, line: &mut String) -> Result<usize> {
+        let mut bytes = Vec::new();
+        let n = self.read_until(b'\n', &mut bytes).await?;
+        framing::push_line(line, bytes)?;
+        Ok(n)
+    }
+
+    /// 读取下一行并去掉结尾的 `\n` 或 `\r\n`，流结束时返回 `Ok(None)`，可用于逐行遍历
+    pub async fn next_line(&mut self) -> Result<Option<String>> {
+        let mut line = String::new();
+        if self.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        framing::trim_newline(&mut line);
+        Ok(Some(line))
+    }
+
+    /// 以字节流方式写入数据，与 `read()` 配合使用
+    ///
+    /// 缓冲区按 `chunk_size` 分为多条消息依次提交给传输层，成功时返回值恒等于 `buf.len()`，因此无需循环调用。
+    /// 中途失败时返回 `VirgeError::PartialWrite`，`committed` 为此前已完整发出（启用 ACK 时已被确认）的字节数，
+    /// 对端按序收到的恰好是这些字节；尚未发出任何字节时返回原错误。
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let mut committed = 0;
+        for chunk in buf.chunks(self.chunk_size as usize) {
+            if let Err(e) = self.send(chunk.to_vec()).await {
+                return Err(VirgeError::partial_write(committed, e));
+            }
+            committed += chunk.len();
+        }
+        Ok(committed)
+    }
+
+    /// 将多个缓冲区作为一条消息写入，与 `Write::write_vectored` 对应，对端的一次 `recv()` 即收到全部数据
+    ///
+    /// 不拼接缓冲区，直接经 `send_slices` 发送；成功时返回各缓冲区的总字节数。
+    pub async fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Ok(0);
+        }
+        self.send_slices(bufs).await
+    }
+
+    /// 接收数据到调用方提供的缓冲区，缓冲区仅在容量不足时增长
+    ///
+    /// # Returns
+    /// 返回接收到的字节数
+    pub async fn recv_into(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        if !self.read_buffer.is_empty() {
+            buf.clear();
+            buf.append(&mut self.read_buffer);
+            return Ok(buf.len());
+        }
+        self.transport.recv_into(buf).await
+    }
+
+    /// 发送一条带 8 字节大端长度前缀的消息
+    pub async fn send_msg(&mut self, data: &[u8]) -> Result<()> {
+        let frame = framing::encode(data, self.max_message_size)?;
+        self.send_frame(frame, true).await
+    }
+
+    /// 接收一条带长度前缀的消息
+    ///
+    /// 消息可跨越多次传输层接收；对端在消息中途断开时返回 `ErrorKind::UnexpectedEof`。
+    /// 长度前缀超过 `max_reassembly_bytes`（默认为 `max_message_size`）时在分配内存前返回
+    /// `MessageTooLarge`，该消息被丢弃，连接保持可用。
+    pub async fn recv_msg(&mut self) -> Result<Vec<u8>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        self.skip_discarded().await?;
+        let result = framing::recv(self.transport.as_mut(), &mut self.read_buffer, self.reassembly_limit).await;
+        self.abort_oversized(result).await
+    }
+
+    /// 接收一条带长度前缀的消息的前缀，返回按到达顺序逐块读出负载的读取器
+    ///
+    /// 负载不在内存中组装，不受 `max_reassembly_bytes` 限制，可用于接收 `send_file` 等发送的超大消息。
+    /// 读取器未读完即被释放时，剩余负载在下一次按消息接收时被丢弃，不会混入后续消息。
+    pub async fn recv_stream(&mut self) -> Result<MessageReader<'_>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        self.skip_discarded().await?;
+        self.discard = framing::recv_prefix(self.transport.as_mut(), &mut self.read_buffer).await?;
+        let len = if self.discard.chunked { u64::MAX } else { self.discard.bytes };
+        Ok(MessageReader {
+            transport: Some(self.transport.as_mut()),
+            buf: &mut self.read_buffer,
+            remaining: &mut self.discard,
+            len,
+            receiving: None,
+        })
+    }
+
+    /// 丢弃之前超限或未读完的消息剩余的负载
+    async fn skip_discarded(&mut self) -> Result<()> {
+        if self.discard.is_done() {
+            return Ok(());
+        }
+        framing::skip(self.transport.as_mut(), &mut self.read_buffer, &mut self.discard).await
+    }
+
+    /// 待组装的消息超过上限时移除其长度前缀并丢弃负载，原样返回 `MessageTooLarge`
+    ///
+    /// 丢弃中途出错（如读超时）时剩余的字节数被记录，由下一次按消息接收继续丢弃。
+    async fn abort_oversized<T>(&mut self, result: Result<T>) -> Result<T> {
+        if !matches!(result, Err(VirgeError::MessageTooLarge { .. })) {
+            return result;
+        }
+        if let Some(remaining) = framing::abort_oversized(&mut self.read_buffer, self.reassembly_limit)? {
+            if remaining.chunked {
+                warn!("VirgeServer discarding a chunked message over the reassembly limit {}", self.reassembly_limit);
+            } else {
+                warn!(
+                    "VirgeServer discarding a {} byte message over the reassembly limit {}",
+                    remaining.bytes, self.reassembly_limit
+                );
+            }
+            self.discard = remaining;
+            if let Err(e) = self.skip_discarded().await {
+                debug!("VirgeServer will finish discarding the oversized message later: {}", e);
+            }
+        }
+        result
+    }
+
+    /// 在 `timeout` 内接收一条带长度前缀的消息，超时时报告是否已收到部分数据
+    ///
+    /// 行为与 `VirgeClient::recv_msg_timeout` 相同，返回前恢复 `set_read_timeout` 设置的读超时。
+    pub async fn recv_msg_timeout(&mut self, timeout: Duration) -> Result<RecvOutcome> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        if self.peer_eof && self.read_buffer.is_empty() {
+            return Ok(RecvOutcome::Closed);
+        }
+        let started = Instant::now();
+        if !self.discard.is_done() {
+            if timeout.is_zero() {
+                return Ok(RecvOutcome::TimedOutIdle);
+            }
+            self.transport.set_read_timeout(Some(timeout))?;
+            let skipped = self.skip_discarded().await;
+            self.transport.set_read_timeout(self.read_timeout)?;
+            match skipped {
+                Err(e) if e.is_timeout() => return Ok(RecvOutcome::TimedOutIdle),
+                skipped => skipped?,
+            }
+        }
+        let timeout = timeout.saturating_sub(started.elapsed());
+        let result = RecvOutcome::receive(self.transport.as_mut(), &mut self.read_buffer, self.reassembly_limit, timeout).await;
+        self.transport.set_read_timeout(self.read_timeout)?;
+        if matches!(result, Ok(RecvOutcome::Closed)) {
+            self.peer_eof = true;
+        }
+        self.abort_oversized(result).await
+    }
+
+    /// 按连接的 chunk_size 分块发送一条带长度前缀的消息，每块发送后回调 `(已发送字节数, 总字节数)`
+    ///
+    /// 对端可使用 `recv_msg` 或 `recv_with_progress` 接收。回调 panic 时返回错误并断开连接，
+    /// 对端以 `UnexpectedEof` 结束接收，不会收到被截断却看似完整的消息。
+    pub async fn send_with_progress(&mut self, data: &[u8], callback: impl FnMut(u64, u64)) -> Result<()> {
+        self.check_writable()?;
+        framing::send_with_progress(
+            self.transport.as_mut(),
+            data,
+            self.chunk_size as usize,
+            self.max_message_size,
+            callback,
+        ).await
+    }
+
+    /// 从 `reader` 流式发送 `len` 字节，按 chunk_size 分块读取，不在内存中缓存整个负载
+    ///
+    /// 对端需使用 `recv_to_file` 接收；`reader` 提前结束时返回错误并断开连接。
+    pub async fn send_file<R: Read>(&mut self, reader: &mut R, len: u64) -> Result<u64> {
+        self.check_writable()?;
+        framing::send_stream(self.transport.as_mut(), reader, len, self.chunk_size as usize).await
+    }
+
+    /// 接收 `send_file` 发送的数据并逐块写入 `writer`，返回写入的字节数
+    pub async fn recv_to_file<W: Write>(&mut self, writer: &mut W) -> Result<u64> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        self.skip_discarded().await?;
+        framing::recv_stream(self.transport.as_mut(), &mut self.read_buffer, writer).await
+    }
+
+    /// 接收一条带长度前缀的消息，每次收到数据后回调 `(已接收字节数, 总字节数)`
+    ///
+    /// 回调 panic 时返回错误，已收到的数据保留，可继续通过 `recv_msg` 接收该消息。
+    /// 超过 `max_reassembly_bytes` 的消息与 `recv_msg` 一样被丢弃。
+    pub async fn recv_with_progress(&mut self, callback: impl FnMut(u64, u64)) -> Result<Vec<u8>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        self.skip_discarded().await?;
+        let result = framing::recv_with_progress(
+            self.transport.as_mut(),
+            &mut self.read_buffer,
+            self.reassembly_limit,
+            callback,
+        ).await;
+        self.abort_oversized(result).await
+    }
+
+    /// 断开连接
+    ///
+    /// 等同于原因码为 0、没有说明的 `disconnect_with_reason`，客户端随后照常读到流结束。
+    pub async fn disconnect(&mut self) -> Result<()> {
+        self.disconnect_with_reason(0, "").await
+    }
+
+    /// 携带原因码与说明断开连接
+    ///
+    /// 客户端之后的 `recv()` 返回 `VirgeError::PeerClosed { code, message }`，原因码的含义由应用自行约定；
+    /// 说明超过 `MAX_CLOSE_REASON_SIZE` 字节的部分被截断。xtransport 传输无法传递原因，仅断开连接。
+    pub async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        if self.connected {
+            self.transport.disconnect_with_reason(code, message).await?;
+            self.connected = false;
+            if let Some(mut guard) = self.guard.take() {
+                guard.reason.get_or_insert(DisconnectReason::Closed);
+            }
+            self.read_buffer.clear();
+        }
+        Ok(())
+    }
+
+    /// 按 `policy` 处理尚未读取的数据后断开连接
+    ///
+    /// `RefuseIfUnreadData` 拒绝时返回 `VirgeError::Other`，连接与未读数据保持不变。
+    pub async fn disconnect_with(&mut self, policy: DisconnectPolicy) -> Result<()> {
+        if self.connected {
+            policy.prepare(self.transport.as_mut(), &mut self.read_buffer).await?;
+        }
+        self.disconnect().await
+    }
+
+    /// 逐条处理客户端 `call()` 发来的请求，以 `handler` 的返回值作为响应
+    ///
+    /// 客户端断开或关闭写方向时返回 `Ok(())`；响应超过 `max_message_size` 时返回 `MessageTooLarge`。
+    pub async fn serve_requests(&mut self, handler: impl FnMut(Vec<u8>) -> Vec<u8>) -> Result<()> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        if self.peer_eof {
+            return Ok(());
+        }
+        rpc::serve(self.transport.as_mut(), self.max_message_size, handler).await
+    }
+
+    /// 持续接收消息并逐条交给 `on_msg` 处理，回调返回 `ControlFlow::Break` 时停止
+    ///
+    /// 每条消息与 `recv()` 的结果相同，连接上配置的读超时、心跳与空闲超时照常生效，
+    /// 超时及其他错误结束循环并返回给调用方；对端关闭连接或关闭写方向时返回 `Ok(())`，
+    /// 对端以 `disconnect_with_reason` 附带原因关闭时返回 `VirgeError::PeerClosed`。
+    /// 回调 panic 时断开连接并返回错误，panic 不会展开到调用方。
+    pub async fn run_recv_loop(&mut self, mut on_msg: impl FnMut(Vec<u8>) -> ControlFlow<()>) -> Result<()> {
+        loop {
+            let data = self.recv().await?;
+            if data.is_empty() && self.peer_eof {
+                return Ok(());
+            }
+            let flow = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| on_msg(data)));
+            match flow {
+                Ok(ControlFlow::Continue(())) => {}
+                Ok(ControlFlow::Break(())) => return Ok(()),
+                Err(_) => {
+                    warn!("VirgeServer receive callback panicked, disconnecting from {:?}", self.peer_addr);
+                    if let Err(e) = self.disconnect().await {
+                        debug!("Failed to disconnect after callback panic: {}", e);
+                    }
+                    return Err(VirgeError::Other("Receive callback panicked".to_string()));
+                }
+            }
+        }
+    }
+
+    /// 将值按配置的 `WireFormat` 编码为一条消息发送
+    #[cfg(feature = "serde")]
+    pub async fn send_serialized<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let data = codec::encode(self.wire_format, value, self.max_message_size)?;
+        self.send(data).await
+    }
+
+    /// 接收一条消息并按配置的 `WireFormat` 解码，数据与类型不符时返回 `VirgeError::CodecError`
+    #[cfg(feature = "serde")]
+    pub async fn recv_deserialized<T: DeserializeOwned>(&mut self) -> Result<T> {
+        let data = self.recv().await?;
+        codec::decode(self.wire_format, &data, self.max_message_size)
+    }
+
+    /// 类型化消息的编码格式与单条消息大小上限
+    #[cfg(feature = "serde")]
+    pub(crate) fn codec_options(&self) -> (WireFormat, usize) {
+        (self.wire_format, self.max_message_size)
+    }
+
+    /// 连接统计快照
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot()
+    }
+
+    /// 将连接拆分为读半部与写半部，可分别在不同的任务或线程中使用
+    ///
+    /// 已收到但未消费的数据转移到读半部；两个半部都释放后该连接才从活跃连接数中移除。
+    pub fn split(mut self) -> (VirgeReadHalf, VirgeWriteHalf) {
+        let guard = self.guard.take().map(|guard| Box::new(guard) as Box<dyn Any + Send + Sync>);
+        let transport = std::mem::replace(&mut self.transport, Box::new(split::Detached));
+        self.connected = false;
+        split::split(transport, std::mem::take(&mut self.read_buffer), self.max_message_size, guard)
+    }
+
+    /// 将连接转换为可克隆的句柄，交给多个任务或线程共用
+    ///
+    /// 并发发送按消息粒度串行化，加锁行为见 `handle` 模块文档；所有句柄都释放后该连接才从活跃连接数中移除。
+    /// 管理器启用 `with_connection_tracking` 时句柄同时登记到管理器，参与 `ServerManager::broadcast`。
+    pub fn handle(mut self) -> VirgeServerHandle {
+        let registry = self.guard.as_ref().filter(|guard| guard.tracked).map(|guard| guard.shared.clone());
+        let guard = self.guard.take().map(|guard| Box::new(guard) as Box<dyn Any + Send + Sync>);
+        let transport = std::mem::replace(&mut self.transport, Box::new(split::Detached));
+        self.connected = false;
+        let handle = VirgeServerHandle::new(
+            transport,
+            std::mem::take(&mut self.read_buffer),
+            self.max_message_size,
+            guard,
+            self.id,
+            self.peer_addr,
+        );
+        if let Some(shared) = registry {
+            shared.registry.insert(&handle);
+        }
+        handle
+    }
+
+    /// 非阻塞接收数据，当前没有可读数据时返回 `Ok(None)`
+    pub async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        self.transport.try_recv().await
+    }
+
+    /// 非阻塞发送数据，当前无法写入时返回 `Ok(None)`，成功时返回发送的字节数
+    pub async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        self.check_writable()?;
+        framing::check_size(data.len(), self.max_message_size)?;
+        self.transport.try_send(data).await
+    }
+
+    /// 经优先通道发送一条消息，对端通过 `recv_priority` 接收
+    ///
+    /// 需两端都启用 `with_priority_lanes`，否则返回 `VirgeError::ConfigError`。
+    /// 要在大消息发送期间插队，需将连接转换为句柄，见 `VirgeServerHandle::send_priority`。
+    pub async fn send_priority(&mut self, data: &[u8]) -> Result<()> {
+        self.check_writable()?;
+        framing::check_size(data.len(), self.max_message_size)?;
+        self.transport.send_priority(data.to_vec()).await
+    }
+
+    /// 接收一条优先消息，期间收到的普通消息留给 `recv`
+    ///
+    /// 客户端关闭写方向后返回空消息。
+    pub async fn recv_priority(&mut self) -> Result<Vec<u8>> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        if self.peer_eof {
+            return Ok(Vec::new());
+        }
+        let result = self.transport.recv_priority().await;
+        self.end_of_stream(result, Vec::new())
+    }
+
+    /// 发送一条附带元数据的消息，对端通过 `recv_with_meta` 取回元数据，普通的 `recv` 只收到 `data`
+    ///
+    /// 需两端都启用 `with_metadata`，否则返回 `VirgeError::ConfigError`；
+    /// 元数据编码后超过 4 KiB 或单个键超过 255 字节时返回 `VirgeError::MessageTooLarge`。
+    pub async fn send_with_meta(&mut self, data: &[u8], meta: &HeaderMap) -> Result<()> {
+        self.check_writable()?;
+        framing::check_size(data.len(), self.max_message_size)?;
+        self.transport.send_with_meta(data.to_vec(), meta).await
+    }
+
+    /// 接收一条消息及其元数据，对端以普通 `send` 发出的消息元数据为空
+    ///
+    /// 客户端关闭写方向后返回空消息与空元数据。
+    pub async fn recv_with_meta(&mut self) -> Result<(Vec<u8>, HeaderMap)> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        // 优先返回 recv_msg 预读但未消费的数据，其元数据已随之前的读取丢弃
+        if !self.read_buffer.is_empty() {
+            return Ok((std::mem::take(&mut self.read_buffer), HeaderMap::new()));
+        }
+        if self.peer_eof {
+            return Ok((Vec::new(), HeaderMap::new()));
+        }
+        let result = self.transport.recv_with_meta().await;
+        self.end_of_stream(result, (Vec::new(), HeaderMap::new()))
+    }
+
+    /// 设置读超时，`None` 表示一直阻塞
+    ///
+    /// 超时后 `recv()` 返回 `VirgeError::Timeout`，连接保持可用，可直接重试。
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.transport.set_read_timeout(timeout)?;
+        self.read_timeout = timeout;
+        Ok(())
+    }
+
+    /// 设置写超时，`None` 表示一直阻塞
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.transport.set_write_timeout(timeout)?;
+        self.write_timeout = timeout;
+        Ok(())
+    }
+
+    /// 接受客户端通过 open_stream 打开的下一个虚拟流，仅 yamux 传输支持
+    #[cfg(feature = "use-yamux")]
+    pub async fn accept_stream(&mut self) -> Result<VirgeStream> {
+        if !self.connected {
+            return Err(VirgeError::Disconnected(
+                "Server not connected".to_string(),
+            ));
+        }
+        self.transport.accept_stream().await
+    }
+
+    /// 检查连接状态（被动）
+    ///
+    /// 只读取本地记录的状态，不访问套接字：对端异常退出后，在下一次收发失败之前仍可能返回 `true`。
+    /// 启用 keepalive 时，发出的 PING 超过心跳超时未获回应也返回 `false`。需要确认对端存活时使用 `probe_connection`。
+    pub fn is_connected(&self) -> bool {
+        self.connected && self.transport.is_connected()
+    }
+
+    /// 主动检测连接是否存活，最多等待 `timeout`
+    ///
+    /// 检查底层套接字是否已挂断或读到对端关闭；启用 keepalive 时还会立即发送 PING，
+    /// 并等待对端在 `timeout` 内发来任意帧，期间收到的消息保留给之后的接收调用。
+    /// 对端需要在此期间调用接收接口才能应答 PING。未启用 keepalive 时无法发现未关闭套接字就失联的对端。
+    ///
+    /// # Returns
+    /// 存活返回 `Ok(true)`；已断开或未在 `timeout` 内回应返回 `Ok(false)`，连接状态保持不变；
+    /// `timeout` 为零或发送 PING 时出现其他错误返回 `Err`
+    pub async fn probe_connection(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        crate::transport::check_timeout(Some(timeout))?;
+        if !self.is_connected() {
+            return Ok(false);
+        }
+        match self.transport.probe(timeout).await {
+            Ok(alive) => Ok(alive),
+            Err(e) if e.is_disconnected() => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 是否有已读出但尚未消费的数据，为 `true` 时即使描述符不可读也可能立即收到数据
+    pub fn has_buffered_data(&self) -> bool {
+        !self.read_buffer.is_empty() || self.transport.has_buffered_data()
+    }
+
+    /// 是否有一条消息可由 `recv`/`try_recv` 立即取得而无需等待新数据
+    ///
+    /// 先把套接字上已到达的消息读入传输内部的队列，再像 `peek_msg_len` 一样非阻塞地查看下一条消息，
+    /// 查看到的消息留给下一次接收；已开始到达的消息会等待其余分块。在执行耗时工作前取完积压的消息：
+    /// `while conn.has_pending_message().await? { handle(conn.recv().await?) }`。
+    pub async fn has_pending_message(&mut self) -> std::io::Result<bool> {
+        if self.connected && self.read_buffer.is_empty() && !self.peer_eof {
+            self.transport.prefetch().await?;
+        }
+        Ok(self.peek_msg_len().await?.is_some())
+    }
+
+    /// 已接收但尚未被读取的字节数：`recv_msg`、`peek_msg_len` 等接口预读的数据，加上传输内部已完整接收、
+    /// 尚未交付的消息（目前只有 xtransport 在 `has_pending_message` 时预读整条消息）
+    pub fn pending_bytes(&self) -> usize {
+        self.read_buffer.len() + self.transport.pending_bytes()
+    }
+
+    /// 获取连接序号，与 `ServerEvent` 中的 `conn_id` 一致
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 获取对端地址
+    pub fn peer_addr(&self) -> VsockAddr {
+        self.peer_addr
+    }
+
+    /// 连接的 vsock 套接字实际生效的缓冲区大小，见 `VirgeClient::socket_buffer_sizes`
+    pub fn socket_buffer_sizes(&self) -> Result<SocketBufferSizes> {
+        crate::transport::socket_buffer_sizes(self.transport.as_ref())
+    }
+
+    /// 对端在握手中出示并通过认证的令牌，管理器未配置认证函数或对端未出示令牌时为 `None`
+    ///
+    /// 应用可据此区分不同的来宾，例如为每个来宾分配不同的令牌。
+    pub fn peer_identity(&self) -> Option<&[u8]> {
+        self.peer_identity.as_deref()
+    }
+
+    /// 连接到达的本地监听端口，监听多个端口时用于区分连接来源，内存连接为 0
+    pub fn local_port(&self) -> u32 {
+        self.local_port
+    }
+
+    /// 获取对端 CID
+    pub fn peer_cid(&self) -> u32 {
+        self.peer_addr.cid()
+    }
+
+    /// 获取对端端口
+    pub fn peer_port(&self) -> u32 {
+        self.peer_addr.port()
+    }
+}
+
+/// 返回底层套接字的描述符，未连接或内存连接时返回 `-1`，注意事项同 `VirgeClient` 的实现
+impl AsRawFd for VirgeServer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.transport.raw_fd().unwrap_or(-1)
+    }
+}
+
+/// 返回监听套接字的描述符，可读时表示有待接受的连接；未启动、已停止或启用接受队列
+/// （监听器由后台任务持有）时返回 `-1`
+///
+/// 连接被取走后在后台完成握手，可读之后的 `try_accept` 可能先返回 `Ok(None)`。
+impl AsRawFd for ServerManager {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_ref().map_or(-1, |listener| listener.as_raw_fd())
+    }
+}
+
+impl Drop for VirgeServer {
+    fn drop(&mut self) {
+        if !self.connected {
+            return;
+        }
+        // 传输实例随连接一同释放，其持有的套接字在此关闭
+        if self.read_buffer.is_empty() {
+            debug!("VirgeServer for {:?} dropped without disconnect, closing transport", self.peer_addr);
+        } else {
+            warn!(
+                "VirgeServer for {:?} dropped without disconnect, discarding {} unread bytes",
+                self.peer_addr,
+                self.read_buffer.len()
+            );
+        }
+    }
+}
+
+/// `VirgeServer::recv_stream` 返回的单条消息读取器，按到达顺序逐块读出负载
+///
+/// 用法与 `std::io::Read` 相同，但 `read` 为 `async fn`；读取器存在期间独占该连接。
+/// 启用 tokio 运行时时实现 `tokio::io::AsyncRead`，语义与 `read` 相同，可直接交给 `tokio::io::copy` 或解码器；
+/// 消息读完时返回 EOF，错误按 `From<VirgeError> for io::Error` 转换。
+pub struct MessageReader<'a> {
+    /// 等待数据期间移入进行中的接收，完成后取回
+    transport: Option<&'a mut dyn Transport>,
+    buf: &'a mut Vec<u8>,
+    /// 尚未读出的负载，即连接的 `discard`，读取器提前释放时由下一次接收丢弃
+    remaining: &'a mut framing::Remaining,
+    len: u64,
+    /// 进行中的接收，`read` 被取消后由下一次读取继续等待
+    receiving: Option<PendingRecv<'a>>,
+}
+
+type PendingRecv<'a> = futures::future::BoxFuture<'a, (&'a mut dyn Transport, Result<Vec<u8>>)>;
+
+impl<'a> MessageReader<'a> {
+    /// 读取至多 `buf.len()` 字节负载，消息已读完时返回 `Ok(0)`
+    ///
+    /// 缓冲区为空时等待下一块数据到达；读超时照常返回，之后可继续读取。
+    /// 对端在消息中途断开时返回 `ErrorKind::UnexpectedEof`；分块消息的发送方放弃了该消息时返回
+    /// `MessageAborted`，已读出的数据不构成完整消息，连接仍可接收后续消息。
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        futures::future::poll_fn(|cx| self.poll_read_payload(cx, buf)).await
+    }
+
+    /// 读出已收到的负载，缓冲区中没有可读的数据时从传输层接收
+    fn poll_read_payload(&mut self, cx: &mut Context<'_>, out: &mut [u8]) -> Poll<Result<usize>> {
+        loop {
+            if let Some(receiving) = self.receiving.as_mut() {
+                let (transport, received) = ready!(receiving.poll_unpin(cx));
+                self.receiving = None;
+                self.transport = Some(transport);
+                framing::append_more(self.buf, received)?;
+            }
+            if let Some(n) = framing::take_payload(self.buf, self.remaining, out)? {
+                return Poll::Ready(Ok(n));
+            }
+            let transport = self.transport.take().expect("MessageReader holds the transport while not receiving");
+            self.receiving = Some(
+                async move {
+                    let received = transport.recv().await;
+                    (transport, received)
+                }
+                .boxed(),
+            );
+        }
+    }
+
+    /// 消息负载的总字节数，取自长度前缀；分块发送（`start_message`）的消息总长度事先未知，返回 `u64::MAX`
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// 消息负载是否为空
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 尚未读出的负载字节数，分块消息为当前块尚未读出的字节数
+    pub fn remaining(&self) -> u64 {
+        self.remaining.bytes
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl tokio::io::AsyncRead for MessageReader<'_> {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let n = ready!(self.get_mut().poll_read_payload(cx, buf.initialize_unfilled()))?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
     }
 }
@@ -0,0 +1,393 @@
+//! 连接读写分离模块
+//!
+//! `VirgeClient::split` / `VirgeServer::split` 将连接拆分为可分别移交给不同任务或线程的读半部与写半部。
+//!
+//! # 机制
+//! - 两个半部通过 `Arc` 共享同一个传输实例，传输实例由异步互斥锁保护
+//! - 读半部以非阻塞的 `try_recv` 轮询数据，每次只在取数据的瞬间持有锁，
+//!   因此等待中的接收不会阻塞另一半部的发送
+//! - 读缓冲区只属于读半部；任一半部被 drop 不影响另一半部，两者都释放后连接关闭
+//! - 启用 tokio 运行时时，读半部实现 `tokio::io::AsyncRead`，写半部实现 `tokio::io::AsyncWrite`，
+//!   语义与 `read`/`write` 相同；每次 `poll_write` 的数据作为一条消息发送，`poll_shutdown` 关闭写方向
+//!
+//! 拆分后的连接不再自动重连。
+
+use crate::error::{Result, VirgeError};
+use crate::transport::{framing, Transport};
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use std::any::Any;
+use std::io::IoSlice;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+#[cfg(feature = "tokio-runtime")]
+use futures::future::BoxFuture;
+#[cfg(feature = "tokio-runtime")]
+use futures::FutureExt;
+#[cfg(feature = "tokio-runtime")]
+use std::pin::Pin;
+#[cfg(feature = "tokio-runtime")]
+use std::task::{ready, Context, Poll};
+
+/// 读半部轮询的初始间隔
+pub(crate) const POLL_INTERVAL_MIN: Duration = Duration::from_micros(100);
+/// 读半部轮询的最大间隔
+pub(crate) const POLL_INTERVAL_MAX: Duration = Duration::from_millis(10);
+
+/// 两个半部共享的连接状态
+struct Shared {
+    transport: Mutex<Box<dyn Transport>>,
+    /// 随连接一同释放的资源（如服务器的活跃连接计数）
+    _guard: Option<Box<dyn Any + Send + Sync>>,
+}
+
+/// 拆分连接，`pending` 为拆分前已收到但尚未消费的数据
+pub(crate) fn split(
+    transport: Box<dyn Transport>,
+    pending: Vec<u8>,
+    max_message_size: usize,
+    guard: Option<Box<dyn Any + Send + Sync>>,
+) -> (VirgeReadHalf, VirgeWriteHalf) {
+    let shared = Arc::new(Shared {
+        transport: Mutex::new(transport),
+        _guard: guard,
+    });
+    let read = VirgeReadHalf {
+        shared: shared.clone(),
+        read_buffer: pending,
+        read_timeout: None,
+        max_message_size,
+        #[cfg(feature = "tokio-runtime")]
+        reading: std::sync::Mutex::new(None),
+    };
+    let write = VirgeWriteHalf {
+        shared,
+        max_message_size,
+        #[cfg(feature = "tokio-runtime")]
+        writing: std::sync::Mutex::new(None),
+    };
+    (read, write)
+}
+
+/// 连接的读半部
+pub struct VirgeReadHalf {
+    shared: Arc<Shared>,
+    /// 已从传输层收到但尚未被 recv_msg/read 消费的数据
+    read_buffer: Vec<u8>,
+    read_timeout: Option<Duration>,
+    max_message_size: usize,
+    /// `poll_read` 进行中的接收；只经 `&mut self` 访问，互斥锁仅用于保持 `Sync`
+    #[cfg(feature = "tokio-runtime")]
+    reading: std::sync::Mutex<Option<BoxFuture<'static, Result<Vec<u8>>>>>,
+}
+
+impl VirgeReadHalf {
+    /// 接收数据
+    ///
+    /// 设置了读超时时，超时返回 `VirgeError::Timeout`，连接保持可用。
+    pub async fn recv(&mut self) -> Result<Vec<u8>> {
+        if !self.read_buffer.is_empty() {
+            return Ok(std::mem::take(&mut self.read_buffer));
+        }
+        self.recv_frame().await
+    }
+
+    /// 非阻塞接收数据，当前没有可读数据时返回 `Ok(None)`
+    pub async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        if !self.read_buffer.is_empty() {
+            return Ok(Some(std::mem::take(&mut self.read_buffer)));
+        }
+        self.shared.transport.lock().await.try_recv().await
+    }
+
+    /// 接收一条带 8 字节长度前缀的消息，与对端的 `send_msg` 配合使用
+    pub async fn recv_msg(&mut self) -> Result<Vec<u8>> {
+        loop {
+            if let Some(message) = framing::take_message(&mut self.read_buffer, self.max_message_size)? {
+                return Ok(message);
+            }
+
+            let partial = !self.read_buffer.is_empty();
+            match self.recv_frame().await {
+                Ok(data) if data.is_empty() && partial => return Err(framing::unexpected_eof()),
+                Ok(data) => self.read_buffer.extend_from_slice(&data),
+                Err(e) if partial && !e.is_timeout() => return Err(framing::unexpected_eof()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// 以字节流方式读取数据，仅在对端关闭连接时返回 `Ok(0)`
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.read_buffer.is_empty() {
+            match self.recv_frame().await {
+                Ok(data) => self.read_buffer.extend_from_slice(&data),
+                Err(VirgeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(0),
+                Err(e) => return Err(e),
+            }
+        }
+        let n = buf.len().min(self.read_buffer.len());
+        buf[..n].copy_from_slice(&self.read_buffer[..n]);
+        self.read_buffer.drain(..n);
+        Ok(n)
+    }
+
+    /// 设置读超时，`None` 表示一直等待
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        crate::transport::check_timeout(timeout)?;
+        self.read_timeout = timeout;
+        Ok(())
+    }
+
+    /// 轮询传输层直到收到一条消息或读超时
+    async fn recv_frame(&mut self) -> Result<Vec<u8>> {
+        let deadline = self.read_timeout.map(|timeout| Instant::now() + timeout);
+        poll_recv(&self.shared.transport, deadline).await
+    }
+}
+
+/// 以非阻塞的 `try_recv` 轮询共享的传输实例直到收到一条消息，只在取数据的瞬间持有锁
+///
+/// 到达 `deadline` 仍未收到时返回 `VirgeError::Timeout`，`None` 表示一直等待。
+pub(crate) async fn poll_recv(transport: &Mutex<Box<dyn Transport>>, deadline: Option<Instant>) -> Result<Vec<u8>> {
+    let mut interval = POLL_INTERVAL_MIN;
+    loop {
+        if let Some(data) = transport.lock().await.try_recv().await? {
+            return Ok(data);
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(VirgeError::Timeout("Split read half recv timed out".to_string()));
+        }
+        crate::transport::sleep(interval).await;
+        interval = (interval * 2).min(POLL_INTERVAL_MAX);
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl tokio::io::AsyncRead for VirgeReadHalf {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        if this.read_buffer.is_empty() {
+            let reading = this.reading.get_mut().unwrap_or_else(|e| e.into_inner());
+            let future = reading.get_or_insert_with(|| {
+                let shared = this.shared.clone();
+                let deadline = this.read_timeout.map(|timeout| Instant::now() + timeout);
+                async move { poll_recv(&shared.transport, deadline).await }.boxed()
+            });
+            let result = ready!(future.poll_unpin(cx));
+            *reading = None;
+            match result {
+                Ok(data) => this.read_buffer = data,
+                Err(VirgeError::EndOfStream) => {}
+                Err(VirgeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {}
+                Err(e) => return Poll::Ready(Err(e.into())),
+            }
+        }
+        // 读缓冲区仍为空表示流结束
+        let n = buf.remaining().min(this.read_buffer.len());
+        buf.put_slice(&this.read_buffer[..n]);
+        this.read_buffer.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// 连接的写半部
+pub struct VirgeWriteHalf {
+    shared: Arc<Shared>,
+    max_message_size: usize,
+    /// `AsyncWrite` 进行中的操作；只经 `&mut self` 访问，互斥锁仅用于保持 `Sync`
+    #[cfg(feature = "tokio-runtime")]
+    writing: std::sync::Mutex<Option<PendingWrite>>,
+}
+
+/// `AsyncWrite` 的操作类型，完成的结果只交给发起同类操作的 poll
+#[cfg(feature = "tokio-runtime")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum WriteOp {
+    Write,
+    Flush,
+    Shutdown,
+}
+
+#[cfg(feature = "tokio-runtime")]
+struct PendingWrite {
+    op: WriteOp,
+    future: BoxFuture<'static, Result<usize>>,
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl VirgeWriteHalf {
+    /// 驱动 `op` 类操作直到完成；先完成其他类的进行中操作，其错误直接返回
+    fn poll_op(
+        &mut self,
+        cx: &mut Context<'_>,
+        op: WriteOp,
+        start: impl FnOnce(Arc<Shared>) -> BoxFuture<'static, Result<usize>>,
+    ) -> Poll<std::io::Result<usize>> {
+        let mut start = Some(start);
+        let writing = self.writing.get_mut().unwrap_or_else(|e| e.into_inner());
+        loop {
+            let Some(pending) = writing.as_mut() else {
+                let start = start.take().expect("an operation is started at most once per poll");
+                *writing = Some(PendingWrite { op, future: start(self.shared.clone()) });
+                continue;
+            };
+            let result = ready!(pending.future.poll_unpin(cx));
+            let done = pending.op;
+            *writing = None;
+            if done == op {
+                return Poll::Ready(result.map_err(std::io::Error::from));
+            }
+            result?;
+        }
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl tokio::io::AsyncWrite for VirgeWriteHalf {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        framing::check_size(buf.len(), this.max_message_size)?;
+        this.poll_op(cx, WriteOp::Write, |shared| {
+            let data = buf.to_vec();
+            async move {
+                let len = data.len();
+                shared.transport.lock().await.send(data).await.map(|()| len)
+            }
+            .boxed()
+        })
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let flushed = self.get_mut().poll_op(cx, WriteOp::Flush, |shared| {
+            async move { shared.transport.lock().await.flush().await.map(|()| 0) }.boxed()
+        });
+        ready!(flushed)?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let shutdown = self.get_mut().poll_op(cx, WriteOp::Shutdown, |shared| {
+            async move { shared.transport.lock().await.shutdown_write().await.map(|()| 0) }.boxed()
+        });
+        ready!(shutdown)?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl VirgeWriteHalf {
+    /// 发送数据，超过 `max_message_size` 时在本地返回 `VirgeError::MessageTooLarge`
+    pub async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        framing::check_size(data.len(), self.max_message_size)?;
+        self.shared.transport.lock().await.send(data).await
+    }
+
+    /// 发送数据且不等待对端确认，未启用 ACK 时与 `send` 相同
+    pub async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        framing::check_size(data.len(), self.max_message_size)?;
+        self.shared.transport.lock().await.send_noack(data).await
+    }
+
+    /// 将多个缓冲区作为一条消息发送
+    pub async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        let total = slices.iter().map(|slice| slice.len()).sum();
+        framing::check_size(total, self.max_message_size)?;
+        self.shared.transport.lock().await.send_slices(slices).await
+    }
+
+    /// 发送一条带 8 字节大端长度前缀的消息
+    pub async fn send_msg(&mut self, data: &[u8]) -> Result<()> {
+        let frame = framing::encode(data, self.max_message_size)?;
+        self.shared.transport.lock().await.send(frame).await
+    }
+
+    /// 以字节流方式写入数据，成功时返回值恒等于 `buf.len()`
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.send(buf.to_vec()).await?;
+        Ok(buf.len())
+    }
+
+    /// 非阻塞发送数据，当前无法写入时返回 `Ok(None)`
+    pub async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        framing::check_size(data.len(), self.max_message_size)?;
+        self.shared.transport.lock().await.try_send(data).await
+    }
+
+    /// 设置写超时，`None` 表示一直阻塞
+    pub async fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.shared.transport.lock().await.set_write_timeout(timeout)
+    }
+
+    /// 断开连接，读半部随后的接收返回错误
+    pub async fn disconnect(&mut self) -> Result<()> {
+        self.shared.transport.lock().await.disconnect().await
+    }
+}
+
+/// 连接被拆分或转换为句柄后留在原 `VirgeClient`/`VirgeServer` 中的占位传输，所有操作均返回 `Disconnected`
+pub(crate) struct Detached;
+
+impl Detached {
+    fn error() -> VirgeError {
+        VirgeError::Disconnected("Connection has been split or converted into a handle".to_string())
+    }
+}
+
+#[async_trait]
+impl Transport for Detached {
+    async fn connect(&mut self, _: u32, _: u32, _: u32, _: bool) -> Result<()> {
+        Err(Self::error())
+    }
+
+    async fn connect_timeout(&mut self, _: u32, _: u32, _: u32, _: bool, _: Duration) -> Result<()> {
+        Err(Self::error())
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn send(&mut self, _: Vec<u8>) -> Result<()> {
+        Err(Self::error())
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        Err(Self::error())
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        Err(Self::error())
+    }
+
+    async fn try_send(&mut self, _: &[u8]) -> Result<Option<usize>> {
+        Err(Self::error())
+    }
+
+    fn is_connected(&self) -> bool {
+        false
+    }
+
+    fn set_read_timeout(&mut self, _: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, _: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,470 @@
+//! 送达确认模块
+//!
+//! 为没有内建 ACK 的传输协议（yamux、raw）提供消息级确认，以包装器的形式叠加在传输协议之上。
+//! xtransport 由其自身的 ACK 机制完成确认，不使用本包装器。
+//!
+//! # 机制
+//! - `send` 为消息分配序号后交给下层发送；未确认的消息达到 ACK 窗口时等待最早一条的 ACK，
+//!   写超时内未收到时返回 `VirgeError::Timeout`。窗口为 1（默认）时逐条等待确认，
+//!   更大的窗口允许连续发出多条消息，`send` 返回时只保证窗口之外的消息已确认
+//! - 窗口在握手中协商，取双方设置中较小的值；关闭握手时使用本端的设置
+//! - 记录最近 `ACK_SAMPLES` 次确认的往返时间，供 `ack_stats` 统计
+//! - 等待期间收到的用户数据暂存，由随后的 recv/try_recv 按序返回；对端的半关闭通知排在这些数据之后
+//! - 接收方在读取到需要确认的消息时立即回复 ACK，ACK 帧不会出现在用户可见的接收结果中
+//! - `send_noack` 与 `try_send` 发送无需确认的消息，交给内核后立即返回
+//!
+//! 对端只有在调用 recv/try_recv（或自身等待 ACK）时才会读取消息并回复确认。
+//!
+//! # 帧格式
+//! ```text
+//! ┌──────────┬──────────────────────────────────┐
+//! │ type: u8 │ 0：payload                       │
+//! │          │ 1：seq: u32 BE + payload（需确认）│
+//! │          │ 2：seq: u32 BE（确认）            │
+//! └──────────┴──────────────────────────────────┘
+//! ```
+//!
+//! 帧头的编解码见 `protocol` 模块。
+
+use crate::error::{Result, VirgeError};
+use crate::protocol::{FrameHeader, Layer};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::{check_timeout, Transport, VsockAddr};
+use async_trait::async_trait;
+use log::*;
+use std::collections::VecDeque;
+use std::io::IoSlice;
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// 参与往返时间统计的最近确认次数
+const ACK_SAMPLES: usize = 128;
+
+/// 需确认消息的帧头字节数，也是本包装器对单条消息增加的最大开销
+pub(crate) const HEADER_SIZE: usize = crate::protocol::ACK_HEADER_SIZE;
+
+/// 收到的一个帧
+enum Incoming {
+    Data(Vec<u8>),
+    Ack(u32),
+}
+
+/// 确认往返时间统计，取最近 128 次确认
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AckStats {
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub p99: Duration,
+    /// 参与统计的确认次数
+    pub samples: usize,
+}
+
+/// 最近若干次确认往返时间的滑动窗口
+#[derive(Debug, Default)]
+pub(crate) struct AckSamples {
+    samples: VecDeque<Duration>,
+}
+
+impl AckSamples {
+    pub(crate) fn record(&mut self, rtt: Duration) {
+        if self.samples.len() == ACK_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(rtt);
+    }
+
+    pub(crate) fn last(&self) -> Option<Duration> {
+        self.samples.back().copied()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub(crate) fn stats(&self) -> Option<AckStats> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+        sorted.sort();
+        let p99 = sorted[(sorted.len() * 99 / 100).min(sorted.len() - 1)];
+        Some(AckStats {
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            mean: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            p99,
+            samples: sorted.len(),
+        })
+    }
+}
+
+/// 送达确认包装器
+pub(crate) struct AckTransport {
+    inner: Box<dyn Transport>,
+    /// 下一条需确认消息的序号
+    next_seq: u32,
+    /// 用户设置的读超时，等待 ACK 结束后恢复
+    read_timeout: Option<Duration>,
+    /// 写超时同时限制等待 ACK 的时间
+    write_timeout: Option<Duration>,
+    /// 等待 ACK 期间收到的用户数据
+    pending: VecDeque<Vec<u8>>,
+    /// 等待 ACK 期间读到了对端的半关闭通知，`pending` 取完后返回 `EndOfStream`
+    pending_eof: bool,
+    /// 本端设置的 ACK 窗口
+    window: u32,
+    /// 当前连接生效的 ACK 窗口，握手后取协商值
+    effective_window: u32,
+    /// 已发出但尚未确认的消息序号与发送时间，按发送顺序排列
+    in_flight: VecDeque<(u32, Instant)>,
+    samples: AckSamples,
+}
+
+impl AckTransport {
+    pub(crate) fn new(inner: Box<dyn Transport>, window: u32) -> Self {
+        Self {
+            inner,
+            next_seq: 0,
+            read_timeout: None,
+            write_timeout: None,
+            pending: VecDeque::new(),
+            pending_eof: false,
+            window,
+            effective_window: window,
+            in_flight: VecDeque::new(),
+            samples: AckSamples::default(),
+        }
+    }
+
+    /// 丢弃上一个连接的状态
+    fn reset(&mut self) {
+        self.pending.clear();
+        self.pending_eof = false;
+        self.in_flight.clear();
+    }
+
+    /// 连接建立后按握手结果确定生效的窗口
+    fn connected(&mut self) {
+        self.effective_window = self.inner.negotiated_ack_window().unwrap_or(self.window).max(1);
+        if self.effective_window != self.window {
+            info!("Ack: using negotiated window {} instead of {}", self.effective_window, self.window);
+        }
+    }
+
+    fn data_frame(data: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(data.len() + 1);
+        FrameHeader::Data.encode_into(&mut frame);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    fn take_seq(&mut self) -> u32 {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        seq
+    }
+
+    /// 返回等待 ACK 期间读到的半关闭通知，只返回一次
+    fn take_pending_eof(&mut self) -> Result<()> {
+        if std::mem::take(&mut self.pending_eof) {
+            return Err(VirgeError::EndOfStream);
+        }
+        Ok(())
+    }
+
+    /// 解析一个收到的帧，需要确认的消息在此回复 ACK
+    async fn handle_frame(&mut self, mut frame: Vec<u8>) -> Result<Incoming> {
+        let (header, header_len) = FrameHeader::decode(Layer::Ack, &frame)?;
+        match header {
+            FrameHeader::DataAck { seq } => {
+                self.inner.send(FrameHeader::Ack { seq }.to_bytes()).await?;
+            }
+            FrameHeader::Ack { seq } => return Ok(Incoming::Ack(seq)),
+            _ => {}
+        }
+        frame.drain(..header_len);
+        Ok(Incoming::Data(frame))
+    }
+
+    /// 处理收到的 ACK：该序号及之前发出的消息均视为已确认，并记录往返时间
+    fn acked(&mut self, seq: u32) {
+        let Some(position) = self.in_flight.iter().position(|&(pending, _)| pending == seq) else {
+            debug!("Ack: ignoring stale ack {}", seq);
+            return;
+        };
+        let (_, sent_at) = self.in_flight[position];
+        self.in_flight.drain(..=position);
+        self.samples.record(sent_at.elapsed());
+    }
+
+    /// 记录刚发出的消息，未确认的消息达到窗口时等待最早一条的 ACK
+    async fn track(&mut self, seq: u32, sent_at: Instant) -> Result<()> {
+        self.in_flight.push_back((seq, sent_at));
+        while self.in_flight.len() >= self.effective_window as usize {
+            self.wait_oldest().await?;
+        }
+        Ok(())
+    }
+
+    /// 等待最早一条未确认消息的 ACK，结束后恢复用户设置的读超时
+    async fn wait_oldest(&mut self) -> Result<()> {
+        let Some(&(seq, sent_at)) = self.in_flight.front() else {
+            return Ok(());
+        };
+        let deadline = self.write_timeout.map(|timeout| sent_at + timeout);
+        let result = self.poll_ack(seq, deadline).await;
+        if deadline.is_some() {
+            self.inner.set_read_timeout(self.read_timeout)?;
+        }
+        result
+    }
+
+    async fn poll_ack(&mut self, seq: u32, deadline: Option<Instant>) -> Result<()> {
+        while self.in_flight.front().is_some_and(|&(oldest, _)| oldest == seq) {
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    // 放弃该消息的确认，连接保持可用
+                    self.in_flight.pop_front();
+                    return Err(VirgeError::Timeout(format!("Timed out waiting for ack of message {}", seq)));
+                }
+                self.inner.set_read_timeout(Some(remaining))?;
+            }
+            match self.inner.recv().await {
+                Ok(frame) => match self.handle_frame(frame).await? {
+                    Incoming::Ack(acked) => self.acked(acked),
+                    Incoming::Data(data) => self.pending.push_back(data),
+                },
+                // 用户读超时不限制等待 ACK，截止时间在循环开头检查
+                Err(e) if e.is_timeout() => {}
+                // 对端半关闭后仍会回复 ACK，通知留给之后的 recv
+                Err(VirgeError::EndOfStream) => self.pending_eof = true,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Transport for AckTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        self.reset();
+        self.inner.connect(cid, port, chunksize, isack).await?;
+        self.connected();
+        Ok(())
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        self.reset();
+        self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await?;
+        self.connected();
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.reset();
+        self.inner.from_tokio_stream(stream).await?;
+        self.connected();
+        Ok(())
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.reset();
+        self.inner.from_tcp_stream(stream).await?;
+        self.connected();
+        Ok(())
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.reset();
+        self.inner.from_uds_stream(stream).await?;
+        self.connected();
+        Ok(())
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        self.reset();
+        self.inner.from_seqpacket(socket).await?;
+        self.connected();
+        Ok(())
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        self.reset();
+        self.inner.from_stream(stream, chunksize, isack).await?;
+        self.connected();
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        self.inner.disconnect_with_reason(code, message).await
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        let seq = self.take_seq();
+        let mut frame = Vec::with_capacity(HEADER_SIZE + data.len());
+        FrameHeader::DataAck { seq }.encode_into(&mut frame);
+        frame.extend_from_slice(&data);
+        let sent_at = Instant::now();
+        self.inner.send(frame).await?;
+        self.track(seq, sent_at).await
+    }
+
+    async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        self.inner.send(Self::data_frame(&data)).await
+    }
+
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        let seq = self.take_seq();
+        let header = FrameHeader::DataAck { seq }.to_bytes();
+        let mut framed = Vec::with_capacity(slices.len() + 1);
+        framed.push(IoSlice::new(&header));
+        framed.extend(slices.iter().map(|slice| IoSlice::new(slice)));
+        let sent_at = Instant::now();
+        let sent = self.inner.send_slices(&framed).await?;
+        self.track(seq, sent_at).await?;
+        Ok(sent - header.len())
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        if let Some(data) = self.pending.pop_front() {
+            return Ok(data);
+        }
+        self.take_pending_eof()?;
+        loop {
+            let frame = self.inner.recv().await?;
+            match self.handle_frame(frame).await? {
+                Incoming::Data(data) => return Ok(data),
+                Incoming::Ack(seq) => self.acked(seq),
+            }
+        }
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        if let Some(data) = self.pending.pop_front() {
+            return Ok(Some(data));
+        }
+        self.take_pending_eof()?;
+        while let Some(frame) = self.inner.try_recv().await? {
+            match self.handle_frame(frame).await? {
+                Incoming::Data(data) => return Ok(Some(data)),
+                Incoming::Ack(seq) => self.acked(seq),
+            }
+        }
+        Ok(None)
+    }
+
+    /// 非阻塞发送无法等待确认，始终发送无需确认的消息
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        let sent = self.inner.try_send(&Self::data_frame(data)).await?;
+        Ok(sent.map(|_| data.len()))
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        self.inner.shutdown_write().await
+    }
+
+    /// 推送下层缓冲的数据后等待全部已发出消息的确认，写超时同样限制每条消息的等待
+    async fn flush(&mut self) -> Result<()> {
+        self.inner.flush().await?;
+        while !self.in_flight.is_empty() {
+            self.wait_oldest().await?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        self.inner.probe(timeout).await
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.samples.last()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.samples.stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        self.inner.last_sent_seq()
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        self.inner.last_received_seq()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        !self.pending.is_empty() || self.pending_eof || self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.pending.iter().map(Vec::len).sum::<usize>() + self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        check_timeout(timeout)?;
+        self.read_timeout = timeout;
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        check_timeout(timeout)?;
+        self.write_timeout = timeout;
+        self.inner.set_write_timeout(timeout)
+    }
+}
@@ -0,0 +1,96 @@
+//! 接收缓冲区池
+//!
+//! 字节流传输（Raw、Tcp、UDS、yamux 与内存传输）每次从流中读取都需要一块读缓冲区。
+//! [`BufferPool`] 保存至多 `capacity` 块用过的缓冲区，之后的读取优先取用，
+//! 不再为每次读取分配并清零一块新内存；xtransport 的消息由 xtransport 库自行分配，不经过缓冲区池。
+//!
+//! 取用时的命中与未命中次数由统计包装器读取，见 `Stats::buffer_pool_hits`。
+//! 容量为 0 时不缓存任何缓冲区，也不计数。
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// 一个连接的读缓冲区空闲列表，由传输与统计计数器共享
+#[derive(Debug, Default)]
+pub(crate) struct BufferPool {
+    free: Mutex<Vec<Vec<u8>>>,
+    /// 至多保存的空闲缓冲区数，0 表示不启用
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl BufferPool {
+    pub(crate) fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self { free: Mutex::new(Vec::with_capacity(capacity)), capacity, ..Self::default() })
+    }
+
+    /// 取出一块长度为 `len` 的缓冲区，复用的缓冲区保留上次读取的内容
+    pub(crate) fn take(&self, len: usize) -> Vec<u8> {
+        if self.capacity == 0 {
+            return vec![0; len];
+        }
+        let reused = self.free.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        match reused {
+            Some(mut buf) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                buf.resize(len, 0);
+                buf
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                vec![0; len]
+            }
+        }
+    }
+
+    /// 归还用完的缓冲区，空闲列表已满或未启用时直接释放
+    pub(crate) fn give_back(&self, buf: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+        if free.len() < self.capacity {
+            free.push(buf);
+        }
+    }
+
+    /// 复用了空闲缓冲区的取用次数
+    pub(crate) fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// 空闲列表为空、新分配缓冲区的取用次数
+    pub(crate) fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_reused_up_to_the_capacity() {
+        let pool = BufferPool::new(1);
+        let first = pool.take(16);
+        let second = pool.take(16);
+        assert_eq!((pool.hits(), pool.misses()), (0, 2));
+
+        // 第二块超出容量被释放，之后只有一块可以复用
+        pool.give_back(first);
+        pool.give_back(second);
+        assert_eq!(pool.take(8).len(), 8);
+        assert_eq!(pool.take(8).len(), 8);
+        assert_eq!((pool.hits(), pool.misses()), (1, 3));
+    }
+
+    #[test]
+    fn zero_capacity_disables_the_pool() {
+        let pool = BufferPool::new(0);
+        let buf = pool.take(16);
+        pool.give_back(buf);
+        assert_eq!(pool.take(16).len(), 16);
+        assert_eq!((pool.hits(), pool.misses()), (0, 0));
+    }
+}
@@ -0,0 +1,408 @@
+//! 透明压缩模块
+//!
+//! 以包装器的形式叠加在传输协议之上，发送时压缩每条消息、接收时解压，对调用方透明。
+//! 算法由 `compression-lz4` / `compression-zstd` 特性启用，通过 `with_compression` 为每个连接选择。
+//!
+//! - 短于 [`COMPRESSION_THRESHOLD`] 的消息，以及压缩后没有变小的消息原样发送
+//! - 两端的压缩算法在握手中协商，不一致（包括一端未启用压缩）时连接建立即失败
+//! - 解压前先校验帧头声明的原始长度，超过消息上限时返回 `MessageTooLarge`，不会按对端声明的长度分配内存
+//!
+//! # 帧格式
+//! ```text
+//! ┌────────────┬──────────────────────────────────────────────┐
+//! │ method: u8 │ 0：原样负载                                  │
+//! │            │ 其他：original_len: u32 BE + 压缩后的负载     │
+//! └────────────┴──────────────────────────────────────────────┘
+//! ```
+//!
+//! 帧头的编解码见 `protocol` 模块。
+
+use crate::error::{Result, VirgeError};
+use crate::protocol::{FrameHeader, Layer};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::{AckStats, Transport, VsockAddr};
+use async_trait::async_trait;
+use std::io::IoSlice;
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 小于该字节数的消息不做压缩
+pub(crate) const COMPRESSION_THRESHOLD: usize = 256;
+
+/// 原样发送时的帧头字节数，也是压缩包装器对单条消息增加的最大开销
+pub(crate) const HEADER_SIZE: usize = crate::protocol::COMPRESSION_HEADER_SIZE;
+
+/// 压缩帧的帧头字节数：算法字节加原始长度
+const COMPRESSED_HEADER_SIZE: usize = crate::protocol::COMPRESSED_HEADER_SIZE;
+
+/// 帧头中的算法字节，同时用于握手；0 表示原样负载
+#[cfg(feature = "compression-lz4")]
+const METHOD_LZ4: u8 = 1;
+#[cfg(feature = "compression-zstd")]
+const METHOD_ZSTD: u8 = 2;
+
+/// 压缩算法
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// LZ4 块压缩，速度快、压缩率一般
+    #[cfg(feature = "compression-lz4")]
+    Lz4,
+    /// Zstandard 压缩，`level` 取 1..=22，越大压缩率越高、越慢
+    #[cfg(feature = "compression-zstd")]
+    Zstd { level: i32 },
+}
+
+impl Compression {
+    /// 握手与帧头中的算法字节
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            #[cfg(feature = "compression-lz4")]
+            Compression::Lz4 => METHOD_LZ4,
+            #[cfg(feature = "compression-zstd")]
+            Compression::Zstd { .. } => METHOD_ZSTD,
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "compression-lz4")]
+            Compression::Lz4 => Ok(lz4_flex::block::compress(data)),
+            #[cfg(feature = "compression-zstd")]
+            Compression::Zstd { level } => zstd::bulk::compress(data, level)
+                .map_err(|e| VirgeError::Other(format!("zstd compression failed: {}", e))),
+        }
+    }
+}
+
+/// 按帧头的算法字节解压，`len` 为已校验过的原始长度
+fn decompress(method: u8, data: &[u8], len: usize) -> Result<Vec<u8>> {
+    let decoded = match method {
+        #[cfg(feature = "compression-lz4")]
+        METHOD_LZ4 => lz4_flex::block::decompress(data, len)
+            .map_err(|e| VirgeError::ProtocolError(format!("Invalid lz4 frame: {}", e)))?,
+        #[cfg(feature = "compression-zstd")]
+        METHOD_ZSTD => zstd::bulk::decompress(data, len)
+            .map_err(|e| VirgeError::ProtocolError(format!("Invalid zstd frame: {}", e)))?,
+        other => {
+            return Err(VirgeError::ProtocolError(format!(
+                "Unknown compression method {} in frame",
+                other
+            )));
+        }
+    };
+    if decoded.len() != len {
+        return Err(VirgeError::ProtocolError(format!(
+            "Decompressed {} bytes, frame header declared {}",
+            decoded.len(),
+            len
+        )));
+    }
+    Ok(decoded)
+}
+
+/// 压缩包装器
+pub(crate) struct CompressionTransport {
+    inner: Box<dyn Transport>,
+    compression: Compression,
+    /// 解压后单条消息允许的最大字节数
+    max_len: usize,
+}
+
+impl CompressionTransport {
+    pub(crate) fn new(inner: Box<dyn Transport>, compression: Compression, max_len: usize) -> Self {
+        Self { inner, compression, max_len }
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() >= COMPRESSION_THRESHOLD && data.len() <= u32::MAX as usize {
+            let compressed = self.compression.compress(data)?;
+            if COMPRESSED_HEADER_SIZE + compressed.len() < HEADER_SIZE + data.len() {
+                let mut frame = Vec::with_capacity(COMPRESSED_HEADER_SIZE + compressed.len());
+                FrameHeader::Compressed {
+                    method: self.compression.to_byte(),
+                    original_len: data.len() as u32,
+                }
+                .encode_into(&mut frame);
+                frame.extend_from_slice(&compressed);
+                return Ok(frame);
+            }
+        }
+        let mut frame = Vec::with_capacity(HEADER_SIZE + data.len());
+        FrameHeader::Stored.encode_into(&mut frame);
+        frame.extend_from_slice(data);
+        Ok(frame)
+    }
+
+    fn decode(&self, mut frame: Vec<u8>) -> Result<Vec<u8>> {
+        // 空帧来自下层（如对端关闭），原样交给调用方处理
+        if frame.is_empty() {
+            return Ok(frame);
+        }
+        let (method, len, header_len) = match FrameHeader::decode(Layer::Compression, &frame)? {
+            (FrameHeader::Compressed { method, original_len }, header_len) => {
+                (method, original_len as usize, header_len)
+            }
+            (_, header_len) => {
+                frame.drain(..header_len);
+                return Ok(frame);
+            }
+        };
+        if len > self.max_len {
+            return Err(VirgeError::MessageTooLarge { size: len, max: self.max_len });
+        }
+        decompress(method, &frame[header_len..], len)
+    }
+}
+
+#[async_trait]
+impl Transport for CompressionTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.connect(cid, port, chunksize, isack).await
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.inner.from_tokio_stream(stream).await
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.inner.from_tcp_stream(stream).await
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.inner.from_uds_stream(stream).await
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        self.inner.from_seqpacket(socket).await
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.from_stream(stream, chunksize, isack).await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        self.inner.disconnect_with_reason(code, message).await
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        let frame = self.encode(&data)?;
+        self.inner.send(frame).await
+    }
+
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        // 压缩需要连续的输入，先合并各缓冲区
+        let data: Vec<u8> = slices.iter().flat_map(|slice| slice.iter().copied()).collect();
+        let frame = self.encode(&data)?;
+        self.inner.send(frame).await?;
+        Ok(data.len())
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        let frame = self.inner.recv().await?;
+        self.decode(frame)
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.inner.try_recv().await? {
+            Some(frame) => self.decode(frame).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        let frame = self.encode(data)?;
+        let sent = self.inner.try_send(&frame).await?;
+        Ok(sent.map(|_| data.len()))
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        self.inner.shutdown_write().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        self.inner.probe(timeout).await
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.inner.last_ack_latency()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.inner.ack_stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        self.inner.last_sent_seq()
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        self.inner.last_received_seq()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::tests::Rng;
+
+    const MAX_LEN: usize = 1 << 20;
+
+    /// 当前启用的全部压缩算法
+    fn algorithms() -> Vec<Compression> {
+        let mut algorithms = Vec::new();
+        #[cfg(feature = "compression-lz4")]
+        algorithms.push(Compression::Lz4);
+        #[cfg(feature = "compression-zstd")]
+        algorithms.extend([Compression::Zstd { level: 1 }, Compression::Zstd { level: 19 }]);
+        algorithms
+    }
+
+    fn transport(compression: Compression) -> CompressionTransport {
+        CompressionTransport::new(Box::new(crate::split::Detached), compression, MAX_LEN)
+    }
+
+    /// 字段名反复出现的 JSON 遥测数据
+    fn telemetry(records: usize) -> Vec<u8> {
+        (0..records)
+            .map(|i| format!(r#"{{"seq":{},"cpu":{},"mem_kib":{},"state":"running"}}"#, i, i % 100, 4096 + i % 7))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into_bytes()
+    }
+
+    #[test]
+    fn compressible_data_shrinks_and_round_trips() {
+        let data = telemetry(2000);
+        for compression in algorithms() {
+            let transport = transport(compression);
+            let frame = transport.encode(&data).unwrap();
+            assert_eq!(frame[0], compression.to_byte(), "{:?}", compression);
+            assert!(frame.len() * 4 < data.len(), "{:?}: {} -> {} bytes", compression, data.len(), frame.len());
+            assert!(transport.decode(frame).unwrap() == data, "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn random_data_is_stored_and_round_trips() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        for compression in algorithms() {
+            let transport = transport(compression);
+            for len in [0, 1, COMPRESSION_THRESHOLD - 1, COMPRESSION_THRESHOLD, 64 * 1024] {
+                let data = rng.bytes(len);
+                let frame = transport.encode(&data).unwrap();
+                // 随机数据压缩后不会变小，原样发送
+                assert_eq!(frame.len(), HEADER_SIZE + len, "{:?}, {} bytes", compression, len);
+                assert!(transport.decode(frame).unwrap() == data, "{:?}, {} bytes", compression, len);
+            }
+        }
+    }
+
+    #[test]
+    fn small_messages_skip_compression() {
+        let data = vec![b'a'; COMPRESSION_THRESHOLD - 1];
+        for compression in algorithms() {
+            let frame = transport(compression).encode(&data).unwrap();
+            assert_eq!(frame.len(), HEADER_SIZE + data.len(), "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn declared_length_over_the_limit_is_rejected() {
+        let data = vec![0u8; 2 * MAX_LEN];
+        for compression in algorithms() {
+            let mut sender = transport(compression);
+            sender.max_len = usize::MAX;
+            let frame = sender.encode(&data).unwrap();
+            let err = transport(compression).decode(frame).unwrap_err();
+            assert!(matches!(err, VirgeError::MessageTooLarge { size, max } if size == data.len() && max == MAX_LEN));
+        }
+    }
+
+    #[test]
+    fn corrupted_compressed_frames_are_protocol_errors() {
+        let data = telemetry(100);
+        for compression in algorithms() {
+            let transport = transport(compression);
+            let mut frame = transport.encode(&data).unwrap();
+            frame.truncate(frame.len() / 2);
+            let err = transport.decode(frame).unwrap_err();
+            assert!(matches!(err, VirgeError::ProtocolError(_)), "{:?}: {:?}", compression, err);
+        }
+    }
+}
@@ -0,0 +1,395 @@
+//! 故障注入模块
+//!
+//! 启用 `testing` 特性后可用，以包装器的形式叠加在任意传输协议之上，按 [`FaultPlan`] 模拟链路故障，
+//! 用于验证应用在连接中断、消息损坏或延迟时的行为：
+//! - 累计收发超过 N 字节后断开连接，越过阈值的那条消息不会被发送或交给调用方
+//! - 每次收发前注入固定延迟
+//! - 截断、重复第 N 条收到的消息（从 0 开始计数），或翻转其中一个字节
+//! - 让下一次调用返回指定的 `VirgeError`
+//! - 让接下来的若干次发送返回指定 errno 的 IO 错误，模拟内存紧张时连续的 `EAGAIN`、`ENOBUFS`
+//!
+//! 通过配置的 `with_fault_plan` 注入时，故障位于最内层，计数的是传输层实际收发的帧，
+//! 包含 ACK、心跳等控制帧及校验、压缩的开销。`FaultPlan` 可克隆，克隆共享同一份状态，
+//! 连接建立后仍可通过保留的克隆调用 `fail_next` 注入错误。
+
+use crate::error::{Result, VirgeError};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::{AckStats, Transport, VsockAddr};
+use async_trait::async_trait;
+use log::*;
+use std::io::{self, IoSlice};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+/// 故障计划与运行期计数
+#[derive(Debug, Default)]
+struct FaultState {
+    /// 累计收发字节数上限，超过后断开连接
+    drop_after: Option<u64>,
+    /// 每次收发前的延迟
+    latency: Option<Duration>,
+    /// 截断第 N 条收到的消息到指定长度
+    truncate: Option<(u64, usize)>,
+    /// 重复交付第 N 条收到的消息
+    duplicate: Option<u64>,
+    /// 翻转第 N 条收到的消息中指定偏移处字节的全部比特
+    corrupt: Option<(u64, usize)>,
+    /// 下一次调用返回的错误
+    next_error: Option<VirgeError>,
+    /// 接下来的发送返回的 errno 与剩余次数
+    send_errors: Option<(i32, u64)>,
+    /// 已收发的字节数
+    transferred: u64,
+    /// 已收到的消息数
+    received: u64,
+}
+
+/// 故障计划，克隆共享同一份状态
+#[derive(Clone, Debug, Default)]
+pub struct FaultPlan {
+    state: Arc<Mutex<FaultState>>,
+}
+
+/// 同一份计划及其克隆相等，内容相同但各自创建的计划不相等，因为它们的运行期计数互不影响
+impl PartialEq for FaultPlan {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl FaultPlan {
+    /// 创建不注入任何故障的计划
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 累计收发超过 `bytes` 字节后断开连接，返回 `ConnectionError`
+    pub fn with_drop_after_bytes(self, bytes: u64) -> Self {
+        self.lock().drop_after = Some(bytes);
+        self
+    }
+
+    /// 每次 send/recv 前等待 `latency`
+    pub fn with_latency(self, latency: Duration) -> Self {
+        self.lock().latency = Some(latency);
+        self
+    }
+
+    /// 将第 `nth` 条收到的消息截断为 `len` 字节
+    pub fn with_truncate(self, nth: u64, len: usize) -> Self {
+        self.lock().truncate = Some((nth, len));
+        self
+    }
+
+    /// 将第 `nth` 条收到的消息交付两次
+    pub fn with_duplicate(self, nth: u64) -> Self {
+        self.lock().duplicate = Some(nth);
+        self
+    }
+
+    /// 翻转第 `nth` 条收到的消息中偏移 `offset` 处的字节，偏移超出消息长度时该消息不受影响
+    pub fn with_corrupt(self, nth: u64, offset: usize) -> Self {
+        self.lock().corrupt = Some((nth, offset));
+        self
+    }
+
+    /// 让下一次连接、收发调用返回 `error`，之后恢复正常
+    pub fn fail_next(&self, error: VirgeError) {
+        self.lock().next_error = Some(error);
+    }
+
+    /// 让接下来的 `count` 次发送返回 errno 为 `errno` 的 IO 错误，之后恢复正常；`count` 为 0 时取消尚未用完的错误
+    ///
+    /// 这些发送不会到达下层传输，适合验证 `with_send_retry` 的重试行为。
+    pub fn fail_sends(&self, count: u64, errno: i32) {
+        self.lock().send_errors = (count > 0).then_some((errno, count));
+    }
+
+    /// 已收发的字节数
+    pub fn transferred(&self) -> u64 {
+        self.lock().transferred
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FaultState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 故障注入包装器
+pub struct FaultyTransport {
+    inner: Box<dyn Transport>,
+    plan: FaultPlan,
+    /// 等待再次交付的重复消息
+    duplicate: Option<Vec<u8>>,
+}
+
+impl FaultyTransport {
+    /// 按 `plan` 为 `inner` 注入故障
+    pub fn new(inner: impl Transport + 'static, plan: FaultPlan) -> Self {
+        Self::boxed(Box::new(inner), plan)
+    }
+
+    pub(crate) fn boxed(inner: Box<dyn Transport>, plan: FaultPlan) -> Self {
+        Self { inner, plan, duplicate: None }
+    }
+
+    /// 每次调用前：返回待注入的错误，或注入延迟
+    async fn before_call(&mut self) -> Result<()> {
+        let (error, latency) = {
+            let mut state = self.plan.lock();
+            (state.next_error.take(), state.latency)
+        };
+        if let Some(error) = error {
+            debug!("Fault: injecting {}", error);
+            return Err(error);
+        }
+        if let Some(latency) = latency {
+            crate::transport::sleep(latency).await;
+        }
+        Ok(())
+    }
+
+    /// 每次发送前：消耗一次待注入的发送错误
+    fn before_send(&self) -> Result<()> {
+        let mut state = self.plan.lock();
+        let Some((errno, remaining)) = state.send_errors else {
+            return Ok(());
+        };
+        state.send_errors = (remaining > 1).then_some((errno, remaining - 1));
+        debug!("Fault: failing send with errno {}", errno);
+        Err(VirgeError::IoError(io::Error::from_raw_os_error(errno)))
+    }
+
+    /// 累计收发字节数，越过阈值时断开下层连接
+    async fn account(&mut self, bytes: usize) -> Result<()> {
+        let limit = {
+            let mut state = self.plan.lock();
+            state.transferred = state.transferred.saturating_add(bytes as u64);
+            state.drop_after.filter(|&limit| state.transferred > limit)
+        };
+        if let Some(limit) = limit {
+            warn!("Fault: dropping connection after {} bytes", limit);
+            if let Err(e) = self.inner.disconnect().await {
+                debug!("Fault: disconnect error: {}", e);
+            }
+            return Err(VirgeError::connection(format!(
+                "Fault injected: connection dropped after {} bytes",
+                limit
+            )));
+        }
+        Ok(())
+    }
+
+    /// 对收到的消息应用截断、字节翻转与重复
+    async fn deliver(&mut self, mut data: Vec<u8>) -> Result<Vec<u8>> {
+        self.account(data.len()).await?;
+        let mut state = self.plan.lock();
+        let index = state.received;
+        state.received += 1;
+        if let Some((_, len)) = state.truncate.filter(|&(nth, _)| nth == index) {
+            debug!("Fault: truncating message {} from {} to {} bytes", index, data.len(), len);
+            data.truncate(len);
+        }
+        if let Some((_, offset)) = state.corrupt.filter(|&(nth, _)| nth == index) {
+            if let Some(byte) = data.get_mut(offset) {
+                debug!("Fault: flipping byte {} of message {}", offset, index);
+                *byte ^= 0xff;
+            }
+        }
+        if state.duplicate == Some(index) {
+            debug!("Fault: duplicating message {}", index);
+            self.duplicate = Some(data.clone());
+        }
+        Ok(data)
+    }
+}
+
+#[async_trait]
+impl Transport for FaultyTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        self.before_call().await?;
+        self.duplicate = None;
+        self.inner.connect(cid, port, chunksize, isack).await
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        self.before_call().await?;
+        self.duplicate = None;
+        self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.duplicate = None;
+        self.inner.from_tokio_stream(stream).await
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.duplicate = None;
+        self.inner.from_tcp_stream(stream).await
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.duplicate = None;
+        self.inner.from_uds_stream(stream).await
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        self.duplicate = None;
+        self.inner.from_seqpacket(socket).await
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        self.duplicate = None;
+        self.inner.from_stream(stream, chunksize, isack).await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        self.inner.disconnect_with_reason(code, message).await
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        self.before_call().await?;
+        self.before_send()?;
+        self.account(data.len()).await?;
+        self.inner.send(data).await
+    }
+
+    async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        self.before_call().await?;
+        self.before_send()?;
+        self.account(data.len()).await?;
+        self.inner.send_noack(data).await
+    }
+
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        self.before_call().await?;
+        self.before_send()?;
+        self.account(slices.iter().map(|slice| slice.len()).sum()).await?;
+        self.inner.send_slices(slices).await
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        if let Some(data) = self.duplicate.take() {
+            return Ok(data);
+        }
+        self.before_call().await?;
+        let data = self.inner.recv().await?;
+        self.deliver(data).await
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        if let Some(data) = self.duplicate.take() {
+            return Ok(Some(data));
+        }
+        self.before_call().await?;
+        match self.inner.try_recv().await? {
+            Some(data) => self.deliver(data).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        self.before_call().await?;
+        self.before_send()?;
+        self.account(data.len()).await?;
+        self.inner.try_send(data).await
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        self.before_call().await?;
+        self.inner.shutdown_write().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.before_call().await?;
+        self.inner.flush().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        self.inner.probe(timeout).await
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.inner.last_ack_latency()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.inner.ack_stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        self.inner.last_sent_seq()
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        self.inner.last_received_seq()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
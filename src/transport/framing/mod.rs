@@ -0,0 +1,1040 @@
+//! 消息分帧模块
+//!
+//! 在传输层之上实现 8 字节大端长度前缀的消息帧，供 `VirgeClient` 与 `VirgeServer` 复用；
+//! 同时提供基于字节流的传输（raw、yamux）在流上划分消息边界所用的 4 字节流帧。
+//!
+//! # 帧格式
+//! ```text
+//! ┌──────────────────┬──────────────────┐
+//! │ length: u64 (BE) │ payload: [u8]    │
+//! └──────────────────┴──────────────────┘
+//! ```
+//! 长度事先未知的消息以保留长度前缀开始，负载分块发送，见 `protocol` 模块的消息帧一节。
+//!
+//! 帧头的编解码见 `protocol` 模块。
+
+use crate::error::{Result, VirgeError};
+use crate::protocol::{
+    FrameHeader, Layer, CHUNK_HEADER_SIZE, CLOSE_REASON_HEADER_SIZE, MAX_CLOSE_REASON_SIZE, STREAM_CLOSE_LEN,
+    STREAM_CLOSE_REASON_LEN, STREAM_EOF_LEN,
+};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::Transport;
+use log::*;
+use std::sync::Arc;
+
+/// 长度前缀的字节数
+pub(crate) const LEN_PREFIX_SIZE: usize = crate::protocol::LEN_PREFIX_SIZE;
+
+/// 流帧头的字节数（u32 大端长度）
+pub(crate) const STREAM_HEADER_SIZE: usize = crate::protocol::STREAM_HEADER_SIZE;
+
+/// 半关闭控制帧，由 `shutdown_write` 写入流中
+pub(crate) const STREAM_EOF: [u8; STREAM_HEADER_SIZE] = STREAM_EOF_LEN.to_be_bytes();
+
+/// 正常关闭控制帧，由 `disconnect` 在关闭字节流前写入
+pub(crate) const STREAM_CLOSE: [u8; STREAM_HEADER_SIZE] = STREAM_CLOSE_LEN.to_be_bytes();
+
+/// 按长度前缀预留重组缓冲区的上限，更大的消息随数据到达继续增长，
+/// 避免对端仅凭一个长度前缀就让本端分配大块内存
+const PRESIZE_LIMIT: usize = 1024 * 1024;
+
+/// 分块消息逐块写入 `writer` 时每次复制的最大字节数
+const COPY_SIZE: usize = 64 * 1024;
+
+/// 携带关闭原因的关闭帧的前 4 字节
+const STREAM_CLOSE_REASON: [u8; STREAM_HEADER_SIZE] = STREAM_CLOSE_REASON_LEN.to_be_bytes();
+
+/// 将关闭原因说明截断到 `MAX_CLOSE_REASON_SIZE` 字节以内，截断位置落在字符边界上
+pub(crate) fn truncate_reason(message: &str) -> &str {
+    let mut end = message.len().min(MAX_CLOSE_REASON_SIZE);
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    &message[..end]
+}
+
+/// 编码 `disconnect_with_reason` 的关闭帧；原因码为 0 且没有说明时即普通的正常关闭帧
+pub(crate) fn close_frame(code: u32, message: &str) -> Vec<u8> {
+    let message = truncate_reason(message);
+    if code == 0 && message.is_empty() {
+        return STREAM_CLOSE.to_vec();
+    }
+    let mut frame = Vec::with_capacity(CLOSE_REASON_HEADER_SIZE + message.len());
+    FrameHeader::StreamCloseReason { code, len: message.len() as u16 }.encode_into(&mut frame);
+    frame.extend_from_slice(message.as_bytes());
+    frame
+}
+
+/// 对端正常关闭连接后 recv 返回的错误：携带原因时为 `PeerClosed`，否则为 `EndOfStream`
+pub(crate) fn closed_error(reason: &Option<(u32, String)>) -> VirgeError {
+    match reason {
+        Some((code, message)) => VirgeError::PeerClosed { code: *code, message: message.clone() },
+        None => VirgeError::EndOfStream,
+    }
+}
+
+/// 校验消息大小是否超过上限
+pub(crate) fn check_size(size: usize, max: usize) -> Result<()> {
+    if size > max {
+        return Err(VirgeError::MessageTooLarge { size, max });
+    }
+    Ok(())
+}
+
+/// 编码一条带长度前缀的消息
+pub(crate) fn encode(data: &[u8], max: usize) -> Result<Vec<u8>> {
+    check_size(data.len(), max)?;
+    let mut frame = Vec::with_capacity(LEN_PREFIX_SIZE + data.len());
+    FrameHeader::Message { len: data.len() as u64 }.encode_into(&mut frame);
+    frame.extend_from_slice(data);
+    Ok(frame)
+}
+
+/// 解析缓冲区中的长度前缀，前缀不完整或消息为分块消息（总长度事先未知）时返回 `Ok(None)`
+pub(crate) fn message_len(buf: &[u8], max: usize) -> Result<Option<usize>> {
+    if buf.len() < LEN_PREFIX_SIZE {
+        return Ok(None);
+    }
+    let (header, _) = FrameHeader::decode(Layer::Message, buf)?;
+    let Some(len) = header.payload_len() else {
+        return Ok(None);
+    };
+
+    // 在分配之前检查长度，防止对端构造超大长度前缀
+    let len = usize::try_from(len).unwrap_or(usize::MAX);
+    check_size(len, max)?;
+    Ok(Some(len))
+}
+
+/// 从缓冲区中取出一条完整消息，数据不足时返回 `Ok(None)`
+///
+/// 分块消息的发送方放弃了该消息时移除已收到的部分并返回 `MessageAborted`，后续消息不受影响。
+pub(crate) fn take_message(buf: &mut Vec<u8>, max: usize) -> Result<Option<Vec<u8>>> {
+    if buf.len() >= LEN_PREFIX_SIZE && FrameHeader::decode(Layer::Message, buf)?.0 == FrameHeader::ChunkedMessage {
+        return take_chunked(buf, max);
+    }
+    let Some(len) = message_len(buf, max)? else {
+        return Ok(None);
+    };
+    if buf.len() - LEN_PREFIX_SIZE < len {
+        return Ok(None);
+    }
+    let message = buf[LEN_PREFIX_SIZE..LEN_PREFIX_SIZE + len].to_vec();
+    buf.drain(..LEN_PREFIX_SIZE + len);
+    Ok(Some(message))
+}
+
+/// 解析 `buf` 中 `offset` 处的块头，数据不足时返回 `Ok(None)`
+fn chunk_header(buf: &[u8], offset: usize) -> Result<Option<FrameHeader>> {
+    if buf.len() < offset + CHUNK_HEADER_SIZE {
+        return Ok(None);
+    }
+    let (header, _) = FrameHeader::decode(Layer::Chunk, &buf[offset..])?;
+    Ok(Some(header))
+}
+
+/// 核对块头的消息序号：第一个块头确定当前消息的序号，之后的块头必须与之相同
+///
+/// 不同说明另一条分块消息的块交错了进来，返回 `ProtocolError`；放在块头位置的长度前缀等其他数据
+/// 被解析为块头时序号通常也对不上，同样在拼接出错误的消息之前被发现。
+fn check_chunk_msg(current: &mut Option<u32>, header: FrameHeader) -> Result<()> {
+    let (FrameHeader::Chunk { msg, .. } | FrameHeader::ChunkEnd { msg } | FrameHeader::ChunkAbort { msg }) = header
+    else {
+        return Ok(());
+    };
+    match *current {
+        Some(expected) if expected != msg => Err(VirgeError::ProtocolError(format!(
+            "Chunk of message {} interleaved into chunked message {}",
+            msg, expected
+        ))),
+        _ => {
+            *current = Some(msg);
+            Ok(())
+        }
+    }
+}
+
+/// 从缓冲区中取出一条完整的分块消息，数据不足时返回 `Ok(None)`
+///
+/// 已到达的块头声明的长度之和超过 `max` 时，在收齐这些块之前返回 `MessageTooLarge`。
+/// 块头的消息序号不一致时清空缓冲区并返回 `ProtocolError`，其中的数据已无法可靠地划分为消息。
+fn take_chunked(buf: &mut Vec<u8>, max: usize) -> Result<Option<Vec<u8>>> {
+    let mut end = LEN_PREFIX_SIZE;
+    let mut size = 0usize;
+    let mut msg = None;
+    loop {
+        let Some(header) = chunk_header(buf, end)? else {
+            return Ok(None);
+        };
+        if let Err(e) = check_chunk_msg(&mut msg, header) {
+            buf.clear();
+            return Err(e);
+        }
+        end += CHUNK_HEADER_SIZE;
+        match header {
+            FrameHeader::Chunk { len, .. } => {
+                size = size.saturating_add(len as usize);
+                check_size(size, max)?;
+                end += len as usize;
+                if buf.len() < end {
+                    return Ok(None);
+                }
+            }
+            FrameHeader::ChunkAbort { .. } => {
+                buf.drain(..end);
+                return Err(VirgeError::MessageAborted);
+            }
+            _ => break,
+        }
+    }
+
+    let mut message = Vec::with_capacity(size);
+    let mut offset = LEN_PREFIX_SIZE;
+    while let Some(FrameHeader::Chunk { len, .. }) = chunk_header(buf, offset)? {
+        let start = offset + CHUNK_HEADER_SIZE;
+        offset = start + len as usize;
+        message.extend_from_slice(&buf[start..offset]);
+    }
+    buf.drain(..end);
+    Ok(Some(message))
+}
+
+/// 当前消息尚未读取的负载
+///
+/// 定长消息只需记录剩余字节数；分块消息的总长度事先未知，当前块读完后还需解析下一个块头。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Remaining {
+    /// 当前块（定长消息即整条消息）尚未读取的字节数
+    pub(crate) bytes: u64,
+    /// 分块消息尚未读到结束或放弃块头
+    pub(crate) chunked: bool,
+    /// 分块消息第一个块头给出的消息序号，之后的块头须与之相同
+    msg: Option<u32>,
+}
+
+impl Remaining {
+    fn new(header: FrameHeader) -> Self {
+        match header {
+            FrameHeader::ChunkedMessage => Self { bytes: 0, chunked: true, msg: None },
+            header => Self { bytes: header.payload_len().unwrap_or_default(), chunked: false, msg: None },
+        }
+    }
+
+    /// 当前消息是否已读完
+    pub(crate) fn is_done(&self) -> bool {
+        self.bytes == 0 && !self.chunked
+    }
+
+    /// 从 `buf` 开头取出下一个块头，数据不足时返回 `Ok(false)`；读到放弃块头时返回 `MessageAborted`
+    ///
+    /// 块头的消息序号与之前的块头不同时返回 `ProtocolError`，当前消息随之结束。
+    fn next_chunk(&mut self, buf: &mut Vec<u8>) -> Result<bool> {
+        let Some(header) = chunk_header(buf, 0)? else {
+            return Ok(false);
+        };
+        if let Err(e) = check_chunk_msg(&mut self.msg, header) {
+            self.chunked = false;
+            return Err(e);
+        }
+        buf.drain(..CHUNK_HEADER_SIZE);
+        match header {
+            FrameHeader::Chunk { len, .. } => self.bytes = len as u64,
+            FrameHeader::ChunkAbort { .. } => {
+                self.chunked = false;
+                return Err(VirgeError::MessageAborted);
+            }
+            _ => self.chunked = false,
+        }
+        Ok(true)
+    }
+}
+
+/// 读取消息中途的数据：从传输层接收一次并追加到 `buf`
+///
+/// 超时照常返回；对端断开或返回空数据时返回 `ErrorKind::UnexpectedEof`。
+async fn recv_more(transport: &mut dyn Transport, buf: &mut Vec<u8>) -> Result<()> {
+    append_more(buf, transport.recv().await)
+}
+
+/// 将消息中途一次接收的结果追加到 `buf`，错误的处理与 `recv_more` 相同
+pub(crate) fn append_more(buf: &mut Vec<u8>, received: Result<Vec<u8>>) -> Result<()> {
+    match received {
+        Ok(data) if data.is_empty() => Err(unexpected_eof()),
+        Ok(data) if buf.is_empty() => {
+            *buf = data;
+            Ok(())
+        }
+        Ok(data) => {
+            buf.extend_from_slice(&data);
+            Ok(())
+        }
+        Err(e) if e.is_timeout() => Err(e),
+        Err(_) => Err(unexpected_eof()),
+    }
+}
+
+/// 将从传输层收到的一段数据追加到重组缓冲区
+///
+/// 缓冲区为空时直接接管 `data` 而不复制；长度前缀完整后按消息声明的长度一次预留空间（至多 [`PRESIZE_LIMIT`]），
+/// 跨越多个数据块的消息不再随每块数据反复扩容。
+fn append(buf: &mut Vec<u8>, data: Vec<u8>) {
+    if buf.is_empty() {
+        *buf = data;
+    } else {
+        buf.extend_from_slice(&data);
+    }
+    if let Ok((header, header_len)) = FrameHeader::decode(Layer::Message, buf) {
+        let len = usize::try_from(header.payload_len().unwrap_or_default()).unwrap_or(usize::MAX);
+        let wanted = header_len + len.min(PRESIZE_LIMIT);
+        buf.reserve(wanted.saturating_sub(buf.len()));
+    }
+}
+
+/// 编码流帧头，消息超过 `max` 或 u32 可表示的长度（不含保留的控制帧长度）时返回 `MessageTooLarge`
+pub(crate) fn stream_header(len: usize, max: usize) -> Result<Vec<u8>> {
+    check_size(len, max)?;
+    let len = u32::try_from(len)
+        .ok()
+        .filter(|&len| len < STREAM_CLOSE_REASON_LEN)
+        .ok_or(VirgeError::MessageTooLarge {
+            size: len,
+            max: STREAM_CLOSE_REASON_LEN as usize - 1,
+        })?;
+    Ok(FrameHeader::Stream { len }.to_bytes())
+}
+
+/// 字节流传输的流帧读缓冲区
+///
+/// 帧头声明的长度超过上限时立即返回 `MessageTooLarge`，不会为其缓冲或分配内存；
+/// 该帧的负载在后续读取中被直接丢弃，其后的帧仍可正常接收，连接无需断开。
+/// 每次从流中读取所用的缓冲区经 `read_chunk`/`recycle` 从接收缓冲区池取用与归还。
+pub(crate) struct StreamBuffer {
+    data: Vec<u8>,
+    pool: Arc<BufferPool>,
+    /// 超限帧尚未丢弃的负载字节数
+    discard: usize,
+    max: usize,
+    /// 已读到对端的正常关闭控制帧
+    closed: bool,
+    /// 对端关闭帧携带的原因码与说明
+    reason: Option<(u32, String)>,
+}
+
+impl StreamBuffer {
+    pub(crate) fn new(max: usize) -> Self {
+        Self {
+            data: Vec::new(),
+            pool: BufferPool::new(crate::DEFAULT_BUFFER_POOL_SIZE),
+            discard: 0,
+            max,
+            closed: false,
+            reason: None,
+        }
+    }
+
+    /// 改用至多保存 `size` 块空闲缓冲区的接收缓冲区池，0 表示不启用
+    pub(crate) fn set_pool_size(&mut self, size: usize) {
+        self.pool = BufferPool::new(size);
+    }
+
+    pub(crate) fn pool(&self) -> &Arc<BufferPool> {
+        &self.pool
+    }
+
+    /// 从接收缓冲区池取一块 `len` 字节的读缓冲区
+    pub(crate) fn read_chunk(&self, len: usize) -> Vec<u8> {
+        self.pool.take(len)
+    }
+
+    /// 归还 `read_chunk` 取出的读缓冲区
+    pub(crate) fn recycle(&self, chunk: Vec<u8>) {
+        self.pool.give_back(chunk);
+    }
+
+    pub(crate) fn max(&self) -> usize {
+        self.max
+    }
+
+    pub(crate) fn set_max(&mut self, max: usize) {
+        self.max = max;
+    }
+
+    /// 追加从流中读到的数据，先跳过超限帧的剩余负载
+    pub(crate) fn extend(&mut self, mut bytes: &[u8]) {
+        if self.discard > 0 {
+            let skipped = self.discard.min(bytes.len());
+            self.discard -= skipped;
+            bytes = &bytes[skipped..];
+        }
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// 取出一个完整的流帧，数据不足时返回 `Ok(None)`
+    ///
+    /// 遇到半关闭控制帧时返回一次 `EndOfStream`；遇到正常关闭控制帧时同样返回 `EndOfStream`，
+    /// 之后 `peer_closed()` 为 `true`，每次调用都返回 `EndOfStream`；关闭帧携带原因时改为返回 `PeerClosed`。
+    pub(crate) fn take_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.closed {
+            return Err(closed_error(&self.reason));
+        }
+        if self.data.len() < STREAM_HEADER_SIZE {
+            return Ok(None);
+        }
+        if self.data[..STREAM_HEADER_SIZE] == STREAM_CLOSE_REASON && self.data.len() < CLOSE_REASON_HEADER_SIZE {
+            return Ok(None);
+        }
+        let len = match FrameHeader::decode(Layer::Stream, &self.data)? {
+            (FrameHeader::StreamEof, _) => {
+                self.data.drain(..STREAM_HEADER_SIZE);
+                return Err(VirgeError::EndOfStream);
+            }
+            (FrameHeader::StreamClose, _) => {
+                self.data.clear();
+                self.closed = true;
+                return Err(VirgeError::EndOfStream);
+            }
+            (FrameHeader::StreamCloseReason { code, len }, header_len) => {
+                let end = header_len + len as usize;
+                if self.data.len() < end {
+                    return Ok(None);
+                }
+                let message = String::from_utf8_lossy(&self.data[header_len..end]).into_owned();
+                self.data.clear();
+                self.closed = true;
+                self.reason = Some((code, message));
+                return Err(closed_error(&self.reason));
+            }
+            (header, _) => header.payload_len().unwrap_or_default() as usize,
+        };
+
+        if len > self.max {
+            self.data.drain(..STREAM_HEADER_SIZE);
+            let skipped = len.min(self.data.len());
+            self.data.drain(..skipped);
+            self.discard = len - skipped;
+            return Err(VirgeError::MessageTooLarge { size: len, max: self.max });
+        }
+        if self.data.len() - STREAM_HEADER_SIZE < len {
+            return Ok(None);
+        }
+        let frame = self.data[STREAM_HEADER_SIZE..STREAM_HEADER_SIZE + len].to_vec();
+        self.data.drain(..STREAM_HEADER_SIZE + len);
+        Ok(Some(frame))
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.data.clear();
+        self.discard = 0;
+        self.closed = false;
+        self.reason = None;
+    }
+
+    /// 是否已读到对端的正常关闭控制帧，此后不会再有任何数据
+    pub(crate) fn peer_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// 接收一条带长度前缀的消息
+///
+/// 不足一条消息的数据保留在 `buf` 中；对端在消息中途断开时返回 `ErrorKind::UnexpectedEof`。
+/// 读超时照常返回，已收到的部分消息保留在 `buf` 中，之后的接收从中断处继续。
+pub(crate) async fn recv(transport: &mut dyn Transport, buf: &mut Vec<u8>, max: usize) -> Result<Vec<u8>> {
+    loop {
+        if let Some(message) = take_message(buf, max)? {
+            return Ok(message);
+        }
+
+        let partial = !buf.is_empty();
+        match transport.recv().await {
+            Ok(data) if data.is_empty() && partial => return Err(unexpected_eof()),
+            Ok(data) => append(buf, data),
+            Err(e) if e.is_timeout() => return Err(e),
+            Err(_) if partial => return Err(unexpected_eof()),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// 缓冲区开头是长度超过 `max` 的消息时移除其长度前缀，返回需丢弃的负载
+///
+/// 用于在 `MessageTooLarge` 之后跳过该消息，使后续消息仍可接收；开头为分块消息时总是移除，
+/// 其块长度之和超过 `max` 才会产生 `MessageTooLarge`。
+pub(crate) fn abort_oversized(buf: &mut Vec<u8>, max: usize) -> Result<Option<Remaining>> {
+    if buf.len() < LEN_PREFIX_SIZE {
+        return Ok(None);
+    }
+    let (header, header_len) = FrameHeader::decode(Layer::Message, buf)?;
+    if matches!(header, FrameHeader::Message { len } if usize::try_from(len).is_ok_and(|len| len <= max)) {
+        return Ok(None);
+    }
+    buf.drain(..header_len);
+    Ok(Some(Remaining::new(header)))
+}
+
+/// 丢弃 `remaining` 描述的负载，先丢弃缓冲区中的数据，不足时继续从传输层接收
+///
+/// 分块消息丢弃到结束或放弃块头为止。任何时刻只保留一次接收的数据；
+/// 出错时 `remaining` 记录尚未丢弃的部分，下次调用从中断处继续。
+pub(crate) async fn skip(transport: &mut dyn Transport, buf: &mut Vec<u8>, remaining: &mut Remaining) -> Result<()> {
+    loop {
+        let take = buf.len().min(usize::try_from(remaining.bytes).unwrap_or(usize::MAX));
+        buf.drain(..take);
+        remaining.bytes -= take as u64;
+        if remaining.is_done() {
+            return Ok(());
+        }
+        if remaining.bytes == 0 {
+            match remaining.next_chunk(buf) {
+                // 丢弃的消息被放弃与正常结束没有区别
+                Ok(true) | Err(VirgeError::MessageAborted) => continue,
+                Ok(false) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        recv_more(transport, buf).await?;
+    }
+}
+
+/// 接收一条带长度前缀的消息的前缀，返回其负载，负载留在传输层与 `buf` 中由调用方读取
+///
+/// 超时照常返回，已收到的部分前缀保留在 `buf` 中。
+pub(crate) async fn recv_prefix(transport: &mut dyn Transport, buf: &mut Vec<u8>) -> Result<Remaining> {
+    while buf.len() < LEN_PREFIX_SIZE {
+        let partial = !buf.is_empty();
+        match transport.recv().await {
+            Ok(data) if data.is_empty() && partial => return Err(unexpected_eof()),
+            Ok(data) => buf.extend_from_slice(&data),
+            Err(e) if e.is_timeout() => return Err(e),
+            Err(_) if partial => return Err(unexpected_eof()),
+            Err(e) => return Err(e),
+        }
+    }
+    let (header, header_len) = FrameHeader::decode(Layer::Message, buf)?;
+    buf.drain(..header_len);
+    Ok(Remaining::new(header))
+}
+
+/// 读取当前消息的至多 `out.len()` 字节负载，`remaining` 为该消息尚未读取的部分
+///
+/// 缓冲区为空时从传输层接收一次；属于后续消息的数据保留在 `buf` 中。消息已读完时返回 `Ok(0)`，
+/// 分块消息的发送方放弃了该消息时返回 `MessageAborted`，之后同样返回 `Ok(0)`。
+pub(crate) async fn read_payload(
+    transport: &mut dyn Transport,
+    buf: &mut Vec<u8>,
+    remaining: &mut Remaining,
+    out: &mut [u8],
+) -> Result<usize> {
+    loop {
+        if let Some(n) = take_payload(buf, remaining, out)? {
+            return Ok(n);
+        }
+        recv_more(transport, buf).await?;
+    }
+}
+
+/// 从 `buf` 中已收到的数据读出当前消息的负载，需要从传输层再接收一次时返回 `Ok(None)`
+pub(crate) fn take_payload(buf: &mut Vec<u8>, remaining: &mut Remaining, out: &mut [u8]) -> Result<Option<usize>> {
+    if out.is_empty() {
+        return Ok(Some(0));
+    }
+    while remaining.bytes == 0 {
+        if !remaining.chunked {
+            return Ok(Some(0));
+        }
+        if !remaining.next_chunk(buf)? {
+            return Ok(None);
+        }
+    }
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    let n = out.len().min(buf.len()).min(usize::try_from(remaining.bytes).unwrap_or(usize::MAX));
+    out[..n].copy_from_slice(&buf[..n]);
+    buf.drain(..n);
+    remaining.bytes -= n as u64;
+    Ok(Some(n))
+}
+
+/// 调用进度回调，回调 panic 时返回错误而不是展开到调用方
+fn report<F: FnMut(u64, u64)>(callback: &mut F, done: usize, total: usize) -> Result<()> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| callback(done as u64, total as u64)))
+        .map_err(|_| VirgeError::Other("Progress callback panicked".to_string()))
+}
+
+/// 按 `chunk_size` 分块发送一条带长度前缀的消息，每块发送后回调 (已发送字节数, 总字节数)
+///
+/// 接收方可使用 `recv_msg` 或 `recv_with_progress`。回调 panic 时对端已收到半条消息，
+/// 因此断开连接使对端以 `UnexpectedEof` 结束接收，而不会把后续数据误当作该消息的剩余部分。
+pub(crate) async fn send_with_progress<F: FnMut(u64, u64)>(
+    transport: &mut dyn Transport,
+    data: &[u8],
+    chunk_size: usize,
+    max: usize,
+    mut callback: F,
+) -> Result<()> {
+    check_size(data.len(), max)?;
+    transport.send(FrameHeader::Message { len: data.len() as u64 }.to_bytes()).await?;
+
+    let mut sent = 0;
+    for chunk in data.chunks(chunk_size.max(1)) {
+        transport.send(chunk.to_vec()).await?;
+        sent += chunk.len();
+        if let Err(e) = report(&mut callback, sent, data.len()) {
+            if let Err(close) = transport.disconnect().await {
+                debug!("Failed to close connection after aborted send: {}", close);
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// 接收一条带长度前缀的消息，每次从传输层收到数据后回调 (已接收负载字节数, 总字节数)
+///
+/// 回调 panic 时返回错误，已收到的数据保留在 `buf` 中，可继续通过 `recv_msg` 接收该消息。
+pub(crate) async fn recv_with_progress<F: FnMut(u64, u64)>(
+    transport: &mut dyn Transport,
+    buf: &mut Vec<u8>,
+    max: usize,
+    mut callback: F,
+) -> Result<Vec<u8>> {
+    loop {
+        if let Some(total) = message_len(buf, max)? {
+            report(&mut callback, (buf.len() - LEN_PREFIX_SIZE).min(total), total)?;
+        }
+        if let Some(message) = take_message(buf, max)? {
+            return Ok(message);
+        }
+
+        let partial = !buf.is_empty();
+        match transport.recv().await {
+            Ok(data) if data.is_empty() && partial => return Err(unexpected_eof()),
+            Ok(data) => append(buf, data),
+            Err(_) if partial => return Err(unexpected_eof()),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// 从 `reader` 读取 `len` 字节，按 `chunk_size` 分块作为一条带长度前缀的消息发送
+///
+/// 任何时刻只在内存中保留一个数据块；消息长度不受 `max_message_size` 限制，接收方需使用 `recv_stream`。
+/// `reader` 提前结束或读取失败时断开连接，对端以 `UnexpectedEof` 结束接收。
+pub(crate) async fn send_stream<R: std::io::Read>(
+    transport: &mut dyn Transport,
+    reader: &mut R,
+    len: u64,
+    chunk_size: usize,
+) -> Result<u64> {
+    transport.send(FrameHeader::Message { len }.to_bytes()).await?;
+
+    let mut chunk = vec![0u8; chunk_size.max(1)];
+    let mut sent = 0u64;
+    while sent < len {
+        let want = chunk.len().min(usize::try_from(len - sent).unwrap_or(usize::MAX));
+        let result = match reader.read(&mut chunk[..want]) {
+            Ok(0) => Err(VirgeError::IoError(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("reader ended after {} of {} bytes", sent, len),
+            ))),
+            Ok(n) => Ok(n),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => Err(e.into()),
+        };
+        let n = match result {
+            Ok(n) => n,
+            Err(e) => {
+                if let Err(close) = transport.disconnect().await {
+                    debug!("Failed to close connection after aborted send: {}", close);
+                }
+                return Err(e);
+            }
+        };
+        transport.send(chunk[..n].to_vec()).await?;
+        sent += n as u64;
+    }
+    Ok(sent)
+}
+
+/// 接收一条带长度前缀的消息并将负载逐块写入 `writer`，不在内存中组装完整消息
+///
+/// 返回写入的字节数；属于后续消息的数据保留在 `buf` 中。
+pub(crate) async fn recv_stream<W: std::io::Write>(
+    transport: &mut dyn Transport,
+    buf: &mut Vec<u8>,
+    writer: &mut W,
+) -> Result<u64> {
+    while buf.len() < LEN_PREFIX_SIZE {
+        let partial = !buf.is_empty();
+        match transport.recv().await {
+            Ok(data) => buf.extend_from_slice(&data),
+            Err(_) if partial => return Err(unexpected_eof()),
+            Err(e) => return Err(e),
+        }
+    }
+    let (header, header_len) = FrameHeader::decode(Layer::Message, buf)?;
+    buf.drain(..header_len);
+    if header == FrameHeader::ChunkedMessage {
+        return recv_chunks(transport, buf, writer).await;
+    }
+    let len = header.payload_len().unwrap_or_default();
+
+    let mut written = 0u64;
+    let mut pending = std::mem::take(buf);
+    loop {
+        let take = pending.len().min(usize::try_from(len - written).unwrap_or(usize::MAX));
+        writer.write_all(&pending[..take])?;
+        written += take as u64;
+        if written == len {
+            // 多出的数据属于下一条消息
+            buf.extend_from_slice(&pending[take..]);
+            writer.flush()?;
+            return Ok(written);
+        }
+        pending = match transport.recv().await {
+            Ok(data) => data,
+            Err(_) => return Err(unexpected_eof()),
+        };
+    }
+}
+
+/// 将分块消息的负载逐块写入 `writer`，长度前缀已被移除
+async fn recv_chunks<W: std::io::Write>(transport: &mut dyn Transport, buf: &mut Vec<u8>, writer: &mut W) -> Result<u64> {
+    let mut remaining = Remaining::new(FrameHeader::ChunkedMessage);
+    let mut chunk = vec![0u8; COPY_SIZE];
+    let mut written = 0u64;
+    loop {
+        let n = match read_payload(transport, buf, &mut remaining, &mut chunk).await {
+            Ok(0) => break,
+            Ok(n) => n,
+            // 与定长消息一致，中途超时的消息无法继续接收
+            Err(e) if e.is_timeout() => return Err(unexpected_eof()),
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&chunk[..n])?;
+        written += n as u64;
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+/// 以字节流方式读取数据，不区分消息边界
+///
+/// `pending` 为空时持续从传输层接收，空消息不会被视为 EOF；
+/// 仅当对端关闭连接（传输层返回 `ErrorKind::UnexpectedEof`）时返回 `Ok(0)`。
+pub(crate) async fn read(transport: &mut dyn Transport, pending: &mut Vec<u8>, buf: &mut [u8]) -> Result<usize> {
+    if buf.is_empty() || !fill(transport, pending).await? {
+        return Ok(0);
+    }
+    let n = buf.len().min(pending.len());
+    buf[..n].copy_from_slice(&pending[..n]);
+    pending.drain(..n);
+    Ok(n)
+}
+
+/// `pending` 为空时持续从传输层接收直到有数据，对端关闭连接时返回 `Ok(false)`
+pub(crate) async fn fill(transport: &mut dyn Transport, pending: &mut Vec<u8>) -> Result<bool> {
+    while pending.is_empty() {
+        match transport.recv().await {
+            Ok(data) => pending.extend_from_slice(&data),
+            Err(VirgeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// 将 `pending` 中直到 `delim`（包含）的数据移入 `out`，没有 `delim` 时移入全部数据
+///
+/// # Returns
+/// 找到 `delim` 时返回 `true`
+pub(crate) fn take_until(pending: &mut Vec<u8>, delim: u8, out: &mut Vec<u8>) -> bool {
+    match pending.iter().position(|&b| b == delim) {
+        Some(i) => {
+            out.extend(pending.drain(..=i));
+            true
+        }
+        None => {
+            out.append(pending);
+            false
+        }
+    }
+}
+
+/// 跨消息边界读取直到 `delim`（包含）或对端关闭连接，数据追加到 `out`
+///
+/// 出错时已读取的数据保留在 `out` 中，与 `BufRead::read_until` 一致。
+pub(crate) async fn read_until(
+    transport: &mut dyn Transport,
+    pending: &mut Vec<u8>,
+    delim: u8,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    while fill(transport, pending).await? {
+        if take_until(pending, delim, out) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// 将 `read_until` 读到的一行追加到 `line`，数据不是合法 UTF-8 时返回 `CodecError` 且 `line` 保持不变
+pub(crate) fn push_line(line: &mut String, bytes: Vec<u8>) -> Result<()> {
+    let text = String::from_utf8(bytes)
+        .map_err(|e| VirgeError::CodecError(format!("line is not valid UTF-8: {}", e)))?;
+    line.push_str(&text);
+    Ok(())
+}
+
+/// 去掉行尾的 `\n` 或 `\r\n`
+pub(crate) fn trim_newline(line: &mut String) {
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+}
+
+pub(crate) fn unexpected_eof() -> VirgeError {
+    VirgeError::IoError(std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        "peer disconnected in the middle of a message",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream_frame(payload: &[u8]) -> Vec<u8> {
+        let mut frame = stream_header(payload.len(), usize::MAX).unwrap();
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// 序号为 `msg` 的分块消息，以 `last` 块头结束
+    fn chunked(msg: u32, chunks: &[&[u8]], last: FrameHeader) -> Vec<u8> {
+        let mut buf = FrameHeader::ChunkedMessage.to_bytes();
+        for chunk in chunks {
+            FrameHeader::Chunk { msg, len: chunk.len() as u32 }.encode_into(&mut buf);
+            buf.extend_from_slice(chunk);
+        }
+        last.encode_into(&mut buf);
+        buf
+    }
+
+    #[test]
+    fn partial_length_prefix_waits_for_more_data() {
+        let frame = encode(b"hello", 16).unwrap();
+        for len in 0..LEN_PREFIX_SIZE {
+            assert_eq!(message_len(&frame[..len], 16).unwrap(), None);
+            let mut buf = frame[..len].to_vec();
+            assert_eq!(take_message(&mut buf, 16).unwrap(), None);
+            assert_eq!(buf.len(), len);
+        }
+    }
+
+    #[test]
+    fn partial_payload_waits_for_more_data() {
+        let frame = encode(b"hello", 16).unwrap();
+        let mut buf = frame[..frame.len() - 1].to_vec();
+        assert_eq!(take_message(&mut buf, 16).unwrap(), None);
+        buf.push(*frame.last().unwrap());
+        assert_eq!(take_message(&mut buf, 16).unwrap(), Some(b"hello".to_vec()));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn message_at_the_limit_is_accepted() {
+        let payload = vec![7u8; 32];
+        let mut buf = encode(&payload, 32).unwrap();
+        assert_eq!(message_len(&buf, 32).unwrap(), Some(32));
+        assert_eq!(take_message(&mut buf, 32).unwrap(), Some(payload));
+    }
+
+    #[test]
+    fn message_over_the_limit_is_rejected_before_buffering() {
+        assert!(matches!(encode(&[0; 33], 32), Err(VirgeError::MessageTooLarge { size: 33, max: 32 })));
+        // 只有长度前缀也立即失败，不等待负载到达
+        let buf = FrameHeader::Message { len: 33 }.to_bytes();
+        assert!(matches!(message_len(&buf, 32), Err(VirgeError::MessageTooLarge { size: 33, max: 32 })));
+        let buf = FrameHeader::Message { len: 1 << 40 }.to_bytes();
+        assert!(matches!(message_len(&buf, 32), Err(VirgeError::MessageTooLarge { .. })));
+    }
+
+    #[test]
+    fn zero_length_messages_are_delivered() {
+        let mut buf = encode(&[], 0).unwrap();
+        buf.extend(encode(b"x", 1).unwrap());
+        assert_eq!(take_message(&mut buf, 1).unwrap(), Some(Vec::new()));
+        assert_eq!(take_message(&mut buf, 1).unwrap(), Some(b"x".to_vec()));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn chunked_messages_are_reassembled() {
+        // 长度为 0 的块头即结束块头，因此这里的每一块都不为空
+        let full = chunked(3, &[b"ab", b"c", b"de"], FrameHeader::ChunkEnd { msg: 3 });
+        for len in 0..full.len() {
+            let mut buf = full[..len].to_vec();
+            assert_eq!(take_message(&mut buf, 5).unwrap(), None, "cut to {} bytes", len);
+        }
+        let mut buf = full.clone();
+        buf.extend(encode(b"next", 5).unwrap());
+        assert_eq!(take_message(&mut buf, 5).unwrap(), Some(b"abcde".to_vec()));
+        assert_eq!(take_message(&mut buf, 5).unwrap(), Some(b"next".to_vec()));
+    }
+
+    #[test]
+    fn chunked_message_over_the_limit_fails_before_all_chunks_arrive() {
+        let mut buf = FrameHeader::ChunkedMessage.to_bytes();
+        FrameHeader::Chunk { msg: 0, len: 4 }.encode_into(&mut buf);
+        buf.extend_from_slice(b"abcd");
+        FrameHeader::Chunk { msg: 0, len: 2 }.encode_into(&mut buf);
+        assert!(matches!(take_message(&mut buf, 5), Err(VirgeError::MessageTooLarge { size: 6, max: 5 })));
+    }
+
+    #[test]
+    fn aborted_chunked_message_is_removed() {
+        let mut buf = chunked(9, &[b"partial"], FrameHeader::ChunkAbort { msg: 9 });
+        buf.extend(encode(b"after", 16).unwrap());
+        assert!(matches!(take_message(&mut buf, 16), Err(VirgeError::MessageAborted)));
+        assert_eq!(take_message(&mut buf, 16).unwrap(), Some(b"after".to_vec()));
+    }
+
+    #[test]
+    fn interleaved_chunked_messages_are_rejected() {
+        let is_interleaved = |result: Result<Option<Vec<u8>>>| {
+            matches!(result, Err(VirgeError::ProtocolError(ref m)) if m.contains("interleaved"))
+        };
+
+        // 另一条消息的数据块插在中间
+        let mut buf = FrameHeader::ChunkedMessage.to_bytes();
+        FrameHeader::Chunk { msg: 1, len: 2 }.encode_into(&mut buf);
+        buf.extend_from_slice(b"ab");
+        FrameHeader::Chunk { msg: 2, len: 2 }.encode_into(&mut buf);
+        buf.extend_from_slice(b"xy");
+        FrameHeader::ChunkEnd { msg: 1 }.encode_into(&mut buf);
+        assert!(is_interleaved(take_message(&mut buf, 16)));
+        assert!(buf.is_empty());
+
+        // 另一条分块消息的长度前缀出现在块头的位置，不会被误当作放弃块头
+        let mut buf = FrameHeader::ChunkedMessage.to_bytes();
+        FrameHeader::Chunk { msg: 1, len: 2 }.encode_into(&mut buf);
+        buf.extend_from_slice(b"ab");
+        buf.extend(chunked(2, &[b"xy"], FrameHeader::ChunkEnd { msg: 2 }));
+        assert!(is_interleaved(take_message(&mut buf, 16)));
+
+        // 其他消息的结束块头
+        let mut buf = chunked(1, &[b"ab"], FrameHeader::ChunkEnd { msg: 0 });
+        assert!(is_interleaved(take_message(&mut buf, 16)));
+
+        // 逐块读取的路径同样校验
+        let mut remaining = Remaining::new(FrameHeader::ChunkedMessage);
+        let mut buf = FrameHeader::Chunk { msg: 5, len: 0x10 }.to_bytes();
+        assert!(remaining.next_chunk(&mut buf).unwrap());
+        assert_eq!(remaining.bytes, 0x10);
+        remaining.bytes = 0;
+        let mut buf = FrameHeader::Chunk { msg: 6, len: 1 }.to_bytes();
+        assert!(matches!(remaining.next_chunk(&mut buf), Err(VirgeError::ProtocolError(_))));
+        assert!(remaining.is_done());
+    }
+
+    #[test]
+    fn stream_header_rejects_reserved_lengths() {
+        assert_eq!(stream_header(0, 0).unwrap(), [0, 0, 0, 0]);
+        assert!(matches!(stream_header(9, 8), Err(VirgeError::MessageTooLarge { size: 9, max: 8 })));
+        let reserved = STREAM_CLOSE_REASON_LEN as usize;
+        assert!(matches!(stream_header(reserved, usize::MAX), Err(VirgeError::MessageTooLarge { .. })));
+        assert!(stream_header(reserved - 1, usize::MAX).is_ok());
+    }
+
+    #[test]
+    fn stream_buffer_waits_for_partial_headers_and_payloads() {
+        let frame = stream_frame(b"hello");
+        let mut buffer = StreamBuffer::new(16);
+        for byte in &frame[..frame.len() - 1] {
+            buffer.extend(&[*byte]);
+            assert_eq!(buffer.take_frame().unwrap(), None);
+        }
+        buffer.extend(&frame[frame.len() - 1..]);
+        assert_eq!(buffer.take_frame().unwrap(), Some(b"hello".to_vec()));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn stream_buffer_accepts_frames_at_the_limit_and_zero_length_frames() {
+        let mut buffer = StreamBuffer::new(4);
+        buffer.extend(&stream_frame(b""));
+        buffer.extend(&stream_frame(b"abcd"));
+        assert_eq!(buffer.take_frame().unwrap(), Some(Vec::new()));
+        assert_eq!(buffer.take_frame().unwrap(), Some(b"abcd".to_vec()));
+        assert_eq!(buffer.take_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn stream_buffer_discards_oversized_frames_and_keeps_going() {
+        let mut buffer = StreamBuffer::new(4);
+        let oversized = stream_frame(b"too large");
+        // 超限帧的负载分多次到达，剩余部分在后续 extend 中被丢弃
+        buffer.extend(&oversized[..STREAM_HEADER_SIZE + 2]);
+        assert!(matches!(buffer.take_frame(), Err(VirgeError::MessageTooLarge { size: 9, max: 4 })));
+        assert!(buffer.is_empty());
+        let mut rest = oversized[STREAM_HEADER_SIZE + 2..].to_vec();
+        rest.extend(stream_frame(b"ok"));
+        buffer.extend(&rest);
+        assert_eq!(buffer.take_frame().unwrap(), Some(b"ok".to_vec()));
+    }
+
+    #[test]
+    fn stream_buffer_reports_half_close_once() {
+        let mut buffer = StreamBuffer::new(16);
+        buffer.extend(&STREAM_EOF);
+        buffer.extend(&stream_frame(b"control"));
+        assert!(matches!(buffer.take_frame(), Err(VirgeError::EndOfStream)));
+        assert!(!buffer.peer_closed());
+        assert_eq!(buffer.take_frame().unwrap(), Some(b"control".to_vec()));
+    }
+
+    #[test]
+    fn stream_buffer_stays_closed_after_close_frames() {
+        let mut buffer = StreamBuffer::new(16);
+        buffer.extend(&STREAM_CLOSE);
+        buffer.extend(&stream_frame(b"ignored"));
+        for _ in 0..2 {
+            assert!(matches!(buffer.take_frame(), Err(VirgeError::EndOfStream)));
+        }
+        assert!(buffer.peer_closed());
+
+        let frame = close_frame(7, "bye");
+        let mut buffer = StreamBuffer::new(16);
+        for byte in &frame[..frame.len() - 1] {
+            buffer.extend(&[*byte]);
+            assert_eq!(buffer.take_frame().unwrap(), None);
+        }
+        buffer.extend(&frame[frame.len() - 1..]);
+        for _ in 0..2 {
+            let err = buffer.take_frame().unwrap_err();
+            assert!(matches!(err, VirgeError::PeerClosed { code: 7, ref message } if message == "bye"), "{}", err);
+        }
+        buffer.clear();
+        assert!(!buffer.peer_closed());
+    }
+
+    #[test]
+    fn close_frame_without_reason_is_a_plain_close() {
+        assert_eq!(close_frame(0, ""), STREAM_CLOSE);
+        let long = "é".repeat(MAX_CLOSE_REASON_SIZE);
+        let reason = truncate_reason(&long);
+        assert!(reason.len() <= MAX_CLOSE_REASON_SIZE);
+        assert!(long.starts_with(reason));
+        assert_eq!(close_frame(1, &long).len(), CLOSE_REASON_HEADER_SIZE + reason.len());
+    }
+}
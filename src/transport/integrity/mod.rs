@@ -0,0 +1,267 @@
+//! 完整性校验模块
+//!
+//! 以包装器的形式叠加在传输协议之上，发送时在每条消息末尾追加负载的 CRC32（IEEE，大端），
+//! 接收时重新计算并比对，不一致时返回 `VirgeError::IntegrityError`，该消息被丢弃，连接保持可用。
+//!
+//! 两端是否启用校验在握手中协商，设置不一致时连接建立即失败。
+//!
+//! # 帧格式
+//! ```text
+//! ┌──────────────────┬──────────────────┐
+//! │ payload: [u8]    │ crc32: u32 (BE)  │
+//! └──────────────────┴──────────────────┘
+//! ```
+
+use crate::error::{Result, VirgeError};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::{AckStats, Transport, VsockAddr};
+use async_trait::async_trait;
+use std::io::IoSlice;
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 校验和的字节数
+pub(crate) const CHECKSUM_SIZE: usize = crate::protocol::CHECKSUM_SIZE;
+
+/// CRC32 (IEEE 802.3) 查找表
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// 增量计算 CRC32
+#[derive(Clone, Copy)]
+struct Crc32(u32);
+
+impl Crc32 {
+    fn new() -> Self {
+        Self(0xFFFF_FFFF)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 = CRC32_TABLE[((self.0 ^ byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// 完整性校验包装器
+pub(crate) struct IntegrityTransport {
+    inner: Box<dyn Transport>,
+}
+
+impl IntegrityTransport {
+    pub(crate) fn new(inner: Box<dyn Transport>) -> Self {
+        Self { inner }
+    }
+
+    fn seal(data: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(data.len() + CHECKSUM_SIZE);
+        frame.extend_from_slice(data);
+        frame.extend_from_slice(&checksum(data).to_be_bytes());
+        frame
+    }
+
+    /// 校验并去掉帧尾的校验和
+    fn open(mut frame: Vec<u8>) -> Result<Vec<u8>> {
+        if frame.len() < CHECKSUM_SIZE {
+            return Err(VirgeError::transport(format!(
+                "Frame of {} bytes is shorter than the integrity checksum",
+                frame.len()
+            )));
+        }
+        let split = frame.len() - CHECKSUM_SIZE;
+        let mut trailer = [0u8; CHECKSUM_SIZE];
+        trailer.copy_from_slice(&frame[split..]);
+        let expected = u32::from_be_bytes(trailer);
+        frame.truncate(split);
+
+        let actual = checksum(&frame);
+        if actual != expected {
+            return Err(VirgeError::IntegrityError { expected, actual });
+        }
+        Ok(frame)
+    }
+}
+
+#[async_trait]
+impl Transport for IntegrityTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.connect(cid, port, chunksize, isack).await
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.inner.from_tokio_stream(stream).await
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.inner.from_tcp_stream(stream).await
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.inner.from_uds_stream(stream).await
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        self.inner.from_seqpacket(socket).await
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.from_stream(stream, chunksize, isack).await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        self.inner.disconnect_with_reason(code, message).await
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        self.inner.send(Self::seal(&data)).await
+    }
+
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        let mut crc = Crc32::new();
+        for slice in slices {
+            crc.update(slice);
+        }
+        let trailer = crc.finish().to_be_bytes();
+        let mut framed = Vec::with_capacity(slices.len() + 1);
+        framed.extend(slices.iter().map(|slice| IoSlice::new(slice)));
+        framed.push(IoSlice::new(&trailer));
+        let sent = self.inner.send_slices(&framed).await?;
+        Ok(sent - CHECKSUM_SIZE)
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        Self::open(self.inner.recv().await?)
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        self.inner.try_recv().await?.map(Self::open).transpose()
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        let sent = self.inner.try_send(&Self::seal(data)).await?;
+        Ok(sent.map(|_| data.len()))
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        self.inner.shutdown_write().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        self.inner.probe(timeout).await
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.inner.last_ack_latency()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.inner.ack_stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        self.inner.last_sent_seq()
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        self.inner.last_received_seq()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
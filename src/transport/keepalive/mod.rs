@@ -0,0 +1,398 @@
+//! 心跳保活模块
+//!
+//! 以包装器的形式叠加在任意传输协议之上，为每条消息添加 1 字节帧类型，
+//! 用于区分用户数据与 PING/PONG 心跳帧。
+//!
+//! # 机制
+//! - 接收方向空闲超过 `interval` 时发送 PING，收到任意帧即视为对端存活
+//! - 发出 PING 后超过 `timeout` 仍未收到任何帧，recv/send 返回 `ConnectionError { message: "peer timed out", .. }`
+//! - PING 在 recv/try_recv 中自动应答 PONG，心跳帧不会出现在用户可见的接收结果中
+//! - `probe` 立即发送 PING 并等待对端的任意帧，期间收到的用户数据暂存，由之后的 recv/try_recv 返回
+//! - PING 超过 `timeout` 未获回应后 `is_connected()` 返回 `false`，无需等到下一次收发失败
+//!
+//! 两端必须同时启用 keepalive；空闲一端需定期调用 recv/try_recv 才能应答对端的 PING。
+//!
+//! # 帧格式
+//! ```text
+//! ┌──────────────┬──────────────────┐
+//! │ type: u8     │ payload: [u8]    │
+//! └──────────────┴──────────────────┘
+//! ```
+//!
+//! 帧头的编解码见 `protocol` 模块。
+
+use crate::error::{Result, VirgeError};
+use crate::protocol::{FrameHeader, Layer};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::{check_timeout, AckStats, Transport, VsockAddr};
+use async_trait::async_trait;
+use log::*;
+use std::collections::VecDeque;
+use std::io::IoSlice;
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// 心跳参数
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct KeepaliveConfig {
+    pub(crate) interval: Duration,
+    pub(crate) timeout: Duration,
+}
+
+impl KeepaliveConfig {
+    fn validate(&self) -> Result<()> {
+        if self.interval.is_zero() {
+            return Err(VirgeError::ConfigError("Keepalive interval must be non-zero".to_string()));
+        }
+        if self.timeout < self.interval {
+            return Err(VirgeError::ConfigError(format!(
+                "Keepalive timeout {:?} is shorter than interval {:?}",
+                self.timeout, self.interval
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// 心跳保活包装器
+pub(crate) struct KeepaliveTransport {
+    inner: Box<dyn Transport>,
+    config: KeepaliveConfig,
+    /// 用户设置的读超时
+    read_timeout: Option<Duration>,
+    /// 最近一次收到任意帧的时间
+    last_seen: Instant,
+    /// 最近一次发送 PING 的时间
+    last_ping: Option<Instant>,
+    /// 已发送 PING 但尚未收到对端任何帧的起始时间
+    awaiting_since: Option<Instant>,
+    /// `probe` 等待回应期间收到的用户数据
+    pending: VecDeque<Vec<u8>>,
+}
+
+impl KeepaliveTransport {
+    pub(crate) fn new(inner: Box<dyn Transport>, config: KeepaliveConfig) -> Self {
+        Self {
+            inner,
+            config,
+            read_timeout: None,
+            last_seen: Instant::now(),
+            last_ping: None,
+            awaiting_since: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// 连接建立后重置心跳状态，并让底层 recv 按心跳间隔醒来
+    fn start(&mut self) -> Result<()> {
+        self.last_seen = Instant::now();
+        self.last_ping = None;
+        self.awaiting_since = None;
+        self.pending.clear();
+        self.apply_read_timeout()
+    }
+
+    /// 底层读超时取心跳间隔与用户读超时中较小者
+    fn apply_read_timeout(&mut self) -> Result<()> {
+        let timeout = match self.read_timeout {
+            Some(timeout) => timeout.min(self.config.interval),
+            None => self.config.interval,
+        };
+        self.inner.set_read_timeout(Some(timeout))
+    }
+
+    /// 发出的 PING 超过 `timeout` 仍未收到对端任何帧
+    fn timed_out(&self) -> bool {
+        self.awaiting_since.is_some_and(|since| since.elapsed() >= self.config.timeout)
+    }
+
+    fn check_alive(&self) -> Result<()> {
+        if self.timed_out() {
+            warn!("Keepalive: no response from peer within {:?}", self.config.timeout);
+            return Err(VirgeError::connection("peer timed out"));
+        }
+        Ok(())
+    }
+
+    /// 距离上次收到数据超过心跳间隔时发送 PING
+    async fn ping_if_idle(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let idle = now.duration_since(self.last_seen) >= self.config.interval;
+        let due = self.last_ping.is_none_or(|last| now.duration_since(last) >= self.config.interval);
+        if idle && due {
+            debug!("Keepalive: sending ping");
+            self.inner.send(FrameHeader::Ping.to_bytes()).await?;
+            self.last_ping = Some(now);
+            self.awaiting_since.get_or_insert(now);
+        }
+        Ok(())
+    }
+
+    /// 立即发送 PING，在 `timeout` 内等待对端的任意帧
+    async fn ping_and_wait(&mut self, timeout: Duration) -> Result<bool> {
+        let now = Instant::now();
+        debug!("Keepalive: probing peer");
+        self.inner.send(FrameHeader::Ping.to_bytes()).await?;
+        self.last_ping = Some(now);
+        self.awaiting_since.get_or_insert(now);
+
+        self.inner.set_read_timeout(Some(timeout))?;
+        match self.inner.recv().await {
+            Ok(frame) => {
+                if let Some(data) = self.handle_frame(frame).await? {
+                    self.pending.push_back(data);
+                }
+                Ok(true)
+            }
+            Err(e) if e.is_timeout() => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 处理一个收到的帧，用户数据返回 `Some`，心跳帧返回 `None`
+    async fn handle_frame(&mut self, mut frame: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        self.last_seen = Instant::now();
+        self.awaiting_since = None;
+
+        match FrameHeader::decode(Layer::Keepalive, &frame)? {
+            (FrameHeader::Ping, _) => {
+                debug!("Keepalive: answering ping");
+                self.inner.send(FrameHeader::Pong.to_bytes()).await?;
+                Ok(None)
+            }
+            (FrameHeader::Pong, _) => Ok(None),
+            (_, header_len) => {
+                frame.drain(..header_len);
+                Ok(Some(frame))
+            }
+        }
+    }
+
+    fn data_frame(data: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(data.len() + 1);
+        FrameHeader::KeepaliveData.encode_into(&mut frame);
+        frame.extend_from_slice(data);
+        frame
+    }
+}
+
+#[async_trait]
+impl Transport for KeepaliveTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        self.config.validate()?;
+        self.inner.connect(cid, port, chunksize, isack).await?;
+        self.start()
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        self.config.validate()?;
+        self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await?;
+        self.start()
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.config.validate()?;
+        self.inner.from_tokio_stream(stream).await?;
+        self.start()
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.config.validate()?;
+        self.inner.from_tcp_stream(stream).await?;
+        self.start()
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.config.validate()?;
+        self.inner.from_uds_stream(stream).await?;
+        self.start()
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        self.config.validate()?;
+        self.inner.from_seqpacket(socket).await?;
+        self.start()
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        self.config.validate()?;
+        self.inner.from_stream(stream, chunksize, isack).await?;
+        self.start()
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        self.inner.disconnect_with_reason(code, message).await
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        self.check_alive()?;
+        self.inner.send(Self::data_frame(&data)).await
+    }
+
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        self.check_alive()?;
+        let frame_type = FrameHeader::KeepaliveData.to_bytes();
+        let mut framed = Vec::with_capacity(slices.len() + 1);
+        framed.push(IoSlice::new(&frame_type));
+        framed.extend(slices.iter().map(|slice| IoSlice::new(slice)));
+        let sent = self.inner.send_slices(&framed).await?;
+        Ok(sent - frame_type.len())
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        if let Some(data) = self.pending.pop_front() {
+            return Ok(data);
+        }
+        let deadline = self.read_timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            self.check_alive()?;
+            match self.inner.recv().await {
+                Ok(frame) => {
+                    if let Some(data) = self.handle_frame(frame).await? {
+                        return Ok(data);
+                    }
+                }
+                Err(e) if e.is_timeout() => {
+                    self.check_alive()?;
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        return Err(e);
+                    }
+                    self.ping_if_idle().await?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        if let Some(data) = self.pending.pop_front() {
+            return Ok(Some(data));
+        }
+        loop {
+            self.check_alive()?;
+            match self.inner.try_recv().await? {
+                Some(frame) => {
+                    if let Some(data) = self.handle_frame(frame).await? {
+                        return Ok(Some(data));
+                    }
+                }
+                None => {
+                    self.ping_if_idle().await?;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        self.check_alive()?;
+        let sent = self.inner.try_send(&Self::data_frame(data)).await?;
+        Ok(sent.map(|_| data.len()))
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        self.check_alive()?;
+        self.inner.shutdown_write().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.check_alive()?;
+        self.inner.flush().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        if self.timed_out() || !self.inner.probe(timeout).await? {
+            return Ok(false);
+        }
+        let result = self.ping_and_wait(timeout).await;
+        // 恢复按心跳间隔醒来的读超时
+        self.apply_read_timeout()?;
+        result
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected() && !self.timed_out()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.inner.last_ack_latency()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.inner.ack_stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        self.inner.last_sent_seq()
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        self.inner.last_received_seq()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        !self.pending.is_empty() || self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.pending.iter().map(Vec::len).sum::<usize>() + self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        check_timeout(timeout)?;
+        self.read_timeout = timeout;
+        self.apply_read_timeout()
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
@@ -0,0 +1,436 @@
+//! 优先通道模块
+//!
+//! 将用户消息分为普通通道与优先通道，以包装器的形式叠加在确认包装器之上，两端需同时启用。
+//!
+//! # 机制
+//! - 普通消息按 `FRAGMENT_SIZE` 分片发送，每发完一片检查一次优先发件队列，
+//!   队列中的优先消息插在两片之间发出，无需等待整条普通消息发送完毕
+//! - 优先消息不分片，单独成帧
+//! - 接收方按通道标记分流：`recv`/`try_recv` 只返回拼接完整的普通消息，
+//!   `recv_priority`/`try_recv_priority` 只返回优先消息，读到的另一通道数据暂存给对应的接收方法
+//! - 启用 ACK 时每一片单独确认
+//!
+//! 单个 `&mut` 持有者的发送天然串行，优先消息要插队需要另一个任务在普通发送进行期间
+//! 把消息放入发件队列，见 `VirgeClientHandle::send_priority`。
+//!
+//! # 帧格式
+//! ```text
+//! ┌─────────┬────────────────────────────────────┐
+//! │ lane: u8│ 0：普通消息的中间分片 payload       │
+//! │         │ 1：普通消息的最后一片 payload       │
+//! │         │ 2：优先消息 payload                 │
+//! └─────────┴────────────────────────────────────┘
+//! ```
+//!
+//! 帧头的编解码见 `protocol` 模块。
+
+use crate::error::{Result, VirgeError};
+use crate::protocol::{FrameHeader, Layer};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::{AckStats, Transport, VsockAddr};
+use async_trait::async_trait;
+use futures::channel::oneshot;
+use log::*;
+use std::collections::VecDeque;
+use std::os::unix::io::RawFd;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 通道标记字节数，也是本包装器对单个帧增加的开销
+pub(crate) const HEADER_SIZE: usize = crate::protocol::LANE_HEADER_SIZE;
+/// 普通消息单个分片的最大负载字节数，决定优先消息最多需要等待多少数据发送完毕
+pub(crate) const FRAGMENT_SIZE: usize = 64 * crate::KIB;
+
+/// 等待发出的优先消息
+pub(crate) struct Outgoing {
+    pub(crate) data: Vec<u8>,
+    /// 消息交给下层发送成功后通知提交者
+    pub(crate) done: Option<oneshot::Sender<()>>,
+}
+
+/// 优先发件队列，可在不持有传输实例的情况下提交优先消息
+pub(crate) type PriorityOutbox = Arc<Mutex<VecDeque<Outgoing>>>;
+
+/// 解析出的一条完整消息
+enum Incoming {
+    Bulk(Vec<u8>),
+    Priority(Vec<u8>),
+}
+
+/// 优先通道包装器
+pub(crate) struct LaneTransport {
+    inner: Box<dyn Transport>,
+    outbox: PriorityOutbox,
+    /// 接收优先消息期间拼接完整的普通消息
+    bulk: VecDeque<Vec<u8>>,
+    /// 接收普通消息期间收到的优先消息
+    priority: VecDeque<Vec<u8>>,
+    /// 尚未收到最后一片的普通消息
+    partial: Vec<u8>,
+    /// 正在丢弃超过上限的普通消息的剩余分片
+    discarding: bool,
+    /// 接收优先消息期间读到了对端的半关闭通知，普通消息取完后返回 `EndOfStream`
+    pending_eof: bool,
+    /// 拼接后单条普通消息的最大字节数
+    max_len: usize,
+}
+
+impl LaneTransport {
+    pub(crate) fn new(inner: Box<dyn Transport>, max_len: usize) -> Self {
+        Self {
+            inner,
+            outbox: Arc::new(Mutex::new(VecDeque::new())),
+            bulk: VecDeque::new(),
+            priority: VecDeque::new(),
+            partial: Vec::new(),
+            discarding: false,
+            pending_eof: false,
+            max_len,
+        }
+    }
+
+    fn frame(lane: FrameHeader, data: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(HEADER_SIZE + data.len());
+        lane.encode_into(&mut frame);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    /// 丢弃上一个连接的状态，未发出的优先消息随之丢弃，提交者收到取消通知
+    fn reset(&mut self) {
+        self.outbox.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        self.bulk.clear();
+        self.priority.clear();
+        self.partial.clear();
+        self.discarding = false;
+        self.pending_eof = false;
+    }
+
+    /// 发出发件队列中的全部优先消息；发送失败的消息放回队首，留给提交者重试
+    async fn flush_priority(&mut self) -> Result<()> {
+        loop {
+            let next = self.outbox.lock().unwrap_or_else(|e| e.into_inner()).pop_front();
+            let Some(outgoing) = next else {
+                return Ok(());
+            };
+            if let Err(e) = self.inner.send(Self::frame(FrameHeader::Priority, &outgoing.data)).await {
+                self.outbox.lock().unwrap_or_else(|e| e.into_inner()).push_front(outgoing);
+                return Err(e);
+            }
+            if let Some(done) = outgoing.done {
+                let _ = done.send(());
+            }
+        }
+    }
+
+    /// 分片发送一条普通消息，每片之后发出排队的优先消息
+    async fn send_bulk(&mut self, data: &[u8], ack: bool) -> Result<()> {
+        self.flush_priority().await?;
+        let mut fragments = data.chunks(FRAGMENT_SIZE).peekable();
+        if fragments.peek().is_none() {
+            return self.send_fragment(FrameHeader::BulkLast, &[], ack).await;
+        }
+        while let Some(fragment) = fragments.next() {
+            let lane = if fragments.peek().is_some() { FrameHeader::BulkMore } else { FrameHeader::BulkLast };
+            self.send_fragment(lane, fragment, ack).await?;
+            self.flush_priority().await?;
+        }
+        Ok(())
+    }
+
+    async fn send_fragment(&mut self, lane: FrameHeader, fragment: &[u8], ack: bool) -> Result<()> {
+        let frame = Self::frame(lane, fragment);
+        if ack {
+            self.inner.send(frame).await
+        } else {
+            self.inner.send_noack(frame).await
+        }
+    }
+
+    /// 按通道标记处理一个收到的帧，普通消息尚未拼接完整时返回 `Ok(None)`
+    fn handle_frame(&mut self, mut frame: Vec<u8>) -> Result<Option<Incoming>> {
+        let (lane, header_len) = FrameHeader::decode(Layer::Lane, &frame)?;
+        frame.drain(..header_len);
+        match lane {
+            FrameHeader::Priority => Ok(Some(Incoming::Priority(frame))),
+            _ => {
+                let last = lane == FrameHeader::BulkLast;
+                if self.discarding {
+                    self.discarding = !last;
+                    return Ok(None);
+                }
+                if self.partial.len() + frame.len() > self.max_len {
+                    let size = self.partial.len() + frame.len();
+                    self.partial.clear();
+                    self.discarding = !last;
+                    return Err(VirgeError::MessageTooLarge { size, max: self.max_len });
+                }
+                if self.partial.is_empty() && last {
+                    return Ok(Some(Incoming::Bulk(frame)));
+                }
+                self.partial.extend_from_slice(&frame);
+                if !last {
+                    return Ok(None);
+                }
+                Ok(Some(Incoming::Bulk(std::mem::take(&mut self.partial))))
+            }
+        }
+    }
+
+    /// 返回接收优先消息期间读到的半关闭通知，只返回一次
+    fn take_pending_eof(&mut self) -> Result<()> {
+        if std::mem::take(&mut self.pending_eof) {
+            return Err(VirgeError::EndOfStream);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Transport for LaneTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        self.reset();
+        self.inner.connect(cid, port, chunksize, isack).await
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        self.reset();
+        self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.reset();
+        self.inner.from_tokio_stream(stream).await
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.reset();
+        self.inner.from_tcp_stream(stream).await
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.reset();
+        self.inner.from_uds_stream(stream).await
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        self.reset();
+        self.inner.from_seqpacket(socket).await
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        self.reset();
+        self.inner.from_stream(stream, chunksize, isack).await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        self.inner.disconnect_with_reason(code, message).await
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        self.send_bulk(&data, true).await
+    }
+
+    async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        self.send_bulk(&data, false).await
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        if let Some(data) = self.bulk.pop_front() {
+            return Ok(data);
+        }
+        self.take_pending_eof()?;
+        loop {
+            let frame = self.inner.recv().await?;
+            match self.handle_frame(frame)? {
+                Some(Incoming::Bulk(data)) => return Ok(data),
+                Some(Incoming::Priority(data)) => self.priority.push_back(data),
+                None => {}
+            }
+        }
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        if let Some(data) = self.bulk.pop_front() {
+            return Ok(Some(data));
+        }
+        self.take_pending_eof()?;
+        while let Some(frame) = self.inner.try_recv().await? {
+            match self.handle_frame(frame)? {
+                Some(Incoming::Bulk(data)) => return Ok(Some(data)),
+                Some(Incoming::Priority(data)) => self.priority.push_back(data),
+                None => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// 一旦写入了第一片，其余分片阻塞写完以保证消息完整
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        let first = data.len().min(FRAGMENT_SIZE);
+        let lane = if first == data.len() { FrameHeader::BulkLast } else { FrameHeader::BulkMore };
+        if self.inner.try_send(&Self::frame(lane, &data[..first])).await?.is_none() {
+            return Ok(None);
+        }
+        let mut fragments = data[first..].chunks(FRAGMENT_SIZE).peekable();
+        while let Some(fragment) = fragments.next() {
+            let lane = if fragments.peek().is_some() { FrameHeader::BulkMore } else { FrameHeader::BulkLast };
+            self.send_fragment(lane, fragment, false).await?;
+        }
+        Ok(Some(data.len()))
+    }
+
+    async fn send_priority(&mut self, data: Vec<u8>) -> Result<()> {
+        self.outbox
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(Outgoing { data, done: None });
+        self.flush_priority().await
+    }
+
+    async fn recv_priority(&mut self) -> Result<Vec<u8>> {
+        if let Some(data) = self.priority.pop_front() {
+            return Ok(data);
+        }
+        loop {
+            let frame = match self.inner.recv().await {
+                Ok(frame) => frame,
+                Err(VirgeError::EndOfStream) => {
+                    self.pending_eof = true;
+                    return Err(VirgeError::EndOfStream);
+                }
+                Err(e) => return Err(e),
+            };
+            match self.handle_frame(frame)? {
+                Some(Incoming::Priority(data)) => return Ok(data),
+                Some(Incoming::Bulk(data)) => self.bulk.push_back(data),
+                None => {}
+            }
+        }
+    }
+
+    async fn try_recv_priority(&mut self) -> Result<Option<Vec<u8>>> {
+        if let Some(data) = self.priority.pop_front() {
+            return Ok(Some(data));
+        }
+        loop {
+            let frame = match self.inner.try_recv().await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return Ok(None),
+                Err(VirgeError::EndOfStream) => {
+                    self.pending_eof = true;
+                    return Err(VirgeError::EndOfStream);
+                }
+                Err(e) => return Err(e),
+            };
+            match self.handle_frame(frame)? {
+                Some(Incoming::Priority(data)) => return Ok(Some(data)),
+                Some(Incoming::Bulk(data)) => self.bulk.push_back(data),
+                None => {}
+            }
+        }
+    }
+
+    fn priority_outbox(&self) -> Option<PriorityOutbox> {
+        Some(self.outbox.clone())
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        if let Err(e) = self.flush_priority().await {
+            debug!("Lane: failed to flush priority messages before shutdown: {}", e);
+        }
+        self.inner.shutdown_write().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.flush_priority().await?;
+        self.inner.flush().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        self.inner.probe(timeout).await
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.inner.last_ack_latency()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.inner.ack_stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        self.inner.last_sent_seq()
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        self.inner.last_received_seq()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        !self.bulk.is_empty() || !self.priority.is_empty() || self.pending_eof || self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.bulk.iter().chain(&self.priority).map(Vec::len).sum::<usize>() + self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
@@ -0,0 +1,276 @@
+//! 内存传输协议实现
+//!
+//! 启用 `testing` 特性后可用，无需任何套接字即可在同一进程内连接客户端与服务器，用于单元测试。
+//! 帧格式与 Raw 传输一致，并按配置叠加与真实连接相同的 ACK、心跳、校验与压缩包装器，
+//! 见 `VirgeClient::new_in_memory` 与 `VirgeServer::new_in_memory`。
+//!
+//! # 帧格式
+//! ```text
+//! ┌──────────────────┬──────────────────┐
+//! │ length: u32 (BE) │ payload: [u8]    │
+//! └──────────────────┴──────────────────┘
+//! ```
+
+use crate::error::{Result, VirgeError};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::{check_timeout, framing, Transport, TransportOptions};
+use async_trait::async_trait;
+use futures::FutureExt;
+use log::*;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf};
+
+/// 单次从流中读取的字节数
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// 检查配置能否用于内存传输：加密的密钥交换需要客户端与服务器两种角色，内存传输的两端都以 `connect` 初始化
+pub(crate) fn check_options(options: &TransportOptions) -> Result<()> {
+    if options.uses_encryption() {
+        return Err(VirgeError::ConfigError("encryption is not supported by in-memory transports".to_string()));
+    }
+    Ok(())
+}
+
+/// 按配置创建一对叠加了包装器的传输，供 `new_in_memory` 使用
+///
+/// 两端仍需调用 `connect` 以启动心跳等包装器的连接期状态。
+pub(crate) fn pair(chunk_size: u32, ack: bool, options: &TransportOptions) -> (Box<dyn Transport>, Box<dyn Transport>) {
+    let (a, b) = MemoryTransport::pair_with_chunk_size(chunk_size);
+    let limit = options.frame_limit(ack);
+    let pool = options.buffer_pool_size;
+    (
+        options.wrap(Box::new(a.with_max_message_size(limit).with_buffer_pool_size(pool)), ack),
+        options.wrap(Box::new(b.with_max_message_size(limit).with_buffer_pool_size(pool)), ack),
+    )
+}
+
+/// 内存传输协议实现
+///
+/// 由 [`MemoryTransport::pair`] 成对创建，两端之间是一条进程内字节流，消息以 4 字节大端长度前缀分隔。
+pub struct MemoryTransport {
+    stream: Option<DuplexStream>,
+    /// 已读取但尚未组成完整消息的数据，同时限制单条消息的最大字节数
+    read_buffer: framing::StreamBuffer,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+}
+
+impl MemoryTransport {
+    /// 创建一对互相连接的传输实例，单次写入至多 `DEAFULT_CHUNK_SIZE` 字节
+    pub fn pair() -> (MemoryTransport, MemoryTransport) {
+        Self::pair_with_chunk_size(crate::DEAFULT_CHUNK_SIZE as u32)
+    }
+
+    /// 创建一对互相连接的传输实例，两端之间的缓冲区为 `chunk_size` 字节
+    ///
+    /// 超过 `chunk_size` 的消息被拆分为多次写入，对端读满缓冲区前写入方等待，与真实连接的分块与背压一致。
+    pub fn pair_with_chunk_size(chunk_size: u32) -> (MemoryTransport, MemoryTransport) {
+        let (a, b) = tokio::io::duplex(chunk_size.max(1) as usize);
+        (Self::new(a), Self::new(b))
+    }
+
+    fn new(stream: DuplexStream) -> Self {
+        Self {
+            stream: Some(stream),
+            read_buffer: framing::StreamBuffer::new(crate::DEFAULT_MAX_MESSAGE_SIZE),
+            read_timeout: None,
+            write_timeout: None,
+        }
+    }
+
+    /// 设置单条消息的最大字节数，超限的发送在本地失败，超限的接收返回 `MessageTooLarge` 且连接保持可用
+    pub fn with_max_message_size(mut self, size: usize) -> Self {
+        self.read_buffer.set_max(size);
+        self
+    }
+
+    /// 设置接收缓冲区池至多保存的空闲读缓冲区数，0 表示每次读取都新分配
+    pub(crate) fn with_buffer_pool_size(mut self, size: usize) -> Self {
+        self.read_buffer.set_pool_size(size);
+        self
+    }
+
+    /// 以非阻塞方式读取到 `chunk`，直到组成一个完整的帧或流中暂无数据
+    fn poll_frame(&mut self, chunk: &mut [u8]) -> Result<Option<Vec<u8>>> {
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Some(message) = self.read_buffer.take_frame()? {
+                info!("Memory transport received {} bytes", message.len());
+                return Ok(Some(message));
+            }
+
+            let stream = self.stream.as_mut().ok_or_else(Self::not_connected)?;
+            let mut buf = ReadBuf::new(&mut *chunk);
+            match Pin::new(stream).poll_read(&mut cx, &mut buf) {
+                Poll::Pending => return Ok(None),
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => return Err(unexpected_eof()),
+                Poll::Ready(Ok(())) => self.read_buffer.extend(buf.filled()),
+                Poll::Ready(Err(e)) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn not_connected() -> VirgeError {
+        VirgeError::Disconnected("Memory transport not connected or already disconnected".to_string())
+    }
+}
+
+/// 在可选的超时时间内执行 IO 操作，超时返回 `ErrorKind::TimedOut`，转换为 `VirgeError::Timeout`
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    fut: impl Future<Output = std::io::Result<T>>,
+) -> std::io::Result<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, fut).await.unwrap_or_else(|_| {
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Memory transport operation timed out"))
+        }),
+        None => fut.await,
+    }
+}
+
+fn unexpected_eof() -> VirgeError {
+    VirgeError::IoError(std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        "Memory transport peer closed the connection",
+    ))
+}
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    /// 内存传输在创建时即已连接，本方法只检查连接仍然可用；断开后无法重新连接
+    async fn connect(&mut self, _: u32, _: u32, _: u32, _: bool) -> Result<()> {
+        if self.stream.is_none() {
+            return Err(Self::not_connected());
+        }
+        Ok(())
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, _: Duration) -> Result<()> {
+        self.connect(cid, port, chunksize, isack).await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.disconnect_with_reason(0, "").await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        info!("Memory transport disconnecting");
+        if let Some(mut stream) = self.stream.take() {
+            // 不等待发送缓冲区腾出空间：对端停止接收时放弃关闭帧，对端随后将其视为异常断开
+            match stream.write_all(&framing::close_frame(code, message)).now_or_never() {
+                Some(Ok(())) => {}
+                Some(Err(e)) => debug!("Memory transport failed to send close frame: {}", e),
+                None => debug!("Memory transport send buffer full, skipping close frame"),
+            }
+            if let Err(e) = stream.shutdown().await {
+                debug!("Memory transport shutdown error: {}", e);
+            }
+        }
+        self.read_buffer.clear();
+        Ok(())
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        let header = framing::stream_header(data.len(), self.read_buffer.max())?;
+        let timeout = self.write_timeout;
+        let stream = self.stream.as_mut().ok_or_else(Self::not_connected)?;
+
+        with_timeout(timeout, async {
+            stream.write_all(&header).await?;
+            stream.write_all(&data).await
+        }).await?;
+
+        info!("Memory transport sent {} bytes", data.len());
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        let timeout = self.read_timeout;
+        loop {
+            if let Some(message) = self.read_buffer.take_frame()? {
+                info!("Memory transport received {} bytes", message.len());
+                return Ok(message);
+            }
+
+            let stream = self.stream.as_mut().ok_or_else(Self::not_connected)?;
+            let mut chunk = self.read_buffer.read_chunk(READ_CHUNK_SIZE);
+            let read = with_timeout(timeout, stream.read(&mut chunk)).await;
+            if let Ok(n) = read {
+                self.read_buffer.extend(&chunk[..n]);
+            }
+            self.read_buffer.recycle(chunk);
+            if read? == 0 {
+                return Err(unexpected_eof());
+            }
+        }
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut chunk = self.read_buffer.read_chunk(READ_CHUNK_SIZE);
+        let result = self.poll_frame(&mut chunk);
+        self.read_buffer.recycle(chunk);
+        result
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        let header = framing::stream_header(data.len(), self.read_buffer.max())?;
+        let timeout = self.write_timeout;
+        let stream = self.stream.as_mut().ok_or_else(Self::not_connected)?;
+
+        // 无法写入任何字节时直接返回；一旦写入了部分帧头，则阻塞写完以保证消息完整
+        let mut cx = Context::from_waker(Waker::noop());
+        let written = match Pin::new(&mut *stream).poll_write(&mut cx, &header) {
+            Poll::Pending => return Ok(None),
+            Poll::Ready(result) => result?,
+        };
+        with_timeout(timeout, async {
+            stream.write_all(&header[written..]).await?;
+            stream.write_all(data).await
+        }).await?;
+
+        info!("Memory transport sent {} bytes", data.len());
+        Ok(Some(data.len()))
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        let timeout = self.write_timeout;
+        let stream = self.stream.as_mut().ok_or_else(Self::not_connected)?;
+        with_timeout(timeout, stream.write_all(&framing::STREAM_EOF)).await?;
+        info!("Memory transport shut down its write side");
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        let timeout = self.write_timeout;
+        let stream = self.stream.as_mut().ok_or_else(Self::not_connected)?;
+        with_timeout(timeout, stream.flush()).await?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.stream.is_some() && !self.read_buffer.peer_closed()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        !self.read_buffer.is_empty()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        Some(self.read_buffer.pool().clone())
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        check_timeout(timeout)?;
+        self.read_timeout = timeout;
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        check_timeout(timeout)?;
+        self.write_timeout = timeout;
+        Ok(())
+    }
+}
@@ -0,0 +1,386 @@
+//! 消息元数据模块
+//!
+//! 以包装器的形式叠加在优先通道之上，为每条普通消息附加一组字符串键值对（如 content-type、请求 ID），
+//! 免去在负载中自行拼接头部。两端需同时启用，设置在握手中协商。
+//!
+//! # 机制
+//! - `send_with_meta` 将 [`HeaderMap`] 编码为元数据块放在负载之前，`recv_with_meta` 取回
+//! - 普通的 send/recv 与之互通：发送时附带空的元数据块，接收时丢弃元数据
+//! - 元数据块不超过 [`MAX_METADATA_SIZE`] 字节，超出时在本地返回 `MessageTooLarge`；
+//!   收到超限或格式错误的元数据块时返回 `ProtocolError`
+//! - 优先消息不经过本包装器，不携带元数据
+//!
+//! # 帧格式
+//! ```text
+//! ┌────────────────┬──────────────────┬──────────────────┐
+//! │ meta_len: u16  │ 元数据块          │ payload: [u8]    │
+//! └────────────────┴──────────────────┴──────────────────┘
+//! ```
+//!
+//! 帧头与元数据块的编码见 `protocol` 模块。
+
+use crate::error::{Result, VirgeError};
+use crate::protocol::{FrameHeader, Layer, MAX_METADATA_KEY_SIZE, MAX_METADATA_SIZE};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::lane::PriorityOutbox;
+use crate::transport::{AckStats, Transport, VsockAddr};
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::io::IoSlice;
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 元数据帧头字节数
+pub(crate) const HEADER_SIZE: usize = crate::protocol::METADATA_HEADER_SIZE;
+
+/// 随消息发送的字符串键值对，按键排序
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    entries: BTreeMap<String, String>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 插入一个键值对，返回该键原来的值
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        self.entries.insert(key.into(), value.into())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.entries.remove(key)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 按键的顺序遍历全部键值对
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// 编码后元数据块的字节数
+    pub fn encoded_len(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|(key, value)| 1 + key.len() + 2 + value.len())
+            .sum()
+    }
+
+    /// 编码元数据帧头与元数据块，键或整个块超过上限时返回 `MessageTooLarge`
+    fn encode_into(&self, out: &mut Vec<u8>) -> Result<()> {
+        let len = self.encoded_len();
+        if len > MAX_METADATA_SIZE {
+            return Err(VirgeError::MessageTooLarge { size: len, max: MAX_METADATA_SIZE });
+        }
+        if let Some(key) = self.entries.keys().find(|key| key.len() > MAX_METADATA_KEY_SIZE) {
+            return Err(VirgeError::MessageTooLarge { size: key.len(), max: MAX_METADATA_KEY_SIZE });
+        }
+        out.reserve(HEADER_SIZE + len);
+        FrameHeader::Metadata { len: len as u16 }.encode_into(out);
+        for (key, value) in &self.entries {
+            out.push(key.len() as u8);
+            out.extend_from_slice(key.as_bytes());
+            out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            out.extend_from_slice(value.as_bytes());
+        }
+        Ok(())
+    }
+
+    /// 解析元数据块
+    fn decode(mut block: &[u8]) -> Result<Self> {
+        let mut map = HeaderMap::new();
+        while !block.is_empty() {
+            let key = take_str(&mut block, 1, block[0] as usize)?;
+            let Some(&[high, low]) = block.get(..2) else {
+                return Err(truncated());
+            };
+            let value = take_str(&mut block, 2, u16::from_be_bytes([high, low]) as usize)?;
+            if map.insert(key.clone(), value).is_some() {
+                return Err(VirgeError::ProtocolError(format!("Duplicate metadata key {:?}", key)));
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self {
+            entries: iter.into_iter().map(|(key, value)| (key.into(), value.into())).collect(),
+        }
+    }
+}
+
+/// 跳过 `skip` 字节的长度字段后取出 `len` 字节的 UTF-8 字符串
+fn take_str(block: &mut &[u8], skip: usize, len: usize) -> Result<String> {
+    let Some(bytes) = block.get(skip..skip + len) else {
+        return Err(truncated());
+    };
+    let text = std::str::from_utf8(bytes)
+        .map_err(|e| VirgeError::ProtocolError(format!("Metadata is not valid UTF-8: {}", e)))?
+        .to_string();
+    *block = &block[skip + len..];
+    Ok(text)
+}
+
+fn truncated() -> VirgeError {
+    VirgeError::ProtocolError("Truncated metadata entry".to_string())
+}
+
+/// 元数据包装器
+pub(crate) struct MetadataTransport {
+    inner: Box<dyn Transport>,
+}
+
+impl MetadataTransport {
+    pub(crate) fn new(inner: Box<dyn Transport>) -> Self {
+        Self { inner }
+    }
+
+    fn encode(data: &[u8], meta: &HeaderMap) -> Result<Vec<u8>> {
+        let mut frame = Vec::with_capacity(HEADER_SIZE + meta.encoded_len() + data.len());
+        meta.encode_into(&mut frame)?;
+        frame.extend_from_slice(data);
+        Ok(frame)
+    }
+
+    fn plain(data: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(HEADER_SIZE + data.len());
+        FrameHeader::Metadata { len: 0 }.encode_into(&mut frame);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    fn decode(mut frame: Vec<u8>) -> Result<(Vec<u8>, HeaderMap)> {
+        // 空帧来自下层（如对端关闭），原样交给调用方处理
+        if frame.is_empty() {
+            return Ok((frame, HeaderMap::new()));
+        }
+        let (header, header_len) = FrameHeader::decode(Layer::Metadata, &frame)?;
+        let block_len = header.payload_len().unwrap_or_default() as usize;
+        if block_len > MAX_METADATA_SIZE {
+            return Err(VirgeError::ProtocolError(format!(
+                "Metadata block of {} bytes exceeds {}",
+                block_len, MAX_METADATA_SIZE
+            )));
+        }
+        let end = header_len + block_len;
+        if frame.len() < end {
+            return Err(VirgeError::ProtocolError(format!(
+                "Metadata frame of {} bytes is shorter than its {} byte metadata block",
+                frame.len(),
+                block_len
+            )));
+        }
+        let meta = HeaderMap::decode(&frame[header_len..end])?;
+        frame.drain(..end);
+        Ok((frame, meta))
+    }
+}
+
+#[async_trait]
+impl Transport for MetadataTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.connect(cid, port, chunksize, isack).await
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.inner.from_tokio_stream(stream).await
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.inner.from_tcp_stream(stream).await
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.inner.from_uds_stream(stream).await
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        self.inner.from_seqpacket(socket).await
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.from_stream(stream, chunksize, isack).await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        self.inner.disconnect_with_reason(code, message).await
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        self.inner.send(Self::plain(&data)).await
+    }
+
+    async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        self.inner.send_noack(Self::plain(&data)).await
+    }
+
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        let header = FrameHeader::Metadata { len: 0 }.to_bytes();
+        let mut framed = Vec::with_capacity(slices.len() + 1);
+        framed.push(IoSlice::new(&header));
+        framed.extend(slices.iter().map(|slice| IoSlice::new(slice)));
+        let sent = self.inner.send_slices(&framed).await?;
+        Ok(sent - header.len())
+    }
+
+    async fn send_with_meta(&mut self, data: Vec<u8>, meta: &HeaderMap) -> Result<()> {
+        let frame = Self::encode(&data, meta)?;
+        self.inner.send(frame).await
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        let frame = self.inner.recv().await?;
+        Self::decode(frame).map(|(data, _)| data)
+    }
+
+    async fn recv_with_meta(&mut self) -> Result<(Vec<u8>, HeaderMap)> {
+        let frame = self.inner.recv().await?;
+        Self::decode(frame)
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.inner.try_recv().await? {
+            Some(frame) => Self::decode(frame).map(|(data, _)| Some(data)),
+            None => Ok(None),
+        }
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        let sent = self.inner.try_send(&Self::plain(data)).await?;
+        Ok(sent.map(|_| data.len()))
+    }
+
+    async fn send_priority(&mut self, data: Vec<u8>) -> Result<()> {
+        self.inner.send_priority(data).await
+    }
+
+    async fn recv_priority(&mut self) -> Result<Vec<u8>> {
+        self.inner.recv_priority().await
+    }
+
+    async fn try_recv_priority(&mut self) -> Result<Option<Vec<u8>>> {
+        self.inner.try_recv_priority().await
+    }
+
+    fn priority_outbox(&self) -> Option<PriorityOutbox> {
+        self.inner.priority_outbox()
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        self.inner.shutdown_write().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        self.inner.probe(timeout).await
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.inner.last_ack_latency()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.inner.ack_stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        self.inner.last_sent_seq()
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        self.inner.last_received_seq()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
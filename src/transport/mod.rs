@@ -8,14 +8,72 @@
 //! - 直接管理 vsock 连接和协议逻辑
 //! - 提供开箱即用的 connect/disconnect/send/recv 接口
 //!
+//! # 传输协议选择
+//! 通过 `ClientConfig`/`ServerConfig` 中的 [`TransportKind`] 在运行时选择协议，两端必须一致：
+//! 建立连接后双方先交换握手消息（魔数、协议版本、协议类型、ACK 设置及 xtransport 的 chunk_size），
+//! 不一致时连接失败并返回 `VirgeError::ProtocolError`。
+//!
+//! | 协议 | 优点 | 代价 |
+//! |------|------|------|
+//! | XTransport | 按 chunk_size 分帧，可选 ACK 确认，开销小 | 同步阻塞 IO，单条逻辑流 |
+//! | Yamux | 完全异步，支持多路复用与流量控制 | 帧头与窗口更新带来额外开销，依赖 tokio |
+//! | Raw | 仅 4 字节长度前缀，批量传输开销最小 | ACK 由 virga 逐条确认，无多路复用，依赖 tokio |
+//! | Tcp | 与 Raw 相同的帧格式，走本地回环 TCP，无需虚拟机 | 仅用于开发与测试，cid 被忽略，依赖 tokio |
+//!
+//! 启用 `use-uds` 并配置 `uds_path` 后，Raw 协议改经 Firecracker 等监控程序导出的 Unix 套接字收发，
+//! 见 `UdsTransport`。
+//!
+//! Raw 协议启用 `seqpacket` 后改用 virtio-vsock 的 SOCK_SEQPACKET 套接字，由内核保留消息边界，
+//! 见 `SeqpacketTransport`。
+//!
 
 #[cfg(feature = "use-yamux")]
 pub mod yamux_impl;
 #[cfg(feature = "use-xtransport")]
 pub mod xtransport_impl;
+#[cfg(feature = "use-raw")]
+pub mod raw_impl;
+#[cfg(feature = "use-tcp")]
+pub mod tcp_impl;
+#[cfg(feature = "use-uds")]
+pub mod uds_impl;
+#[cfg(any(feature = "use-raw", feature = "use-tcp"))]
+pub(crate) mod stream_impl;
+#[cfg(feature = "use-raw")]
+pub mod seqpacket_impl;
+#[cfg(feature = "testing")]
+pub mod memory_impl;
+#[cfg(any(feature = "use-xtransport", feature = "use-yamux", feature = "use-raw"))]
+pub(crate) mod sys;
+pub(crate) mod ack;
+pub(crate) mod bufpool;
+#[cfg(feature = "compression")]
+pub(crate) mod compression;
+#[cfg(feature = "testing")]
+pub(crate) mod fault;
+pub(crate) mod framing;
+pub(crate) mod integrity;
+pub(crate) mod keepalive;
+pub(crate) mod lane;
+pub(crate) mod metadata;
+pub(crate) mod observer;
+pub(crate) mod preamble;
+pub(crate) mod ratelimit;
+#[cfg(feature = "testing")]
+pub(crate) mod record;
+pub(crate) mod retry;
+#[cfg(feature = "encryption")]
+pub(crate) mod secure;
+pub(crate) mod sequence;
+pub(crate) mod stats;
 
 use crate::error::Result;
 use async_trait::async_trait;
+use std::io::IoSlice;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 /// 传输协议抽象 trait
 #[async_trait]
@@ -30,6 +88,15 @@ pub trait Transport: Send + Sync {
     /// 连接成功返回 Ok，否则返回错误
     async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()>;
 
+    /// 在限定时间内建立 vsock 连接并初始化传输协议（客户端模式）
+    ///
+    /// # Arguments
+    /// - `timeout`: 连接与协议初始化的总超时时间
+    ///
+    /// # Returns
+    /// 超时返回 `VirgeError::Timeout`，此时传输处于未连接状态，可再次发起连接
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()>;
+
     /// 从现有 vsock 流初始化传输协议（服务器模式）
     ///
     /// # Arguments
@@ -37,9 +104,27 @@ pub trait Transport: Send + Sync {
     ///
     /// # Returns
     /// 初始化成功返回 Ok，否则返回错误
-    #[cfg(feature = "use-yamux")]
+    #[cfg(feature = "tokio-runtime")]
     async fn from_tokio_stream(&mut self, _stream: tokio_vsock::VsockStream) -> Result<()> {
-        Err(crate::error::VirgeError::Other("Yamux from_tokio_stream not implemented".to_string()))
+        Err(crate::error::VirgeError::Other("from_tokio_stream not implemented".to_string()))
+    }
+
+    /// 从已接受的本地 TCP 连接初始化传输协议（服务器模式，`TransportKind::Tcp`）
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, _stream: tokio::net::TcpStream) -> Result<()> {
+        Err(crate::error::VirgeError::Other("from_tcp_stream not implemented".to_string()))
+    }
+
+    /// 从监控程序转发来的 Unix 套接字连接初始化传输协议（服务器模式，配置了 `uds_path` 时）
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, _stream: tokio::net::UnixStream) -> Result<()> {
+        Err(crate::error::VirgeError::Other("from_uds_stream not implemented".to_string()))
+    }
+
+    /// 从已接受的 SOCK_SEQPACKET vsock 连接初始化传输协议（服务器模式，启用了 `seqpacket` 时）
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, _socket: std::os::fd::OwnedFd) -> Result<()> {
+        Err(crate::error::VirgeError::Other("from_seqpacket not implemented".to_string()))
     }
 
     #[cfg(feature = "use-xtransport")]
@@ -50,6 +135,15 @@ pub trait Transport: Send + Sync {
     /// 断开连接并清理资源
     async fn disconnect(&mut self) -> Result<()>;
 
+    /// 携带原因码与说明断开连接
+    ///
+    /// 基于字节流的传输（raw、yamux 等）在关闭前发出携带原因的关闭帧，对端之后的 recv 返回
+    /// `VirgeError::PeerClosed`；说明超过 `MAX_CLOSE_REASON_SIZE` 字节的部分被截断。
+    /// 原因码为 0 且没有说明时与 `disconnect` 相同。xtransport 不支持传递原因，默认实现直接断开连接。
+    async fn disconnect_with_reason(&mut self, _code: u32, _message: &str) -> Result<()> {
+        self.disconnect().await
+    }
+
     /// 发送数据
     ///
     /// # Arguments
@@ -59,18 +153,1001 @@ pub trait Transport: Send + Sync {
     /// 成功发送返回 Ok，否则返回错误
     async fn send(&mut self, data: Vec<u8>) -> Result<()>;
 
+    /// 发送数据且不等待对端确认
+    ///
+    /// 启用 ACK 时 `send` 等待对端确认后才返回，本方法在数据交给内核后即返回。
+    /// 连接级 ACK 的协议（xtransport）无法逐条跳过确认，默认实现等同于 `send`。
+    async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        self.send(data).await
+    }
+
+    /// 将多个缓冲区作为一条消息发送
+    ///
+    /// 所有切片按顺序拼接为一个逻辑负载，接收方通过一次 recv 得到完整数据。
+    ///
+    /// # Returns
+    /// 返回发送的总字节数
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        let total = slices.iter().map(|slice| slice.len()).sum();
+        let mut data = Vec::with_capacity(total);
+        for slice in slices {
+            data.extend_from_slice(slice);
+        }
+        self.send(data).await?;
+        Ok(total)
+    }
+
     /// 接收数据
     ///
     /// # Returns
     /// 返回接收到的字节数据，或错误
     async fn recv(&mut self) -> Result<Vec<u8>>;
 
+    /// 接收数据到调用方提供的缓冲区
+    ///
+    /// 缓冲区先被清空，仅在容量不足时增长，可在多次接收间复用以避免重复分配。
+    ///
+    /// # Returns
+    /// 返回接收到的字节数
+    async fn recv_into(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        let data = self.recv().await?;
+        buf.clear();
+        buf.extend_from_slice(&data);
+        Ok(data.len())
+    }
+
+    /// 非阻塞接收数据
+    ///
+    /// # Returns
+    /// 当前没有可读数据时返回 `Ok(None)`，不会挂起调用线程
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>>;
+
+    /// 非阻塞发送数据
+    ///
+    /// # Returns
+    /// 当前无法写入时返回 `Ok(None)`，数据未被发送；成功时返回发送的字节数
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>>;
+
+    /// 关闭写方向（半关闭）：通知对端不会再发送数据，本端仍可接收
+    ///
+    /// 对端读到该通知时 recv 返回一次 `VirgeError::EndOfStream`。确认、心跳等控制帧不受影响，
+    /// 调用方在此之后不应再发送用户数据。基于字节流的传输（raw、yamux 等）支持，xtransport 不支持。
+    async fn shutdown_write(&mut self) -> Result<()> {
+        Err(crate::error::VirgeError::Other("shutdown_write not supported by this transport".to_string()))
+    }
+
+    /// 将已交给传输的数据推送到线路上，并等待内部的发送缓冲排空
+    ///
+    /// 字节流传输冲刷底层流（yamux 为虚拟流）；叠加确认包装器时同时等待全部已发出消息的确认，
+    /// 优先通道包装器先发出排队的优先消息。受写超时约束，默认实现直接返回 `Ok(())`。
+    async fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// 主动检测连接是否仍然存活，最多等待 `timeout`
+    ///
+    /// 默认实现不发送数据，只在套接字上检查是否已挂断、出错或读到对端关闭，仍有未读数据时视为存活；
+    /// 没有文件描述符的传输（如内存传输）退化为 `is_connected()`。心跳包装器在此之上发送 PING，
+    /// 并等待对端在 `timeout` 内发来任意帧。
+    ///
+    /// # Returns
+    /// 存活返回 `Ok(true)`，已断开或在 `timeout` 内没有回应返回 `Ok(false)`
+    async fn probe(&mut self, _timeout: Duration) -> Result<bool> {
+        if !self.is_connected() {
+            return Ok(false);
+        }
+        Ok(self.raw_fd().is_none_or(socket_alive))
+    }
+
+    /// 在当前连接上打开一个独立的虚拟流
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<VirgeStream> {
+        Err(crate::error::VirgeError::Other("open_stream not supported by this transport".to_string()))
+    }
+
+    /// 接受对端在当前连接上打开的下一个虚拟流
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<VirgeStream> {
+        Err(crate::error::VirgeError::Other("accept_stream not supported by this transport".to_string()))
+    }
+
     /// 检查连接是否活跃
     fn is_connected(&self) -> bool;
+
+    /// 最近一次 `send` 从发出到收到对端确认的耗时，未启用 ACK 或尚未收到确认时为 `None`
+    fn last_ack_latency(&self) -> Option<Duration> {
+        None
+    }
+
+    /// 最近若干次确认往返时间的统计，未启用 ACK 或尚未收到确认时为 `None`
+    fn ack_stats(&self) -> Option<AckStats> {
+        None
+    }
+
+    /// 最近一次握手协商出的 ACK 窗口，未执行握手或协议不协商窗口时为 `None`
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        None
+    }
+
+    /// 当前连接上最近一条发送成功的普通消息的序号，尚未发送时为 0，未启用消息序号时为 `None`
+    fn last_sent_seq(&self) -> Option<u64> {
+        None
+    }
+
+    /// 当前连接上最近收到的普通消息的序号，尚未收到时为 0，未启用消息序号时为 `None`
+    fn last_received_seq(&self) -> Option<u64> {
+        None
+    }
+
+    /// 最近一次握手中对端附带的认证令牌，对端未设置令牌或未执行握手时为 `None`
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// 底层套接字的文件描述符，未连接或没有对应套接字时为 `None`
+    fn raw_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    /// 传输为当前连接分配的连接 ID，不分配 ID 的传输为 `None`
+    fn connection_id(&self) -> Option<u64> {
+        None
+    }
+
+    /// 当前连接的本地地址，未连接或没有对应 vsock 地址时为 `None`
+    fn local_addr(&self) -> Option<VsockAddr> {
+        None
+    }
+
+    /// 是否有已从套接字读出、尚未交付的数据；为 `true` 时描述符不可读也能立即收到数据
+    fn has_buffered_data(&self) -> bool {
+        false
+    }
+
+    /// 已完整接收、缓存在传输内部尚未交付的消息字节数，不含仍在接收中的消息
+    ///
+    /// 按各层缓存的帧计，可能包含心跳等由包装器处理的控制帧。
+    fn pending_bytes(&self) -> usize {
+        0
+    }
+
+    /// 不等待新数据，把套接字上已到达的消息读入内部队列，之后的 `recv`/`try_recv` 先从队列中取
+    ///
+    /// 目前只有 xtransport 预读整条消息，其余传输什么也不做。
+    async fn prefetch(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// 经优先通道发送一条消息，排在进行中的普通消息的下一个分片之前
+    ///
+    /// 需两端都启用 `with_priority_lanes`，否则返回配置错误。
+    async fn send_priority(&mut self, _data: Vec<u8>) -> Result<()> {
+        Err(lanes_disabled())
+    }
+
+    /// 接收一条优先消息，期间收到的普通消息暂存给 `recv`
+    async fn recv_priority(&mut self) -> Result<Vec<u8>> {
+        Err(lanes_disabled())
+    }
+
+    /// 非阻塞接收优先消息，当前没有优先消息时返回 `Ok(None)`
+    async fn try_recv_priority(&mut self) -> Result<Option<Vec<u8>>> {
+        Err(lanes_disabled())
+    }
+
+    /// 优先发件队列，供共享连接的句柄在普通消息发送期间提交优先消息，未启用优先通道时为 `None`
+    fn priority_outbox(&self) -> Option<lane::PriorityOutbox> {
+        None
+    }
+
+    /// 最内层传输读取时使用的接收缓冲区池，由统计包装器读取命中计数；不经由 virga 分配读缓冲区的传输为 `None`
+    fn buffer_pool(&self) -> Option<Arc<bufpool::BufferPool>> {
+        None
+    }
+
+    /// 发送一条附带元数据的消息，对端的普通 `recv` 只收到负载
+    ///
+    /// 需两端都启用 `with_metadata`，否则返回配置错误。
+    async fn send_with_meta(&mut self, _data: Vec<u8>, _meta: &HeaderMap) -> Result<()> {
+        Err(metadata_disabled())
+    }
+
+    /// 接收一条消息及其元数据，对端以普通 `send` 发出的消息元数据为空
+    async fn recv_with_meta(&mut self) -> Result<(Vec<u8>, HeaderMap)> {
+        Err(metadata_disabled())
+    }
+
+    /// 设置读超时
+    ///
+    /// # Arguments
+    /// - `timeout`: 单次 recv 的最长等待时间，`None` 表示一直阻塞
+    ///
+    /// # Returns
+    /// 传入零时长返回配置错误；超时后 recv 返回 `VirgeError::Timeout`
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()>;
+
+    /// 设置写超时
+    ///
+    /// # Arguments
+    /// - `timeout`: 单次 send 的最长等待时间，`None` 表示一直阻塞
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()>;
+}
+
+/// 等待一段时间：启用 tokio 传输时使用 tokio 定时器，否则与 xtransport 一样阻塞当前线程
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(feature = "tokio-runtime")]
+    tokio::time::sleep(duration).await;
+    #[cfg(not(feature = "tokio-runtime"))]
+    std::thread::sleep(duration);
+}
+
+fn lanes_disabled() -> crate::error::VirgeError {
+    crate::error::VirgeError::ConfigError("Priority lanes not enabled, see with_priority_lanes".to_string())
+}
+
+fn metadata_disabled() -> crate::error::VirgeError {
+    crate::error::VirgeError::ConfigError("Message metadata not enabled, see with_metadata".to_string())
+}
+
+/// 校验超时参数，与 `std::net::TcpStream` 一致拒绝零时长
+pub(crate) fn check_timeout(timeout: Option<Duration>) -> Result<()> {
+    if timeout == Some(Duration::ZERO) {
+        return Err(crate::error::VirgeError::ConfigError(
+            "Cannot set a zero duration timeout".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// 校验客户端与服务器配置共有的参数
+pub(crate) fn check_config(chunk_size: u32, max_message_size: usize) -> Result<()> {
+    if !(crate::MIN_CHUNK_SIZE..=crate::MAX_CHUNK_SIZE).contains(&(chunk_size as usize)) {
+        return Err(crate::error::VirgeError::ConfigError(format!(
+            "chunk_size must be in {}..={}, got {}",
+            crate::MIN_CHUNK_SIZE, crate::MAX_CHUNK_SIZE, chunk_size
+        )));
+    }
+    if max_message_size == 0 {
+        return Err(crate::error::VirgeError::ConfigError(
+            "max_message_size must be non-zero".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// 按 `send_buffer_limit` 设置底层套接字的 SO_SNDBUF，没有文件描述符的传输（如内存传输）不受影响
+///
+/// 内核会将设置值翻倍以容纳簿记开销，且上限受 `net.core.wmem_max` 约束。
+pub(crate) fn apply_send_buffer_limit(transport: &dyn Transport, options: &TransportOptions) -> Result<()> {
+    let (Some(limit), Some(fd)) = (options.send_buffer_limit, transport.raw_fd()) else {
+        return Ok(());
+    };
+    let size = libc::c_int::try_from(limit).unwrap_or(libc::c_int::MAX);
+    // SAFETY: fd 为传输持有的有效套接字，size 在调用期间有效
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_SNDBUF,
+            &size as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(crate::error::VirgeError::connection_io(
+            "Failed to set send buffer limit",
+            std::io::Error::last_os_error(),
+        ));
+    }
+    Ok(())
+}
+
+/// vsock 套接字的缓冲区大小（`SO_VM_SOCKETS_BUFFER_*` 选项），单位为字节
+///
+/// 内核把 `size` 夹在 `min` 与 `max` 之间；virtio-vsock 以 `size` 作为向对端通告的接收缓冲区（信用额度）。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SocketBufferSizes {
+    /// 缓冲区下限（`SO_VM_SOCKETS_BUFFER_MIN_SIZE`）
+    pub min: u64,
+    /// 缓冲区大小（`SO_VM_SOCKETS_BUFFER_SIZE`）
+    pub size: u64,
+    /// 缓冲区上限（`SO_VM_SOCKETS_BUFFER_MAX_SIZE`）
+    pub max: u64,
+}
+
+impl SocketBufferSizes {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.min > self.size || self.size > self.max {
+            return Err(crate::error::VirgeError::ConfigError(format!(
+                "socket_buffer requires min <= size <= max, got {} / {} / {}",
+                self.min, self.size, self.max
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// vsock 缓冲区选项，libc 未导出，取值见 `linux/vm_sockets.h`；选项层级为 `AF_VSOCK`
+const SO_VM_SOCKETS_BUFFER_SIZE: libc::c_int = 0;
+const SO_VM_SOCKETS_BUFFER_MIN_SIZE: libc::c_int = 1;
+const SO_VM_SOCKETS_BUFFER_MAX_SIZE: libc::c_int = 2;
+
+/// 套接字是否为 vsock 套接字，tcp 与 uds 传输的套接字返回 `false`
+fn is_vsock(fd: RawFd) -> bool {
+    let mut domain: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: 选项值指向有效的 c_int，长度与之一致
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_DOMAIN,
+            &mut domain as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    ret == 0 && domain == libc::AF_VSOCK
+}
+
+/// 缓冲区选项设置或读取失败：`ConfigError` 中附带选项名与 errno
+fn buffer_option_error(action: &str, name: &str, err: std::io::Error) -> crate::error::VirgeError {
+    crate::error::VirgeError::ConfigError(format!(
+        "Failed to {} {}: {} (errno {})",
+        action,
+        name,
+        err,
+        err.raw_os_error().unwrap_or(0)
+    ))
+}
+
+/// 按 `socket_buffer` 设置 vsock 套接字的缓冲区大小，没有配置、没有文件描述符或不是 vsock 套接字时不做任何事
+///
+/// 内核每次设置都会按当前的上下限夹紧缓冲区大小，因此依次设置上限、下限与大小，不会被旧的上下限截断。
+pub(crate) fn apply_socket_buffer(transport: &dyn Transport, options: &TransportOptions) -> Result<()> {
+    let (Some(sizes), Some(fd)) = (options.socket_buffer, transport.raw_fd()) else {
+        return Ok(());
+    };
+    if !is_vsock(fd) {
+        return Ok(());
+    }
+    let settings = [
+        ("SO_VM_SOCKETS_BUFFER_MAX_SIZE", SO_VM_SOCKETS_BUFFER_MAX_SIZE, sizes.max),
+        ("SO_VM_SOCKETS_BUFFER_MIN_SIZE", SO_VM_SOCKETS_BUFFER_MIN_SIZE, sizes.min),
+        ("SO_VM_SOCKETS_BUFFER_SIZE", SO_VM_SOCKETS_BUFFER_SIZE, sizes.size),
+    ];
+    for (name, option, value) in settings {
+        // SAFETY: fd 为传输持有的有效套接字，value 在调用期间有效
+        let ret = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::AF_VSOCK,
+                option,
+                &value as *const u64 as *const libc::c_void,
+                std::mem::size_of::<u64>() as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            return Err(buffer_option_error("set", name, std::io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+/// 读取连接套接字实际生效的缓冲区大小，没有文件描述符或不是 vsock 套接字时返回 `ConfigError`
+pub(crate) fn socket_buffer_sizes(transport: &dyn Transport) -> Result<SocketBufferSizes> {
+    let Some(fd) = transport.raw_fd().filter(|&fd| is_vsock(fd)) else {
+        return Err(crate::error::VirgeError::ConfigError(
+            "socket buffer sizes are only available on vsock sockets".to_string(),
+        ));
+    };
+    let read = |name: &str, option: libc::c_int| {
+        let mut value: u64 = 0;
+        let mut len = std::mem::size_of::<u64>() as libc::socklen_t;
+        // SAFETY: 选项值指向有效的 u64，长度与之一致
+        let ret = unsafe {
+            libc::getsockopt(fd, libc::AF_VSOCK, option, &mut value as *mut u64 as *mut libc::c_void, &mut len)
+        };
+        if ret != 0 {
+            return Err(buffer_option_error("read", name, std::io::Error::last_os_error()));
+        }
+        Ok(value)
+    };
+    Ok(SocketBufferSizes {
+        min: read("SO_VM_SOCKETS_BUFFER_MIN_SIZE", SO_VM_SOCKETS_BUFFER_MIN_SIZE)?,
+        size: read("SO_VM_SOCKETS_BUFFER_SIZE", SO_VM_SOCKETS_BUFFER_SIZE)?,
+        max: read("SO_VM_SOCKETS_BUFFER_MAX_SIZE", SO_VM_SOCKETS_BUFFER_MAX_SIZE)?,
+    })
+}
+
+/// 不阻塞地检查套接字是否仍然连接：已挂断、出错或对端已关闭套接字的写方向时返回 `false`
+///
+/// virga 的半关闭以流内控制帧传递，不会关闭套接字的写方向，因此后者只出现在对端断开连接时，
+/// 即使关闭控制帧尚未被读出。可读时只窥视一个字节，不消费数据；无法判断（如 poll 被信号中断）时视为存活。
+pub(crate) fn socket_alive(fd: RawFd) -> bool {
+    let mut pfd = libc::pollfd { fd, events: libc::POLLIN | libc::POLLRDHUP, revents: 0 };
+    // SAFETY: pfd 在调用期间有效，超时为 0 不阻塞
+    if unsafe { libc::poll(&mut pfd, 1, 0) } <= 0 {
+        return true;
+    }
+    if pfd.revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL | libc::POLLRDHUP) != 0 {
+        return false;
+    }
+    if pfd.revents & libc::POLLIN == 0 {
+        return true;
+    }
+    let mut byte = 0u8;
+    // SAFETY: 向 1 字节的栈上缓冲区窥视读取，fd 在调用期间保持打开
+    let n = unsafe { libc::recv(fd, (&mut byte as *mut u8).cast(), 1, libc::MSG_PEEK | libc::MSG_DONTWAIT) };
+    match n {
+        0 => false,
+        n if n > 0 => true,
+        _ => std::io::Error::last_os_error().kind() == std::io::ErrorKind::WouldBlock,
+    }
+}
+
+/// 建立 vsock 连接失败时的错误转换，`local_port` 被占用时返回 `VirgeError::LocalPortInUse`
+#[cfg(any(feature = "use-xtransport", feature = "use-yamux", feature = "use-raw"))]
+pub(crate) fn vsock_connect_error(local_port: Option<u32>, e: std::io::Error) -> crate::error::VirgeError {
+    match local_port {
+        Some(port) if e.kind() == std::io::ErrorKind::AddrInUse => crate::error::VirgeError::LocalPortInUse { port },
+        _ => crate::error::VirgeError::connection_io("Failed to connect vsock", e),
+    }
+}
+
+/// 建立 tokio vsock 连接，设置 `local_port` 时先将套接字绑定到该本地端口
+///
+/// 连接完成前 future 被 drop 时套接字随之关闭，可配合 `tokio::time::timeout` 使用。
+#[cfg(any(feature = "use-yamux", feature = "use-raw"))]
+pub(crate) async fn connect_tokio_vsock(local_port: Option<u32>, cid: u32, port: u32) -> Result<tokio_vsock::VsockStream> {
+    use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
+
+    let Some(local) = local_port else {
+        return tokio_vsock::VsockStream::connect(VsockAddr::new(cid, port))
+            .await
+            .map_err(|e| vsock_connect_error(None, e));
+    };
+    let connect = async {
+        let fd = sys::bind_local_vsock(local)?;
+        if sys::start_connect(fd.as_raw_fd(), cid, port)? {
+            return Ok(fd);
+        }
+        let fd = tokio::io::unix::AsyncFd::with_interest(fd, tokio::io::Interest::WRITABLE)?;
+        drop(fd.writable().await?);
+        sys::finish_connect(fd.as_raw_fd())?;
+        Ok::<_, std::io::Error>(fd.into_inner())
+    };
+    let fd = connect.await.map_err(|e| vsock_connect_error(local_port, e))?;
+    // SAFETY: 描述符为已连接的非阻塞 vsock 套接字，所有权转移给流
+    Ok(unsafe { tokio_vsock::VsockStream::from_raw_fd(fd.into_raw_fd()) })
+}
+
+/// 非阻塞地发送一条消息，发送缓冲区或 yamux 发送窗口已满时返回 `ErrorKind::WouldBlock`
+///
+/// 一旦写入了部分数据，则等待整条消息写完以保证消息完整；不等待 ACK 确认。
+pub(crate) async fn send_nonblocking(transport: &mut dyn Transport, data: &[u8]) -> Result<()> {
+    match transport.try_send(data).await? {
+        Some(_) => Ok(()),
+        None => Err(crate::error::VirgeError::IoError(std::io::Error::new(
+            std::io::ErrorKind::WouldBlock,
+            "send buffer full",
+        ))),
+    }
+}
+
+/// 分配进程内唯一的连接 ID，从 1 开始单调递增
+pub(crate) fn next_connection_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// 校验 `uds_path`：Unix 套接字路径只与 raw 帧格式搭配使用
+#[cfg(feature = "use-uds")]
+pub(crate) fn check_uds_path(kind: TransportKind, options: &TransportOptions) -> Result<()> {
+    if options.uds_path.is_some() && kind != TransportKind::Raw {
+        return Err(crate::error::VirgeError::ConfigError(format!(
+            "uds_path requires TransportKind::Raw, got {:?}",
+            kind
+        )));
+    }
+    Ok(())
+}
+
+/// 校验 `seqpacket`：SOCK_SEQPACKET 只用于直连 vsock 的 raw 传输
+#[cfg(feature = "use-raw")]
+pub(crate) fn check_seqpacket(kind: TransportKind, options: &TransportOptions) -> Result<()> {
+    if !options.seqpacket {
+        return Ok(());
+    }
+    if kind != TransportKind::Raw {
+        return Err(crate::error::VirgeError::ConfigError(format!(
+            "seqpacket requires TransportKind::Raw, got {:?}",
+            kind
+        )));
+    }
+    #[cfg(feature = "use-uds")]
+    if options.uds_path.is_some() {
+        return Err(crate::error::VirgeError::ConfigError(
+            "seqpacket cannot be combined with uds_path".to_string(),
+        ));
+    }
+    Ok(())
 }
 
+/// 传输协议类型
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransportKind {
+    /// 基于 xtransport 的分帧传输
+    #[cfg(feature = "use-xtransport")]
+    XTransport,
+    /// 基于 yamux 的多路复用传输
+    #[cfg(feature = "use-yamux")]
+    Yamux,
+    /// 4 字节长度前缀的裸流传输，无多路复用
+    #[cfg(feature = "use-raw")]
+    Raw,
+    /// 映射到 `127.0.0.1:port` 的本地 TCP 传输，用于没有虚拟机的开发与测试
+    #[cfg(feature = "use-tcp")]
+    Tcp,
+}
+
+/// 默认协议：优先级依次为 xtransport、yamux、raw、tcp
+#[cfg(feature = "use-xtransport")]
+const DEFAULT_KIND: TransportKind = TransportKind::XTransport;
+#[cfg(all(feature = "use-yamux", not(feature = "use-xtransport")))]
+const DEFAULT_KIND: TransportKind = TransportKind::Yamux;
+#[cfg(all(feature = "use-raw", not(any(feature = "use-xtransport", feature = "use-yamux"))))]
+const DEFAULT_KIND: TransportKind = TransportKind::Raw;
+#[cfg(all(
+    feature = "use-tcp",
+    not(any(feature = "use-xtransport", feature = "use-yamux", feature = "use-raw"))
+))]
+const DEFAULT_KIND: TransportKind = TransportKind::Tcp;
+
+impl Default for TransportKind {
+    fn default() -> Self {
+        DEFAULT_KIND
+    }
+}
+
+impl std::str::FromStr for TransportKind {
+    type Err = crate::error::VirgeError;
+
+    /// 按协议名解析（`xtransport`、`yamux`、`raw`、`tcp`），未启用对应特性的协议返回 `ConfigError`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            #[cfg(feature = "use-xtransport")]
+            "xtransport" => Ok(TransportKind::XTransport),
+            #[cfg(feature = "use-yamux")]
+            "yamux" => Ok(TransportKind::Yamux),
+            #[cfg(feature = "use-raw")]
+            "raw" => Ok(TransportKind::Raw),
+            #[cfg(feature = "use-tcp")]
+            "tcp" => Ok(TransportKind::Tcp),
+            _ => Err(crate::error::VirgeError::ConfigError(format!(
+                "Unknown or disabled transport kind '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+impl TransportKind {
+    /// 握手消息中的协议类型字节
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            #[cfg(feature = "use-xtransport")]
+            TransportKind::XTransport => crate::protocol::KIND_XTRANSPORT,
+            #[cfg(feature = "use-yamux")]
+            TransportKind::Yamux => crate::protocol::KIND_YAMUX,
+            #[cfg(feature = "use-raw")]
+            TransportKind::Raw => crate::protocol::KIND_RAW,
+            #[cfg(feature = "use-tcp")]
+            TransportKind::Tcp => crate::protocol::KIND_TCP,
+        }
+    }
+
+    /// 为指定协议创建客户端或服务器模式的传输实例
+    ///
+    /// `ack` 为配置中的 ACK 设置：xtransport 交给其自身的 ACK 机制，其他协议叠加 `AckTransport`。
+    #[cfg_attr(not(feature = "use-yamux"), allow(unused_variables))]
+    pub(crate) fn create(self, is_server: bool, ack: bool, options: &TransportOptions) -> Box<dyn Transport> {
+        let ack = ack && !self.has_native_ack();
+        let hello = options.hello(self, ack);
+        let transport: Box<dyn Transport> = match self {
+            #[cfg(feature = "use-xtransport")]
+            TransportKind::XTransport => Box::new(
+                XTransportHandler::new()
+                    .with_handshake(options.handshake)
+                    .with_hello(hello)
+                    .with_local_port(options.local_port),
+            ),
+            #[cfg(feature = "use-yamux")]
+            TransportKind::Yamux => {
+                let transport = if is_server {
+                    YamuxTransport::new_server()
+                } else {
+                    YamuxTransport::new_client()
+                };
+                Box::new(
+                    transport
+                        .with_config(options.yamux.clone())
+                        .with_handshake(options.handshake)
+                        .with_hello(hello)
+                        .with_local_port(options.local_port)
+                        .with_max_message_size(options.frame_limit(ack))
+                        .with_buffer_pool_size(options.buffer_pool_size),
+                )
+            }
+            // 配置了 uds_path 时经监控程序导出的 Unix 套接字收发 raw 帧
+            #[cfg(feature = "use-uds")]
+            TransportKind::Raw if options.uds_path.is_some() => Box::new(
+                UdsTransport::new(options.uds_path.clone().unwrap_or_default())
+                    .with_handshake(options.handshake)
+                    .with_hello(hello)
+                    .with_max_message_size(options.frame_limit(ack))
+                    .with_buffer_pool_size(options.buffer_pool_size),
+            ),
+            // 启用 seqpacket 时改用 SOCK_SEQPACKET 套接字，消息边界由内核保留
+            #[cfg(feature = "use-raw")]
+            TransportKind::Raw if options.seqpacket => Box::new(
+                SeqpacketTransport::new()
+                    .with_handshake(options.handshake)
+                    .with_hello(hello)
+                    .with_local_port(options.local_port)
+                    .with_max_message_size(options.frame_limit(ack)),
+            ),
+            #[cfg(feature = "use-raw")]
+            TransportKind::Raw => Box::new(
+                RawTransport::new()
+                    .with_handshake(options.handshake)
+                    .with_hello(hello)
+                    .with_local_port(options.local_port)
+                    .with_max_message_size(options.frame_limit(ack))
+                    .with_buffer_pool_size(options.buffer_pool_size),
+            ),
+            #[cfg(feature = "use-tcp")]
+            TransportKind::Tcp => Box::new(
+                TcpTransport::new()
+                    .with_handshake(options.handshake)
+                    .with_hello(hello)
+                    .with_max_message_size(options.frame_limit(ack))
+                    .with_buffer_pool_size(options.buffer_pool_size),
+            ),
+        };
+        options.wrap(transport, ack)
+    }
+
+    /// 协议自身是否实现 ACK（xtransport 在连接级别确认每条消息）
+    fn has_native_ack(self) -> bool {
+        #[cfg(feature = "use-xtransport")]
+        if self == TransportKind::XTransport {
+            return true;
+        }
+        false
+    }
+}
+
+/// 配置中携带的认证令牌，调试输出不显示其内容
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct AuthToken(pub(crate) Vec<u8>);
+
+impl std::fmt::Debug for AuthToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AuthToken({} bytes)", self.0.len())
+    }
+}
+
+/// 各传输协议的专有参数，由 `ClientConfig`/`ServerConfig` 携带并在创建传输实例时传入
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TransportOptions {
+    #[cfg(feature = "use-yamux")]
+    pub(crate) yamux: YamuxConfig,
+    pub(crate) keepalive: Option<keepalive::KeepaliveConfig>,
+    /// 是否执行连接握手
+    pub(crate) handshake: bool,
+    /// 单条用户消息的最大字节数
+    pub(crate) max_message_size: usize,
+    /// 收发事件观察者
+    pub(crate) observer: Option<observer::ObserverHandle>,
+    /// 是否为每条消息追加并校验 CRC32
+    pub(crate) integrity: bool,
+    /// 是否启用优先通道
+    pub(crate) lanes: bool,
+    /// 是否启用消息元数据
+    pub(crate) metadata: bool,
+    /// 是否启用消息序号
+    pub(crate) sequence: bool,
+    /// 客户端握手时附带的认证令牌
+    pub(crate) auth_token: Option<AuthToken>,
+    /// 加密使用的预共享密钥，`None` 表示不加密
+    #[cfg(feature = "encryption")]
+    pub(crate) encryption: Option<secure::PresharedKey>,
+    /// 确认包装器允许的未确认消息数
+    pub(crate) ack_window: u32,
+    /// 底层套接字的内核发送缓冲区字节数，`None` 使用系统默认值
+    pub(crate) send_buffer_limit: Option<usize>,
+    /// vsock 套接字的缓冲区大小，`None` 使用系统默认值
+    pub(crate) socket_buffer: Option<SocketBufferSizes>,
+    /// 发送缓冲区已满时 `send` 返回 `ErrorKind::WouldBlock` 而不是等待
+    pub(crate) nonblocking_send: bool,
+    /// 接收缓冲区池至多保存的空闲缓冲区数，0 表示不启用
+    pub(crate) buffer_pool_size: usize,
+    /// 客户端连接前绑定的本地 vsock 端口，`None` 由内核分配临时端口
+    pub(crate) local_port: Option<u32>,
+    /// 握手时双方 chunk_size 不一致的处理策略
+    pub(crate) chunk_policy: ChunkSizePolicy,
+    /// 发送遇到暂时性错误时的重试策略，`None` 表示不重试
+    pub(crate) send_retry: Option<retry::SendRetry>,
+    /// 收发限速，由 `VirgeClient`/`VirgeServer` 叠加在统计包装器之下
+    pub(crate) rate_limit: ratelimit::RateLimits,
+    /// 消息压缩算法，`None` 表示不压缩
+    #[cfg(feature = "compression")]
+    pub(crate) compression: Option<compression::Compression>,
+    /// 监控程序导出的 vsock Unix 套接字路径，设置后 raw 传输经由该套接字连接
+    #[cfg(feature = "use-uds")]
+    pub(crate) uds_path: Option<std::path::PathBuf>,
+    /// raw 传输改用 SOCK_SEQPACKET vsock 套接字
+    #[cfg(feature = "use-raw")]
+    pub(crate) seqpacket: bool,
+    /// 注入到最内层的故障计划
+    #[cfg(feature = "testing")]
+    pub(crate) faults: Option<fault::FaultPlan>,
+    /// 叠加在传输协议之上的流量录制器
+    #[cfg(feature = "testing")]
+    pub(crate) recorder: Option<record::TrafficRecorder>,
+}
+
+impl Default for TransportOptions {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "use-yamux")]
+            yamux: YamuxConfig::default(),
+            keepalive: None,
+            handshake: true,
+            max_message_size: crate::DEFAULT_MAX_MESSAGE_SIZE,
+            observer: None,
+            integrity: false,
+            lanes: false,
+            metadata: false,
+            sequence: false,
+            auth_token: None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            ack_window: 1,
+            send_buffer_limit: None,
+            socket_buffer: None,
+            nonblocking_send: false,
+            buffer_pool_size: crate::DEFAULT_BUFFER_POOL_SIZE,
+            local_port: None,
+            chunk_policy: ChunkSizePolicy::default(),
+            send_retry: None,
+            rate_limit: ratelimit::RateLimits::default(),
+            #[cfg(feature = "compression")]
+            compression: None,
+            #[cfg(feature = "use-uds")]
+            uds_path: None,
+            #[cfg(feature = "use-raw")]
+            seqpacket: false,
+            #[cfg(feature = "testing")]
+            faults: None,
+            #[cfg(feature = "testing")]
+            recorder: None,
+        }
+    }
+}
+
+impl TransportOptions {
+    /// 握手时附带的认证令牌
+    pub(crate) fn auth_token(&self) -> Option<Vec<u8>> {
+        self.auth_token.as_ref().map(|token| token.0.clone())
+    }
+
+    /// 是否使用 SOCK_SEQPACKET 套接字，未启用 `use-raw` 时恒为 `false`
+    pub(crate) fn uses_seqpacket(&self) -> bool {
+        #[cfg(feature = "use-raw")]
+        if self.seqpacket {
+            return true;
+        }
+        false
+    }
+
+    /// 压缩包装器之上的单条消息上限：用户消息上限加上 send_msg 长度前缀、元数据、序号、通道标记、确认帧头与心跳帧类型的开销
+    #[cfg(any(
+        feature = "use-yamux",
+        feature = "use-raw",
+        feature = "use-tcp",
+        feature = "testing",
+        feature = "compression"
+    ))]
+    fn message_limit(&self, ack: bool) -> usize {
+        let ack = if ack { ack::HEADER_SIZE } else { 0 };
+        let keepalive = if self.keepalive.is_some() { 1 } else { 0 };
+        let lanes = if self.lanes { lane::HEADER_SIZE } else { 0 };
+        self.lane_limit()
+            .saturating_add(lanes)
+            .saturating_add(ack)
+            .saturating_add(keepalive)
+    }
+
+    /// 优先通道拼接后的单条消息上限：用户消息上限加上 send_msg 长度前缀、元数据与序号的开销
+    fn lane_limit(&self) -> usize {
+        let metadata = if self.metadata { metadata::HEADER_SIZE + crate::protocol::MAX_METADATA_SIZE } else { 0 };
+        let sequence = if self.sequence { sequence::HEADER_SIZE } else { 0 };
+        self.max_message_size
+            .saturating_add(framing::LEN_PREFIX_SIZE)
+            .saturating_add(metadata)
+            .saturating_add(sequence)
+    }
+
+    /// 字节流传输层的帧长度上限：在 `message_limit` 之上再加压缩帧头、校验和与加密帧的开销
+    #[cfg(any(feature = "use-yamux", feature = "use-raw", feature = "use-tcp", feature = "testing"))]
+    pub(crate) fn frame_limit(&self, ack: bool) -> usize {
+        #[cfg(feature = "compression")]
+        let compression = if self.compression.is_some() { compression::HEADER_SIZE } else { 0 };
+        #[cfg(not(feature = "compression"))]
+        let compression = 0;
+        let integrity = if self.integrity { integrity::CHECKSUM_SIZE } else { 0 };
+        #[cfg(feature = "encryption")]
+        let encryption = if self.encryption.is_some() { secure::OVERHEAD } else { 0 };
+        #[cfg(not(feature = "encryption"))]
+        let encryption = 0;
+        self.message_limit(ack)
+            .saturating_add(compression)
+            .saturating_add(integrity)
+            .saturating_add(encryption)
+    }
+
+    /// 按叠加的包装器生成 `kind` 协议在握手中声明的各层设置，`ack` 为是否叠加确认包装器
+    pub(crate) fn hello(&self, kind: TransportKind, ack: bool) -> preamble::Hello {
+        preamble::Hello::without_chunk_size(kind.to_byte(), ack)
+            .with_integrity(self.integrity)
+            .with_compression(self.compression_byte())
+            .with_lanes(self.lanes)
+            .with_metadata(self.metadata)
+            .with_sequence(self.sequence)
+            .with_auth_token(self.auth_token())
+            .with_encryption(self.encryption_byte())
+            .with_ack_window(self.ack_window)
+            .with_chunk_policy(self.chunk_policy)
+    }
+
+    /// 握手中声明的压缩算法字节，0 表示不压缩
+    fn compression_byte(&self) -> u8 {
+        #[cfg(feature = "compression")]
+        if let Some(compression) = self.compression {
+            return compression.to_byte();
+        }
+        0
+    }
+
+    /// 握手中声明的加密算法字节，0 表示不加密
+    fn encryption_byte(&self) -> u8 {
+        #[cfg(feature = "encryption")]
+        if self.encryption.is_some() {
+            return crate::protocol::CIPHER_X25519_CHACHA20POLY1305;
+        }
+        0
+    }
+
+    /// 是否启用加密，未启用 `encryption` 特性时恒为 `false`
+    pub(crate) fn uses_encryption(&self) -> bool {
+        self.encryption_byte() != 0
+    }
+
+    /// 按配置依次叠加流量录制、故障注入、发送重试、加密、完整性校验、压缩、心跳保活、送达确认、优先通道、消息序号、元数据与观察者包装器
+    pub(crate) fn wrap(&self, transport: Box<dyn Transport>, ack: bool) -> Box<dyn Transport> {
+        // 录制紧贴传输协议，记录的是链路上实际收发的帧，被注入的故障不会进入日志
+        #[cfg(feature = "testing")]
+        let transport: Box<dyn Transport> = match &self.recorder {
+            Some(recorder) => Box::new(record::RecordingTransport::boxed(
+                transport,
+                recorder,
+                record::RecordedLayers::new(self, ack),
+            )),
+            None => transport,
+        };
+        // 故障注入位于录制之上，模拟链路本身的故障
+        #[cfg(feature = "testing")]
+        let transport: Box<dyn Transport> = match &self.faults {
+            Some(plan) => Box::new(fault::FaultyTransport::boxed(transport, plan.clone())),
+            None => transport,
+        };
+        // 重试位于故障注入之上，重发的是已经过各层封装、未能写出的帧
+        let transport: Box<dyn Transport> = match self.send_retry {
+            Some(policy) => Box::new(retry::RetryTransport::new(transport, policy)),
+            None => transport,
+        };
+        // 加密位于重试之上，重发的是同一条密文；其上各层的帧头与数据都被加密
+        #[cfg(feature = "encryption")]
+        let transport: Box<dyn Transport> = match &self.encryption {
+            Some(psk) => Box::new(secure::SecureTransport::new(transport, psk.clone())),
+            None => transport,
+        };
+        // 校验位于加密之上，心跳帧同样受校验保护
+        let transport: Box<dyn Transport> = if self.integrity {
+            Box::new(integrity::IntegrityTransport::new(transport))
+        } else {
+            transport
+        };
+        // 压缩位于校验之上，校验覆盖的是实际传输的压缩后数据
+        #[cfg(feature = "compression")]
+        let transport: Box<dyn Transport> = match self.compression {
+            Some(algorithm) => Box::new(compression::CompressionTransport::new(
+                transport,
+                algorithm,
+                self.message_limit(ack),
+            )),
+            None => transport,
+        };
+        let transport: Box<dyn Transport> = match self.keepalive {
+            Some(config) => Box::new(keepalive::KeepaliveTransport::new(transport, config)),
+            None => transport,
+        };
+        // 确认位于心跳之上，等待 ACK 期间的心跳帧由下层处理
+        let transport: Box<dyn Transport> = if ack {
+            Box::new(ack::AckTransport::new(transport, self.ack_window))
+        } else {
+            transport
+        };
+        // 分片位于确认之上，每个分片单独确认
+        let transport: Box<dyn Transport> = if self.lanes {
+            Box::new(lane::LaneTransport::new(transport, self.lane_limit()))
+        } else {
+            transport
+        };
+        // 序号位于分片之上，为整条普通消息编号，优先消息不编号
+        let transport: Box<dyn Transport> = if self.sequence {
+            Box::new(sequence::SequenceTransport::new(transport))
+        } else {
+            transport
+        };
+        // 元数据位于分片之上，一条消息的元数据只随第一片发送
+        let transport: Box<dyn Transport> = if self.metadata {
+            Box::new(metadata::MetadataTransport::new(transport))
+        } else {
+            transport
+        };
+        // 观察者位于最外层，只看到用户数据，不包含心跳帧
+        match &self.observer {
+            Some(observer) => Box::new(observer::ObservedTransport::new(transport, observer.0.clone())),
+            None => transport,
+        }
+    }
+}
+
+/// vsock 地址类型，tokio-vsock 与 vsock 共用同一定义
+#[cfg(feature = "use-xtransport")]
+pub use vsock::VsockAddr;
+#[cfg(all(feature = "tokio-runtime", not(feature = "use-xtransport")))]
+pub use tokio_vsock::VsockAddr;
+
 // 具体实现模块
 #[cfg(feature = "use-yamux")]
-pub use yamux_impl::YamuxTransport;
+pub use yamux_impl::{VirgeStream, YamuxConfig, YamuxTransport};
 #[cfg(feature = "use-xtransport")]
 pub use xtransport_impl::XTransportHandler;
+#[cfg(any(feature = "use-raw", feature = "use-tcp"))]
+pub use stream_impl::StreamTransport;
+#[cfg(feature = "use-raw")]
+pub use raw_impl::RawTransport;
+#[cfg(feature = "use-tcp")]
+pub use tcp_impl::TcpTransport;
+#[cfg(feature = "use-uds")]
+pub use uds_impl::UdsTransport;
+#[cfg(feature = "use-raw")]
+pub use seqpacket_impl::SeqpacketTransport;
+#[cfg(feature = "testing")]
+pub use memory_impl::MemoryTransport;
+pub use stats::Stats;
+pub use ack::AckStats;
+pub use metadata::HeaderMap;
+pub use preamble::ChunkSizePolicy;
+pub use ratelimit::RateLimitPolicy;
+#[cfg(feature = "compression")]
+pub use compression::Compression;
+#[cfg(feature = "testing")]
+pub use fault::{FaultPlan, FaultyTransport};
+#[cfg(feature = "testing")]
+pub use record::{Record, RecordedEvent, RecordedLayers, RecordingTransport, ReplayTransport, TrafficLog, TrafficRecorder};
+pub use observer::{HexDumpObserver, TransportObserver};
@@ -0,0 +1,352 @@
+//! 传输观察者模块
+//!
+//! 通过 `ClientConfig::with_observer` / `ServerConfig::with_observer` 注册观察者后，
+//! 传输实例外层叠加一个包装器，在连接、断开与每条消息收发成功时回调观察者。
+//! 未注册观察者时不创建包装器，没有任何额外开销。
+//!
+//! 回调只拿到数据的只读切片，无法修改收发的内容；回调在收发路径上同步执行，应保持轻量。
+
+use crate::error::Result;
+use crate::transport::bufpool::BufferPool;
+use crate::transport::lane::PriorityOutbox;
+use crate::transport::{AckStats, HeaderMap, Transport, VsockAddr};
+use async_trait::async_trait;
+use log::*;
+use std::fmt;
+use std::io::IoSlice;
+use std::sync::Arc;
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+/// 传输事件观察者，所有回调默认不做任何事
+pub trait TransportObserver: Send + Sync {
+    /// 连接建立（含服务器从已接受的流初始化）
+    fn on_connect(&self) {}
+
+    /// 连接断开
+    fn on_disconnect(&self) {}
+
+    /// 一条消息发送成功
+    fn on_send(&self, _data: &[u8]) {}
+
+    /// 收到一条消息
+    fn on_recv(&self, _data: &[u8]) {}
+}
+
+/// 以 `trace!` 级别输出收发数据十六进制摘要的观察者
+#[derive(Clone, Copy, Debug)]
+pub struct HexDumpObserver {
+    /// 每条消息最多输出的字节数
+    limit: usize,
+}
+
+impl HexDumpObserver {
+    pub fn new(limit: usize) -> Self {
+        Self { limit }
+    }
+
+    fn dump(&self, direction: &str, data: &[u8]) {
+        if !log_enabled!(Level::Trace) {
+            return;
+        }
+        let shown = &data[..data.len().min(self.limit)];
+        let mut hex = String::with_capacity(shown.len() * 3);
+        for (i, byte) in shown.iter().enumerate() {
+            if i > 0 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x}", byte));
+        }
+        let ellipsis = if data.len() > shown.len() { " ..." } else { "" };
+        trace!("{} {} bytes: {}{}", direction, data.len(), hex, ellipsis);
+    }
+}
+
+impl Default for HexDumpObserver {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+
+impl TransportObserver for HexDumpObserver {
+    fn on_connect(&self) {
+        trace!("connected");
+    }
+
+    fn on_disconnect(&self) {
+        trace!("disconnected");
+    }
+
+    fn on_send(&self, data: &[u8]) {
+        self.dump("send", data);
+    }
+
+    fn on_recv(&self, data: &[u8]) {
+        self.dump("recv", data);
+    }
+}
+
+/// 配置中携带的观察者，可随配置一起克隆
+#[derive(Clone)]
+pub(crate) struct ObserverHandle(pub(crate) Arc<dyn TransportObserver>);
+
+impl fmt::Debug for ObserverHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TransportObserver")
+    }
+}
+
+/// 观察者没有可比较的内容，两个配置携带同一个观察者实例（包括其克隆）时相等
+impl PartialEq for ObserverHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// 观察者包装器
+pub(crate) struct ObservedTransport {
+    inner: Box<dyn Transport>,
+    observer: Arc<dyn TransportObserver>,
+}
+
+impl ObservedTransport {
+    pub(crate) fn new(inner: Box<dyn Transport>, observer: Arc<dyn TransportObserver>) -> Self {
+        Self { inner, observer }
+    }
+}
+
+#[async_trait]
+impl Transport for ObservedTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.connect(cid, port, chunksize, isack).await?;
+        self.observer.on_connect();
+        Ok(())
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await?;
+        self.observer.on_connect();
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.inner.from_tokio_stream(stream).await?;
+        self.observer.on_connect();
+        Ok(())
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.inner.from_tcp_stream(stream).await?;
+        self.observer.on_connect();
+        Ok(())
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.inner.from_uds_stream(stream).await?;
+        self.observer.on_connect();
+        Ok(())
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        self.inner.from_seqpacket(socket).await?;
+        self.observer.on_connect();
+        Ok(())
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.from_stream(stream, chunksize, isack).await?;
+        self.observer.on_connect();
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        let result = self.inner.disconnect().await;
+        self.observer.on_disconnect();
+        result
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        let result = self.inner.disconnect_with_reason(code, message).await;
+        self.observer.on_disconnect();
+        result
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        // 回调只需要只读视图，send 消费数据前无法借出，因此保留一份副本
+        let copy = data.clone();
+        self.inner.send(data).await?;
+        self.observer.on_send(&copy);
+        Ok(())
+    }
+
+    async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        let copy = data.clone();
+        self.inner.send_noack(data).await?;
+        self.observer.on_send(&copy);
+        Ok(())
+    }
+
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        let sent = self.inner.send_slices(slices).await?;
+        let data: Vec<u8> = slices.iter().flat_map(|slice| slice.iter().copied()).collect();
+        self.observer.on_send(&data);
+        Ok(sent)
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        let data = self.inner.recv().await?;
+        self.observer.on_recv(&data);
+        Ok(data)
+    }
+
+    async fn recv_into(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        let n = self.inner.recv_into(buf).await?;
+        self.observer.on_recv(&buf[..n]);
+        Ok(n)
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        let data = self.inner.try_recv().await?;
+        if let Some(data) = &data {
+            self.observer.on_recv(data);
+        }
+        Ok(data)
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        let sent = self.inner.try_send(data).await?;
+        if sent.is_some() {
+            self.observer.on_send(data);
+        }
+        Ok(sent)
+    }
+
+    async fn send_priority(&mut self, data: Vec<u8>) -> Result<()> {
+        let copy = data.clone();
+        self.inner.send_priority(data).await?;
+        self.observer.on_send(&copy);
+        Ok(())
+    }
+
+    async fn recv_priority(&mut self) -> Result<Vec<u8>> {
+        let data = self.inner.recv_priority().await?;
+        self.observer.on_recv(&data);
+        Ok(data)
+    }
+
+    async fn try_recv_priority(&mut self) -> Result<Option<Vec<u8>>> {
+        let data = self.inner.try_recv_priority().await?;
+        if let Some(data) = &data {
+            self.observer.on_recv(data);
+        }
+        Ok(data)
+    }
+
+    fn priority_outbox(&self) -> Option<PriorityOutbox> {
+        self.inner.priority_outbox()
+    }
+
+    async fn send_with_meta(&mut self, data: Vec<u8>, meta: &HeaderMap) -> Result<()> {
+        let copy = data.clone();
+        self.inner.send_with_meta(data, meta).await?;
+        self.observer.on_send(&copy);
+        Ok(())
+    }
+
+    async fn recv_with_meta(&mut self) -> Result<(Vec<u8>, HeaderMap)> {
+        let (data, meta) = self.inner.recv_with_meta().await?;
+        self.observer.on_recv(&data);
+        Ok((data, meta))
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        self.inner.shutdown_write().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        self.inner.probe(timeout).await
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.inner.last_ack_latency()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.inner.ack_stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        self.inner.last_sent_seq()
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        self.inner.last_received_seq()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
@@ -0,0 +1,606 @@
+//! 连接握手
+//!
+//! 连接建立后、传输协议初始化前，客户端与服务器交换一条固定长度的握手消息并各自校验：
+//! 魔数不符（对端不是 virga 或数据被破坏）、协议版本不一致、传输协议类型不一致，
+//! ACK 设置、完整性校验、压缩算法、优先通道、元数据或消息序号设置不一致时，双方都以 `VirgeError::ProtocolError` 失败。
+//! xtransport 的 chunk_size 不一致时按本端的 `ChunkSizePolicy` 处理：以 `VirgeError::ConfigError`
+//! 失败，或双方都采用两者中较小的值；其他传输协议不使用 chunk_size，不参与协商。
+//! 确认包装器允许的未确认消息数（ACK 窗口）取双方声明中较小的值，不会导致握手失败。
+//! 设置了认证令牌的一端在握手消息之后附带令牌，对端记录收到的令牌，由服务器在交付连接前交给认证函数检查。
+//! 双方的加密设置不一致时（一端加密另一端不加密）以 `VirgeError::EncryptionError` 失败，不会交换数据。
+//!
+//! 握手可通过 `with_handshake(false)` 关闭，以便与未进行握手的旧版本互通。
+//!
+//! # 消息格式
+//! ```text
+//! ┌──────────────┬────────────────┬──────────┬───────────┬────────────────────┬────────────────────┐
+//! │ magic: "VIRG"│ version: u16 BE│ kind: u8 │ flags: u8 │ chunk_size: u32 BE │ ack_window: u32 BE │
+//! └──────────────┴────────────────┴──────────┴───────────┴────────────────────┴────────────────────┘
+//! ```
+//!
+//! `flags` 的 bit 0 为 ACK，bit 1 为完整性校验，bit 2..=3 为压缩算法（0 不压缩、1 lz4、2 zstd），bit 4 为优先通道，bit 5 为消息元数据，bit 6 为消息序号，
+//! bit 7 表示其后附带扩展块（认证令牌、加密算法等条目），格式见 `protocol` 模块。
+//! 不认识 bit 5、bit 6 的旧版本对端不会校验它们，由启用该功能的一端检测到不一致并关闭连接；
+//! 不认识 bit 7 的旧版本对端会把扩展块误读为数据，设置令牌或启用加密的一端需连接新版本的对端。
+//! 各字段的常量定义见 `protocol` 模块。
+
+use crate::error::{Result, VirgeError};
+use crate::protocol::{
+    CIPHER_X25519_CHACHA20POLY1305, COMPRESSION_MASK, COMPRESSION_SHIFT, EXTENSION_HEADER_SIZE, EXTENSIONS_LEN_SIZE,
+    EXT_AUTH_TOKEN, EXT_ENCRYPTION, FLAG_ACK, FLAG_EXTENSIONS, FLAG_INTEGRITY, FLAG_LANES, FLAG_METADATA,
+    FLAG_SEQUENCE, HELLO_SIZE, KIND_RAW, KIND_TCP, KIND_XTRANSPORT, KIND_YAMUX, MAGIC, MAX_AUTH_TOKEN_SIZE,
+    MAX_EXTENSIONS_SIZE, PROTOCOL_VERSION,
+};
+
+/// 协议类型字节对应的名称，用于错误信息
+fn kind_name(byte: u8) -> &'static str {
+    match byte {
+        KIND_XTRANSPORT => "xtransport",
+        KIND_YAMUX => "yamux",
+        KIND_RAW => "raw",
+        KIND_TCP => "tcp",
+        _ => "unknown",
+    }
+}
+
+/// 压缩算法字节对应的名称，用于错误信息
+fn compression_name(byte: u8) -> &'static str {
+    match byte {
+        0 => "none",
+        1 => "lz4",
+        2 => "zstd",
+        _ => "unknown",
+    }
+}
+
+/// 加密算法字节对应的名称，用于错误信息
+fn cipher_name(byte: u8) -> &'static str {
+    match byte {
+        0 => "none",
+        CIPHER_X25519_CHACHA20POLY1305 => "x25519-chacha20poly1305",
+        _ => "unknown",
+    }
+}
+
+/// 握手时双方 chunk_size 不一致的处理策略，两端应一致
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChunkSizePolicy {
+    /// 要求双方相同，不一致时连接以 `VirgeError::ConfigError` 失败
+    #[default]
+    RequireEqual,
+    /// 双方都采用两者中较小的 chunk_size
+    UseMinimum,
+}
+
+/// 握手消息
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Hello {
+    version: u16,
+    kind: u8,
+    ack: bool,
+    integrity: bool,
+    compression: u8,
+    lanes: bool,
+    metadata: bool,
+    sequence: bool,
+    chunk_size: u32,
+    /// 允许的未确认消息数，1 表示逐条等待确认
+    ack_window: u32,
+    /// 本端处理 chunk_size 不一致的策略，不在握手消息中传输
+    chunk_policy: ChunkSizePolicy,
+    /// 附带在握手消息之后的认证令牌
+    auth_token: Option<Vec<u8>>,
+    /// 加密算法字节，0 表示不加密
+    encryption: u8,
+}
+
+impl Hello {
+    /// xtransport 握手，需校验 chunk_size 与 ACK 设置
+    pub(crate) fn new(kind: u8, chunk_size: u32, ack: bool) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            kind,
+            ack,
+            integrity: false,
+            compression: 0,
+            lanes: false,
+            metadata: false,
+            sequence: false,
+            chunk_size,
+            ack_window: 1,
+            chunk_policy: ChunkSizePolicy::default(),
+            auth_token: None,
+            encryption: 0,
+        }
+    }
+
+    /// 声明 xtransport 使用的 chunk_size
+    pub(crate) fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// 声明是否启用 ACK
+    pub(crate) fn with_ack(mut self, enabled: bool) -> Self {
+        self.ack = enabled;
+        self
+    }
+
+    /// 声明允许的未确认消息数
+    pub(crate) fn with_ack_window(mut self, window: u32) -> Self {
+        self.ack_window = window;
+        self
+    }
+
+    /// 设置 chunk_size 不一致时的处理策略
+    pub(crate) fn with_chunk_policy(mut self, policy: ChunkSizePolicy) -> Self {
+        self.chunk_policy = policy;
+        self
+    }
+
+    /// 声明是否启用逐消息 CRC32 校验
+    pub(crate) fn with_integrity(mut self, enabled: bool) -> Self {
+        self.integrity = enabled;
+        self
+    }
+
+    /// 声明使用的压缩算法字节，0 表示不压缩
+    pub(crate) fn with_compression(mut self, method: u8) -> Self {
+        self.compression = method;
+        self
+    }
+
+    /// 声明是否启用优先通道
+    pub(crate) fn with_lanes(mut self, enabled: bool) -> Self {
+        self.lanes = enabled;
+        self
+    }
+
+    /// 声明是否启用消息元数据
+    pub(crate) fn with_metadata(mut self, enabled: bool) -> Self {
+        self.metadata = enabled;
+        self
+    }
+
+    /// 声明是否启用消息序号
+    pub(crate) fn with_sequence(mut self, enabled: bool) -> Self {
+        self.sequence = enabled;
+        self
+    }
+
+    /// 在握手消息之后附带认证令牌，长度已由配置校验
+    pub(crate) fn with_auth_token(mut self, token: Option<Vec<u8>>) -> Self {
+        self.auth_token = token;
+        self
+    }
+
+    /// 声明使用的加密算法字节，0 表示不加密
+    pub(crate) fn with_encryption(mut self, cipher: u8) -> Self {
+        self.encryption = cipher;
+        self
+    }
+
+    /// 不使用 chunk_size 的传输协议（yamux、raw）的握手
+    pub(crate) fn without_chunk_size(kind: u8, ack: bool) -> Self {
+        Self::new(kind, 0, ack)
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = [0u8; HELLO_SIZE];
+        buf[..4].copy_from_slice(&MAGIC);
+        buf[4..6].copy_from_slice(&self.version.to_be_bytes());
+        buf[6] = self.kind;
+        buf[7] = if self.ack { FLAG_ACK } else { 0 }
+            | if self.integrity { FLAG_INTEGRITY } else { 0 }
+            | (self.compression << COMPRESSION_SHIFT) & COMPRESSION_MASK
+            | if self.lanes { FLAG_LANES } else { 0 }
+            | if self.metadata { FLAG_METADATA } else { 0 }
+            | if self.sequence { FLAG_SEQUENCE } else { 0 }
+            | if self.has_extensions() { FLAG_EXTENSIONS } else { 0 };
+        buf[8..12].copy_from_slice(&self.chunk_size.to_be_bytes());
+        buf[12..].copy_from_slice(&self.ack_window.to_be_bytes());
+        if !self.has_extensions() {
+            return buf.to_vec();
+        }
+        let mut extensions = Vec::new();
+        if let Some(token) = &self.auth_token {
+            push_extension(&mut extensions, EXT_AUTH_TOKEN, token);
+        }
+        if self.encryption != 0 {
+            push_extension(&mut extensions, EXT_ENCRYPTION, &[self.encryption]);
+        }
+        let mut message = Vec::with_capacity(HELLO_SIZE + EXTENSIONS_LEN_SIZE + extensions.len());
+        message.extend_from_slice(&buf);
+        message.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        message.extend_from_slice(&extensions);
+        message
+    }
+
+    /// 本端握手消息之后是否附带扩展块
+    fn has_extensions(&self) -> bool {
+        self.auth_token.is_some() || self.encryption != 0
+    }
+
+    fn decode(buf: &[u8; HELLO_SIZE]) -> Result<Self> {
+        if buf[..4] != MAGIC {
+            return Err(VirgeError::ProtocolError(format!(
+                "Invalid handshake magic {:02x?}, peer is not a virga endpoint or has handshake disabled",
+                &buf[..4]
+            )));
+        }
+        Ok(Self {
+            version: u16::from_be_bytes([buf[4], buf[5]]),
+            kind: buf[6],
+            ack: buf[7] & FLAG_ACK != 0,
+            integrity: buf[7] & FLAG_INTEGRITY != 0,
+            compression: (buf[7] & COMPRESSION_MASK) >> COMPRESSION_SHIFT,
+            lanes: buf[7] & FLAG_LANES != 0,
+            metadata: buf[7] & FLAG_METADATA != 0,
+            sequence: buf[7] & FLAG_SEQUENCE != 0,
+            chunk_size: u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]),
+            ack_window: u32::from_be_bytes([buf[12], buf[13], buf[14], buf[15]]),
+            chunk_policy: ChunkSizePolicy::default(),
+            auth_token: None,
+            encryption: 0,
+        })
+    }
+
+    /// 对端声明握手消息之后附带扩展块
+    fn has_extensions(buf: &[u8; HELLO_SIZE]) -> bool {
+        buf[7] & FLAG_EXTENSIONS != 0
+    }
+
+    /// 解析扩展块中的条目，跳过不认识的类型
+    fn apply_extensions(&mut self, mut block: &[u8]) -> Result<()> {
+        let truncated = || VirgeError::ProtocolError("Truncated handshake extension".to_string());
+        while !block.is_empty() {
+            let (header, rest) = block.split_first_chunk::<EXTENSION_HEADER_SIZE>().ok_or_else(truncated)?;
+            let len = u16::from_be_bytes([header[1], header[2]]) as usize;
+            if rest.len() < len {
+                return Err(truncated());
+            }
+            let (value, rest) = rest.split_at(len);
+            match header[0] {
+                EXT_AUTH_TOKEN => {
+                    if len > MAX_AUTH_TOKEN_SIZE {
+                        return Err(VirgeError::ProtocolError(format!(
+                            "Auth token of {} bytes exceeds the {} byte limit",
+                            len, MAX_AUTH_TOKEN_SIZE
+                        )));
+                    }
+                    self.auth_token = Some(value.to_vec());
+                }
+                EXT_ENCRYPTION => {
+                    let [cipher] = value else {
+                        return Err(VirgeError::ProtocolError(format!(
+                            "Invalid encryption extension of {} bytes",
+                            len
+                        )));
+                    };
+                    self.encryption = *cipher;
+                }
+                _ => {}
+            }
+            block = rest;
+        }
+        Ok(())
+    }
+
+    /// 校验对端握手消息与本端是否兼容，返回双方协商后的参数
+    fn check(&self, peer: &Hello) -> Result<Negotiated> {
+        if self.version != peer.version {
+            return Err(VirgeError::ProtocolError(format!(
+                "Protocol version mismatch: local {}, peer {}",
+                self.version, peer.version
+            )));
+        }
+        if self.kind != peer.kind {
+            return Err(VirgeError::ProtocolError(format!(
+                "Transport kind mismatch: local {}, peer {}",
+                kind_name(self.kind),
+                kind_name(peer.kind)
+            )));
+        }
+        if self.ack != peer.ack {
+            return Err(VirgeError::ProtocolError(format!(
+                "ACK setting mismatch: local {}, peer {}",
+                self.ack, peer.ack
+            )));
+        }
+        if self.integrity != peer.integrity {
+            return Err(VirgeError::ProtocolError(format!(
+                "Integrity check setting mismatch: local {}, peer {}",
+                self.integrity, peer.integrity
+            )));
+        }
+        if self.compression != peer.compression {
+            return Err(VirgeError::ProtocolError(format!(
+                "Compression mismatch: local {}, peer {}",
+                compression_name(self.compression),
+                compression_name(peer.compression)
+            )));
+        }
+        if self.lanes != peer.lanes {
+            return Err(VirgeError::ProtocolError(format!(
+                "Priority lanes setting mismatch: local {}, peer {}",
+                self.lanes, peer.lanes
+            )));
+        }
+        if self.metadata != peer.metadata {
+            return Err(VirgeError::ProtocolError(format!(
+                "Metadata setting mismatch: local {}, peer {}",
+                self.metadata, peer.metadata
+            )));
+        }
+        if self.sequence != peer.sequence {
+            return Err(VirgeError::ProtocolError(format!(
+                "Sequence numbering setting mismatch: local {}, peer {}",
+                self.sequence, peer.sequence
+            )));
+        }
+        if self.encryption != peer.encryption {
+            return Err(VirgeError::EncryptionError(format!(
+                "Encryption setting mismatch: local {}, peer {}",
+                cipher_name(self.encryption),
+                cipher_name(peer.encryption)
+            )));
+        }
+        if self.chunk_size != peer.chunk_size && self.chunk_policy == ChunkSizePolicy::RequireEqual {
+            return Err(VirgeError::ConfigError(format!(
+                "Chunk size mismatch: local {}, peer {}",
+                self.chunk_size, peer.chunk_size
+            )));
+        }
+        let chunk_size = self.chunk_size.min(peer.chunk_size);
+        Ok(Negotiated {
+            chunk_size,
+            ack_window: self.ack_window.min(peer.ack_window).max(1),
+            peer_auth_token: peer.auth_token.clone(),
+        })
+    }
+}
+
+/// 握手协商的结果
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Negotiated {
+    pub(crate) chunk_size: u32,
+    pub(crate) ack_window: u32,
+    /// 对端附带的认证令牌
+    pub(crate) peer_auth_token: Option<Vec<u8>>,
+}
+
+/// 向扩展块追加一个条目，长度已由配置校验
+fn push_extension(block: &mut Vec<u8>, kind: u8, value: &[u8]) {
+    block.push(kind);
+    block.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    block.extend_from_slice(value);
+}
+
+/// 解析扩展块的总长度字段，超过上限时返回错误
+fn extensions_len(buf: [u8; EXTENSIONS_LEN_SIZE]) -> Result<usize> {
+    let len = u16::from_be_bytes(buf) as usize;
+    if len > MAX_EXTENSIONS_SIZE {
+        return Err(VirgeError::ProtocolError(format!(
+            "Handshake extensions of {} bytes exceed the {} byte limit",
+            len, MAX_EXTENSIONS_SIZE
+        )));
+    }
+    Ok(len)
+}
+
+/// 执行握手（阻塞 IO）：先发送本端消息再读取对端消息，客户端与服务器流程相同
+#[cfg(feature = "use-xtransport")]
+pub(crate) fn handshake_sync<S: std::io::Read + std::io::Write>(stream: &mut S, local: &Hello) -> Result<Negotiated> {
+    stream.write_all(&local.encode())?;
+    let mut buf = [0u8; HELLO_SIZE];
+    stream.read_exact(&mut buf)?;
+    let mut peer = Hello::decode(&buf)?;
+    if Hello::has_extensions(&buf) {
+        let mut len = [0u8; EXTENSIONS_LEN_SIZE];
+        stream.read_exact(&mut len)?;
+        let mut block = vec![0u8; extensions_len(len)?];
+        stream.read_exact(&mut block)?;
+        peer.apply_extensions(&block)?;
+    }
+    local.check(&peer)
+}
+
+/// SOCK_SEQPACKET 传输接收握手数据报所需的缓冲区大小，多出的一个字节用于发现超长的数据报
+#[cfg(feature = "use-raw")]
+pub(crate) const DATAGRAM_BUFFER_SIZE: usize = HELLO_SIZE + EXTENSIONS_LEN_SIZE + MAX_EXTENSIONS_SIZE + 1;
+
+/// 校验以单个数据报收到的对端握手消息，用于 SOCK_SEQPACKET 传输：本端消息由调用方以 `encode` 的结果发出
+#[cfg(feature = "use-raw")]
+pub(crate) fn check_datagram(local: &Hello, peer: &[u8]) -> Result<Negotiated> {
+    let invalid = || {
+        VirgeError::ProtocolError(format!(
+            "Invalid handshake datagram of {} bytes, peer is not a virga endpoint or has handshake disabled",
+            peer.len()
+        ))
+    };
+    let (buf, rest) = peer.split_first_chunk::<HELLO_SIZE>().ok_or_else(invalid)?;
+    let mut hello = Hello::decode(buf)?;
+    if Hello::has_extensions(buf) {
+        let (len, block) = rest.split_first_chunk::<EXTENSIONS_LEN_SIZE>().ok_or_else(invalid)?;
+        if block.len() != extensions_len(*len)? {
+            return Err(invalid());
+        }
+        hello.apply_extensions(block)?;
+    } else if !rest.is_empty() {
+        return Err(invalid());
+    }
+    local.check(&hello)
+}
+
+/// 执行握手（tokio 异步 IO），用于不使用 chunk_size 的传输协议
+#[cfg(feature = "tokio-runtime")]
+pub(crate) async fn handshake_async<S>(stream: &mut S, local: &Hello) -> Result<Negotiated>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    stream.write_all(&local.encode()).await?;
+    let mut buf = [0u8; HELLO_SIZE];
+    stream.read_exact(&mut buf).await?;
+    let mut peer = Hello::decode(&buf)?;
+    if Hello::has_extensions(&buf) {
+        let mut len = [0u8; EXTENSIONS_LEN_SIZE];
+        stream.read_exact(&mut len).await?;
+        let mut block = vec![0u8; extensions_len(len)?];
+        stream.read_exact(&mut block).await?;
+        peer.apply_extensions(&block)?;
+    }
+    local.check(&peer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Result<Hello> {
+        let buf: &[u8; HELLO_SIZE] = bytes[..HELLO_SIZE].try_into().unwrap();
+        let mut hello = Hello::decode(buf)?;
+        if Hello::has_extensions(buf) {
+            let len = extensions_len([bytes[HELLO_SIZE], bytes[HELLO_SIZE + 1]])?;
+            hello.apply_extensions(&bytes[HELLO_SIZE + EXTENSIONS_LEN_SIZE..][..len])?;
+        }
+        Ok(hello)
+    }
+
+    fn protocol_error(result: Result<Negotiated>) -> String {
+        match result {
+            Err(VirgeError::ProtocolError(message)) => message,
+            other => panic!("expected ProtocolError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn matching_hellos_negotiate() {
+        let local = Hello::new(KIND_XTRANSPORT, 4096, true).with_integrity(true).with_ack_window(8);
+        let peer = Hello::new(KIND_XTRANSPORT, 4096, true)
+            .with_integrity(true)
+            .with_ack_window(4)
+            .with_auth_token(Some(b"token".to_vec()));
+        let decoded = decode(&peer.encode()).unwrap();
+        assert_eq!(decoded.chunk_size, 4096);
+        let negotiated = local.check(&decoded).unwrap();
+        assert_eq!(
+            negotiated,
+            Negotiated { chunk_size: 4096, ack_window: 4, peer_auth_token: Some(b"token".to_vec()) }
+        );
+    }
+
+    #[test]
+    fn version_mismatch_names_both_versions() {
+        let local = Hello::without_chunk_size(KIND_RAW, false);
+        let mut bytes = local.encode();
+        bytes[4..6].copy_from_slice(&(PROTOCOL_VERSION + 1).to_be_bytes());
+        let message = protocol_error(local.check(&decode(&bytes).unwrap()));
+        assert!(message.contains(&format!("local {}", PROTOCOL_VERSION)), "{}", message);
+        assert!(message.contains(&format!("peer {}", PROTOCOL_VERSION + 1)), "{}", message);
+    }
+
+    #[test]
+    fn kind_and_flag_mismatches_are_rejected() {
+        let local = Hello::without_chunk_size(KIND_RAW, false);
+        let message = protocol_error(local.check(&Hello::without_chunk_size(KIND_YAMUX, false)));
+        assert!(message.contains("raw") && message.contains("yamux"), "{}", message);
+        protocol_error(local.check(&Hello::without_chunk_size(KIND_RAW, true)));
+        protocol_error(local.check(&Hello::without_chunk_size(KIND_RAW, false).with_sequence(true)));
+        let result = local.check(&Hello::without_chunk_size(KIND_RAW, false).with_encryption(1));
+        assert!(matches!(result, Err(VirgeError::EncryptionError(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn chunk_size_mismatch_follows_the_policy() {
+        let peer = Hello::new(KIND_XTRANSPORT, 1024, false);
+        let strict = Hello::new(KIND_XTRANSPORT, 4096, false);
+        assert!(matches!(strict.check(&peer), Err(VirgeError::ConfigError(_))));
+        let lenient = strict.with_chunk_policy(ChunkSizePolicy::UseMinimum);
+        assert_eq!(lenient.check(&peer).unwrap().chunk_size, 1024);
+    }
+
+    #[test]
+    fn garbage_is_not_a_hello() {
+        let garbage = *b"GET / HTTP/1.1\r\n";
+        let result = Hello::decode(&garbage);
+        assert!(matches!(result, Err(VirgeError::ProtocolError(ref m)) if m.contains("magic")), "{:?}", result);
+    }
+
+    #[test]
+    fn malformed_extensions_are_rejected() {
+        let hello = Hello::without_chunk_size(KIND_RAW, false).with_auth_token(Some(vec![7; 16]));
+        let bytes = hello.encode();
+        let block = &bytes[HELLO_SIZE + EXTENSIONS_LEN_SIZE..];
+        let mut peer = Hello::without_chunk_size(KIND_RAW, false);
+        assert!(matches!(peer.apply_extensions(&block[..block.len() - 1]), Err(VirgeError::ProtocolError(_))));
+        assert!(matches!(peer.apply_extensions(&block[..2]), Err(VirgeError::ProtocolError(_))));
+        assert!(matches!(extensions_len([0xFF, 0xFF]), Err(VirgeError::ProtocolError(_))));
+
+        // 不认识的条目类型被跳过
+        let mut unknown = Vec::new();
+        push_extension(&mut unknown, 0xEE, b"future");
+        push_extension(&mut unknown, EXT_AUTH_TOKEN, b"ok");
+        peer.apply_extensions(&unknown).unwrap();
+        assert_eq!(peer.auth_token.as_deref(), Some(&b"ok"[..]));
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    #[tokio::test]
+    async fn async_handshake_round_trips() {
+        let (mut a, mut b) = tokio::io::duplex(4096);
+        let local = Hello::without_chunk_size(KIND_TCP, true).with_ack_window(3);
+        let peer = Hello::without_chunk_size(KIND_TCP, true).with_ack_window(5);
+        let (left, right) = tokio::join!(handshake_async(&mut a, &local), handshake_async(&mut b, &peer));
+        assert_eq!(left.unwrap().ack_window, 3);
+        assert_eq!(right.unwrap().ack_window, 3);
+    }
+
+    /// 两端的 chunk_size 不同，同一策略下两端得到一致的结果
+    #[cfg(feature = "tokio-runtime")]
+    #[tokio::test]
+    async fn both_chunk_size_policies_agree_across_a_handshake() {
+        for policy in [ChunkSizePolicy::RequireEqual, ChunkSizePolicy::UseMinimum] {
+            let (mut a, mut b) = tokio::io::duplex(4096);
+            let client = Hello::new(KIND_XTRANSPORT, 8192, false).with_chunk_policy(policy);
+            let server = Hello::new(KIND_XTRANSPORT, 2048, false).with_chunk_policy(policy);
+            let (left, right) = tokio::join!(handshake_async(&mut a, &client), handshake_async(&mut b, &server));
+            match policy {
+                ChunkSizePolicy::RequireEqual => {
+                    assert!(matches!(left, Err(VirgeError::ConfigError(ref m)) if m.contains("8192")), "{:?}", left);
+                    assert!(matches!(right, Err(VirgeError::ConfigError(ref m)) if m.contains("2048")), "{:?}", right);
+                }
+                ChunkSizePolicy::UseMinimum => {
+                    assert_eq!(left.unwrap().chunk_size, 2048);
+                    assert_eq!(right.unwrap().chunk_size, 2048);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    #[tokio::test]
+    async fn async_handshake_rejects_garbage_and_version_mismatch() {
+        use tokio::io::AsyncWriteExt;
+
+        let local = Hello::without_chunk_size(KIND_TCP, false);
+        let (mut a, mut b) = tokio::io::duplex(4096);
+        b.write_all(&[0x5A; HELLO_SIZE]).await.unwrap();
+        let result = handshake_async(&mut a, &local).await;
+        assert!(matches!(result, Err(VirgeError::ProtocolError(_))), "{:?}", result);
+
+        let (mut a, mut b) = tokio::io::duplex(4096);
+        let mut newer = local.encode();
+        newer[4..6].copy_from_slice(&(PROTOCOL_VERSION + 1).to_be_bytes());
+        b.write_all(&newer).await.unwrap();
+        let message = protocol_error(handshake_async(&mut a, &local).await);
+        assert!(message.contains("version"), "{}", message);
+
+        // 对端在握手消息中途关闭连接
+        let (mut a, mut b) = tokio::io::duplex(4096);
+        b.write_all(&local.encode()[..HELLO_SIZE / 2]).await.unwrap();
+        drop(b);
+        assert!(handshake_async(&mut a, &local).await.is_err());
+    }
+}
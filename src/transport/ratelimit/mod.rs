@@ -0,0 +1,518 @@
+//! 限速模块
+//!
+//! 以包装器的形式叠加在统计包装器之下，用令牌桶限制每个连接收发用户数据的速率：
+//! 桶以配置的速率补充令牌，容量即允许的突发量。
+//!
+//! # 机制
+//! - 接收前令牌不足时推迟读取，未读取的数据留在内核缓冲区中，对端随之因发送缓冲区写满而减速
+//! - 发送前令牌不足时推迟写出，相当于本端主动让出带宽
+//! - 每次收发先等待令牌恢复为非负，再按实际字节数扣除（消息数限速按帧计，与 `Stats::messages_received` 一致），
+//!   因此单条消息可以超过突发量，超出的部分由之后的收发偿还
+//! - 等待由定时器唤醒，不会忙等；`try_recv`/`try_send` 不等待，令牌不足时返回 `Ok(None)`，
+//!   与暂无数据或发送缓冲区已满相同，拆分的读半部与共享句柄借此照常按各自的间隔轮询
+//! - 等待受读写超时约束：所需等待超过超时时等满超时后返回 `VirgeError::Timeout`，令牌留给下一次收发
+//!
+//! # 违规判定
+//! 接收限速对对端形成背压，对端的实际速率不会超过限额，超出的倍数无法在接收端直接观察。
+//! 因此以持续受限的时长判定违规：接收令牌桶自某次等待起一直没有恢复为满、且持续超过策略给出的时长，
+//! 说明对端在这段时间内始终以高于限额的速率发送。每段连续受限只报告一次。
+
+use crate::error::{Result, VirgeError};
+use crate::protocol::CLOSE_RATE_LIMITED;
+use crate::transport::bufpool::BufferPool;
+use crate::transport::lane::PriorityOutbox;
+use crate::transport::stats::StatsCounters;
+use crate::transport::{AckStats, HeaderMap, Transport, VsockAddr};
+use async_trait::async_trait;
+use log::*;
+use std::io::IoSlice;
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// 令牌桶参数
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Rate {
+    /// 每秒补充的令牌数
+    pub(crate) per_sec: u64,
+    /// 桶的容量
+    pub(crate) burst: u64,
+}
+
+/// 连接持续超出接收限速时的处理策略
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RateLimitPolicy {
+    /// 只限速，不做判定
+    #[default]
+    Throttle,
+    /// 连续受限超过 `after` 时投递 `ServerEvent::RateLimited`，连接继续限速
+    Report { after: Duration },
+    /// 连续受限超过 `after` 时投递 `ServerEvent::RateLimited`，随后以 `CLOSE_RATE_LIMITED` 关闭连接
+    Disconnect { after: Duration },
+}
+
+impl RateLimitPolicy {
+    fn after(&self) -> Option<Duration> {
+        match self {
+            Self::Throttle => None,
+            Self::Report { after } | Self::Disconnect { after } => Some(*after),
+        }
+    }
+}
+
+/// 一个连接的限速配置
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct RateLimits {
+    /// 接收字节数
+    pub(crate) recv_bytes: Option<Rate>,
+    /// 接收消息数
+    pub(crate) recv_messages: Option<Rate>,
+    /// 发送字节数
+    pub(crate) send_bytes: Option<Rate>,
+    pub(crate) policy: RateLimitPolicy,
+}
+
+impl RateLimits {
+    fn is_enabled(&self) -> bool {
+        self.recv_bytes.is_some() || self.recv_messages.is_some() || self.send_bytes.is_some()
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        let limits = [
+            ("rate_limit", self.recv_bytes),
+            ("message_rate_limit", self.recv_messages),
+            ("send_rate_limit", self.send_bytes),
+        ];
+        for (name, rate) in limits {
+            if rate.is_some_and(|rate| rate.per_sec == 0 || rate.burst == 0) {
+                return Err(VirgeError::ConfigError(format!("{} rate and burst must be greater than 0", name)));
+            }
+        }
+        if self.policy.after() == Some(Duration::ZERO) {
+            return Err(VirgeError::ConfigError("rate limit policy duration must be greater than 0".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// 对端持续超出接收限速时的回调，参数为已连续受限的时长
+pub(crate) type ViolationHook = Arc<dyn Fn(Duration) + Send + Sync>;
+
+/// 令牌桶，令牌数可以为负，表示尚待偿还的超额
+struct TokenBucket {
+    rate: Rate,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: Rate) -> Self {
+        Self { rate, tokens: rate.burst as f64, updated: Instant::now() }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate.per_sec as f64).min(self.rate.burst as f64);
+        self.updated = now;
+    }
+
+    /// 令牌恢复为非负之前需要等待的时间
+    fn wait(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        if self.tokens >= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-self.tokens / self.rate.per_sec as f64)
+    }
+
+    fn take(&mut self, amount: usize) {
+        self.tokens -= amount as f64;
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.rate.burst as f64
+    }
+}
+
+/// 等待 `wait` 后返回，等待期间在统计中标记为受限；`wait` 超过 `timeout` 时等满超时后返回 `Timeout`
+async fn pace(counters: &StatsCounters, wait: Duration, timeout: Option<Duration>, direction: &str) -> Result<()> {
+    if wait.is_zero() {
+        return Ok(());
+    }
+    match timeout {
+        Some(timeout) if timeout < wait => {
+            let _throttled = counters.throttle(timeout);
+            crate::transport::sleep(timeout).await;
+            Err(VirgeError::Timeout(format!("{} rate limit wait exceeded the timeout of {:?}", direction, timeout)))
+        }
+        _ => {
+            let _throttled = counters.throttle(wait);
+            crate::transport::sleep(wait).await;
+            Ok(())
+        }
+    }
+}
+
+fn rate_limited_error() -> VirgeError {
+    VirgeError::Disconnected("connection closed after the peer exceeded the rate limit".to_string())
+}
+
+/// 限速包装器
+pub(crate) struct RateLimitTransport {
+    inner: Box<dyn Transport>,
+    recv_bytes: Option<TokenBucket>,
+    recv_messages: Option<TokenBucket>,
+    send_bytes: Option<TokenBucket>,
+    policy: RateLimitPolicy,
+    counters: Arc<StatsCounters>,
+    on_violation: Option<ViolationHook>,
+    /// 接收令牌桶自该时刻起没有恢复为满，`None` 表示当前未受限
+    limited_since: Option<Instant>,
+    /// 本段连续受限已经报告
+    reported: bool,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+}
+
+impl RateLimitTransport {
+    /// 按配置叠加限速包装器，没有配置任何限速时原样返回
+    pub(crate) fn wrap(
+        inner: Box<dyn Transport>,
+        limits: &RateLimits,
+        counters: &Arc<StatsCounters>,
+        on_violation: Option<ViolationHook>,
+    ) -> Box<dyn Transport> {
+        if !limits.is_enabled() {
+            return inner;
+        }
+        Box::new(Self {
+            inner,
+            recv_bytes: limits.recv_bytes.map(TokenBucket::new),
+            recv_messages: limits.recv_messages.map(TokenBucket::new),
+            send_bytes: limits.send_bytes.map(TokenBucket::new),
+            policy: limits.policy,
+            counters: counters.clone(),
+            on_violation,
+            limited_since: None,
+            reported: false,
+            read_timeout: None,
+            write_timeout: None,
+        })
+    }
+
+    /// 接收前检查令牌，返回令牌恢复为非负前需要等待的时间；对端持续超限且策略要求断开时关闭连接并返回错误
+    async fn admit_recv(&mut self) -> Result<Duration> {
+        let now = Instant::now();
+        let buckets = [self.recv_bytes.as_mut(), self.recv_messages.as_mut()];
+        let mut wait = Duration::ZERO;
+        let mut full = true;
+        for bucket in buckets.into_iter().flatten() {
+            wait = wait.max(bucket.wait(now));
+            full &= bucket.is_full();
+        }
+        if full {
+            self.limited_since = None;
+            self.reported = false;
+        } else if !wait.is_zero() {
+            self.limited_since.get_or_insert(now);
+        }
+
+        if let Some(limited) = self.violation(now) {
+            warn!("Peer stayed above the receive rate limit for {:?}", limited);
+            if let Some(hook) = &self.on_violation {
+                hook(limited);
+            }
+            if matches!(self.policy, RateLimitPolicy::Disconnect { .. }) {
+                self.counters.mark_rate_limited();
+                if let Err(e) = self.inner.disconnect_with_reason(CLOSE_RATE_LIMITED, "Rate limit exceeded").await {
+                    debug!("Failed to notify the peer of the rate limit: {}", e);
+                }
+                return Err(rate_limited_error());
+            }
+        }
+        Ok(wait)
+    }
+
+    /// 接收前等待令牌
+    async fn before_recv(&mut self) -> Result<()> {
+        let wait = self.admit_recv().await?;
+        pace(&self.counters, wait, self.read_timeout, "receive").await
+    }
+
+    /// 本段连续受限达到策略给出的时长且尚未报告时返回已受限的时长
+    fn violation(&mut self, now: Instant) -> Option<Duration> {
+        let after = self.policy.after()?;
+        let limited = now.saturating_duration_since(self.limited_since?);
+        if self.reported || limited < after {
+            return None;
+        }
+        self.reported = true;
+        Some(limited)
+    }
+
+    /// 接收成功后扣除令牌，下层报告对端关闭的空帧不计入
+    fn received(&mut self, len: usize) {
+        if len == 0 {
+            return;
+        }
+        if let Some(bucket) = &mut self.recv_bytes {
+            bucket.take(len);
+        }
+        if let Some(bucket) = &mut self.recv_messages {
+            bucket.take(1);
+        }
+    }
+
+    /// 发送前等待令牌
+    async fn before_send(&mut self) -> Result<()> {
+        let Some(bucket) = &mut self.send_bytes else {
+            return Ok(());
+        };
+        let wait = bucket.wait(Instant::now());
+        pace(&self.counters, wait, self.write_timeout, "send").await
+    }
+
+    fn sent(&mut self, len: usize) {
+        if let Some(bucket) = &mut self.send_bytes {
+            bucket.take(len);
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for RateLimitTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.connect(cid, port, chunksize, isack).await
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.inner.from_tokio_stream(stream).await
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.inner.from_tcp_stream(stream).await
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.inner.from_uds_stream(stream).await
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        self.inner.from_seqpacket(socket).await
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.from_stream(stream, chunksize, isack).await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        self.inner.disconnect_with_reason(code, message).await
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        self.before_send().await?;
+        let len = data.len();
+        self.inner.send(data).await?;
+        self.sent(len);
+        Ok(())
+    }
+
+    async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        self.before_send().await?;
+        let len = data.len();
+        self.inner.send_noack(data).await?;
+        self.sent(len);
+        Ok(())
+    }
+
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        self.before_send().await?;
+        let sent = self.inner.send_slices(slices).await?;
+        self.sent(sent);
+        Ok(sent)
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        self.before_recv().await?;
+        let data = self.inner.recv().await?;
+        self.received(data.len());
+        Ok(data)
+    }
+
+    async fn recv_into(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        self.before_recv().await?;
+        let n = self.inner.recv_into(buf).await?;
+        self.received(n);
+        Ok(n)
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        if !self.admit_recv().await?.is_zero() {
+            return Ok(None);
+        }
+        let data = self.inner.try_recv().await?;
+        if let Some(data) = &data {
+            self.received(data.len());
+        }
+        Ok(data)
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        if self.send_bytes.as_mut().is_some_and(|bucket| !bucket.wait(Instant::now()).is_zero()) {
+            return Ok(None);
+        }
+        let sent = self.inner.try_send(data).await?;
+        if let Some(n) = sent {
+            self.sent(n);
+        }
+        Ok(sent)
+    }
+
+    async fn send_priority(&mut self, data: Vec<u8>) -> Result<()> {
+        self.before_send().await?;
+        let len = data.len();
+        self.inner.send_priority(data).await?;
+        self.sent(len);
+        Ok(())
+    }
+
+    async fn recv_priority(&mut self) -> Result<Vec<u8>> {
+        self.before_recv().await?;
+        let data = self.inner.recv_priority().await?;
+        self.received(data.len());
+        Ok(data)
+    }
+
+    async fn try_recv_priority(&mut self) -> Result<Option<Vec<u8>>> {
+        if !self.admit_recv().await?.is_zero() {
+            return Ok(None);
+        }
+        let data = self.inner.try_recv_priority().await?;
+        if let Some(data) = &data {
+            self.received(data.len());
+        }
+        Ok(data)
+    }
+
+    fn priority_outbox(&self) -> Option<PriorityOutbox> {
+        self.inner.priority_outbox()
+    }
+
+    async fn send_with_meta(&mut self, data: Vec<u8>, meta: &HeaderMap) -> Result<()> {
+        self.before_send().await?;
+        let len = data.len();
+        self.inner.send_with_meta(data, meta).await?;
+        self.sent(len);
+        Ok(())
+    }
+
+    async fn recv_with_meta(&mut self) -> Result<(Vec<u8>, HeaderMap)> {
+        self.before_recv().await?;
+        let (data, meta) = self.inner.recv_with_meta().await?;
+        self.received(data.len());
+        Ok((data, meta))
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        self.inner.shutdown_write().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        self.inner.probe(timeout).await
+    }
+
+    fn is_connected(&self) -> bool {
+        !self.counters.is_rate_limited() && self.inner.is_connected()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.inner.last_ack_latency()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.inner.ack_stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        self.inner.last_sent_seq()
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        self.inner.last_received_seq()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(timeout)?;
+        self.read_timeout = timeout;
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_write_timeout(timeout)?;
+        self.write_timeout = timeout;
+        Ok(())
+    }
+}
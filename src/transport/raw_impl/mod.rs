@@ -0,0 +1,52 @@
+//! Raw 传输协议实现
+//!
+//! 直接在 tokio-vsock 流上收发消息，仅添加 4 字节长度前缀，帧的收发由 `StreamTransport` 完成。
+
+use crate::error::Result;
+use crate::transport::stream_impl::{FramedStream, StreamTransport};
+use crate::transport::{connect_tokio_vsock, TransportKind};
+use async_trait::async_trait;
+use log::*;
+use tokio_vsock::{VsockAddr, VsockStream};
+
+#[async_trait]
+impl FramedStream for VsockStream {
+    /// 连接前绑定的本地端口，`None` 由内核分配临时端口
+    type Target = Option<u32>;
+
+    const NAME: &'static str = "Raw";
+
+    const KIND: TransportKind = TransportKind::Raw;
+
+    async fn open(local_port: &Option<u32>, cid: u32, port: u32) -> Result<Self> {
+        info!("Raw transport connecting to cid={}, port={}", cid, port);
+        connect_tokio_vsock(*local_port, cid, port).await
+    }
+
+    fn vsock_addr(&self) -> Option<VsockAddr> {
+        self.local_addr().ok()
+    }
+}
+
+/// Raw 传输协议实现
+///
+/// 直接管理 tokio-vsock 连接，消息以 4 字节大端长度前缀分隔。
+pub type RawTransport = StreamTransport<VsockStream>;
+
+impl StreamTransport<VsockStream> {
+    pub fn new() -> Self {
+        Self::with_target(None)
+    }
+
+    /// 设置连接前绑定的本地端口，由 `TransportOptions` 同步设置
+    pub(crate) fn with_local_port(mut self, port: Option<u32>) -> Self {
+        *self.target_mut() = port;
+        self
+    }
+}
+
+impl Default for StreamTransport<VsockStream> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
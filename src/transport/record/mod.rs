@@ -0,0 +1,702 @@
+//! 流量录制与回放模块
+//!
+//! 启用 `testing` 特性后可用，用于离线复现线上的分帧与数据损坏问题：
+//! - [`TrafficRecorder`] 把传输层实际收发的每一帧连同时间戳追加到一个紧凑的二进制日志文件
+//! - [`RecordingTransport`] 以包装器的形式叠加在任意传输协议之上，把收发交给录制器
+//! - [`TrafficLog`] 读取日志文件，[`ReplayTransport`] 把其中一条连接收到的帧按原顺序交给客户端或服务器，
+//!   见 `VirgeClient::replay` 与 `VirgeServer::replay`
+//!
+//! 通过配置的 `with_recorder` 注入时，录制位于最内层（故障注入之下），记录的帧包含 ACK、心跳等控制帧
+//! 及校验、压缩的开销，但不包含握手与字节流的长度前缀。同一录制器可被多条连接共享，
+//! 每个包装器分配一个从 0 开始的连接编号，服务器的每个连接各占一个编号，客户端的重连沿用同一编号。
+//! 写入日志失败只输出一次警告，不影响收发。
+//!
+//! # 日志格式
+//! ```text
+//! 文件头：
+//! ┌──────────────┬─────────────┬───────────────────────────┐
+//! │ magic: "VRGL"│ version: u8 │ started: u64 (BE, 微秒)   │
+//! └──────────────┴─────────────┴───────────────────────────┘
+//! 随后每条记录：
+//! ┌──────────┬─────────────────┬─────────────────────┬──────────────────┬──────────────┐
+//! │ kind: u8 │ conn: u32 (BE)  │ at: u64 (BE, 微秒)  │ length: u32 (BE) │ data: [u8]   │
+//! └──────────┴─────────────────┴─────────────────────┴──────────────────┴──────────────┘
+//! ```
+//!
+//! `started` 为录制开始时的 Unix 时间，`at` 为相对录制开始的时间。`kind` 为 1 时 `data` 是一个字节的
+//! 包装器组合（见 [`RecordedLayers`]），2 表示连接建立，3/4 为发送/收到的帧，5 表示本端断开。
+
+use crate::error::{Result, VirgeError};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::{AckStats, Transport, TransportOptions, VsockAddr};
+use async_trait::async_trait;
+use log::*;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, IoSlice, Write};
+use std::os::unix::io::RawFd;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// 日志文件的魔数
+const MAGIC: [u8; 4] = *b"VRGL";
+/// 日志格式版本
+const VERSION: u8 = 1;
+/// 文件头字节数
+const FILE_HEADER_SIZE: usize = 13;
+/// 记录头字节数
+const RECORD_HEADER_SIZE: usize = 17;
+
+const KIND_OPEN: u8 = 1;
+const KIND_CONNECT: u8 = 2;
+const KIND_SEND: u8 = 3;
+const KIND_RECV: u8 = 4;
+const KIND_DISCONNECT: u8 = 5;
+
+const LAYER_ACK: u8 = 1 << 0;
+const LAYER_INTEGRITY: u8 = 1 << 1;
+const LAYER_COMPRESSION: u8 = 1 << 2;
+const LAYER_KEEPALIVE: u8 = 1 << 3;
+const LAYER_LANES: u8 = 1 << 4;
+const LAYER_SEQUENCE: u8 = 1 << 5;
+const LAYER_METADATA: u8 = 1 << 6;
+const LAYER_ENCRYPTION: u8 = 1 << 7;
+
+/// 录制时叠加在传输协议之上的包装器，决定了每一帧由哪些帧头组成
+///
+/// 从内到外依次为加密、完整性校验、压缩、心跳、确认、优先通道、序号与元数据，
+/// 与 `protocol` 模块中各 `Layer` 的嵌套顺序一致。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecordedLayers {
+    pub encryption: bool,
+    pub integrity: bool,
+    pub compression: bool,
+    pub keepalive: bool,
+    pub ack: bool,
+    pub lanes: bool,
+    pub sequence: bool,
+    pub metadata: bool,
+}
+
+impl RecordedLayers {
+    /// 按配置与实际叠加的 ACK 设置推导包装器组合
+    pub(crate) fn new(options: &TransportOptions, ack: bool) -> Self {
+        Self {
+            encryption: options.uses_encryption(),
+            integrity: options.integrity,
+            compression: options.compression_byte() != 0,
+            keepalive: options.keepalive.is_some(),
+            ack,
+            lanes: options.lanes,
+            sequence: options.sequence,
+            metadata: options.metadata,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        [
+            (self.ack, LAYER_ACK),
+            (self.integrity, LAYER_INTEGRITY),
+            (self.compression, LAYER_COMPRESSION),
+            (self.keepalive, LAYER_KEEPALIVE),
+            (self.lanes, LAYER_LANES),
+            (self.sequence, LAYER_SEQUENCE),
+            (self.metadata, LAYER_METADATA),
+            (self.encryption, LAYER_ENCRYPTION),
+        ]
+        .into_iter()
+        .filter(|&(enabled, _)| enabled)
+        .fold(0, |byte, (_, bit)| byte | bit)
+    }
+
+    fn from_byte(byte: u8) -> Self {
+        Self {
+            encryption: byte & LAYER_ENCRYPTION != 0,
+            integrity: byte & LAYER_INTEGRITY != 0,
+            compression: byte & LAYER_COMPRESSION != 0,
+            keepalive: byte & LAYER_KEEPALIVE != 0,
+            ack: byte & LAYER_ACK != 0,
+            lanes: byte & LAYER_LANES != 0,
+            sequence: byte & LAYER_SEQUENCE != 0,
+            metadata: byte & LAYER_METADATA != 0,
+        }
+    }
+}
+
+/// 录制器的输出与运行期状态
+#[derive(Debug)]
+struct RecorderState {
+    out: BufWriter<File>,
+    started: Instant,
+    /// 下一个包装器的连接编号
+    next_conn: u32,
+    /// 是否已经写入失败，失败后只警告一次
+    failed: bool,
+}
+
+/// 流量录制器，克隆共享同一个日志文件
+#[derive(Clone, Debug)]
+pub struct TrafficRecorder {
+    state: Arc<Mutex<RecorderState>>,
+}
+
+/// 同一个录制器及其克隆相等
+impl PartialEq for TrafficRecorder {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl TrafficRecorder {
+    /// 创建（或截断）`path` 处的日志文件并写入文件头
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        out.write_all(&MAGIC)?;
+        out.write_all(&[VERSION])?;
+        out.write_all(&(started.as_micros() as u64).to_be_bytes())?;
+        out.flush()?;
+        Ok(Self {
+            state: Arc::new(Mutex::new(RecorderState {
+                out,
+                started: Instant::now(),
+                next_conn: 0,
+                failed: false,
+            })),
+        })
+    }
+
+    /// 已分配的连接编号数
+    pub fn connections(&self) -> u32 {
+        self.lock().next_conn
+    }
+
+    /// 为新的包装器分配连接编号并记录其包装器组合
+    fn open(&self, layers: RecordedLayers) -> u32 {
+        let conn = {
+            let mut state = self.lock();
+            let conn = state.next_conn;
+            state.next_conn += 1;
+            conn
+        };
+        self.write(conn, KIND_OPEN, &[&[layers.to_byte()]]);
+        conn
+    }
+
+    /// 追加一条记录，`data` 的各段依次拼接为记录的数据；每条记录写完即刷新，进程异常退出时日志仍然完整
+    fn write(&self, conn: u32, kind: u8, data: &[&[u8]]) {
+        let mut guard = self.lock();
+        let state = &mut *guard;
+        if state.failed {
+            return;
+        }
+        let at = state.started.elapsed().as_micros() as u64;
+        let len: usize = data.iter().map(|part| part.len()).sum();
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        header[0] = kind;
+        header[1..5].copy_from_slice(&conn.to_be_bytes());
+        header[5..13].copy_from_slice(&at.to_be_bytes());
+        header[13..].copy_from_slice(&(len as u32).to_be_bytes());
+        if let Err(e) = append(&mut state.out, &header, data) {
+            warn!("Recorder: failed to write traffic log, recording stopped: {}", e);
+            state.failed = true;
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RecorderState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 日志中的一条记录
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// 连接编号
+    pub conn: u32,
+    /// 相对录制开始的时间
+    pub at: Duration,
+    pub event: RecordedEvent,
+}
+
+/// 记录的事件
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordedEvent {
+    /// 创建包装器，携带该连接的包装器组合
+    Open(RecordedLayers),
+    /// 连接建立（含服务器从已接受的流初始化）
+    Connect,
+    /// 向下层发送的帧
+    Send(Vec<u8>),
+    /// 从下层收到的帧
+    Recv(Vec<u8>),
+    /// 本端断开连接
+    Disconnect,
+}
+
+/// 读取到内存中的流量日志
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrafficLog {
+    /// 录制开始的时间
+    pub started: SystemTime,
+    /// 按写入顺序排列的记录
+    pub records: Vec<Record>,
+}
+
+impl TrafficLog {
+    /// 读取 `path` 处的日志文件
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::decode(&std::fs::read(path)?)
+    }
+
+    /// 解析日志文件的内容
+    ///
+    /// 魔数、版本或记录类型不符时返回 `VirgeError::ProtocolError`；末尾不完整的记录
+    /// （通常是录制进程在写入途中退出）被忽略。
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < FILE_HEADER_SIZE || buf[..4] != MAGIC {
+            return Err(VirgeError::ProtocolError("Not a virga traffic log".to_string()));
+        }
+        if buf[4] != VERSION {
+            return Err(VirgeError::ProtocolError(format!("Unsupported traffic log version {}", buf[4])));
+        }
+        let started = UNIX_EPOCH + Duration::from_micros(read_u64(&buf[5..]));
+        let mut records = Vec::new();
+        let mut rest = &buf[FILE_HEADER_SIZE..];
+        while rest.len() >= RECORD_HEADER_SIZE {
+            let len = read_u32(&rest[13..]) as usize;
+            let Some(data) = rest[RECORD_HEADER_SIZE..].get(..len) else {
+                warn!("Traffic log: ignoring truncated record at the end of the log");
+                break;
+            };
+            let event = match rest[0] {
+                KIND_OPEN => RecordedEvent::Open(RecordedLayers::from_byte(data.first().copied().unwrap_or(0))),
+                KIND_CONNECT => RecordedEvent::Connect,
+                KIND_SEND => RecordedEvent::Send(data.to_vec()),
+                KIND_RECV => RecordedEvent::Recv(data.to_vec()),
+                KIND_DISCONNECT => RecordedEvent::Disconnect,
+                other => {
+                    return Err(VirgeError::ProtocolError(format!("Unknown traffic log record kind {}", other)));
+                }
+            };
+            records.push(Record {
+                conn: read_u32(&rest[1..]),
+                at: Duration::from_micros(read_u64(&rest[5..])),
+                event,
+            });
+            rest = &rest[RECORD_HEADER_SIZE + len..];
+        }
+        Ok(Self { started, records })
+    }
+
+    /// 日志中出现的连接编号，按首次出现的顺序排列
+    pub fn connections(&self) -> Vec<u32> {
+        let mut conns = Vec::new();
+        for record in &self.records {
+            if !conns.contains(&record.conn) {
+                conns.push(record.conn);
+            }
+        }
+        conns
+    }
+
+    /// 连接 `conn` 录制时的包装器组合，日志中没有该连接的 `Open` 记录时返回 `None`
+    pub fn layers(&self, conn: u32) -> Option<RecordedLayers> {
+        self.records.iter().find_map(|record| match record.event {
+            RecordedEvent::Open(layers) if record.conn == conn => Some(layers),
+            _ => None,
+        })
+    }
+}
+
+fn append(out: &mut impl Write, header: &[u8], data: &[&[u8]]) -> io::Result<()> {
+    out.write_all(header)?;
+    for part in data {
+        out.write_all(part)?;
+    }
+    out.flush()
+}
+
+fn read_u32(buf: &[u8]) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&buf[..4]);
+    u32::from_be_bytes(bytes)
+}
+
+fn read_u64(buf: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buf[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+/// 流量录制包装器
+pub struct RecordingTransport {
+    inner: Box<dyn Transport>,
+    recorder: TrafficRecorder,
+    conn: u32,
+}
+
+impl RecordingTransport {
+    /// 把 `inner` 收发的每一帧记录到 `recorder`，包装器组合记为空
+    pub fn new(inner: impl Transport + 'static, recorder: &TrafficRecorder) -> Self {
+        Self::boxed(Box::new(inner), recorder, RecordedLayers::default())
+    }
+
+    pub(crate) fn boxed(inner: Box<dyn Transport>, recorder: &TrafficRecorder, layers: RecordedLayers) -> Self {
+        let conn = recorder.open(layers);
+        Self { inner, recorder: recorder.clone(), conn }
+    }
+
+    /// 本包装器的连接编号
+    pub fn connection(&self) -> u32 {
+        self.conn
+    }
+
+    /// 操作成功时追加一条记录
+    fn record<T>(&self, kind: u8, data: &[&[u8]], result: Result<T>) -> Result<T> {
+        if result.is_ok() {
+            self.recorder.write(self.conn, kind, data);
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl Transport for RecordingTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        let result = self.inner.connect(cid, port, chunksize, isack).await;
+        self.record(KIND_CONNECT, &[], result)
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        let result = self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await;
+        self.record(KIND_CONNECT, &[], result)
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        let result = self.inner.from_tokio_stream(stream).await;
+        self.record(KIND_CONNECT, &[], result)
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        let result = self.inner.from_tcp_stream(stream).await;
+        self.record(KIND_CONNECT, &[], result)
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        let result = self.inner.from_uds_stream(stream).await;
+        self.record(KIND_CONNECT, &[], result)
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        let result = self.inner.from_seqpacket(socket).await;
+        self.record(KIND_CONNECT, &[], result)
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        let result = self.inner.from_stream(stream, chunksize, isack).await;
+        self.record(KIND_CONNECT, &[], result)
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.recorder.write(self.conn, KIND_DISCONNECT, &[]);
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        self.recorder.write(self.conn, KIND_DISCONNECT, &[]);
+        self.inner.disconnect_with_reason(code, message).await
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        // 发送会消耗数据，先复制一份供成功后记录
+        let frame = data.clone();
+        let result = self.inner.send(data).await;
+        self.record(KIND_SEND, &[&frame], result)
+    }
+
+    async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        let frame = data.clone();
+        let result = self.inner.send_noack(data).await;
+        self.record(KIND_SEND, &[&frame], result)
+    }
+
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        let result = self.inner.send_slices(slices).await;
+        let parts: Vec<&[u8]> = slices.iter().map(|slice| &slice[..]).collect();
+        self.record(KIND_SEND, &parts, result)
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        let data = self.inner.recv().await?;
+        self.recorder.write(self.conn, KIND_RECV, &[&data]);
+        Ok(data)
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        let data = self.inner.try_recv().await?;
+        if let Some(data) = &data {
+            self.recorder.write(self.conn, KIND_RECV, &[data]);
+        }
+        Ok(data)
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        let sent = self.inner.try_send(data).await?;
+        if sent.is_some() {
+            self.recorder.write(self.conn, KIND_SEND, &[data]);
+        }
+        Ok(sent)
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        self.inner.shutdown_write().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        self.inner.probe(timeout).await
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.inner.last_ack_latency()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.inner.ack_stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        self.inner.last_sent_seq()
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        self.inner.last_received_seq()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
+
+/// 回放一条录制连接的传输
+///
+/// 代替真实的传输协议位于最内层，其上按配置叠加与录制时相同的包装器：
+/// - `recv`/`try_recv` 按录制顺序立即交付当时收到的帧，不等待对应的发送，也不重现原来的时间间隔；
+///   帧用尽后返回 `ErrorKind::UnexpectedEof` 的 IO 错误，与对端关闭连接一致
+/// - 发送的帧与录制时的发送逐条比对，出现差异时输出警告，发送本身总是成功
+/// - 连接与断开只改变连接状态，可反复连接，录制中的重连在回放中是连续的
+pub struct ReplayTransport {
+    layers: RecordedLayers,
+    /// 尚未交付的收到的帧
+    inbound: VecDeque<Vec<u8>>,
+    /// 尚未比对的发送的帧
+    outbound: VecDeque<Vec<u8>>,
+    /// 已发送的帧数
+    sent: u64,
+    connected: bool,
+}
+
+impl ReplayTransport {
+    /// 读取 `path` 处的日志，回放其中的第一条连接
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let log = TrafficLog::open(path)?;
+        let Some(&conn) = log.connections().first() else {
+            return Err(VirgeError::ConfigError("Traffic log contains no connections".to_string()));
+        };
+        Self::from_log(&log, conn)
+    }
+
+    /// 回放 `log` 中编号为 `conn` 的连接，日志中没有该连接时返回 `VirgeError::ConfigError`
+    pub fn from_log(log: &TrafficLog, conn: u32) -> Result<Self> {
+        let Some(layers) = log.layers(conn) else {
+            return Err(VirgeError::ConfigError(format!("Traffic log has no connection {}", conn)));
+        };
+        let mut inbound = VecDeque::new();
+        let mut outbound = VecDeque::new();
+        for record in log.records.iter().filter(|record| record.conn == conn) {
+            match &record.event {
+                RecordedEvent::Recv(data) => inbound.push_back(data.clone()),
+                RecordedEvent::Send(data) => outbound.push_back(data.clone()),
+                _ => {}
+            }
+        }
+        Ok(Self { layers, inbound, outbound, sent: 0, connected: false })
+    }
+
+    /// 录制时的包装器组合
+    pub fn layers(&self) -> RecordedLayers {
+        self.layers
+    }
+
+    /// 尚未交付的收到的帧数
+    pub fn remaining(&self) -> usize {
+        self.inbound.len()
+    }
+
+    /// 按配置叠加包装器，配置须与录制时的包装器组合一致，加密的连接无法回放
+    pub(crate) fn wrap(self, options: &TransportOptions) -> Result<Box<dyn Transport>> {
+        if self.layers.encryption {
+            return Err(VirgeError::ConfigError("Encrypted traffic cannot be replayed".to_string()));
+        }
+        // ACK 取决于录制时的传输协议（xtransport 自行确认），以日志为准
+        let ack = self.layers.ack;
+        let configured = RecordedLayers::new(options, ack);
+        if configured != self.layers {
+            return Err(VirgeError::ConfigError(format!(
+                "Replay config {:?} does not match the recorded layers {:?}",
+                configured, self.layers
+            )));
+        }
+        Ok(options.wrap(Box::new(self), ack))
+    }
+
+    fn not_connected() -> VirgeError {
+        VirgeError::Disconnected("Replay transport not connected or already disconnected".to_string())
+    }
+
+    fn check_connected(&self) -> Result<()> {
+        if !self.connected {
+            return Err(Self::not_connected());
+        }
+        Ok(())
+    }
+
+    /// 与录制时的下一个发送帧比对
+    fn compare(&mut self, data: &[u8]) -> Result<()> {
+        self.check_connected()?;
+        let index = self.sent;
+        self.sent += 1;
+        match self.outbound.pop_front() {
+            Some(expected) if expected == data => {}
+            Some(expected) => warn!(
+                "Replay: sent frame {} ({} bytes) differs from the recording ({} bytes)",
+                index,
+                data.len(),
+                expected.len()
+            ),
+            None => warn!("Replay: sent frame {} ({} bytes) is beyond the recording", index, data.len()),
+        }
+        Ok(())
+    }
+
+    fn next_inbound(&mut self) -> Result<Vec<u8>> {
+        self.check_connected()?;
+        self.inbound.pop_front().ok_or_else(|| {
+            VirgeError::IoError(io::Error::new(io::ErrorKind::UnexpectedEof, "Replay log exhausted"))
+        })
+    }
+}
+
+#[async_trait]
+impl Transport for ReplayTransport {
+    async fn connect(&mut self, _: u32, _: u32, _: u32, _: bool) -> Result<()> {
+        self.connected = true;
+        Ok(())
+    }
+
+    async fn connect_timeout(&mut self, _: u32, _: u32, _: u32, _: bool, _: Duration) -> Result<()> {
+        self.connected = true;
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.connected = false;
+        Ok(())
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        self.compare(&data)
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        self.next_inbound()
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        self.next_inbound().map(Some)
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        self.compare(data)?;
+        Ok(Some(data.len()))
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.connected && !self.inbound.is_empty()
+    }
+
+    fn set_read_timeout(&mut self, _: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, _: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+}
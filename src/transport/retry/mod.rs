@@ -0,0 +1,286 @@
+//! 发送重试模块
+//!
+//! 以包装器的形式叠加在传输协议之上，发送遇到暂时性错误时短暂等待后重发同一帧，
+//! 尝试次数或累计等待时间用尽后才将错误交给调用方，错误信息附带尝试次数。
+//!
+//! 暂时性错误指内核暂时无法接收数据：来宾内存紧张时 vsock 发送可能返回 `EAGAIN`、`ENOBUFS` 或 `ENOMEM`，
+//! 或调用被信号中断（`EINTR`）。连接重置等其他错误立即返回，不会重试。
+//! 只有带底层 IO 错误的 `VirgeError` 才能被识别，超时（`VirgeError::Timeout`）同样不会重试。
+//!
+//! 重试时重新发送整条帧，`try_send` 保持非阻塞语义，不参与重试。
+
+use crate::error::{Result, VirgeError};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::{AckStats, Transport, VsockAddr};
+use async_trait::async_trait;
+use log::*;
+use std::io::{self, IoSlice};
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 第一次重试前的等待时间，之后每次翻倍
+const INITIAL_DELAY: Duration = Duration::from_millis(1);
+
+/// 发送重试策略
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct SendRetry {
+    /// 包括第一次发送在内最多尝试的次数
+    pub(crate) max_attempts: u32,
+    /// 各次重试之间累计等待时间的上限
+    pub(crate) max_total_delay: Duration,
+}
+
+/// 错误是否由暂时性的内核资源不足引起，是则返回其底层 IO 错误
+fn transient(err: &VirgeError) -> Option<&io::Error> {
+    let source = match err {
+        VirgeError::IoError(e) => e,
+        VirgeError::ConnectionError { source: Some(e), .. } | VirgeError::TransportError { source: Some(e), .. } => e,
+        _ => return None,
+    };
+    let retryable = matches!(source.raw_os_error(), Some(libc::EAGAIN | libc::ENOBUFS | libc::ENOMEM))
+        || source.kind() == io::ErrorKind::Interrupted;
+    retryable.then_some(source)
+}
+
+/// 重试用尽后的错误：保留原始 IO 错误作为 `source`，信息中附带尝试次数
+fn exhausted(err: VirgeError, attempts: u32) -> VirgeError {
+    let source = match err {
+        VirgeError::IoError(e)
+        | VirgeError::ConnectionError { source: Some(e), .. }
+        | VirgeError::TransportError { source: Some(e), .. } => e,
+        other => return other,
+    };
+    VirgeError::TransportError {
+        message: format!("Send failed after {} attempts", attempts),
+        source: Some(source),
+    }
+}
+
+/// 一条帧的发送进度
+struct Attempts {
+    /// 已尝试的次数
+    count: u32,
+    /// 已累计等待的时间
+    waited: Duration,
+    /// 下一次重试前的等待时间
+    delay: Duration,
+}
+
+impl Attempts {
+    fn new() -> Self {
+        Self { count: 0, waited: Duration::ZERO, delay: INITIAL_DELAY }
+    }
+
+    /// 处理一次失败的发送：可以重试时等待后返回 `Ok(())`，否则返回交给调用方的错误
+    async fn retry(&mut self, policy: &SendRetry, err: VirgeError) -> Result<()> {
+        self.count += 1;
+        let Some(source) = transient(&err) else {
+            return Err(err);
+        };
+        if self.count >= policy.max_attempts || self.waited >= policy.max_total_delay {
+            warn!("Send failed after {} attempts: {}", self.count, source);
+            return Err(exhausted(err, self.count));
+        }
+        let pause = self.delay.min(policy.max_total_delay - self.waited);
+        debug!(
+            "Send failed with transient error {}, retry {}/{} in {:?}",
+            source,
+            self.count,
+            policy.max_attempts - 1,
+            pause
+        );
+        crate::transport::sleep(pause).await;
+        self.waited += pause;
+        self.delay = self.delay.saturating_mul(2);
+        Ok(())
+    }
+}
+
+/// 发送重试包装器
+pub(crate) struct RetryTransport {
+    inner: Box<dyn Transport>,
+    policy: SendRetry,
+}
+
+impl RetryTransport {
+    pub(crate) fn new(inner: Box<dyn Transport>, policy: SendRetry) -> Self {
+        Self { inner, policy }
+    }
+}
+
+#[async_trait]
+impl Transport for RetryTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.connect(cid, port, chunksize, isack).await
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.inner.from_tokio_stream(stream).await
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.inner.from_tcp_stream(stream).await
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.inner.from_uds_stream(stream).await
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        self.inner.from_seqpacket(socket).await
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.from_stream(stream, chunksize, isack).await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        self.inner.disconnect_with_reason(code, message).await
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        let mut attempts = Attempts::new();
+        loop {
+            match self.inner.send(data.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(e) => attempts.retry(&self.policy, e).await?,
+            }
+        }
+    }
+
+    async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        let mut attempts = Attempts::new();
+        loop {
+            match self.inner.send_noack(data.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(e) => attempts.retry(&self.policy, e).await?,
+            }
+        }
+    }
+
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        let mut attempts = Attempts::new();
+        loop {
+            match self.inner.send_slices(slices).await {
+                Ok(sent) => return Ok(sent),
+                Err(e) => attempts.retry(&self.policy, e).await?,
+            }
+        }
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        self.inner.recv().await
+    }
+
+    async fn recv_into(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        self.inner.recv_into(buf).await
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        self.inner.try_recv().await
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        self.inner.try_send(data).await
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        self.inner.shutdown_write().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        self.inner.probe(timeout).await
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.inner.last_ack_latency()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.inner.ack_stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        self.inner.last_sent_seq()
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        self.inner.last_received_seq()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
@@ -0,0 +1,456 @@
+//! 加密模块
+//!
+//! 以包装器的形式叠加在传输协议之上，为 vsock 连接提供纵深防御：连接建立后双方交换临时 X25519 公钥，
+//! 结合双方配置的预共享密钥导出两个方向的 ChaCha20-Poly1305 密钥，此后每条帧都被加密并认证。
+//! 预共享密钥同时用于认证对端，密钥不一致时密钥交换以 `VirgeError::EncryptionError` 失败并关闭连接。
+//!
+//! 由 `encryption` 特性启用，通过 `with_encryption_key` 为每个连接配置预共享密钥。
+//! 两端是否启用加密在握手中协商，一端加密另一端不加密时连接建立即失败，不会交换任何数据。
+//! 客户端（`connect`）发起密钥交换，服务器（`from_*`）响应，每次重新连接都使用新的临时密钥。
+//!
+//! 无法通过认证或计数器重复、倒退的帧被丢弃并返回 `VirgeError::EncryptionError`，连接保持可用。
+//! 传输层自身的控制帧（半关闭、关闭原因）与 yamux 的虚拟流不经过本包装器，不被加密。
+//!
+//! # 帧格式
+//! ```text
+//! ┌────────────────┬──────────────────────┬──────────────┐
+//! │ counter: u64   │ ciphertext: [u8]     │ tag: [u8;16] │
+//! └────────────────┴──────────────────────┴──────────────┘
+//! ```
+//! 密钥交换消息与密钥导出方式见 `protocol` 模块。
+
+use crate::error::{Result, VirgeError};
+use crate::protocol::{ENCRYPTION_HEADER_SIZE, ENCRYPTION_TAG_SIZE};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::{AckStats, Transport, VsockAddr};
+use async_trait::async_trait;
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, Tag};
+use hkdf::Hkdf;
+use log::*;
+use sha2::Sha256;
+use std::io::IoSlice;
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+use std::time::Duration;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+/// 每条加密帧相对明文增加的字节数
+pub(crate) const OVERHEAD: usize = ENCRYPTION_HEADER_SIZE + ENCRYPTION_TAG_SIZE;
+
+/// 预共享密钥与 X25519 公钥的字节数
+const KEY_SIZE: usize = 32;
+
+/// HKDF 导出密钥时 info 的前缀
+const HKDF_INFO: &[u8] = b"virga secure v1";
+
+/// 配置中携带的预共享密钥，调试输出不显示其内容
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct PresharedKey(pub(crate) [u8; KEY_SIZE]);
+
+impl std::fmt::Debug for PresharedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PresharedKey(..)")
+    }
+}
+
+/// 计数器对应的 nonce：4 个零字节加大端计数器
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    *Nonce::from_slice(&nonce)
+}
+
+/// 格式不符的密钥交换消息
+fn malformed(len: usize) -> VirgeError {
+    VirgeError::EncryptionError(format!("Malformed key exchange message of {} bytes", len))
+}
+
+/// 一次密钥交换导出的密钥
+struct Schedule {
+    client_to_server: ChaCha20Poly1305,
+    server_to_client: ChaCha20Poly1305,
+    /// `pub_c | pub_s`，作为确认标签的附加数据
+    transcript: [u8; 2 * KEY_SIZE],
+}
+
+impl Schedule {
+    /// 由本端临时私钥与对端公钥 `peer`（`client`、`server` 之一）导出两个方向的密钥
+    fn derive(
+        psk: &PresharedKey,
+        secret: EphemeralSecret,
+        peer: &PublicKey,
+        client: &PublicKey,
+        server: &PublicKey,
+    ) -> Result<Self> {
+        let shared = secret.diffie_hellman(peer);
+        if !shared.was_contributory() {
+            return Err(VirgeError::EncryptionError("Peer sent a low-order public key".to_string()));
+        }
+        let mut transcript = [0u8; 2 * KEY_SIZE];
+        transcript[..KEY_SIZE].copy_from_slice(client.as_bytes());
+        transcript[KEY_SIZE..].copy_from_slice(server.as_bytes());
+        let mut info = Vec::with_capacity(HKDF_INFO.len() + transcript.len());
+        info.extend_from_slice(HKDF_INFO);
+        info.extend_from_slice(&transcript);
+        let mut okm = [0u8; 2 * KEY_SIZE];
+        Hkdf::<Sha256>::new(Some(&psk.0[..]), shared.as_bytes())
+            .expand(&info, &mut okm)
+            .map_err(|_| VirgeError::EncryptionError("Key derivation failed".to_string()))?;
+        Ok(Self {
+            client_to_server: ChaCha20Poly1305::new(Key::from_slice(&okm[..KEY_SIZE])),
+            server_to_client: ChaCha20Poly1305::new(Key::from_slice(&okm[KEY_SIZE..])),
+            transcript,
+        })
+    }
+
+    /// 本方向的确认标签：以全零 nonce、握手记录为附加数据加密空消息
+    fn confirmation(&self, cipher: &ChaCha20Poly1305) -> Result<Tag> {
+        cipher
+            .encrypt_in_place_detached(&nonce(0), &self.transcript, &mut [])
+            .map_err(|_| VirgeError::EncryptionError("Key confirmation failed".to_string()))
+    }
+
+    /// 校验对端发来的确认标签，比较以常数时间进行
+    fn verify(&self, cipher: &ChaCha20Poly1305, received: &[u8]) -> Result<()> {
+        if received.len() != ENCRYPTION_TAG_SIZE {
+            return Err(malformed(received.len()));
+        }
+        cipher
+            .decrypt_in_place_detached(&nonce(0), &self.transcript, &mut [], Tag::from_slice(received))
+            .map_err(|_| {
+                VirgeError::EncryptionError(
+                    "Key confirmation mismatch, the peer uses a different pre-shared key".to_string(),
+                )
+            })
+    }
+}
+
+/// 已建立的加密会话
+struct Session {
+    send: ChaCha20Poly1305,
+    recv: ChaCha20Poly1305,
+    /// 最近一条发出的帧的计数器
+    sent: u64,
+    /// 最近一条收到的帧的计数器
+    received: u64,
+}
+
+impl Session {
+    /// 加密按顺序拼接的各部分，为其分配下一个计数器
+    fn seal(&mut self, parts: &[&[u8]]) -> Result<Vec<u8>> {
+        let counter = self.sent + 1;
+        let len = parts.iter().map(|part| part.len()).sum::<usize>();
+        let mut frame = Vec::with_capacity(OVERHEAD + len);
+        frame.extend_from_slice(&counter.to_be_bytes());
+        for part in parts {
+            frame.extend_from_slice(part);
+        }
+        let tag = self
+            .send
+            .encrypt_in_place_detached(&nonce(counter), &[], &mut frame[ENCRYPTION_HEADER_SIZE..])
+            .map_err(|_| VirgeError::EncryptionError(format!("Failed to encrypt a frame of {} bytes", len)))?;
+        frame.extend_from_slice(&tag);
+        self.sent = counter;
+        Ok(frame)
+    }
+
+    /// 校验计数器与认证标签并解密
+    fn open(&mut self, mut frame: Vec<u8>) -> Result<Vec<u8>> {
+        if frame.len() < OVERHEAD {
+            return Err(VirgeError::EncryptionError(format!(
+                "Frame of {} bytes is shorter than the encryption overhead",
+                frame.len()
+            )));
+        }
+        let mut header = [0u8; ENCRYPTION_HEADER_SIZE];
+        header.copy_from_slice(&frame[..ENCRYPTION_HEADER_SIZE]);
+        let counter = u64::from_be_bytes(header);
+        if counter <= self.received {
+            return Err(VirgeError::EncryptionError(format!(
+                "Replayed frame counter {}, last received {}",
+                counter, self.received
+            )));
+        }
+        let split = frame.len() - ENCRYPTION_TAG_SIZE;
+        let tag = *Tag::from_slice(&frame[split..]);
+        frame.truncate(split);
+        self.recv
+            .decrypt_in_place_detached(&nonce(counter), &[], &mut frame[ENCRYPTION_HEADER_SIZE..], &tag)
+            .map_err(|_| VirgeError::EncryptionError(format!("Frame {} failed authentication", counter)))?;
+        self.received = counter;
+        frame.drain(..ENCRYPTION_HEADER_SIZE);
+        Ok(frame)
+    }
+}
+
+/// 加密包装器
+pub(crate) struct SecureTransport {
+    inner: Box<dyn Transport>,
+    psk: PresharedKey,
+    /// 当前连接的加密会话，密钥交换完成前为 `None`
+    session: Option<Session>,
+}
+
+impl SecureTransport {
+    pub(crate) fn new(inner: Box<dyn Transport>, psk: PresharedKey) -> Self {
+        Self { inner, psk, session: None }
+    }
+
+    /// 作为客户端发起密钥交换
+    async fn initiate(&mut self) -> Result<Session> {
+        let secret = EphemeralSecret::random();
+        let client = PublicKey::from(&secret);
+        self.inner.send(client.as_bytes().to_vec()).await?;
+        let reply = self.inner.recv().await?;
+        let Some((server, confirm)) = reply.split_first_chunk::<KEY_SIZE>() else {
+            return Err(malformed(reply.len()));
+        };
+        let server = PublicKey::from(*server);
+        let schedule = Schedule::derive(&self.psk, secret, &server, &client, &server)?;
+        // 先发出本端的确认再校验对端的确认，密钥不一致时双方都能发现
+        let tag = schedule.confirmation(&schedule.client_to_server)?;
+        self.inner.send(tag.to_vec()).await?;
+        schedule.verify(&schedule.server_to_client, confirm)?;
+        Ok(Session {
+            send: schedule.client_to_server,
+            recv: schedule.server_to_client,
+            sent: 0,
+            received: 0,
+        })
+    }
+
+    /// 作为服务器响应密钥交换
+    async fn respond(&mut self) -> Result<Session> {
+        let offer = self.inner.recv().await?;
+        let client: [u8; KEY_SIZE] = offer.as_slice().try_into().map_err(|_| malformed(offer.len()))?;
+        let client = PublicKey::from(client);
+        let secret = EphemeralSecret::random();
+        let server = PublicKey::from(&secret);
+        let schedule = Schedule::derive(&self.psk, secret, &client, &client, &server)?;
+        let tag = schedule.confirmation(&schedule.server_to_client)?;
+        let mut reply = Vec::with_capacity(KEY_SIZE + ENCRYPTION_TAG_SIZE);
+        reply.extend_from_slice(server.as_bytes());
+        reply.extend_from_slice(&tag);
+        self.inner.send(reply).await?;
+        let confirm = self.inner.recv().await?;
+        schedule.verify(&schedule.client_to_server, &confirm)?;
+        Ok(Session {
+            send: schedule.server_to_client,
+            recv: schedule.client_to_server,
+            sent: 0,
+            received: 0,
+        })
+    }
+
+    /// 下层连接建立后进行密钥交换，失败时关闭下层连接
+    async fn establish(&mut self, initiator: bool) -> Result<()> {
+        let result = if initiator { self.initiate().await } else { self.respond().await };
+        match result {
+            Ok(session) => {
+                self.session = Some(session);
+                Ok(())
+            }
+            Err(e) => {
+                warn!("Encryption handshake failed: {}", e);
+                let _ = self.inner.disconnect().await;
+                Err(e)
+            }
+        }
+    }
+
+    fn session(&mut self) -> Result<&mut Session> {
+        self.session
+            .as_mut()
+            .ok_or_else(|| VirgeError::Disconnected("Encrypted session is not established".to_string()))
+    }
+}
+
+#[async_trait]
+impl Transport for SecureTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        self.session = None;
+        self.inner.connect(cid, port, chunksize, isack).await?;
+        self.establish(true).await
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        self.session = None;
+        self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await?;
+        self.establish(true).await
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.session = None;
+        self.inner.from_tokio_stream(stream).await?;
+        self.establish(false).await
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.session = None;
+        self.inner.from_tcp_stream(stream).await?;
+        self.establish(false).await
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.session = None;
+        self.inner.from_uds_stream(stream).await?;
+        self.establish(false).await
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        self.session = None;
+        self.inner.from_seqpacket(socket).await?;
+        self.establish(false).await
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        self.session = None;
+        self.inner.from_stream(stream, chunksize, isack).await?;
+        self.establish(false).await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.session = None;
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        self.session = None;
+        self.inner.disconnect_with_reason(code, message).await
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        let frame = self.session()?.seal(&[&data])?;
+        self.inner.send(frame).await
+    }
+
+    async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        let frame = self.session()?.seal(&[&data])?;
+        self.inner.send_noack(frame).await
+    }
+
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        let parts: Vec<&[u8]> = slices.iter().map(|slice| &**slice).collect();
+        let frame = self.session()?.seal(&parts)?;
+        let sent = self.inner.send_slices(&[IoSlice::new(&frame)]).await?;
+        Ok(sent - OVERHEAD)
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        let frame = self.inner.recv().await?;
+        self.session()?.open(frame)
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.inner.try_recv().await? {
+            Some(frame) => self.session()?.open(frame).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        let frame = self.session()?.seal(&[data])?;
+        match self.inner.try_send(&frame).await {
+            // 没有写出任何数据，计数器留给下一条帧
+            Ok(None) => {
+                if let Some(session) = self.session.as_mut() {
+                    session.sent -= 1;
+                }
+                Ok(None)
+            }
+            result => result.map(|sent| sent.map(|_| data.len())),
+        }
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        self.inner.shutdown_write().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        self.inner.probe(timeout).await
+    }
+
+    fn is_connected(&self) -> bool {
+        self.session.is_some() && self.inner.is_connected()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.inner.last_ack_latency()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.inner.ack_stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        self.inner.last_sent_seq()
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        self.inner.last_received_seq()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
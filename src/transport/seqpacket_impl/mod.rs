@@ -0,0 +1,466 @@
+//! SOCK_SEQPACKET 传输协议实现
+//!
+//! 在 virtio-vsock 的 SOCK_SEQPACKET 套接字上收发消息，由内核保留消息边界，不使用长度前缀。
+//!
+//! # 特点
+//! - 不超过 `SEQPACKET_FRAGMENT_SIZE` 的消息以单个数据报发送，更大的消息拆分为多个数据报依次发送
+//! - `recv` 每次恰好返回一条完整消息，超时或非阻塞接收时已收到的分片保留到下一次调用继续组装
+//! - 分片累计超过 `max_message_size` 时返回 `MessageTooLarge`，该消息的剩余分片被丢弃，连接保持可用
+//! - 内核（Linux 5.14 之前）或 vsock 传输不支持 SOCK_SEQPACKET 时，连接与监听返回 `ConfigError`
+//!
+//! # 数据报格式
+//! ```text
+//! ┌──────────────┬──────────────────┐
+//! │ flag: u8     │ payload: [u8]    │
+//! └──────────────┴──────────────────┘
+//! ```
+//! 标志字节的取值见 `protocol` 模块。
+
+use crate::error::{Result, VirgeError};
+use crate::protocol::{
+    SEQPACKET_CLOSE, SEQPACKET_CLOSE_REASON, SEQPACKET_EOF, SEQPACKET_FRAGMENT_SIZE, SEQPACKET_LAST, SEQPACKET_MORE,
+};
+use crate::transport::{check_timeout, framing, preamble, sys, vsock_connect_error, Transport, TransportKind, VsockAddr};
+use async_trait::async_trait;
+use log::*;
+use std::io::IoSlice;
+use std::os::unix::io::{AsRawFd, OwnedFd, RawFd};
+use std::time::Duration;
+use tokio::io::unix::AsyncFd;
+
+/// SOCK_SEQPACKET 传输协议实现
+///
+/// 直接管理 SOCK_SEQPACKET vsock 套接字，握手与控制帧之外的每个数据报携带一个分片标志字节。
+pub struct SeqpacketTransport {
+    socket: Option<AsyncFd<OwnedFd>>,
+    /// 已收到但尚未组成完整消息的分片
+    partial: Vec<u8>,
+    /// 接收单个数据报的缓冲区
+    datagram: Vec<u8>,
+    /// 正在丢弃一条超限消息的剩余分片
+    discarding: bool,
+    /// 已收到对端的正常关闭通知
+    closed: bool,
+    /// 对端关闭通知携带的原因码与说明
+    reason: Option<(u32, String)>,
+    max_message_size: usize,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    /// 是否在收发数据前执行握手
+    handshake: bool,
+    /// 握手中声明的各层设置（各层本身由外层包装器完成），由 `TransportOptions::hello` 生成
+    hello: preamble::Hello,
+    /// 最近一次握手中对端附带的认证令牌
+    peer_auth_token: Option<Vec<u8>>,
+    /// 最近一次握手协商出的 ACK 窗口，未执行握手时为 `None`
+    negotiated_window: Option<u32>,
+    /// 连接前绑定的本地端口，`None` 由内核分配临时端口
+    local_port: Option<u32>,
+}
+
+impl SeqpacketTransport {
+    pub fn new() -> Self {
+        Self {
+            socket: None,
+            partial: Vec::new(),
+            datagram: vec![0u8; 1 + SEQPACKET_FRAGMENT_SIZE],
+            discarding: false,
+            closed: false,
+            reason: None,
+            max_message_size: crate::DEFAULT_MAX_MESSAGE_SIZE,
+            read_timeout: None,
+            write_timeout: None,
+            handshake: true,
+            // 线路上与流式 raw 传输使用相同的协议类型：两种套接字类型之间本就无法建立连接
+            hello: preamble::Hello::without_chunk_size(TransportKind::Raw.to_byte(), false),
+            peer_auth_token: None,
+            negotiated_window: None,
+            local_port: None,
+        }
+    }
+
+    /// 设置是否执行连接握手
+    pub fn with_handshake(mut self, enabled: bool) -> Self {
+        self.handshake = enabled;
+        self
+    }
+
+    /// 设置握手中声明的各层设置，由 `TransportOptions` 按叠加的包装器生成
+    pub(crate) fn with_hello(mut self, hello: preamble::Hello) -> Self {
+        self.hello = hello;
+        self
+    }
+
+    /// 设置单条消息的最大字节数，超限的发送在本地失败，超限的接收返回 `MessageTooLarge` 且连接保持可用
+    pub fn with_max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = size;
+        self
+    }
+
+    /// 设置连接前绑定的本地端口，由 `TransportOptions` 同步设置
+    pub(crate) fn with_local_port(mut self, port: Option<u32>) -> Self {
+        self.local_port = port;
+        self
+    }
+
+    /// 按配置执行握手：双方各发送一个握手数据报，再接收并校验对端的握手数据报
+    async fn handshake(&mut self, socket: &AsyncFd<OwnedFd>) -> Result<()> {
+        self.negotiated_window = None;
+        self.peer_auth_token = None;
+        if !self.handshake {
+            return Ok(());
+        }
+        let hello = &self.hello;
+        send_datagram(socket, &[IoSlice::new(&hello.encode())]).await?;
+        let mut buf = [0u8; preamble::DATAGRAM_BUFFER_SIZE];
+        let n = recv_datagram(socket, &mut buf).await?;
+        if n == 0 {
+            return Err(unexpected_eof());
+        }
+        let negotiated = preamble::check_datagram(&hello, &buf[..n.min(buf.len())])?;
+        self.negotiated_window = Some(negotiated.ack_window);
+        self.peer_auth_token = negotiated.peer_auth_token;
+        Ok(())
+    }
+
+    /// 处理收到的一个数据报，组成完整消息时返回该消息
+    fn on_datagram(&mut self, len: usize) -> Result<Option<Vec<u8>>> {
+        if len == 0 {
+            return Err(unexpected_eof());
+        }
+        if len > self.datagram.len() {
+            return Err(VirgeError::ProtocolError(format!(
+                "Seqpacket datagram of {} bytes exceeds the fragment limit {}",
+                len - 1,
+                SEQPACKET_FRAGMENT_SIZE
+            )));
+        }
+        let (flag, payload) = (self.datagram[0], &self.datagram[1..len]);
+        match flag {
+            SEQPACKET_CLOSE => {
+                self.partial.clear();
+                self.closed = true;
+                Err(VirgeError::EndOfStream)
+            }
+            SEQPACKET_CLOSE_REASON => {
+                let Some((code, message)) = payload.split_first_chunk::<4>() else {
+                    return Err(VirgeError::ProtocolError(format!(
+                        "Seqpacket close datagram of {} bytes is missing its reason code",
+                        payload.len()
+                    )));
+                };
+                let message = String::from_utf8_lossy(message).into_owned();
+                self.partial.clear();
+                self.closed = true;
+                self.reason = Some((u32::from_be_bytes(*code), message));
+                Err(framing::closed_error(&self.reason))
+            }
+            SEQPACKET_EOF => Err(VirgeError::EndOfStream),
+            SEQPACKET_MORE | SEQPACKET_LAST => {
+                let last = flag == SEQPACKET_LAST;
+                if self.discarding {
+                    self.discarding = !last;
+                    return Ok(None);
+                }
+                let size = self.partial.len() + payload.len();
+                if size > self.max_message_size {
+                    self.partial.clear();
+                    self.discarding = !last;
+                    return Err(VirgeError::MessageTooLarge { size, max: self.max_message_size });
+                }
+                self.partial.extend_from_slice(payload);
+                Ok(last.then(|| std::mem::take(&mut self.partial)))
+            }
+            flag => Err(VirgeError::ProtocolError(format!("Invalid seqpacket fragment flag {}", flag))),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.socket = None;
+        self.partial.clear();
+        self.discarding = false;
+        self.closed = false;
+        self.reason = None;
+    }
+
+    fn not_connected() -> VirgeError {
+        VirgeError::Disconnected("Seqpacket transport not connected".to_string())
+    }
+}
+
+impl Default for SeqpacketTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 建立 SOCK_SEQPACKET vsock 连接，设置 `local_port` 时先将套接字绑定到该本地端口
+///
+/// 内核或 vsock 传输不支持 SOCK_SEQPACKET 时返回 `ConfigError`，而不是退回到流式套接字。
+async fn connect_seqpacket(local_port: Option<u32>, cid: u32, port: u32) -> Result<AsyncFd<OwnedFd>> {
+    let connect = async {
+        let fd = sys::vsock_socket(libc::SOCK_SEQPACKET)?;
+        if let Some(local) = local_port {
+            sys::bind_vsock_addr(fd.as_raw_fd(), libc::VMADDR_CID_ANY, local)?;
+        }
+        let fd = AsyncFd::new(fd)?;
+        if !sys::start_connect(fd.as_raw_fd(), cid, port)? {
+            drop(fd.writable().await?);
+            sys::finish_connect(fd.as_raw_fd())?;
+        }
+        Ok::<_, std::io::Error>(fd)
+    };
+    connect.await.map_err(|e| {
+        if sys::seqpacket_unsupported(&e) {
+            unsupported_error()
+        } else {
+            vsock_connect_error(local_port, e)
+        }
+    })
+}
+
+/// 内核或 vsock 传输不支持 SOCK_SEQPACKET 时返回的错误
+pub(crate) fn unsupported_error() -> VirgeError {
+    VirgeError::ConfigError(
+        "SOCK_SEQPACKET vsock is not supported by this kernel or vsock transport \
+         (requires Linux 5.14+ with virtio-vsock), disable seqpacket"
+            .to_string(),
+    )
+}
+
+/// 等待套接字可写并发送一个数据报
+async fn send_datagram(socket: &AsyncFd<OwnedFd>, slices: &[IoSlice<'_>]) -> std::io::Result<()> {
+    loop {
+        let mut guard = socket.writable().await?;
+        if let Ok(result) = guard.try_io(|fd| sys::send_datagram(fd.as_raw_fd(), slices)) {
+            return result.map(drop);
+        }
+    }
+}
+
+/// 等待套接字可读并接收一个数据报，返回数据报的完整字节数
+async fn recv_datagram(socket: &AsyncFd<OwnedFd>, buf: &mut [u8]) -> std::io::Result<usize> {
+    loop {
+        let mut guard = socket.readable().await?;
+        if let Ok(result) = guard.try_io(|fd| sys::recv_datagram(fd.as_raw_fd(), buf)) {
+            return result;
+        }
+    }
+}
+
+/// 依次发送一条消息的全部分片，空消息发送一个不带负载的最后一片
+async fn send_fragments(socket: &AsyncFd<OwnedFd>, data: &[u8]) -> std::io::Result<()> {
+    let mut fragments = data.chunks(SEQPACKET_FRAGMENT_SIZE).peekable();
+    if fragments.peek().is_none() {
+        return send_datagram(socket, &[IoSlice::new(&[SEQPACKET_LAST])]).await;
+    }
+    while let Some(fragment) = fragments.next() {
+        let flag = if fragments.peek().is_some() { SEQPACKET_MORE } else { SEQPACKET_LAST };
+        send_datagram(socket, &[IoSlice::new(&[flag]), IoSlice::new(fragment)]).await?;
+    }
+    Ok(())
+}
+
+/// 在可选的超时时间内执行 IO 操作，超时返回 `ErrorKind::TimedOut`，转换为 `VirgeError::Timeout`
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    fut: impl Future<Output = std::io::Result<T>>,
+) -> std::io::Result<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, fut).await.unwrap_or_else(|_| {
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Seqpacket transport operation timed out"))
+        }),
+        None => fut.await,
+    }
+}
+
+fn unexpected_eof() -> VirgeError {
+    VirgeError::IoError(std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        "Seqpacket transport peer closed the connection",
+    ))
+}
+
+#[async_trait]
+impl Transport for SeqpacketTransport {
+    async fn connect(&mut self, cid: u32, port: u32, _: u32, _: bool) -> Result<()> {
+        info!("Seqpacket transport connecting to cid={}, port={}", cid, port);
+        self.reset();
+
+        let socket = connect_seqpacket(self.local_port, cid, port).await?;
+        self.handshake(&socket).await?;
+
+        self.socket = Some(socket);
+        info!("Seqpacket transport connected successfully");
+        Ok(())
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        // 超时后 connect future 被 drop，未完成的 vsock 连接随之取消
+        let result = tokio::time::timeout(timeout, self.connect(cid, port, chunksize, isack)).await;
+        match result {
+            Ok(result) => result,
+            Err(_) => {
+                self.reset();
+                Err(VirgeError::Timeout("Seqpacket transport connect timed out".to_string()))
+            }
+        }
+    }
+
+    async fn from_seqpacket(&mut self, socket: OwnedFd) -> Result<()> {
+        self.reset();
+        let socket = AsyncFd::new(socket)?;
+        self.handshake(&socket).await?;
+
+        self.socket = Some(socket);
+        info!("Seqpacket transport initialized from socket successfully");
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.disconnect_with_reason(0, "").await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        info!("Seqpacket transport disconnecting");
+        if let Some(socket) = self.socket.take() {
+            // 原因码为 0 且没有说明时发送不带负载的普通关闭通知
+            let message = framing::truncate_reason(message);
+            let plain = code == 0 && message.is_empty();
+            let flag = [if plain { SEQPACKET_CLOSE } else { SEQPACKET_CLOSE_REASON }];
+            let code = code.to_be_bytes();
+            let slices = [IoSlice::new(&flag), IoSlice::new(&code), IoSlice::new(message.as_bytes())];
+            let close = if plain { &slices[..1] } else { &slices[..] };
+            // 不等待发送缓冲区腾出空间：对端停止接收时放弃关闭通知，对端随后将其视为异常断开
+            match sys::send_datagram(socket.as_raw_fd(), close) {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    debug!("Seqpacket transport send buffer full, skipping close frame")
+                }
+                Err(e) => debug!("Seqpacket transport failed to send close frame: {}", e),
+            }
+        }
+        self.reset();
+        info!("Seqpacket transport disconnected");
+        Ok(())
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        framing::check_size(data.len(), self.max_message_size)?;
+        let timeout = self.write_timeout;
+        let socket = self.socket.as_ref().ok_or_else(Self::not_connected)?;
+
+        with_timeout(timeout, send_fragments(socket, &data)).await?;
+
+        info!("Seqpacket transport sent {} bytes", data.len());
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        let timeout = self.read_timeout;
+        loop {
+            if self.closed {
+                return Err(framing::closed_error(&self.reason));
+            }
+            let socket = self.socket.as_ref().ok_or_else(Self::not_connected)?;
+            // 超时时已收到的分片保留在 partial 中，下一次 recv 继续组装
+            let n = with_timeout(timeout, recv_datagram(socket, &mut self.datagram)).await?;
+            if let Some(message) = self.on_datagram(n)? {
+                info!("Seqpacket transport received {} bytes", message.len());
+                return Ok(message);
+            }
+        }
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            if self.closed {
+                return Err(framing::closed_error(&self.reason));
+            }
+            let socket = self.socket.as_ref().ok_or_else(Self::not_connected)?;
+            let n = match sys::recv_datagram(socket.as_raw_fd(), &mut self.datagram) {
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            if let Some(message) = self.on_datagram(n)? {
+                info!("Seqpacket transport received {} bytes", message.len());
+                return Ok(Some(message));
+            }
+        }
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        framing::check_size(data.len(), self.max_message_size)?;
+        let timeout = self.write_timeout;
+        let socket = self.socket.as_ref().ok_or_else(Self::not_connected)?;
+
+        // 第一个分片无法发送时直接返回；一旦发出了第一个分片，则等待其余分片发完以保证消息完整
+        let split = data.len().min(SEQPACKET_FRAGMENT_SIZE);
+        let (first, rest) = data.split_at(split);
+        let flag = if rest.is_empty() { SEQPACKET_LAST } else { SEQPACKET_MORE };
+        match sys::send_datagram(socket.as_raw_fd(), &[IoSlice::new(&[flag]), IoSlice::new(first)]) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        if !rest.is_empty() {
+            with_timeout(timeout, send_fragments(socket, rest)).await?;
+        }
+
+        info!("Seqpacket transport sent {} bytes", data.len());
+        Ok(Some(data.len()))
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        let timeout = self.write_timeout;
+        let socket = self.socket.as_ref().ok_or_else(Self::not_connected)?;
+        with_timeout(timeout, send_datagram(socket, &[IoSlice::new(&[SEQPACKET_EOF])])).await?;
+        info!("Seqpacket transport shut down its write side");
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        // 数据报在 send 返回时已整体交给内核，没有需要刷新的用户态缓冲
+        self.socket.as_ref().ok_or_else(Self::not_connected)?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.socket.is_some() && !self.closed
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.socket.as_ref().map(|socket| socket.as_raw_fd())
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        let socket = self.socket.as_ref()?;
+        let (cid, port) = sys::local_vsock_addr(socket.as_raw_fd()).ok()?;
+        Some(VsockAddr::new(cid, port))
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.negotiated_window
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.peer_auth_token.as_deref()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        !self.partial.is_empty()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        check_timeout(timeout)?;
+        self.read_timeout = timeout;
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        check_timeout(timeout)?;
+        self.write_timeout = timeout;
+        Ok(())
+    }
+}
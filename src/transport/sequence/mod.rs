@@ -0,0 +1,302 @@
+//! 消息序号模块
+//!
+//! 以包装器的形式叠加在优先通道之上，为每条普通消息附加一个 u64 序号，
+//! 重连后应用可比较双方记录的序号，判断断开前最后发出的消息是否已被对端收到。
+//! 两端需同时启用，设置在握手中协商。
+//!
+//! # 机制
+//! - 发送方从 1 开始为每条消息分配严格递增的序号，分配后发送失败的序号不再复用，
+//!   因此接收方可能看到序号跳跃，但不会看到重复或倒退
+//! - 接收方要求序号大于上一条消息的序号，重复或倒退的序号（通常意味着分帧错误或重放）
+//!   返回 `VirgeError::ProtocolError`，该消息被丢弃，连接保持可用
+//! - 发送序号在同一传输实例的重连之间延续，接收序号随每次新建立的连接重新开始
+//! - 优先消息不经过本包装器，不携带序号
+//!
+//! # 帧格式
+//! ```text
+//! ┌────────────────┬──────────────────┐
+//! │ seq: u64       │ payload: [u8]    │
+//! └────────────────┴──────────────────┘
+//! ```
+
+use crate::error::{Result, VirgeError};
+use crate::protocol::{FrameHeader, Layer};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::lane::PriorityOutbox;
+use crate::transport::{AckStats, Transport, VsockAddr};
+use async_trait::async_trait;
+use log::*;
+use std::io::IoSlice;
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 序号帧头字节数
+pub(crate) const HEADER_SIZE: usize = crate::protocol::SEQUENCE_HEADER_SIZE;
+
+/// 消息序号包装器
+pub(crate) struct SequenceTransport {
+    inner: Box<dyn Transport>,
+    /// 下一条消息的序号
+    next: u64,
+    /// 最近一条发送成功的消息的序号
+    sent: u64,
+    /// 最近收到的消息的序号
+    received: u64,
+}
+
+impl SequenceTransport {
+    pub(crate) fn new(inner: Box<dyn Transport>) -> Self {
+        Self { inner, next: 1, sent: 0, received: 0 }
+    }
+
+    /// 为下一条消息分配序号
+    fn assign(&mut self) -> u64 {
+        let seq = self.next;
+        self.next += 1;
+        seq
+    }
+
+    fn frame(seq: u64, data: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(HEADER_SIZE + data.len());
+        FrameHeader::Sequence { seq }.encode_into(&mut frame);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    /// 记录发送结果，发送成功时更新最近发出的序号
+    fn record<T>(&mut self, seq: u64, result: Result<T>) -> Result<T> {
+        if result.is_ok() {
+            self.sent = seq;
+        }
+        result
+    }
+
+    /// 校验并去掉帧头的序号
+    fn open(&mut self, mut frame: Vec<u8>) -> Result<Vec<u8>> {
+        // 空帧来自下层（如对端关闭），原样交给调用方处理
+        if frame.is_empty() {
+            return Ok(frame);
+        }
+        let (FrameHeader::Sequence { seq }, header_len) = FrameHeader::decode(Layer::Sequence, &frame)? else {
+            return Err(VirgeError::ProtocolError("Invalid sequence header".to_string()));
+        };
+        if seq <= self.received {
+            let kind = if seq == self.received { "Duplicate" } else { "Out-of-order" };
+            return Err(VirgeError::ProtocolError(format!(
+                "{} message sequence {}, last received {}",
+                kind, seq, self.received
+            )));
+        }
+        if seq > self.received + 1 {
+            debug!("Sequence: skipped from {} to {}", self.received, seq);
+        }
+        self.received = seq;
+        frame.drain(..header_len);
+        Ok(frame)
+    }
+
+    /// 新连接建立前：接收序号重新开始
+    fn reset(&mut self) {
+        self.received = 0;
+    }
+}
+
+#[async_trait]
+impl Transport for SequenceTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        self.reset();
+        self.inner.connect(cid, port, chunksize, isack).await
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        self.reset();
+        self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.reset();
+        self.inner.from_tokio_stream(stream).await
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.reset();
+        self.inner.from_tcp_stream(stream).await
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.reset();
+        self.inner.from_uds_stream(stream).await
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        self.reset();
+        self.inner.from_seqpacket(socket).await
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        self.reset();
+        self.inner.from_stream(stream, chunksize, isack).await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        self.inner.disconnect_with_reason(code, message).await
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        let seq = self.assign();
+        let result = self.inner.send(Self::frame(seq, &data)).await;
+        self.record(seq, result)
+    }
+
+    async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        let seq = self.assign();
+        let result = self.inner.send_noack(Self::frame(seq, &data)).await;
+        self.record(seq, result)
+    }
+
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        let seq = self.assign();
+        let header = FrameHeader::Sequence { seq }.to_bytes();
+        let mut framed = Vec::with_capacity(slices.len() + 1);
+        framed.push(IoSlice::new(&header));
+        framed.extend(slices.iter().map(|slice| IoSlice::new(slice)));
+        let result = self.inner.send_slices(&framed).await;
+        self.record(seq, result).map(|sent| sent - header.len())
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        let frame = self.inner.recv().await?;
+        self.open(frame)
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.inner.try_recv().await? {
+            Some(frame) => self.open(frame).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        let seq = self.assign();
+        match self.inner.try_send(&Self::frame(seq, data)).await {
+            // 没有写出任何数据，序号留给下一条消息
+            Ok(None) => {
+                self.next = seq;
+                Ok(None)
+            }
+            result => self.record(seq, result).map(|sent| sent.map(|_| data.len())),
+        }
+    }
+
+    async fn send_priority(&mut self, data: Vec<u8>) -> Result<()> {
+        self.inner.send_priority(data).await
+    }
+
+    async fn recv_priority(&mut self) -> Result<Vec<u8>> {
+        self.inner.recv_priority().await
+    }
+
+    async fn try_recv_priority(&mut self) -> Result<Option<Vec<u8>>> {
+        self.inner.try_recv_priority().await
+    }
+
+    fn priority_outbox(&self) -> Option<PriorityOutbox> {
+        self.inner.priority_outbox()
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        self.inner.shutdown_write().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        self.inner.probe(timeout).await
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.inner.last_ack_latency()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.inner.ack_stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        Some(self.sent)
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        Some(self.received)
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
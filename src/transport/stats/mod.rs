@@ -0,0 +1,483 @@
+//! 连接统计模块
+//!
+//! 以包装器的形式叠加在传输协议之上，统计每个连接收发的用户数据：
+//! 字节数与消息数按成功的 send/recv 累加，使用原子计数器，不引入锁。
+//! 心跳帧等协议内部数据不计入统计。
+//!
+//! 服务器的空闲回收任务通过计数器读取最近活动时间，并以 `mark_idle` 标记被关闭的连接，
+//! 此后包装器上的收发操作均返回 `VirgeError::Timeout`。
+//!
+//! 限速包装器在等待令牌期间通过计数器标记连接受限，并累计受限时长。
+//!
+//! 最内层传输的接收缓冲区池在包装器创建时登记到计数器，快照中的命中与未命中次数直接读取自该池。
+
+use crate::error::{Result, VirgeError};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::lane::PriorityOutbox;
+use crate::transport::{AckStats, HeaderMap, Transport, VsockAddr};
+use async_trait::async_trait;
+use std::io::IoSlice;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::os::unix::io::RawFd;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 连接统计快照
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Stats {
+    /// 已发送的用户数据字节数
+    pub bytes_sent: u64,
+    /// 已接收的用户数据字节数
+    pub bytes_received: u64,
+    /// 已发送的消息数
+    pub messages_sent: u64,
+    /// 已接收的消息数
+    pub messages_received: u64,
+    /// 最近一次建立连接的时间，尚未连接时为 `None`
+    pub connect_time: Option<SystemTime>,
+    /// 最近一次成功收发数据的时间
+    pub last_activity: Option<SystemTime>,
+    /// 连接建立在 SOCK_SEQPACKET 套接字上（见 `with_seqpacket`），为 `false` 时使用流式套接字
+    pub seqpacket: bool,
+    /// 收发当前正因限速（见 `with_rate_limit`）等待令牌
+    pub throttled: bool,
+    /// 因限速等待的累计时长
+    pub throttled_time: Duration,
+    /// 读取复用了接收缓冲区池中空闲缓冲区的次数，见 `with_buffer_pool_size`
+    pub buffer_pool_hits: u64,
+    /// 接收缓冲区池为空、为读取新分配缓冲区的次数；未启用缓冲区池或传输不经由池读取时两者均为 0
+    pub buffer_pool_misses: u64,
+}
+
+impl Stats {
+    /// 累加另一个连接的统计：计数、缓冲区池命中次数与受限时长求和，连接时间取最早，活动时间取最晚，
+    /// 任一连接使用 SOCK_SEQPACKET 或正在受限即为 `true`
+    pub fn merge(&mut self, other: &Stats) {
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.messages_sent += other.messages_sent;
+        self.messages_received += other.messages_received;
+        self.connect_time = match (self.connect_time, other.connect_time) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.last_activity = self.last_activity.max(other.last_activity);
+        self.seqpacket |= other.seqpacket;
+        self.throttled |= other.throttled;
+        self.throttled_time += other.throttled_time;
+        self.buffer_pool_hits += other.buffer_pool_hits;
+        self.buffer_pool_misses += other.buffer_pool_misses;
+    }
+}
+
+/// 连接统计计数器，包装器与 `VirgeClient`/`VirgeServer` 共享
+#[derive(Debug, Default)]
+pub(crate) struct StatsCounters {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+    /// 以 UNIX 纪元以来的微秒数记录，0 表示未设置
+    connect_time: AtomicU64,
+    last_activity: AtomicU64,
+    /// 最近一次建立的连接使用 SOCK_SEQPACKET 套接字
+    seqpacket: AtomicBool,
+    /// 连接已因空闲超时被服务器关闭
+    idle: AtomicBool,
+    /// 正在等待令牌的收发数
+    throttling: AtomicUsize,
+    /// 因限速等待的累计微秒数
+    throttled_micros: AtomicU64,
+    /// 连接已因对端持续超出限速被关闭
+    rate_limited: AtomicBool,
+    /// 最内层传输的接收缓冲区池
+    buffer_pool: OnceLock<Arc<BufferPool>>,
+}
+
+/// 限速等待期间持有，释放时清除受限标记
+pub(crate) struct Throttled<'a>(&'a StatsCounters);
+
+impl Drop for Throttled<'_> {
+    fn drop(&mut self) {
+        self.0.throttling.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_micros() as u64)
+}
+
+fn from_micros(micros: u64) -> Option<SystemTime> {
+    (micros != 0).then(|| UNIX_EPOCH + Duration::from_micros(micros))
+}
+
+impl StatsCounters {
+    pub(crate) fn snapshot(&self) -> Stats {
+        Stats {
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+            connect_time: from_micros(self.connect_time.load(Ordering::Relaxed)),
+            last_activity: from_micros(self.last_activity.load(Ordering::Relaxed)),
+            seqpacket: self.seqpacket.load(Ordering::Relaxed),
+            throttled: self.throttling.load(Ordering::Relaxed) > 0,
+            throttled_time: Duration::from_micros(self.throttled_micros.load(Ordering::Relaxed)),
+            buffer_pool_hits: self.buffer_pool.get().map_or(0, |pool| pool.hits()),
+            buffer_pool_misses: self.buffer_pool.get().map_or(0, |pool| pool.misses()),
+        }
+    }
+
+    /// 最近一次成功收发数据的时间，尚无收发时为建立连接的时间
+    pub(crate) fn idle_since(&self) -> Option<SystemTime> {
+        let last = self.last_activity.load(Ordering::Relaxed).max(self.connect_time.load(Ordering::Relaxed));
+        from_micros(last)
+    }
+
+    /// 标记连接已因空闲超时被关闭
+    pub(crate) fn mark_idle(&self) {
+        self.idle.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_idle(&self) -> bool {
+        self.idle.load(Ordering::SeqCst)
+    }
+
+    /// 开始一次 `wait` 时长的限速等待，等待时长预先计入累计值
+    pub(crate) fn throttle(&self, wait: Duration) -> Throttled<'_> {
+        self.throttled_micros.fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+        self.throttling.fetch_add(1, Ordering::Relaxed);
+        Throttled(self)
+    }
+
+    /// 标记连接已因对端持续超出限速被关闭
+    pub(crate) fn mark_rate_limited(&self) {
+        self.rate_limited.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_rate_limited(&self) -> bool {
+        self.rate_limited.load(Ordering::SeqCst)
+    }
+
+    fn connected(&self, seqpacket: bool) {
+        self.connect_time.store(now_micros(), Ordering::Relaxed);
+        self.seqpacket.store(seqpacket, Ordering::Relaxed);
+    }
+
+    fn sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.last_activity.store(now_micros(), Ordering::Relaxed);
+    }
+
+    fn received(&self, bytes: usize) {
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.last_activity.store(now_micros(), Ordering::Relaxed);
+    }
+}
+
+/// 统计包装器
+pub(crate) struct StatsTransport {
+    inner: Box<dyn Transport>,
+    counters: Arc<StatsCounters>,
+    /// 下层为 SOCK_SEQPACKET 传输，建立连接后记入统计
+    seqpacket: bool,
+}
+
+impl StatsTransport {
+    pub(crate) fn new(inner: Box<dyn Transport>, counters: Arc<StatsCounters>) -> Self {
+        if let Some(pool) = inner.buffer_pool() {
+            let _ = counters.buffer_pool.set(pool);
+        }
+        Self { inner, counters, seqpacket: false }
+    }
+
+    /// 设置下层是否为 SOCK_SEQPACKET 传输，由 `TransportOptions` 同步设置
+    ///
+    /// 不支持 SOCK_SEQPACKET 的内核上连接直接失败，不会退回到流式套接字，连接成功即表示该模式生效。
+    pub(crate) fn with_seqpacket(mut self, enabled: bool) -> Self {
+        self.seqpacket = enabled;
+        self
+    }
+
+    /// 连接已因空闲超时被关闭时返回 `Timeout`
+    fn check_idle(&self) -> Result<()> {
+        if self.counters.is_idle() {
+            return Err(idle_error());
+        }
+        Ok(())
+    }
+
+    /// 操作期间连接因空闲超时被关闭时，以 `Timeout` 代替下层报告的断开错误
+    fn map_idle<T>(&self, result: Result<T>) -> Result<T> {
+        match result {
+            Err(_) if self.counters.is_idle() => Err(idle_error()),
+            result => result,
+        }
+    }
+}
+
+fn idle_error() -> VirgeError {
+    VirgeError::Timeout("connection closed by the server after being idle".to_string())
+}
+
+#[async_trait]
+impl Transport for StatsTransport {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.connect(cid, port, chunksize, isack).await?;
+        self.counters.connected(self.seqpacket);
+        Ok(())
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        self.inner.connect_timeout(cid, port, chunksize, isack, timeout).await?;
+        self.counters.connected(self.seqpacket);
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.inner.from_tokio_stream(stream).await?;
+        self.counters.connected(self.seqpacket);
+        Ok(())
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.inner.from_tcp_stream(stream).await?;
+        self.counters.connected(self.seqpacket);
+        Ok(())
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.inner.from_uds_stream(stream).await?;
+        self.counters.connected(self.seqpacket);
+        Ok(())
+    }
+
+    #[cfg(feature = "use-raw")]
+    async fn from_seqpacket(&mut self, socket: std::os::fd::OwnedFd) -> Result<()> {
+        self.inner.from_seqpacket(socket).await?;
+        self.counters.connected(self.seqpacket);
+        Ok(())
+    }
+
+    #[cfg(feature = "use-xtransport")]
+    async fn from_stream(&mut self, stream: vsock::VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+        self.inner.from_stream(stream, chunksize, isack).await?;
+        self.counters.connected(self.seqpacket);
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        self.inner.disconnect_with_reason(code, message).await
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        self.check_idle()?;
+        let len = data.len();
+        self.map_idle(self.inner.send(data).await)?;
+        self.counters.sent(len);
+        Ok(())
+    }
+
+    async fn send_noack(&mut self, data: Vec<u8>) -> Result<()> {
+        self.check_idle()?;
+        let len = data.len();
+        self.map_idle(self.inner.send_noack(data).await)?;
+        self.counters.sent(len);
+        Ok(())
+    }
+
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        self.check_idle()?;
+        let sent = self.map_idle(self.inner.send_slices(slices).await)?;
+        self.counters.sent(sent);
+        Ok(sent)
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        self.check_idle()?;
+        let data = self.map_idle(self.inner.recv().await)?;
+        if data.is_empty() {
+            // 回收任务关闭套接字后下层可能报告为对端关闭
+            self.check_idle()?;
+        }
+        self.counters.received(data.len());
+        Ok(data)
+    }
+
+    async fn recv_into(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        self.check_idle()?;
+        let n = self.map_idle(self.inner.recv_into(buf).await)?;
+        if n == 0 {
+            self.check_idle()?;
+        }
+        self.counters.received(n);
+        Ok(n)
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        self.check_idle()?;
+        let data = self.map_idle(self.inner.try_recv().await)?;
+        if let Some(data) = &data {
+            self.counters.received(data.len());
+        }
+        Ok(data)
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        self.check_idle()?;
+        let sent = self.map_idle(self.inner.try_send(data).await)?;
+        if let Some(n) = sent {
+            self.counters.sent(n);
+        }
+        Ok(sent)
+    }
+
+    async fn send_priority(&mut self, data: Vec<u8>) -> Result<()> {
+        self.check_idle()?;
+        let len = data.len();
+        self.map_idle(self.inner.send_priority(data).await)?;
+        self.counters.sent(len);
+        Ok(())
+    }
+
+    async fn recv_priority(&mut self) -> Result<Vec<u8>> {
+        self.check_idle()?;
+        let data = self.map_idle(self.inner.recv_priority().await)?;
+        self.counters.received(data.len());
+        Ok(data)
+    }
+
+    async fn try_recv_priority(&mut self) -> Result<Option<Vec<u8>>> {
+        self.check_idle()?;
+        let data = self.map_idle(self.inner.try_recv_priority().await)?;
+        if let Some(data) = &data {
+            self.counters.received(data.len());
+        }
+        Ok(data)
+    }
+
+    fn priority_outbox(&self) -> Option<PriorityOutbox> {
+        self.inner.priority_outbox()
+    }
+
+    async fn send_with_meta(&mut self, data: Vec<u8>, meta: &HeaderMap) -> Result<()> {
+        self.check_idle()?;
+        let len = data.len();
+        self.map_idle(self.inner.send_with_meta(data, meta).await)?;
+        self.counters.sent(len);
+        Ok(())
+    }
+
+    async fn recv_with_meta(&mut self) -> Result<(Vec<u8>, HeaderMap)> {
+        self.check_idle()?;
+        let (data, meta) = self.map_idle(self.inner.recv_with_meta().await)?;
+        if data.is_empty() {
+            self.check_idle()?;
+        }
+        self.counters.received(data.len());
+        Ok((data, meta))
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        self.inner.shutdown_write().await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.check_idle()?;
+        self.map_idle(self.inner.flush().await)
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn open_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.open_stream().await
+    }
+
+    #[cfg(feature = "use-yamux")]
+    async fn accept_stream(&mut self) -> Result<crate::transport::VirgeStream> {
+        self.inner.accept_stream().await
+    }
+
+    async fn probe(&mut self, timeout: Duration) -> Result<bool> {
+        // 探测不是用户数据的收发，不更新最近活动时间
+        if self.counters.is_idle() {
+            return Ok(false);
+        }
+        self.inner.probe(timeout).await
+    }
+
+    fn is_connected(&self) -> bool {
+        !self.counters.is_idle() && self.inner.is_connected()
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.inner.last_ack_latency()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.inner.ack_stats()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        self.inner.buffer_pool()
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.inner.negotiated_ack_window()
+    }
+
+    fn last_sent_seq(&self) -> Option<u64> {
+        self.inner.last_sent_seq()
+    }
+
+    fn last_received_seq(&self) -> Option<u64> {
+        self.inner.last_received_seq()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.inner.peer_auth_token()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.inner.raw_fd()
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.inner.connection_id()
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        self.inner.has_buffered_data()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+
+    async fn prefetch(&mut self) -> Result<()> {
+        self.inner.prefetch().await
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
@@ -0,0 +1,393 @@
+//! 字节流传输的公共实现
+//!
+//! Raw、Tcp 与 UDS 传输在各自的字节流上使用相同的帧格式与握手，只在建立连接的方式上不同：
+//! `StreamTransport` 实现帧的收发、超时与关闭，底层流经 [`FramedStream`] 提供连接方式、
+//! 握手中声明的协议类型与本地地址。
+//!
+//! # 特点
+//! - 无 ACK、无多路复用，批量传输开销最小
+//! - 读取的数据先进入内部缓冲区，超时或非阻塞接收不会破坏消息边界
+//! - 帧头声明的长度超过 `max_message_size` 时在缓冲前返回 `MessageTooLarge`
+//!
+//! # 帧格式
+//! ```text
+//! ┌──────────────────┬──────────────────┐
+//! │ length: u32 (BE) │ payload: [u8]    │
+//! └──────────────────┴──────────────────┘
+//! ```
+
+use crate::error::{Result, VirgeError};
+use crate::transport::bufpool::BufferPool;
+use crate::transport::{check_timeout, framing, preamble, Transport, TransportKind, VsockAddr};
+use async_trait::async_trait;
+use futures::FutureExt;
+use log::*;
+use std::any::Any;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+/// 单次从流中读取的字节数
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// 承载长度前缀帧的底层字节流
+///
+/// 由各传输模块为 tokio-vsock、本地 TCP 与 Unix 套接字流实现。
+#[async_trait]
+pub trait FramedStream: AsyncRead + AsyncWrite + AsRawFd + Unpin + Send + Sync + Sized + 'static {
+    /// 建立连接所需的参数，如绑定的本地端口或套接字路径
+    type Target: Send + Sync;
+
+    /// 日志与错误信息中的传输名称
+    const NAME: &'static str;
+
+    /// 握手中声明的协议类型
+    const KIND: TransportKind;
+
+    /// 连接到 `cid`/`port` 对应的对端
+    async fn open(target: &Self::Target, cid: u32, port: u32) -> Result<Self>;
+
+    /// 新建或接受的连接在握手前的设置
+    fn prepare(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// 本地地址对应的 vsock 地址，没有对应地址时为 `None`
+    fn vsock_addr(&self) -> Option<VsockAddr> {
+        None
+    }
+}
+
+/// 字节流传输协议实现
+///
+/// 管理一条 `S` 类型的连接，消息以 4 字节大端长度前缀分隔。
+pub struct StreamTransport<S: FramedStream> {
+    /// 建立连接所需的参数
+    target: S::Target,
+    stream: Option<S>,
+    /// 已读取但尚未组成完整消息的数据，同时限制单条消息的最大字节数
+    read_buffer: framing::StreamBuffer,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    /// 是否在收发数据前执行握手
+    handshake: bool,
+    /// 握手中声明的各层设置（各层本身由外层包装器完成），由 `TransportOptions::hello` 生成
+    hello: preamble::Hello,
+    /// 最近一次握手中对端附带的认证令牌
+    peer_auth_token: Option<Vec<u8>>,
+    /// 最近一次握手协商出的 ACK 窗口，未执行握手时为 `None`
+    negotiated_window: Option<u32>,
+}
+
+impl<S: FramedStream> StreamTransport<S> {
+    /// 创建以 `target` 建立连接的传输实例，由各传输的构造函数调用
+    pub(crate) fn with_target(target: S::Target) -> Self {
+        Self {
+            target,
+            stream: None,
+            read_buffer: framing::StreamBuffer::new(crate::DEFAULT_MAX_MESSAGE_SIZE),
+            read_timeout: None,
+            write_timeout: None,
+            handshake: true,
+            hello: preamble::Hello::without_chunk_size(S::KIND.to_byte(), false),
+            peer_auth_token: None,
+            negotiated_window: None,
+        }
+    }
+
+    /// 建立连接所需的参数
+    pub(crate) fn target_mut(&mut self) -> &mut S::Target {
+        &mut self.target
+    }
+
+    /// 设置是否执行连接握手，关闭后可与未握手的旧版本互通
+    pub fn with_handshake(mut self, enabled: bool) -> Self {
+        self.handshake = enabled;
+        self
+    }
+
+    /// 设置握手中声明的各层设置，由 `TransportOptions` 按叠加的包装器生成
+    pub(crate) fn with_hello(mut self, hello: preamble::Hello) -> Self {
+        self.hello = hello;
+        self
+    }
+
+    /// 设置单条消息的最大字节数，超限的发送在本地失败，超限的接收返回 `MessageTooLarge` 且连接保持可用
+    pub fn with_max_message_size(mut self, size: usize) -> Self {
+        self.read_buffer.set_max(size);
+        self
+    }
+
+    /// 设置接收缓冲区池至多保存的空闲读缓冲区数，0 表示每次读取都新分配，由 `TransportOptions` 同步设置
+    pub(crate) fn with_buffer_pool_size(mut self, size: usize) -> Self {
+        self.read_buffer.set_pool_size(size);
+        self
+    }
+
+    /// 以非阻塞方式读取到 `chunk`，直到组成一个完整的帧或流中暂无数据
+    fn poll_frame(&mut self, chunk: &mut [u8]) -> Result<Option<Vec<u8>>> {
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Some(message) = self.read_buffer.take_frame()? {
+                info!("{} transport received {} bytes", S::NAME, message.len());
+                return Ok(Some(message));
+            }
+
+            let stream = self.stream.as_mut().ok_or_else(Self::not_connected)?;
+            let mut buf = ReadBuf::new(&mut *chunk);
+            match Pin::new(stream).poll_read(&mut cx, &mut buf) {
+                Poll::Pending => return Ok(None),
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => return Err(Self::unexpected_eof()),
+                Poll::Ready(Ok(())) => self.read_buffer.extend(buf.filled()),
+                Poll::Ready(Err(e)) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// 按配置执行握手，校验双方的协议版本、类型与 ACK 设置
+    async fn handshake(&mut self, stream: &mut S) -> Result<()> {
+        self.negotiated_window = None;
+        self.peer_auth_token = None;
+        if !self.handshake {
+            return Ok(());
+        }
+        let negotiated = preamble::handshake_async(stream, &self.hello).await?;
+        self.negotiated_window = Some(negotiated.ack_window);
+        self.peer_auth_token = negotiated.peer_auth_token;
+        Ok(())
+    }
+
+    /// 以服务器接受的连接初始化
+    ///
+    /// 接受的流不是 `S` 时与 `Transport` 的默认实现一样返回错误，`method` 为调用的 trait 方法名。
+    async fn accept<T: Any + Send>(&mut self, stream: T, method: &str) -> Result<()> {
+        let stream: Box<dyn Any + Send> = Box::new(stream);
+        let mut stream = match stream.downcast::<S>() {
+            Ok(stream) => *stream,
+            Err(_) => return Err(VirgeError::Other(format!("{} not implemented", method))),
+        };
+        self.reset();
+        stream.prepare()?;
+        self.handshake(&mut stream).await?;
+
+        self.stream = Some(stream);
+        info!("{} transport initialized from stream successfully", S::NAME);
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.stream = None;
+        self.read_buffer.clear();
+    }
+
+    fn not_connected() -> VirgeError {
+        VirgeError::Disconnected(format!("{} transport not connected", S::NAME))
+    }
+
+    /// 在可选的超时时间内执行 IO 操作，超时返回 `ErrorKind::TimedOut`，转换为 `VirgeError::Timeout`
+    async fn with_timeout<T>(
+        timeout: Option<Duration>,
+        fut: impl Future<Output = std::io::Result<T>>,
+    ) -> std::io::Result<T> {
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, fut).await.unwrap_or_else(|_| {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("{} transport operation timed out", S::NAME),
+                ))
+            }),
+            None => fut.await,
+        }
+    }
+
+    fn unexpected_eof() -> VirgeError {
+        VirgeError::IoError(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!("{} transport peer closed the connection", S::NAME),
+        ))
+    }
+}
+
+#[async_trait]
+impl<S: FramedStream> Transport for StreamTransport<S> {
+    async fn connect(&mut self, cid: u32, port: u32, _: u32, _: bool) -> Result<()> {
+        self.reset();
+
+        let mut stream = S::open(&self.target, cid, port).await?;
+        stream.prepare()?;
+        self.handshake(&mut stream).await?;
+
+        self.stream = Some(stream);
+        info!("{} transport connected successfully", S::NAME);
+        Ok(())
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        // 超时后 connect future 被 drop，未完成的连接随之取消
+        let result = tokio::time::timeout(timeout, self.connect(cid, port, chunksize, isack)).await;
+        match result {
+            Ok(result) => result,
+            Err(_) => {
+                self.reset();
+                Err(VirgeError::Timeout(format!("{} transport connect timed out", S::NAME)))
+            }
+        }
+    }
+
+    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+        self.accept(stream, "from_tokio_stream").await
+    }
+
+    #[cfg(feature = "use-tcp")]
+    async fn from_tcp_stream(&mut self, stream: tokio::net::TcpStream) -> Result<()> {
+        self.accept(stream, "from_tcp_stream").await
+    }
+
+    #[cfg(feature = "use-uds")]
+    async fn from_uds_stream(&mut self, stream: tokio::net::UnixStream) -> Result<()> {
+        self.accept(stream, "from_uds_stream").await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.disconnect_with_reason(0, "").await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
+        info!("{} transport disconnecting", S::NAME);
+        if let Some(mut stream) = self.stream.take() {
+            // 不等待发送缓冲区腾出空间：对端停止接收时放弃关闭帧，对端随后将其视为异常断开
+            match stream.write_all(&framing::close_frame(code, message)).now_or_never() {
+                Some(Ok(())) => {}
+                Some(Err(e)) => debug!("{} transport failed to send close frame: {}", S::NAME, e),
+                None => debug!("{} transport send buffer full, skipping close frame", S::NAME),
+            }
+            if let Err(e) = stream.shutdown().await {
+                debug!("{} transport shutdown error: {}", S::NAME, e);
+            }
+        }
+        self.reset();
+        info!("{} transport disconnected", S::NAME);
+        Ok(())
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        let header = framing::stream_header(data.len(), self.read_buffer.max())?;
+        let timeout = self.write_timeout;
+        let stream = self.stream.as_mut().ok_or_else(Self::not_connected)?;
+
+        Self::with_timeout(timeout, async {
+            stream.write_all(&header).await?;
+            stream.write_all(&data).await
+        }).await?;
+
+        info!("{} transport sent {} bytes", S::NAME, data.len());
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Result<Vec<u8>> {
+        let timeout = self.read_timeout;
+        loop {
+            if let Some(message) = self.read_buffer.take_frame()? {
+                info!("{} transport received {} bytes", S::NAME, message.len());
+                return Ok(message);
+            }
+
+            let stream = self.stream.as_mut().ok_or_else(Self::not_connected)?;
+            let mut chunk = self.read_buffer.read_chunk(READ_CHUNK_SIZE);
+            // 超时时已读到的数据保留在缓冲区中，下一次 recv 继续组装
+            let read = Self::with_timeout(timeout, stream.read(&mut chunk)).await;
+            if let Ok(n) = read {
+                self.read_buffer.extend(&chunk[..n]);
+            }
+            self.read_buffer.recycle(chunk);
+            if read? == 0 {
+                return Err(Self::unexpected_eof());
+            }
+        }
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut chunk = self.read_buffer.read_chunk(READ_CHUNK_SIZE);
+        let result = self.poll_frame(&mut chunk);
+        self.read_buffer.recycle(chunk);
+        result
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        let header = framing::stream_header(data.len(), self.read_buffer.max())?;
+        let timeout = self.write_timeout;
+        let stream = self.stream.as_mut().ok_or_else(Self::not_connected)?;
+
+        // 无法写入任何字节时直接返回；一旦写入了部分帧头，则阻塞写完以保证消息完整
+        let mut cx = Context::from_waker(Waker::noop());
+        let written = match Pin::new(&mut *stream).poll_write(&mut cx, &header) {
+            Poll::Pending => return Ok(None),
+            Poll::Ready(result) => result?,
+        };
+        Self::with_timeout(timeout, async {
+            stream.write_all(&header[written..]).await?;
+            stream.write_all(data).await
+        }).await?;
+
+        info!("{} transport sent {} bytes", S::NAME, data.len());
+        Ok(Some(data.len()))
+    }
+
+    async fn shutdown_write(&mut self) -> Result<()> {
+        let timeout = self.write_timeout;
+        let stream = self.stream.as_mut().ok_or_else(Self::not_connected)?;
+        Self::with_timeout(timeout, stream.write_all(&framing::STREAM_EOF)).await?;
+        info!("{} transport shut down its write side", S::NAME);
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        let timeout = self.write_timeout;
+        let stream = self.stream.as_mut().ok_or_else(Self::not_connected)?;
+        Self::with_timeout(timeout, stream.flush()).await?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.stream.is_some() && !self.read_buffer.peer_closed()
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.stream.as_ref().map(|stream| stream.as_raw_fd())
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.stream.as_ref().and_then(S::vsock_addr)
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.negotiated_window
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.peer_auth_token.as_deref()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        !self.read_buffer.is_empty()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        Some(self.read_buffer.pool().clone())
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        check_timeout(timeout)?;
+        self.read_timeout = timeout;
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        check_timeout(timeout)?;
+        self.write_timeout = timeout;
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+//! 系统调用辅助模块
+//!
+//! 封装 vsock 文件描述符上的 poll、从指定本地端口连接等底层操作，供各传输实现与服务器监听复用。
+
+use std::io;
+#[cfg(feature = "use-raw")]
+use std::io::IoSlice;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+#[cfg(feature = "use-xtransport")]
+use std::time::Duration;
+
+/// 等待文件描述符可读
+///
+/// # Arguments
+/// - `fd`: 待等待的文件描述符
+/// - `timeout`: 最长等待时间，`None` 表示一直等待
+///
+/// # Returns
+/// 超时前可读（或对端关闭、出错）返回 `Ok(true)`，超时返回 `Ok(false)`
+#[cfg(feature = "use-xtransport")]
+pub(crate) fn wait_readable(fd: RawFd, timeout: Option<Duration>) -> io::Result<bool> {
+    Ok(wait_any_readable(&[fd], timeout)?.is_some())
+}
+
+/// 等待文件描述符可写
+///
+/// # Returns
+/// 超时前可写返回 `Ok(true)`，超时返回 `Ok(false)`
+#[cfg(feature = "use-xtransport")]
+pub(crate) fn wait_writable(fd: RawFd, timeout: Option<Duration>) -> io::Result<bool> {
+    let mut pfds = [libc::pollfd { fd, events: libc::POLLOUT, revents: 0 }];
+    Ok(poll_fds(&mut pfds, timeout)?.is_some())
+}
+
+/// 对端是否已正常关闭连接：套接字上没有待读的数据且已读到字节流结束
+///
+/// 仅窥视一个字节，不消费数据也不阻塞；连接被重置或仍有数据可读时返回 `false`。
+#[cfg(feature = "use-xtransport")]
+pub(crate) fn peer_closed(fd: RawFd) -> bool {
+    let mut byte = 0u8;
+    // SAFETY: 向 1 字节的栈上缓冲区窥视读取，fd 在调用期间保持打开
+    let n = unsafe {
+        libc::recv(fd, (&mut byte as *mut u8).cast(), 1, libc::MSG_PEEK | libc::MSG_DONTWAIT)
+    };
+    n == 0
+}
+
+/// 清零当前线程的 errno，之后可用 `last_errno` 判断一次库调用是否因系统调用失败
+#[cfg(feature = "use-xtransport")]
+pub(crate) fn clear_errno() {
+    // SAFETY: __errno_location 返回当前线程 errno 的有效地址
+    unsafe { *libc::__errno_location() = 0 };
+}
+
+/// 当前线程最近一次失败的系统调用留下的 errno，`clear_errno` 之后没有系统调用失败时为 `None`
+#[cfg(feature = "use-xtransport")]
+pub(crate) fn last_errno() -> Option<i32> {
+    io::Error::last_os_error().raw_os_error().filter(|&errno| errno != 0)
+}
+
+/// 同时等待多个文件描述符可读
+///
+/// # Returns
+/// 返回第一个就绪的文件描述符在 `fds` 中的下标，超时返回 `Ok(None)`
+#[cfg(feature = "use-xtransport")]
+pub(crate) fn wait_any_readable(fds: &[RawFd], timeout: Option<Duration>) -> io::Result<Option<usize>> {
+    let mut pfds: Vec<libc::pollfd> = fds.iter()
+        .map(|&fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 })
+        .collect();
+    poll_fds(&mut pfds, timeout)
+}
+
+#[cfg(feature = "use-xtransport")]
+fn poll_fds(pfds: &mut [libc::pollfd], timeout: Option<Duration>) -> io::Result<Option<usize>> {
+    let timeout_ms = match timeout {
+        Some(t) => t.as_micros().div_ceil(1000).min(libc::c_int::MAX as u128) as libc::c_int,
+        None => -1,
+    };
+
+    loop {
+        let ret = unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as libc::nfds_t, timeout_ms) };
+        if ret >= 0 {
+            return Ok(pfds.iter().position(|pfd| pfd.revents != 0));
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// 创建非阻塞 vsock 套接字并绑定到本地端口 `local_port`（CID 为 `VMADDR_CID_ANY`）
+///
+/// # Returns
+/// 端口被占用时返回 `ErrorKind::AddrInUse`
+pub(crate) fn bind_local_vsock(local_port: u32) -> io::Result<OwnedFd> {
+    let fd = vsock_socket(libc::SOCK_STREAM)?;
+    bind_vsock_addr(fd.as_raw_fd(), libc::VMADDR_CID_ANY, local_port)?;
+    Ok(fd)
+}
+
+/// 创建非阻塞的 vsock 套接字，`ty` 为 `SOCK_STREAM` 或 `SOCK_SEQPACKET`
+pub(crate) fn vsock_socket(ty: libc::c_int) -> io::Result<OwnedFd> {
+    // SAFETY: socket 返回新建的描述符或 -1，成功时立即交给 OwnedFd 管理
+    let fd = unsafe { libc::socket(libc::AF_VSOCK, ty | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: fd 为刚创建且未被其他对象持有的套接字
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// 将套接字绑定到 (cid, port)
+pub(crate) fn bind_vsock_addr(fd: RawFd, cid: u32, port: u32) -> io::Result<()> {
+    let addr = vsock_sockaddr(cid, port);
+    // SAFETY: addr 为完整初始化的 sockaddr_vm，长度与之一致
+    let ret = unsafe {
+        libc::bind(
+            fd,
+            &addr as *const libc::sockaddr_vm as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 内核（Linux 5.14 之前）或 vsock 传输（如 VMCI）不支持 SOCK_SEQPACKET 时 socket/connect 返回的错误
+#[cfg(feature = "use-raw")]
+pub(crate) fn seqpacket_unsupported(e: &io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(libc::ESOCKTNOSUPPORT | libc::EPROTONOSUPPORT | libc::EOPNOTSUPP)
+    )
+}
+
+/// 非阻塞地发送一个由 `slices` 依次拼接而成的数据报
+///
+/// # Returns
+/// 发送缓冲区已满时返回 `ErrorKind::WouldBlock`，SOCK_SEQPACKET 套接字上数据报要么整体发送要么不发送
+#[cfg(feature = "use-raw")]
+pub(crate) fn send_datagram(fd: RawFd, slices: &[IoSlice<'_>]) -> io::Result<usize> {
+    // SAFETY: msghdr 全零是合法的初始值
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    // IoSlice 在 Unix 上与 iovec 的内存布局一致，sendmsg 只读取其指向的数据
+    msg.msg_iov = slices.as_ptr() as *mut libc::iovec;
+    msg.msg_iovlen = slices.len() as _;
+    loop {
+        // SAFETY: msg 指向的 iovec 数组在调用期间有效
+        let n = unsafe { libc::sendmsg(fd, &msg, libc::MSG_NOSIGNAL | libc::MSG_DONTWAIT) };
+        if n >= 0 {
+            return Ok(n as usize);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// 非阻塞地接收一个数据报
+///
+/// # Returns
+/// 数据报的完整字节数，大于 `buf.len()` 时超出的部分已被内核丢弃；对端关闭时返回 0
+#[cfg(feature = "use-raw")]
+pub(crate) fn recv_datagram(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+        // SAFETY: buf 在调用期间有效，长度与之一致
+        let n = unsafe {
+            libc::recv(fd, buf.as_mut_ptr().cast(), buf.len(), libc::MSG_DONTWAIT | libc::MSG_TRUNC)
+        };
+        if n >= 0 {
+            return Ok(n as usize);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// 在非阻塞的监听套接字上接受一个连接
+///
+/// # Returns
+/// 非阻塞的已连接套接字与对端的 (cid, port)；没有待接受的连接时返回 `ErrorKind::WouldBlock`
+#[cfg(feature = "use-raw")]
+pub(crate) fn accept_vsock(fd: RawFd) -> io::Result<(OwnedFd, (u32, u32))> {
+    // SAFETY: sockaddr_vm 全零是合法的初始值
+    let mut addr: libc::sockaddr_vm = unsafe { std::mem::zeroed() };
+    loop {
+        let mut len = std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t;
+        // SAFETY: addr 与 len 指向有效的内存，长度与之一致
+        let ret = unsafe {
+            libc::accept4(
+                fd,
+                &mut addr as *mut libc::sockaddr_vm as *mut libc::sockaddr,
+                &mut len,
+                libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+            )
+        };
+        if ret >= 0 {
+            // SAFETY: ret 为 accept4 新建且未被其他对象持有的套接字
+            return Ok((unsafe { OwnedFd::from_raw_fd(ret) }, (addr.svm_cid, addr.svm_port)));
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// 查询 vsock 套接字绑定的本地 (cid, port)
+#[cfg(feature = "use-raw")]
+pub(crate) fn local_vsock_addr(fd: RawFd) -> io::Result<(u32, u32)> {
+    // SAFETY: sockaddr_vm 全零是合法的初始值
+    let mut addr: libc::sockaddr_vm = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t;
+    // SAFETY: addr 与 len 指向有效的内存，长度与之一致
+    let ret = unsafe {
+        libc::getsockname(fd, &mut addr as *mut libc::sockaddr_vm as *mut libc::sockaddr, &mut len)
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((addr.svm_cid, addr.svm_port))
+}
+
+/// 在非阻塞套接字上发起到 (cid, port) 的连接
+///
+/// # Returns
+/// 连接立即完成返回 `Ok(true)`，仍在进行中返回 `Ok(false)`，之后等待可写并调用 [`finish_connect`]
+pub(crate) fn start_connect(fd: RawFd, cid: u32, port: u32) -> io::Result<bool> {
+    let addr = vsock_sockaddr(cid, port);
+    loop {
+        // SAFETY: addr 为完整初始化的 sockaddr_vm，长度与之一致
+        let ret = unsafe {
+            libc::connect(
+                fd,
+                &addr as *const libc::sockaddr_vm as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+            )
+        };
+        if ret == 0 {
+            return Ok(true);
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EINPROGRESS) => return Ok(false),
+            Some(libc::EINTR) => continue,
+            _ => return Err(err),
+        }
+    }
+}
+
+/// 读取并清除套接字上挂起的错误，用于判断非阻塞连接的结果
+pub(crate) fn finish_connect(fd: RawFd) -> io::Result<()> {
+    let mut error: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: error 与 len 指向有效的内存，长度与之一致
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_ERROR,
+            &mut error as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    match error {
+        0 => Ok(()),
+        error => Err(io::Error::from_raw_os_error(error)),
+    }
+}
+
+/// 从本地端口 `local_port` 阻塞地连接到 (cid, port)，`timeout` 为连接的等待上限
+///
+/// # Returns
+/// 已连接的阻塞模式套接字；超时返回 `ErrorKind::TimedOut`
+#[cfg(feature = "use-xtransport")]
+pub(crate) fn connect_vsock_from(local_port: u32, cid: u32, port: u32, timeout: Option<Duration>) -> io::Result<OwnedFd> {
+    let fd = bind_local_vsock(local_port)?;
+    if !start_connect(fd.as_raw_fd(), cid, port)? {
+        if !wait_writable(fd.as_raw_fd(), timeout)? {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "vsock connect timed out"));
+        }
+        finish_connect(fd.as_raw_fd())?;
+    }
+    set_nonblocking(fd.as_raw_fd(), false)?;
+    Ok(fd)
+}
+
+fn vsock_sockaddr(cid: u32, port: u32) -> libc::sockaddr_vm {
+    // SAFETY: sockaddr_vm 全零是合法的初始值
+    let mut addr: libc::sockaddr_vm = unsafe { std::mem::zeroed() };
+    addr.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+    addr.svm_cid = cid;
+    addr.svm_port = port;
+    addr
+}
+
+#[cfg(feature = "use-xtransport")]
+fn set_nonblocking(fd: RawFd, nonblocking: bool) -> io::Result<()> {
+    // SAFETY: fcntl 只读写描述符标志
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let flags = if nonblocking { flags | libc::O_NONBLOCK } else { flags & !libc::O_NONBLOCK };
+    // SAFETY: 同上
+    if unsafe { libc::fcntl(fd, libc::F_SETFL, flags) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! TCP 传输协议实现
+//!
+//! 用于没有虚拟机监控程序的本地开发与 CI：将 vsock 的 cid/port 映射为 `127.0.0.1:port`，
+//! cid 被忽略。帧格式、握手以及心跳、ACK、校验、压缩等包装器与 Raw 传输完全一致，
+//! 帧的收发由 `StreamTransport` 完成。
+
+use crate::error::{Result, VirgeError};
+use crate::transport::stream_impl::{FramedStream, StreamTransport};
+use crate::transport::{TransportKind, VsockAddr};
+use async_trait::async_trait;
+use log::*;
+use std::net::{Ipv4Addr, SocketAddr};
+use tokio::net::TcpStream;
+
+/// vsock 端口对应的本地 TCP 地址，`VMADDR_PORT_ANY` 映射为由系统分配的端口 0
+pub(crate) fn local_addr(port: u32) -> Result<SocketAddr> {
+    if port as usize == crate::VMADDR_PORT_ANY {
+        return Ok(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)));
+    }
+    let port = u16::try_from(port).map_err(|_| {
+        VirgeError::ConfigError(format!("Port {} does not fit in a TCP port", port))
+    })?;
+    Ok(SocketAddr::from((Ipv4Addr::LOCALHOST, port)))
+}
+
+#[async_trait]
+impl FramedStream for TcpStream {
+    type Target = ();
+
+    const NAME: &'static str = "TCP";
+
+    const KIND: TransportKind = TransportKind::Tcp;
+
+    async fn open(_: &(), cid: u32, port: u32) -> Result<Self> {
+        let addr = local_addr(port)?;
+        info!("TCP transport connecting to {} (cid={} ignored)", addr, cid);
+        TcpStream::connect(addr)
+            .await
+            .map_err(|e| VirgeError::connection_io("Failed to connect tcp", e))
+    }
+
+    fn prepare(&self) -> Result<()> {
+        self.set_nodelay(true)?;
+        Ok(())
+    }
+
+    /// 本地 TCP 地址映射为 `VMADDR_CID_LOCAL` 加本地的 TCP 端口
+    fn vsock_addr(&self) -> Option<VsockAddr> {
+        let addr = self.local_addr().ok()?;
+        Some(VsockAddr::new(crate::VMADDR_CID_LOCAL as u32, addr.port() as u32))
+    }
+}
+
+/// TCP 传输协议实现
+///
+/// 管理一条本地回环 TCP 连接，消息以 4 字节大端长度前缀分隔。
+pub type TcpTransport = StreamTransport<TcpStream>;
+
+impl StreamTransport<TcpStream> {
+    pub fn new() -> Self {
+        Self::with_target(())
+    }
+}
+
+impl Default for StreamTransport<TcpStream> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
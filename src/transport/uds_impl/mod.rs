@@ -0,0 +1,97 @@
+//! Unix 域套接字传输协议实现
+//!
+//! Firecracker、cloud-hypervisor 等监控程序把来宾的 vsock 设备导出为宿主机上的 Unix 套接字：
+//! - 宿主机主动连接：连接 `uds_path` 后发送 `CONNECT <port>\n`，收到 `OK <host_port>\n` 后即转发到来宾端口
+//! - 来宾主动连接：监控程序连接宿主机上的 `<uds_path>_<port>`，无需额外握手
+//!
+//! 转发建立后帧格式与握手与 Raw 传输完全一致，来宾内使用 `TransportKind::Raw` 即可互通，
+//! 帧的收发由 `StreamTransport` 完成；cid 由套接字路径决定，被忽略。
+
+use crate::error::{Result, VirgeError};
+use crate::transport::stream_impl::{FramedStream, StreamTransport};
+use crate::transport::TransportKind;
+use async_trait::async_trait;
+use log::*;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+/// 来宾主动连接 `port` 时监控程序连接的宿主机套接字路径 `<uds_path>_<port>`
+pub(crate) fn listen_path(path: &Path, port: u32) -> PathBuf {
+    let mut listen = path.as_os_str().to_owned();
+    listen.push(format!("_{}", port));
+    PathBuf::from(listen)
+}
+
+/// `CONNECT` 应答行的最大字节数
+const MAX_REPLY_LEN: usize = 64;
+
+/// 执行监控程序的端口转发握手：发送 `CONNECT <port>\n`，等待 `OK <host_port>\n`
+///
+/// 来宾端口上没有监听者时监控程序直接关闭连接，返回 `ConnectionError`。
+async fn connect_port(stream: &mut UnixStream, port: u32) -> Result<()> {
+    stream.write_all(format!("CONNECT {}\n", port).as_bytes()).await?;
+
+    // 逐字节读取，避免把应答之后的握手数据读入行缓冲
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    while line.len() < MAX_REPLY_LEN {
+        let n = stream.read(&mut byte).await?;
+        if n == 0 {
+            return Err(VirgeError::connection_io(
+                format!("Hypervisor refused CONNECT to port {}", port),
+                std::io::Error::from(std::io::ErrorKind::ConnectionRefused),
+            ));
+        }
+        if byte[0] == b'\n' {
+            let reply = String::from_utf8_lossy(&line);
+            return match reply.strip_prefix("OK ") {
+                Some(host_port) => {
+                    debug!("UDS transport forwarded to guest port {} via host port {}", port, host_port);
+                    Ok(())
+                }
+                None => Err(VirgeError::ProtocolError(format!(
+                    "Unexpected reply to CONNECT {}: '{}'",
+                    port, reply
+                ))),
+            };
+        }
+        line.push(byte[0]);
+    }
+    Err(VirgeError::ProtocolError(format!(
+        "Reply to CONNECT {} exceeds {} bytes",
+        port, MAX_REPLY_LEN
+    )))
+}
+
+#[async_trait]
+impl FramedStream for UnixStream {
+    /// 监控程序导出的 vsock UDS 路径
+    type Target = PathBuf;
+
+    const NAME: &'static str = "UDS";
+
+    /// 以 raw 协议类型声明，与来宾内的 Raw 传输互通
+    const KIND: TransportKind = TransportKind::Raw;
+
+    async fn open(path: &PathBuf, _: u32, port: u32) -> Result<Self> {
+        info!("UDS transport connecting to port {} via {}", port, path.display());
+        let mut stream = UnixStream::connect(path)
+            .await
+            .map_err(|e| VirgeError::connection_io(format!("Failed to connect uds {}", path.display()), e))?;
+        connect_port(&mut stream, port).await?;
+        Ok(stream)
+    }
+}
+
+/// Unix 域套接字传输协议实现
+///
+/// 经由虚拟机监控程序导出的 UDS 与来宾 vsock 端口通信，消息以 4 字节大端长度前缀分隔。
+pub type UdsTransport = StreamTransport<UnixStream>;
+
+impl StreamTransport<UnixStream> {
+    /// 创建连接到 `path` 的传输实例，`path` 为监控程序配置中的 vsock `uds_path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self::with_target(path.into())
+    }
+}
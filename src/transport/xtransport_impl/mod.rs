@@ -5,15 +5,54 @@
 //! # 特点
 //! - 针对 vsock 优化的传输协议
 //! - 轻量级设计
+//!
+//! xtransport 每条消息返回独立的 `Vec<u8>`，因此 `recv_into` 使用默认实现，
+//! 只能复用调用方缓冲区，无法省去 xtransport 内部的分配。
 
 use log::*;
 use crate::error::{Result, VirgeError};
-use crate::transport::Transport;
+use crate::transport::ack::AckSamples;
+use crate::transport::{check_timeout, preamble, sys, vsock_connect_error, AckStats, Transport, TransportKind};
 use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::time::{Duration, Instant};
 
 use vsock::{VsockAddr, VsockStream};
 use xtransport::{TransportConfig, XTransport};
 
+/// `prefetch` 一次最多预读的消息数
+const PREFETCH_LIMIT: usize = 16;
+
+/// 转换 xtransport 的发送错误
+///
+/// xtransport 的错误不携带 errno，这里取失败的写调用留下的 errno 作为 `source`，
+/// 供发送重试识别 `EAGAIN`、`ENOBUFS` 等暂时性错误，调用前需先 `sys::clear_errno()`。
+/// 设置了写超时时 `EAGAIN` 表示超时到期而不是内核暂时缺少内存，与 `ETIMEDOUT` 一样返回 `Timeout`。
+fn send_error(err: impl std::fmt::Display, write_timeout: Option<Duration>) -> VirgeError {
+    let message = format!("XTransport send error: {}", err);
+    let source = match sys::last_errno() {
+        Some(errno) => io::Error::from_raw_os_error(errno),
+        None => return VirgeError::Other(message),
+    };
+    match source.kind() {
+        io::ErrorKind::TimedOut => VirgeError::Timeout(message),
+        io::ErrorKind::WouldBlock if write_timeout.is_some() => VirgeError::Timeout(message),
+        _ => VirgeError::TransportError { message, source: Some(source) },
+    }
+}
+
+/// 转换 xtransport 的接收错误
+///
+/// 与发送相同，取失败的读调用留下的 errno 还原 `io::ErrorKind`，以 `IoError` 返回，
+/// 调用方可用 `io_kind()`、`is_disconnected()` 区分连接重置与超时；调用前需先 `sys::clear_errno()`。
+/// 没有 errno 时错误来自 xtransport 自身对分块的校验，kind 为 `InvalidData`。
+fn recv_error(err: xtransport::Error) -> VirgeError {
+    let kind = sys::last_errno()
+        .map_or(io::ErrorKind::InvalidData, |errno| io::Error::from_raw_os_error(errno).kind());
+    VirgeError::IoError(io::Error::new(kind, format!("XTransport recv error: {}", err)))
+}
 
 /// XTransport 传输协议实现
 ///
@@ -21,6 +60,31 @@ use xtransport::{TransportConfig, XTransport};
 pub struct XTransportHandler {
     stream: Option<VsockStream>,
     transport: Option<XTransport<VsockStream>>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    /// 是否在初始化 xtransport 前执行握手
+    handshake: bool,
+    /// 握手中声明的各层设置（各层本身由外层包装器完成），由 `TransportOptions::hello` 生成
+    hello: preamble::Hello,
+    /// 最近一次握手中对端附带的认证令牌
+    peer_auth_token: Option<Vec<u8>>,
+    /// 连接前绑定的本地端口，`None` 由内核分配临时端口
+    local_port: Option<u32>,
+    /// 当前连接是否启用 xtransport 的 ACK，启用时 send_message 在收到确认后返回
+    is_ack: bool,
+    /// 最近若干次发送从发出到收到确认的耗时
+    ack_samples: AckSamples,
+    /// 当前连接的 ID，建立连接时分配
+    conn_id: Option<u64>,
+    /// 接收失败时对端已正常关闭了套接字，之后的 recv 返回 `EndOfStream`
+    peer_closed: bool,
+    /// `prefetch` 预读、尚未交付的完整消息，recv 先从这里取
+    received: VecDeque<Vec<u8>>,
+    /// 预读时遇到的错误，预读的消息取完后由下一次接收返回
+    read_error: Option<VirgeError>,
+    /// 当前连接的 tracing span，记录 cid、端口与连接 ID
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
 }
 
 impl XTransportHandler {
@@ -28,26 +92,208 @@ impl XTransportHandler {
         Self {
             stream: None,
             transport: None,
+            read_timeout: None,
+            write_timeout: None,
+            handshake: true,
+            hello: preamble::Hello::new(TransportKind::XTransport.to_byte(), 0, false),
+            peer_auth_token: None,
+            local_port: None,
+            is_ack: false,
+            ack_samples: AckSamples::default(),
+            conn_id: None,
+            peer_closed: false,
+            received: VecDeque::new(),
+            read_error: None,
+            #[cfg(feature = "tracing")]
+            span: tracing::Span::none(),
         }
     }
-}
 
-#[async_trait]
-impl Transport for XTransportHandler {
-    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
-        info!("XTransport connecting to cid={}, port={}", cid, port);
+    /// 设置是否执行连接握手，关闭后可与未握手的旧版本互通
+    pub fn with_handshake(mut self, enabled: bool) -> Self {
+        self.handshake = enabled;
+        self
+    }
 
-        let stream = VsockStream::connect(&VsockAddr::new(cid, port))
-            .map_err(|e| VirgeError::ConnectionError(format!("Failed to connect vsock: {}", e)))?;
+    /// 设置握手中声明的各层设置，由 `TransportOptions` 按叠加的包装器生成
+    pub(crate) fn with_hello(mut self, hello: preamble::Hello) -> Self {
+        self.hello = hello;
+        self
+    }
 
-        // 初始化 xtransport
+    /// 包装为按 `plan` 注入故障的传输
+    #[cfg(feature = "testing")]
+    pub fn wrap_with_faults(self, plan: crate::transport::FaultPlan) -> crate::transport::FaultyTransport {
+        crate::transport::FaultyTransport::new(self, plan)
+    }
+
+    /// 设置连接前绑定的本地端口，由 `TransportOptions` 同步设置
+    pub(crate) fn with_local_port(mut self, port: Option<u32>) -> Self {
+        self.local_port = port;
+        self
+    }
+
+    /// 建立 vsock 连接，设置了本地端口时先绑定该端口；超时返回 `ErrorKind::TimedOut`
+    fn connect_stream(&self, cid: u32, port: u32, timeout: Option<Duration>) -> io::Result<VsockStream> {
+        let Some(local_port) = self.local_port else {
+            let addr = VsockAddr::new(cid, port);
+            return match timeout {
+                Some(timeout) => VsockStream::connect_timeout(&addr, timeout),
+                None => VsockStream::connect(&addr),
+            };
+        };
+        let fd = sys::connect_vsock_from(local_port, cid, port, timeout)?;
+        // SAFETY: 描述符为已连接的阻塞模式 vsock 套接字，所有权转移给流
+        Ok(unsafe { VsockStream::from_raw_fd(fd.into_raw_fd()) })
+    }
+
+    /// 按配置执行握手，校验双方的协议版本、类型与 chunk_size/ACK 设置
+    ///
+    /// # Returns
+    /// 返回协商后的 chunk_size，未启用握手时为本端的设置
+    fn handshake(&mut self, stream: &mut VsockStream, chunksize: u32, isack: bool) -> Result<u32> {
+        self.peer_auth_token = None;
+        if !self.handshake {
+            return Ok(chunksize);
+        }
+        let hello = self.hello.clone().with_chunk_size(chunksize).with_ack(isack);
+        let negotiated = preamble::handshake_sync(stream, &hello)?;
+        if negotiated.chunk_size != chunksize {
+            info!("XTransport adopting peer chunk size {} instead of {}", negotiated.chunk_size, chunksize);
+        }
+        self.peer_auth_token = negotiated.peer_auth_token;
+        Ok(negotiated.chunk_size)
+    }
+
+    /// 基于已建立的 vsock 流初始化 xtransport
+    fn init_transport(&mut self, stream: VsockStream, chunksize: u32, isack: bool) -> Result<()> {
         let config = TransportConfig::default()
             .with_max_frame_size(chunksize as usize)
             .with_ack(isack);
         let transport = XTransport::new(stream.try_clone()?, config);
 
+        // 套接字超时作用于同一 fd，对 xtransport 持有的克隆流同样生效
+        stream.set_read_timeout(self.read_timeout)?;
+        stream.set_write_timeout(self.write_timeout)?;
+
         self.stream = Some(stream);
         self.transport = Some(transport);
+        self.is_ack = isack;
+        self.ack_samples.clear();
+        self.peer_closed = false;
+        self.received.clear();
+        self.read_error = None;
+        let conn_id = crate::transport::next_connection_id();
+        self.conn_id = Some(conn_id);
+        #[cfg(feature = "tracing")]
+        {
+            let (cid, port) = match self.stream.as_ref().and_then(|stream| stream.peer_addr().ok()) {
+                Some(addr) => (addr.cid(), addr.port()),
+                None => (0, 0),
+            };
+            self.span = tracing::info_span!("virga_connection", conn_id, cid, port);
+            tracing::info!(parent: &self.span, chunksize, isack, "xtransport connection established");
+        }
+        Ok(())
+    }
+
+    /// 在连接的 span 中记录一次操作的字节数与耗时，失败时记录错误
+    #[cfg(feature = "tracing")]
+    fn trace<T>(&self, op: &'static str, started: Instant, bytes: usize, result: &Result<T>) {
+        let elapsed_us = started.elapsed().as_micros() as u64;
+        match result {
+            Ok(_) => tracing::debug!(parent: &self.span, op, bytes, elapsed_us, "xtransport operation"),
+            Err(e) => tracing::warn!(parent: &self.span, op, elapsed_us, error = %e, "xtransport operation failed"),
+        }
+    }
+
+    /// 丢弃当前连接状态，使实例可以重新连接
+    fn reset(&mut self) {
+        self.transport = None;
+        self.stream = None;
+        self.conn_id = None;
+        self.peer_closed = false;
+        self.received.clear();
+        self.read_error = None;
+        #[cfg(feature = "tracing")]
+        {
+            self.span = tracing::Span::none();
+        }
+    }
+
+    /// 从套接字接收一条完整的消息，对端已关闭时标记并返回 `EndOfStream`
+    fn recv_message(&mut self) -> Result<Vec<u8>> {
+        let transport = self.transport.as_mut()
+            .ok_or_else(|| VirgeError::Disconnected("XTransport not connected".to_string()))?;
+
+        #[cfg(feature = "tracing")]
+        let started = Instant::now();
+        sys::clear_errno();
+        let result = transport.recv_message().map_err(recv_error);
+        #[cfg(feature = "tracing")]
+        self.trace("recv", started, result.as_ref().map_or(0, Vec::len), &result);
+        let data = match result {
+            Ok(data) => data,
+            // xtransport 没有关闭帧，以套接字上读到字节流结束作为对端正常关闭的信号
+            Err(_) if self.stream.as_ref().is_some_and(|stream| sys::peer_closed(stream.as_raw_fd())) => {
+                info!("XTransport peer closed the connection");
+                self.peer_closed = true;
+                return Err(VirgeError::EndOfStream);
+            }
+            Err(e) => return Err(e),
+        };
+
+        info!("XTransport received {} bytes", data.len());
+        Ok(data)
+    }
+
+    /// 取出预读的消息，取完后返回预读时遇到的错误
+    fn take_received(&mut self) -> Option<Result<Vec<u8>>> {
+        self.received.pop_front().map(Ok).or_else(|| self.read_error.take().map(Err))
+    }
+}
+
+#[async_trait]
+impl Transport for XTransportHandler {
+    async fn connect(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool) -> Result<()> {
+        info!("XTransport connecting to cid={}, port={}", cid, port);
+        self.reset();
+
+        let mut stream = self.connect_stream(cid, port, None)
+            .map_err(|e| vsock_connect_error(self.local_port, e))?;
+        let chunksize = self.handshake(&mut stream, chunksize, isack)?;
+
+        // 初始化 xtransport
+        self.init_transport(stream, chunksize, isack)?;
+
+        info!("XTransport connected successfully");
+        Ok(())
+    }
+
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        info!("XTransport connecting to cid={}, port={} with timeout {:?}", cid, port, timeout);
+        self.reset();
+
+        let deadline = Instant::now() + timeout;
+        let mut stream = self.connect_stream(cid, port, Some(timeout))
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::TimedOut => VirgeError::Timeout(format!("XTransport connect timed out: {}", e)),
+                _ => vsock_connect_error(self.local_port, e),
+            })?;
+
+        // 握手阶段同样受剩余时间约束，完成后恢复为用户设置的超时
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(VirgeError::Timeout("XTransport connect timed out".to_string()));
+        }
+        stream.set_read_timeout(Some(remaining))?;
+        stream.set_write_timeout(Some(remaining))?;
+        let chunksize = self.handshake(&mut stream, chunksize, isack)?;
+
+        if let Err(e) = self.init_transport(stream, chunksize, isack) {
+            self.reset();
+            return Err(e);
+        }
 
         info!("XTransport connected successfully");
         Ok(())
@@ -55,56 +301,272 @@ impl Transport for XTransportHandler {
 
     async fn disconnect(&mut self) -> Result<()> {
         info!("XTransport disconnecting");
-        
+        #[cfg(feature = "tracing")]
+        let started = Instant::now();
+
         self.transport = None;
-        if let Some(stream) = &self.stream {
-            stream.shutdown(std::net::Shutdown::Both).map_err(
-                |e| VirgeError::ConnectionError(format!("Failed to disconnect vsock: {}", e))
-            )?;
-        }
+        let result = match &self.stream {
+            Some(stream) => stream.shutdown(std::net::Shutdown::Both)
+                .map_err(|e| VirgeError::connection_io("Failed to disconnect vsock", e)),
+            None => Ok(()),
+        };
+        #[cfg(feature = "tracing")]
+        self.trace("disconnect", started, 0, &result);
+        result?;
 
         info!("XTransport disconnected");
         Ok(())
     }
 
+    /// 一条消息的全部分块由 `send_message` 连续写出后才返回
+    ///
+    /// `&mut self` 保证同一时刻只有一个发送方，多个任务共用连接时须经由 `VirgeClientHandle` 或
+    /// `VirgeServerHandle`，句柄在整条消息发送期间持有锁，不同消息的分块不会在线路上交错。
+    /// 分块头由 xtransport 库定义，接收端的重组与校验也由其完成。
     async fn send(&mut self, data: Vec<u8>) -> Result<()> {
         let transport = self.transport.as_mut()
-            .ok_or_else(|| VirgeError::TransportError("XTransport not connected".to_string()))?;
+            .ok_or_else(|| VirgeError::Disconnected("XTransport not connected".to_string()))?;
 
-        transport.send_message(&data)
-            .map_err(|e| VirgeError::Other(format!("XTransport send error: {}", e)))?;
+        let started = Instant::now();
+        sys::clear_errno();
+        let result = transport.send_message(&data).map_err(|e| send_error(e, self.write_timeout));
+        #[cfg(feature = "tracing")]
+        self.trace("send", started, data.len(), &result);
+        result?;
+        if self.is_ack {
+            self.ack_samples.record(started.elapsed());
+        }
 
         info!("XTransport sent {} bytes", data.len());
         Ok(())
     }
 
     async fn recv(&mut self) -> Result<Vec<u8>> {
-        let transport = self.transport.as_mut()
-            .ok_or_else(|| VirgeError::TransportError("XTransport not connected".to_string()))?;
+        if let Some(received) = self.take_received() {
+            return received;
+        }
+        if self.peer_closed {
+            return Err(VirgeError::EndOfStream);
+        }
+        // 先等待数据到达，超时不会读走任何字节，连接保持可用
+        if let (Some(timeout), Some(stream)) = (self.read_timeout, &self.stream) {
+            if !sys::wait_readable(stream.as_raw_fd(), Some(timeout))? {
+                return Err(VirgeError::Timeout("XTransport recv timed out".to_string()));
+            }
+        }
+        self.recv_message()
+    }
 
-        let data = transport.recv_message()
-            .map_err(|e| VirgeError::Other(format!("XTransport recv error: {}", e)))?;
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        if let Some(received) = self.take_received() {
+            return received.map(Some);
+        }
+        let stream = self.stream.as_ref()
+            .ok_or_else(|| VirgeError::Disconnected("XTransport not connected".to_string()))?;
 
-        info!("XTransport received {} bytes", data.len());
-        Ok(data)
+        // 仅在已有数据到达时才进入阻塞的消息接收
+        if !sys::wait_readable(stream.as_raw_fd(), Some(Duration::ZERO))? {
+            return Ok(None);
+        }
+        self.recv().await.map(Some)
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        let stream = self.stream.as_ref()
+            .ok_or_else(|| VirgeError::Disconnected("XTransport not connected".to_string()))?;
+
+        if !sys::wait_writable(stream.as_raw_fd(), Some(Duration::ZERO))? {
+            return Ok(None);
+        }
+        self.send(data.to_vec()).await?;
+        Ok(Some(data.len()))
+    }
+
+    /// xtransport 的发送在数据写入套接字后才返回，这里只冲刷 vsock 流本身
+    async fn flush(&mut self) -> Result<()> {
+        let stream = self.stream.as_mut()
+            .ok_or_else(|| VirgeError::Disconnected("XTransport not connected".to_string()))?;
+        stream.flush().map_err(|e| VirgeError::connection_io("Failed to flush vsock", e))
     }
 
     fn is_connected(&self) -> bool {
-        self.stream.is_some() && self.transport.is_some()
+        self.stream.is_some() && self.transport.is_some() && !self.peer_closed
+    }
+
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.stream.as_ref().filter(|_| self.transport.is_some()).map(|stream| stream.as_raw_fd())
+    }
+
+    fn connection_id(&self) -> Option<u64> {
+        self.conn_id
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.stream.as_ref().filter(|_| self.transport.is_some()).and_then(|stream| stream.local_addr().ok())
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        !self.received.is_empty() || self.read_error.is_some()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.received.iter().map(Vec::len).sum()
+    }
+
+    /// 套接字可读时逐条接收消息放入预读队列，已开始到达的消息会等待其余分块
+    async fn prefetch(&mut self) -> Result<()> {
+        while self.received.len() < PREFETCH_LIMIT && self.read_error.is_none() && !self.peer_closed {
+            let Some(stream) = &self.stream else {
+                break;
+            };
+            if !sys::wait_readable(stream.as_raw_fd(), Some(Duration::ZERO))? {
+                break;
+            }
+            match self.recv_message() {
+                Ok(data) => self.received.push_back(data),
+                // 先交付已预读的消息，错误留给之后的接收
+                Err(e) => self.read_error = Some(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn last_ack_latency(&self) -> Option<Duration> {
+        self.ack_samples.last()
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.peer_auth_token.as_deref()
+    }
+
+    fn ack_stats(&self) -> Option<AckStats> {
+        self.ack_samples.stats()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        check_timeout(timeout)?;
+        // 套接字级超时兜底消息中途停滞的情况，此时连接状态不再可靠
+        if let Some(stream) = &self.stream {
+            stream.set_read_timeout(timeout)?;
+        }
+        self.read_timeout = timeout;
+        Ok(())
     }
 
-    async fn from_stream(&mut self, stream: VsockStream, chunksize: u32, isack: bool) -> Result<()> {
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        check_timeout(timeout)?;
+        if let Some(stream) = &self.stream {
+            stream.set_write_timeout(timeout)?;
+        }
+        self.write_timeout = timeout;
+        Ok(())
+    }
+
+    async fn from_stream(&mut self, mut stream: VsockStream, chunksize: u32, isack: bool) -> Result<()> {
         info!("XTransport initializing from existing stream");
 
-        let config = TransportConfig::default()
-            .with_max_frame_size(chunksize as usize)
-            .with_ack(isack);
-        let transport = XTransport::new(stream.try_clone()?, config);
+        let chunksize = self.handshake(&mut stream, chunksize, isack)?;
 
-        self.stream = Some(stream);
-        self.transport = Some(transport);
+        self.init_transport(stream, chunksize, isack)?;
 
         info!("XTransport initialized from stream successfully");
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::os::unix::net::UnixStream;
+
+    /// 向无人读取的套接字持续写入，直到写调用失败，失败的写调用留下的 errno 保留给 `send_error`
+    fn fill(stream: &mut UnixStream) -> io::Error {
+        let block = [0u8; 64 * 1024];
+        sys::clear_errno();
+        loop {
+            if let Err(e) = stream.write(&block) {
+                return e;
+            }
+        }
+    }
+
+    #[test]
+    fn send_timeout_maps_to_timeout() {
+        let (mut stream, _peer) = UnixStream::pair().unwrap();
+        let timeout = Duration::from_millis(20);
+        stream.set_write_timeout(Some(timeout)).unwrap();
+
+        let err = fill(&mut stream);
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        let err = send_error("chunk write failed", Some(timeout));
+        assert!(matches!(err, VirgeError::Timeout(_)), "{:?}", err);
+        assert!(err.is_timeout() && err.is_retryable());
+    }
+
+    #[test]
+    fn eagain_without_write_timeout_keeps_the_errno() {
+        let (mut stream, _peer) = UnixStream::pair().unwrap();
+        stream.set_nonblocking(true).unwrap();
+
+        // 未设置写超时时 EAGAIN 是内核暂时缺少缓冲区，原始 errno 留给发送重试识别
+        fill(&mut stream);
+        let err = send_error("chunk write failed", None);
+        let source = std::error::Error::source(&err).and_then(|source| source.downcast_ref::<io::Error>());
+        assert_eq!(source.and_then(io::Error::raw_os_error), Some(libc::EAGAIN));
+    }
+
+    #[test]
+    fn closed_peer_keeps_the_error_kind() {
+        let (mut stream, peer) = UnixStream::pair().unwrap();
+        drop(peer);
+
+        fill(&mut stream);
+        let err = send_error("chunk write failed", Some(Duration::from_millis(20)));
+        assert_eq!(err.io_kind(), Some(io::ErrorKind::BrokenPipe));
+        assert!(err.is_disconnected());
+
+        sys::clear_errno();
+        let err = send_error("chunk header rejected", None);
+        assert!(matches!(err, VirgeError::Other(_)), "{:?}", err);
+    }
+
+    /// 以 SO_LINGER 0 关闭套接字，内核向对端发送 RST 而不是 FIN
+    fn close_with_reset(stream: TcpStream) {
+        let linger = libc::linger { l_onoff: 1, l_linger: 0 };
+        let ret = unsafe {
+            libc::setsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_LINGER,
+                &linger as *const libc::linger as *const libc::c_void,
+                std::mem::size_of::<libc::linger>() as libc::socklen_t,
+            )
+        };
+        assert_eq!(ret, 0, "{}", io::Error::last_os_error());
+    }
+
+    #[test]
+    fn reset_peer_is_recoverable_through_source() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        close_with_reset(listener.accept().unwrap().0);
+
+        // 收到 RST 后的第一次写调用以 ECONNRESET 失败
+        sys::clear_errno();
+        let err = loop {
+            if let Err(e) = stream.write(&[0u8; 1024]) {
+                break e;
+            }
+        };
+        assert_eq!(err.raw_os_error(), Some(libc::ECONNRESET));
+
+        let err = send_error("chunk write failed", None);
+        let source = std::error::Error::source(&err)
+            .and_then(|source| source.downcast_ref::<io::Error>())
+            .expect("the errno is kept as the source");
+        assert_eq!(source.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(source.raw_os_error(), Some(libc::ECONNRESET));
+        assert!(err.is_disconnected() && err.is_retryable());
+    }
+}
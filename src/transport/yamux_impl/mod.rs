@@ -6,25 +6,39 @@
 //! - 支持多个独立的虚拟流
 //! - 适合多并发场景
 //! - 由 libp2p 社区维护
+//! - 每个连接复用同一个双向虚拟流，仅在 disconnect 时关闭
+//! - 可通过 open_stream/accept_stream 在同一连接上建立额外的独立虚拟流（[`VirgeStream`]）
+//!
+//! # 帧格式
+//! 消息边界由流上的 4 字节大端长度前缀划分，与 raw 传输一致，不依赖流 EOF。
 //!
 //! # 结构
 //! ```text
-//! ┌─────────────────────────────────┐
-//! │ YamuxTransport                  │
-//! │ - connection: Option<Connection>│
-//! │ - yamux_stream: Option<Stream>  │
-//! └─────────────────────────────────┘
+//! ┌─────────────────────────────────┐        ┌──────────────────────────┐
+//! │ YamuxTransport                  │ 命令   │ 驱动任务 (tokio task)    │
+//! │ - driver: Option<Driver>        │ ─────▶ │ - 独占 Connection        │
+//! │ - yamux_stream: Option<Stream>  │ ◀───── │ - 持续轮询入站帧与心跳   │
+//! │ - read_buffer: StreamBuffer     │ 入站流 │ - 按请求打开出站流       │
+//! └─────────────────────────────────┘        └──────────────────────────┘
 //! ```
 
 use crate::error::{Result, VirgeError};
-use crate::transport::Transport;
+use crate::transport::bufpool::BufferPool;
+use crate::transport::{check_timeout, connect_tokio_vsock, framing, preamble, Transport, TransportKind};
 use async_trait::async_trait;
 use futures::future::poll_fn;
 use futures::AsyncReadExt;
 use futures::AsyncWriteExt;
+use futures::FutureExt;
+use std::collections::VecDeque;
+use std::io::IoSlice;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio_util::compat::{TokioAsyncReadCompatExt, Compat};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::compat::{Compat, FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt};
 use tokio_vsock::{VsockStream, VsockAddr};
 use log::*;
 
@@ -38,134 +52,556 @@ use yamux::Stream;
 /// Yamux需要持续的驱动程序来处理入站流和连接生命周期。
 pub struct YamuxTransport {
     yamux_stream: Option<Stream>,
-    connection: Option<Arc<Mutex<Connection<Compat<VsockStream>>>>>,
-    driver_handle: Option<tokio::task::JoinHandle<()>>,
-    is_server: bool,
+    driver: Option<Driver>,
+    config: YamuxConfig,
+    /// 连接模式：客户端打开出站流，服务器接受客户端打开的入站流
+    mode: Mode,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    /// 是否在初始化 yamux 前执行握手
+    handshake: bool,
+    /// 握手中声明的各层设置（各层本身由外层包装器完成），由 `TransportOptions::hello` 生成
+    hello: preamble::Hello,
+    /// 最近一次握手中对端附带的认证令牌
+    peer_auth_token: Option<Vec<u8>>,
+    /// 最近一次握手协商出的 ACK 窗口，未执行握手时为 `None`
+    negotiated_window: Option<u32>,
+    /// 已从流中读取但尚未组成完整消息的数据，同时限制单条消息的最大字节数
+    read_buffer: framing::StreamBuffer,
+    /// 服务器等待主虚拟流期间收到的其他入站流，供 accept_stream 取用
+    pending_streams: VecDeque<Stream>,
+    /// 底层 vsock 套接字的描述符，套接字由驱动任务独占读取
+    fd: Option<RawFd>,
+    /// 底层 vsock 套接字的本地地址，连接建立时记录
+    local_addr: Option<VsockAddr>,
+    /// 连接前绑定的本地端口，`None` 由内核分配临时端口
+    local_port: Option<u32>,
+}
+
+type YamuxConnection = Connection<Compat<VsockStream>>;
+
+/// yamux 为每个流预留的最小接收窗口（256 KiB）
+const YAMUX_MIN_STREAM_WINDOW: usize = 256 * 1024;
+/// yamux 默认的连接级接收窗口上限（1 GiB）
+const YAMUX_DEFAULT_CONNECTION_WINDOW: usize = 1024 * 1024 * 1024;
+/// yamux 默认的最大流数量
+const YAMUX_DEFAULT_MAX_NUM_STREAMS: usize = 512;
+
+/// Yamux 协议参数
+///
+/// 未设置的项沿用 yamux 默认值。yamux 会自动调整每个流的缓冲区大小，
+/// 因此这里只暴露连接级接收窗口；yamux 也不提供心跳，因此没有 keep-alive 开关。
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct YamuxConfig {
+    receive_window: Option<usize>,
+    max_num_streams: Option<usize>,
+    split_send_size: Option<usize>,
+    read_after_close: Option<bool>,
+}
+
+impl YamuxConfig {
+    /// 设置连接级接收窗口上限（字节），所有流共享
+    ///
+    /// 不得小于 `max_num_streams * 256 KiB`，否则连接时返回 `ConfigError`。
+    pub fn with_receive_window(mut self, size: usize) -> Self {
+        self.receive_window = Some(size);
+        self
+    }
+
+    /// 设置单个连接上允许的最大流数量
+    pub fn with_max_num_streams(mut self, max: usize) -> Self {
+        self.max_num_streams = Some(max);
+        self
+    }
+
+    /// 设置单个数据帧的最大负载字节数，较大的写入会被拆分
+    pub fn with_split_send_size(mut self, size: usize) -> Self {
+        self.split_send_size = Some(size);
+        self
+    }
+
+    /// 设置对端关闭流后是否仍允许读取已缓冲的数据
+    pub fn with_read_after_close(mut self, enabled: bool) -> Self {
+        self.read_after_close = Some(enabled);
+        self
+    }
+
+    /// 校验参数并构造 yamux 配置，yamux 内部对非法组合直接 panic，因此需事先检查
+    fn build(&self) -> Result<Config> {
+        let max_num_streams = self.max_num_streams.unwrap_or(YAMUX_DEFAULT_MAX_NUM_STREAMS);
+        let receive_window = self.receive_window.unwrap_or(YAMUX_DEFAULT_CONNECTION_WINDOW);
+        if max_num_streams == 0 {
+            return Err(VirgeError::ConfigError("Yamux max_num_streams must be non-zero".to_string()));
+        }
+        let min_window = max_num_streams.saturating_mul(YAMUX_MIN_STREAM_WINDOW);
+        if receive_window < min_window {
+            return Err(VirgeError::ConfigError(format!(
+                "Yamux receive window {} is smaller than the minimum {} for {} streams",
+                receive_window, min_window, max_num_streams
+            )));
+        }
+        if self.split_send_size == Some(0) {
+            return Err(VirgeError::ConfigError("Yamux split_send_size must be non-zero".to_string()));
+        }
+
+        let mut config = Config::default();
+        if self.receive_window.is_some() || self.max_num_streams.is_some() {
+            // 先放开窗口上限，避免中间状态触发 yamux 的断言
+            config.set_max_connection_receive_window(None);
+            config.set_max_num_streams(max_num_streams);
+            config.set_max_connection_receive_window(Some(receive_window));
+        }
+        if let Some(size) = self.split_send_size {
+            config.set_split_send_size(size);
+        }
+        if let Some(enabled) = self.read_after_close {
+            config.set_read_after_close(enabled);
+        }
+        Ok(config)
+    }
+}
+
+/// 主虚拟流的 ID：客户端在连接时首先打开出站流，yamux 为客户端分配的首个流 ID 为 1
+const MAIN_STREAM_ID: u32 = 1;
+
+/// 同一 yamux 连接上的独立虚拟流
+///
+/// 实现 tokio 的 `AsyncRead`/`AsyncWrite`，与主连接及其他虚拟流互不影响，
+/// 可移动到不同任务中并发读写；连接的驱动任务在后台持续运行，任一虚拟流都不会阻塞其他流。
+/// 虚拟流上不做消息分帧，按字节流读写。
+pub struct VirgeStream {
+    inner: Compat<Stream>,
+}
+
+impl VirgeStream {
+    fn new(stream: Stream) -> Self {
+        Self { inner: stream.compat() }
+    }
+
+    /// yamux 流 ID
+    pub fn id(&self) -> u32 {
+        self.inner.get_ref().id().val()
+    }
+}
+
+impl tokio::io::AsyncRead for VirgeStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        tokio::io::AsyncRead::poll_read(Pin::new(&mut self.inner), cx, buf)
+    }
+}
+
+impl tokio::io::AsyncWrite for VirgeStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        tokio::io::AsyncWrite::poll_write(Pin::new(&mut self.inner), cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        tokio::io::AsyncWrite::poll_flush(Pin::new(&mut self.inner), cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        tokio::io::AsyncWrite::poll_shutdown(Pin::new(&mut self.inner), cx)
+    }
+}
+
+/// 发送给驱动任务的命令
+enum DriverCommand {
+    /// 打开一个出站流，结果通过 oneshot 返回
+    OpenStream(oneshot::Sender<std::result::Result<Stream, yamux::ConnectionError>>),
+}
+
+/// yamux 连接驱动任务的句柄
+///
+/// 驱动任务独占 `Connection` 并持续轮询，保证心跳、窗口更新与入站帧得到及时处理；
+/// 虚拟流本身可在任务之外独立读写。释放句柄即通知驱动任务优雅关闭连接。
+struct Driver {
+    commands: mpsc::UnboundedSender<DriverCommand>,
+    inbound: mpsc::UnboundedReceiver<Stream>,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+impl Driver {
+    fn spawn(connection: YamuxConnection) -> Self {
+        let (commands, command_rx) = mpsc::unbounded_channel();
+        let (inbound_tx, inbound) = mpsc::unbounded_channel();
+        let handle = tokio::spawn(drive(connection, command_rx, inbound_tx));
+        Self { commands, inbound, handle }
+    }
+
+    /// 通过驱动任务打开出站流
+    async fn open_stream(&self) -> Result<Stream> {
+        let (reply, result) = oneshot::channel();
+        self.commands.send(DriverCommand::OpenStream(reply))
+            .map_err(|_| driver_stopped())?;
+        let stream = result.await
+            .map_err(|_| driver_stopped())?
+            .map_err(|e| VirgeError::transport(format!("Failed to open yamux stream: {}", e)))?;
+        info!("Opened outbound stream: {:?}", stream.id());
+        Ok(stream)
+    }
+
+    /// 等待驱动任务转发的下一个入站流
+    async fn accept_stream(&mut self) -> Result<Stream> {
+        let stream = self.inbound.recv().await.ok_or_else(driver_stopped)?;
+        info!("Accepted inbound stream: {:?}", stream.id());
+        Ok(stream)
+    }
+
+    fn is_running(&self) -> bool {
+        !self.handle.is_finished()
+    }
+
+    /// 通知驱动任务关闭连接并等待其退出
+    async fn shutdown(self) {
+        let Driver { commands, inbound, handle } = self;
+        drop(commands);
+        drop(inbound);
+        if let Err(e) = handle.await {
+            debug!("Yamux connection driver join error: {}", e);
+        }
+    }
+}
+
+/// 驱动任务主循环：处理命令、转发入站流，命令通道关闭后优雅关闭连接
+async fn drive(
+    mut connection: YamuxConnection,
+    mut commands: mpsc::UnboundedReceiver<DriverCommand>,
+    inbound: mpsc::UnboundedSender<Stream>,
+) {
+    debug!("Starting yamux connection driver");
+    let mut pending_open = VecDeque::new();
+
+    let result = poll_fn(|cx| {
+        loop {
+            match commands.poll_recv(cx) {
+                Poll::Ready(Some(DriverCommand::OpenStream(reply))) => pending_open.push_back(reply),
+                // 所有命令发送端都已释放，即传输层请求关闭连接
+                Poll::Ready(None) => return Poll::Ready(Ok(true)),
+                Poll::Pending => break,
+            }
+        }
+
+        while !pending_open.is_empty() {
+            match connection.poll_new_outbound(cx) {
+                Poll::Ready(result) => {
+                    if let Some(reply) = pending_open.pop_front() {
+                        let _ = reply.send(result);
+                    }
+                }
+                Poll::Pending => break,
+            }
+        }
+
+        loop {
+            match connection.poll_next_inbound(cx) {
+                Poll::Ready(Some(Ok(stream))) => {
+                    // 接收端已释放时直接丢弃该流
+                    let _ = inbound.send(stream);
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => return Poll::Ready(Ok(false)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }).await;
+
+    match result {
+        Ok(true) => {
+            if let Err(e) = poll_fn(|cx| connection.poll_close(cx)).await {
+                debug!("Yamux connection close error: {}", e);
+            }
+        }
+        Ok(false) => debug!("Yamux connection closed by peer"),
+        Err(e) => debug!("Yamux connection error: {}", e),
+    }
+    info!("Yamux connection driver stopped");
+}
+
+fn not_initialized() -> VirgeError {
+    VirgeError::transport("Yamux not initialized")
+}
+
+fn driver_stopped() -> VirgeError {
+    VirgeError::transport("Yamux connection driver stopped")
+}
+
+/// 在可选的超时时间内执行 IO 操作，超时返回 `ErrorKind::TimedOut`，转换为 `VirgeError::Timeout`
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    fut: impl Future<Output = std::io::Result<T>>,
+) -> std::io::Result<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, fut).await.unwrap_or_else(|_| {
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Yamux operation timed out"))
+        }),
+        None => fut.await,
+    }
+}
+
+/// 单次从流中读取的字节数
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+fn unexpected_eof() -> VirgeError {
+    VirgeError::IoError(std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        "Yamux stream closed by peer",
+    ))
 }
 
 impl YamuxTransport {
     /// 创建客户端模式的 Yamux 传输实例
     pub fn new_client() -> Self {
         Self {
-            connection: None,
             yamux_stream: None,
-            driver_handle: None,
-            is_server: false,
+            driver: None,
+            config: YamuxConfig::default(),
+            mode: Mode::Client,
+            read_timeout: None,
+            write_timeout: None,
+            handshake: true,
+            hello: preamble::Hello::without_chunk_size(TransportKind::Yamux.to_byte(), false),
+            peer_auth_token: None,
+            negotiated_window: None,
+            read_buffer: framing::StreamBuffer::new(crate::DEFAULT_MAX_MESSAGE_SIZE),
+            pending_streams: VecDeque::new(),
+            fd: None,
+            local_addr: None,
+            local_port: None,
         }
     }
 
     /// 创建服务器模式的 Yamux 传输实例
     pub fn new_server() -> Self {
         Self {
-            connection: None,
             yamux_stream: None,
-            driver_handle: None,
-            is_server: true,
+            driver: None,
+            config: YamuxConfig::default(),
+            mode: Mode::Server,
+            read_timeout: None,
+            write_timeout: None,
+            handshake: true,
+            hello: preamble::Hello::without_chunk_size(TransportKind::Yamux.to_byte(), false),
+            peer_auth_token: None,
+            negotiated_window: None,
+            read_buffer: framing::StreamBuffer::new(crate::DEFAULT_MAX_MESSAGE_SIZE),
+            pending_streams: VecDeque::new(),
+            fd: None,
+            local_addr: None,
+            local_port: None,
         }
     }
 
+    /// 设置 yamux 协议参数，在下一次连接时生效
+    pub fn with_config(mut self, config: YamuxConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// 设置单条消息的最大字节数，超限的发送在本地失败，超限的接收返回 `MessageTooLarge` 且连接保持可用
+    pub fn with_max_message_size(mut self, size: usize) -> Self {
+        self.read_buffer.set_max(size);
+        self
+    }
+
+    /// 设置接收缓冲区池至多保存的空闲读缓冲区数，0 表示每次接收都新分配，由 `TransportOptions` 同步设置
+    pub(crate) fn with_buffer_pool_size(mut self, size: usize) -> Self {
+        self.read_buffer.set_pool_size(size);
+        self
+    }
+
+    /// 设置是否执行连接握手，关闭后可与未握手的旧版本互通
+    pub fn with_handshake(mut self, enabled: bool) -> Self {
+        self.handshake = enabled;
+        self
+    }
+
+    /// 设置握手中声明的各层设置，由 `TransportOptions` 按叠加的包装器生成
+    pub(crate) fn with_hello(mut self, hello: preamble::Hello) -> Self {
+        self.hello = hello;
+        self
+    }
+
+    /// 设置客户端连接前绑定的本地端口，由 `TransportOptions` 同步设置
+    pub(crate) fn with_local_port(mut self, port: Option<u32>) -> Self {
+        self.local_port = port;
+        self
+    }
+
+    /// 按配置执行握手，校验双方的协议版本、类型与 ACK 设置
+    async fn handshake(&mut self, stream: &mut VsockStream) -> Result<()> {
+        self.negotiated_window = None;
+        self.peer_auth_token = None;
+        if !self.handshake {
+            return Ok(());
+        }
+        let negotiated = preamble::handshake_async(stream, &self.hello).await?;
+        self.negotiated_window = Some(negotiated.ack_window);
+        self.peer_auth_token = negotiated.peer_auth_token;
+        Ok(())
+    }
+
+    fn not_connected(what: &str) -> VirgeError {
+        VirgeError::Disconnected(format!("Yamux transport not connected about {}", what))
+    }
+
     /// 获取或创建 yamux 虚拟流
+    ///
+    /// 客户端在连接时打开出站流；服务器在首次收发时接受客户端打开的入站流。
     async fn get_or_create_stream(&mut self) -> Result<&mut Stream> {
         if self.yamux_stream.is_none() {
-            if self.is_server {
-                // 服务器模式：等待从驱动程序接收入站流
-                if let Some(connection_arc) = self.connection.clone() {
-                    let mut conn_guard = connection_arc.lock().await;
-                    let stream = poll_fn(|cx| conn_guard.poll_next_inbound(cx)).await;
-                    match stream {
-                        Some(Ok(yamux_stream)) => {
-                            self.yamux_stream = Some(yamux_stream);
-                        }
-                        Some(Err(e)) => {
-                            return Err(VirgeError::TransportError(format!("Failed to open yamux stream: {}", e)));
-                        }
-                        None => {
-                            return Err(VirgeError::TransportError("Failed to open yamux stream".to_string()));
-                        }
+            let driver = self.driver.as_mut().ok_or_else(not_initialized)?;
+            let stream = match self.mode {
+                // 入站流的到达顺序不确定，按流 ID 识别主虚拟流，其余留给 accept_stream
+                Mode::Server => loop {
+                    let stream = driver.accept_stream().await?;
+                    if stream.id().val() == MAIN_STREAM_ID {
+                        break stream;
                     }
-                } else {
-                    return Err(VirgeError::TransportError("Yamux not initialized".to_string()));
-                }
-            } else {
-                // 客户端模式：创建出站流
-                if let Some(connection_arc) = self.connection.clone() {
-                    let mut conn_guard = connection_arc.lock().await;
-                    let stream = poll_fn(|cx| conn_guard.poll_new_outbound(cx)).await
-                        .map_err(|e| VirgeError::TransportError(format!("Failed to open yamux stream: {}", e)))?;
-                    info!("Client created outbound stream: {:?}", stream.id());
-                    self.yamux_stream = Some(stream);
-                } else {
-                    return Err(VirgeError::TransportError("Yamux not initialized".to_string()));
-                }
-            }
+                    self.pending_streams.push_back(stream);
+                },
+                Mode::Client => driver.open_stream().await?,
+            };
+            self.yamux_stream = Some(stream);
         }
 
         Ok(self.yamux_stream.as_mut().unwrap())
     }
 
-    /// yamux 连接驱动程序
-    fn start_driver(&mut self) {
-        if let Some(conn_arc) = self.connection.clone() {
-            let driver_handle = tokio::spawn(async move {
-                    debug!("Starting yamux connection driver");
-                    loop {
-                        let mut conn_guard = conn_arc.lock().await;
-                        match poll_fn(|cx| conn_guard.poll_next_inbound(cx)).await {
-                            Some(Ok(_)) => {
-                            }
-                            Some(Err(e)) => {
-                                debug!("Yamux connection error: {}", e);
-                                break;
-                            }
-                            None => {
-                                debug!("Yamux connection closed");
-                                break;
-                            }
-                        }
-                        drop(conn_guard);
-                    }
-                info!("Yamux connection driver stopped");
-            });
+    /// 非阻塞获取虚拟流，服务器尚未收到客户端的入站流时返回 `Ok(None)`
+    fn try_get_stream(&mut self) -> Result<Option<&mut Stream>> {
+        if self.yamux_stream.is_some() {
+            return Ok(self.yamux_stream.as_mut());
+        }
+        match self.get_or_create_stream().now_or_never() {
+            Some(result) => result.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// 经 `chunk` 从虚拟流读取，直到组成一个完整的帧
+    async fn recv_frame(&mut self, chunk: &mut [u8]) -> Result<Vec<u8>> {
+        let timeout = self.read_timeout;
+        loop {
+            if let Some(message) = self.read_buffer.take_frame()? {
+                info!("Yamux received {} bytes", message.len());
+                return Ok(message);
+            }
+
+            // 超时时已读到的数据保留在缓冲区中，下一次 recv 继续组装
+            let stream = self.get_or_create_stream().await?;
+            let n = with_timeout(timeout, stream.read(chunk)).await?;
+            if n == 0 {
+                return Err(unexpected_eof());
+            }
+            self.read_buffer.extend(&chunk[..n]);
+        }
+    }
+
+    /// 以非阻塞方式经 `chunk` 读取，直到组成一个完整的帧或流中暂无数据
+    fn try_recv_frame(&mut self, chunk: &mut [u8]) -> Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(message) = self.read_buffer.take_frame()? {
+                info!("Yamux received {} bytes", message.len());
+                return Ok(Some(message));
+            }
 
-            self.driver_handle = Some(driver_handle);
+            if !self.is_connected() {
+                return Err(Self::not_connected("try_recv"));
+            }
+            let Some(stream) = self.try_get_stream()? else {
+                return Ok(None);
+            };
+            match stream.read(chunk).now_or_never() {
+                None => return Ok(None),
+                Some(Ok(0)) => return Err(unexpected_eof()),
+                Some(Ok(n)) => self.read_buffer.extend(&chunk[..n]),
+                Some(Err(e)) => return Err(e.into()),
+            }
         }
     }
+
+    /// 丢弃连接状态，使实例可以重新连接；驱动任务在句柄释放后自行关闭连接
+    fn reset(&mut self) {
+        self.driver = None;
+        self.yamux_stream = None;
+        self.read_buffer.clear();
+        self.pending_streams.clear();
+        self.fd = None;
+        self.local_addr = None;
+    }
 }
 
 #[async_trait]
 impl Transport for YamuxTransport {
     async fn connect(&mut self, cid: u32, port: u32, _: u32, _: bool) -> Result<()> {
         info!("Yamux transport connecting to cid={}, port={}", cid, port);
+        self.reset();
+        // 在建立 vsock 连接前校验配置
+        let config = self.config.build()?;
 
-        let stream = VsockStream::connect(VsockAddr::new(cid, port))
-            .await
-            .map_err(|e| VirgeError::ConnectionError(format!("Failed to connect vsock: {}", e)))?;
+        let mut stream = connect_tokio_vsock(self.local_port, cid, port).await?;
+        self.handshake(&mut stream).await?;
 
         // 初始化 yamux
-        let config = Config::default();
-        let connection = Connection::new(stream.compat(), config, Mode::Client);
-        self.connection = Some(Arc::new(Mutex::new(connection)));
-
-        // 启动驱动程序来处理连接生命周期
-        self.start_driver();
-        // 创建yamux_stream
-        let _ = self.get_or_create_stream().await?;
-        
+        self.fd = Some(stream.as_raw_fd());
+        self.local_addr = stream.local_addr().ok();
+        let connection = Connection::new(stream.compat(), config, self.mode);
+        self.driver = Some(Driver::spawn(connection));
+
+        // 打开复用的出站流
+        if let Err(e) = self.get_or_create_stream().await {
+            self.reset();
+            return Err(e);
+        }
+
         info!("Yamux transport connected successfully");
         Ok(())
     }
 
+    async fn connect_timeout(&mut self, cid: u32, port: u32, chunksize: u32, isack: bool, timeout: Duration) -> Result<()> {
+        // 超时后 connect future 被 drop，未完成的 vsock 连接随之取消
+        let result = tokio::time::timeout(timeout, self.connect(cid, port, chunksize, isack)).await;
+        match result {
+            Ok(result) => result,
+            Err(_) => {
+                self.reset();
+                Err(VirgeError::Timeout("Yamux transport connect timed out".to_string()))
+            }
+        }
+    }
+
     async fn disconnect(&mut self) -> Result<()> {
+        self.disconnect_with_reason(0, "").await
+    }
+
+    async fn disconnect_with_reason(&mut self, code: u32, message: &str) -> Result<()> {
         info!("Yamux transport disconnecting");
 
-        // 清理驱动程序
-        if let Some(handle) = self.driver_handle.take() {
-            handle.abort();
+        // 写入关闭帧后关闭复用的虚拟流，通知对端连接正常结束；不等待对端的接收窗口
+        if let Some(mut stream) = self.yamux_stream.take() {
+            match stream.write_all(&framing::close_frame(code, message)).now_or_never() {
+                Some(Ok(())) => {}
+                Some(Err(e)) => debug!("Yamux failed to send close frame: {}", e),
+                None => debug!("Yamux stream window exhausted, skipping close frame"),
+            }
+            if let Err(e) = stream.close().await {
+                debug!("Yamux stream close error: {}", e);
+            }
         }
 
-        // 清理资源
-        self.connection = None;
-        self.yamux_stream = None;
+        // 等待驱动任务关闭连接，再清理资源
+        if let Some(driver) = self.driver.take() {
+            driver.shutdown().await;
+        }
+        self.reset();
 
         info!("Yamux transport disconnected");
         Ok(())
@@ -173,49 +609,193 @@ impl Transport for YamuxTransport {
 
     async fn send(&mut self, data: Vec<u8>) -> Result<()> {
         if !self.is_connected() {
-            return Err(VirgeError::TransportError(
-                "Yamux transport not connected about send".to_string(),
-            ));
+            return Err(Self::not_connected("send"));
         }
 
+        let header = framing::stream_header(data.len(), self.read_buffer.max())?;
+        let timeout = self.write_timeout;
         let stream = self.get_or_create_stream().await?;
-        stream.write_all(&data).await
-            .map_err(|e| VirgeError::Other(format!("yamux send error: {}", e)))?;
-        stream.close().await?;
+        with_timeout(timeout, async {
+            stream.write_all(&header).await?;
+            stream.write_all(&data).await?;
+            stream.flush().await
+        }).await?;
 
         info!("Yamux sent {} bytes", data.len());
         Ok(())
     }
 
+    async fn send_slices(&mut self, slices: &[IoSlice<'_>]) -> Result<usize> {
+        if !self.is_connected() {
+            return Err(Self::not_connected("send"));
+        }
+
+        // 帧头写入总长度后依次写入各切片，无需拼接
+        let timeout = self.write_timeout;
+        let total = slices.iter().map(|slice| slice.len()).sum();
+        let header = framing::stream_header(total, self.read_buffer.max())?;
+        let stream = self.get_or_create_stream().await?;
+        with_timeout(timeout, async {
+            stream.write_all(&header).await?;
+            for slice in slices {
+                stream.write_all(slice).await?;
+            }
+            stream.flush().await
+        }).await?;
+
+        info!("Yamux sent {} bytes", total);
+        Ok(total)
+    }
+
     async fn recv(&mut self) -> Result<Vec<u8>> {
+        if self.read_buffer.peer_closed() {
+            return Err(VirgeError::EndOfStream);
+        }
         if !self.is_connected() {
-            return Err(VirgeError::TransportError(
-                "Yamux transport not connected about recv".to_string(),
-            ));
+            return Err(Self::not_connected("recv"));
         }
+
+        let mut chunk = self.read_buffer.read_chunk(READ_CHUNK_SIZE);
+        let result = self.recv_frame(&mut chunk).await;
+        self.read_buffer.recycle(chunk);
+        result
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut chunk = self.read_buffer.read_chunk(READ_CHUNK_SIZE);
+        let result = self.try_recv_frame(&mut chunk);
+        self.read_buffer.recycle(chunk);
+        result
+    }
+
+    async fn try_send(&mut self, data: &[u8]) -> Result<Option<usize>> {
+        let header = framing::stream_header(data.len(), self.read_buffer.max())?;
+        let timeout = self.write_timeout;
+        if !self.is_connected() {
+            return Err(Self::not_connected("try_send"));
+        }
+        let Some(stream) = self.try_get_stream()? else {
+            return Ok(None);
+        };
+
+        // 窗口耗尽时不写入任何数据；一旦写入了部分帧头，则阻塞写完以保证消息完整
+        let written = match stream.write(&header).now_or_never() {
+            None => return Ok(None),
+            Some(result) => result?,
+        };
+        with_timeout(timeout, async {
+            stream.write_all(&header[written..]).await?;
+            stream.write_all(data).await?;
+            stream.flush().await
+        }).await?;
+
+        info!("Yamux sent {} bytes", data.len());
+        Ok(Some(data.len()))
+    }
+
+    /// 以半关闭控制帧通知对端，不关闭 yamux 虚拟流，确认与心跳帧仍可双向收发
+    async fn shutdown_write(&mut self) -> Result<()> {
+        if !self.is_connected() {
+            return Err(Self::not_connected("shutdown_write"));
+        }
+
+        let timeout = self.write_timeout;
         let stream = self.get_or_create_stream().await?;
-        let mut buf = Vec::new();
-        stream.read_to_end(&mut buf).await
-            .map_err(|e| VirgeError::Other(format!("yamux recv error: {}", e)))?;
-        info!("Yamux received {} bytes", buf.len());
-        Ok(buf)
+        with_timeout(timeout, async {
+            stream.write_all(&framing::STREAM_EOF).await?;
+            stream.flush().await
+        }).await?;
+
+        info!("Yamux shut down its write side");
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        if !self.is_connected() {
+            return Err(Self::not_connected("flush"));
+        }
+        // 虚拟流尚未打开时没有待发送的数据
+        let timeout = self.write_timeout;
+        let Some(stream) = self.yamux_stream.as_mut() else {
+            return Ok(());
+        };
+        with_timeout(timeout, stream.flush()).await?;
+        Ok(())
     }
 
     fn is_connected(&self) -> bool {
-        self.yamux_stream.is_some() && self.connection.is_some()
+        // 服务器的虚拟流在首次收发时才被接受，驱动任务运行即视为已连接
+        self.driver.as_ref().is_some_and(Driver::is_running) && !self.read_buffer.peer_closed()
     }
 
-    async fn from_tokio_stream(&mut self, stream: tokio_vsock::VsockStream) -> Result<()> {
+    fn raw_fd(&self) -> Option<RawFd> {
+        self.fd.filter(|_| self.is_connected())
+    }
+
+    fn local_addr(&self) -> Option<VsockAddr> {
+        self.local_addr.filter(|_| self.is_connected())
+    }
+
+    fn negotiated_ack_window(&self) -> Option<u32> {
+        self.negotiated_window
+    }
+
+    fn peer_auth_token(&self) -> Option<&[u8]> {
+        self.peer_auth_token.as_deref()
+    }
+
+    fn has_buffered_data(&self) -> bool {
+        !self.read_buffer.is_empty()
+    }
+
+    fn buffer_pool(&self) -> Option<Arc<BufferPool>> {
+        Some(self.read_buffer.pool().clone())
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        check_timeout(timeout)?;
+        self.read_timeout = timeout;
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        check_timeout(timeout)?;
+        self.write_timeout = timeout;
+        Ok(())
+    }
+
+    async fn open_stream(&mut self) -> Result<VirgeStream> {
+        let driver = self.driver.as_ref().ok_or_else(not_initialized)?;
+        Ok(VirgeStream::new(driver.open_stream().await?))
+    }
+
+    async fn accept_stream(&mut self) -> Result<VirgeStream> {
+        if let Some(stream) = self.pending_streams.pop_front() {
+            return Ok(VirgeStream::new(stream));
+        }
+        let driver = self.driver.as_mut().ok_or_else(not_initialized)?;
+        loop {
+            let stream = driver.accept_stream().await?;
+            // 服务器尚未接受主虚拟流时，先将其保留给 recv/send
+            if self.mode == Mode::Server && self.yamux_stream.is_none() && stream.id().val() == MAIN_STREAM_ID {
+                self.yamux_stream = Some(stream);
+                continue;
+            }
+            return Ok(VirgeStream::new(stream));
+        }
+    }
+
+    async fn from_tokio_stream(&mut self, mut stream: tokio_vsock::VsockStream) -> Result<()> {
+        let config = self.config.build()?;
+        self.handshake(&mut stream).await?;
+
         // 初始化 yamux
-        let config = Config::default();
-        let connection = Connection::new(stream.compat(), config, Mode::Server);
-
-        self.connection = Some(Arc::new(Mutex::new(connection)));
-        
-        // 启动驱动程序来处理连接生命周期
-        self.start_driver();
-        // 创建yamux_stream
-        let _ = self.get_or_create_stream().await?;
+        self.fd = Some(stream.as_raw_fd());
+        self.local_addr = stream.local_addr().ok();
+        let connection = Connection::new(stream.compat(), config, self.mode);
+
+        // 入站流由驱动任务转发，在首次 recv/send 时接受
+        self.driver = Some(Driver::spawn(connection));
 
         info!("Yamux transport initialized from stream successfully");
         Ok(())
@@ -0,0 +1,1843 @@
+//! 端到端测试：在同一进程内经本地回环 TCP 运行 `ServerManager` 与 `VirgeClient`
+//!
+//! 覆盖 `example/server_test` 与 `example/client_test` 的场景，无需虚拟机或 vsock 内核模块，
+//! 运行方式：`cargo test --features use-tcp --test e2e`。
+
+use std::ops::ControlFlow;
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+use virga::client::{ClientConfig, VirgeClient};
+use virga::protocol::{CLOSE_AUTH_FAILED, CLOSE_RATE_LIMITED};
+use virga::server::{
+    DisconnectReason, QueueOverflow, RejectReason, ServerConfig, ServerEvent, ServerManager, VirgeServer,
+};
+use virga::{RateLimitPolicy, Stats, TransportKind, VirgeError};
+#[cfg(feature = "testing")]
+use virga::transport::{RecordedEvent, ReplayTransport};
+#[cfg(feature = "testing")]
+use virga::{FaultPlan, TrafficLog, TrafficRecorder};
+
+/// 单个测试中等待对端的最长时间，超过视为挂起
+const WAIT: Duration = Duration::from_secs(10);
+
+/// 以 `config` 在随机端口上启动回环 TCP 服务器，返回管理器与实际监听的端口
+async fn start_server(config: ServerConfig) -> (ServerManager, u32) {
+    let config = config.with_listen_port(ServerConfig::PORT_ANY).with_transport_kind(TransportKind::Tcp);
+    let mut manager = ServerManager::new(config);
+    manager.start().await.unwrap();
+    let port = manager.local_addr().unwrap().port();
+    (manager, port)
+}
+
+/// 把 `config` 指向 `port` 上的回环 TCP 服务器，其余设置保持不变
+fn client_for(port: u32, config: ClientConfig) -> ClientConfig {
+    config
+        .with_server_cid(ClientConfig::CID_LOCAL)
+        .with_server_port(port)
+        .with_transport_kind(TransportKind::Tcp)
+}
+
+/// 以 `config` 连接 `port` 上的回环 TCP 服务器
+async fn connect(port: u32, config: ClientConfig) -> VirgeClient {
+    let mut client = VirgeClient::new(client_for(port, config));
+    client.connect().await.unwrap();
+    client
+}
+
+/// 以长度前缀消息原样回显，直到客户端断开
+async fn echo(mut server: VirgeServer) {
+    while let Ok(message) = server.recv_msg().await {
+        if server.send_msg(&message).await.is_err() {
+            break;
+        }
+    }
+    let _ = server.disconnect().await;
+}
+
+/// 每个字节都不同的测试数据，错位或重复的数据块会被比较发现
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[tokio::test]
+async fn length_prefixed_echo() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let server = tokio::spawn(async move { echo(manager.accept().await.unwrap()).await });
+
+    let mut client = connect(port, ClientConfig::default()).await;
+    assert_eq!(client.peer_addr().unwrap().port(), port);
+    let messages: [&[u8]; 3] = [b"ping", &[1; 512], &[]];
+    for message in messages {
+        client.send_msg(message).await.unwrap();
+        let reply = tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap();
+        assert_eq!(reply, message);
+    }
+    client.disconnect().await.unwrap();
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn large_payload_spans_many_chunks() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let server = tokio::spawn(async move { echo(manager.accept().await.unwrap()).await });
+
+    let mut client = connect(port, ClientConfig::default()).await;
+    // 不是 chunk_size 的整数倍，最后一块不满
+    let payload = pattern(2 * 1024 * 1024 + 17);
+    client.send_msg(&payload).await.unwrap();
+    let reply = tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap();
+    assert_eq!(reply.len(), payload.len());
+    assert!(reply == payload, "echoed payload differs from the original");
+    client.disconnect().await.unwrap();
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn ten_megabyte_write_with_one_kib_chunks() {
+    const LEN: usize = 10 * 1024 * 1024;
+    let (mut manager, port) = start_server(
+        ServerConfig::default().with_chunk_size(1024).with_max_message_size(2 * LEN),
+    )
+    .await;
+    let server = tokio::spawn(async move {
+        let mut server = manager.accept().await.unwrap();
+        let mut received = vec![0u8; LEN];
+        let mut filled = 0;
+        while filled < LEN {
+            let n = server.read(&mut received[filled..]).await.unwrap();
+            assert_ne!(n, 0, "stream ended after {} bytes", filled);
+            filled += n;
+        }
+        received
+    });
+
+    let mut client = connect(port, ClientConfig::default().with_chunk_size(1024).with_max_message_size(2 * LEN)).await;
+    let payload = pattern(LEN);
+    // 一次调用提交全部数据，返回值即为已提交的字节数
+    assert_eq!(client.write(&payload).await.unwrap(), LEN);
+    let received = tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+    assert!(received == payload, "received payload differs from the original");
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn interleaved_bidirectional_traffic() {
+    const COUNT: usize = 200;
+    let (mut manager, port) = start_server(ServerConfig::default().with_chunk_size(4096)).await;
+
+    // 服务器与客户端同时发送各自的消息序列，并各自按序接收对方的全部消息
+    let server = tokio::spawn(async move {
+        let (mut reader, mut writer) = manager.accept().await.unwrap().split();
+        let sender = tokio::spawn(async move {
+            for i in 0..COUNT {
+                writer.send_msg(format!("server-{}", i).as_bytes()).await.unwrap();
+            }
+            writer
+        });
+        for i in 0..COUNT {
+            assert_eq!(reader.recv_msg().await.unwrap(), format!("client-{}", i).into_bytes());
+        }
+        sender.await.unwrap()
+    });
+
+    let (mut reader, mut writer) = connect(port, ClientConfig::default().with_chunk_size(4096)).await.split();
+    let sender = tokio::spawn(async move {
+        for i in 0..COUNT {
+            writer.send_msg(format!("client-{}", i).as_bytes()).await.unwrap();
+        }
+        writer
+    });
+    for i in 0..COUNT {
+        let message = tokio::time::timeout(WAIT, reader.recv_msg()).await.unwrap().unwrap();
+        assert_eq!(message, format!("server-{}", i).into_bytes());
+    }
+
+    let mut writer = tokio::time::timeout(WAIT, sender).await.unwrap().unwrap();
+    let mut server_writer = tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+    server_writer.disconnect().await.unwrap();
+    writer.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn disconnect_while_reading() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let server = tokio::spawn(async move {
+        let mut server = manager.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        server.disconnect().await.unwrap();
+    });
+
+    let mut client = connect(port, ClientConfig::default()).await;
+    // 客户端在服务器断开前已阻塞在接收中，服务器正常断开后返回空消息而不是错误或挂起
+    let result = tokio::time::timeout(WAIT, client.recv()).await.expect("recv hung after peer disconnect");
+    assert_eq!(result.unwrap(), Vec::<u8>::new());
+    assert!(!client.is_connected());
+    assert_eq!(client.recv().await.unwrap(), Vec::<u8>::new());
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+}
+
+/// 按 `read` 的字节流语义读满 `buf`，流在读满之前结束时失败
+async fn read_exact(client: &mut VirgeClient, buf: &mut [u8]) {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = tokio::time::timeout(WAIT, client.read(&mut buf[filled..])).await.unwrap().unwrap();
+        assert_ne!(n, 0, "stream ended after {} of {} bytes", filled, buf.len());
+        filled += n;
+    }
+}
+
+/// 按 `read` 的字节流语义读到流结束
+async fn read_to_end(client: &mut VirgeClient) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 3];
+    loop {
+        match tokio::time::timeout(WAIT, client.read(&mut chunk)).await.unwrap().unwrap() {
+            0 => return data,
+            n => data.extend_from_slice(&chunk[..n]),
+        }
+    }
+}
+
+#[tokio::test]
+async fn read_exact_spans_message_boundaries() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let server = tokio::spawn(async move {
+        let mut server = manager.accept().await.unwrap();
+        for message in [&b"abc"[..], b"defgh", b"ij"] {
+            server.send(message.to_vec()).await.unwrap();
+        }
+        let _ = server.recv().await;
+    });
+
+    let mut client = connect(port, ClientConfig::default()).await;
+    // 消息边界不表现为 EOF：每次读取都可能跨越多条消息
+    let mut buf = [0u8; 4];
+    read_exact(&mut client, &mut buf).await;
+    assert_eq!(&buf, b"abcd");
+    read_exact(&mut client, &mut buf).await;
+    assert_eq!(&buf, b"efgh");
+    let mut buf = [0u8; 2];
+    read_exact(&mut client, &mut buf).await;
+    assert_eq!(&buf, b"ij");
+    client.disconnect().await.unwrap();
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn read_to_end_stops_at_peer_disconnect() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let payload = pattern(10_000);
+    let expected = payload.clone();
+    let server = tokio::spawn(async move {
+        let mut server = manager.accept().await.unwrap();
+        for part in payload.chunks(777) {
+            server.send(part.to_vec()).await.unwrap();
+        }
+        server.disconnect().await.unwrap();
+    });
+
+    let mut client = connect(port, ClientConfig::default()).await;
+    // 读到对端断开为止得到全部消息拼接的数据，之后的读取继续返回 0
+    assert!(read_to_end(&mut client).await == expected, "read_to_end returned different data");
+    assert_eq!(client.read(&mut [0u8; 8]).await.unwrap(), 0);
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn accept_with_gives_concurrent_connections_their_own_chunk_size() {
+    const LEN: usize = 64 * 1024;
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let clients = [
+        tokio::spawn(connect(port, ClientConfig::default())),
+        tokio::spawn(connect(port, ClientConfig::default())),
+    ];
+
+    // 管理器的 chunk_size 为默认的 1 KiB，两条连接各自使用覆盖配置中的值
+    let mut senders = Vec::new();
+    for chunk_size in [4096u32, 16384] {
+        let accept = manager.accept_with(|_| {
+            ServerConfig::default().with_chunk_size(chunk_size).with_transport_kind(TransportKind::Tcp)
+        });
+        let mut server = tokio::time::timeout(WAIT, accept).await.unwrap().unwrap();
+        senders.push(tokio::spawn(async move {
+            let mut chunks = 0;
+            server.send_with_progress(&pattern(LEN), |_, _| chunks += 1).await.unwrap();
+            let _ = server.recv().await;
+            (chunk_size, chunks)
+        }));
+    }
+
+    for client in clients {
+        let mut client = tokio::time::timeout(WAIT, client).await.unwrap().unwrap();
+        let message = tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap();
+        assert!(message == pattern(LEN), "received payload differs from the original");
+        client.disconnect().await.unwrap();
+    }
+    for sender in senders {
+        let (chunk_size, chunks) = tokio::time::timeout(WAIT, sender).await.unwrap().unwrap();
+        assert_eq!(chunks, LEN / chunk_size as usize, "connection configured with chunk_size {}", chunk_size);
+    }
+}
+
+#[tokio::test]
+async fn second_manager_on_the_same_port_reports_port_in_use() {
+    let (_first, port) = start_server(ServerConfig::default()).await;
+    for reuse_addr in [false, true] {
+        let config = ServerConfig::default()
+            .with_listen_port(port)
+            .with_transport_kind(TransportKind::Tcp)
+            .with_reuse_addr(reuse_addr);
+        let err = ServerManager::new(config).start().await.unwrap_err();
+        assert!(matches!(err, VirgeError::PortInUse { port: p, .. } if p == port), "{:?}", err);
+        assert_eq!(err.io_kind(), Some(std::io::ErrorKind::AddrInUse));
+    }
+}
+
+#[tokio::test]
+async fn client_half_close_still_receives_the_response() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let mut client = connect(port, ClientConfig::default()).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    client.send(b"request".to_vec()).await.unwrap();
+    client.shutdown_write().await.unwrap();
+    assert!(matches!(client.send(b"late".to_vec()).await, Err(VirgeError::Disconnected(_))));
+
+    // 服务器先收到请求，随后是干净的流结束，而不是错误
+    assert_eq!(tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap(), b"request");
+    assert_eq!(tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap(), b"");
+    assert_eq!(server.read(&mut [0u8; 8]).await.unwrap(), 0);
+
+    // 反方向不受影响
+    server.send(b"response".to_vec()).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap(), b"response");
+}
+
+#[tokio::test]
+async fn server_half_close_still_receives_requests() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let mut client = connect(port, ClientConfig::default()).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    server.send(b"final".to_vec()).await.unwrap();
+    server.shutdown_write().await.unwrap();
+    assert!(matches!(server.send(b"late".to_vec()).await, Err(VirgeError::Disconnected(_))));
+
+    assert_eq!(tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap(), b"final");
+    assert_eq!(tokio::time::timeout(WAIT, client.read(&mut [0u8; 8])).await.unwrap().unwrap(), 0);
+    assert_eq!(tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap(), b"");
+
+    client.send(b"request".to_vec()).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap(), b"request");
+}
+
+#[tokio::test]
+async fn split_halves_work_with_tokio_io_copy() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    const LEN: usize = 512 * 1024;
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let server = tokio::spawn(async move {
+        let (mut reader, mut writer) = manager.accept().await.unwrap().split();
+        let copied = tokio::io::copy(&mut reader, &mut writer).await.unwrap();
+        writer.shutdown().await.unwrap();
+        copied
+    });
+
+    let (mut reader, mut writer) = connect(port, ClientConfig::default()).await.split();
+    let sender = tokio::spawn(async move {
+        for chunk in pattern(LEN).chunks(7000) {
+            writer.write_all(chunk).await.unwrap();
+        }
+        writer.shutdown().await.unwrap();
+    });
+    let mut echoed = Vec::new();
+    tokio::time::timeout(WAIT, reader.read_to_end(&mut echoed)).await.unwrap().unwrap();
+    assert!(echoed == pattern(LEN), "echo of {} bytes differs", echoed.len());
+    tokio::time::timeout(WAIT, sender).await.unwrap().unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, server).await.unwrap().unwrap(), LEN as u64);
+}
+
+#[tokio::test]
+async fn vectored_writes_arrive_as_one_message() {
+    use std::io::IoSlice;
+    use tokio::io::AsyncWriteExt;
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let mut client = connect(port, ClientConfig::default()).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    let header = b"HDR:0004;".to_vec();
+    let body = pattern(64 * 1024);
+    let expected = [header.as_slice(), &body].concat();
+
+    // 头部与正文各自的缓冲区，对端一次 recv() 得到拼接后的完整消息
+    let slices = [IoSlice::new(&header), IoSlice::new(&[]), IoSlice::new(&body)];
+    assert_eq!(client.write_vectored(&slices).await.unwrap(), expected.len());
+    assert!(tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap() == expected);
+    assert_eq!(server.write_vectored(&slices).await.unwrap(), expected.len());
+    assert!(tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap() == expected);
+    assert_eq!(client.write_vectored(&[IoSlice::new(&[])]).await.unwrap(), 0);
+
+    // tokio 的 write_vectored 同样合并为一条消息
+    let mut client = virga::VirgeClientAsync::from(client);
+    assert_eq!(client.write_vectored(&slices).await.unwrap(), expected.len());
+    client.flush().await.unwrap();
+    assert!(tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap() == expected);
+}
+
+#[tokio::test]
+async fn async_wrappers_round_trip_through_tokio_io() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use virga::{VirgeClientAsync, VirgeServerAsync};
+    const LEN: usize = 256 * 1024;
+
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let server = tokio::spawn(async move {
+        let mut server = VirgeServerAsync::from(manager.accept().await.unwrap());
+        let mut request = Vec::new();
+        server.read_to_end(&mut request).await.unwrap();
+        request.reverse();
+        server.write_all(&request).await.unwrap();
+        server.shutdown().await.unwrap();
+        server.into_inner().await
+    });
+
+    let mut client = VirgeClientAsync::new(client_for(port, ClientConfig::default()));
+    client.connect().await.unwrap();
+    client.write_all(&pattern(LEN)).await.unwrap();
+    client.shutdown().await.unwrap();
+    let mut response = Vec::new();
+    tokio::time::timeout(WAIT, client.read_to_end(&mut response)).await.unwrap().unwrap();
+    let mut expected = pattern(LEN);
+    expected.reverse();
+    assert!(response == expected, "response of {} bytes differs", response.len());
+
+    // 取回的连接仍可按消息收发
+    let mut server = tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+    let mut client = client.into_inner().await;
+    assert!(matches!(server.send(b"late".to_vec()).await, Err(VirgeError::Disconnected(_))));
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn priority_message_overtakes_a_concurrent_bulk_send() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    const BULK: usize = 100 * 1024 * 1024;
+    const LIMIT: usize = 128 * 1024 * 1024;
+
+    let config = ServerConfig::default().with_priority_lanes(true).with_max_message_size(LIMIT);
+    let (mut manager, port) = start_server(config).await;
+    let client = connect(port, ClientConfig::default().with_priority_lanes(true).with_max_message_size(LIMIT)).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    let client = client.handle();
+
+    let bulk_done = Arc::new(AtomicBool::new(false));
+    let bulk = tokio::spawn({
+        let (client, bulk_done) = (client.clone(), bulk_done.clone());
+        async move {
+            client.send(pattern(BULK)).await.unwrap();
+            bulk_done.store(true, Ordering::SeqCst);
+        }
+    });
+    // 服务器尚未读取，批量发送在套接字缓冲区填满后停在某个分片上
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!bulk_done.load(Ordering::SeqCst));
+    let control = tokio::spawn({
+        let client = client.clone();
+        async move { client.send_priority(b"stop").await.unwrap() }
+    });
+    tokio::task::yield_now().await;
+
+    let priority = tokio::time::timeout(WAIT, server.recv_priority()).await.unwrap().unwrap();
+    assert_eq!(priority, b"stop");
+    assert!(!bulk_done.load(Ordering::SeqCst), "priority message arrived after the bulk send completed");
+
+    let received = tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap();
+    assert_eq!(received.len(), BULK);
+    assert!(received.iter().enumerate().all(|(i, &b)| b == (i % 251) as u8), "bulk payload differs");
+    tokio::time::timeout(WAIT, bulk).await.unwrap().unwrap();
+    tokio::time::timeout(WAIT, control).await.unwrap().unwrap();
+}
+
+/// 等待 `condition` 成立，超过 `WAIT` 视为挂起
+async fn wait_until(mut condition: impl FnMut() -> bool) {
+    tokio::time::timeout(WAIT, async {
+        while !condition() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
+
+/// 以默认配置连接 `port`，返回连接结果而不是在失败时 panic
+async fn try_connect(port: u32) -> Result<VirgeClient, VirgeError> {
+    let mut client = VirgeClient::new(client_for(port, ClientConfig::default()));
+    client.connect().await.map(|()| client)
+}
+
+/// 容量为 2 的接受队列依次收到三个连接，返回各客户端是否连接成功
+async fn accept_queue_outcome(overflow: QueueOverflow) -> Vec<bool> {
+    let (mut manager, port) = start_server(ServerConfig::default().with_accept_queue(2, overflow)).await;
+    // 连接在 accept() 取走后才握手，客户端的 connect 在此之前不会返回
+    let mut clients = Vec::new();
+    for queued in 1..=2 {
+        clients.push(tokio::spawn(try_connect(port)));
+        wait_until(|| manager.pending_connections() == queued).await;
+    }
+    clients.push(tokio::spawn(try_connect(port)));
+    // 队列已满，被关闭的连接使对应客户端的握手失败
+    wait_until(|| clients.iter().any(|client| client.is_finished())).await;
+    assert_eq!(manager.pending_connections(), 2);
+
+    let mut servers = Vec::new();
+    for _ in 0..2 {
+        servers.push(tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap());
+    }
+    assert_eq!(manager.pending_connections(), 0);
+    let mut outcome = Vec::new();
+    for client in clients {
+        outcome.push(tokio::time::timeout(WAIT, client).await.unwrap().unwrap().is_ok());
+    }
+    outcome
+}
+
+#[tokio::test]
+async fn full_accept_queue_refuses_new_connections() {
+    assert_eq!(accept_queue_outcome(QueueOverflow::RefuseNew).await, [true, true, false]);
+}
+
+#[tokio::test]
+async fn full_accept_queue_drops_the_oldest_connection() {
+    assert_eq!(accept_queue_outcome(QueueOverflow::DropOldest).await, [false, true, true]);
+}
+
+#[tokio::test]
+async fn stopped_incoming_stream_yields_queued_connections_first() {
+    use futures::StreamExt;
+    const CLIENTS: usize = 3;
+    let config = ServerConfig::default().with_accept_queue(8, QueueOverflow::RefuseNew);
+    let (mut manager, port) = start_server(config).await;
+    let clients: Vec<_> = (0..CLIENTS).map(|_| tokio::spawn(try_connect(port))).collect();
+    wait_until(|| manager.pending_connections() == CLIENTS).await;
+
+    // 停止后不再接受新连接，已在队列中的连接仍逐个产出，之后流结束
+    manager.stop_handle().unwrap().stop();
+    let incoming = tokio::time::timeout(WAIT, manager.incoming().collect::<Vec<_>>()).await.unwrap();
+    assert_eq!(incoming.len(), CLIENTS);
+    for server in &incoming {
+        assert!(server.is_ok(), "{:?}", server.as_ref().err());
+    }
+    for client in clients {
+        assert!(tokio::time::timeout(WAIT, client).await.unwrap().unwrap().is_ok());
+    }
+    assert!(!manager.is_running());
+}
+
+#[tokio::test]
+async fn reconnect() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let server = tokio::spawn(async move {
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let server = manager.accept().await.unwrap();
+            ids.push(server.id());
+            echo(server).await;
+        }
+        ids
+    });
+
+    let mut client = connect(port, ClientConfig::default()).await;
+    client.send_msg(b"first").await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap(), b"first");
+
+    client.reconnect().await.unwrap();
+    client.send_msg(b"second").await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap(), b"second");
+    client.disconnect().await.unwrap();
+
+    let ids = tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+    assert_ne!(ids[0], ids[1]);
+}
+
+#[tokio::test]
+async fn batched_writes_are_sent_on_flush() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let config = ClientConfig::default().with_batching(Duration::from_secs(60), 64);
+    let mut client = VirgeClient::new(client_for(port, config));
+    let (server, connected) = tokio::join!(manager.accept(), client.connect());
+    let mut server = server.unwrap();
+    connected.unwrap();
+    server.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+
+    // 批次未满且未到 max_delay，flush 之前对端收不到任何数据
+    client.write(b"hello ").await.unwrap();
+    client.write(b"world").await.unwrap();
+    assert!(server.recv().await.unwrap_err().is_timeout());
+    client.flush().await.unwrap();
+    assert_eq!(server.recv().await.unwrap(), b"hello world");
+
+    // 批次达到 max_bytes 时不需要 flush
+    client.write(&[7; 64]).await.unwrap();
+    assert_eq!(server.recv().await.unwrap(), vec![7; 64]);
+
+    client.flush_timeout(Duration::from_secs(1)).await.unwrap();
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn manager_dials_listening_peer() {
+    let (mut listener, port) = start_server(ServerConfig::default().with_chunk_size(2048)).await;
+    let accepted = tokio::spawn(async move {
+        let server = listener.accept().await.unwrap();
+        let peer = server.peer_addr();
+        echo(server).await;
+        peer
+    });
+
+    // 另一个管理器不监听，只以自身配置主动发起连接
+    let config = ServerConfig::builder()
+        .chunk_size(2048)
+        .build()
+        .unwrap()
+        .with_transport_kind(TransportKind::Tcp);
+    let mut dialer = ServerManager::new(config);
+    let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = events.clone();
+    dialer.on_event(move |event| sink.lock().unwrap().push(event));
+
+    let mut server = dialer.dial(ClientConfig::CID_LOCAL, port).await.unwrap();
+    assert_eq!(server.peer_addr().port(), port);
+    assert_eq!(dialer.active_connections(), 1);
+
+    let payload = pattern(10_000);
+    server.send_msg(&payload).await.unwrap();
+    let reply = tokio::time::timeout(WAIT, server.recv_msg()).await.unwrap().unwrap();
+    assert!(reply == payload, "echoed payload differs from the original");
+    assert_eq!(server.stats().bytes_sent, dialer.aggregate_stats().bytes_sent);
+    let (conn_id, local_port) = (server.id(), server.local_port());
+    server.disconnect().await.unwrap();
+
+    // 监听端看到的对端端口即发起端的本地端口
+    let peer = tokio::time::timeout(WAIT, accepted).await.unwrap().unwrap();
+    assert_eq!(dialer.active_connections(), 0);
+    let events = events.lock().unwrap();
+    assert!(matches!(events[0], ServerEvent::Dialed { conn_id: id, .. } if id == conn_id));
+    assert!(matches!(events[1], ServerEvent::Disconnected { conn_id: id, .. } if id == conn_id));
+    assert_eq!(peer.port(), local_port);
+}
+
+#[tokio::test]
+async fn socket_buffer_is_validated_and_skipped_on_tcp() {
+    let invalid = ClientConfig::builder().socket_buffer(4096, 1024, 8192).build();
+    assert!(matches!(invalid, Err(VirgeError::ConfigError(_))), "{:?}", invalid.err());
+
+    // tcp 套接字不是 vsock 套接字：设置被跳过，连接照常建立，读回返回配置错误
+    let config = ServerConfig::builder().socket_buffer(4096, 65536, 1 << 20).build().unwrap();
+    let (mut manager, port) = start_server(config).await;
+    let config = ClientConfig::builder().socket_buffer(4096, 65536, 1 << 20).build().unwrap();
+    let mut client = VirgeClient::new(client_for(port, config));
+    assert!(matches!(client.socket_buffer_sizes(), Err(VirgeError::Disconnected(_))));
+    client.connect().await.unwrap();
+    let server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    assert!(matches!(client.socket_buffer_sizes(), Err(VirgeError::ConfigError(_))));
+    assert!(matches!(server.socket_buffer_sizes(), Err(VirgeError::ConfigError(_))));
+    tokio::spawn(echo(server));
+    client.send_msg(b"buffered").await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap(), b"buffered");
+}
+
+#[tokio::test]
+async fn stats_count_a_known_exchange() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let mut client = connect(port, ClientConfig::default()).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    assert_eq!(client.stats().messages_sent, 0);
+    assert!(client.stats().connect_time.is_some() && server.stats().connect_time.is_some());
+
+    // 客户端发送 10 + 20 + 30 字节，服务器回复 5 + 7 字节
+    for len in [10, 20, 30] {
+        client.send(pattern(len)).await.unwrap();
+    }
+    for len in [10, 20, 30] {
+        let message = tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap();
+        assert_eq!(message, pattern(len));
+    }
+    for len in [5, 7] {
+        server.send(pattern(len)).await.unwrap();
+    }
+    for len in [5, 7] {
+        let message = tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap();
+        assert_eq!(message, pattern(len));
+    }
+
+    // (发送消息数, 发送字节数, 接收消息数, 接收字节数)
+    let counts = |stats: Stats| (stats.messages_sent, stats.bytes_sent, stats.messages_received, stats.bytes_received);
+    assert_eq!(counts(client.stats()), (3, 60, 2, 12));
+    assert_eq!(counts(server.stats()), (2, 12, 3, 60));
+    assert_eq!(counts(manager.aggregate_stats()), (2, 12, 3, 60));
+    for stats in [client.stats(), server.stats()] {
+        assert!(stats.last_activity >= stats.connect_time);
+    }
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn acked_send_fails_once_the_peer_is_gone() {
+    let (mut manager, port) = start_server(ServerConfig::default().with_ack(true)).await;
+    let mut client = connect(port, ClientConfig::default().with_ack(true)).await;
+    client.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    // 对端读取并确认第一条消息后被释放
+    let peer = tokio::spawn(async move {
+        assert_eq!(server.recv().await.unwrap(), b"acked");
+    });
+    tokio::time::timeout(WAIT, client.send(b"acked".to_vec())).await.unwrap().unwrap();
+    assert!(client.last_ack_latency().is_some());
+    tokio::time::timeout(WAIT, peer).await.unwrap().unwrap();
+
+    // 没有对端确认，send 返回错误而不是在交给内核后成功
+    let result = tokio::time::timeout(WAIT, client.send(b"lost".to_vec())).await.unwrap();
+    assert!(result.is_err(), "send to a dropped peer succeeded");
+}
+
+#[tokio::test]
+async fn receive_rate_limit_paces_the_peer() {
+    let config = ServerConfig::default()
+        .with_rate_limit(20_000, 1000)
+        .with_rate_limit_policy(RateLimitPolicy::Throttle);
+    let (mut manager, port) = start_server(config).await;
+    let mut client = connect(port, ClientConfig::default()).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    // 10 KB 数据在 1 KB 突发之后以 20 KB/s 接收，至少需要约 0.45 秒
+    let sender = tokio::spawn(async move {
+        for _ in 0..10 {
+            client.send_msg(&pattern(1000)).await.unwrap();
+        }
+        client
+    });
+    let started = std::time::Instant::now();
+    for _ in 0..10 {
+        let message = tokio::time::timeout(WAIT, server.recv_msg()).await.unwrap().unwrap();
+        assert_eq!(message, pattern(1000));
+    }
+    assert!(started.elapsed() >= Duration::from_millis(350), "received too fast: {:?}", started.elapsed());
+    let stats = server.stats();
+    assert!(stats.throttled_time >= Duration::from_millis(300), "{:?}", stats.throttled_time);
+    assert!(!stats.throttled);
+    assert!(manager.aggregate_stats().throttled_time >= stats.throttled_time);
+    drop(tokio::time::timeout(WAIT, sender).await.unwrap().unwrap());
+}
+
+#[tokio::test]
+async fn sustained_rate_limit_violation_disconnects() {
+    let policy = RateLimitPolicy::Disconnect { after: Duration::from_millis(200) };
+    let config = ServerConfig::default().with_rate_limit(10_000, 1000).with_rate_limit_policy(policy);
+    let (mut manager, port) = start_server(config).await;
+    let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = events.clone();
+    manager.on_event(move |event| sink.lock().unwrap().push(event));
+    let mut client = connect(port, ClientConfig::default()).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    let conn_id = server.id();
+
+    // 客户端持续以远超限额的速率发送，直到连接被关闭
+    let sender = tokio::spawn(async move {
+        while client.send_msg(&pattern(1000)).await.is_ok() {}
+    });
+    let error = tokio::time::timeout(WAIT, async {
+        loop {
+            if let Err(e) = server.recv_msg().await {
+                break e;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert!(matches!(error, VirgeError::Disconnected(_)), "{:?}", error);
+    drop(server);
+    tokio::time::timeout(WAIT, sender).await.unwrap().unwrap();
+
+    let events = events.lock().unwrap();
+    let limited = events.iter().find_map(|event| match event {
+        ServerEvent::RateLimited { conn_id: id, limited_for } if *id == conn_id => Some(*limited_for),
+        _ => None,
+    });
+    assert!(limited.is_some_and(|limited| limited >= Duration::from_millis(200)), "{:?}", events);
+    assert!(events.contains(&ServerEvent::Disconnected { conn_id, reason: DisconnectReason::RateLimited }));
+}
+
+#[tokio::test]
+async fn rate_limited_peer_sees_the_close_reason() {
+    let policy = RateLimitPolicy::Disconnect { after: Duration::from_millis(100) };
+    let config = ServerConfig::default().with_rate_limit(10_000, 1000).with_rate_limit_policy(policy);
+    let (mut manager, port) = start_server(config).await;
+    let client = connect(port, ClientConfig::default()).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    let (mut reader, mut writer) = client.split();
+    tokio::spawn(async move { while writer.send_msg(&pattern(1000)).await.is_ok() {} });
+    tokio::spawn(async move { while server.recv_msg().await.is_ok() {} });
+    let closed = tokio::time::timeout(WAIT, reader.recv()).await.unwrap();
+    match closed {
+        Err(VirgeError::PeerClosed { code, .. }) => assert_eq!(code, CLOSE_RATE_LIMITED),
+        other => panic!("expected the server to close with CLOSE_RATE_LIMITED, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn listener_hands_over_to_new_manager() {
+    let (mut old, port) = start_server(ServerConfig::default()).await;
+    let mut client = connect(port, ClientConfig::default()).await;
+    let server = tokio::time::timeout(WAIT, old.accept()).await.unwrap().unwrap();
+    tokio::spawn(echo(server));
+
+    // 交出监听套接字后，已交付的连接不受影响，新连接在监听队列中等待接管
+    let fd = old.into_raw_listener().unwrap();
+    client.send_msg(b"still here").await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap(), b"still here");
+    let pending = tokio::spawn(async move { connect(port, ClientConfig::default()).await });
+
+    let config = ServerConfig::builder()
+        .chunk_size(1024)
+        .build()
+        .unwrap()
+        .with_transport_kind(TransportKind::Tcp);
+    // SAFETY: fd 由 into_raw_listener 返回，此后只由新的管理器持有
+    let mut new = unsafe { ServerManager::from_raw_listener(fd, config.clone()) }.await.unwrap();
+    assert!(new.is_running());
+    assert_eq!(new.local_addr().unwrap().port(), port);
+    let server = tokio::time::timeout(WAIT, new.accept()).await.unwrap().unwrap();
+    tokio::spawn(echo(server));
+    let mut client = tokio::time::timeout(WAIT, pending).await.unwrap().unwrap();
+    client.send_msg(b"upgraded").await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap(), b"upgraded");
+
+    // 不是监听套接字的描述符被拒绝，仍归调用方所有
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    // SAFETY: 校验失败时描述符不会被接管，socket 仍持有它
+    let result = unsafe { ServerManager::from_raw_listener(socket.as_raw_fd(), config) }.await;
+    assert!(matches!(result, Err(VirgeError::ConfigError(_))), "{:?}", result.err());
+    assert!(socket.local_addr().is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn concurrent_handle_senders_do_not_interleave() {
+    const SENDERS: u8 = 8;
+    const COUNT: u32 = 100;
+    // 每条消息跨越多个数据块，不同消息的分块交错时会被接收端发现
+    fn message_len(sender: u8) -> usize {
+        3000 + sender as usize * 517
+    }
+
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let server = tokio::spawn(async move {
+        let mut server = manager.accept().await.unwrap();
+        let mut next = [0u32; SENDERS as usize];
+        for _ in 0..SENDERS as u32 * COUNT {
+            let message = server.recv().await.unwrap();
+            // 消息头为发送方编号与其序号，其余字节全部等于发送方编号
+            let sender = message[0];
+            let seq = u32::from_le_bytes(message[1..5].try_into().unwrap());
+            assert_eq!(message.len(), message_len(sender), "message from sender {} was split or merged", sender);
+            assert!(message[5..].iter().all(|&b| b == sender), "message from sender {} is interleaved", sender);
+            assert_eq!(seq, next[sender as usize], "messages from sender {} arrived out of order", sender);
+            next[sender as usize] += 1;
+        }
+        server
+    });
+
+    let handle = connect(port, ClientConfig::default()).await.handle();
+    let senders = (0..SENDERS).map(|sender| {
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            for seq in 0..COUNT {
+                let mut message = vec![sender; message_len(sender)];
+                message[1..5].copy_from_slice(&seq.to_le_bytes());
+                handle.send(message).await.unwrap();
+            }
+        })
+    }).collect::<Vec<_>>();
+    for sender in senders {
+        tokio::time::timeout(WAIT, sender).await.unwrap().unwrap();
+    }
+
+    let mut server = tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+    handle.disconnect().await.unwrap();
+    server.disconnect().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn server_handle_is_shared_by_responder_and_logger() {
+    const REQUESTS: usize = 50;
+    const LOGS: usize = 50;
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let mut client = connect(port, ClientConfig::default()).await;
+    let handle = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap().handle();
+
+    // 应答与日志两个子系统各持一个克隆，在不同的工作线程上同时发送跨越多个数据块的消息
+    let responder = {
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            for _ in 0..REQUESTS {
+                let mut request = handle.recv().await.unwrap();
+                request.insert(0, b'R');
+                handle.send(request).await.unwrap();
+            }
+        })
+    };
+    let logger = {
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            for i in 0..LOGS {
+                let mut line = vec![b'L'; 2500 + i];
+                line[1] = i as u8;
+                handle.send(line).await.unwrap();
+            }
+        })
+    };
+
+    for i in 0..REQUESTS {
+        client.send(vec![i as u8; 1500 + i]).await.unwrap();
+    }
+    let (mut replies, mut logs) = (0, 0);
+    while replies < REQUESTS || logs < LOGS {
+        let message = tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap();
+        match message[0] {
+            b'R' => {
+                assert_eq!(message.len(), 1 + 1500 + replies, "reply {} was split or merged", replies);
+                assert!(message[1..].iter().all(|&b| b == replies as u8), "reply {} is interleaved", replies);
+                replies += 1;
+            }
+            b'L' => {
+                assert_eq!(message.len(), 2500 + logs, "log line {} was split or merged", logs);
+                assert_eq!(message[1], logs as u8);
+                assert!(message[2..].iter().all(|&b| b == b'L'), "log line {} is interleaved", logs);
+                logs += 1;
+            }
+            other => panic!("unexpected message tag {}", other),
+        }
+    }
+    tokio::time::timeout(WAIT, responder).await.unwrap().unwrap();
+    tokio::time::timeout(WAIT, logger).await.unwrap().unwrap();
+    assert!(handle.is_connected());
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn broadcast_reaches_tracked_handles() {
+    let (mut manager, port) = start_server(ServerConfig::default().with_connection_tracking(true)).await;
+
+    let mut first = connect(port, ClientConfig::default()).await;
+    let first_handle = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap().handle();
+    let mut second = connect(port, ClientConfig::default()).await;
+    let second_handle = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap().handle();
+    // 未转换为句柄的连接由调用方独占，不参与广播
+    let _plain_client = connect(port, ClientConfig::default()).await;
+    let plain = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    assert_eq!(manager.tracked_connections(), 2);
+
+    let report = manager.broadcast(b"reload").await;
+    assert_eq!(report.len(), 2);
+    assert_eq!(report.delivered(), 2);
+    assert_eq!(report.failures().count(), 0);
+    assert!(!report.results().contains_key(&plain.id()));
+    assert_eq!(tokio::time::timeout(WAIT, first.recv()).await.unwrap().unwrap(), b"reload");
+    assert_eq!(tokio::time::timeout(WAIT, second.recv()).await.unwrap().unwrap(), b"reload");
+
+    // 句柄全部释放后连接从登记表中移除
+    drop(second_handle);
+    assert_eq!(manager.tracked_connections(), 1);
+    let report = manager.broadcast(b"again").await;
+    assert_eq!(report.results().keys().copied().collect::<Vec<_>>(), vec![first_handle.id()]);
+    assert_eq!(tokio::time::timeout(WAIT, first.recv()).await.unwrap().unwrap(), b"again");
+}
+
+#[tokio::test]
+async fn auth_token_gates_accepted_connections() {
+    let config = ServerConfig::default().with_required_auth_token(b"open-sesame".to_vec());
+    let (mut manager, port) = start_server(config).await;
+    let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = events.clone();
+    manager.on_event(move |event| sink.lock().unwrap().push(event));
+
+    // 未出示令牌与令牌错误的连接完成握手后即被关闭，并带有认证失败的原因码
+    for config in [ClientConfig::default(), ClientConfig::default().with_auth_token(b"open-sesame!".to_vec())] {
+        let mut client = connect(port, config).await;
+        match tokio::time::timeout(WAIT, client.recv()).await.unwrap() {
+            Err(VirgeError::PeerClosed { code, .. }) => assert_eq!(code, CLOSE_AUTH_FAILED),
+            other => panic!("expected the server to close with CLOSE_AUTH_FAILED, got {:?}", other),
+        }
+    }
+    assert_eq!(manager.rejected_connections(), 2);
+    let rejected = events.lock().unwrap().iter().filter(|event| {
+        matches!(event, ServerEvent::Rejected { reason: RejectReason::AuthFailed, .. })
+    }).count();
+    assert_eq!(rejected, 2);
+
+    // 只有出示正确令牌的连接交给 accept()，令牌作为对端身份保留
+    let mut client = connect(port, ClientConfig::default().with_auth_token(b"open-sesame".to_vec())).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    assert_eq!(server.peer_identity(), Some(&b"open-sesame"[..]));
+    client.send(b"hello".to_vec()).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap(), b"hello");
+
+    // 超长的令牌在本地被拒绝
+    let oversized = vec![0u8; virga::protocol::MAX_AUTH_TOKEN_SIZE + 1];
+    let config = client_for(port, ClientConfig::default().with_auth_token(oversized));
+    let err = VirgeClient::new(config).connect().await.unwrap_err();
+    assert!(matches!(err, VirgeError::ConfigError(_)), "{}", err);
+}
+
+#[tokio::test]
+async fn oversized_messages_are_dropped_or_streamed() {
+    const LIMIT: usize = 64 * 1024;
+    let (mut manager, port) = start_server(ServerConfig::default().with_max_reassembly_bytes(Some(LIMIT))).await;
+
+    let payload = pattern(4 * LIMIT + 3);
+    let expected = payload.clone();
+    let server = tokio::spawn(async move {
+        let mut server = manager.accept().await.unwrap();
+
+        // 超限的消息被丢弃，连接保持可用
+        assert!(matches!(server.recv_msg().await, Err(VirgeError::MessageTooLarge { .. })));
+        assert_eq!(server.recv_msg().await.unwrap(), b"after");
+
+        // 流式接收不受组装上限限制，逐块读出完整负载
+        let mut reader = server.recv_stream().await.unwrap();
+        assert_eq!(reader.len(), expected.len() as u64);
+        let mut received = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let n = reader.read(&mut chunk).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            assert!(n <= LIMIT);
+            received.extend_from_slice(&chunk[..n]);
+        }
+        assert!(received == expected, "streamed payload differs from the original");
+
+        // 未读完就释放的读取器不会让剩余负载混入下一条消息
+        let mut reader = server.recv_stream().await.unwrap();
+        assert_eq!(reader.read(&mut chunk[..10]).await.unwrap(), 10);
+        assert_eq!(reader.remaining(), expected.len() as u64 - 10);
+        drop(reader);
+        assert_eq!(server.recv_msg().await.unwrap(), b"tail");
+        echo(server).await;
+    });
+
+    let mut client = connect(port, ClientConfig::default()).await;
+    client.send_msg(&payload).await.unwrap();
+    client.send_msg(b"after").await.unwrap();
+    client.send_file(&mut &payload[..], payload.len() as u64).await.unwrap();
+    client.send_file(&mut &payload[..], payload.len() as u64).await.unwrap();
+    client.send_msg(b"tail").await.unwrap();
+
+    // 服务器丢弃超限消息之后仍正常回显
+    client.send_msg(b"still alive").await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap(), b"still alive");
+    client.disconnect().await.unwrap();
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn message_reader_streams_oversized_messages_through_async_read() {
+    use tokio::io::AsyncReadExt;
+    const LIMIT: usize = 64 * 1024;
+    let (mut manager, port) = start_server(ServerConfig::default().with_max_reassembly_bytes(Some(LIMIT))).await;
+
+    let payload = pattern(4 * LIMIT + 3);
+    let expected = payload.clone();
+    let server = tokio::spawn(async move {
+        let mut server = manager.accept().await.unwrap();
+
+        // 超过组装上限的消息经 io::copy 逐块读出，读完时返回 EOF
+        let mut reader = server.recv_stream().await.unwrap();
+        let mut received = Vec::new();
+        let copied = tokio::io::copy(&mut reader, &mut received).await.unwrap();
+        assert_eq!(copied, expected.len() as u64);
+        assert!(received == expected, "copied payload differs from the original");
+
+        // 分块发送的消息同样可以读到结尾
+        let mut reader = server.recv_stream().await.unwrap();
+        let mut received = Vec::new();
+        reader.read_to_end(&mut received).await.unwrap();
+        assert!(received == expected, "chunked payload differs from the original");
+        drop(reader);
+        assert_eq!(server.recv_msg().await.unwrap(), b"tail");
+        echo(server).await;
+    });
+
+    let mut client = connect(port, ClientConfig::default()).await;
+    client.send_msg(&payload).await.unwrap();
+    let mut writer = client.start_message(0).await.unwrap();
+    writer.write_all(&payload).await.unwrap();
+    writer.finish().await.unwrap();
+    client.send_msg(b"tail").await.unwrap();
+
+    client.send_msg(b"still alive").await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap(), b"still alive");
+    client.disconnect().await.unwrap();
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn message_writer_builds_messages_in_place() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let payload = pattern(3 * 1024 + 17);
+    let expected = payload.clone();
+    let server = tokio::spawn(async move {
+        let mut server = manager.accept().await.unwrap();
+
+        // 分块发送的消息与普通消息一样整条接收
+        assert_eq!(server.recv_msg().await.unwrap(), expected);
+        assert_eq!(server.recv_msg().await.unwrap(), b"");
+
+        // 未完成的消息以 MessageAborted 结束，之后的消息不受影响
+        assert!(matches!(server.recv_msg().await, Err(VirgeError::MessageAborted)));
+        assert_eq!(server.recv_msg().await.unwrap(), b"after abort");
+
+        // 流式接收逐块读出分块消息
+        let mut reader = server.recv_stream().await.unwrap();
+        assert_eq!(reader.len(), u64::MAX);
+        let mut received = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let n = reader.read(&mut chunk).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            received.extend_from_slice(&chunk[..n]);
+        }
+        assert!(received == expected, "streamed payload differs from the original");
+
+        let mut reader = server.recv_stream().await.unwrap();
+        let err = loop {
+            match reader.read(&mut chunk).await {
+                Ok(n) => assert!(n > 0, "aborted message ended normally"),
+                Err(e) => break e,
+            }
+        };
+        assert!(matches!(err, VirgeError::MessageAborted), "{}", err);
+        drop(reader);
+        assert_eq!(server.recv_msg().await.unwrap(), b"tail");
+        echo(server).await;
+    });
+
+    let mut client = connect(port, ClientConfig::default()).await;
+    assert!(matches!(client.start_message(usize::MAX).await, Err(VirgeError::MessageTooLarge { .. })));
+
+    let mut writer = client.start_message(payload.len()).await.unwrap();
+    assert_eq!(writer.write(&payload[..100]).await.unwrap(), 100);
+    writer.write_all(&payload[100..]).await.unwrap();
+    assert_eq!(writer.written(), payload.len());
+    writer.finish().await.unwrap();
+    client.start_message(0).await.unwrap().finish().await.unwrap();
+
+    // 已发出部分数据后放弃
+    let mut writer = client.start_message(0).await.unwrap();
+    writer.write_all(&payload).await.unwrap();
+    drop(writer);
+    client.send_msg(b"after abort").await.unwrap();
+
+    // 尚未发出任何数据就放弃的消息不会出现在线路上
+    let mut writer = client.start_message(0).await.unwrap();
+    writer.write_all(b"never sent").await.unwrap();
+    drop(writer);
+
+    let mut writer = client.start_message(0).await.unwrap();
+    writer.write_all(&payload).await.unwrap();
+    writer.finish().await.unwrap();
+    let mut writer = client.start_message(0).await.unwrap();
+    writer.write_all(&payload).await.unwrap();
+    drop(writer);
+    client.send_msg(b"tail").await.unwrap();
+
+    client.send_msg(b"still alive").await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap(), b"still alive");
+    client.disconnect().await.unwrap();
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn probe_detects_closed_peer() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let mut client = connect(port, ClientConfig::default()).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    assert!(client.probe_connection(Duration::from_millis(100)).await.unwrap());
+
+    server.disconnect().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    // is_connected 只读取本地状态，在下一次接收之前尚未察觉对端已关闭
+    assert!(client.is_connected());
+    assert!(!client.probe_connection(Duration::from_millis(100)).await.unwrap());
+}
+
+#[tokio::test]
+async fn keepalive_detects_a_silent_peer() {
+    const INTERVAL: Duration = Duration::from_millis(100);
+    const TIMEOUT: Duration = Duration::from_millis(300);
+    let config = ServerConfig::default().with_keepalive(INTERVAL, TIMEOUT);
+    let (mut manager, port) = start_server(config).await;
+    // 服务器建立连接后不再接收，也就不会应答客户端的 PING，相当于来宾突然停止响应
+    let (release, released) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(async move {
+        let server = manager.accept().await.unwrap();
+        let _ = released.await;
+        drop(server);
+    });
+
+    let mut client = connect(port, ClientConfig::default().with_keepalive(INTERVAL, TIMEOUT)).await;
+    let start = std::time::Instant::now();
+    let err = tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap_err();
+    let elapsed = start.elapsed();
+    assert!(
+        matches!(err, VirgeError::ConnectionError { ref message, .. } if message == "peer timed out"),
+        "{}",
+        err
+    );
+    // 空闲 INTERVAL 后发出 PING，再等待 TIMEOUT；留出调度余量
+    assert!(elapsed >= TIMEOUT, "detected after only {:?}", elapsed);
+    assert!(elapsed < INTERVAL + TIMEOUT + Duration::from_secs(1), "detected after {:?}", elapsed);
+    assert!(!client.is_connected());
+
+    release.send(()).unwrap();
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn keepalive_probe_keeps_pending_messages() {
+    let config = ServerConfig::default().with_keepalive(Duration::from_secs(1), Duration::from_secs(5));
+    let (mut manager, port) = start_server(config).await;
+    let server = tokio::spawn(async move {
+        let mut server = manager.accept().await.unwrap();
+        server.send_msg(b"hello").await.unwrap();
+        echo(server).await;
+    });
+
+    let config = ClientConfig::default().with_keepalive(Duration::from_secs(1), Duration::from_secs(5));
+    let mut client = connect(port, config).await;
+
+    // 探测期间先到达的消息被暂存，之后的 recv_msg 照常读到
+    assert!(client.probe_connection(WAIT).await.unwrap());
+    assert_eq!(tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap(), b"hello");
+    client.send_msg(b"echo").await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap(), b"echo");
+    client.disconnect().await.unwrap();
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn stalled_handshake_does_not_block_accept() {
+    let config = ServerConfig::default().with_handshake_timeout(Duration::from_millis(200));
+    let (mut manager, port) = start_server(config).await;
+
+    // 只建立 TCP 连接、从不发送握手消息的对端排在正常客户端之前
+    let stalled = tokio::net::TcpStream::connect(("127.0.0.1", port as u16)).await.unwrap();
+    let client = tokio::spawn(async move { connect(port, ClientConfig::default()).await });
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    let mut client = client.await.unwrap();
+    client.send_msg(b"hello").await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, server.recv_msg()).await.unwrap().unwrap(), b"hello");
+
+    // 超过握手时限的连接被关闭并计数，accept 只得到其错误
+    tokio::time::timeout(WAIT, async {
+        while manager.failed_handshakes() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    let failed = manager.try_accept().await;
+    assert!(matches!(failed, Err(VirgeError::Timeout(_))), "{:?}", failed.map(|server| server.is_some()));
+    assert!(manager.try_accept().await.unwrap().is_none());
+    drop(stalled);
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn incoming_yields_failed_handshakes_as_errors() {
+    use futures::StreamExt;
+    let config = ServerConfig::default().with_handshake_timeout(Duration::from_millis(200));
+    let (mut manager, port) = start_server(config).await;
+
+    // 握手超时的连接以错误产出，流继续产出之后的正常连接
+    let stalled = tokio::net::TcpStream::connect(("127.0.0.1", port as u16)).await.unwrap();
+    let mut incoming = std::pin::pin!(manager.incoming());
+    let failed = tokio::time::timeout(WAIT, incoming.next()).await.unwrap().unwrap();
+    assert!(matches!(failed, Err(VirgeError::Timeout(_))), "{:?}", failed.map(|server| server.id()));
+    drop(stalled);
+
+    let client = tokio::spawn(async move { connect(port, ClientConfig::default()).await });
+    let server = tokio::time::timeout(WAIT, incoming.next()).await.unwrap().unwrap();
+    assert!(server.is_ok(), "{:?}", server.err());
+    client.await.unwrap().disconnect().await.unwrap();
+}
+
+/// 对端从不接收时反复发送 64 KiB 的消息直到出错，返回出错前发送成功的消息数与该错误
+async fn send_until_error(client: &mut VirgeClient) -> (usize, VirgeError) {
+    let message = pattern(64 * 1024);
+    // 至多 256 MiB，远超套接字缓冲区
+    for sent in 0..4096 {
+        if let Err(e) = client.send(message.clone()).await {
+            return (sent, e);
+        }
+    }
+    panic!("send kept succeeding although the peer never reads");
+}
+
+#[tokio::test]
+async fn blocking_send_to_an_unresponsive_peer_times_out() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let mut client = connect(port, ClientConfig::default().with_send_buffer_limit(16 * 1024)).await;
+    let _server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    client.set_write_timeout(Some(Duration::from_millis(200))).unwrap();
+
+    // 缓冲区填满后 send 等待可用空间，超过写超时返回 Timeout
+    let (sent, err) = tokio::time::timeout(WAIT, send_until_error(&mut client)).await.unwrap();
+    assert!(sent > 0, "no message fit into the socket buffers");
+    assert!(matches!(err, VirgeError::Timeout(_)), "{:?}", err);
+}
+
+#[tokio::test]
+async fn nonblocking_send_to_an_unresponsive_peer_would_block() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let config = ClientConfig::default().with_send_buffer_limit(16 * 1024).with_nonblocking_send(true);
+    let mut client = connect(port, config).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    // 缓冲区填满后 send 立即返回 WouldBlock，不会留下半条消息
+    let (sent, err) = tokio::time::timeout(WAIT, send_until_error(&mut client)).await.unwrap();
+    assert!(sent > 0, "no message fit into the socket buffers");
+    assert_eq!(err.io_kind(), Some(std::io::ErrorKind::WouldBlock), "{:?}", err);
+    let expected = pattern(64 * 1024);
+    for _ in 0..sent {
+        let message = tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap();
+        assert!(message == expected, "buffered message differs from the original");
+    }
+
+    // 对端读完后可以继续发送
+    client.send(b"after".to_vec()).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap(), b"after");
+}
+
+#[tokio::test]
+async fn drain_pending_messages() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let mut client = connect(port, ClientConfig::default()).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    assert!(!server.has_pending_message().await.unwrap());
+
+    for message in [&b"one"[..], b"two", b"three"] {
+        client.send(message.to_vec()).await.unwrap();
+    }
+    tokio::time::timeout(WAIT, async {
+        while !server.has_pending_message().await.unwrap() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert!(server.pending_bytes() > 0);
+
+    let mut received = Vec::new();
+    while received.len() < 3 {
+        if server.has_pending_message().await.unwrap() {
+            received.push(server.recv().await.unwrap());
+        } else {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+    assert_eq!(received, [&b"one"[..], b"two", b"three"]);
+    assert!(!server.has_pending_message().await.unwrap());
+    assert_eq!(server.pending_bytes(), 0);
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn disconnect_reason_reaches_peer() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let mut client = connect(port, ClientConfig::default()).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    server.send(b"bye".to_vec()).await.unwrap();
+    server.disconnect_with_reason(4001, "server shutting down").await.unwrap();
+
+    // 关闭前发出的消息先于关闭原因交付
+    assert_eq!(tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap(), b"bye");
+    match tokio::time::timeout(WAIT, client.recv()).await.expect("recv hung after peer disconnect") {
+        Err(VirgeError::PeerClosed { code, message }) => {
+            assert_eq!(code, 4001);
+            assert_eq!(message, "server shutting down");
+        }
+        other => panic!("expected PeerClosed, got {:?}", other),
+    }
+    assert!(matches!(client.recv().await, Err(VirgeError::PeerClosed { code: 4001, .. })));
+    assert!(!client.is_connected());
+}
+
+#[tokio::test]
+async fn recv_loop_pushes_messages_until_break() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let mut client = connect(port, ClientConfig::default()).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    for message in [&b"one"[..], b"two", b"stop", b"after"] {
+        client.send(message.to_vec()).await.unwrap();
+    }
+    let mut received = Vec::new();
+    let result = tokio::time::timeout(WAIT, server.run_recv_loop(|data| {
+        if data == b"stop" {
+            return ControlFlow::Break(());
+        }
+        received.push(data);
+        ControlFlow::Continue(())
+    }))
+    .await
+    .unwrap();
+    result.unwrap();
+    assert_eq!(received, [b"one".to_vec(), b"two".to_vec()]);
+    // Break 之后的消息留给后续接收
+    assert_eq!(server.recv().await.unwrap(), b"after");
+
+    // 回调 panic 时断开连接并返回错误，客户端读到流结束
+    client.send(b"boom".to_vec()).await.unwrap();
+    let err = server.run_recv_loop(|_| panic!("callback failure")).await.unwrap_err();
+    assert!(err.to_string().contains("panicked"), "{}", err);
+    assert!(!server.is_connected());
+    let result = tokio::time::timeout(WAIT, client.recv()).await.expect("recv hung after peer disconnect");
+    assert_eq!(result.unwrap(), Vec::<u8>::new());
+}
+
+#[tokio::test]
+async fn recv_loop_ends_when_peer_closes() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let mut client = connect(port, ClientConfig::default()).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    client.send(b"last".to_vec()).await.unwrap();
+    client.disconnect().await.unwrap();
+    let mut count = 0;
+    tokio::time::timeout(WAIT, server.run_recv_loop(|_| {
+        count += 1;
+        ControlFlow::Continue(())
+    }))
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[cfg(feature = "compression-lz4")]
+#[tokio::test]
+async fn compressed_connection_round_trips_random_and_compressible_data() {
+    let compression = Some(virga::Compression::Lz4);
+    let (mut manager, port) = start_server(ServerConfig::default().with_compression(compression)).await;
+    let mut client = connect(port, ClientConfig::default().with_compression(compression)).await;
+    let server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    tokio::spawn(echo(server));
+
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    let random: Vec<u8> = (0..256 * 1024)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    let compressible = br#"{"cpu":12,"mem_kib":4096,"state":"running"}"#.repeat(4096);
+    for payload in [random, compressible, b"short".to_vec()] {
+        client.send_msg(&payload).await.unwrap();
+        let echoed = tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap();
+        assert!(echoed == payload, "payload of {} bytes differs after the round trip", payload.len());
+    }
+}
+
+#[cfg(feature = "compression-lz4")]
+#[tokio::test]
+async fn uncompressed_peer_is_rejected_in_the_handshake() {
+    let (_manager, port) = start_server(ServerConfig::default().with_compression(Some(virga::Compression::Lz4))).await;
+    let err = VirgeClient::new(client_for(port, ClientConfig::default())).connect().await.unwrap_err();
+    assert!(matches!(err, VirgeError::ProtocolError(ref message) if message.contains("Compression")), "{:?}", err);
+}
+
+#[cfg(feature = "encryption")]
+#[tokio::test]
+async fn encrypted_connection_round_trips_and_rekeys() {
+    let key = [7u8; 32];
+    let (mut manager, port) = start_server(ServerConfig::default().with_encryption_key(key)).await;
+    let mut client = connect(port, ClientConfig::default().with_encryption_key(key)).await;
+    let server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    tokio::spawn(echo(server));
+
+    let payload = pattern(200 * 1024);
+    client.send_msg(&payload).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap(), payload);
+
+    // 重连后以新的临时密钥重新交换，收发照常
+    client.reconnect().await.unwrap();
+    let server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    tokio::spawn(echo(server));
+    client.send_msg(b"after reconnect").await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap(), b"after reconnect");
+
+    // 密钥不会出现在调试输出中
+    let debug = format!("{:?}", client_for(port, ClientConfig::default().with_encryption_key(key)));
+    assert!(debug.contains("PresharedKey(..)"), "{}", debug);
+    assert!(!debug.contains("7, 7, 7"), "{}", debug);
+}
+
+#[cfg(feature = "encryption")]
+#[tokio::test]
+async fn encryption_key_mismatch_fails_the_handshake() {
+    let (manager, port) = start_server(ServerConfig::default().with_encryption_key([1u8; 32])).await;
+    let mut client = VirgeClient::new(client_for(port, ClientConfig::default().with_encryption_key([2u8; 32])));
+    let err = client.connect().await.unwrap_err();
+    assert!(matches!(err, VirgeError::EncryptionError(_)), "{:?}", err);
+
+    // 服务器同样发现密钥不一致，连接不会交给 accept()
+    tokio::time::timeout(WAIT, async {
+        while manager.failed_handshakes() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[cfg(feature = "encryption")]
+#[tokio::test]
+async fn encryption_must_match_peer() {
+    // 不加密的客户端连接加密的服务器
+    let (_manager, port) = start_server(ServerConfig::default().with_encryption_key([3u8; 32])).await;
+    let err = VirgeClient::new(client_for(port, ClientConfig::default())).connect().await.unwrap_err();
+    assert!(matches!(err, VirgeError::EncryptionError(_)), "{:?}", err);
+
+    // 加密的客户端连接不加密的服务器
+    let (_manager, port) = start_server(ServerConfig::default()).await;
+    let config = client_for(port, ClientConfig::default().with_encryption_key([3u8; 32]));
+    let err = VirgeClient::new(config.clone()).connect().await.unwrap_err();
+    assert!(matches!(err, VirgeError::EncryptionError(_)), "{:?}", err);
+
+    // 加密需要握手
+    let config = config.with_handshake(false);
+    assert!(matches!(config.validate(), Err(VirgeError::ConfigError(_))));
+}
+
+#[tokio::test]
+async fn sequence_numbers_track_both_directions() {
+    let (mut manager, port) = start_server(ServerConfig::default().with_sequence_numbers(true)).await;
+    let mut client = connect(port, ClientConfig::default().with_sequence_numbers(true)).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    assert_eq!(client.last_sent_seq(), Some(0));
+    assert_eq!(server.last_received_seq(), Some(0));
+
+    for message in [&b"one"[..], b"two", b"three"] {
+        client.send(message.to_vec()).await.unwrap();
+        assert_eq!(tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap(), message);
+    }
+    assert_eq!(client.last_sent_seq(), Some(3));
+    assert_eq!(server.last_received_seq(), Some(3));
+
+    server.send(b"reply".to_vec()).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap(), b"reply");
+    assert_eq!(server.last_sent_seq(), Some(1));
+    assert_eq!(client.last_received_seq(), Some(1));
+
+    // 重连后发送序号延续，服务器的新连接从客户端的下一个序号开始接收
+    client.reconnect().await.unwrap();
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    client.send(b"four".to_vec()).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap(), b"four");
+    assert_eq!(client.last_sent_seq(), Some(4));
+    assert_eq!(server.last_received_seq(), Some(4));
+    assert_eq!(client.last_received_seq(), Some(0));
+}
+
+#[tokio::test]
+async fn sequence_numbers_must_match_peer() {
+    let (_manager, port) = start_server(ServerConfig::default()).await;
+    let mut client = VirgeClient::new(client_for(port, ClientConfig::default().with_sequence_numbers(true)));
+    let err = client.connect().await.unwrap_err();
+    assert!(matches!(err, VirgeError::ProtocolError(_)), "{:?}", err);
+
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let client = connect(port, ClientConfig::default()).await;
+    let server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    assert_eq!(client.last_sent_seq(), None);
+    assert_eq!(server.last_received_seq(), None);
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test]
+async fn duplicate_sequence_is_rejected() {
+    let (mut manager, port) = start_server(ServerConfig::default().with_sequence_numbers(true)).await;
+    // 客户端收到的第一条消息被重复交付一次
+    let config = ClientConfig::default()
+        .with_sequence_numbers(true)
+        .with_fault_plan(FaultPlan::new().with_duplicate(0));
+    let mut client = connect(port, config).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    server.send(b"first".to_vec()).await.unwrap();
+    server.send(b"second".to_vec()).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap(), b"first");
+    let err = tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap_err();
+    assert!(matches!(err, VirgeError::ProtocolError(ref message) if message.contains("Duplicate")), "{:?}", err);
+    // 重复的消息被丢弃，连接保持可用
+    assert_eq!(tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap(), b"second");
+    assert_eq!(client.last_received_seq(), Some(2));
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test]
+async fn integrity_detects_a_byte_flipped_in_flight() {
+    let (mut manager, port) = start_server(ServerConfig::default().with_integrity(true)).await;
+    // 客户端收到的第一条消息在校验之前被翻转一个字节
+    let config = ClientConfig::default()
+        .with_integrity(true)
+        .with_fault_plan(FaultPlan::new().with_corrupt(0, 3));
+    let mut client = connect(port, config).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    server.send(pattern(64)).await.unwrap();
+    server.send(pattern(32)).await.unwrap();
+    let err = tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap_err();
+    assert!(
+        matches!(err, VirgeError::IntegrityError { expected, actual } if expected != actual),
+        "{:?}",
+        err
+    );
+    // 损坏的消息被丢弃，连接保持可用
+    assert_eq!(tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap(), pattern(32));
+    assert!(client.is_connected());
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test]
+async fn send_retry_rides_out_transient_errors() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let plan = FaultPlan::new();
+    let config = ClientConfig::default().with_fault_plan(plan.clone()).with_send_retry(5, Duration::from_secs(1));
+    let mut client = connect(port, config).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    // 突发的 EAGAIN 在尝试次数内消退，消息照常送达
+    plan.fail_sends(3, libc::EAGAIN);
+    client.send(b"first".to_vec()).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap(), b"first");
+
+    // 超过尝试次数后返回附带尝试次数的错误，原始 errno 保留在 source 中
+    plan.fail_sends(10, libc::ENOBUFS);
+    let err = client.send(b"lost".to_vec()).await.unwrap_err();
+    assert!(err.to_string().contains("after 5 attempts"), "{}", err);
+    let source = std::error::Error::source(&err).and_then(|source| source.downcast_ref::<std::io::Error>());
+    assert_eq!(source.and_then(std::io::Error::raw_os_error), Some(libc::ENOBUFS));
+
+    // 连接重置不是暂时性错误，不经重试立即返回
+    plan.fail_sends(1, libc::ECONNRESET);
+    let err = client.send(b"reset".to_vec()).await.unwrap_err();
+    assert_eq!(err.io_kind(), Some(std::io::ErrorKind::ConnectionReset));
+    assert!(!err.to_string().contains("attempts"), "{}", err);
+
+    client.send(b"last".to_vec()).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap(), b"last");
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test]
+async fn connection_dropped_mid_message_fails_both_ends() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let config = ClientConfig::default().with_fault_plan(FaultPlan::new().with_drop_after_bytes(4096));
+    let mut client = connect(port, config).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    // 64 KiB 的消息按 1 KiB 分块发送，越过 4 KiB 后连接被断开
+    let receiver = tokio::spawn(async move { server.recv_msg().await });
+    let err = client.send_with_progress(&pattern(64 * 1024), |_, _| {}).await.unwrap_err();
+    assert!(matches!(err, VirgeError::ConnectionError { ref message, .. } if message.contains("4096")), "{:?}", err);
+    assert!(!client.is_connected());
+
+    // 对端收到半条消息后连接关闭
+    let err = tokio::time::timeout(WAIT, receiver).await.unwrap().unwrap().unwrap_err();
+    assert_eq!(err.io_kind(), Some(std::io::ErrorKind::UnexpectedEof), "{:?}", err);
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test]
+async fn write_failing_midway_reports_the_committed_bytes() {
+    const CHUNK: usize = 1024;
+    let (mut manager, port) = start_server(ServerConfig::default().with_chunk_size(CHUNK as u32)).await;
+    let config = ClientConfig::default()
+        .with_chunk_size(CHUNK as u32)
+        .with_fault_plan(FaultPlan::new().with_drop_after_bytes(16 * 1024));
+    let mut client = connect(port, config).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    // 64 KiB 按块分为多条消息写入，越过 16 KiB 后连接被断开，此前的各块已完整发出
+    let payload = pattern(64 * 1024);
+    let err = client.write(&payload).await.unwrap_err();
+    let VirgeError::PartialWrite { committed, ref source } = err else {
+        panic!("expected a partial write, got {:?}", err);
+    };
+    assert!(committed > 0 && committed < 16 * 1024 && committed % CHUNK == 0, "{} bytes committed", committed);
+    assert!(matches!(**source, VirgeError::ConnectionError { .. }), "{:?}", source);
+    assert!(err.is_disconnected());
+    let source = std::error::Error::source(&err).and_then(|source| source.downcast_ref::<VirgeError>());
+    assert!(matches!(source, Some(VirgeError::ConnectionError { .. })), "{:?}", source);
+
+    // 对端按序读到的恰好是已提交的前缀，之后连接关闭
+    let mut received = Vec::new();
+    let mut buf = vec![0u8; 4096];
+    loop {
+        match tokio::time::timeout(WAIT, server.read(&mut buf)).await.unwrap() {
+            Ok(0) | Err(_) => break,
+            Ok(n) => received.extend_from_slice(&buf[..n]),
+        }
+    }
+    assert!(received == payload[..committed], "received {} of {} committed bytes", received.len(), committed);
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test]
+async fn truncated_messages_and_injected_errors_reach_the_caller() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let plan = FaultPlan::new().with_truncate(0, 3);
+    let mut client = connect(port, ClientConfig::default().with_fault_plan(plan.clone())).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    server.send(pattern(100)).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap(), pattern(3));
+
+    // 注入的错误原样返回一次，之后恢复正常
+    plan.fail_next(VirgeError::Timeout("injected".to_string()));
+    let err = client.recv().await.unwrap_err();
+    assert!(matches!(err, VirgeError::Timeout(ref message) if message == "injected"), "{:?}", err);
+    server.send(b"intact".to_vec()).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap(), b"intact");
+
+    // 每次收发前注入的延迟
+    let plan = FaultPlan::new().with_latency(Duration::from_millis(100));
+    let mut slow = connect(port, ClientConfig::default().with_fault_plan(plan)).await;
+    let started = std::time::Instant::now();
+    slow.send(b"slow".to_vec()).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(100), "{:?}", started.elapsed());
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test]
+async fn injected_link_error_triggers_a_reconnect() {
+    let (mut manager, port) = start_server(ServerConfig::default()).await;
+    let plan = FaultPlan::new();
+    let config = ClientConfig::default()
+        .with_fault_plan(plan.clone())
+        .with_reconnect(3, Duration::from_millis(10));
+    let mut client = connect(port, config).await;
+    let mut first = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    client.send(b"before".to_vec()).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, first.recv()).await.unwrap().unwrap(), b"before");
+
+    // 链路错误使客户端重新连接，并在新连接上重发该消息
+    plan.fail_next(VirgeError::ConnectionError { message: "injected link failure".to_string(), source: None });
+    let sender = tokio::spawn(async move {
+        client.send(b"after".to_vec()).await.unwrap();
+        client
+    });
+    let mut second = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, second.recv()).await.unwrap().unwrap(), b"after");
+    assert!(tokio::time::timeout(WAIT, sender).await.unwrap().unwrap().is_connected());
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test]
+async fn recorded_traffic_replays_without_a_server() {
+    let path = std::env::temp_dir().join(format!("virga-e2e-replay-{}.log", std::process::id()));
+    let (mut manager, port) = start_server(ServerConfig::default().with_sequence_numbers(true)).await;
+    let recorder = TrafficRecorder::create(&path).unwrap();
+    let config = ClientConfig::default().with_sequence_numbers(true);
+    let mut client = connect(port, config.clone().with_recorder(recorder.clone())).await;
+    let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+
+    client.send(b"ping".to_vec()).await.unwrap();
+    let request = tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap();
+    server.send(request).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap(), b"ping");
+    client.disconnect().await.unwrap();
+    assert_eq!(recorder.connections(), 1);
+
+    let log = TrafficLog::open(&path).unwrap();
+    assert_eq!(log.connections(), vec![0]);
+    let layers = log.layers(0).unwrap();
+    assert!(layers.sequence && !layers.integrity, "{:?}", layers);
+    assert!(log.records.iter().any(|record| matches!(record.event, RecordedEvent::Connect)));
+    assert!(log.records.iter().any(|record| matches!(record.event, RecordedEvent::Disconnect)));
+
+    // 回放不需要服务器，收到的帧按录制顺序交付
+    let replay = ReplayTransport::from_log(&log, 0).unwrap();
+    let mut client = VirgeClient::replay(client_for(port, config.clone()), replay).await.unwrap();
+    client.send(b"ping".to_vec()).await.unwrap();
+    assert_eq!(client.recv().await.unwrap(), b"ping");
+    assert_eq!(client.last_received_seq(), Some(1));
+    // 录制的帧用尽后与对端关闭一致
+    assert!(client.recv().await.is_err());
+
+    // 包装器组合与录制时不一致时拒绝回放
+    let replay = ReplayTransport::from_log(&log, 0).unwrap();
+    let err = VirgeClient::replay(client_for(port, config.with_integrity(true)), replay).await.unwrap_err();
+    assert!(matches!(err, VirgeError::ConfigError(_)), "{:?}", err);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn port_any_binds_distinct_ephemeral_ports() {
+    let mut managers = Vec::new();
+    let mut ports = Vec::new();
+    for port in [ServerConfig::PORT_ANY, virga::VMADDR_PORT_ANY as u32] {
+        let config = ServerConfig::builder()
+            .listen_port(port)
+            .chunk_size(1024)
+            .build()
+            .unwrap()
+            .with_transport_kind(TransportKind::Tcp);
+        let mut manager = ServerManager::new(config);
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        manager.on_event(move |event| sink.lock().unwrap().push(event));
+        manager.start().await.unwrap();
+
+        // 实际端口在 accept() 之前即可获知，Listening 事件携带的也是实际端口
+        let port = manager.local_addr().unwrap().port();
+        assert_ne!(port, 0);
+        let listening: Vec<u32> = events.lock().unwrap().iter().filter_map(|event| match event {
+            ServerEvent::Listening { port } => Some(*port),
+            _ => None,
+        }).collect();
+        assert_eq!(listening, vec![port]);
+        ports.push(port);
+        managers.push(manager);
+    }
+    assert_ne!(ports[0], ports[1]);
+
+    // 报告的端口可直接用于连接
+    for (manager, port) in managers.iter_mut().zip(ports) {
+        let mut client = connect(port, ClientConfig::default()).await;
+        let mut server = tokio::time::timeout(WAIT, manager.accept()).await.unwrap().unwrap();
+        client.send(b"hello".to_vec()).await.unwrap();
+        assert_eq!(tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap(), b"hello");
+    }
+
+    // 多端口监听时由系统分配的端口可以重复列出，各自绑定到不同的端口
+    let config = ServerConfig::builder()
+        .ports(&[ServerConfig::PORT_ANY, ServerConfig::PORT_ANY])
+        .chunk_size(1024)
+        .build()
+        .unwrap()
+        .with_transport_kind(TransportKind::Tcp);
+    let mut manager = ServerManager::new(config);
+    manager.start().await.unwrap();
+    let addrs = manager.local_addrs();
+    assert_eq!(addrs.len(), 2);
+    assert_ne!(addrs[0].port(), addrs[1].port());
+}
@@ -0,0 +1,453 @@
+//! 内存传输测试：经 `new_in_memory` 直接相连的 `VirgeClient` 与 `VirgeServer`
+//!
+//! 不使用任何套接字，收发经过与真实连接相同的分帧与 ACK 代码，
+//! 运行方式：`cargo test --features testing --test memory`，类型化消息的测试另需 `serde`（或 `serde-json`）特性。
+
+use std::time::Duration;
+use virga::client::{ClientConfig, VirgeClient};
+use virga::server::{ServerConfig, VirgeServer};
+use virga::VirgeError;
+
+/// 单个测试中等待对端的最长时间，超过视为挂起
+const WAIT: Duration = Duration::from_secs(10);
+
+/// 每个字节都不同的测试数据，错位或重复的数据块会被比较发现
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// 以长度前缀消息原样回显，直到客户端断开
+async fn echo(mut server: VirgeServer) {
+    while let Ok(message) = server.recv_msg().await {
+        if message.is_empty() || server.send_msg(&message).await.is_err() {
+            break;
+        }
+    }
+    let _ = server.disconnect().await;
+}
+
+/// 多行文本，含空行、长行与末尾没有换行符的行
+const TEXT: &str = "first line\nsecond\n\na third line that is longer than any of the message pieces\n\
+                    \u{4e2d}\u{6587}\u{884c}\nno trailing newline";
+
+/// 按 `sizes` 循环给出的长度把 `TEXT` 切分为若干条消息，切分点与行边界无关，也可能落在多字节字符中间
+fn pieces(sizes: &[usize]) -> Vec<Vec<u8>> {
+    let mut rest = TEXT.as_bytes();
+    let mut pieces = Vec::new();
+    for &size in sizes.iter().cycle() {
+        if rest.is_empty() {
+            break;
+        }
+        let (piece, tail) = rest.split_at(size.min(rest.len()));
+        pieces.push(piece.to_vec());
+        rest = tail;
+    }
+    pieces
+}
+
+/// 每种切分方式下逐条发送 `TEXT` 的各个片段
+const CUTS: [&[usize]; 4] = [&[1], &[3, 7, 2], &[11, 1, 25, 4], &[200]];
+
+#[tokio::test]
+async fn server_lines_span_message_boundaries() {
+    use tokio::io::AsyncBufReadExt;
+    let expected: Vec<&str> = TEXT.lines().collect();
+    for sizes in CUTS {
+        let (mut client, server) = VirgeClient::new_in_memory(ClientConfig::default()).await.unwrap();
+        let reader = tokio::spawn(async move {
+            let mut lines = virga::VirgeServerAsync::from(server).lines();
+            let mut received = Vec::new();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                received.push(line);
+            }
+            received
+        });
+        for piece in pieces(sizes) {
+            client.send(piece).await.unwrap();
+        }
+        client.shutdown_write().await.unwrap();
+        let received = tokio::time::timeout(WAIT, reader).await.unwrap().unwrap();
+        assert_eq!(received, expected, "pieces of {:?}", sizes);
+    }
+}
+
+#[tokio::test]
+async fn client_read_line_and_read_until_span_message_boundaries() {
+    use tokio::io::AsyncBufReadExt;
+    let expected: Vec<&str> = TEXT.split_inclusive('\n').collect();
+    for sizes in CUTS {
+        let (mut client, mut server) = VirgeClient::new_in_memory(ClientConfig::default()).await.unwrap();
+        let writer = tokio::spawn(async move {
+            for piece in pieces(sizes) {
+                server.send(piece).await.unwrap();
+            }
+            server.shutdown_write().await.unwrap();
+            server
+        });
+
+        // 先用连接自身的 read_line，再交给 tokio 的 read_until，缓冲区中已收到的数据不会丢失
+        let mut received = Vec::new();
+        let mut line = String::new();
+        assert_eq!(client.read_line(&mut line).await.unwrap(), expected[0].len());
+        received.push(line);
+        let mut client = virga::VirgeClientAsync::from(client);
+        loop {
+            let mut line = Vec::new();
+            if tokio::time::timeout(WAIT, client.read_until(b'\n', &mut line)).await.unwrap().unwrap() == 0 {
+                break;
+            }
+            received.push(String::from_utf8(line).unwrap());
+        }
+        assert_eq!(received, expected, "pieces of {:?}", sizes);
+        tokio::time::timeout(WAIT, writer).await.unwrap().unwrap();
+    }
+}
+
+#[tokio::test]
+async fn request_handler_round_trips_without_sockets() {
+    let (mut client, server) = VirgeClient::new_in_memory(ClientConfig::default()).await.unwrap();
+    let handler = tokio::spawn(echo(server));
+
+    // 1 MiB 的消息远大于两端之间 1 KiB 的缓冲区，经过分块与背压
+    for payload in [b"ping".to_vec(), pattern(4096), pattern(1024 * 1024)] {
+        client.send_msg(&payload).await.unwrap();
+        let echoed = tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap();
+        assert!(echoed == payload, "payload of {} bytes differs after the round trip", payload.len());
+    }
+    client.disconnect().await.unwrap();
+    tokio::time::timeout(WAIT, handler).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn server_side_constructor_and_disconnect() {
+    let (mut server, mut client) = VirgeServer::new_in_memory(ServerConfig::default()).await.unwrap();
+    let (sent, received) = tokio::join!(server.send(b"hello".to_vec()), client.recv());
+    sent.unwrap();
+    assert_eq!(received.unwrap(), b"hello");
+
+    // 服务器断开后客户端读到流结束
+    server.disconnect().await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, client.recv()).await.unwrap().unwrap(), b"");
+}
+
+#[tokio::test]
+async fn chunk_size_is_honored() {
+    const LEN: usize = 64 * 1024;
+    for chunk_size in [4096u32, 16384] {
+        let config = ClientConfig::default().with_chunk_size(chunk_size);
+        let (mut client, mut server) = VirgeClient::new_in_memory(config).await.unwrap();
+        let mut chunks = 0;
+        let (sent, received) = tokio::join!(
+            client.send_with_progress(&pattern(LEN), |_, _| chunks += 1),
+            server.recv_msg(),
+        );
+        sent.unwrap();
+        assert!(received.unwrap() == pattern(LEN), "chunk_size {}: payload differs", chunk_size);
+        assert_eq!(chunks, LEN / chunk_size as usize, "chunk_size {}", chunk_size);
+    }
+}
+
+#[tokio::test]
+async fn chunk_size_outside_the_bounds_is_rejected() {
+    for chunk_size in [virga::MIN_CHUNK_SIZE - 1, virga::MAX_CHUNK_SIZE + 1] {
+        let config = ClientConfig::default().with_chunk_size(chunk_size as u32);
+        let err = VirgeClient::new_in_memory(config).await.err().unwrap();
+        assert!(matches!(err, VirgeError::ConfigError(_)), "chunk_size {}: {:?}", chunk_size, err);
+    }
+    let config = ClientConfig::default().with_chunk_size(virga::MIN_CHUNK_SIZE as u32);
+    assert!(VirgeClient::new_in_memory(config).await.is_ok());
+}
+
+#[tokio::test]
+async fn receive_buffers_are_reused_from_the_pool() {
+    for pool_size in [virga::DEFAULT_BUFFER_POOL_SIZE, 0] {
+        let config = ClientConfig::default().with_buffer_pool_size(pool_size);
+        let (mut client, mut server) = VirgeClient::new_in_memory(config).await.unwrap();
+
+        // 64 KiB 的消息经 1 KiB 的缓冲区分多次读取
+        for payload in [b"ping".to_vec(), pattern(64 * 1024)] {
+            let (sent, received) = tokio::join!(client.send_msg(&payload), server.recv_msg());
+            sent.unwrap();
+            assert!(received.unwrap() == payload, "pool of {}: payload differs", pool_size);
+        }
+        let stats = server.stats();
+        if pool_size == 0 {
+            assert_eq!((stats.buffer_pool_hits, stats.buffer_pool_misses), (0, 0));
+        } else {
+            // 读取依次进行，只有第一次读取需要新分配缓冲区
+            assert_eq!(stats.buffer_pool_misses, 1, "{:?}", stats);
+            assert!(stats.buffer_pool_hits >= 64, "{:?}", stats);
+        }
+    }
+}
+
+#[tokio::test]
+async fn interleaved_chunked_messages_fail_with_a_protocol_error() {
+    use virga::protocol::FrameHeader;
+    let (mut client, mut server) = VirgeClient::new_in_memory(ClientConfig::default()).await.unwrap();
+
+    // 分块消息 1 的第一块之后紧跟消息 2 的块，接收方不会把两者拼成一条消息
+    let mut first = FrameHeader::ChunkedMessage.to_bytes();
+    FrameHeader::Chunk { msg: 1, len: 3 }.encode_into(&mut first);
+    first.extend_from_slice(b"one");
+    let mut second = FrameHeader::Chunk { msg: 2, len: 3 }.to_bytes();
+    second.extend_from_slice(b"two");
+    FrameHeader::ChunkEnd { msg: 1 }.encode_into(&mut second);
+    let sends = async {
+        client.send(first).await?;
+        client.send(second).await
+    };
+    let (sent, received) = tokio::join!(sends, server.recv_msg());
+    sent.unwrap();
+    let interleaved = matches!(&received, Err(VirgeError::ProtocolError(m)) if m.contains("interleaved"));
+    assert!(interleaved, "{:?}", received);
+
+    // start_message 为每条消息分配新的序号，连续的分块消息照常接收
+    for payload in [b"alpha".to_vec(), pattern(3000)] {
+        let mut writer = client.start_message(0).await.unwrap();
+        let sent = async {
+            writer.write_all(&payload).await?;
+            writer.finish().await
+        };
+        let (sent, received) = tokio::join!(sent, server.recv_msg());
+        sent.unwrap();
+        assert!(received.unwrap() == payload);
+    }
+}
+
+#[tokio::test]
+async fn message_writer_sends_each_chunk_as_it_fills() {
+    const CHUNK: usize = 64 * 1024;
+    // 远大于默认的消息上限，写入器与接收方都不在内存中保留整条消息
+    const TOTAL: usize = 64 * 1024 * 1024;
+    let config = ClientConfig::default().with_chunk_size(CHUNK as u32).with_max_message_size(virga::GIB);
+    let (mut client, mut server) = VirgeClient::new_in_memory(config).await.unwrap();
+
+    let reader = tokio::spawn(async move {
+        // 第 i 个字节为 i % 251，从任意位置开始的一段都能在参照数据中找到
+        let reference = pattern(251 + CHUNK);
+        let mut stream = server.recv_stream().await.unwrap();
+        let mut buf = vec![0u8; CHUNK];
+        let mut received = 0;
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            assert!(buf[..n] == reference[received % 251..][..n], "data differs at byte {}", received);
+            received += n;
+        }
+        received
+    });
+
+    // 每段长度都是 251 的整数倍，首尾相接仍是连续的测试数据；对端接收时写入才能继续，块写满即发出
+    let block = pattern(251 * 4096);
+    let mut writer = client.start_message(TOTAL).await.unwrap();
+    while writer.written() < TOTAL {
+        let len = block.len().min(TOTAL - writer.written());
+        writer.write_all(&block[..len]).await.unwrap();
+        assert!(writer.buffered() < CHUNK, "{} bytes buffered", writer.buffered());
+    }
+    writer.finish().await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, reader).await.unwrap().unwrap(), TOTAL);
+}
+
+#[tokio::test]
+async fn message_writer_is_an_async_write() {
+    use tokio::io::AsyncWriteExt;
+    let config = ClientConfig::default().with_chunk_size(1024);
+    let (mut client, mut server) = VirgeClient::new_in_memory(config).await.unwrap();
+    let payload = pattern(10 * 1024 + 7);
+    let expected = payload.clone();
+    let receiver = tokio::spawn(async move {
+        assert!(server.recv_msg().await.unwrap() == expected, "copied payload differs");
+        assert_eq!(server.recv_msg().await.unwrap(), b"flushed");
+    });
+
+    // io::copy 逐段写入，shutdown 写入结束块头完成这条消息，之后的写入被拒绝
+    let mut writer = client.start_message(payload.len()).await.unwrap();
+    let copied = tokio::io::copy(&mut &payload[..], &mut writer).await.unwrap();
+    assert_eq!(copied, payload.len() as u64);
+    writer.shutdown().await.unwrap();
+    assert!(writer.write(b"late").await.is_err());
+    drop(writer);
+
+    // flush 将未满的块也发出，消息仍在 shutdown 后才完成
+    let mut writer = client.start_message(0).await.unwrap();
+    AsyncWriteExt::write_all(&mut writer, b"flushed").await.unwrap();
+    writer.flush().await.unwrap();
+    assert_eq!(writer.buffered(), 0);
+    writer.shutdown().await.unwrap();
+    tokio::time::timeout(WAIT, receiver).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn abandoned_message_is_aborted_before_a_priority_message() {
+    let config = ClientConfig::default().with_chunk_size(1024).with_priority_lanes(true);
+    let (mut client, mut server) = VirgeClient::new_in_memory(config).await.unwrap();
+    let receiver = tokio::spawn(async move {
+        let aborted = server.recv_msg().await;
+        assert!(matches!(aborted, Err(VirgeError::MessageAborted)), "{:?}", aborted);
+        assert_eq!(server.recv_priority().await.unwrap(), b"stop");
+    });
+
+    // 第一块已发出后放弃，下一次发送是优先消息，放弃块头须先于它发出
+    let mut writer = client.start_message(0).await.unwrap();
+    writer.write_all(&pattern(1500)).await.unwrap();
+    drop(writer);
+    client.send_priority(b"stop").await.unwrap();
+    tokio::time::timeout(WAIT, receiver).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn ack_send_waits_for_the_peer() {
+    let (mut client, mut server) = VirgeClient::new_in_memory(ClientConfig::default().with_ack(true)).await.unwrap();
+    client.set_write_timeout(Some(Duration::from_millis(200))).unwrap();
+
+    // 对端读取时回复确认，send 随之返回
+    let (sent, received) = tokio::join!(client.send(b"acked".to_vec()), server.recv());
+    sent.unwrap();
+    assert_eq!(received.unwrap(), b"acked");
+    assert!(client.last_ack_latency().is_some());
+
+    // 对端不读取就不会确认，send 在写超时后失败
+    let err = tokio::time::timeout(WAIT, client.send(b"unread".to_vec())).await.unwrap().unwrap_err();
+    assert!(matches!(err, VirgeError::Timeout(_)), "{:?}", err);
+}
+
+#[tokio::test]
+async fn cancelled_recv_on_a_silent_peer_returns_promptly() {
+    let (mut client, mut server) = VirgeClient::new_in_memory(ClientConfig::default()).await.unwrap();
+    let token = virga::CancelToken::new();
+
+    // 另一个线程在接收开始等待后取消，接收须在 100ms 内返回 Interrupted
+    let canceller = token.clone();
+    let cancelled_at = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        canceller.cancel();
+        std::time::Instant::now()
+    });
+    let err = tokio::time::timeout(WAIT, client.recv_cancellable(&token)).await.unwrap().unwrap_err();
+    let elapsed = cancelled_at.join().unwrap().elapsed();
+    assert!(
+        matches!(&err, VirgeError::IoError(e) if e.kind() == std::io::ErrorKind::Interrupted),
+        "{:?}",
+        err
+    );
+    assert!(elapsed < Duration::from_millis(100), "recv returned {:?} after cancel", elapsed);
+
+    // 连接保持可用，已取消的令牌使之后的接收立即返回
+    let (sent, received) = tokio::join!(server.send(b"after".to_vec()), client.recv());
+    sent.unwrap();
+    assert_eq!(received.unwrap(), b"after");
+    assert!(client.recv_cancellable(&token).await.is_err());
+
+    // 服务器端同样可被取消
+    let token = virga::CancelToken::new();
+    token.cancel();
+    let err = tokio::time::timeout(WAIT, server.recv_cancellable(&token)).await.unwrap().unwrap_err();
+    assert!(err.is_retryable(), "{:?}", err);
+}
+
+#[cfg(feature = "encryption")]
+#[tokio::test]
+async fn encryption_is_rejected() {
+    let config = ClientConfig::default().with_encryption_key([1u8; 32]);
+    let err = VirgeClient::new_in_memory(config).await.err().unwrap();
+    assert!(matches!(err, VirgeError::ConfigError(_)), "{:?}", err);
+}
+
+#[cfg(feature = "serde")]
+mod typed {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use virga::WireFormat;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum State {
+        Pending,
+        Shipped { tracking: String },
+        Cancelled(u32),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Item {
+        name: String,
+        quantity: u16,
+        tags: Vec<String>,
+        discount: Option<u8>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: u64,
+        items: Vec<Item>,
+        note: Option<String>,
+        state: State,
+        history: Vec<State>,
+    }
+
+    fn wire_formats() -> Vec<WireFormat> {
+        #[allow(unused_mut)]
+        let mut formats = vec![WireFormat::Bincode];
+        #[cfg(feature = "serde-json")]
+        formats.push(WireFormat::Json);
+        formats
+    }
+
+    fn order(id: u64) -> Order {
+        Order {
+            id,
+            items: vec![
+                Item { name: "disk".into(), quantity: 2, tags: vec!["ssd".into(), "nvme".into()], discount: Some(15) },
+                Item { name: "cable".into(), quantity: 1, tags: Vec::new(), discount: None },
+            ],
+            note: Some("leave at the door".into()),
+            state: State::Shipped { tracking: "VX-42".into() },
+            history: vec![State::Pending, State::Cancelled(7), State::Pending],
+        }
+    }
+
+    #[tokio::test]
+    async fn nested_values_round_trip_in_every_wire_format() {
+        for format in wire_formats() {
+            let config = ClientConfig::default().with_wire_format(format);
+            let (mut client, mut server) = VirgeClient::new_in_memory(config).await.unwrap();
+
+            let (sent, received) = tokio::join!(client.send_serialized(&order(1)), server.recv_deserialized::<Order>());
+            sent.unwrap();
+            assert_eq!(received.unwrap(), order(1), "{:?}", format);
+
+            // 空集合、None 与单元变体同样能还原
+            let reply = Order { id: 2, items: Vec::new(), note: None, state: State::Pending, history: Vec::new() };
+            let (sent, received) = tokio::join!(server.send_serialized(&reply), client.recv_deserialized::<Order>());
+            sent.unwrap();
+            assert_eq!(received.unwrap(), reply, "{:?}", format);
+        }
+    }
+
+    #[tokio::test]
+    async fn mismatched_data_is_a_codec_error() {
+        for format in wire_formats() {
+            let config = ClientConfig::default().with_wire_format(format);
+            let (mut client, mut server) = VirgeClient::new_in_memory(config).await.unwrap();
+
+            // 按另一种类型编码的消息与原始字节都无法解码为 Order
+            let mismatched = client.send_serialized("not an order");
+            let (sent, received) = tokio::join!(mismatched, server.recv_deserialized::<Order>());
+            sent.unwrap();
+            let err = received.unwrap_err();
+            assert!(matches!(err, VirgeError::CodecError(_)), "{:?}: {:?}", format, err);
+
+            let (sent, received) = tokio::join!(client.send(vec![0xff; 3]), server.recv_deserialized::<Order>());
+            sent.unwrap();
+            let err = received.unwrap_err();
+            assert!(matches!(err, VirgeError::CodecError(_)), "{:?}: {:?}", format, err);
+
+            // 解码失败不影响连接，下一条消息照常收到
+            let (sent, received) = tokio::join!(client.send_serialized(&order(3)), server.recv_deserialized::<Order>());
+            sent.unwrap();
+            assert_eq!(received.unwrap(), order(3), "{:?}", format);
+        }
+    }
+}
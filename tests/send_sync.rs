@@ -0,0 +1,49 @@
+//! 公开的连接类型及其异步方法返回的 future 可在线程间移动与共享，
+//! 可放入 `Arc<Mutex<_>>` 或交给多线程运行时执行。断言在编译期完成，任何一项退化都会使本测试无法编译。
+
+use virga::server::StopHandle;
+use virga::{
+    ClientPool, PooledClient, ServerManager, VirgeClient, VirgeClientHandle, VirgeError, VirgeReadHalf, VirgeServer,
+    VirgeServerHandle, VirgeWriteHalf,
+};
+
+fn assert_send_sync<T: Send + Sync>() {}
+fn assert_send<T: Send>(_: T) {}
+
+#[test]
+fn connection_types_are_send_and_sync() {
+    assert_send_sync::<VirgeClient>();
+    assert_send_sync::<VirgeServer>();
+    assert_send_sync::<ServerManager>();
+    assert_send_sync::<StopHandle>();
+    assert_send_sync::<VirgeReadHalf>();
+    assert_send_sync::<VirgeWriteHalf>();
+    assert_send_sync::<VirgeClientHandle>();
+    assert_send_sync::<VirgeServerHandle>();
+    assert_send_sync::<ClientPool>();
+    assert_send_sync::<PooledClient>();
+    assert_send_sync::<VirgeError>();
+    #[cfg(feature = "use-yamux")]
+    assert_send_sync::<virga::VirgeStream>();
+}
+
+/// 只需通过类型检查，不会被调用
+#[allow(dead_code)]
+fn futures_are_send(client: &mut VirgeClient, server: &mut VirgeServer, manager: &mut ServerManager, pool: &ClientPool) {
+    assert_send(client.connect());
+    assert_send(client.send(Vec::new()));
+    assert_send(client.recv());
+    assert_send(server.send(Vec::new()));
+    assert_send(server.recv());
+    assert_send(manager.accept());
+    assert_send(pool.get());
+}
+
+/// tokio IO 适配器持有进行中的操作，只能在线程间移动
+#[cfg(feature = "tokio-runtime")]
+#[test]
+fn async_wrappers_are_send() {
+    fn assert_send_type<T: Send>() {}
+    assert_send_type::<virga::VirgeClientAsync>();
+    assert_send_type::<virga::VirgeServerAsync>();
+}
@@ -0,0 +1,203 @@
+//! vsock 传输测试：经 vsock 回环地址（`VMADDR_CID_LOCAL`）运行 raw 与 yamux 传输
+//!
+//! 需要宿主机加载 `vsock_loopback` 内核模块，默认不运行，
+//! 运行方式：`cargo test --features use-raw,use-yamux --test vsock -- --ignored --nocapture`。
+
+use std::time::{Duration, Instant};
+use virga::client::{ClientConfig, RecvOutcome, VirgeClient};
+use virga::server::{ServerConfig, ServerManager, VirgeServer};
+use virga::{TransportKind, VirgeError};
+
+/// 单个测试中等待对端的最长时间，超过视为挂起
+const WAIT: Duration = Duration::from_secs(30);
+
+/// 以 `config` 在随机端口上启动 `kind` 协议的 vsock 服务器，返回管理器与实际监听的端口
+async fn start_server(kind: TransportKind, config: ServerConfig) -> (ServerManager, u32) {
+    let config = config.with_listen_port(ServerConfig::PORT_ANY).with_transport_kind(kind);
+    let mut manager = ServerManager::new(config);
+    manager.start().await.unwrap();
+    let port = manager.local_addr().unwrap().port();
+    (manager, port)
+}
+
+/// 以 `config` 经回环地址连接 `port` 上的 `kind` 协议服务器
+async fn connect(kind: TransportKind, port: u32, config: ClientConfig) -> VirgeClient {
+    let config = config
+        .with_server_cid(ClientConfig::CID_LOCAL)
+        .with_server_port(port)
+        .with_transport_kind(kind);
+    let mut client = VirgeClient::new(config);
+    client.connect().await.unwrap();
+    client
+}
+
+/// 以长度前缀消息原样回显，直到客户端断开
+async fn echo(mut server: VirgeServer) {
+    while let Ok(message) = server.recv_msg().await {
+        if server.send_msg(&message).await.is_err() {
+            break;
+        }
+    }
+    let _ = server.disconnect().await;
+}
+
+/// 每个字节都不同的测试数据，错位或重复的数据块会被比较发现
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// 经 `kind` 协议单向发送 `total` 字节（每条消息 `message_len` 字节），返回每秒字节数
+async fn throughput(kind: TransportKind, message_len: usize, total: usize) -> f64 {
+    let (mut manager, port) = start_server(kind, ServerConfig::default()).await;
+    let count = total / message_len;
+    let expected = pattern(message_len);
+    let check = expected.clone();
+    let server = tokio::spawn(async move {
+        let mut server = manager.accept().await.unwrap();
+        for _ in 0..count {
+            let message = server.recv_msg().await.unwrap();
+            assert!(message == check, "received message differs from the original");
+        }
+        // 应答最后一条消息，使计时包括对端完整收到全部数据
+        server.send_msg(b"done").await.unwrap();
+        let _ = server.recv_msg().await;
+    });
+
+    let mut client = connect(kind, port, ClientConfig::default()).await;
+    let start = Instant::now();
+    for _ in 0..count {
+        client.send_msg(&expected).await.unwrap();
+    }
+    assert_eq!(tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap(), b"done");
+    let elapsed = start.elapsed();
+    client.disconnect().await.unwrap();
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+    (count * message_len) as f64 / elapsed.as_secs_f64()
+}
+
+#[tokio::test]
+#[ignore = "needs the vsock_loopback kernel module"]
+async fn raw_throughput_compared_with_yamux() {
+    const TOTAL: usize = 256 * 1024 * 1024;
+    for message_len in [4 * 1024, 64 * 1024, 1024 * 1024] {
+        let raw = throughput(TransportKind::Raw, message_len, TOTAL).await;
+        let yamux = throughput(TransportKind::Yamux, message_len, TOTAL).await;
+        println!(
+            "{:>8} byte messages: raw {:>8.1} MiB/s, yamux {:>8.1} MiB/s ({:.2}x)",
+            message_len,
+            raw / (1024.0 * 1024.0),
+            yamux / (1024.0 * 1024.0),
+            raw / yamux
+        );
+        // raw 传输没有多路复用与窗口控制，不应明显慢于 yamux
+        assert!(raw * 2.0 > yamux, "raw transport is much slower than yamux for {} byte messages", message_len);
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs the vsock_loopback kernel module"]
+async fn yamux_round_trips_share_one_stream() {
+    let (mut manager, port) = start_server(TransportKind::Yamux, ServerConfig::default()).await;
+    let server = tokio::spawn(async move { echo(manager.accept().await.unwrap()).await });
+
+    let mut client = connect(TransportKind::Yamux, port, ClientConfig::default()).await;
+    // 每次发送后都能在同一连接上接收应答，消息边界由长度前缀而不是流的关闭划分
+    for i in 0..100usize {
+        let message = pattern(i * 97 + 1);
+        client.send_msg(&message).await.unwrap();
+        let reply = tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap();
+        assert!(reply == message, "round trip {} returned a different payload", i);
+    }
+    client.disconnect().await.unwrap();
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs the vsock_loopback kernel module"]
+async fn yamux_server_receives_before_sending() {
+    let (mut manager, port) = start_server(TransportKind::Yamux, ServerConfig::default()).await;
+    // 与示例服务器一样，服务器先接收：必须接受客户端打开的入站流，而不是自己打开出站流
+    let server = tokio::spawn(async move {
+        let mut server = manager.accept().await.unwrap();
+        let request = server.recv_msg().await.unwrap();
+        assert_eq!(request, b"client speaks first");
+        server.send_msg(b"server answers").await.unwrap();
+        let _ = server.recv_msg().await;
+    });
+
+    let mut client = connect(TransportKind::Yamux, port, ClientConfig::default()).await;
+    client.send_msg(b"client speaks first").await.unwrap();
+    let reply = tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap();
+    assert_eq!(reply, b"server answers");
+    client.disconnect().await.unwrap();
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs the vsock_loopback kernel module"]
+async fn idle_yamux_connection_stays_healthy() {
+    const INTERVAL: Duration = Duration::from_millis(200);
+    let config = ServerConfig::default().with_keepalive(INTERVAL, 5 * INTERVAL);
+    let (mut manager, port) = start_server(TransportKind::Yamux, config).await;
+    let server = tokio::spawn(async move { echo(manager.accept().await.unwrap()).await });
+
+    let config = ClientConfig::default().with_keepalive(INTERVAL, 5 * INTERVAL);
+    let mut client = connect(TransportKind::Yamux, port, config).await;
+    // 空闲时间远超心跳间隔，期间的 PING/PONG 与窗口更新都由后台任务驱动的连接承载
+    let outcome = client.recv_msg_timeout(20 * INTERVAL).await.unwrap();
+    assert_eq!(outcome, RecvOutcome::TimedOutIdle);
+    assert!(client.is_connected());
+
+    // 空闲之后大消息仍能通过，需要连接持续处理对端的窗口更新
+    let payload = pattern(4 * 1024 * 1024 + 3);
+    client.send_msg(&payload).await.unwrap();
+    let reply = tokio::time::timeout(WAIT, client.recv_msg()).await.unwrap().unwrap();
+    assert!(reply == payload, "echoed payload differs from the original");
+    client.disconnect().await.unwrap();
+    tokio::time::timeout(WAIT, server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs the vsock_loopback kernel module"]
+async fn second_raw_manager_on_the_same_port_reports_port_in_use() {
+    let (_first, port) = start_server(TransportKind::Raw, ServerConfig::default()).await;
+    // 内核的 vsock 实现接受 SO_REUSEADDR，但端口仍被占用
+    for reuse_addr in [false, true] {
+        let config = ServerConfig::default()
+            .with_listen_port(port)
+            .with_transport_kind(TransportKind::Raw)
+            .with_reuse_addr(reuse_addr);
+        let err = ServerManager::new(config).start().await.unwrap_err();
+        assert!(matches!(err, VirgeError::PortInUse { port: p, .. } if p == port), "{:?}", err);
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs the vsock_loopback kernel module"]
+async fn yamux_nonblocking_send_would_block_when_the_window_is_exhausted() {
+    let (mut manager, port) = start_server(TransportKind::Yamux, ServerConfig::default()).await;
+    let config = ClientConfig::default().with_nonblocking_send(true);
+    let (mut client, server) = tokio::join!(connect(TransportKind::Yamux, port, config), manager.accept());
+    let mut server = server.unwrap();
+
+    // 对端不接收就不会发出窗口更新，接收窗口耗尽后 send 立即返回 WouldBlock
+    let message = pattern(64 * 1024);
+    let mut sent = 0;
+    let err = loop {
+        match client.send(message.clone()).await {
+            Ok(()) => sent += 1,
+            Err(e) => break e,
+        }
+        assert!(sent < 4096, "send kept succeeding although the peer never reads");
+    };
+    assert!(sent > 0, "no message fit into the receive window");
+    assert_eq!(err.io_kind(), Some(std::io::ErrorKind::WouldBlock), "{:?}", err);
+
+    // 对端读完已发出的消息后窗口恢复，可以继续发送
+    for _ in 0..sent {
+        let received = tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap();
+        assert!(received == message, "buffered message differs from the original");
+    }
+    client.send(b"after".to_vec()).await.unwrap();
+    assert_eq!(tokio::time::timeout(WAIT, server.recv()).await.unwrap().unwrap(), b"after");
+}